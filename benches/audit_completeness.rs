@@ -0,0 +1,82 @@
+//! Time taken to audit a large database against its manifest.
+//!
+//! A synthetic manifest lists 256 Chapters of 500 Volumes (128,000 files).
+//! Half of the Chapter directories are present locally, with every file.
+//!
+//! Run with: `cargo bench --bench audit_completeness`
+use std::{fs, time::Instant};
+
+use anyhow::Result;
+use cid::{
+    multihash::{Code, MultihashDigest},
+    Cid,
+};
+use min_know::{
+    config::choices::{DataKind, DirNature},
+    database::types::Todd,
+    specs::{
+        nametags::{NameTagsChapterId, NameTagsSpec, NameTagsVolumeId},
+        traits::{ChapterMethods, DataSpec, ManifestMethods, VolumeIdMethods},
+    },
+};
+
+const VOLUMES: u32 = 500;
+
+fn main() -> Result<()> {
+    let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Ephemeral)?;
+    let chapter_ids = NameTagsSpec::get_all_chapter_ids()?;
+    let volume_ids = (0..VOLUMES)
+        .map(NameTagsVolumeId::nth_id)
+        .collect::<Result<Vec<_>>>()?;
+    // Every file has the same contents, so shares one CID.
+    let bytes =
+        <NameTagsSpec as DataSpec>::AssociatedChapter::new_empty(&volume_ids[0], &chapter_ids[0])
+            .as_file_bytes()?;
+    let cid = Cid::new_v0(Code::Sha2_256.digest(&bytes))?.to_string();
+
+    let mut cids: Vec<(String, NameTagsVolumeId, NameTagsChapterId)> = vec![];
+    for (n, chapter_id) in chapter_ids.iter().enumerate() {
+        let present = n % 2 == 0;
+        let dir = db.config.chapter_dir_path(chapter_id);
+        if present {
+            fs::create_dir_all(&dir)?;
+        }
+        for volume_id in &volume_ids {
+            let chapter =
+                <NameTagsSpec as DataSpec>::AssociatedChapter::new_empty(volume_id, chapter_id);
+            if present {
+                fs::write(dir.join(chapter.filename()), &bytes)?;
+            }
+            cids.push((cid.clone(), volume_id.clone(), chapter_id.clone()));
+        }
+    }
+    let mut manifest = <NameTagsSpec as DataSpec>::AssociatedManifest::default();
+    manifest.set_latest_volume_identifier(volume_ids[volume_ids.len() - 1].interface_id());
+    manifest.set_cids(&cids);
+    fs::write(
+        db.config.manifest_file_path()?,
+        serde_json::to_string(&manifest)?,
+    )?;
+    println!(
+        "Manifest of {} files ({} Chapters, {} Volumes), {} files present.",
+        cids.len(),
+        chapter_ids.len(),
+        VOLUMES,
+        cids.len() / 2
+    );
+
+    for run in ["first (hashes files)", "second (unchanged files)"] {
+        let start = Instant::now();
+        let audit = db.check_completeness()?;
+        println!(
+            "Audit {}: {:?}, {} absent Chapters, {} absent files. {}.",
+            run,
+            start.elapsed(),
+            audit.absent_chapter_ids.len(),
+            audit.absent_individual_files.len(),
+            audit.throughput
+        );
+    }
+    fs::remove_dir_all(&db.config.base_dir_nature_dependent)?;
+    Ok(())
+}
@@ -1,7 +1,6 @@
 use std::env;
 
 use anyhow::Result;
-use env_logger;
 
 use min_know::{
     config::{
@@ -19,7 +19,11 @@ fn main() -> Result<()> {
 
     let data_kind = DataKind::AddressAppearanceIndex(Network::default());
     let db: Todd<AAISpec> = Todd::init(data_kind, DirNature::Sample)?;
-    db.extend()?;
+    let outcome = db.extend()?;
+    println!("{}", outcome);
+    for failure in &outcome.errors {
+        println!("Failed: {}", failure);
+    }
 
     Ok(())
 }
@@ -0,0 +1,20 @@
+use std::env;
+
+use anyhow::Result;
+
+use min_know::{
+    config::choices::{DataKind, DirNature},
+    database::types::Todd,
+    specs::events::EventsSpec,
+};
+
+fn main() -> Result<()> {
+    // For full error backtraces with anyhow.
+    env::set_var("RUST_BACKTRACE", "full");
+    env::set_var("RUST_LOG", "debug");
+    env_logger::init();
+
+    let db: Todd<EventsSpec> = Todd::init(DataKind::Events, DirNature::Sample)?;
+    db.get_sample_data()?;
+    Ok(())
+}
@@ -6,6 +6,7 @@ use min_know::{
     config::choices::{DataKind, DirNature},
     database::types::Todd,
     specs::nametags::NameTagsSpec,
+    utils::gateway::Gateway,
 };
 
 /// Uses a manifest file to obtain data relevant for a user.
@@ -24,12 +25,15 @@ fn main() -> Result<()> {
         // EF dev wallet.
         "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae",
     ];
-    static IPFS_GATEWAY_URL: &str = "https://127.0.0.1:8080";
+    static IPFS_GATEWAY_URL: &str = "http://127.0.0.1:8080";
+    let gateway = Gateway::new(IPFS_GATEWAY_URL)?;
 
     // Obtain Chapters with ChapterIds: 0x84 and 0xde
-    db.obtain_relevant_data(&addresses, IPFS_GATEWAY_URL)?;
+    db.obtain_relevant_data(&addresses, Some(&gateway))?;
 
-    let Some(address) = addresses.get(0) else { bail!("Address not in list.")};
+    let Some(address) = addresses.first() else {
+        bail!("Address not in list.")
+    };
     let appearances = db.find(address)?;
     for a in appearances {
         println!("Appearance Tx ID: {:?}", a);
@@ -48,8 +48,7 @@ impl Default for Network {
 impl Network {
     /// Creates a new network config. Checks parameters.
     pub fn new(bytes_per_address: u32, network_name: String) -> Result<Self> {
-        if network_name.as_bytes().len() as u32 > MAX_NETWORK_NAME_BYTES || !network_name.is_ascii()
-        {
+        if network_name.len() as u32 > MAX_NETWORK_NAME_BYTES || !network_name.is_ascii() {
             return Err(anyhow!(
                 "The network name must be {} valid ASCII chars or less",
                 MAX_NETWORK_NAME_BYTES
@@ -1,18 +1,58 @@
-use std::path::PathBuf;
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use anyhow::{anyhow, Result};
 use directories::ProjectDirs;
+use log::warn;
 use serde::{Deserialize, Serialize};
 
+pub use crate::extraction::limits::OversizePolicy;
+pub use crate::extraction::traits::RawSourceFormat;
+use crate::manifest::{mirrors::ManifestMirrors, provenance::ManifestProvenance};
+pub use crate::utils::parallelism::Parallelism;
+
 use super::{address_appearance_index::Network, dirs::ConfigStruct};
 
+/// The kinds of database, each with exactly one spec.
+///
+/// Only kinds with a spec are listed. Kinds that are planned but not yet
+/// implemented (e.g., Sourcify contract metadata or 4byte signatures) have no
+/// variant, and naming one (see [DataKind::from_name]) returns an
+/// [UnsupportedDataKind] error.
 #[derive(Clone, Debug, PartialEq, PartialOrd, Hash, Deserialize, Serialize)]
 pub enum DataKind {
     AddressAppearanceIndex(Network),
     NameTags,
     Signatures,
+    Events,
+}
+
+/// Error returned when a data kind has no spec, or does not match the spec
+/// of the database being opened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsupportedDataKind {
+    /// The data kind that was requested. E.g., "sourcify".
+    pub requested: String,
+    /// The data kinds that could be used instead.
+    pub implemented: Vec<String>,
+}
+
+impl Display for UnsupportedDataKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The data kind {:?} is not supported here. Implemented kinds: {}.",
+            self.requested,
+            self.implemented.join(", ")
+        )
+    }
 }
 
+impl std::error::Error for UnsupportedDataKind {}
+
 /// Helper for setting up a config.
 #[derive(Clone, Debug, Default, PartialEq, PartialOrd, Hash, Deserialize, Serialize)]
 pub enum DirNature {
@@ -20,8 +60,30 @@ pub enum DirNature {
     Sample,
     Default,
     Custom(PathPair),
+    /// A new temporary directory, removed when the database (and all clones) are dropped.
+    ///
+    /// Useful for tests and embedding, where the platform directories should not be used.
+    Ephemeral,
 }
 
+/// What extending a database does if the raw data ends before the latest
+/// Volume already formed (e.g., a raw source truncated by a disk recovery).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Hash, Deserialize, Serialize)]
+pub enum RawRegressionPolicy {
+    /// Keep the existing Chapters and form nothing new. A warning is logged.
+    #[default]
+    TrustExisting,
+    /// Remove all Chapter files and form the database again from the raw data.
+    ///
+    /// Volumes beyond the end of the raw data are no longer held.
+    RebuildFromRaw,
+    /// Stop with an error.
+    Error,
+}
+
+/// Distinguishes ephemeral directories created by the same process.
+static EPHEMERAL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Clone, Debug, Default, PartialEq, PartialOrd, Hash, Deserialize, Serialize)]
 pub struct PathPair {
     /// Path for unprocessed data.
@@ -31,11 +93,52 @@ pub struct PathPair {
 }
 
 impl DataKind {
+    /// Returns one DataKind for each variant, using default parameters.
+    ///
+    /// Each variant corresponds to exactly one spec.
+    pub fn all() -> Vec<DataKind> {
+        let all = vec![
+            DataKind::AddressAppearanceIndex(Network::default()),
+            DataKind::NameTags,
+            DataKind::Signatures,
+            DataKind::Events,
+        ];
+        // Adding a variant causes a compile error here as a reminder to update the list.
+        for kind in &all {
+            match kind {
+                DataKind::AddressAppearanceIndex(_)
+                | DataKind::NameTags
+                | DataKind::Signatures
+                | DataKind::Events => {}
+            }
+        }
+        all
+    }
+    /// Returns the DataKind with the given name (e.g., "nametags"), or interface
+    /// ID (e.g., "address_appearance_index_mainnet").
+    ///
+    /// ## Errors
+    /// An [UnsupportedDataKind] error if no implemented kind has the name.
+    pub fn from_name(name: &str) -> Result<DataKind> {
+        let all = DataKind::all();
+        match all
+            .iter()
+            .find(|kind| kind.interface_id() == name || kind.as_string() == name)
+        {
+            Some(kind) => Ok(kind.clone()),
+            None => Err(UnsupportedDataKind {
+                requested: name.to_string(),
+                implemented: all.iter().map(|kind| kind.interface_id()).collect(),
+            }
+            .into()),
+        }
+    }
     pub(crate) fn as_string(&self) -> &str {
         match self {
             DataKind::AddressAppearanceIndex(_) => "address_appearance_index",
             DataKind::Signatures => "signatures",
             DataKind::NameTags => "nametags",
+            DataKind::Events => "events",
         }
     }
     /// Returns the data kind as a stirng starting with "todd_".
@@ -71,6 +174,34 @@ impl DataKind {
             .ok_or_else(|| anyhow!("Could not access env var (e.g., $HOME) to set up project."))?;
         Ok(proj.data_dir().to_path_buf())
     }
+    /// Returns the cache directory for the database.
+    ///
+    /// Raw source data can be derived again, so it is stored here rather than
+    /// alongside the processed data. This lets OS cleanup tools reclaim the space.
+    /// Conforms to the `ProjectDirs.cache_dir()` schema in the Directories crate
+    /// (e.g., $XDG_CACHE_HOME on Linux).
+    pub(crate) fn platform_cache_directory(&self) -> Result<PathBuf> {
+        let proj = ProjectDirs::from("", "", &self.as_todd_string())
+            .ok_or_else(|| anyhow!("Could not access env var (e.g., $HOME) to set up project."))?;
+        Ok(proj.cache_dir().to_path_buf())
+    }
+}
+
+/// Returns the raw source path under the cache directory.
+///
+/// Raw source data in the previous location (alongside processed data) is
+/// still used if present, with a warning.
+fn raw_source_path(cache_base: &Path, legacy_base: &Path, dir_name: &str) -> PathBuf {
+    let legacy = legacy_base.join(dir_name);
+    let cache = cache_base.join(dir_name);
+    if legacy.is_dir() && !cache.exists() {
+        warn!(
+            "Raw source data found at {:?}. Move it to the cache directory {:?} so it can be cleaned up separately from the database.",
+            legacy, cache
+        );
+        return legacy;
+    }
+    cache
 }
 
 impl DirNature {
@@ -82,53 +213,110 @@ impl DirNature {
             DirNature::Sample => self.sample_config(data_kind)?,
             DirNature::Default => self.default_config(data_kind)?,
             DirNature::Custom(ref paths) => self.custom_config(data_kind, paths)?,
+            DirNature::Ephemeral => self.ephemeral_config(data_kind)?,
         };
         Ok(config)
     }
+    /// A config with every setting at its default, for the constructors below
+    /// to complete with their paths.
+    fn base_config(self, data_kind: DataKind) -> ConfigStruct {
+        ConfigStruct {
+            dir_nature: self,
+            base_dir_nature_dependent: PathBuf::new(),
+            data_kind,
+            raw_source: PathBuf::new(),
+            data_dir: PathBuf::new(),
+            ignore_unknown_dirs: false,
+            skip_corrupt_raw_files: false,
+            shared_data_dirs: vec![],
+            ens_rpc_url: None,
+            oversize_policy: OversizePolicy::default(),
+            validate_raw_first: false,
+            decoy_chapters: 0,
+            watched_keys: vec![],
+            single_pass_volumes: false,
+            verify_existing_chapters: false,
+            fetch_on_miss: None,
+            query_cache: None,
+            manifest_mirrors: ManifestMirrors::default(),
+            manifest_provenance: ManifestProvenance::default(),
+            manifest_superseded_by: None,
+            keep_manifest_history: false,
+            publisher_manifest_url: None,
+            trusted_manifest_cids: vec![],
+            parallelism: Parallelism::default(),
+            raw_regression: RawRegressionPolicy::default(),
+            hash_buffer_bytes: None,
+            raw_source_format: RawSourceFormat::default(),
+            read_only: false,
+            encryption_key: None,
+        }
+    }
     /// Used for common pattern of default config setup.
     fn default_config(self, data_kind: DataKind) -> Result<ConfigStruct> {
         let project = data_kind.platform_directory()?;
+        let cache = data_kind.platform_cache_directory()?;
+        let raw_source = raw_source_path(&cache, &project, &data_kind.raw_source_dir_name());
+        let data_dir = project.join(data_kind.interface_id());
         Ok(ConfigStruct {
-            dir_nature: self,
-            base_dir_nature_dependent: project.clone(),
-            raw_source: project.join(data_kind.raw_source_dir_name()),
-            data_dir: project.join(data_kind.interface_id()),
-            data_kind,
+            base_dir_nature_dependent: project,
+            raw_source,
+            data_dir,
+            ..self.base_config(data_kind)
         })
     }
     /// Used for common pattern of sample config setup.
     fn sample_config(self, data_kind: DataKind) -> Result<ConfigStruct> {
-        let project = data_kind.platform_directory()?;
+        let project = data_kind.platform_directory()?.join("samples");
+        let cache = data_kind.platform_cache_directory()?.join("samples");
+        let raw_source = raw_source_path(&cache, &project, &data_kind.raw_source_dir_name());
+        let data_dir = project.join(data_kind.interface_id());
         Ok(ConfigStruct {
-            dir_nature: self,
-            base_dir_nature_dependent: project.join("samples"),
-            raw_source: project
-                .join("samples")
-                .join(data_kind.raw_source_dir_name()),
-            data_dir: project.join("samples").join(data_kind.interface_id()),
-            data_kind,
+            base_dir_nature_dependent: project,
+            raw_source,
+            data_dir,
+            ..self.base_config(data_kind)
+        })
+    }
+    /// Used for a temporary directory that is unique to this config.
+    fn ephemeral_config(self, data_kind: DataKind) -> Result<ConfigStruct> {
+        let base = std::env::temp_dir().join(format!(
+            "{}_ephemeral_{}_{}",
+            data_kind.as_todd_string(),
+            std::process::id(),
+            EPHEMERAL_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&base)?;
+        Ok(ConfigStruct {
+            raw_source: base.join(data_kind.raw_source_dir_name()),
+            data_dir: base.join(data_kind.interface_id()),
+            base_dir_nature_dependent: base,
+            ..self.base_config(data_kind)
         })
     }
     /// Used for common pattern of custom config setup.
     ///
-    /// Use may pass both, one or none for custom paths in PathPair.
+    /// Use may pass both, one or none for custom paths in PathPair. The platform
+    /// directories are only used (and so $HOME only needed) for paths not passed.
     fn custom_config(&self, data_kind: DataKind, paths: &PathPair) -> Result<ConfigStruct> {
-        let project = data_kind.platform_directory()?;
         let base_dir_nature_dependent = match paths.processed_data_dir.clone() {
             Some(p) => p,
-            None => project.clone(),
+            None => data_kind.platform_directory()?,
         };
         let raw_source = match paths.raw_source.clone() {
             Some(p) => p,
-            None => project.join(data_kind.raw_source_dir_name()),
+            None => raw_source_path(
+                &data_kind.platform_cache_directory()?,
+                &data_kind.platform_directory()?,
+                &data_kind.raw_source_dir_name(),
+            ),
         };
         let data_dir = base_dir_nature_dependent.join(data_kind.interface_id());
         Ok(ConfigStruct {
-            dir_nature: self.clone(),
             base_dir_nature_dependent,
-            data_kind,
             raw_source,
             data_dir,
+            ..self.clone().base_config(data_kind)
         })
     }
 }
@@ -175,8 +363,71 @@ fn config_custom_paths_correct_for_nametags() {
     let config = dbg!(DirNature::Custom(paths)
         .into_config(DataKind::NameTags)
         .unwrap());
-    let raw = format!("{}", src);
+    let raw = src.to_string();
     assert!(config.raw_source.to_str().unwrap().ends_with(&raw));
     let data = format!("{}/nametags", dst);
     assert!(config.data_dir.to_str().unwrap().ends_with(&data));
 }
+
+#[test]
+fn ephemeral_paths_are_unique() {
+    let a = DirNature::Ephemeral
+        .into_config(DataKind::NameTags)
+        .unwrap();
+    let b = DirNature::Ephemeral
+        .into_config(DataKind::NameTags)
+        .unwrap();
+    assert_ne!(a.base_dir_nature_dependent, b.base_dir_nature_dependent);
+    assert!(a.data_dir.starts_with(std::env::temp_dir()));
+    assert!(a.raw_source.starts_with(&a.base_dir_nature_dependent));
+    std::fs::remove_dir_all(a.base_dir_nature_dependent).unwrap();
+    std::fs::remove_dir_all(b.base_dir_nature_dependent).unwrap();
+}
+
+#[test]
+fn raw_source_prefers_cache() {
+    let base = std::env::temp_dir().join("min_know_test_raw_source_path");
+    let _ = std::fs::remove_dir_all(&base);
+    let (cache, legacy) = (base.join("cache"), base.join("data"));
+    assert_eq!(raw_source_path(&cache, &legacy, "raw"), cache.join("raw"));
+    std::fs::create_dir_all(legacy.join("raw")).unwrap();
+    assert_eq!(raw_source_path(&cache, &legacy, "raw"), legacy.join("raw"));
+    std::fs::create_dir_all(cache.join("raw")).unwrap();
+    assert_eq!(raw_source_path(&cache, &legacy, "raw"), cache.join("raw"));
+    std::fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn each_data_kind_has_one_spec() {
+    use crate::specs::{
+        address_appearance_index::AAISpec, events::EventsSpec, nametags::NameTagsSpec,
+        signatures::SignaturesSpec, traits::DataSpec,
+    };
+    let specs: [fn(&DataKind) -> bool; 4] = [
+        AAISpec::spec_matches_input,
+        NameTagsSpec::spec_matches_input,
+        SignaturesSpec::spec_matches_input,
+        EventsSpec::spec_matches_input,
+    ];
+    for kind in DataKind::all() {
+        let matching = specs.iter().filter(|matches| matches(&kind)).count();
+        assert_eq!(matching, 1, "{:?} matches {} specs", kind, matching);
+    }
+    for matches in specs {
+        assert_eq!(DataKind::all().iter().filter(|k| matches(k)).count(), 1);
+    }
+}
+
+#[test]
+fn unimplemented_kinds_are_named() {
+    assert_eq!(DataKind::from_name("nametags").unwrap(), DataKind::NameTags);
+    assert_eq!(
+        DataKind::from_name("address_appearance_index_mainnet").unwrap(),
+        DataKind::AddressAppearanceIndex(Network::default())
+    );
+    let err = DataKind::from_name("sourcify").unwrap_err();
+    let unsupported = err.downcast_ref::<UnsupportedDataKind>().unwrap();
+    assert_eq!(unsupported.requested, "sourcify");
+    assert_eq!(unsupported.implemented.len(), DataKind::all().len());
+    assert!(err.to_string().contains("nametags, signatures, events"));
+}
@@ -1,11 +1,30 @@
-use std::{fs, path::PathBuf};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{bail, Context, Result};
+use log::warn;
 use serde::{Deserialize, Serialize};
 
-use crate::specs::traits::{ChapterIdMethods, DataSpec, VolumeIdMethods};
+use crate::{
+    database::{
+        acquisition::FetchOnMiss, encryption::EncryptionKey, query_cache::QueryCacheLimits,
+    },
+    manifest::{
+        mirrors::ManifestMirrors, provenance::ManifestProvenance,
+        samples::SAMPLES_MANIFEST_FILENAME, succession::ManifestSuccessor,
+    },
+    specs::traits::{ChapterIdMethods, DataSpec, VolumeIdMethods},
+    utils::{
+        ipfs::{cid_v0_string_from_file, DEFAULT_HASH_BUFFER_BYTES},
+        system::dir_paths,
+    },
+};
 
-use super::choices::{DataKind, DirNature};
+use super::choices::{
+    DataKind, DirNature, OversizePolicy, Parallelism, RawRegressionPolicy, RawSourceFormat,
+};
 
 #[derive(Clone, Debug, PartialEq, PartialOrd, Hash, Deserialize, Serialize)]
 pub struct ConfigStruct {
@@ -18,11 +37,148 @@ pub struct ConfigStruct {
     pub data_kind: DataKind,
     /// The path to the unformatted raw source data. Used for populating the database.
     pub raw_source: PathBuf,
+    /// The format of the files in `raw_source`. Databases other than the
+    /// address-appearance-index only support the native format.
+    #[serde(default)]
+    pub raw_source_format: RawSourceFormat,
     /// The path to the functional database.
     pub data_dir: PathBuf,
+    /// Whether entries in data_dir that are not Chapter directories are skipped
+    /// (with a warning) rather than causing an error.
+    #[serde(default)]
+    pub ignore_unknown_dirs: bool,
+    /// Whether raw source files that cannot be read are skipped (with a warning)
+    /// when creating Chapters, rather than causing the Chapter to fail.
+    #[serde(default)]
+    pub skip_corrupt_raw_files: bool,
+    /// Data directories of other databases of the same kind (e.g., other networks).
+    /// A newly created Chapter with the same CID as a file in one of these is
+    /// hardlinked to that file rather than written again.
+    #[serde(default)]
+    pub shared_data_dirs: Vec<PathBuf>,
+    /// Ethereum JSON-RPC endpoint used to resolve ENS names given as keys, for
+    /// databases keyed by address. Requires the `net` feature.
+    #[serde(default)]
+    pub ens_rpc_url: Option<String>,
+    /// How raw values that exceed the size limits of the spec (e.g., very long
+    /// names) are handled when creating Chapters.
+    #[serde(default)]
+    pub oversize_policy: OversizePolicy,
+    /// Whether all raw data is checked before a full transformation, which stops
+    /// before any Chapters are created if problems are found.
+    #[serde(default)]
+    pub validate_raw_first: bool,
+    /// Number of randomly chosen Chapters obtained alongside the Chapters for
+    /// requested keys, so that a gateway cannot tell which Chapters are of interest.
+    #[serde(default)]
+    pub decoy_chapters: usize,
+    /// Raw keys (e.g., addresses) that a locally built database is limited to.
+    ///
+    /// If not empty, builds only create the Chapters for these keys, and those
+    /// Chapters only hold the Records for these keys. This makes a small local
+    /// database (e.g., of one's own addresses) as an alternative to obtaining
+    /// full Chapters.
+    #[serde(default)]
+    pub watched_keys: Vec<String>,
+    /// Whether builds form all the Chapters of a Volume together, rather than
+    /// one Chapter at a time.
+    ///
+    /// Databases whose raw files hold data for many Chapters (e.g., the address
+    /// appearance index) then read each raw file once per Volume. Volumes are
+    /// created in parallel, rather than Chapters.
+    #[serde(default)]
+    pub single_pass_volumes: bool,
+    /// Whether a build checks the CID of Chapter files completed by an earlier
+    /// build (recorded in the manifest draft) before skipping them, rather than
+    /// only their size.
+    #[serde(default)]
+    pub verify_existing_chapters: bool,
+    /// Whether a query for a key whose Chapter is absent locally obtains the
+    /// Chapter from a gateway first. Requires the `net` feature.
+    ///
+    /// None (the default) means queries only use local data.
+    #[serde(default)]
+    pub fetch_on_miss: Option<FetchOnMiss>,
+    /// Whether [Todd::find](crate::database::types::Todd::find) keeps its results
+    /// until the manifest changes (see [query_cache](crate::database::query_cache)).
+    ///
+    /// None (the default) means every query reads the Chapter files.
+    #[serde(default)]
+    pub query_cache: Option<QueryCacheLimits>,
+    /// Mirrors that a publisher lists in the manifest (see [ManifestMirrors]).
+    ///
+    /// If empty (the default), the mirrors of an existing manifest are kept.
+    #[serde(default)]
+    pub manifest_mirrors: ManifestMirrors,
+    /// Upstream source and license that a publisher states in the manifest
+    /// (see [ManifestProvenance]).
+    ///
+    /// If empty (the default), the provenance of an existing manifest is kept.
+    #[serde(default)]
+    pub manifest_provenance: ManifestProvenance,
+    /// The database that replaces this one, stated in the manifest by a
+    /// publisher that deprecates the database (see [ManifestSuccessor]).
+    ///
+    /// If None (the default), the successor in an existing manifest is kept.
+    #[serde(default)]
+    pub manifest_superseded_by: Option<ManifestSuccessor>,
+    /// Whether `Todd::generate_manifest` also keeps a copy of each manifest, named
+    /// with the spec version and the time it was generated (see
+    /// [manifest_history](crate::database::manifest_history)).
+    #[serde(default)]
+    pub keep_manifest_history: bool,
+    /// Where the publisher makes the latest manifest available. E.g., an IPNS
+    /// name through a gateway ("http://127.0.0.1:8080/ipns/<name>").
+    ///
+    /// Used by `Todd::sync_and_pin` (requires the `net` feature). None (the
+    /// default) means the local manifest is used.
+    #[serde(default)]
+    pub publisher_manifest_url: Option<String>,
+    /// CIDs of the manifests that this application trusts, in addition to any
+    /// set at compile time (see [crate::manifest::trust]).
+    ///
+    /// If not empty, a manifest obtained from a publisher or an archive must
+    /// have one of these CIDs. Empty (the default) means any manifest is accepted.
+    #[serde(default)]
+    pub trusted_manifest_cids: Vec<String>,
+    /// Threads used to form Chapters, bloom filters and to check existing
+    /// Chapter files (see [Parallelism]). By default, all cores are used.
+    #[serde(default)]
+    pub parallelism: Parallelism,
+    /// What extending the database does if the raw data ends before the
+    /// latest Volume already formed.
+    #[serde(default)]
+    pub raw_regression: RawRegressionPolicy,
+    /// Size of the buffer that files are read into when their CIDs are computed
+    /// (e.g., by audits and `generate_manifest`). Files are read in chunks of this
+    /// size rather than all at once.
+    ///
+    /// None (the default) means 1 MiB.
+    #[serde(default)]
+    pub hash_buffer_bytes: Option<usize>,
+    /// Whether the database must not be written (e.g., on a read-only volume).
+    ///
+    /// Operations that would create directories, or write Chapter files or the
+    /// manifest, return a [ReadOnlyDatabase](crate::database::lock::ReadOnlyDatabase)
+    /// error instead. Caches of verified files are not updated.
+    #[serde(default)]
+    pub read_only: bool,
+    /// Key for encrypting Chapter files at rest. Requires the `encryption` feature.
+    ///
+    /// Never serialized. Encrypted files cannot be checked against public CIDs,
+    /// see [encryption](crate::database::encryption).
+    #[serde(skip)]
+    pub encryption_key: Option<EncryptionKey>,
 }
 
 impl ConfigStruct {
+    /// Computes the CID of a file, returning it with the size of the file.
+    ///
+    /// The file is read in chunks of `hash_buffer_bytes`.
+    pub fn file_cid(&self, path: &Path) -> Result<(String, u64)> {
+        let buffer_bytes = self.hash_buffer_bytes.unwrap_or(DEFAULT_HASH_BUFFER_BYTES);
+        cid_v0_string_from_file(path, buffer_bytes)
+    }
     /// Gets the path of the manifest file.
     pub fn manifest_file_path(&self) -> Result<PathBuf> {
         let mut manifest_filename = self.data_kind.interface_id();
@@ -31,6 +187,63 @@ impl ConfigStruct {
         path.set_extension("json");
         Ok(path)
     }
+    /// Gets the path of the manifest draft, which records Chapters completed
+    /// during a build.
+    pub fn manifest_draft_path(&self) -> Result<PathBuf> {
+        let mut path = self.manifest_file_path()?;
+        path.set_extension("draft.jsonl");
+        Ok(path)
+    }
+    /// Gets the directory that holds earlier manifests, if `keep_manifest_history` is set.
+    pub fn manifest_history_dir(&self) -> PathBuf {
+        self.base_dir_nature_dependent.join(format!(
+            "{}_manifest_history",
+            self.data_kind.interface_id()
+        ))
+    }
+    /// Gets the path of a manifest in the history directory.
+    ///
+    /// E.g., `{history_dir}/nametags_manifest_0.1.0_1697000000.json` for spec version
+    /// 0.1.0, generated 1697000000 seconds after the unix epoch.
+    pub fn manifest_history_path(&self, spec_version: &str, generated: u64) -> PathBuf {
+        self.manifest_history_dir().join(format!(
+            "{}_manifest_{}_{}.json",
+            self.data_kind.interface_id(),
+            spec_version,
+            generated
+        ))
+    }
+    /// Gets the path of the holdings file, which records Volumes removed by pruning.
+    pub fn holdings_file_path(&self) -> Result<PathBuf> {
+        let mut path = self.manifest_file_path()?;
+        path.set_file_name(format!("{}_holdings.json", self.data_kind.interface_id()));
+        Ok(path)
+    }
+    /// Gets the path of the verification cache, which records the CIDs of
+    /// Chapter files checked by completeness audits.
+    pub fn verification_cache_path(&self) -> Result<PathBuf> {
+        let mut path = self.manifest_file_path()?;
+        path.set_file_name(format!("{}_verified.json", self.data_kind.interface_id()));
+        Ok(path)
+    }
+    /// Gets the path of the installed samples manifest, which records the
+    /// expected sample files.
+    pub fn samples_manifest_path(&self) -> PathBuf {
+        self.base_dir_nature_dependent
+            .join(SAMPLES_MANIFEST_FILENAME)
+    }
+    /// Gets the path of the lock file held while the database is being written.
+    pub fn lock_file_path(&self) -> Result<PathBuf> {
+        let mut path = self.manifest_file_path()?;
+        path.set_file_name(format!("{}.lock", self.data_kind.interface_id()));
+        Ok(path)
+    }
+    /// Gets the path of the compiled index (see the `compiled-index` feature).
+    pub fn compiled_index_path(&self) -> Result<PathBuf> {
+        let mut path = self.manifest_file_path()?;
+        path.set_file_name(format!("{}_compiled.redb", self.data_kind.interface_id()));
+        Ok(path)
+    }
     /// Returns the path for the directory that holds all chapters that
     /// match the given ChapterId.
     pub fn chapter_dir_path<T, U>(&self, chapter: &T) -> PathBuf
@@ -42,6 +255,42 @@ impl ConfigStruct {
         p.push(chapter.interface_id());
         p
     }
+    /// Returns the directory that holds the optional Chapter bloom filters.
+    pub fn bloom_dir(&self) -> PathBuf {
+        self.base_dir_nature_dependent
+            .join(format!("{}_bloom", self.data_kind.interface_id()))
+    }
+    /// Returns the path of the bloom filter for a Chapter.
+    pub fn bloom_file_path<T: DataSpec>(
+        &self,
+        volume_id: &T::AssociatedVolumeId,
+        chapter_id: &T::AssociatedChapterId,
+    ) -> PathBuf {
+        self.bloom_dir().join(format!(
+            "{}_{}.bloom",
+            volume_id.interface_id(),
+            chapter_id.interface_id()
+        ))
+    }
+    /// Returns the directory that holds errata, with a directory for each ChapterId.
+    pub fn errata_dir(&self) -> PathBuf {
+        self.base_dir_nature_dependent
+            .join(format!("{}_errata", self.data_kind.interface_id()))
+    }
+    /// Returns the path of an erratum for a Chapter, given the filename of the
+    /// Chapter it corrects.
+    ///
+    /// E.g., `{errata_dir}/addresses_0xab/errata_002_nametags_from_000_001_000_addresses_0xab.ssz`
+    pub fn errata_file_path<T: DataSpec>(
+        &self,
+        chapter_id: &T::AssociatedChapterId,
+        sequence: u32,
+        chapter_filename: &str,
+    ) -> PathBuf {
+        self.errata_dir()
+            .join(chapter_id.interface_id())
+            .join(format!("errata_{:03}_{}", sequence, chapter_filename))
+    }
     /// Returns the VolumeId for the latest Chapter file present.
     ///
     /// Assumes that all the Chapter directories contain data for the same Volumes.
@@ -51,16 +300,12 @@ impl ConfigStruct {
     /// navigated using the db.check_completeness() method.
     pub fn latest_volume<T: DataSpec>(&self) -> Result<T::AssociatedVolumeId> {
         // Read the first chapter directory (at random)
-        let chapter_dirs = fs::read_dir(&self.data_dir)
-            .with_context(|| format!("Couldn't read data directory {:?}.", &self.data_dir))?
-            .next();
-        let Some(first) = chapter_dirs else {
-            bail!("No chapter directories found in {:?}",
-            chapter_dirs)};
-        let first = first?.path();
-        let chapter = T::AssociatedChapterId::from_chapter_directory(&first)?;
+        let chapter_dirs = self.chapter_dirs::<T>()?;
+        let Some((_path, chapter)) = chapter_dirs.first() else {
+            bail!("No chapter directories found in {:?}", &self.data_dir)
+        };
         let vols: Vec<(PathBuf, T::AssociatedVolumeId)> =
-            self.parse_all_files_for_chapter::<T>(&chapter)?;
+            self.parse_all_files_for_chapter::<T>(chapter)?;
         let mut order: u32 = 0;
         let mut latest = T::AssociatedVolumeId::default();
         for (_path, vol) in vols {
@@ -72,31 +317,92 @@ impl ConfigStruct {
         }
         Ok(latest)
     }
+    /// Returns the Chapter directories present in the data directory.
+    ///
+    /// Entries left by other programs (e.g., ".DS_Store") are ignored.
+    ///
+    /// ## Errors
+    /// If the data directory contains entries that are not Chapter directories
+    /// for the spec (e.g., data from a different database), unless
+    /// `ignore_unknown_dirs` is set.
+    pub fn chapter_dirs<T: DataSpec>(&self) -> Result<Vec<(PathBuf, T::AssociatedChapterId)>> {
+        let paths = dir_paths(&self.data_dir)
+            .with_context(|| format!("Couldn't read data directory {:?}.", &self.data_dir))?;
+        let mut chapter_dirs = vec![];
+        let mut unknown: Vec<String> = vec![];
+        for path in paths {
+            let chapter = match path.is_dir() {
+                true => T::AssociatedChapterId::from_chapter_directory(&path).ok(),
+                false => None,
+            };
+            match chapter {
+                Some(c) => chapter_dirs.push((path, c)),
+                None => unknown.push(
+                    path.file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                ),
+            }
+        }
+        if !unknown.is_empty() {
+            unknown.sort();
+            let msg = format!(
+                "Data directory {:?} contains {} entries that are not {} Chapter directories: {:?}",
+                &self.data_dir,
+                unknown.len(),
+                self.data_kind.interface_id(),
+                unknown
+            );
+            if !self.ignore_unknown_dirs {
+                bail!(
+                    "{}. Remove them, use a different directory, or ignore unknown directories.",
+                    msg
+                )
+            }
+            warn!("{} (ignored).", msg);
+        }
+        Ok(chapter_dirs)
+    }
     /// For a given chapter returns the filenames and volume_ids it contains.
+    ///
+    /// ## Errors
+    /// If the directory contains a file that is not named like a Chapter file.
     pub fn parse_all_files_for_chapter<T: DataSpec>(
         &self,
         chapter: &T::AssociatedChapterId,
     ) -> Result<Vec<(PathBuf, T::AssociatedVolumeId)>> {
+        let (all_files, mut unknown) = self.sort_files_for_chapter::<T>(chapter)?;
+        if let Some((_path, e)) = unknown.pop() {
+            return Err(e);
+        }
+        Ok(all_files)
+    }
+    /// For a given chapter returns the filenames and volume_ids it contains,
+    /// separately from files that are not named like Chapter files (with the reason).
+    ///
+    /// Files left by other programs (e.g., ".DS_Store") are in neither.
+    pub fn sort_files_for_chapter<T: DataSpec>(
+        &self,
+        chapter: &T::AssociatedChapterId,
+    ) -> Result<ChapterDirFiles<T>> {
         let chapter_name = chapter.interface_id();
         let dir = self.chapter_dir_path(chapter);
-        let files = fs::read_dir(&dir)
+        let files = dir_paths(&dir)
             .with_context(|| format!("Couldn't read chapter directory {:?}.", &dir))?;
 
         let mut all_files: Vec<(PathBuf, T::AssociatedVolumeId)> = vec![];
-        for chapterfile in files {
-            let file = chapterfile?;
-            let filename = file.file_name();
-            let Some(filename) = filename.to_str() else {bail!("Couldn't read filename {:?}.", file)};
-            let without_chapter = filename.replace(&chapter_name, "");
-            let Some((volume_str, _suffix)) = without_chapter.split_once("_.") else {
-                bail!("Filename could not be split by '_' and '.': {}", filename)};
-            let vol_id = T::AssociatedVolumeId::from_interface_id(volume_str)?;
-            all_files.push((file.path(), vol_id))
+        let mut unknown: Vec<(PathBuf, anyhow::Error)> = vec![];
+        for path in files {
+            let filename = path.file_name().unwrap_or_default();
+            match volume_of_chapter_file::<T>(filename, &chapter_name) {
+                Ok(vol_id) => all_files.push((path, vol_id)),
+                Err(e) => unknown.push((path, e)),
+            }
         }
-        Ok(all_files)
+        Ok((all_files, unknown))
     }
     /// Gets the path of the local repository sample data.
-    fn local_sample_base_dir(&self) -> PathBuf {
+    pub(crate) fn local_sample_base_dir(&self) -> PathBuf {
         PathBuf::from("./data/samples").join(self.data_kind.as_todd_string())
     }
     /// Gets the path of the local repository processed sample data.
@@ -104,6 +410,10 @@ impl ConfigStruct {
         self.local_sample_base_dir()
             .join(self.data_kind.as_string())
     }
+    /// Gets the path of the local repository samples manifest.
+    pub fn local_samples_manifest_path(&self) -> PathBuf {
+        self.local_sample_base_dir().join(SAMPLES_MANIFEST_FILENAME)
+    }
     /// Gets the path of the local repository raw sample data.
     pub fn local_sample_raw_source(&self) -> PathBuf {
         self.local_sample_base_dir()
@@ -111,6 +421,28 @@ impl ConfigStruct {
     }
 }
 
+/// Files in a Chapter directory: (Chapter files with their VolumeId, other files
+/// with the reason they are not Chapter files).
+pub type ChapterDirFiles<T> = (
+    Vec<(PathBuf, <T as DataSpec>::AssociatedVolumeId)>,
+    Vec<(PathBuf, anyhow::Error)>,
+);
+
+/// Gets the VolumeId from the name of a file in a Chapter directory.
+fn volume_of_chapter_file<T: DataSpec>(
+    filename: &OsStr,
+    chapter_name: &str,
+) -> Result<T::AssociatedVolumeId> {
+    let Some(filename) = filename.to_str() else {
+        bail!("Filename is not valid UTF-8: {:?}.", filename)
+    };
+    let without_chapter = filename.replace(chapter_name, "");
+    let Some((volume_str, _suffix)) = without_chapter.split_once("_.") else {
+        bail!("Filename could not be split by '_' and '.': {}", filename)
+    };
+    T::AssociatedVolumeId::from_interface_id(volume_str)
+}
+
 #[test]
 fn config_local_paths_correct_for_nametags() {
     let config = DirNature::Sample.into_config(DataKind::NameTags).unwrap();
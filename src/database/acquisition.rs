@@ -0,0 +1,153 @@
+//! Plans for obtaining parts of a database from a network.
+//!
+//! A plan is made locally (no network activity) so that it can be reviewed
+//! before any files are downloaded.
+//!
+//! ## Privacy
+//! A gateway learns which Chapters are downloaded, and so which group of keys
+//! the user is interested in. Setting `ConfigStruct::decoy_chapters` adds that
+//! many randomly chosen Chapters to each plan. The gateway then only learns that
+//! the keys of interest are in one of the Chapters of the anonymity set (see
+//! [AcquisitionPlan::anonymity_set]). Queries are made locally and are not seen
+//! by the gateway.
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The files required to answer queries for a set of keys.
+///
+/// Created by [Todd::plan_acquisition][crate::database::types::Todd::plan_acquisition]
+/// and carried out by [Todd::execute_plan][crate::database::types::Todd::execute_plan].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AcquisitionPlan {
+    /// Interface ids of the Chapters that hold the requested keys.
+    pub chapters: Vec<String>,
+    /// Interface ids of Chapters chosen at random to be obtained alongside
+    /// the requested Chapters.
+    #[serde(default)]
+    pub decoys: Vec<String>,
+    /// Chapter files listed in the manifest for the requested and decoy Chapters,
+    /// ordered by Chapter.
+    pub files: Vec<PlannedFile>,
+}
+
+/// A Chapter file listed in the manifest.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedFile {
+    pub cid: String,
+    pub volume_interface_id: String,
+    pub chapter_interface_id: String,
+    /// Directory that the file will be created in.
+    pub dest_dir: PathBuf,
+    pub filename: String,
+    /// Size of the file if it is already present locally.
+    ///
+    /// The manifest does not record file sizes, so the size of files
+    /// that are yet to be downloaded is not known in advance.
+    pub local_bytes: Option<u64>,
+}
+
+impl AcquisitionPlan {
+    /// Files that are not yet present locally.
+    pub fn files_to_download(&self) -> impl Iterator<Item = &PlannedFile> {
+        self.files.iter().filter(|f| f.local_bytes.is_none())
+    }
+    /// Total size of the planned files that are already present locally.
+    pub fn local_bytes(&self) -> u64 {
+        self.files.iter().filter_map(|f| f.local_bytes).sum()
+    }
+    /// The requested and decoy Chapters (sorted), which a gateway cannot tell apart.
+    pub fn anonymity_set(&self) -> Vec<String> {
+        let mut set: Vec<String> = self.chapters.iter().chain(&self.decoys).cloned().collect();
+        set.sort();
+        set.dedup();
+        set
+    }
+}
+
+/// How [Todd::find][crate::database::types::Todd::find] obtains a Chapter that
+/// is absent locally. Requires the `net` feature.
+///
+/// The files obtained are those of [AcquisitionPlan] for the key, so decoy
+/// Chapters (`ConfigStruct::decoy_chapters`) are obtained as well.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct FetchOnMiss {
+    /// The gateway to obtain files from. E.g., "http://127.0.0.1:8080".
+    pub gateway_url: String,
+    /// Whether the gateway is subdomain style, rather than path style.
+    #[serde(default)]
+    pub subdomain_gateway: bool,
+    /// The most bytes downloaded for a query.
+    ///
+    /// The manifest does not record file sizes, so a file is downloaded until
+    /// it would exceed the bytes remaining, and then discarded.
+    pub max_bytes: u64,
+}
+
+#[cfg(feature = "net")]
+impl FetchOnMiss {
+    pub fn gateway(&self) -> anyhow::Result<crate::utils::gateway::Gateway> {
+        use crate::utils::gateway::Gateway;
+        match self.subdomain_gateway {
+            true => Gateway::subdomain(&self.gateway_url),
+            false => Gateway::new(&self.gateway_url),
+        }
+    }
+}
+
+/// Chooses up to `n` of the candidates at random.
+///
+/// The choice differs between processes (the randomness comes from the
+/// operating system), but is not suitable for cryptographic purposes.
+pub(crate) fn choose_at_random<C>(mut candidates: Vec<C>, n: usize) -> Vec<C> {
+    let state = RandomState::new();
+    let n = n.min(candidates.len());
+    for i in 0..n {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        let j = i + hasher.finish() as usize % (candidates.len() - i);
+        candidates.swap(i, j);
+    }
+    candidates.truncate(n);
+    candidates
+}
+
+/// The outcome of obtaining a single Chapter file.
+///
+/// Returned by [Todd::obtain_chapter][crate::database::types::Todd::obtain_chapter].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ObtainedChapter {
+    /// The local file already has the CID in the manifest. Nothing was downloaded.
+    AlreadyPresent(PathBuf),
+    /// The file was downloaded, verified and installed.
+    Downloaded { path: PathBuf, bytes: u64 },
+    /// A local file with a different CID was replaced by the downloaded file.
+    Replaced { path: PathBuf, bytes: u64 },
+}
+
+impl ObtainedChapter {
+    /// Path of the installed Chapter file.
+    pub fn path(&self) -> &Path {
+        match self {
+            ObtainedChapter::AlreadyPresent(path) => path,
+            ObtainedChapter::Downloaded { path, .. } => path,
+            ObtainedChapter::Replaced { path, .. } => path,
+        }
+    }
+}
+
+#[test]
+fn random_choice_is_a_subset() {
+    let candidates: Vec<u32> = (0..256).collect();
+    let mut chosen = choose_at_random(candidates.clone(), 8);
+    assert_eq!(chosen.len(), 8);
+    chosen.sort();
+    chosen.dedup();
+    assert_eq!(chosen.len(), 8);
+    assert!(chosen.iter().all(|c| candidates.contains(c)));
+    assert_eq!(choose_at_random(vec![1, 2], 5).len(), 2);
+}
@@ -0,0 +1,126 @@
+//! Helpers specific to the address appearance index database.
+use anyhow::Result;
+
+use crate::{
+    specs::{
+        address_appearance_index::{AAISpec, AAIVolumeId},
+        traits::{DataSpec, ManifestMethods, VolumeIdMethods},
+    },
+    utils::unchained::types::BlockRange,
+};
+
+use super::types::Todd;
+
+/// Block coverage of local or published data.
+///
+/// ## Example
+/// ```
+/// # use min_know::{
+/// #    config::{address_appearance_index::Network, choices::{DataKind, DirNature}},
+/// #    database::{appearances::AppearancesClient, types::Todd},
+/// # };
+/// let data_kind = DataKind::AddressAppearanceIndex(Network::default());
+/// let client = AppearancesClient::new(Todd::init(data_kind, DirNature::Sample)?);
+/// let local = client.local_coverage()?;
+/// assert!(local.covers(11_250_000));
+/// assert!(!local.covers(17_345_678));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct AppearancesClient {
+    pub db: Todd<AAISpec>,
+}
+
+/// The blocks of a Volume and how many of its Chapters are held.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VolumeBlocks {
+    pub volume_interface_id: String,
+    pub range: BlockRange,
+    /// Number of Chapters of the Volume that are held.
+    pub chapters: usize,
+}
+
+impl VolumeBlocks {
+    /// Whether all Chapters of the Volume are held.
+    pub fn is_complete(&self) -> bool {
+        self.chapters == AAISpec::NUM_CHAPTERS
+    }
+}
+
+/// The Volumes held, ordered by block.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CoverageMap {
+    pub volumes: Vec<VolumeBlocks>,
+}
+
+impl CoverageMap {
+    /// The Volume that holds appearances for a block, if any of its Chapters are held.
+    pub fn volume_for(&self, block: u32) -> Option<&VolumeBlocks> {
+        self.volumes.iter().find(|v| v.range.contains_block(block))
+    }
+    /// Whether all Chapters are held for the Volume that contains the block.
+    ///
+    /// A user that holds only some Chapters (e.g., with `config.watched_keys`)
+    /// can check [Self::volume_for] instead.
+    pub fn covers(&self, block: u32) -> bool {
+        self.volume_for(block)
+            .is_some_and(VolumeBlocks::is_complete)
+    }
+    /// The blocks covered by complete Volumes, with adjacent Volumes joined.
+    pub fn ranges(&self) -> Vec<BlockRange> {
+        let mut ranges: Vec<BlockRange> = vec![];
+        for v in self.volumes.iter().filter(|v| v.is_complete()) {
+            match ranges.last_mut() {
+                Some(last) if last.new.checked_add(1) == Some(v.range.old) => {
+                    last.new = v.range.new
+                }
+                _ => ranges.push(v.range),
+            }
+        }
+        ranges
+    }
+    /// Counts the Chapters of each Volume.
+    fn from_volume_ids(mut volume_ids: Vec<AAIVolumeId>) -> Result<Self> {
+        let mut map = CoverageMap::default();
+        volume_ids.sort_by_key(|v| v.oldest_block);
+        for volume_id in volume_ids {
+            match map.volumes.last_mut() {
+                Some(last) if last.volume_interface_id == volume_id.interface_id() => {
+                    last.chapters += 1
+                }
+                _ => map.volumes.push(VolumeBlocks {
+                    volume_interface_id: volume_id.interface_id(),
+                    range: volume_id.block_range()?,
+                    chapters: 1,
+                }),
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl AppearancesClient {
+    pub fn new(db: Todd<AAISpec>) -> Self {
+        AppearancesClient { db }
+    }
+    /// The blocks covered by the Chapter files that exist in the file system.
+    pub fn local_coverage(&self) -> Result<CoverageMap> {
+        let volume_ids = self
+            .db
+            .inventory()?
+            .into_iter()
+            .map(|(volume_id, _chapter_id, _path, _size)| volume_id)
+            .collect();
+        CoverageMap::from_volume_ids(volume_ids)
+    }
+    /// The blocks covered by the Chapter files in the manifest.
+    pub fn published_coverage(&self) -> Result<CoverageMap> {
+        let volume_ids = self
+            .db
+            .manifest()?
+            .cids()?
+            .into_iter()
+            .map(|m| m.volume_id)
+            .collect();
+        CoverageMap::from_volume_ids(volume_ids)
+    }
+}
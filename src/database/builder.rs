@@ -0,0 +1,158 @@
+//! Forming Chapters from Records made in-process, rather than from raw files.
+//!
+//! A publisher that computes its data directly (e.g., nametags from an internal
+//! database) adds (RecordKey, RecordValue) pairs to a [ChapterBuilder] for each
+//! Chapter, saves the built Chapters with
+//! [Todd::save_built_chapters](crate::database::types::Todd::save_built_chapters)
+//! and then generates the manifest as usual. Requires the `publisher` feature.
+//!
+//! ## Example
+//! ```ignore
+//! let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Default)?;
+//! let volume_id = NameTagsVolumeId::nth_id(0)?;
+//! let chapter_id = NameTagsChapterId::from_interface_id("addresses_0xde")?;
+//! let mut builder = ChapterBuilder::<NameTagsSpec>::new(&volume_id, &chapter_id)?;
+//! builder.add_raw(
+//!     "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae",
+//!     NameTagsRecordValue::from_strings(vec!["Ethereum Foundation".to_string()], vec![]),
+//! )?;
+//! db.save_built_chapters(vec![builder.build()?])?;
+//! db.generate_manifest()?;
+//! ```
+use anyhow::{bail, Result};
+
+use crate::{
+    extraction::traits::canonical_order,
+    specs::traits::{
+        ChapterIdMethods, ChapterMethods, DataSpec, RecordKeyMethods, RecordMethods,
+        VolumeIdMethods,
+    },
+};
+
+/// Collects the Records of one Chapter and forms the Chapter.
+///
+/// Records are checked as they are added: the key must belong to the Chapter
+/// (see [DataSpec::record_key_to_chapter_id]) and may only be added once. The
+/// built Chapter holds the Records in canonical order, so it does not depend on
+/// the order they were added.
+#[derive(Clone, Debug)]
+pub struct ChapterBuilder<T: DataSpec> {
+    volume_id: T::AssociatedVolumeId,
+    chapter_id: T::AssociatedChapterId,
+    records: Vec<T::AssociatedRecord>,
+}
+
+impl<T: DataSpec> ChapterBuilder<T> {
+    /// Starts an empty Chapter.
+    ///
+    /// ## Errors
+    /// If the VolumeId is not the start of a Volume (see [VolumeIdMethods::nth_id]).
+    pub fn new(
+        volume_id: &T::AssociatedVolumeId,
+        chapter_id: &T::AssociatedChapterId,
+    ) -> Result<Self> {
+        let nth = volume_id.is_nth()?;
+        if &T::AssociatedVolumeId::nth_id(nth)? != volume_id {
+            bail!("{} is not the start of a Volume.", volume_id.interface_id())
+        }
+        Ok(ChapterBuilder {
+            volume_id: volume_id.clone(),
+            chapter_id: chapter_id.clone(),
+            records: vec![],
+        })
+    }
+    /// Adds a Record.
+    ///
+    /// ## Errors
+    /// If the key belongs to a different Chapter, or already has a Record.
+    pub fn add(
+        &mut self,
+        key: T::AssociatedRecordKey,
+        value: T::AssociatedRecordValue,
+    ) -> Result<&mut Self> {
+        let chapter_id = T::record_key_to_chapter_id(&key)?;
+        if chapter_id != self.chapter_id {
+            bail!(
+                "Key {} belongs to Chapter {}, not {}.",
+                key.summary_string()?,
+                chapter_id.interface_id(),
+                self.chapter_id.interface_id()
+            )
+        }
+        if self.records.iter().any(|r| r.key() == &key) {
+            bail!(
+                "Key {} was already added to Chapter {}.",
+                key.summary_string()?,
+                self.chapter_id.interface_id()
+            )
+        }
+        self.records
+            .push(T::AssociatedRecord::from_key_value(key, value));
+        Ok(self)
+    }
+    /// Adds a Record for a raw key (e.g., "0x" prefixed hex), as accepted by
+    /// [Todd::find](crate::database::types::Todd::find).
+    pub fn add_raw(&mut self, raw_key: &str, value: T::AssociatedRecordValue) -> Result<&mut Self> {
+        self.add(T::raw_key_as_record_key(raw_key)?, value)
+    }
+    /// The number of Records added.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+    /// Forms the Chapter, with the Records in canonical order.
+    ///
+    /// ## Errors
+    /// If there are more Records than a Chapter may hold.
+    pub fn build(self) -> Result<T::AssociatedChapter> {
+        let records = canonical_order::<T>(self.records)?;
+        T::AssociatedChapter::from_records(&self.volume_id, &self.chapter_id, records)
+    }
+}
+
+#[test]
+fn builder_enforces_membership() {
+    use crate::specs::nametags::{
+        NameTagsChapterId, NameTagsRecordValue, NameTagsSpec, NameTagsVolumeId,
+    };
+
+    let volume_id = NameTagsVolumeId::nth_id(1).unwrap();
+    let chapter_id = NameTagsChapterId::from_interface_id("addresses_0xde").unwrap();
+    let value = |name: &str| NameTagsRecordValue::from_strings(vec![name.to_string()], vec![]);
+    let mut builder = ChapterBuilder::<NameTagsSpec>::new(&volume_id, &chapter_id).unwrap();
+    builder
+        .add_raw("0xdeffffffffffffffffffffffffffffffffffffff", value("b"))
+        .unwrap()
+        .add_raw("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae", value("a"))
+        .unwrap();
+    assert!(builder
+        .add_raw("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae", value("c"))
+        .is_err());
+    assert!(builder
+        .add_raw("0x000b295669a9fd93d5f28d9ec85e40f4cb697bae", value("d"))
+        .is_err());
+    assert_eq!(builder.len(), 2);
+
+    // Records are in canonical order, whatever order they were added in.
+    let chapter = builder.build().unwrap();
+    let keys: Vec<String> = chapter
+        .records()
+        .iter()
+        .map(|r| r.key().summary_string().unwrap())
+        .collect();
+    assert_eq!(
+        keys,
+        vec![
+            "de0b295669a9fd93d5f28d9ec85e40f4cb697bae",
+            "deffffffffffffffffffffffffffffffffffffff"
+        ]
+    );
+    assert_eq!(chapter.volume_id(), &volume_id);
+
+    let mid_volume = NameTagsVolumeId {
+        first_address: 1500,
+    };
+    assert!(ChapterBuilder::<NameTagsSpec>::new(&mid_volume, &chapter_id).is_err());
+}
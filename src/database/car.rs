@@ -0,0 +1,227 @@
+//! A whole database as a single content addressable archive (CAR) file.
+//!
+//! Distributors can seed a database to IPFS (or hand it to a pinning service)
+//! in one transfer, and users can install a database from an archive.
+//!
+//! The manifest is the root block of the archive, followed by a block for each
+//! Chapter file in the manifest. Blocks have CIDv1 with the raw codec and the
+//! same sha256 hash as the CIDv0 in the manifest.
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use cid::Cid;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::types::Todd,
+    manifest::trust::check_trusted_manifest,
+    specs::{
+        fingerprint::check_manifest_schema,
+        traits::{ChapterMethods, DataSpec, ManifestMethods},
+    },
+    utils::car::{CarBlock, CarReader, CarWriter, RAW},
+};
+
+/// The outcome of [Todd::export_car].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CarExport {
+    /// CIDv1 of the manifest, which is the root of the archive.
+    pub root: String,
+    /// Number of Chapter files in the archive.
+    pub chapters: usize,
+    /// Chapter files in the manifest that were absent locally, or did not
+    /// match the manifest, and so are not in the archive.
+    pub missing: Vec<PathBuf>,
+}
+
+/// The outcome of [Todd::import_car].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CarImport {
+    /// Chapter files written to the data directory.
+    pub written: Vec<PathBuf>,
+    /// Chapter files in the manifest that are not in the archive.
+    pub missing: Vec<PathBuf>,
+    /// Number of blocks in the archive that are not in the manifest.
+    pub unused_blocks: usize,
+}
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Writes the manifest and all Chapter files in it to a CAR file.
+    ///
+    /// Only Chapter files with the CID in the manifest are included. Others are
+    /// listed in the result. The archive can be imported with [Self::import_car],
+    /// or by IPFS software (e.g., `ipfs dag import`).
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<SignaturesSpec> = Todd::init(DataKind::Signatures, DirNature::Sample)?;
+    /// let export = db.export_car(Path::new("signatures.car"))?;
+    /// println!("Archive root: {}", export.root);
+    /// ```
+    pub fn export_car(&self, path: &Path) -> Result<CarExport> {
+        self.config.require_unencrypted("export_car")?;
+        let manifest_path = self.config.manifest_file_path()?;
+        let manifest_bytes = fs::read(&manifest_path)
+            .with_context(|| format!("Failed to read manifest: {:?}", &manifest_path))?;
+        let manifest = self.manifest()?;
+        let root = CarBlock::raw(manifest_bytes);
+        let file = File::create(path).with_context(|| format!("Unable to create {:?}", path))?;
+        let mut writer = CarWriter::new(BufWriter::new(file), &root.cid)?;
+        writer.write_block(&root)?;
+        let mut export = CarExport {
+            root: root.cid.to_string(),
+            ..Default::default()
+        };
+        for m in manifest.cids()? {
+            let filename = T::AssociatedChapter::new_empty(&m.volume_id, &m.chapter_id).filename();
+            let chapter_path = self.config.chapter_dir_path(&m.chapter_id).join(filename);
+            let block = match fs::read(&chapter_path) {
+                Ok(data) => CarBlock {
+                    cid: raw_cid(&m.cid)?,
+                    data,
+                },
+                Err(_) => {
+                    export.missing.push(chapter_path);
+                    continue;
+                }
+            };
+            if block.verify().is_err() {
+                warn!(
+                    "Not exporting {:?}, which differs from the manifest.",
+                    chapter_path
+                );
+                export.missing.push(chapter_path);
+                continue;
+            }
+            writer.write_block(&block)?;
+            export.chapters += 1;
+        }
+        writer.finish()?;
+        info!(
+            "Exported {} Chapter files to {:?} ({} missing).",
+            export.chapters,
+            path,
+            export.missing.len()
+        );
+        Ok(export)
+    }
+    /// Installs the manifest and Chapter files from a CAR file made by [Self::export_car].
+    ///
+    /// ## Algorithm
+    /// 1. Read each block, check that it matches its CID, and write it to a
+    ///    staging directory alongside the manifest.
+    /// 2. Parse the root block as the manifest and check that it is trusted
+    ///    (see [crate::manifest::trust]) and for this database.
+    /// 3. Copy the blocks for the Chapter files in the manifest into place.
+    /// 4. Replace the local manifest.
+    ///
+    /// ## Errors
+    /// If any block does not match its CID, or the root is not a trusted manifest
+    /// for this database. Nothing is installed in that case.
+    pub fn import_car(&self, path: &Path) -> Result<CarImport> {
+        self.config.require_unencrypted("import_car")?;
+        let _lock = self.write_lock("import_car")?;
+        let staging = self.config.base_dir_nature_dependent.join(format!(
+            "{}_car_import",
+            self.config.data_kind.interface_id()
+        ));
+        fs::create_dir_all(&staging)?;
+        let result = self.import_car_staged(path, &staging);
+        if let Err(e) = fs::remove_dir_all(&staging) {
+            warn!("Could not remove staging directory {:?}: {}", staging, e);
+        }
+        result
+    }
+    fn import_car_staged(&self, path: &Path, staging: &Path) -> Result<CarImport> {
+        let file = File::open(path).with_context(|| format!("Unable to open {:?}", path))?;
+        let mut reader = CarReader::new(BufReader::new(file))?;
+        let [root] = reader.roots.as_slice() else {
+            bail!(
+                "The archive has {} roots, expected one (the manifest).",
+                reader.roots.len()
+            )
+        };
+        let root = *root;
+        let mut manifest_bytes: Option<Vec<u8>> = None;
+        // Staged blocks, keyed by hash.
+        let mut staged: HashMap<Vec<u8>, PathBuf> = HashMap::new();
+        while let Some(block) = reader.next_block()? {
+            block.verify()?;
+            if block.cid == root {
+                manifest_bytes = Some(block.data);
+                continue;
+            }
+            let digest = block.cid.hash().to_bytes();
+            let staged_path = staging.join(hex::encode(&digest));
+            fs::write(&staged_path, &block.data)?;
+            staged.insert(digest, staged_path);
+        }
+        let Some(manifest_bytes) = manifest_bytes else {
+            bail!("The archive does not contain its root block (the manifest).")
+        };
+        check_trusted_manifest(&manifest_bytes, &self.config.trusted_manifest_cids)
+            .context("Not importing the archive")?;
+        let manifest: T::AssociatedManifest = serde_json::from_slice(&manifest_bytes)
+            .context("The archive root is not a manifest for this database")?;
+        check_manifest_schema::<T>(&manifest).context("Not importing the archive")?;
+        let expected = self.config.data_kind.interface_id();
+        if manifest.database_interface_id() != expected {
+            bail!(
+                "The archive is for database {}, not {}.",
+                manifest.database_interface_id(),
+                expected
+            )
+        }
+        let mut import = CarImport::default();
+        let mut used: Vec<&Vec<u8>> = vec![];
+        for m in manifest.cids()? {
+            let dest_dir = self.config.chapter_dir_path(&m.chapter_id);
+            let filename = T::AssociatedChapter::new_empty(&m.volume_id, &m.chapter_id).filename();
+            let dest = dest_dir.join(&filename);
+            let digest = raw_cid(&m.cid)?.hash().to_bytes();
+            let Some((digest, staged_path)) = staged.get_key_value(&digest) else {
+                import.missing.push(dest);
+                continue;
+            };
+            fs::create_dir_all(&dest_dir)?;
+            let partial = dest_dir.join(format!("{}.partial", filename));
+            // Identical Chapters share a block, so the block is copied rather than moved.
+            fs::copy(staged_path, &partial)
+                .with_context(|| format!("Unable to write file {:?}", partial))?;
+            fs::rename(&partial, &dest)
+                .with_context(|| format!("Unable to move {:?} to {:?}", partial, dest))?;
+            used.push(digest);
+            import.written.push(dest);
+        }
+        used.sort();
+        used.dedup();
+        import.unused_blocks = staged.len() - used.len();
+        let manifest_path = self.config.manifest_file_path()?;
+        let partial = manifest_path.with_extension("json.partial");
+        fs::write(&partial, &manifest_bytes)?;
+        fs::rename(&partial, &manifest_path)?;
+        info!(
+            "Imported {} Chapter files from {:?} ({} missing).",
+            import.written.len(),
+            path,
+            import.missing.len()
+        );
+        Ok(import)
+    }
+}
+
+/// The CIDv1 (raw) with the same hash as a CID from a manifest.
+fn raw_cid(manifest_cid: &str) -> Result<Cid> {
+    let cid = Cid::try_from(manifest_cid)
+        .with_context(|| format!("Invalid CID in manifest: {}", manifest_cid))?;
+    Ok(Cid::new_v1(RAW, *cid.hash()))
+}
@@ -0,0 +1,289 @@
+//! Summaries of what a newer manifest adds to a local database.
+//!
+//! A user with a local manifest can compare it to a newer one (e.g., fetched from
+//! the publisher) before obtaining anything, and be told what is new. E.g., "34 new
+//! Volumes covering blocks 14400000-17799999".
+//!
+//! The summary is serializable for applications and has a human readable form
+//! (its `Display` implementation).
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    manifest::{provenance::ManifestProvenance, succession::ManifestSuccessor},
+    specs::traits::{ChapterIdMethods, DataSpec, ManifestMethods, VolumeCoverage, VolumeIdMethods},
+};
+
+/// The differences between a local manifest and a newer one.
+///
+/// Created by [Todd::manifest_changes][crate::database::types::Todd::manifest_changes].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestChanges {
+    /// E.g., "nametags".
+    pub spec_id: String,
+    /// Spec version of the local manifest.
+    pub previous_spec_version: String,
+    /// Spec version of the newer manifest.
+    pub spec_version: String,
+    /// Interface id of the latest Volume in the local manifest.
+    pub previous_latest_volume: String,
+    /// Interface id of the latest Volume in the newer manifest.
+    pub latest_volume: String,
+    /// Interface ids of Volumes that only the newer manifest has files for, oldest first.
+    pub new_volumes: Vec<String>,
+    /// The raw data covered by the new Volumes, if the spec defines it.
+    ///
+    /// From the start of the oldest new Volume to the end of the latest.
+    pub new_coverage: Option<VolumeCoverage>,
+    /// Interface ids of Chapters that only the newer manifest has files for.
+    pub new_chapters: Vec<String>,
+    /// Files that are only in the newer manifest.
+    pub new_files: usize,
+    /// Files in both manifests, with a different CID in the newer manifest.
+    pub changed_files: Vec<FileChange>,
+    /// Files that are only in the local manifest.
+    pub removed_files: usize,
+    /// The changes that affect the keys of interest.
+    pub for_keys: KeyChanges,
+    /// Upstream source and license stated in the newer manifest.
+    #[serde(default)]
+    pub provenance: ManifestProvenance,
+    /// The database that replaces this one, as stated in the newer manifest.
+    #[serde(default)]
+    pub superseded_by: Option<ManifestSuccessor>,
+}
+
+/// A Chapter file listed in the newer manifest.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    pub volume_interface_id: String,
+    pub chapter_interface_id: String,
+    /// CIDv0 in the newer manifest.
+    pub cid: String,
+}
+
+/// The new and changed files in the Chapters for a set of keys.
+///
+/// These are the files a user interested in the keys would obtain to update
+/// their local database.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyChanges {
+    /// Interface ids of the Chapters that hold the keys.
+    pub chapters: Vec<String>,
+    /// New or changed files in those Chapters.
+    pub files: Vec<FileChange>,
+    /// Approximate size of the files.
+    ///
+    /// The manifest does not record file sizes, so this is estimated from the
+    /// average size of the local Chapter files. None if there are none.
+    pub estimated_bytes: Option<u64>,
+}
+
+impl ManifestChanges {
+    /// Compares two manifests.
+    ///
+    /// The Chapters are those that hold the keys of interest and
+    /// `average_file_bytes` is used to estimate the size of their files.
+    pub(crate) fn new<T: DataSpec>(
+        previous: &T::AssociatedManifest,
+        next: &T::AssociatedManifest,
+        chapter_ids: &[T::AssociatedChapterId],
+        average_file_bytes: Option<u64>,
+    ) -> Result<Self> {
+        let mut previous_cids: HashMap<(String, String), String> = HashMap::new();
+        let mut previous_volumes: Vec<String> = vec![];
+        let mut previous_chapters: Vec<String> = vec![];
+        for m in previous.cids()? {
+            let key = (m.volume_id.interface_id(), m.chapter_id.interface_id());
+            if !previous_volumes.contains(&key.0) {
+                previous_volumes.push(key.0.clone());
+            }
+            if !previous_chapters.contains(&key.1) {
+                previous_chapters.push(key.1.clone());
+            }
+            previous_cids.insert(key, m.cid);
+        }
+        let mut new_volume_ids: Vec<T::AssociatedVolumeId> = vec![];
+        let mut new_chapters: Vec<String> = vec![];
+        let mut new_files = 0;
+        let mut changed_files: Vec<FileChange> = vec![];
+        let mut for_keys = KeyChanges {
+            chapters: chapter_ids.iter().map(|c| c.interface_id()).collect(),
+            ..Default::default()
+        };
+        let mut listed = 0;
+        for m in next.cids()? {
+            listed += 1;
+            let key = (m.volume_id.interface_id(), m.chapter_id.interface_id());
+            if !previous_volumes.contains(&key.0) && !new_volume_ids.contains(&m.volume_id) {
+                new_volume_ids.push(m.volume_id.clone());
+            }
+            if !previous_chapters.contains(&key.1) && !new_chapters.contains(&key.1) {
+                new_chapters.push(key.1.clone());
+            }
+            let change = FileChange {
+                volume_interface_id: key.0.clone(),
+                chapter_interface_id: key.1.clone(),
+                cid: m.cid.clone(),
+            };
+            match previous_cids.get(&key) {
+                Some(cid) if cid == &m.cid => continue,
+                Some(_) => changed_files.push(change.clone()),
+                None => new_files += 1,
+            }
+            if chapter_ids.contains(&m.chapter_id) {
+                for_keys.files.push(change);
+            }
+        }
+        let removed_files = previous_cids.len().saturating_sub(listed - new_files);
+        for_keys.estimated_bytes =
+            average_file_bytes.map(|bytes| bytes * for_keys.files.len() as u64);
+
+        let mut ordered: Vec<(u32, T::AssociatedVolumeId)> = vec![];
+        for volume_id in new_volume_ids {
+            ordered.push((volume_id.is_nth()?, volume_id));
+        }
+        ordered.sort_by_key(|(n, _)| *n);
+        let new_coverage = match (ordered.first(), ordered.last()) {
+            (Some((_, oldest)), Some((_, latest))) => {
+                match (oldest.coverage(), latest.coverage()) {
+                    (Some(oldest), Some(latest)) => Some(VolumeCoverage {
+                        unit: oldest.unit,
+                        first: oldest.first,
+                        last: latest.last,
+                    }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        Ok(ManifestChanges {
+            spec_id: T::SPEC_ID.to_string(),
+            previous_spec_version: previous.spec_version().to_string(),
+            spec_version: next.spec_version().to_string(),
+            previous_latest_volume: previous.latest_volume_identifier().to_string(),
+            latest_volume: next.latest_volume_identifier().to_string(),
+            new_volumes: ordered.iter().map(|(_, v)| v.interface_id()).collect(),
+            new_coverage,
+            new_chapters,
+            new_files,
+            changed_files,
+            removed_files,
+            for_keys,
+            provenance: next.provenance().clone(),
+            superseded_by: next.superseded_by().cloned(),
+        })
+    }
+    /// Whether the newer manifest lists the same files as the local manifest.
+    ///
+    /// A newly stated successor is not a change to the files (see `superseded_by`).
+    pub fn is_empty(&self) -> bool {
+        self.new_files == 0 && self.changed_files.is_empty() && self.removed_files == 0
+    }
+}
+
+impl std::fmt::Display for ManifestChanges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(successor) = &self.superseded_by {
+            writeln!(f, "{}", successor)?;
+        }
+        if self.is_empty() {
+            return write!(f, "No changes to {}.", self.spec_id);
+        }
+        if !self.previous_spec_version.is_empty() && self.spec_version != self.previous_spec_version
+        {
+            writeln!(
+                f,
+                "Spec version {} (was {}).",
+                self.spec_version, self.previous_spec_version
+            )?;
+        }
+        if !self.provenance.is_empty() {
+            writeln!(f, "{}", self.provenance)?;
+        }
+        if let (Some(first), Some(last)) = (self.new_volumes.first(), self.new_volumes.last()) {
+            write!(
+                f,
+                "{} new Volumes ({} to {})",
+                self.new_volumes.len(),
+                first,
+                last
+            )?;
+            if let Some(coverage) = &self.new_coverage {
+                write!(
+                    f,
+                    " covering {} {}-{}",
+                    coverage.unit, coverage.first, coverage.last
+                )?;
+            }
+            writeln!(f, ".")?;
+        }
+        if !self.new_chapters.is_empty() {
+            writeln!(f, "{} new Chapters.", self.new_chapters.len())?;
+        }
+        writeln!(
+            f,
+            "{} new, {} changed and {} removed files.",
+            self.new_files,
+            self.changed_files.len(),
+            self.removed_files
+        )?;
+        write!(
+            f,
+            "{} files to obtain for the {} Chapters of interest",
+            self.for_keys.files.len(),
+            self.for_keys.chapters.len()
+        )?;
+        match self.for_keys.estimated_bytes {
+            Some(bytes) => write!(f, " (about {} bytes).", bytes),
+            None => write!(f, "."),
+        }
+    }
+}
+
+#[test]
+fn changes_between_manifests() {
+    use crate::{
+        manifest::nametags::NameTagsManifest,
+        specs::nametags::{NameTagsChapterId, NameTagsSpec, NameTagsVolumeId},
+    };
+
+    let volume = |n| NameTagsVolumeId::nth_id(n).unwrap();
+    let chapter = |n| NameTagsChapterId::nth_id(n).unwrap();
+    let mut previous = NameTagsManifest::default();
+    previous.set_cids(&[
+        ("cid_a", volume(0), chapter(0)),
+        ("cid_b", volume(0), chapter(1)),
+    ]);
+    let mut next = NameTagsManifest::default();
+    next.provenance.license = Some("CC0-1.0".to_string());
+    next.set_cids(&[
+        ("cid_a", volume(0), chapter(0)),
+        ("cid_c", volume(0), chapter(1)),
+        ("cid_d", volume(1), chapter(1)),
+        ("cid_e", volume(2), chapter(2)),
+    ]);
+    let changes =
+        ManifestChanges::new::<NameTagsSpec>(&previous, &next, &[chapter(1)], Some(100)).unwrap();
+    assert_eq!(changes.new_volumes.len(), 2);
+    let coverage = changes.new_coverage.clone().unwrap();
+    assert_eq!((coverage.first, coverage.last), (1000, 2999));
+    assert_eq!(changes.new_chapters, vec![chapter(2).interface_id()]);
+    assert_eq!(changes.new_files, 2);
+    assert_eq!(changes.changed_files.len(), 1);
+    assert_eq!(changes.removed_files, 0);
+    // The changed and new files of the Chapter of interest.
+    assert_eq!(changes.for_keys.files.len(), 2);
+    assert_eq!(changes.for_keys.estimated_bytes, Some(200));
+    assert!(changes.to_string().contains("covering addresses 1000-2999"));
+    assert!(changes.to_string().contains("License: CC0-1.0."));
+
+    let reverse =
+        ManifestChanges::new::<NameTagsSpec>(&next, &previous, &[chapter(1)], None).unwrap();
+    assert_eq!(reverse.removed_files, 2);
+    assert!(reverse.new_volumes.is_empty());
+    let same = ManifestChanges::new::<NameTagsSpec>(&next, &next, &[], None).unwrap();
+    assert!(same.is_empty());
+}
@@ -0,0 +1,264 @@
+//! An optional index of every local Record, for fast random lookups.
+//!
+//! Requires the `compiled-index` feature. Chapter files are loaded into an embedded
+//! key-value store (redb) keyed by RecordKey, so that a lookup does not decode
+//! every Chapter file for the key.
+//!
+//! The index records a fingerprint of the manifest it was compiled against and is
+//! only used while the manifest is unchanged.
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info, warn};
+use redb::{Database, ReadableTable, TableDefinition};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    database::types::Todd,
+    specs::traits::{
+        ChapterMethods, DataSpec, RecordKeyMethods, RecordMethods, RecordValueMethods,
+        VolumeIdMethods,
+    },
+};
+
+/// (RecordKey, Volume number, Record position) to RecordValue, all as bytes.
+///
+/// RecordKeys are fixed size for a spec, so the Records for a key are found by prefix.
+const RECORDS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("records");
+/// Index metadata, such as the manifest fingerprint.
+const META: TableDefinition<&str, &str> = TableDefinition::new("meta");
+const MANIFEST_FINGERPRINT: &str = "manifest_fingerprint";
+
+/// An open compiled index.
+#[derive(Debug)]
+struct OpenIndex {
+    db: Arc<Database>,
+    /// The manifest (modified time, length) when last compared to the index.
+    manifest_checked: Option<(SystemTime, u64)>,
+    /// Whether the index was compiled against the current manifest.
+    current: bool,
+}
+
+/// Shares an open compiled index between clones of a database.
+///
+/// The index file can only be opened once per process.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CompiledIndexCache(Arc<Mutex<Option<OpenIndex>>>);
+
+impl PartialEq for CompiledIndexCache {
+    /// The cache does not affect the identity of a database.
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl CompiledIndexCache {
+    /// Gets the index if it was compiled against the current manifest, opening it
+    /// if needed.
+    ///
+    /// The manifest is only hashed again if its modified time or length changed.
+    pub(crate) fn current(&self, path: &Path, manifest: &Path) -> Result<Option<Arc<Database>>> {
+        let mut cached = self
+            .0
+            .lock()
+            .map_err(|_| anyhow!("Compiled index lock poisoned."))?;
+        if cached.is_none() {
+            if !path.exists() {
+                return Ok(None);
+            }
+            *cached = Some(OpenIndex {
+                db: Arc::new(Database::open(path)?),
+                manifest_checked: None,
+                current: false,
+            });
+        }
+        let Some(open) = cached.as_mut() else {
+            return Ok(None);
+        };
+        let manifest_state = file_state(manifest);
+        if manifest_state.is_none() || manifest_state != open.manifest_checked {
+            open.current =
+                stored_fingerprint(&open.db)?.is_some_and(|f| Some(f) == fingerprint(manifest));
+            open.manifest_checked = manifest_state;
+            if !open.current {
+                warn!(
+                    "Compiled index {:?} does not match the manifest and will not be used. Compile it again.",
+                    path
+                );
+            }
+        }
+        Ok(open.current.then(|| open.db.clone()))
+    }
+    /// Compares the index to the manifest again at the next lookup, even if the
+    /// manifest has the same modified time and length.
+    pub(crate) fn invalidate(&self) {
+        let mut cached = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(open) = cached.as_mut() {
+            open.manifest_checked = None;
+        }
+    }
+    /// Replaces the index with one compiled from the given Chapters.
+    ///
+    /// Returns the number of Records in the index.
+    pub(crate) fn compile<T: DataSpec>(
+        &self,
+        path: &Path,
+        manifest: &Path,
+        chapters: impl Iterator<Item = Result<T::AssociatedChapter>>,
+    ) -> Result<u64> {
+        let Some(manifest_fingerprint) = fingerprint(manifest) else {
+            bail!(
+                "No manifest at {:?}. The index is compiled against a manifest.",
+                manifest
+            )
+        };
+        let manifest_state = file_state(manifest);
+        let mut cached = self
+            .0
+            .lock()
+            .map_err(|_| anyhow!("Compiled index lock poisoned."))?;
+        // Release any open handle so the file can be opened for writing.
+        *cached = None;
+        let db = Database::create(path)?;
+        let mut count = 0;
+        let txn = db.begin_write()?;
+        txn.delete_table(RECORDS)?;
+        {
+            let mut table = txn.open_table(RECORDS)?;
+            for chapter in chapters {
+                let chapter = chapter?;
+                let volume = chapter.volume_id().is_nth()?;
+                for (position, record) in chapter.records().iter().enumerate() {
+                    let key = record_entry_key(
+                        &record.key().as_serialized_bytes()?,
+                        volume,
+                        position as u32,
+                    );
+                    let value = record.value().as_serialized_bytes()?;
+                    table.insert(key.as_slice(), value.as_slice())?;
+                    count += 1;
+                }
+            }
+            let mut meta = txn.open_table(META)?;
+            meta.insert(MANIFEST_FINGERPRINT, manifest_fingerprint.as_str())?;
+        }
+        txn.commit()?;
+        *cached = Some(OpenIndex {
+            db: Arc::new(db),
+            manifest_checked: manifest_state,
+            current: true,
+        });
+        Ok(count)
+    }
+}
+
+/// Gets the RecordValues for a RecordKey, ordered by Volume.
+fn find_in_index<T: DataSpec>(
+    db: &Database,
+    record_key: &T::AssociatedRecordKey,
+) -> Result<Vec<T::AssociatedRecordValue>> {
+    let prefix = record_key.as_serialized_bytes()?;
+    let txn = db.begin_read()?;
+    let table = txn.open_table(RECORDS)?;
+    let mut values = vec![];
+    for entry in table.range::<&[u8]>(prefix.as_slice()..)? {
+        let (key, value) = entry?;
+        if !key.value().starts_with(&prefix) {
+            break;
+        }
+        values.push(T::AssociatedRecordValue::from_serialized_bytes(
+            value.value(),
+        )?);
+    }
+    debug!("Found {} values in the compiled index.", values.len());
+    Ok(values)
+}
+
+fn record_entry_key(record_key: &[u8], volume: u32, position: u32) -> Vec<u8> {
+    let mut key = record_key.to_vec();
+    key.extend_from_slice(&volume.to_be_bytes());
+    key.extend_from_slice(&position.to_be_bytes());
+    key
+}
+
+/// SHA-256 (hex) of the manifest file, if it exists.
+fn fingerprint(manifest: &Path) -> Option<String> {
+    let bytes = fs::read(manifest).ok()?;
+    Some(hex::encode(Sha256::digest(bytes)))
+}
+
+/// The modified time and length of a file, if it exists.
+fn file_state(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn stored_fingerprint(db: &Database) -> Result<Option<String>> {
+    let txn = db.begin_read()?;
+    let meta = match txn.open_table(META) {
+        Ok(meta) => meta,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let stored = meta
+        .get(MANIFEST_FINGERPRINT)?
+        .map(|f| f.value().to_string());
+    Ok(stored)
+}
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Loads every local Chapter into the compiled index, replacing any existing index.
+    ///
+    /// Afterwards [Self::find] uses the index rather than reading Chapter files, until
+    /// the manifest changes. Compile again after obtaining new data or a new manifest.
+    ///
+    /// Returns the number of Records in the index.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Default)?;
+    /// db.compile_index()?;
+    /// // Uses the compiled index.
+    /// let values = db.find("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae")?;
+    /// ```
+    pub fn compile_index(&self) -> Result<u64> {
+        self.config.require_unencrypted("compile_index")?;
+        let chapters = self
+            .inventory()?
+            .into_iter()
+            .map(|(_vol, _chap, path, _size)| {
+                let bytes = self.config.read_chapter_file(&path)?;
+                T::AssociatedChapter::from_file(bytes)
+                    .with_context(|| format!("Failed to read/decode file: {:?}", path))
+            });
+        let path = self.config.compiled_index_path()?;
+        let manifest = self.config.manifest_file_path()?;
+        let count = self
+            .compiled_index
+            .compile::<T>(&path, &manifest, chapters)?;
+        info!("Compiled {} Records into {:?}.", count, path);
+        Ok(count)
+    }
+    /// RecordValues from the compiled index, if it exists and matches the manifest.
+    pub(crate) fn find_compiled(
+        &self,
+        record_key: &T::AssociatedRecordKey,
+    ) -> Result<Option<Vec<T::AssociatedRecordValue>>> {
+        let index = self.compiled_index.current(
+            &self.config.compiled_index_path()?,
+            &self.config.manifest_file_path()?,
+        )?;
+        match index {
+            Some(db) => Ok(Some(find_in_index::<T>(&db, record_key)?)),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! A write-ahead record of Chapters completed during a build.
+//!
+//! Each completed Chapter is appended to the draft as a line of JSON. If a build
+//! stops before the manifest is generated, the next build skips the Chapters in
+//! the draft and the manifest reuses the CIDs recorded there rather than
+//! hashing every file again. The draft is removed once the manifest is written.
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// A Chapter that was processed during a build.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DraftEntry {
+    pub volume_interface_id: String,
+    pub chapter_interface_id: String,
+    /// CIDv0 of the Chapter file. None if there was no data for the Chapter.
+    pub cid_v0: Option<String>,
+    /// Size of the Chapter file in bytes.
+    pub bytes: u64,
+}
+
+/// Completed Chapters, keyed by (volume_interface_id, chapter_interface_id).
+pub(crate) type DraftEntries = HashMap<(String, String), DraftEntry>;
+
+/// An append-only draft file that can be shared across threads.
+pub(crate) struct ManifestDraft {
+    file: Mutex<File>,
+}
+
+impl ManifestDraft {
+    /// Opens the draft for appending, creating it if absent.
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open manifest draft {:?}", path))?;
+        Ok(ManifestDraft {
+            file: Mutex::new(file),
+        })
+    }
+    /// Appends an entry as a single line.
+    pub(crate) fn record(&self, entry: &DraftEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|e| anyhow!("Manifest draft lock poisoned: {}", e))?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Reads the entries of a draft. An absent draft has no entries.
+///
+/// Lines that cannot be parsed (e.g., partially written during a crash) are skipped.
+pub(crate) fn read_draft(path: &Path) -> Result<DraftEntries> {
+    let mut entries = DraftEntries::new();
+    if !path.exists() {
+        return Ok(entries);
+    }
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read manifest draft {:?}", path))?;
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        match serde_json::from_str::<DraftEntry>(line) {
+            Ok(e) => {
+                let key = (
+                    e.volume_interface_id.clone(),
+                    e.chapter_interface_id.clone(),
+                );
+                entries.insert(key, e);
+            }
+            Err(e) => warn!("Skipping unreadable manifest draft line ({}): {}", e, line),
+        }
+    }
+    Ok(entries)
+}
+
+/// Removes the draft once the manifest has been finalized.
+pub(crate) fn remove_draft(path: &PathBuf) -> Result<()> {
+    if path.exists() {
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to remove manifest draft {:?}", path))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn draft_round_trip_skips_partial_line() {
+    let path = std::env::temp_dir().join("min_know_test_manifest_draft.jsonl");
+    remove_draft(&path).unwrap();
+    let draft = ManifestDraft::open(&path).unwrap();
+    let entry = DraftEntry {
+        volume_interface_id: "volume_011_200_000".to_string(),
+        chapter_interface_id: "chapter_0x00".to_string(),
+        cid_v0: Some("QmY6LjJ1HExi2TgHshc56ecPdVSNaWrzFbWq9sahHFrNoM".to_string()),
+        bytes: 42,
+    };
+    draft.record(&entry).unwrap();
+    // Simulate a crash part way through writing a line.
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(b"{\"volume_interface_id\":\"vol")
+        .unwrap();
+    let entries = read_draft(&path).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries.values().next().unwrap(), &entry);
+    remove_draft(&path).unwrap();
+    assert!(read_draft(&path).unwrap().is_empty());
+}
@@ -0,0 +1,235 @@
+//! Encrypting Chapter files at rest, for private databases.
+//!
+//! A database of one's own keys (e.g., built with `config.watched_keys`) may
+//! reveal the keys of interest. With `config.encryption_key` set (requires the
+//! `encryption` feature), Chapter files are encrypted with AES-256-GCM when
+//! written, and decrypted when read. Files without encryption (e.g., written
+//! before the key was set) are still read.
+//!
+//! Encrypted files are incompatible with public CID verification: each file
+//! has a random nonce, so its CID differs from that of the same Chapter
+//! published elsewhere. A manifest generated for an encrypted database has
+//! the CIDs of the encrypted files, which can only be checked locally. Operations
+//! that rely on public CIDs (obtaining, syncing, CAR files, reproduction) or
+//! that would keep Records unencrypted (the compiled index) return an
+//! [EncryptedDatabase] error. Chapter headers (see
+//! [ChapterMethods::peek_metadata]) are encrypted too, so cannot be peeked.
+//!
+//! ## Layout
+//! - Magic bytes "TODE" (4 bytes)
+//! - Encryption format version (1 byte)
+//! - Nonce (12 bytes)
+//! - The Chapter file (header and encoded Chapter), encrypted. The magic bytes
+//!   and version are authenticated as associated data.
+use std::{
+    fmt::{Debug, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    config::dirs::ConfigStruct,
+    specs::traits::{ChapterMethods, DataSpec},
+};
+
+/// Bytes at the start of every encrypted Chapter file.
+pub const ENCRYPTED_MAGIC: [u8; 4] = *b"TODE";
+/// Version of the encrypted file layout.
+#[cfg(feature = "encryption")]
+const ENCRYPTION_VERSION: u8 = 1;
+/// Bytes in an AES-GCM nonce.
+#[cfg(feature = "encryption")]
+const NONCE_BYTES: usize = 12;
+
+/// A user supplied AES-256 key for Chapter files.
+///
+/// The key is never serialized with the config, and is not shown by `Debug`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Hash)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        EncryptionKey(bytes)
+    }
+    /// Reads a key from 64 hex characters (an optional 0x prefix is ignored).
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let bytes = hex::decode(key.trim_start_matches("0x")).context("Invalid hex key")?;
+        let Ok(bytes) = <[u8; 32]>::try_from(bytes.as_slice()) else {
+            bail!("Expected a 32 byte key, got {} bytes.", bytes.len())
+        };
+        Ok(EncryptionKey(bytes))
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EncryptionKey(..)")
+    }
+}
+
+/// Error returned when an operation is not available for a database with
+/// encrypted Chapter files (`config.encryption_key`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncryptedDatabase {
+    /// The base directory of the database.
+    pub path: PathBuf,
+    /// The operation that is not available. E.g., "export_car".
+    pub operation: String,
+}
+
+impl Display for EncryptedDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The database in {:?} is encrypted (config.encryption_key), so {} is not available: \
+            it relies on unencrypted Chapter files.",
+            self.path, self.operation
+        )
+    }
+}
+
+impl std::error::Error for EncryptedDatabase {}
+
+/// Whether file contents are an encrypted Chapter file.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&ENCRYPTED_MAGIC)
+}
+
+impl ConfigStruct {
+    /// The contents of the file for a Chapter, encrypted if `encryption_key` is set.
+    pub(crate) fn chapter_file_bytes<T: DataSpec>(
+        &self,
+        chapter: &T::AssociatedChapter,
+    ) -> Result<Vec<u8>> {
+        let bytes = chapter.as_file_bytes()?;
+        match &self.encryption_key {
+            Some(key) => seal(key, &bytes),
+            None => Ok(bytes),
+        }
+    }
+    /// Reads a Chapter file, decrypting it if it is encrypted.
+    ///
+    /// ## Errors
+    /// If the file cannot be read, or is encrypted and cannot be decrypted
+    /// with `encryption_key` (or that is not set).
+    pub(crate) fn read_chapter_file(&self, path: &Path) -> Result<Vec<u8>> {
+        let bytes =
+            fs::read(path).with_context(|| format!("Failed to read file from {:?}", path))?;
+        if !is_encrypted(&bytes) {
+            return Ok(bytes);
+        }
+        let Some(key) = &self.encryption_key else {
+            bail!(
+                "Chapter file {:?} is encrypted, but config.encryption_key is not set.",
+                path
+            )
+        };
+        open(key, &bytes).with_context(|| format!("Failed to decrypt {:?}", path))
+    }
+    /// Checks that Chapter files are not encrypted, for operations that rely on
+    /// unencrypted files.
+    ///
+    /// ## Errors
+    /// [EncryptedDatabase] if `encryption_key` is set.
+    pub(crate) fn require_unencrypted(&self, operation: &str) -> Result<()> {
+        if self.encryption_key.is_some() {
+            return Err(EncryptedDatabase {
+                path: self.base_dir_nature_dependent.clone(),
+                operation: operation.to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// The bytes before the nonce, which are authenticated.
+#[cfg(feature = "encryption")]
+fn associated_data() -> Vec<u8> {
+    let mut prefix = ENCRYPTED_MAGIC.to_vec();
+    prefix.push(ENCRYPTION_VERSION);
+    prefix
+}
+
+#[cfg(feature = "encryption")]
+fn seal(key: &EncryptionKey, plaintext: &[u8]) -> Result<Vec<u8>> {
+    use aes_gcm::{
+        aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+        Aes256Gcm, Key,
+    };
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.0));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let aad = associated_data();
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt Chapter file."))?;
+    let mut bytes = aad;
+    bytes.extend_from_slice(&nonce);
+    bytes.extend(ciphertext);
+    Ok(bytes)
+}
+
+#[cfg(feature = "encryption")]
+fn open(key: &EncryptionKey, data: &[u8]) -> Result<Vec<u8>> {
+    use aes_gcm::{
+        aead::{Aead, KeyInit, Payload},
+        Aes256Gcm, Key, Nonce,
+    };
+    let aad = associated_data();
+    let Some(rest) = data.strip_prefix(aad.as_slice()) else {
+        bail!("Unsupported encrypted file version.")
+    };
+    if rest.len() < NONCE_BYTES {
+        bail!("Encrypted file is truncated.")
+    }
+    let (nonce, ciphertext) = rest.split_at(NONCE_BYTES);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.0));
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("The key does not match, or the file was modified."))
+}
+
+#[cfg(not(feature = "encryption"))]
+fn seal(_key: &EncryptionKey, _plaintext: &[u8]) -> Result<Vec<u8>> {
+    bail!("config.encryption_key is set, but encryption requires the encryption feature.")
+}
+
+#[cfg(not(feature = "encryption"))]
+fn open(_key: &EncryptionKey, _data: &[u8]) -> Result<Vec<u8>> {
+    bail!("Chapter file is encrypted, but decryption requires the encryption feature.")
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn sealed_files_open_with_the_key_only() {
+    let key = EncryptionKey::new([7; 32]);
+    let plaintext = b"TODD chapter".to_vec();
+    let sealed = seal(&key, &plaintext).unwrap();
+    assert!(is_encrypted(&sealed));
+    assert_eq!(open(&key, &sealed).unwrap(), plaintext);
+    // Each file has its own nonce.
+    assert_ne!(seal(&key, &plaintext).unwrap(), sealed);
+
+    let other = EncryptionKey::from_hex(&hex::encode([8; 32])).unwrap();
+    assert!(open(&other, &sealed).is_err());
+    let mut modified = sealed.clone();
+    *modified.last_mut().unwrap() ^= 1;
+    assert!(open(&key, &modified).is_err());
+    assert!(open(&key, &sealed[..10]).is_err());
+    assert!(EncryptionKey::from_hex("0x1234").is_err());
+    assert_eq!(format!("{:?}", key), "EncryptionKey(..)");
+}
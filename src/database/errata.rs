@@ -0,0 +1,228 @@
+//! Corrections to published Volumes.
+//!
+//! Published Chapters are never changed. Instead, a correction is published as an
+//! erratum: a small Chapter for the same VolumeId and ChapterId that holds only the
+//! corrected Records. Errata are append-only and numbered in sequence for each
+//! Chapter. [Todd::find] applies them, in sequence, over the Records of the Volume
+//! they correct (see [DataSpec::apply_errata]).
+//!
+//! Errata are stored apart from Chapter files (see `ConfigStruct::errata_dir`),
+//! listed in the manifest and obtained along with the Chapters they correct.
+//! They are only used by specs that set [DataSpec::SUPPORTS_ERRATA].
+use std::{fs, path::PathBuf};
+#[cfg(feature = "publisher")]
+use std::{fs::OpenOptions, io::Write};
+
+use anyhow::{bail, Context, Result};
+#[cfg(feature = "publisher")]
+use log::info;
+
+#[cfg(feature = "publisher")]
+use crate::specs::traits::VolumeIdMethods;
+use crate::{
+    database::types::Todd,
+    specs::traits::{ChapterIdMethods, ChapterMethods, DataSpec, RecordMethods},
+    utils::system::dir_paths,
+};
+#[cfg(feature = "publisher")]
+use crate::{
+    extraction::traits::canonical_order, specs::traits::ManifestErratum,
+    utils::ipfs::cid_v0_string_from_bytes,
+};
+
+/// RecordValues for a RecordKey, grouped by the Volume they are from.
+pub type VolumeValues<T> = Vec<(
+    <T as DataSpec>::AssociatedVolumeId,
+    Vec<<T as DataSpec>::AssociatedRecordValue>,
+)>;
+
+/// An erratum present locally.
+pub(crate) struct LocalErratum<T: DataSpec> {
+    pub(crate) sequence: u32,
+    #[cfg_attr(not(feature = "publisher"), allow(dead_code))]
+    pub(crate) path: PathBuf,
+    pub(crate) chapter: T::AssociatedChapter,
+}
+
+/// Applies errata to the RecordValues for a RecordKey.
+///
+/// Errata must be in ascending sequence. A Volume that has no values for the
+/// key gains any values added by its errata.
+pub(crate) fn apply_errata<T: DataSpec>(
+    values: &mut VolumeValues<T>,
+    errata: &[LocalErratum<T>],
+    record_key: &T::AssociatedRecordKey,
+) {
+    for erratum in errata {
+        let corrections: Vec<T::AssociatedRecordValue> = erratum
+            .chapter
+            .records()
+            .iter()
+            .filter(|r| r.key() == record_key)
+            .map(|r| r.value().clone())
+            .collect();
+        if corrections.is_empty() {
+            continue;
+        }
+        let volume_id = erratum.chapter.volume_id();
+        let index = match values.iter().position(|(v, _)| v == volume_id) {
+            Some(index) => index,
+            None => {
+                values.push((volume_id.clone(), vec![]));
+                values.len() - 1
+            }
+        };
+        let original = std::mem::take(&mut values[index].1);
+        values[index].1 = T::apply_errata(original, corrections);
+    }
+}
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Publishes corrections to Records of an existing Volume as errata.
+    ///
+    /// The Records are grouped by Chapter and each group is saved as the next
+    /// erratum for that Chapter. Requires the `publisher` feature. Generate the
+    /// manifest afterwards to list the errata.
+    ///
+    /// Returns the paths of the new errata.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Default)?;
+    /// let corrected = NameTagsRecord {
+    ///     key: NameTagsRecordKey::from_address("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae")?,
+    ///     value: NameTagsRecordValue::from_strings(vec!["Ethereum Foundation".to_string()], vec![]),
+    /// };
+    /// db.add_errata(&volume_id, vec![corrected])?;
+    /// db.generate_manifest()?;
+    /// ```
+    /// ## Errors
+    /// If the spec does not support errata, or the Volume is later than the latest
+    /// local Volume.
+    #[cfg(feature = "publisher")]
+    pub fn add_errata(
+        &self,
+        volume_id: &T::AssociatedVolumeId,
+        records: Vec<T::AssociatedRecord>,
+    ) -> Result<Vec<PathBuf>> {
+        if !T::SUPPORTS_ERRATA {
+            bail!(
+                "The {} spec does not support errata.",
+                self.config.data_kind.interface_id()
+            )
+        }
+        let _lock = self.write_lock("add_errata")?;
+        let latest = self.config.latest_volume::<T>()?;
+        if volume_id.is_nth()? > latest.is_nth()? {
+            bail!(
+                "Volume {} is later than the latest Volume ({}). Errata only correct existing Volumes.",
+                volume_id.interface_id(),
+                latest.interface_id()
+            )
+        }
+        let mut by_chapter: Vec<(T::AssociatedChapterId, Vec<T::AssociatedRecord>)> = vec![];
+        for record in records {
+            let chapter_id = T::record_key_to_chapter_id(record.key())?;
+            match by_chapter.iter_mut().find(|(c, _)| c == &chapter_id) {
+                Some((_, chapter_records)) => chapter_records.push(record),
+                None => by_chapter.push((chapter_id, vec![record])),
+            }
+        }
+        let mut paths = vec![];
+        for (chapter_id, records) in by_chapter {
+            let sequence = self
+                .errata_for_chapter(&chapter_id)?
+                .iter()
+                .filter(|e| e.chapter.volume_id() == volume_id)
+                .map(|e| e.sequence)
+                .max()
+                .unwrap_or(0)
+                + 1;
+            let records = canonical_order::<T>(records)?;
+            let chapter = T::AssociatedChapter::from_records(volume_id, &chapter_id, records)?;
+            let path =
+                self.config
+                    .errata_file_path::<T>(&chapter_id, sequence, &chapter.filename());
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let bytes = self.config.chapter_file_bytes::<T>(&chapter)?;
+            // Errata are append-only, so an existing file is never replaced.
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(&bytes))
+                .with_context(|| format!("Failed to write erratum {:?}", path))?;
+            info!("Saved erratum {:?}.", path);
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+    /// Reads the local errata for a Chapter, in ascending sequence.
+    ///
+    /// Empty if the spec does not support errata.
+    pub(crate) fn errata_for_chapter(
+        &self,
+        chapter_id: &T::AssociatedChapterId,
+    ) -> Result<Vec<LocalErratum<T>>> {
+        if !T::SUPPORTS_ERRATA {
+            return Ok(vec![]);
+        }
+        let dir = self.config.errata_dir().join(chapter_id.interface_id());
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut errata = vec![];
+        for path in dir_paths(&dir)? {
+            let sequence = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix("errata_"))
+                .and_then(|n| n.split_once('_'))
+                .and_then(|(s, _)| s.parse::<u32>().ok());
+            let Some(sequence) = sequence else {
+                bail!(
+                    "Errata directory {:?} contains a file that is not an erratum: {:?}",
+                    dir,
+                    path
+                )
+            };
+            let bytes = self.config.read_chapter_file(&path)?;
+            let chapter = T::AssociatedChapter::from_file(bytes)
+                .with_context(|| format!("Failed to read/decode erratum: {:?}", path))?;
+            errata.push(LocalErratum {
+                sequence,
+                path,
+                chapter,
+            });
+        }
+        errata.sort_by_key(|e| e.sequence);
+        Ok(errata)
+    }
+    /// Computes CIDs for all local errata.
+    #[cfg(feature = "publisher")]
+    pub(crate) fn errata_cids(&self) -> Result<Vec<ManifestErratum<T>>> {
+        let dir = self.config.errata_dir();
+        if !T::SUPPORTS_ERRATA || !dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut cids = vec![];
+        for path in dir_paths(&dir)? {
+            let chapter_id = T::AssociatedChapterId::from_chapter_directory(&path)?;
+            for erratum in self.errata_for_chapter(&chapter_id)? {
+                let bytes = fs::read(&erratum.path)?;
+                cids.push(ManifestErratum {
+                    cid: cid_v0_string_from_bytes(&bytes)?,
+                    volume_id: erratum.chapter.volume_id().clone(),
+                    chapter_id: chapter_id.clone(),
+                    sequence: erratum.sequence,
+                })
+            }
+        }
+        Ok(cids)
+    }
+}
@@ -0,0 +1,244 @@
+//! The outcome of building or extending a database, for scheduled (e.g., cron) runs.
+//!
+//! Requires the `publisher` feature. See [Todd::extend][crate::database::types::Todd::extend]
+//! for the guarantees made when a run is repeated or interrupted.
+//!
+//! Chapters that cannot be created do not stop a build. They are returned in
+//! [ExtendOutcome::errors] by `Todd::extend`, and as a [ChaptersFailed] error by
+//! `Todd::full_transformation` and `Todd::repair_from_raw`.
+//!
+//! To react to each Chapter as it is completed (e.g., to show progress), use
+//! `Todd::transform_stream` or `Todd::extend_stream`, which return a
+//! [ChapterStream] of [ChapterOutcome]s.
+use std::{
+    fmt::Display,
+    sync::mpsc::{sync_channel, Receiver, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// The number of completed Chapters a [ChapterStream] holds before the build
+/// waits for them to be received.
+const STREAM_CAPACITY: usize = 64;
+
+/// What [Todd::extend][crate::database::types::Todd::extend] did.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtendOutcome {
+    /// Interface ids of the Volumes the run formed Chapters for.
+    pub volumes_added: Vec<String>,
+    /// Number of Chapter files written.
+    pub chapters_written: usize,
+    /// Number of Chapters not written: completed by an earlier run, unchanged
+    /// from the manifest, or with no raw data.
+    pub chapters_skipped: usize,
+    /// Chapters that could not be formed or saved.
+    pub errors: Vec<ChapterFailure>,
+    /// Whether the manifest was written. It is not written if there are errors.
+    pub manifest_updated: bool,
+}
+
+/// A Chapter that could not be formed or saved.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChapterFailure {
+    pub volume_interface_id: String,
+    pub chapter_interface_id: String,
+    pub reason: String,
+}
+
+/// A Chapter handled during a build, received from a [ChapterStream].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChapterOutcome {
+    pub volume_interface_id: String,
+    pub chapter_interface_id: String,
+    pub status: ChapterStatus,
+}
+
+/// What happened to a Chapter during a build.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChapterStatus {
+    /// A Chapter file was saved.
+    Written { cid_v0: String, bytes: u64 },
+    /// There was no raw data for the Chapter.
+    Empty,
+    /// The Chapter could not be formed or saved.
+    Failed { reason: String },
+}
+
+/// The overall result of an [ExtendOutcome], e.g., for an exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExtendStatus {
+    /// There was no new raw data. Nothing was changed.
+    NothingToDo,
+    /// New Volumes were added and the manifest was written.
+    Extended,
+    /// Some Chapters could not be created. The manifest was not written, and
+    /// running again retries only those Chapters.
+    PartiallyFailed,
+}
+
+impl ExtendOutcome {
+    pub fn status(&self) -> ExtendStatus {
+        if !self.errors.is_empty() {
+            ExtendStatus::PartiallyFailed
+        } else if self.volumes_added.is_empty() && !self.manifest_updated {
+            ExtendStatus::NothingToDo
+        } else {
+            ExtendStatus::Extended
+        }
+    }
+}
+
+impl Display for ExtendOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?}: {} Volumes, {} Chapter files written, {} Chapters skipped",
+            self.status(),
+            self.volumes_added.len(),
+            self.chapters_written,
+            self.chapters_skipped
+        )?;
+        match self.errors.len() {
+            0 => write!(f, "."),
+            n => write!(f, ", {} Chapters failed.", n),
+        }
+    }
+}
+
+impl Display for ChapterFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {}: {}",
+            self.chapter_interface_id, self.volume_interface_id, self.reason
+        )
+    }
+}
+
+impl Display for ChapterOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {}: ",
+            self.chapter_interface_id, self.volume_interface_id
+        )?;
+        match &self.status {
+            ChapterStatus::Written { cid_v0, bytes } => {
+                write!(f, "written ({} bytes, CID {})", bytes, cid_v0)
+            }
+            ChapterStatus::Empty => write!(f, "no raw data"),
+            ChapterStatus::Failed { reason } => write!(f, "failed ({})", reason),
+        }
+    }
+}
+
+/// The Chapters of a build that runs on another thread, as they are completed.
+///
+/// Iterating yields a [ChapterOutcome] for each Chapter formed, in the order
+/// they are completed. Chapters kept from an earlier build are not yielded.
+/// Iteration ends when the build ends (after the manifest is written, if it
+/// is). Then [ChapterStream::finish] returns the result of the build.
+///
+/// The stream holds a limited number of outcomes: a build that is not read
+/// from waits. Dropping the stream does not stop the build.
+#[derive(Debug)]
+pub struct ChapterStream<R> {
+    receiver: Receiver<ChapterOutcome>,
+    build: JoinHandle<Result<R>>,
+}
+
+impl<R: Send + 'static> ChapterStream<R> {
+    /// Runs the build on a new thread. The build sends each Chapter it handles.
+    ///
+    /// Waits for the build to report through [BuildStart] whether it started,
+    /// and returns the error if it did not.
+    pub(crate) fn spawn<F>(build: F) -> Result<Self>
+    where
+        F: FnOnce(BuildStart, SyncSender<ChapterOutcome>) -> Result<R> + Send + 'static,
+    {
+        let (sender, receiver) = sync_channel(STREAM_CAPACITY);
+        let (start_sender, start) = sync_channel(1);
+        let build = thread::spawn(move || build(BuildStart(start_sender), sender));
+        // A build that ends without reporting returns its result from finish().
+        if let Ok(Err(e)) = start.recv() {
+            let _ = build.join();
+            return Err(e);
+        }
+        Ok(ChapterStream { receiver, build })
+    }
+    /// Waits for the build to end and returns its result, as the blocking
+    /// method would have.
+    ///
+    /// Outcomes that were not yet received are discarded.
+    pub fn finish(self) -> Result<R> {
+        // Dropped first so that a build waiting on a full stream continues.
+        drop(self.receiver);
+        self.build
+            .join()
+            .map_err(|_| anyhow!("The build thread panicked."))?
+    }
+}
+
+/// Passed to the build of a [ChapterStream] to report whether it started.
+///
+/// The database lock is held per thread, so a build acquires it on its own
+/// thread and reports the result here.
+#[derive(Debug)]
+pub(crate) struct BuildStart(SyncSender<Result<()>>);
+
+impl BuildStart {
+    /// Reports the result of starting the build (e.g., acquiring the lock).
+    ///
+    /// An error is returned to the caller of [ChapterStream::spawn], and the
+    /// build should then end.
+    pub(crate) fn report<G>(self, started: Result<G>) -> Result<G> {
+        match started {
+            Ok(guard) => {
+                let _ = self.0.send(Ok(()));
+                Ok(guard)
+            }
+            Err(e) => {
+                let _ = self.0.send(Err(e));
+                Err(anyhow!("The build did not start."))
+            }
+        }
+    }
+}
+
+impl<R> Iterator for ChapterStream<R> {
+    type Item = ChapterOutcome;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Error returned when a build completes with some Chapters not created.
+///
+/// The Chapters that were created are kept, and the manifest is not written.
+/// Running the build again only forms the failed Chapters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChaptersFailed {
+    /// E.g., "full_transformation".
+    pub operation: String,
+    pub failures: Vec<ChapterFailure>,
+}
+
+impl Display for ChaptersFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} Chapters could not be created during {}",
+            self.failures.len(),
+            self.operation
+        )?;
+        if let Some(first) = self.failures.first() {
+            write!(f, " (first: {})", first)?;
+        }
+        write!(f, ". Run again to retry them.")
+    }
+}
+
+impl std::error::Error for ChaptersFailed {}
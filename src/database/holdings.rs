@@ -0,0 +1,108 @@
+//! A local record of which parts of the database the user chooses to hold.
+//!
+//! Users may only need recent Volumes. Volumes removed by a retention policy are
+//! recorded here so that completeness audits and repairs do not treat them as
+//! missing. Clearing the record makes them eligible for restoration again.
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Which Volumes to keep when pruning a database.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RetentionPolicy {
+    /// Keep the latest N Volumes present locally.
+    KeepLastN(u32),
+    /// Keep Volumes between two Volume interface ids (inclusive).
+    ///
+    /// E.g., "volume_014_000_000" to "volume_014_400_000".
+    VolumeRange { oldest: String, newest: String },
+}
+
+impl RetentionPolicy {
+    /// Returns the inclusive range of zero-based Volume positions to keep.
+    ///
+    /// The positions of the range bounds (if any) and of the latest Volume
+    /// present are supplied by the caller.
+    pub(crate) fn positions_kept(
+        &self,
+        latest: u32,
+        range: Option<(u32, u32)>,
+    ) -> Result<(u32, u32)> {
+        match self {
+            RetentionPolicy::KeepLastN(0) => {
+                bail!("A retention policy must keep at least one Volume.")
+            }
+            RetentionPolicy::KeepLastN(n) => Ok(((latest + 1).saturating_sub(*n), latest)),
+            RetentionPolicy::VolumeRange { oldest, newest } => {
+                let Some((first, last)) = range else {
+                    bail!("Volume range {} to {} was not resolved.", oldest, newest)
+                };
+                if first > last {
+                    bail!(
+                        "Volume range is reversed: {} is newer than {}.",
+                        oldest,
+                        newest
+                    )
+                }
+                Ok((first, last))
+            }
+        }
+    }
+}
+
+/// The Volumes the user holds locally, stored alongside the manifest.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Holdings {
+    /// The most recent policy used to prune the database.
+    pub retention: Option<RetentionPolicy>,
+    /// Interface ids of Volumes deliberately removed from the database.
+    pub pruned_volumes: Vec<String>,
+}
+
+impl Holdings {
+    /// Reads the holdings file. An absent file means all data is held.
+    pub fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Holdings::default());
+        }
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read holdings file {:?}", path))?;
+        serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse holdings file {:?}", path))
+    }
+    /// Writes the holdings file, creating the parent directory if needed.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write holdings file {:?}", path))
+    }
+    /// Whether the Volume was removed by pruning.
+    pub fn is_pruned(&self, volume_interface_id: &str) -> bool {
+        self.pruned_volumes.iter().any(|v| v == volume_interface_id)
+    }
+}
+
+#[test]
+fn keep_last_n_positions() {
+    let policy = RetentionPolicy::KeepLastN(2);
+    assert_eq!(policy.positions_kept(10, None).unwrap(), (9, 10));
+    let policy = RetentionPolicy::KeepLastN(20);
+    assert_eq!(policy.positions_kept(10, None).unwrap(), (0, 10));
+    assert!(RetentionPolicy::KeepLastN(0)
+        .positions_kept(10, None)
+        .is_err());
+}
+
+#[test]
+fn volume_range_positions() {
+    let policy = RetentionPolicy::VolumeRange {
+        oldest: "a".to_string(),
+        newest: "b".to_string(),
+    };
+    assert_eq!(policy.positions_kept(10, Some((3, 5))).unwrap(), (3, 5));
+    assert!(policy.positions_kept(10, Some((5, 3))).is_err());
+    assert!(policy.positions_kept(10, None).is_err());
+}
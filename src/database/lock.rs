@@ -0,0 +1,298 @@
+//! An advisory lock that prevents two processes from writing to a database at once.
+//!
+//! Operations that modify Chapter files or the manifest hold a lock file in the
+//! base directory of the database. A second process that tries to write receives
+//! a [DatabaseLocked] error rather than interleaving its writes.
+//!
+//! The lock is reentrant within a thread, so a writing operation may call another.
+//! Other threads of the same process receive [DatabaseLocked], as another process
+//! would. A lock left behind by a process that is no longer running is removed.
+//!
+//! A database configured with `read_only` cannot be locked: writing operations
+//! receive a [ReadOnlyDatabase] error.
+use std::{
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+    thread::{self, ThreadId},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+/// Age after which a lock is stale, if the process that holds it cannot be checked.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Age after which a lock file that cannot be read is stale. A lock file is
+/// briefly empty while it is being written.
+const UNREADABLE_LOCK_AGE: Duration = Duration::from_secs(60);
+
+/// Lock files held by this process, with the thread holding each and its number
+/// of holders.
+static HELD: Mutex<Vec<(PathBuf, ThreadId, usize)>> = Mutex::new(vec![]);
+
+/// The contents of a lock file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHolder {
+    /// Process id of the writer.
+    pub pid: u32,
+    /// When the lock was acquired (seconds since the unix epoch).
+    pub since: u64,
+    /// The operation that acquired the lock. E.g., "extend".
+    pub operation: String,
+}
+
+/// Error returned when another process, or another thread of this process, is
+/// writing to the database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatabaseLocked {
+    /// The lock file.
+    pub path: PathBuf,
+    /// The process holding the lock, if the lock file could be read.
+    pub holder: Option<LockHolder>,
+}
+
+impl Display for DatabaseLocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let this_process = self.holder.as_ref().is_some_and(|h| h.pid == process::id());
+        match this_process {
+            true => write!(
+                f,
+                "Another thread of this process is writing to the database"
+            )?,
+            false => write!(f, "Another process is writing to the database")?,
+        }
+        if let Some(h) = &self.holder {
+            write!(
+                f,
+                " (pid {}, {} since {} seconds after the unix epoch)",
+                h.pid, h.operation, h.since
+            )?;
+        }
+        if this_process {
+            return write!(f, ". Wait for it to finish.");
+        }
+        write!(
+            f,
+            ". Wait for it to finish, or remove the lock file {:?} if that process is no longer running.",
+            self.path
+        )
+    }
+}
+
+impl std::error::Error for DatabaseLocked {}
+
+/// Error returned when an operation would write to a database configured as
+/// read only (`config.read_only`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReadOnlyDatabase {
+    /// The base directory of the database.
+    pub path: PathBuf,
+    /// The operation that would write. E.g., "extend".
+    pub operation: String,
+}
+
+impl Display for ReadOnlyDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The database in {:?} is read only (config.read_only), so {} cannot write to it.",
+            self.path, self.operation
+        )
+    }
+}
+
+impl std::error::Error for ReadOnlyDatabase {}
+
+/// Holds the lock until dropped.
+#[derive(Debug)]
+pub(crate) struct WriteLock {
+    path: PathBuf,
+    /// The thread that acquired the lock.
+    thread: ThreadId,
+}
+
+impl WriteLock {
+    /// Acquires the lock file at the path, removing it first if it is stale.
+    ///
+    /// ## Errors
+    /// [DatabaseLocked] if another process, or another thread of this process,
+    /// holds the lock.
+    pub(crate) fn acquire(path: &Path, operation: &str) -> Result<Self> {
+        let thread = thread::current().id();
+        let mut held = HELD
+            .lock()
+            .map_err(|_| anyhow!("Database lock registry poisoned."))?;
+        if let Some((_path, holder, count)) = held.iter_mut().find(|(p, _, _)| p == path) {
+            if *holder != thread {
+                let existing = fs::read(path)
+                    .ok()
+                    .and_then(|c| serde_json::from_slice::<LockHolder>(&c).ok());
+                return Err(DatabaseLocked {
+                    path: path.to_path_buf(),
+                    holder: existing,
+                }
+                .into());
+            }
+            *count += 1;
+            return Ok(WriteLock {
+                path: path.to_path_buf(),
+                thread,
+            });
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let holder = LockHolder {
+            pid: process::id(),
+            since: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            operation: operation.to_string(),
+        };
+        let mut existing = None;
+        // A second attempt is made after removing a stale lock.
+        for _attempt in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    file.write_all(&serde_json::to_vec(&holder)?)?;
+                    held.push((path.to_path_buf(), thread, 1));
+                    debug!("Acquired database lock {:?} for {}.", path, operation);
+                    return Ok(WriteLock {
+                        path: path.to_path_buf(),
+                        thread,
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let contents = fs::read(path).ok();
+                    existing = contents
+                        .as_ref()
+                        .and_then(|c| serde_json::from_slice::<LockHolder>(c).ok());
+                    if !is_stale(path, existing.as_ref()) {
+                        break;
+                    }
+                    warn!("Removing stale database lock {:?} ({:?}).", path, existing);
+                    // Only remove the lock that was found to be stale.
+                    if fs::read(path).ok() == contents {
+                        remove_lock_file(path)?;
+                    }
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create lock file {:?}", path))
+                }
+            }
+        }
+        Err(DatabaseLocked {
+            path: path.to_path_buf(),
+            holder: existing,
+        }
+        .into())
+    }
+}
+
+impl Drop for WriteLock {
+    fn drop(&mut self) {
+        let Ok(mut held) = HELD.lock() else { return };
+        let Some(index) = held
+            .iter()
+            .position(|(p, t, _)| p == &self.path && t == &self.thread)
+        else {
+            return;
+        };
+        held[index].2 -= 1;
+        if held[index].2 == 0 {
+            held.remove(index);
+            if let Err(e) = remove_lock_file(&self.path) {
+                warn!("Could not remove database lock {:?}: {}", self.path, e)
+            }
+        }
+    }
+}
+
+/// Whether a lock is left over from a process that is no longer running.
+///
+/// The process is checked via /proc where available, otherwise the lock is
+/// stale once it is old. Processes on other machines are not checked.
+fn is_stale(path: &Path, holder: Option<&LockHolder>) -> bool {
+    let Some(holder) = holder else {
+        let modified = fs::metadata(path).and_then(|m| m.modified());
+        return modified
+            .ok()
+            .and_then(|m| m.elapsed().ok())
+            .is_some_and(|age| age > UNREADABLE_LOCK_AGE);
+    };
+    // This process does not hold the lock, so the pid has been reused.
+    if holder.pid == process::id() {
+        return true;
+    }
+    let proc = Path::new("/proc");
+    if proc.join("self").exists() {
+        return !proc.join(holder.pid.to_string()).exists();
+    }
+    let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) else {
+        return false;
+    };
+    now.as_secs().saturating_sub(holder.since) > STALE_LOCK_AGE.as_secs()
+}
+
+fn remove_lock_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove lock file {:?}", path)),
+    }
+}
+
+#[test]
+fn lock_reentrant_and_exclusive() {
+    let path = std::env::temp_dir().join(format!("min_know_test_lock_{}.lock", process::id()));
+    let _ = fs::remove_file(&path);
+    {
+        let _outer = WriteLock::acquire(&path, "outer").unwrap();
+        let inner = WriteLock::acquire(&path, "inner").unwrap();
+        drop(inner);
+        // Still held by the outer lock.
+        let holder: LockHolder = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(holder.operation, "outer");
+        // Not reentrant for another thread of this process.
+        let other_path = path.clone();
+        let err = thread::spawn(move || WriteLock::acquire(&other_path, "other").unwrap_err())
+            .join()
+            .unwrap();
+        let locked = err.downcast_ref::<DatabaseLocked>().unwrap();
+        assert_eq!(locked.holder.as_ref().unwrap().operation, "outer");
+        assert!(err.to_string().contains("Another thread of this process"));
+    }
+    assert!(!path.exists());
+    // Free for another thread once released.
+    let other_path = path.clone();
+    thread::spawn(move || drop(WriteLock::acquire(&other_path, "other").unwrap()))
+        .join()
+        .unwrap();
+    assert!(!path.exists());
+
+    // A lock from a running process (pid 1 always exists where /proc does).
+    let other = LockHolder {
+        pid: 1,
+        since: 0,
+        operation: "extend".to_string(),
+    };
+    fs::write(&path, serde_json::to_vec(&other).unwrap()).unwrap();
+    if Path::new("/proc/1").exists() {
+        let err = WriteLock::acquire(&path, "test").unwrap_err();
+        let locked = err.downcast_ref::<DatabaseLocked>().unwrap();
+        assert_eq!(locked.holder.as_ref(), Some(&other));
+        assert!(err.to_string().contains("Another process is writing"));
+    }
+    // A lock from a process that has ended.
+    let ended = LockHolder {
+        pid: u32::MAX,
+        ..other
+    };
+    fs::write(&path, serde_json::to_vec(&ended).unwrap()).unwrap();
+    let lock = WriteLock::acquire(&path, "test").unwrap();
+    drop(lock);
+    assert!(!path.exists());
+}
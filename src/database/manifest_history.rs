@@ -0,0 +1,129 @@
+//! Earlier manifests, kept alongside the current manifest.
+//!
+//! If `config.keep_manifest_history` is set, `Todd::generate_manifest` (requires
+//! the `publisher` feature) also saves each manifest in the history directory,
+//! named with the spec version and the time it was generated. E.g.,
+//! `nametags_manifest_history/nametags_manifest_0.1.0_1697000000.json`.
+//!
+//! The manifest at [ConfigStruct::manifest_file_path][crate::config::dirs::ConfigStruct::manifest_file_path]
+//! is the current manifest, which is used by every other method. It is a copy of
+//! the manifest it points to, rather than a link, so that it can be published as-is.
+//! [Todd::use_manifest_version] makes an earlier manifest current again (e.g., to
+//! roll back a release, or to switch between two builds).
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::types::Todd,
+    specs::traits::{DataSpec, ManifestMethods},
+};
+
+/// A manifest in the history directory.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestVersion {
+    /// The spec version in the filename. E.g., "0.1.0".
+    pub spec_version: String,
+    /// When the manifest was generated (seconds since the unix epoch).
+    pub generated: u64,
+    pub path: PathBuf,
+}
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// The manifests in the history directory, oldest first.
+    ///
+    /// Files in the directory that are not named as historical manifests are ignored.
+    pub fn manifest_history(&self) -> Result<Vec<ManifestVersion>> {
+        let dir = self.config.manifest_history_dir();
+        let mut versions: Vec<ManifestVersion> = vec![];
+        if !dir.exists() {
+            return Ok(versions);
+        }
+        let prefix = format!("{}_manifest_", self.config.data_kind.interface_id());
+        for entry in
+            fs::read_dir(&dir).with_context(|| format!("Failed to read directory {:?}", dir))?
+        {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let Some(version) = name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".json"))
+                .and_then(|rest| rest.rsplit_once('_'))
+            else {
+                continue;
+            };
+            let Ok(generated) = version.1.parse::<u64>() else {
+                continue;
+            };
+            versions.push(ManifestVersion {
+                spec_version: version.0.to_string(),
+                generated,
+                path,
+            });
+        }
+        versions.sort_by(|a, b| (a.generated, &a.path).cmp(&(b.generated, &b.path)));
+        Ok(versions)
+    }
+    /// Makes an earlier manifest the current manifest.
+    ///
+    /// The Chapter files are not changed. Files that are listed in the manifest
+    /// but not present can be obtained afterwards (e.g., with `Todd::repair_from_raw`
+    /// or `Todd::obtain_relevant_data`).
+    ///
+    /// ## Errors
+    /// If the manifest cannot be read or is for another database, or if the
+    /// database cannot be written.
+    pub fn use_manifest_version(&self, version: &ManifestVersion) -> Result<()> {
+        let _lock = self.write_lock("use_manifest_version")?;
+        let manifest = self.manifest_at(&version.path)?;
+        let database = self.config.data_kind.interface_id();
+        if manifest.database_interface_id() != database {
+            bail!(
+                "Manifest {:?} is for database {}, not {}.",
+                version.path,
+                manifest.database_interface_id(),
+                database
+            )
+        }
+        let current = self.config.manifest_file_path()?;
+        fs::copy(&version.path, &current)
+            .with_context(|| format!("Failed to copy {:?} to {:?}", version.path, current))?;
+        info!(
+            "Manifest generated at {} (spec version {}) is now current.",
+            version.generated, version.spec_version
+        );
+        Ok(())
+    }
+    /// Saves a copy of a newly generated manifest in the history directory, if
+    /// `config.keep_manifest_history` is set.
+    #[cfg_attr(not(feature = "publisher"), allow(dead_code))]
+    pub(crate) fn record_manifest_history(
+        &self,
+        json_manifest: &str,
+        spec_version: &str,
+        generated: u64,
+    ) -> Result<Option<PathBuf>> {
+        if !self.config.keep_manifest_history {
+            return Ok(None);
+        }
+        let path = self.config.manifest_history_path(spec_version, generated);
+        write_history_file(&path, json_manifest)?;
+        Ok(Some(path))
+    }
+}
+
+fn write_history_file(path: &Path, json_manifest: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, json_manifest)
+        .with_context(|| format!("Failed to write manifest history file {:?}", path))
+}
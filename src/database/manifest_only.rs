@@ -0,0 +1,152 @@
+//! Starting a database from only a manifest, for constrained devices.
+//!
+//! An application (e.g., on a phone) can ship the manifest alone and obtain
+//! Chapters as they are needed. [Todd::init_from_manifest] places the manifest
+//! in a cache directory. The data directory and raw source are not created:
+//! Chapter directories are created when their files are first obtained (e.g.,
+//! by [Todd::find] with `config.fetch_on_miss` set, or by
+//! [Todd::obtain_relevant_data]).
+//!
+//! A manifest given by CID is obtained from the gateway in `fetch_on_miss`
+//! (requires the `net` feature), unless the cache directory already holds it.
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use log::info;
+#[cfg(feature = "net")]
+use tokio::runtime::Runtime;
+
+#[cfg(feature = "net")]
+use crate::utils::download::download_bytes;
+use crate::{
+    config::choices::{DataKind, DirNature, PathPair},
+    database::{acquisition::FetchOnMiss, types::Todd},
+    manifest::trust::check_trusted_manifest,
+    specs::traits::{DataSpec, ManifestMethods},
+    utils::ipfs::cid_v0_string_from_bytes,
+};
+
+/// The manifest for [Todd::init_from_manifest].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ManifestSource {
+    /// The contents of the manifest file (e.g., bundled with an application).
+    Bytes(Vec<u8>),
+    /// The CID (v0) of the manifest file.
+    Cid(String),
+}
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Initialise the database library with only a manifest, for devices that
+    /// obtain Chapters on demand.
+    ///
+    /// The manifest is kept in `cache_dir`, which also becomes the base of the
+    /// data directory. Neither the data directory nor the raw source need exist,
+    /// and neither is created. Set `fetch_on_miss` for [Self::find] to obtain
+    /// absent Chapters.
+    ///
+    /// ## Algorithm
+    ///
+    /// - For a CID, use the manifest in `cache_dir` if it has that CID, otherwise
+    ///   obtain it from the `fetch_on_miss` gateway and check its CID.
+    /// - Check the manifest is trusted, parses, and is for `data_kind`.
+    /// - Write it to the manifest path in `cache_dir`.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let fetch = FetchOnMiss {
+    ///     gateway_url: "http://127.0.0.1:8080".to_string(),
+    ///     subdomain_gateway: false,
+    ///     max_bytes: 10_000_000,
+    /// };
+    /// let manifest = ManifestSource::Cid("QmY...".to_string());
+    /// let db: Todd<SignaturesSpec> =
+    ///     Todd::init_from_manifest(DataKind::Signatures, manifest, &cache_dir, Some(fetch))?;
+    /// let texts = db.find("0xddf252ad")?;
+    /// ```
+    ///
+    /// ## Errors
+    /// If the manifest cannot be obtained, does not have the CID, is not trusted
+    /// (see [crate::manifest::trust]), cannot be parsed, or is for another database.
+    /// A CID requires the `net` feature and `fetch_on_miss` when the manifest
+    /// is not already in `cache_dir`.
+    pub fn init_from_manifest(
+        data_kind: DataKind,
+        manifest: ManifestSource,
+        cache_dir: &Path,
+        fetch_on_miss: Option<FetchOnMiss>,
+    ) -> Result<Self> {
+        let paths = PathPair {
+            raw_source: Some(cache_dir.join(data_kind.raw_source_dir_name())),
+            processed_data_dir: Some(cache_dir.to_path_buf()),
+        };
+        let mut db = Self::init(data_kind, DirNature::Custom(paths))?;
+        db.config.fetch_on_miss = fetch_on_miss;
+        let path = db.config.manifest_file_path()?;
+        let bytes = match manifest {
+            ManifestSource::Bytes(bytes) => bytes,
+            ManifestSource::Cid(cid) => {
+                if let Ok(local) = fs::read(&path) {
+                    if cid_v0_string_from_bytes(&local)? == cid {
+                        info!("Using the manifest {} already in {:?}.", cid, cache_dir);
+                        db.check_manifest_bytes(&local)?;
+                        return Ok(db);
+                    }
+                }
+                db.obtain_manifest(&cid)?
+            }
+        };
+        db.check_manifest_bytes(&bytes)?;
+        fs::create_dir_all(cache_dir)
+            .with_context(|| format!("Unable to create cache dir {:?}", cache_dir))?;
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, &bytes)
+            .with_context(|| format!("Unable to write file {:?}", partial))?;
+        fs::rename(&partial, &path)
+            .with_context(|| format!("Unable to move {:?} to {:?}", partial, path))?;
+        Ok(db)
+    }
+    /// Checks that manifest bytes are trusted, parse, and are for this database.
+    fn check_manifest_bytes(&self, bytes: &[u8]) -> Result<()> {
+        check_trusted_manifest(bytes, &self.config.trusted_manifest_cids)?;
+        let manifest: T::AssociatedManifest =
+            serde_json::from_slice(bytes).context("Failed to parse the manifest")?;
+        let expected = self.config.data_kind.interface_id();
+        if manifest.database_interface_id() != expected {
+            bail!(
+                "The manifest is for database {}, not {}.",
+                manifest.database_interface_id(),
+                expected
+            )
+        }
+        Ok(())
+    }
+    /// Obtains the manifest with the CID from the `fetch_on_miss` gateway.
+    #[cfg(feature = "net")]
+    fn obtain_manifest(&self, cid: &str) -> Result<Vec<u8>> {
+        let Some(fetch) = &self.config.fetch_on_miss else {
+            bail!(
+                "The manifest {} is not held locally and config.fetch_on_miss is not set.",
+                cid
+            )
+        };
+        let url = fetch.gateway()?.fetch_url(cid)?;
+        let rt = Runtime::new()?;
+        let bytes = rt.block_on(download_bytes(url))?;
+        let found = cid_v0_string_from_bytes(&bytes)?;
+        if found != cid {
+            bail!("Downloaded manifest has CID {}, expected {}.", found, cid)
+        }
+        info!("Obtained manifest {} from {}.", cid, fetch.gateway_url);
+        Ok(bytes)
+    }
+    #[cfg(not(feature = "net"))]
+    fn obtain_manifest(&self, cid: &str) -> Result<Vec<u8>> {
+        bail!(
+            "The manifest {} is not held locally. Obtaining it requires the net feature.",
+            cid
+        )
+    }
+}
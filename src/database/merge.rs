@@ -0,0 +1,54 @@
+//! Reports for combining Chapter files from another local copy of a database.
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// What happened to each Chapter file found in the other data directory.
+///
+/// Created by [Todd::merge_from][crate::database::types::Todd::merge_from].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeReport {
+    /// Files copied because they were absent locally.
+    pub imported: Vec<PathBuf>,
+    /// Local files that did not match the manifest and were replaced by a matching file.
+    pub replaced: Vec<PathBuf>,
+    /// Files already present locally with the CID in the manifest.
+    pub already_present: Vec<PathBuf>,
+    /// Files in the other directory that were not used.
+    pub rejected: Vec<RejectedFile>,
+}
+
+/// A file in the other data directory that was not imported.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RejectedFile {
+    pub path: PathBuf,
+    pub reason: RejectionReason,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectionReason {
+    /// The file is not listed in the manifest.
+    NotInManifest,
+    /// The CID of the file differs from the manifest.
+    DifferentHash { expected: String, found: String },
+}
+
+impl MergeReport {
+    /// Number of files added or replaced locally.
+    pub fn files_written(&self) -> usize {
+        self.imported.len() + self.replaced.len()
+    }
+}
+
+impl std::fmt::Display for MergeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} imported, {} replaced, {} already present and {} rejected",
+            self.imported.len(),
+            self.replaced.len(),
+            self.already_present.len(),
+            self.rejected.len()
+        )
+    }
+}
@@ -0,0 +1,225 @@
+//! Describing the files held by a partial mirror, and obtaining files from
+//! several partial mirrors (see [crate::manifest::mirror_holdings]).
+use std::{collections::HashSet, fs};
+
+#[cfg(feature = "net")]
+use anyhow::bail;
+use anyhow::{Context, Result};
+#[cfg(feature = "net")]
+use log::{info, warn};
+
+#[cfg(feature = "net")]
+use crate::{
+    database::{acquisition::AcquisitionPlan, types::download_plan},
+    manifest::mirror_holdings::assign_files_except,
+};
+use crate::{
+    database::{types::Todd, verified::VerificationCache},
+    manifest::{mirror_holdings::MirrorHoldings, mirrors::ManifestMirrors},
+    specs::traits::{ChapterIdMethods, DataSpec, ManifestMethods, VolumeIdMethods},
+    utils::ipfs::cid_v0_string_from_bytes,
+};
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Describes the Chapter files held locally, for a mirror to publish
+    /// alongside its pins.
+    ///
+    /// Only files with the CID in the manifest are listed. Chapters are listed
+    /// if every one of their files in the manifest is held, other files by CID.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Default)?;
+    /// let mirrors = ManifestMirrors {
+    ///     http_mirrors: vec!["https://example.com/nametags".to_string()],
+    ///     ..Default::default()
+    /// };
+    /// let holdings = db.describe_holdings(mirrors)?;
+    /// println!("{}", serde_json::to_string_pretty(&holdings)?);
+    /// ```
+    pub fn describe_holdings(&self, mirrors: ManifestMirrors) -> Result<MirrorHoldings> {
+        let manifest_path = self.config.manifest_file_path()?;
+        let manifest_bytes = fs::read(&manifest_path)
+            .with_context(|| format!("Failed to read manifest: {:?}", &manifest_path))?;
+        let manifest_cids = self.manifest()?.cids()?;
+        let (present_files, _orphans) = self.sort_local_files(&manifest_cids)?;
+        let cache_path = self.config.verification_cache_path()?;
+        let mut cache =
+            VerificationCache::read(&cache_path).with_buffer(self.config.hash_buffer_bytes);
+
+        let mut chapters: Vec<String> = vec![];
+        let mut partial_chapters: HashSet<String> = HashSet::new();
+        let mut held: Vec<(String, String)> = vec![];
+        for m in manifest_cids {
+            let key = (m.volume_id.interface_id(), m.chapter_id.interface_id());
+            let verified = match present_files.get(&key) {
+                Some(path) => cache.cid_of(path, false)? == m.cid,
+                None => false,
+            };
+            match verified {
+                true => held.push((key.1.clone(), m.cid)),
+                false => {
+                    partial_chapters.insert(key.1.clone());
+                }
+            }
+            if !chapters.contains(&key.1) {
+                chapters.push(key.1);
+            }
+        }
+        self.record_verified(&cache, &cache_path);
+        let held_chapters: HashSet<&String> = held.iter().map(|(chapter, _)| chapter).collect();
+        chapters.retain(|c| held_chapters.contains(c) && !partial_chapters.contains(c));
+        chapters.sort();
+        let files = held
+            .iter()
+            .filter(|(chapter, _)| partial_chapters.contains(chapter))
+            .map(|(_, cid)| cid.clone())
+            .collect();
+        Ok(MirrorHoldings {
+            database_interface_id: self.config.data_kind.interface_id(),
+            manifest_cid: cid_v0_string_from_bytes(&manifest_bytes)?,
+            mirrors,
+            chapters,
+            files,
+            signature: None,
+        })
+    }
+    /// Downloads the files of a plan from partial mirrors described by holdings
+    /// descriptors. Requires the `net` feature.
+    ///
+    /// ## Algorithm
+    /// 1. Skip descriptors for another database or another manifest (by CID), or
+    ///    with an invalid signature (checked with the `web3` feature).
+    /// 2. Assign each absent file to a descriptor that holds it (see
+    ///    [assign_files][crate::manifest::mirror_holdings::assign_files]).
+    /// 3. Download the files from the mirrors of their descriptor. Files that
+    ///    differ from the manifest CID are removed.
+    /// 4. Assign the files still absent to descriptors not yet tried for them,
+    ///    and repeat until none remain.
+    ///
+    /// ## Errors
+    /// If some files are not held by any usable descriptor, or could not be
+    /// obtained from any that hold them.
+    #[cfg(feature = "net")]
+    pub fn execute_plan_from_holdings(
+        &self,
+        plan: &AcquisitionPlan,
+        holdings: &[MirrorHoldings],
+    ) -> Result<()> {
+        let _lock = self.write_lock("execute_plan")?;
+        let manifest_path = self.config.manifest_file_path()?;
+        let manifest_bytes = fs::read(&manifest_path)
+            .with_context(|| format!("Failed to read manifest: {:?}", &manifest_path))?;
+        let manifest_cid = cid_v0_string_from_bytes(&manifest_bytes)?;
+        let database = self.config.data_kind.interface_id();
+        let usable: Vec<MirrorHoldings> = holdings
+            .iter()
+            .filter(|h| match usable_holdings(h, &database, &manifest_cid) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("Skipped holdings descriptor: {:#}", e);
+                    false
+                }
+            })
+            .cloned()
+            .collect();
+        let mut remaining: Vec<_> = plan
+            .files_to_download()
+            .filter(|file| !holds_verified(file))
+            .cloned()
+            .collect();
+        let mut tried: HashSet<(usize, String)> = HashSet::new();
+        loop {
+            let assignment = assign_files_except(&remaining, &usable, |i, file| {
+                tried.contains(&(i, file.filename.clone()))
+            });
+            if assignment
+                .by_descriptor
+                .iter()
+                .all(|files| files.is_empty())
+            {
+                break;
+            }
+            for (i, files) in assignment.by_descriptor.into_iter().enumerate() {
+                for file in &files {
+                    tried.insert((i, file.filename.clone()));
+                }
+                let mut sub_plan = AcquisitionPlan {
+                    files,
+                    ..Default::default()
+                };
+                for source in usable[i].mirrors.sources() {
+                    if sub_plan.files.is_empty() {
+                        break;
+                    }
+                    if let Err(e) = download_plan(&sub_plan, &source) {
+                        warn!(
+                            "Could not obtain all files from mirror {:?}: {:#}",
+                            source, e
+                        );
+                    }
+                    sub_plan.files.retain(|file| !holds_verified(file));
+                }
+            }
+            remaining.retain(|file| !holds_verified(file));
+        }
+        if !remaining.is_empty() {
+            bail!(
+                "{} files could not be obtained from the {} usable holdings descriptors (of {}).",
+                remaining.len(),
+                usable.len(),
+                holdings.len()
+            )
+        }
+        info!(
+            "Obtained {} files from partial mirrors.",
+            plan.files_to_download().count()
+        );
+        Ok(())
+    }
+}
+
+/// Checks that a descriptor is for the database and manifest.
+#[cfg(feature = "net")]
+fn usable_holdings(holdings: &MirrorHoldings, database: &str, manifest_cid: &str) -> Result<()> {
+    if holdings.database_interface_id != database {
+        bail!(
+            "The holdings are for database {}, not {}.",
+            holdings.database_interface_id,
+            database
+        )
+    }
+    if holdings.manifest_cid != manifest_cid {
+        bail!(
+            "The holdings are for manifest {}, not the local manifest {}.",
+            holdings.manifest_cid,
+            manifest_cid
+        )
+    }
+    #[cfg(feature = "web3")]
+    holdings.verified_signer()?;
+    Ok(())
+}
+
+/// Whether the planned file is present with its CID. A file with a different
+/// CID is removed.
+#[cfg(feature = "net")]
+fn holds_verified(file: &crate::database::acquisition::PlannedFile) -> bool {
+    let path = file.dest_dir.join(&file.filename);
+    let Ok(bytes) = fs::read(&path) else {
+        return false;
+    };
+    match cid_v0_string_from_bytes(&bytes) {
+        Ok(cid) if cid == file.cid => true,
+        _ => {
+            warn!("Removing {:?}, which differs from the manifest.", path);
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Could not remove {:?}: {}", path, e);
+            }
+            false
+        }
+    }
+}
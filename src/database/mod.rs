@@ -1,3 +1,40 @@
 //! The database creation is generic over all types of destination
 //! databases.
+pub mod acquisition;
+pub mod appearances;
+#[cfg(feature = "publisher")]
+pub mod builder;
+pub mod car;
+pub mod changes;
+#[cfg(feature = "compiled-index")]
+mod compiled;
+#[cfg(feature = "publisher")]
+pub mod draft;
+pub mod encryption;
+pub mod errata;
+#[cfg(feature = "publisher")]
+pub mod extension;
+pub mod holdings;
+pub mod lock;
+pub mod manifest_history;
+pub mod manifest_only;
+pub mod merge;
+pub mod mirror_holdings;
+pub mod nametags;
+#[cfg(feature = "net")]
+pub mod probe;
+pub mod proof;
+pub mod prototype;
+#[cfg(feature = "publisher")]
+mod publish;
+pub mod query_cache;
+pub mod report;
+#[cfg(feature = "publisher")]
+pub mod reproduction;
+pub mod signatures;
+#[cfg(feature = "net")]
+pub mod sync;
 pub mod types;
+pub(crate) mod verified;
+#[cfg(feature = "watch")]
+pub mod watch;
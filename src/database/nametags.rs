@@ -0,0 +1,41 @@
+//! Helpers specific to the nametags database.
+use anyhow::Result;
+
+use crate::specs::nametags::{merge_volumes, NameTagsSpec};
+pub use crate::specs::nametags::{Label, MergedNameTags};
+
+use super::types::Todd;
+
+/// Name and tag lookups with the Records from all Volumes merged.
+///
+/// ## Example
+/// ```
+/// # use min_know::{
+/// #    config::choices::{DataKind, DirNature},
+/// #    database::{nametags::NametagClient, types::Todd},
+/// # };
+/// let client = NametagClient::new(Todd::init(DataKind::NameTags, DirNature::Sample)?);
+/// let labels = client.labels("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae")?;
+/// assert_eq!(labels.names[0].text, "EthDev");
+/// assert_eq!(labels.tags[0].text, "ethereum-foundation");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct NametagClient {
+    pub db: Todd<NameTagsSpec>,
+}
+
+impl NametagClient {
+    pub fn new(db: Todd<NameTagsSpec>) -> Self {
+        NametagClient { db }
+    }
+    /// Returns the names and tags of an address.
+    ///
+    /// Records from later Volumes replace those from the same source in
+    /// earlier Volumes. See [crate::specs::nametags] for the rule.
+    ///
+    /// ## Errors
+    /// As for [Todd::find].
+    pub fn labels(&self, address: &str) -> Result<MergedNameTags> {
+        merge_volumes(&self.db.find_by_volume(address)?)
+    }
+}
@@ -0,0 +1,226 @@
+//! Checking that a gateway serves correct bytes before a large download.
+//!
+//! A gateway may be slow, or serve something other than the requested file
+//! (e.g., an error page with a success status). [Todd::probe_gateway] fetches
+//! one small Chapter file listed in the manifest, checks it against its CID and
+//! measures the latency and throughput. Requires the `net` feature.
+//!
+//! When the manifest lists more than one mirror,
+//! [Todd::execute_plan_from_mirrors] probes them first (see [Todd::rank_sources])
+//! and tries healthy mirrors first, fastest first.
+use std::{
+    fmt::Display,
+    fs,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use log::info;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::{
+    database::{acquisition::PlannedFile, types::Todd},
+    manifest::mirrors::FileSource,
+    specs::traits::{ChapterIdMethods, ChapterMethods, DataSpec, ManifestMethods, VolumeIdMethods},
+    utils::{gateway::Gateway, ipfs::cid_v0_string_from_bytes},
+};
+
+/// How long a probe waits for the file before the source is unreachable.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Whether a source served the probed file correctly.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SourceHealth {
+    /// The file was served and has the CID in the manifest.
+    Healthy,
+    /// The file was served, but has a different CID.
+    WrongBytes { found_cid: String },
+    /// The file could not be fetched (e.g., a timeout or non-success status).
+    Unreachable { reason: String },
+}
+
+/// The outcome of probing a gateway or mirror. Requires the `net` feature.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceProbe {
+    /// The gateway or mirror probed.
+    pub source: String,
+    /// The CID of the file that was fetched.
+    pub cid: String,
+    pub health: SourceHealth,
+    /// Bytes received.
+    pub bytes: u64,
+    /// Milliseconds until the response headers were received.
+    pub latency_ms: u64,
+    /// Milliseconds until the whole file was received.
+    pub total_ms: u64,
+}
+
+impl SourceProbe {
+    pub fn is_healthy(&self) -> bool {
+        self.health == SourceHealth::Healthy
+    }
+    /// Bytes received per second, over the whole request.
+    pub fn throughput(&self) -> f64 {
+        self.bytes as f64 / (self.total_ms.max(1) as f64 / 1000.0)
+    }
+}
+
+impl Display for SourceProbe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.source)?;
+        match &self.health {
+            SourceHealth::Healthy => write!(
+                f,
+                "healthy, {} bytes, {} ms latency, {:.1} KB/s.",
+                self.bytes,
+                self.latency_ms,
+                self.throughput() / 1000.0
+            ),
+            SourceHealth::WrongBytes { found_cid } => write!(
+                f,
+                "served {} bytes with CID {} rather than {}.",
+                self.bytes, found_cid, self.cid
+            ),
+            SourceHealth::Unreachable { reason } => write!(f, "unreachable ({}).", reason),
+        }
+    }
+}
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Checks that a gateway serves a file from the manifest correctly, and
+    /// measures its latency and throughput. Requires the `net` feature.
+    ///
+    /// Nothing is written locally.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let gateway = Gateway::new("http://127.0.0.1:8080")?;
+    /// let probe = db.probe_gateway(&gateway)?;
+    /// if !probe.is_healthy() {
+    ///     println!("{}", probe);
+    /// }
+    /// ```
+    ///
+    /// ## Errors
+    /// If the manifest cannot be read or lists no Chapters. A gateway that
+    /// cannot be reached or serves other bytes is reported in the [SourceProbe].
+    pub fn probe_gateway(&self, gateway: &Gateway) -> Result<SourceProbe> {
+        self.probe_source(&FileSource::Gateway(gateway.clone()))
+    }
+    /// Checks a gateway or HTTP mirror, as for [Self::probe_gateway].
+    pub fn probe_source(&self, source: &FileSource) -> Result<SourceProbe> {
+        let file = self.probe_file()?;
+        Runtime::new()?.block_on(probe(source, &file))
+    }
+    /// Probes each source (see [Self::probe_gateway]) and orders them: healthy
+    /// sources first, by throughput (fastest first), then the others in the
+    /// order given.
+    ///
+    /// The same file is fetched from every source.
+    pub fn rank_sources(&self, sources: Vec<FileSource>) -> Result<Vec<(FileSource, SourceProbe)>> {
+        let file = self.probe_file()?;
+        let rt = Runtime::new()?;
+        let mut ranked = vec![];
+        for source in sources {
+            let probe = rt.block_on(probe(&source, &file))?;
+            info!("Probed {}", probe);
+            ranked.push((source, probe));
+        }
+        let speed = |p: &SourceProbe| match p.is_healthy() {
+            true => p.throughput(),
+            false => 0.0,
+        };
+        // Stable, so that unhealthy sources keep their order.
+        ranked.sort_by(|(_, a), (_, b)| {
+            b.is_healthy()
+                .cmp(&a.is_healthy())
+                .then(speed(b).total_cmp(&speed(a)))
+        });
+        Ok(ranked)
+    }
+    /// The file used to probe sources: the smallest Chapter file in the
+    /// manifest that is present locally, or else the first in the manifest.
+    ///
+    /// The manifest does not record file sizes, so local files are the only
+    /// indication of which files are small.
+    fn probe_file(&self) -> Result<PlannedFile> {
+        let mut files = vec![];
+        for m in self.manifest()?.cids()? {
+            let dest_dir = self.config.chapter_dir_path(&m.chapter_id);
+            let filename = T::AssociatedChapter::new_empty(&m.volume_id, &m.chapter_id).filename();
+            let local_bytes = fs::metadata(dest_dir.join(&filename))
+                .ok()
+                .map(|meta| meta.len());
+            files.push(PlannedFile {
+                cid: m.cid,
+                volume_interface_id: m.volume_id.interface_id(),
+                chapter_interface_id: m.chapter_id.interface_id(),
+                dest_dir,
+                filename,
+                local_bytes,
+            })
+        }
+        let smallest_local = files
+            .iter()
+            .filter(|f| f.local_bytes.is_some())
+            .min_by_key(|f| f.local_bytes)
+            .cloned();
+        match smallest_local.or_else(|| files.into_iter().next()) {
+            Some(file) => Ok(file),
+            None => bail!("The manifest lists no Chapters to probe sources with."),
+        }
+    }
+}
+
+/// Fetches the file from the source, checks its CID and times the request.
+///
+/// ## Errors
+/// Only if the URL of the file cannot be formed.
+async fn probe(source: &FileSource, file: &PlannedFile) -> Result<SourceProbe> {
+    let url = source.file_url(file)?;
+    let mut report = SourceProbe {
+        source: match source {
+            FileSource::Gateway(gateway) => gateway.to_string(),
+            FileSource::HttpMirror(url) => url.to_string(),
+        },
+        cid: file.cid.clone(),
+        health: SourceHealth::Healthy,
+        bytes: 0,
+        latency_ms: 0,
+        total_ms: 0,
+    };
+    let unreachable = |reason: String| SourceHealth::Unreachable { reason };
+    let client = Client::builder().timeout(PROBE_TIMEOUT).build()?;
+    let start = Instant::now();
+    let response = match client.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            report.health = unreachable(e.to_string());
+            return Ok(report);
+        }
+    };
+    report.latency_ms = start.elapsed().as_millis() as u64;
+    if !response.status().is_success() {
+        report.health = unreachable(format!("status {}", response.status()));
+        return Ok(report);
+    }
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => {
+            report.health = unreachable(e.to_string());
+            return Ok(report);
+        }
+    };
+    report.total_ms = start.elapsed().as_millis() as u64;
+    report.bytes = body.len() as u64;
+    let found_cid = cid_v0_string_from_bytes(&body)?;
+    if found_cid != file.cid {
+        report.health = SourceHealth::WrongBytes { found_cid };
+    }
+    Ok(report)
+}
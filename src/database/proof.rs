@@ -0,0 +1,39 @@
+//! Proofs that a Record is part of a Chapter.
+//!
+//! The root of a Chapter is computed from its Records (see [crate::utils::merkle]).
+//! Anyone holding the Chapter (e.g., fetched by the CID in the manifest) can compute
+//! the root once, and then check proofs for single Records without the rest of the data.
+use serde::{Deserialize, Serialize};
+
+use crate::utils::merkle::{leaf, root_from_branch, Node};
+
+/// A Record and the nodes needed to recompute the root of its Chapter.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub volume_interface_id: String,
+    pub chapter_interface_id: String,
+    /// The SSZ encoded Record.
+    pub record: Vec<u8>,
+    /// Position of the Record in the Chapter.
+    pub index: u64,
+    /// Number of Records in the Chapter.
+    pub record_count: u64,
+    /// Sibling nodes, starting next to the Record.
+    pub branch: Vec<Node>,
+}
+
+impl InclusionProof {
+    /// Root of the Chapter implied by the proof.
+    pub fn root(&self) -> Node {
+        root_from_branch(
+            &leaf(&self.record),
+            self.index as usize,
+            &self.branch,
+            self.record_count,
+        )
+    }
+    /// Whether the proof shows the Record is in the Chapter with the given root.
+    pub fn verify(&self, chapter_root: &Node) -> bool {
+        self.index < self.record_count && &self.root() == chapter_root
+    }
+}
@@ -0,0 +1,244 @@
+//! Data built by the prototype pipeline (`transform.rs`) of the address appearance index.
+//!
+//! The prototype wrote snappy compressed Chapter files with a different Record
+//! layout and names. E.g., `chapter_0x4e_volume_014_400_000.ssz_snappy` rather
+//! than `volume_014_400_000_chapter_0x4e.ssz`.
+//!
+//! Prototype files are detected by [AAIChapter::format_of] and read by
+//! [AAIChapter::from_file], so queries work before the files are migrated. They are not recognised as Chapter files elsewhere
+//! (e.g., in an inventory or manifest) until they are migrated by
+//! [Todd::migrate_prototype_layout].
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{encryption::is_encrypted, types::Todd},
+    specs::{
+        address_appearance_index::{AAIChapter, AAIChapterFormat, AAISpec},
+        traits::{ChapterIdMethods, ChapterMethods, VolumeIdMethods},
+    },
+};
+
+/// Name of a prototype Chapter file: chapter interface id, then Volume interface id.
+const PROTOTYPE_FILENAME: &str = r"^(chapter_0x[0-9a-f]+)_(volume_\d{3}_\d{3}_\d{3})\.ssz_snappy$";
+
+/// The outcome of [Todd::migrate_prototype_layout].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrototypeMigration {
+    /// Chapter files written in the current format.
+    pub converted: Vec<PathBuf>,
+    /// Prototype files that were left in place, with the reason.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl Todd<AAISpec> {
+    /// Chapter files in the prototype layout, in the data directory or its
+    /// Chapter directories.
+    pub fn prototype_files(&self) -> Result<Vec<PathBuf>> {
+        let pattern = Regex::new(PROTOTYPE_FILENAME)?;
+        let mut found: Vec<PathBuf> = vec![];
+        if !self.config.data_dir.exists() {
+            return Ok(found);
+        }
+        let mut dirs = vec![self.config.data_dir.clone()];
+        for entry in fs::read_dir(&self.config.data_dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            }
+        }
+        for dir in dirs {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                if path.is_file() && pattern.is_match(&name) {
+                    found.push(path);
+                }
+            }
+        }
+        found.sort();
+        Ok(found)
+    }
+    /// Converts Chapter files in the prototype layout to the current format.
+    ///
+    /// ## Algorithm
+    /// 1. Find the prototype files (see [Self::prototype_files]).
+    /// 2. Decode each file and check that it holds the Chapter and Volume in its name.
+    /// 3. Write the Chapter (with a header) to its Chapter directory, alongside
+    ///    the destination and then moved into place.
+    /// 4. Remove the prototype file.
+    /// 5. Read every Chapter file with a current name, and rewrite those with
+    ///    prototype contents (see [AAIChapter::format_of]) in the same way.
+    ///
+    /// A file that cannot be converted is left in place and listed in the result.
+    /// Generate a manifest afterwards to list the converted files.
+    pub fn migrate_prototype_layout(&self) -> Result<PrototypeMigration> {
+        let _lock = self.write_lock("migrate_prototype_layout")?;
+        let mut migration = PrototypeMigration::default();
+        for path in self.prototype_files()? {
+            match self.migrate_prototype_file(&path) {
+                Ok(converted) => migration.converted.push(converted),
+                Err(e) => {
+                    warn!("Prototype file {:?} not converted: {:#}", path, e);
+                    migration.failed.push((path, format!("{:#}", e)));
+                }
+            }
+        }
+        let mut current_files = vec![];
+        for (_dir, chapter_id) in self.config.chapter_dirs::<AAISpec>()? {
+            // Prototype files that were not converted are not Chapter files.
+            let (files, _unknown) = self.config.sort_files_for_chapter::<AAISpec>(&chapter_id)?;
+            current_files.extend(files.into_iter().map(|(path, _volume_id)| path));
+        }
+        for path in current_files {
+            match self.migrate_prototype_contents(&path) {
+                Ok(false) => {}
+                Ok(true) => migration.converted.push(path),
+                Err(e) => {
+                    warn!("Chapter file {:?} not converted: {:#}", path, e);
+                    migration.failed.push((path, format!("{:#}", e)));
+                }
+            }
+        }
+        info!(
+            "Converted {} prototype Chapter files ({} failed).",
+            migration.converted.len(),
+            migration.failed.len()
+        );
+        Ok(migration)
+    }
+    /// Rewrites a Chapter file with a current name if it has prototype contents.
+    ///
+    /// Returns whether the file was rewritten.
+    fn migrate_prototype_contents(&self, path: &Path) -> Result<bool> {
+        let bytes = fs::read(path).with_context(|| format!("Unable to read file {:?}", path))?;
+        if is_encrypted(&bytes) || AAIChapter::format_of(&bytes)? == AAIChapterFormat::Current {
+            return Ok(false);
+        }
+        let chapter = AAIChapter::from_prototype_bytes(&bytes)?;
+        if path.file_name().unwrap_or_default().to_string_lossy() != chapter.filename() {
+            bail!("File contents are for {}", chapter.filename())
+        }
+        let partial = path.with_file_name(format!("{}.partial", chapter.filename()));
+        fs::write(
+            &partial,
+            self.config.chapter_file_bytes::<AAISpec>(&chapter)?,
+        )
+        .with_context(|| format!("Unable to write file {:?}", partial))?;
+        fs::rename(&partial, path)
+            .with_context(|| format!("Unable to move {:?} to {:?}", partial, path))?;
+        Ok(true)
+    }
+    /// Converts a single prototype file, returning the path of the converted file.
+    fn migrate_prototype_file(&self, path: &Path) -> Result<PathBuf> {
+        let bytes = fs::read(path).with_context(|| format!("Unable to read file {:?}", path))?;
+        let chapter = AAIChapter::from_prototype_bytes(&bytes)?;
+        let expected = format!(
+            "{}_{}.ssz_snappy",
+            chapter.chapter_id.interface_id(),
+            chapter.volume_id.interface_id()
+        );
+        if path.file_name().unwrap_or_default().to_string_lossy() != expected {
+            bail!("File contents are for {}", expected)
+        }
+        let dest_dir = self.config.chapter_dir_path(&chapter.chapter_id);
+        fs::create_dir_all(&dest_dir)?;
+        let dest = dest_dir.join(chapter.filename());
+        let partial = dest_dir.join(format!("{}.partial", chapter.filename()));
+        fs::write(
+            &partial,
+            self.config.chapter_file_bytes::<AAISpec>(&chapter)?,
+        )
+        .with_context(|| format!("Unable to write file {:?}", partial))?;
+        fs::rename(&partial, &dest)
+            .with_context(|| format!("Unable to move {:?} to {:?}", partial, dest))?;
+        fs::remove_file(path)?;
+        Ok(dest)
+    }
+}
+
+#[test]
+fn prototype_files_read_and_migrated() {
+    use ssz_rs::prelude::List;
+
+    use crate::{
+        config::{
+            address_appearance_index::Network,
+            choices::{DataKind, DirNature},
+        },
+        specs::{
+            address_appearance_index::{
+                AAIAppearanceTx, AAIChapterId, AAIRecord, AAIRecordValue, AAIVolumeId,
+            },
+            traits::DataSpec,
+        },
+    };
+
+    let address = "0x4e8a9a2b47e2c6ee2e5ed1d7b0fd07d6ee3e8c51";
+    let db: Todd<AAISpec> = Todd::init(
+        DataKind::AddressAppearanceIndex(Network::default()),
+        DirNature::Ephemeral,
+    )
+    .unwrap();
+    let value = AAIRecordValue {
+        value: List::from_iter(vec![AAIAppearanceTx {
+            block: 14_400_001,
+            index: 3,
+        }]),
+    };
+    let chapter_id = AAIChapterId::nth_id(0x4e).unwrap();
+    let chapter = AAIChapter {
+        chapter_id: chapter_id.clone(),
+        volume_id: AAIVolumeId::nth_id(144).unwrap(),
+        records: List::from_iter(vec![AAIRecord {
+            key: AAISpec::raw_key_as_record_key(address).unwrap(),
+            value: value.clone(),
+        }]),
+    };
+    let chapter_dir = db.config.chapter_dir_path(&chapter_id);
+    fs::create_dir_all(&chapter_dir).unwrap();
+    let prototype = chapter_dir.join("chapter_0x4e_volume_014_400_000.ssz_snappy");
+    fs::write(&prototype, chapter.as_prototype_bytes().unwrap()).unwrap();
+    // Misnamed files are not converted.
+    let misnamed = chapter_dir.join("chapter_0x4e_volume_014_500_000.ssz_snappy");
+    fs::write(&misnamed, chapter.as_prototype_bytes().unwrap()).unwrap();
+    assert_eq!(
+        db.prototype_files().unwrap(),
+        vec![prototype.clone(), misnamed.clone()]
+    );
+    fs::remove_file(&misnamed).unwrap();
+    // Queries read prototype files.
+    assert_eq!(db.find(address).unwrap(), vec![value.clone()]);
+
+    fs::write(&misnamed, chapter.as_prototype_bytes().unwrap()).unwrap();
+    let migration = db.migrate_prototype_layout().unwrap();
+    assert_eq!(
+        migration.converted,
+        vec![chapter_dir.join("volume_014_400_000_chapter_0x4e.ssz")]
+    );
+    assert_eq!(migration.failed.len(), 1);
+    assert!(!prototype.exists());
+    fs::remove_file(&misnamed).unwrap();
+    assert_eq!(db.inventory().unwrap().len(), 1);
+    assert_eq!(db.find(address).unwrap(), vec![value.clone()]);
+
+    // Prototype contents under a current name are rewritten in place.
+    let current = chapter_dir.join("volume_014_400_000_chapter_0x4e.ssz");
+    fs::write(&current, chapter.as_prototype_bytes().unwrap()).unwrap();
+    let migration = db.migrate_prototype_layout().unwrap();
+    assert_eq!(migration.converted, vec![current.clone()]);
+    let bytes = fs::read(&current).unwrap();
+    assert_eq!(
+        AAIChapter::format_of(&bytes).unwrap(),
+        AAIChapterFormat::Current
+    );
+    assert!(db.migrate_prototype_layout().unwrap().converted.is_empty());
+    assert_eq!(db.find(address).unwrap(), vec![value]);
+}
@@ -0,0 +1,1662 @@
+//! Creation of databases and manifests from raw data.
+//!
+//! Requires the `publisher` feature. A user that only queries and obtains
+//! existing data does not need these methods.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::SyncSender,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, error, info, warn};
+use rayon::prelude::*;
+
+use crate::{
+    config::choices::{DirNature, RawRegressionPolicy},
+    database::{
+        draft::{read_draft, remove_draft, DraftEntries, DraftEntry, ManifestDraft},
+        extension::{
+            ChapterFailure, ChapterOutcome, ChapterStatus, ChapterStream, ChaptersFailed,
+            ExtendOutcome,
+        },
+        holdings::Holdings,
+        report::HashThroughput,
+        types::{bloom_item, OversizeValue, Todd, ValidationReport},
+        verified::VerificationCache,
+    },
+    extraction::traits::{ExtractedChapter, ExtractorMethods},
+    manifest::samples::{SampleMismatchReason, SamplesManifest, SamplesMismatch},
+    samples::traits::{SampleArtifact, SampleObtainerMethods},
+    specs::traits::{
+        ChapterIdMethods, ChapterMethods, DataSpec, ManifestCids, ManifestMethods, RecordMethods,
+        VolumeIdMethods,
+    },
+    utils::{
+        bloom::BloomFilter, ipfs::cid_v0_string_from_bytes, parallelism::IoLimit,
+        progress::Progress, system::DirFunctions,
+    },
+};
+
+/// A CID for a file that belongs to a Chapter: (CID, VolumeId, ChapterId).
+type ChapterCid<T> = (
+    String,
+    <T as DataSpec>::AssociatedVolumeId,
+    <T as DataSpec>::AssociatedChapterId,
+);
+
+/// The scanned raw source directory of a database.
+pub(super) type Source<T> = <<T as DataSpec>::AssociatedExtractor as ExtractorMethods<T>>::Source;
+
+/// Where the raw samples came from.
+enum RawSamples {
+    /// Present, copied or downloaded.
+    Real,
+    /// Generated because the samples could not be obtained.
+    Synthetic,
+}
+
+/// A VolumeId with its position and the raw data it covers, for messages.
+///
+/// E.g., "nametags_from_000_001_000 (number 1, addresses 1000-1999)".
+fn describe_volume<T: DataSpec>(volume_id: &T::AssociatedVolumeId) -> Result<String> {
+    let position = format!("number {}", volume_id.is_nth()?);
+    Ok(match volume_id.coverage() {
+        Some(c) => format!(
+            "{} ({}, {} {}-{})",
+            volume_id.interface_id(),
+            position,
+            c.unit,
+            c.first,
+            c.last
+        ),
+        None => format!("{} ({})", volume_id.interface_id(), position),
+    })
+}
+
+/// What happened to one Chapter during a build.
+enum ChapterBuilt {
+    /// A Chapter file was saved.
+    Written {
+        cid_v0: String,
+        bytes: u64,
+    },
+    /// There was no raw data for the Chapter.
+    Empty,
+    Failed(ChapterFailure),
+}
+
+/// Where the Chapters of a streamed build are sent, see [ChapterStream].
+type OutcomeSender = SyncSender<ChapterOutcome>;
+
+/// The Chapters handled during a build.
+#[derive(Default)]
+struct BuildSummary {
+    written: usize,
+    /// Kept from an earlier build, or with no raw data.
+    skipped: usize,
+    errors: Vec<ChapterFailure>,
+}
+
+impl BuildSummary {
+    fn add(&mut self, built: ChapterBuilt) {
+        match built {
+            ChapterBuilt::Written { .. } => self.written += 1,
+            ChapterBuilt::Empty => self.skipped += 1,
+            ChapterBuilt::Failed(failure) => self.errors.push(failure),
+        }
+    }
+    /// Logs the failed Chapters, returning them as an error if there are any.
+    fn check(self, operation: &str) -> Result<Self> {
+        if self.errors.is_empty() {
+            return Ok(self);
+        }
+        for failure in &self.errors {
+            error!("Chapter not created: {}", failure);
+        }
+        Err(ChaptersFailed {
+            operation: operation.to_string(),
+            failures: self.errors,
+        }
+        .into())
+    }
+}
+
+/// Records why a Chapter could not be formed or saved.
+fn chapter_failure<T: DataSpec>(
+    volume_id: &T::AssociatedVolumeId,
+    chapter_id: &T::AssociatedChapterId,
+    error: &anyhow::Error,
+) -> ChapterFailure {
+    ChapterFailure {
+        volume_interface_id: volume_id.interface_id(),
+        chapter_interface_id: chapter_id.interface_id(),
+        reason: format!("{:#}", error),
+    }
+}
+
+/// Sends a handled Chapter to the stream, if the build is streamed.
+fn send_outcome<T: DataSpec>(
+    stream: Option<&OutcomeSender>,
+    volume_id: &T::AssociatedVolumeId,
+    chapter_id: &T::AssociatedChapterId,
+    built: &ChapterBuilt,
+) {
+    let Some(sender) = stream else { return };
+    let status = match built {
+        ChapterBuilt::Written { cid_v0, bytes } => ChapterStatus::Written {
+            cid_v0: cid_v0.clone(),
+            bytes: *bytes,
+        },
+        ChapterBuilt::Empty => ChapterStatus::Empty,
+        ChapterBuilt::Failed(failure) => ChapterStatus::Failed {
+            reason: failure.reason.clone(),
+        },
+    };
+    // Fails if the stream was dropped, in which case the build continues.
+    let _ = sender.send(ChapterOutcome {
+        volume_interface_id: volume_id.interface_id(),
+        chapter_interface_id: chapter_id.interface_id(),
+        status,
+    });
+}
+
+/// Groups (VolumeId, ChapterId) pairs by VolumeId, keeping the order of first appearance.
+fn group_by_volume<T: DataSpec>(
+    ids: &[&(&T::AssociatedVolumeId, &T::AssociatedChapterId)],
+) -> Vec<(T::AssociatedVolumeId, Vec<T::AssociatedChapterId>)> {
+    let mut volumes: Vec<(T::AssociatedVolumeId, Vec<T::AssociatedChapterId>)> = vec![];
+    for (volume_id, chapter_id) in ids {
+        match volumes.iter_mut().find(|(v, _)| v == *volume_id) {
+            Some((_, chapter_ids)) => chapter_ids.push((*chapter_id).clone()),
+            None => volumes.push(((*volume_id).clone(), vec![(*chapter_id).clone()])),
+        }
+    }
+    volumes
+}
+
+/// Interval at which progress is logged during Chapter creation, regardless of completions.
+const PROGRESS_HEARTBEAT: Duration = Duration::from_secs(30);
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Creates new and complete TODD-compliant database from
+    /// a specification and corresponding raw data source.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut db: Todd<AAISpec> = Todd::init(DataKind::default(), DirNature::Sample)?;
+    /// db.full_transformation()?;
+    /// ```
+    /// ## Algorithm
+    /// Relies on the existence of an Extractor method that each database must implement.
+    /// That method raw source data in the specified directory and produces a Chapter
+    /// that matches the specified VolumeId and ChapterId.
+    ///
+    /// The returned Chapter is then saved.
+    /// This is repeated for all possible Chapters and may occur in parallel.
+    /// The raw source directory is scanned once and shared by every Chapter.
+    ///
+    /// If `config.validate_raw_first` is set, the raw data is checked with
+    /// [Self::validate_raw] first.
+    ///
+    /// If `config.watched_keys` is set, only the Chapters for those keys are
+    /// created, and they only hold the Records for those keys.
+    ///
+    /// Chapter files from an earlier build that have the CID in the existing
+    /// manifest (or were recorded in the manifest draft) are kept rather than
+    /// formed again. To form them again from changed raw data, remove the manifest.
+    ///
+    /// If `config.single_pass_volumes` is set (and there are no watched keys),
+    /// all the Chapters of a Volume are formed together, see
+    /// [ExtractorMethods::volume_from_source].
+    ///
+    /// Chapters that cannot be formed or saved do not stop the build. If there
+    /// are any, the manifest is not written and they are returned as a
+    /// [ChaptersFailed] error. Running again forms only those Chapters.
+    ///
+    /// ## Errors
+    /// If `config.validate_raw_first` is set and the raw data has problems, or
+    /// if some Chapters could not be created ([ChaptersFailed]).
+    pub fn full_transformation(&self) -> Result<()> {
+        let _lock = self.write_lock("full_transformation")?;
+        self.transform(None)
+    }
+    /// Starts [Self::full_transformation] on another thread, returning each
+    /// Chapter as it is completed.
+    ///
+    /// The Chapters are formed in parallel as usual. The result of the build
+    /// is returned by [ChapterStream::finish].
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut stream = db.transform_stream()?;
+    /// for outcome in stream.by_ref() {
+    ///     println!("{}", outcome);
+    /// }
+    /// stream.finish()?;
+    /// ```
+    ///
+    /// ## Errors
+    /// If the database cannot be written to (e.g., another build holds the
+    /// lock). Errors during the build are returned by [ChapterStream::finish].
+    pub fn transform_stream(&self) -> Result<ChapterStream<()>>
+    where
+        Self: Clone + Send + 'static,
+    {
+        let db = self.clone();
+        ChapterStream::spawn(move |start, sender| {
+            let _lock = start.report(db.write_lock("full_transformation"))?;
+            db.transform(Some(&sender))
+        })
+    }
+    /// Forms every Chapter and the manifest, see [Self::full_transformation].
+    fn transform(&self, stream: Option<&OutcomeSender>) -> Result<()> {
+        if self.config.validate_raw_first {
+            let report = self.validate_raw()?;
+            if !report.is_valid() {
+                for issue in &report.issues {
+                    error!("Invalid raw data: {}", issue);
+                }
+                bail!(
+                    "Raw data in {:?} is invalid ({}). No Chapters were created.",
+                    self.config.raw_source,
+                    report
+                )
+            }
+            info!("Raw data is valid ({}).", report);
+        }
+        let source = self.scan_source()?;
+        let volume_ids = &T::AssociatedExtractor::latest_volume_in_source(&source)?.all_prior()?;
+        let chapter_ids = &self.chapter_ids_to_create()?;
+        self.create_chapter_combinations(&source, volume_ids, chapter_ids, stream)?
+            .check("full_transformation")?;
+        info!("Finished creating database.");
+        self.generate_manifest()?;
+        Ok(())
+    }
+    /// Extends the database by transforming unincorporated raw data.
+    ///
+    /// Suitable for running on a schedule (e.g., from cron). The returned
+    /// [ExtendOutcome] describes what was done, see [ExtendOutcome::status].
+    ///
+    /// ## Algorithm
+    /// - Get the latest VolumeId present in processed data. If a previous run
+    ///   did not finish (the manifest draft is present), the latest VolumeId in
+    ///   the manifest is used instead, so that its Volumes are completed.
+    /// - Get all VolumeIds possible based on raw data (use extractor methods)
+    /// - Keep only the VolumeIds that are later than the latest existing VolumeId.
+    /// - For vol_ids/chapter_ids combinations, self.create_chapter
+    ///     - Only the ChapterIds for `config.watched_keys`, if set. Changing the
+    ///       watched keys requires a full transformation.
+    /// - Generate manifest unless there were no new Volumes, or some Chapters failed.
+    ///
+    /// ## Idempotency
+    /// - Running again with the same raw data changes nothing and returns
+    ///   [ExtendStatus::NothingToDo][crate::database::extension::ExtendStatus::NothingToDo].
+    /// - If some Chapters cannot be formed or saved, the others are kept, the
+    ///   failures are returned in [ExtendOutcome::errors] and the manifest is not
+    ///   written. Running again (e.g., after fixing the raw data) forms only the
+    ///   Chapters that were not completed, then writes the manifest.
+    /// - If a run is interrupted, the next run resumes in the same way.
+    /// - Runs are serialised by the database write lock: a run started while
+    ///   another is in progress returns an error without making changes.
+    ///
+    /// If the raw data ends before the latest existing Volume (e.g., the raw
+    /// source was restored from an old backup), `config.raw_regression` decides
+    /// what happens, see [RawRegressionPolicy]. By default a warning is logged and
+    /// the existing Chapters are kept.
+    ///
+    /// ## Database specific concepts
+    ///
+    /// For each database, the latest volume can be found from raw data properties:
+    /// - AAI: Block number of the latest chunk is used.
+    /// - Nametag: Index of the last file in the append-only raw database.
+    ///     - Edits are appended not added as a new entry, not included in the exsisting file.
+    ///     - All entries have an index. The index of the latest entry is used.
+    /// - Contract source code: The index of the latest entry is used.
+    /// - 4 byte signature: The index of the latest entry is used.
+    pub fn extend(&self) -> Result<ExtendOutcome> {
+        let _lock = self.write_lock("extend")?;
+        self.extend_chapters(None)
+    }
+    /// Starts [Self::extend] on another thread, returning each Chapter as it
+    /// is completed, as for [Self::transform_stream].
+    ///
+    /// The [ExtendOutcome] is returned by [ChapterStream::finish].
+    pub fn extend_stream(&self) -> Result<ChapterStream<ExtendOutcome>>
+    where
+        Self: Clone + Send + 'static,
+    {
+        let db = self.clone();
+        ChapterStream::spawn(move |start, sender| {
+            let _lock = start.report(db.write_lock("extend"))?;
+            db.extend_chapters(Some(&sender))
+        })
+    }
+    /// Forms the Chapters of new Volumes, see [Self::extend].
+    fn extend_chapters(&self, stream: Option<&OutcomeSender>) -> Result<ExtendOutcome> {
+        let source = self.scan_source()?;
+        let latest_raw_vol = T::AssociatedExtractor::latest_volume_in_source(&source)?;
+        let all_possible_volume_ids = latest_raw_vol.all_prior()?;
+
+        let resuming = self.config.manifest_draft_path()?.exists();
+        let latest_existing_vol = self.extension_baseline(resuming)?;
+        let index_of_existing = latest_existing_vol.is_nth()? as usize;
+        if (latest_raw_vol.is_nth()? as usize) < index_of_existing {
+            return self.handle_raw_regression(
+                &source,
+                &latest_raw_vol,
+                &latest_existing_vol,
+                stream,
+            );
+        }
+
+        let mut new_volume_ids: Vec<T::AssociatedVolumeId> = vec![];
+        for (index, vol) in all_possible_volume_ids.into_iter().enumerate() {
+            if index > index_of_existing {
+                new_volume_ids.push(vol);
+            }
+        }
+        if new_volume_ids.is_empty() && !resuming && self.config.manifest_file_path()?.exists() {
+            info!("No new raw data. The database is up to date.");
+            return Ok(ExtendOutcome::default());
+        }
+        let chapter_ids = &self.chapter_ids_to_create()?;
+        let summary =
+            self.create_chapter_combinations(&source, &new_volume_ids, chapter_ids, stream)?;
+        let outcome = self.finish_extension(&new_volume_ids, summary)?;
+        info!("Finished extending database. {}", outcome);
+        Ok(outcome)
+    }
+    /// The latest VolumeId already in the database, which extension starts after.
+    ///
+    /// When resuming, this is the VolumeId in the manifest, because the files of
+    /// an unfinished Volume may already be present.
+    fn extension_baseline(&self, resuming: bool) -> Result<T::AssociatedVolumeId> {
+        if resuming && self.config.manifest_file_path()?.exists() {
+            let manifest = self.manifest()?;
+            return T::AssociatedVolumeId::from_interface_id(manifest.latest_volume_identifier());
+        }
+        self.config.latest_volume::<T>()
+    }
+    /// Writes the manifest if every Chapter was completed.
+    ///
+    /// If some Chapters failed, the draft is kept so that the next run only forms those.
+    fn finish_extension(
+        &self,
+        volume_ids: &[T::AssociatedVolumeId],
+        summary: BuildSummary,
+    ) -> Result<ExtendOutcome> {
+        let mut outcome = ExtendOutcome {
+            volumes_added: volume_ids.iter().map(|v| v.interface_id()).collect(),
+            chapters_written: summary.written,
+            chapters_skipped: summary.skipped,
+            errors: summary.errors,
+            manifest_updated: false,
+        };
+        if !outcome.errors.is_empty() {
+            for failure in &outcome.errors {
+                error!("Chapter not created: {}", failure);
+            }
+            warn!(
+                "{} Chapters failed. The manifest was not updated. Run again to retry them.",
+                outcome.errors.len()
+            );
+            return Ok(outcome);
+        }
+        self.generate_manifest()?;
+        outcome.manifest_updated = true;
+        Ok(outcome)
+    }
+    /// Responds to raw data that ends before the latest existing Volume,
+    /// according to `config.raw_regression`.
+    ///
+    /// ## Errors
+    /// If the policy is [RawRegressionPolicy::Error].
+    fn handle_raw_regression(
+        &self,
+        source: &Source<T>,
+        latest_raw_vol: &T::AssociatedVolumeId,
+        latest_existing_vol: &T::AssociatedVolumeId,
+        stream: Option<&OutcomeSender>,
+    ) -> Result<ExtendOutcome> {
+        let message = format!(
+            "The raw data in {:?} ends at Volume {}, before the latest existing Volume {}.",
+            self.config.raw_source,
+            describe_volume::<T>(latest_raw_vol)?,
+            describe_volume::<T>(latest_existing_vol)?
+        );
+        match self.config.raw_regression {
+            RawRegressionPolicy::TrustExisting => {
+                warn!(
+                    "{} Keeping the existing Chapters, no new Chapters were created.",
+                    message
+                );
+                Ok(ExtendOutcome::default())
+            }
+            RawRegressionPolicy::Error => bail!("{} No changes were made.", message),
+            RawRegressionPolicy::RebuildFromRaw => {
+                warn!(
+                    "{} Removing all Chapters and creating them again from the raw data.",
+                    message
+                );
+                for (_volume_id, _chapter_id, path, _size) in self.inventory()? {
+                    fs::remove_file(&path)
+                        .with_context(|| format!("Failed to remove {:?}", path))?;
+                }
+                remove_draft(&self.config.manifest_draft_path()?)?;
+                let volume_ids = &latest_raw_vol.all_prior()?;
+                let chapter_ids = &self.chapter_ids_to_create()?;
+                let summary =
+                    self.create_chapter_combinations(source, volume_ids, chapter_ids, stream)?;
+                let outcome = self.finish_extension(volume_ids, summary)?;
+                info!("Finished rebuilding database. {}", outcome);
+                Ok(outcome)
+            }
+        }
+    }
+    /// Identifies missing database files and creates them
+    /// by transforming unincorporated raw data.
+    ///
+    /// Files are considered missing if they are present in the manifest and
+    /// absent in the file system.
+    ///
+    /// ## Errors
+    /// If some Chapters could not be created ([ChaptersFailed]).
+    pub fn repair_from_raw(&self) -> Result<()> {
+        let _lock = self.write_lock("repair_from_raw")?;
+        let audit = self.check_completeness()?;
+        let manifest_cids = self.manifest()?.cids()?;
+        let holdings = Holdings::read(&self.config.holdings_file_path()?)?;
+        let missing = audit.missing_chapters(&manifest_cids, &holdings);
+        let missing_chapters: Vec<(&T::AssociatedVolumeId, &T::AssociatedChapterId)> =
+            missing.iter().map(|(v, c)| (v, c)).collect();
+        if missing_chapters.is_empty() {
+            info!("Database is complete. No repairs needed.");
+            return Ok(());
+        }
+        info!(
+            "{} Chapter(s) are missing and will be created from raw data.",
+            missing_chapters.len()
+        );
+        self.create_specific_chapters(&self.scan_source()?, &missing_chapters, None)?
+            .check("repair_from_raw")?;
+        info!("Finished rapairing database.");
+
+        Ok(())
+    }
+    /// Forms a Chapter again from raw data, in every Volume that the raw data holds.
+    ///
+    /// Existing files for the Chapter are replaced, whether or not they match the
+    /// manifest. The raw data is scanned once, and the Chapter of every Volume is
+    /// formed together (see `ExtractorMethods::chapter_across_volumes`). For
+    /// example, each chunk file of the address-appearance-index is read once rather
+    /// than once per Volume. If `config.watched_keys` is set, only their Records
+    /// are kept, as in a build.
+    ///
+    /// The manifest is not changed. Use [Self::generate_manifest] if the raw data
+    /// has changed since it was generated.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<AAISpec> = Todd::init(DataKind::default(), DirNature::Sample)?;
+    /// let chapter_id = AAIChapterId::from_interface_id("chapter_0x4e")?;
+    /// db.rebuild_chapter(&chapter_id)?;
+    /// ```
+    ///
+    /// ## Errors
+    /// If the raw data cannot be scanned, or if some Chapters could not be
+    /// created ([ChaptersFailed]).
+    pub fn rebuild_chapter(&self, chapter_id: &T::AssociatedChapterId) -> Result<()> {
+        let _lock = self.write_lock("rebuild_chapter")?;
+        let source = self.scan_source()?;
+        let volume_ids = T::AssociatedExtractor::latest_volume_in_source(&source)?.all_prior()?;
+        info!(
+            "Rebuilding Chapter {} in {} Volumes.",
+            chapter_id.interface_id(),
+            volume_ids.len()
+        );
+        let ids: Vec<(&T::AssociatedVolumeId, &T::AssociatedChapterId)> =
+            volume_ids.iter().map(|v| (v, chapter_id)).collect();
+        let formed = match self.config.watched_keys.is_empty() {
+            true => T::AssociatedExtractor::chapter_across_volumes(
+                chapter_id,
+                &volume_ids,
+                &source,
+                self.config.skip_corrupt_raw_files,
+                self.config.oversize_policy,
+            )?,
+            false => self.extract_each(&source, &ids)?,
+        };
+        self.save_rebuilt("rebuild_chapter", &ids, formed)
+    }
+    /// Forms every Chapter of a Volume again from raw data.
+    ///
+    /// As for [Self::rebuild_chapter], existing files are replaced and the manifest
+    /// is not changed. The Chapters are formed together (see
+    /// `ExtractorMethods::volume_from_source`), so each relevant raw file is read once.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<AAISpec> = Todd::init(DataKind::default(), DirNature::Sample)?;
+    /// let volume_id = AAIVolumeId::from_interface_id("volume_014_400_000")?;
+    /// db.rebuild_volume(&volume_id)?;
+    /// ```
+    ///
+    /// ## Errors
+    /// If the raw data cannot be scanned, or if some Chapters could not be
+    /// created ([ChaptersFailed]).
+    pub fn rebuild_volume(&self, volume_id: &T::AssociatedVolumeId) -> Result<()> {
+        let _lock = self.write_lock("rebuild_volume")?;
+        let source = self.scan_source()?;
+        let chapter_ids = self.chapter_ids_to_create()?;
+        info!(
+            "Rebuilding {} Chapters of Volume {}.",
+            chapter_ids.len(),
+            volume_id.interface_id()
+        );
+        let ids: Vec<(&T::AssociatedVolumeId, &T::AssociatedChapterId)> =
+            chapter_ids.iter().map(|c| (volume_id, c)).collect();
+        let formed = match self.config.watched_keys.is_empty() {
+            true => T::AssociatedExtractor::volume_from_source(
+                volume_id,
+                &chapter_ids,
+                &source,
+                self.config.skip_corrupt_raw_files,
+                self.config.oversize_policy,
+            )?,
+            false => self.extract_each(&source, &ids)?,
+        };
+        self.save_rebuilt("rebuild_volume", &ids, formed)
+    }
+    /// Forms each Chapter separately, limited to `config.watched_keys` if set.
+    fn extract_each(
+        &self,
+        source: &Source<T>,
+        ids: &[(&T::AssociatedVolumeId, &T::AssociatedChapterId)],
+    ) -> Result<Vec<ExtractedChapter<T>>> {
+        ids.iter()
+            .map(|(volume_id, chapter_id)| self.extract_chapter(source, volume_id, chapter_id))
+            .collect()
+    }
+    /// Saves the Chapters formed by a rebuild, in the order of the ids, and
+    /// records them in the manifest draft.
+    fn save_rebuilt(
+        &self,
+        operation: &str,
+        ids: &[(&T::AssociatedVolumeId, &T::AssociatedChapterId)],
+        formed: Vec<ExtractedChapter<T>>,
+    ) -> Result<()> {
+        let draft = ManifestDraft::open(&self.config.manifest_draft_path()?)?;
+        let io = self.config.parallelism.io_limit();
+        let mut summary = BuildSummary::default();
+        for ((volume_id, chapter_id), chapter) in ids.iter().zip(formed) {
+            summary.add(self.finish_chapter(volume_id, chapter_id, Ok(chapter), &draft, &io));
+        }
+        let summary = summary.check(operation)?;
+        info!(
+            "Finished {}: {} Chapters written, {} without raw data.",
+            operation, summary.written, summary.skipped
+        );
+        Ok(())
+    }
+    /// Saves Chapters formed in-process (see
+    /// [ChapterBuilder](crate::database::builder::ChapterBuilder)) rather than from
+    /// raw data, and records them in the manifest draft.
+    ///
+    /// Existing files for the same Chapters are replaced. The manifest is not
+    /// changed: call [Self::generate_manifest] once all Chapters are saved.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut builder = ChapterBuilder::<NameTagsSpec>::new(&volume_id, &chapter_id)?;
+    /// builder.add_raw("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae", value)?;
+    /// db.save_built_chapters(vec![builder.build()?])?;
+    /// db.generate_manifest()?;
+    /// ```
+    ///
+    /// ## Errors
+    /// If some Chapters could not be saved ([ChaptersFailed]).
+    pub fn save_built_chapters(&self, chapters: Vec<T::AssociatedChapter>) -> Result<()> {
+        let _lock = self.write_lock("save_built_chapters")?;
+        let draft = ManifestDraft::open(&self.config.manifest_draft_path()?)?;
+        let io = self.config.parallelism.io_limit();
+        let mut summary = BuildSummary::default();
+        for chapter in chapters {
+            let volume_id = chapter.volume_id().clone();
+            let chapter_id = chapter.chapter_id().clone();
+            let formed = Ok((Some(chapter), vec![]));
+            summary.add(self.finish_chapter(&volume_id, &chapter_id, formed, &draft, &io));
+        }
+        let summary = summary.check("save_built_chapters")?;
+        info!("Saved {} built Chapters.", summary.written);
+        Ok(())
+    }
+    /// Scans the raw source directory once, for use by every Chapter in a build.
+    ///
+    /// The raw data is read in the format of `config.raw_source_format`.
+    pub(super) fn scan_source(&self) -> Result<Source<T>> {
+        let format = self.config.raw_source_format;
+        T::AssociatedExtractor::scan_source_as(&self.config.raw_source, format)
+            .with_context(|| format!("Failed to scan raw data in {:?}", self.config.raw_source))
+    }
+    /// The ChapterIds that a build creates.
+    ///
+    /// All ChapterIds, or only those for `config.watched_keys` if set.
+    fn chapter_ids_to_create(&self) -> Result<Vec<T::AssociatedChapterId>> {
+        if self.config.watched_keys.is_empty() {
+            return T::get_all_chapter_ids();
+        }
+        let mut chapter_ids: Vec<T::AssociatedChapterId> = vec![];
+        for key in self.watched_record_keys()? {
+            let chapter_id = T::record_key_to_chapter_id(&key)?;
+            if !chapter_ids.contains(&chapter_id) {
+                chapter_ids.push(chapter_id)
+            }
+        }
+        info!(
+            "Building {} Chapters for {} watched keys.",
+            chapter_ids.len(),
+            self.config.watched_keys.len()
+        );
+        Ok(chapter_ids)
+    }
+    /// The RecordKeys for `config.watched_keys`.
+    fn watched_record_keys(&self) -> Result<Vec<T::AssociatedRecordKey>> {
+        self.config
+            .watched_keys
+            .iter()
+            .map(|key| {
+                T::raw_key_as_record_key(key)
+                    .with_context(|| format!("Invalid watched key {:?}", key))
+            })
+            .collect()
+    }
+    /// Forms a Chapter from raw data, limited to `config.watched_keys` if set.
+    fn extract_chapter(
+        &self,
+        source: &Source<T>,
+        volume_id: &T::AssociatedVolumeId,
+        chapter_id: &T::AssociatedChapterId,
+    ) -> Result<ExtractedChapter<T>> {
+        if self.config.watched_keys.is_empty() {
+            return T::AssociatedExtractor::chapter_from_source(
+                chapter_id,
+                volume_id,
+                source,
+                self.config.skip_corrupt_raw_files,
+                self.config.oversize_policy,
+            );
+        }
+        let mut keys = vec![];
+        for key in self.watched_record_keys()? {
+            if &T::record_key_to_chapter_id(&key)? == chapter_id {
+                keys.push(key)
+            }
+        }
+        T::AssociatedExtractor::chapter_from_source_for_keys(
+            chapter_id,
+            volume_id,
+            source,
+            &keys,
+            self.config.skip_corrupt_raw_files,
+            self.config.oversize_policy,
+        )
+    }
+    /// Creates every possible Chapter using the VolumeIds/ChapterIds provided.
+    ///
+    /// Every combination of is created.
+    ///
+    /// Used by self.full_transformation() and self.extend().
+    fn create_chapter_combinations(
+        &self,
+        source: &Source<T>,
+        volume_ids: &[T::AssociatedVolumeId],
+        chapter_ids: &[T::AssociatedChapterId],
+        stream: Option<&OutcomeSender>,
+    ) -> Result<BuildSummary> {
+        info!(
+            "{} VolumeIds, each with {} ChapterIds.",
+            volume_ids.len(),
+            chapter_ids.len()
+        );
+        let mut ids: Vec<(&T::AssociatedVolumeId, &T::AssociatedChapterId)> = vec![];
+        for v in volume_ids {
+            for c in chapter_ids {
+                ids.push((v, c))
+            }
+        }
+        self.create_specific_chapters(source, &ids, stream)
+    }
+    /// Creates specific Chapters using the VolumeIds/ChapterIds provided.
+    ///
+    /// Used by self.repair() and indirectly by self.full_transformation() and self.extend().
+    ///
+    /// Each Chapter formed is sent to the stream, if there is one.
+    fn create_specific_chapters(
+        &self,
+        source: &Source<T>,
+        ids: &[(&T::AssociatedVolumeId, &T::AssociatedChapterId)],
+        stream: Option<&OutcomeSender>,
+    ) -> Result<BuildSummary> {
+        let draft_path = self.config.manifest_draft_path()?;
+        let completed = read_draft(&draft_path)?;
+        let draft = ManifestDraft::open(&draft_path)?;
+        let unchanged = self.chapters_matching_manifest(&completed)?;
+        for entry in unchanged.values() {
+            // Recorded so that the manifest reuses the CID.
+            if let Err(e) = draft.record(entry) {
+                warn!(
+                    "Could not record unchanged Chapter in manifest draft: {}",
+                    e
+                )
+            }
+        }
+        let requested = ids.len();
+        let ids: Vec<&(&T::AssociatedVolumeId, &T::AssociatedChapterId)> = ids
+            .iter()
+            .filter(|(v, c)| {
+                let key = (v.interface_id(), c.interface_id());
+                !unchanged.contains_key(&key) && !self.is_completed_in_draft(&completed, v, c)
+            })
+            .collect();
+        if !completed.is_empty() {
+            info!("Resuming: {} Chapters already completed.", completed.len());
+        }
+        if !unchanged.is_empty() {
+            info!(
+                "{} Chapter files match the existing manifest and are kept.",
+                unchanged.len()
+            );
+        }
+
+        let total_chapters = ids.len() as u64;
+        info!("{} total Chapters.", total_chapters);
+        let progress = Progress::new(total_chapters, "Finished checking/creating chapter", 100);
+        let pool = self.config.parallelism.thread_pool()?;
+        let io = self.config.parallelism.io_limit();
+
+        let built: Vec<ChapterBuilt> =
+            if self.config.single_pass_volumes && self.config.watched_keys.is_empty() {
+                let volumes = group_by_volume::<T>(&ids);
+                progress.run_with_heartbeat(Some(PROGRESS_HEARTBEAT), || {
+                    pool.install(|| {
+                        volumes
+                            .par_iter()
+                            .flat_map_iter(|(volume_id, chapter_ids)| {
+                                let built = self.create_volume(
+                                    source,
+                                    volume_id,
+                                    chapter_ids,
+                                    &draft,
+                                    &io,
+                                    &progress,
+                                );
+                                for (chapter_id, b) in chapter_ids.iter().zip(&built) {
+                                    send_outcome::<T>(stream, volume_id, chapter_id, b);
+                                }
+                                built
+                            })
+                            .collect()
+                    })
+                })
+            } else {
+                progress.run_with_heartbeat(Some(PROGRESS_HEARTBEAT), || {
+                    pool.install(|| {
+                        ids.par_iter()
+                            .map(|(volume_id, chapter_id)| {
+                                let built =
+                                    self.create_chapter(source, volume_id, chapter_id, &draft, &io);
+                                send_outcome::<T>(stream, volume_id, chapter_id, &built);
+                                progress.inc();
+                                built
+                            })
+                            .collect()
+                    })
+                })
+            };
+        let mut summary = BuildSummary {
+            skipped: requested - ids.len(),
+            ..Default::default()
+        };
+        built.into_iter().for_each(|b| summary.add(b));
+        Ok(summary)
+    }
+    /// Whether a Chapter was completed in an earlier build, according to the draft.
+    ///
+    /// Chapters with a file are only considered complete if the file is still
+    /// present with the recorded size, and with the recorded CID if
+    /// `config.verify_existing_chapters` is set.
+    fn is_completed_in_draft(
+        &self,
+        completed: &DraftEntries,
+        volume_id: &T::AssociatedVolumeId,
+        chapter_id: &T::AssociatedChapterId,
+    ) -> bool {
+        let key = (volume_id.interface_id(), chapter_id.interface_id());
+        let Some(entry) = completed.get(&key) else {
+            return false;
+        };
+        if entry.cid_v0.is_none() {
+            return true;
+        }
+        let filename = T::AssociatedChapter::new_empty(volume_id, chapter_id).filename();
+        let path = self.config.chapter_dir_path(chapter_id).join(filename);
+        if !matches!(fs::metadata(&path), Ok(m) if m.len() == entry.bytes) {
+            return false;
+        }
+        if !self.config.verify_existing_chapters {
+            return true;
+        }
+        let cid = self.config.file_cid(&path).ok().map(|(cid, _bytes)| cid);
+        cid.is_some() && cid == entry.cid_v0
+    }
+    /// Chapter files from an earlier complete build, which need not be formed again.
+    ///
+    /// These are the files that have the CID listed in the existing manifest.
+    /// Chapters already in the draft are not checked again.
+    ///
+    /// None are returned if there is no manifest, if it is for a different spec
+    /// version, or if `config.watched_keys` is set (the files may hold the Records
+    /// of other keys). To form Chapters again from changed raw data, remove the
+    /// manifest first.
+    fn chapters_matching_manifest(&self, completed: &DraftEntries) -> Result<DraftEntries> {
+        let mut matching = DraftEntries::new();
+        let manifest_path = self.config.manifest_file_path()?;
+        if !self.config.watched_keys.is_empty() || !manifest_path.exists() {
+            return Ok(matching);
+        }
+        let manifest = match self.manifest() {
+            Ok(m) => m,
+            Err(e) => {
+                warn!("Existing manifest not used to skip Chapters: {:#}", e);
+                return Ok(matching);
+            }
+        };
+        if manifest.spec_version() != T::spec_version() {
+            info!(
+                "Existing manifest is for spec version {}: all Chapters will be formed.",
+                manifest.spec_version()
+            );
+            return Ok(matching);
+        }
+        let listed: Vec<ManifestCids<T>> = manifest
+            .cids()?
+            .into_iter()
+            .filter(|c| {
+                let key = (c.volume_id.interface_id(), c.chapter_id.interface_id());
+                !completed.contains_key(&key)
+            })
+            .collect();
+        let entries: Vec<DraftEntry> = self.config.parallelism.thread_pool()?.install(|| {
+            listed
+                .par_iter()
+                .filter_map(|listed| {
+                    let filename =
+                        T::AssociatedChapter::new_empty(&listed.volume_id, &listed.chapter_id)
+                            .filename();
+                    let path = self
+                        .config
+                        .chapter_dir_path(&listed.chapter_id)
+                        .join(filename);
+                    let (cid, bytes) = self.config.file_cid(&path).ok()?;
+                    if cid != listed.cid {
+                        return None;
+                    }
+                    Some(DraftEntry {
+                        volume_interface_id: listed.volume_id.interface_id(),
+                        chapter_interface_id: listed.chapter_id.interface_id(),
+                        cid_v0: Some(listed.cid.clone()),
+                        bytes,
+                    })
+                })
+                .collect()
+        });
+        for entry in entries {
+            let key = (
+                entry.volume_interface_id.clone(),
+                entry.chapter_interface_id.clone(),
+            );
+            matching.insert(key, entry);
+        }
+        Ok(matching)
+    }
+    /// Creates a new manifest file.
+    ///
+    /// This will override an existing manifest file. The file
+    /// in the directory alongside the data and raw data directories.
+    ///
+    /// ## Algorithm
+    /// 1. Goes through each Chapter file in the data directory.
+    /// 2. The IPFS CID (v0) is computed from the file bytes as-is (encoded), read in
+    ///    chunks of `config.hash_buffer_bytes`. The rate is logged.
+    /// 3. Additional database metadata is recorded.
+    /// 4. Mirrors, provenance and any successor are taken from `config.manifest_mirrors`,
+    ///    `config.manifest_provenance` and `config.manifest_superseded_by`, or kept
+    ///    from the existing manifest if not configured.
+    /// 5. For databases that assign raw entries to Volumes by position (nametags,
+    ///    signatures), the entries of each Volume are described from the raw source,
+    ///    or kept from the existing manifest if the raw source is absent.
+    /// 6. File is saved as a {database_interface_id}_manifest.json.
+    /// 7. If `config.keep_manifest_history` is set, a copy is saved in the history
+    ///    directory (see [manifest_history](crate::database::manifest_history)).
+    pub fn generate_manifest(&self) -> Result<()> {
+        let _lock = self.write_lock("generate_manifest")?;
+        info!("Generating manifest.");
+        let start = Instant::now();
+        let mut throughput = HashThroughput::default();
+        let mut manifest = T::AssociatedManifest::default();
+        let mut cids: Vec<ChapterCid<T>> = vec![];
+        // CIDs recorded during a build do not need to be computed again.
+        let draft_path = self.config.manifest_draft_path()?;
+        let draft = read_draft(&draft_path)?;
+        // Go through all the files in config.data_dir
+        for (volume_id, chap_id, chapter_path, size) in self.inventory()? {
+            let key = (volume_id.interface_id(), chap_id.interface_id());
+            let cid = match draft.get(&key) {
+                Some(DraftEntry {
+                    cid_v0: Some(cid),
+                    bytes,
+                    ..
+                }) if *bytes == size => cid.clone(),
+                _ => {
+                    let hashing = Instant::now();
+                    let (cid, bytes) = self.config.file_cid(&chapter_path)?;
+                    throughput.files_hashed += 1;
+                    throughput.bytes_hashed += bytes;
+                    throughput.hashing_ms += hashing.elapsed().as_millis() as u64;
+                    cid
+                }
+            };
+            cids.push((cid, volume_id, chap_id))
+        }
+        throughput.total_ms = start.elapsed().as_millis() as u64;
+        info!("Chapter files for manifest: {}.", throughput);
+        let latest_volume: T::AssociatedVolumeId = self.config.latest_volume::<T>()?;
+        // For each file get filename (--> volume_id and chapter_id) and bytes
+        // CID from bytes
+        manifest.set_spec_version(T::spec_version());
+        manifest.set_schema_fingerprint(Some(T::schema_fingerprint()));
+        manifest.set_schemas(T::spec_schemas_resource());
+        manifest.set_database_interface_id(self.config.data_kind.interface_id());
+        manifest.set_latest_volume_identifier(latest_volume.interface_id());
+        manifest.set_cids(&cids);
+        manifest.set_bloom_filter_cids(&self.bloom_filter_cids()?);
+        manifest.set_errata_cids(&self.errata_cids()?);
+        // Ranges are described from the raw source, if present.
+        let mut volume_ids: Vec<T::AssociatedVolumeId> = vec![];
+        for (_cid, volume_id, _chapter_id) in &cids {
+            if !volume_ids.contains(volume_id) {
+                volume_ids.push(volume_id.clone());
+            }
+        }
+        let mut raw_entry_ranges = match self.config.raw_source.exists() {
+            true => T::AssociatedExtractor::raw_entry_ranges(&volume_ids, &self.config.raw_source)?,
+            false => vec![],
+        };
+
+        let manifest_path = self.config.manifest_file_path()?;
+        let mut mirrors = self.config.manifest_mirrors.clone();
+        let mut provenance = self.config.manifest_provenance.clone();
+        let mut superseded_by = self.config.manifest_superseded_by.clone();
+        let keep_existing = mirrors.is_empty()
+            || provenance.is_empty()
+            || superseded_by.is_none()
+            || raw_entry_ranges.is_empty();
+        if keep_existing && manifest_path.exists() {
+            match self.manifest() {
+                Ok(existing) => {
+                    if raw_entry_ranges.is_empty() {
+                        raw_entry_ranges = existing.raw_entry_ranges()?;
+                    }
+                    if mirrors.is_empty() {
+                        mirrors = existing.mirrors().clone();
+                    }
+                    if provenance.is_empty() {
+                        provenance = existing.provenance().clone();
+                    }
+                    if superseded_by.is_none() {
+                        superseded_by = existing.superseded_by().cloned();
+                    }
+                }
+                Err(e) => warn!("Details of the existing manifest not kept: {:#}", e),
+            }
+        }
+        manifest.set_mirrors(mirrors);
+        manifest.set_provenance(provenance);
+        manifest.set_superseded_by(superseded_by);
+        manifest.set_raw_entry_ranges(&raw_entry_ranges);
+        let json_manifest = serde_json::to_string_pretty(&manifest)?;
+
+        fs::write(&manifest_path, &json_manifest)
+            .with_context(|| format!("Failed to write file: {:?}", &manifest_path))?;
+        let generated = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if let Some(path) =
+            self.record_manifest_history(&json_manifest, &T::spec_version(), generated)?
+        {
+            debug!("Manifest history saved as {:?}.", path);
+        }
+        remove_draft(&draft_path)?;
+        debug!("Manifest saved.");
+        Ok(())
+    }
+    /// Creates a bloom filter for every Chapter file present.
+    ///
+    /// The filters are optional and allow a user to check if a key may be in
+    /// a Chapter before obtaining it. They are listed in the manifest
+    /// by [Self::generate_manifest] if present.
+    ///
+    /// The false positive rate is defined by the spec.
+    pub fn generate_bloom_filters(&self) -> Result<()> {
+        let _lock = self.write_lock("generate_bloom_filters")?;
+        let bloom_dir = self.config.bloom_dir();
+        fs::create_dir_all(&bloom_dir)
+            .with_context(|| format!("Failed to create bloom directory {:?}", &bloom_dir))?;
+        let inventory = self.inventory()?;
+        info!("Generating {} bloom filters.", inventory.len());
+        self.config.parallelism.thread_pool()?.install(|| {
+            inventory
+                .par_iter()
+                .map(|(volume_id, chapter_id, chapter_path, _size)| {
+                    let bytes = self.config.read_chapter_file(chapter_path)?;
+                    let chapter = T::AssociatedChapter::from_file(bytes)?;
+                    let records = chapter.records();
+                    let mut filter = BloomFilter::new(records.len(), T::BLOOM_FALSE_POSITIVE_RATE)?;
+                    for r in records {
+                        filter.insert(&bloom_item::<T>(r.key())?);
+                    }
+                    let path = self.config.bloom_file_path::<T>(volume_id, chapter_id);
+                    fs::write(&path, filter.to_bytes())
+                        .with_context(|| format!("Failed to write file {:?}", &path))?;
+                    Ok(())
+                })
+                .collect::<Result<Vec<()>>>()
+        })?;
+        Ok(())
+    }
+    /// Computes CIDs for the bloom filters of the Chapter files present.
+    fn bloom_filter_cids(&self) -> Result<Vec<ChapterCid<T>>> {
+        let mut cids = vec![];
+        for (volume_id, chapter_id, _path, _size) in self.inventory()? {
+            let path = self.config.bloom_file_path::<T>(&volume_id, &chapter_id);
+            if !path.exists() {
+                continue;
+            }
+            let bytes = fs::read(&path)?;
+            cids.push((cid_v0_string_from_bytes(&bytes)?, volume_id, chapter_id))
+        }
+        Ok(cids)
+    }
+    /// Lists the raw values that exceed the size limits of the spec (e.g., names
+    /// that are too long), without creating any Chapters.
+    ///
+    /// When Chapters are created, these values are handled according to
+    /// `config.oversize_policy`.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Sample)?;
+    /// for value in db.oversize_report()? {
+    ///     println!("{}", value);
+    /// }
+    /// ```
+    pub fn oversize_report(&self) -> Result<Vec<OversizeValue>> {
+        T::AssociatedExtractor::oversize_report(&self.config.raw_source)
+    }
+    /// Checks all the raw data without creating Chapters.
+    ///
+    /// Problems that would otherwise appear partway through a build (e.g.,
+    /// truncated chunk files or invalid JSON) are listed in the report.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Sample)?;
+    /// let report = db.validate_raw()?;
+    /// for issue in &report.issues {
+    ///     println!("{}", issue);
+    /// }
+    /// ```
+    pub fn validate_raw(&self) -> Result<ValidationReport> {
+        let format = self.config.raw_source_format;
+        T::AssociatedExtractor::validate_raw_as(&self.config.raw_source, format)
+    }
+    /// Creates then saves a single chapter.
+    ///
+    /// ## Errors
+    /// All errors encountered during child function execution are handled
+    /// by logging here and returned as [ChapterBuilt::Failed]. This is to
+    /// enable the function to be called concurrently.
+    fn create_chapter(
+        &self,
+        source: &Source<T>,
+        volume_id: &T::AssociatedVolumeId,
+        chapter_id: &T::AssociatedChapterId,
+        draft: &ManifestDraft,
+        io: &IoLimit,
+    ) -> ChapterBuilt {
+        let chapter_result = {
+            let _permit = io.acquire();
+            self.extract_chapter(source, volume_id, chapter_id)
+        };
+        self.finish_chapter(volume_id, chapter_id, chapter_result, draft, io)
+    }
+    /// Creates then saves the given Chapters of a single Volume, forming them
+    /// together (see `config.single_pass_volumes`).
+    ///
+    /// ## Errors
+    /// As for [Self::create_chapter], errors are logged and returned as
+    /// [ChapterBuilt::Failed]. If the Volume cannot be formed, every Chapter fails.
+    fn create_volume(
+        &self,
+        source: &Source<T>,
+        volume_id: &T::AssociatedVolumeId,
+        chapter_ids: &[T::AssociatedChapterId],
+        draft: &ManifestDraft,
+        io: &IoLimit,
+        progress: &Progress,
+    ) -> Vec<ChapterBuilt> {
+        let chapters = {
+            let _permit = io.acquire();
+            T::AssociatedExtractor::volume_from_source(
+                volume_id,
+                chapter_ids,
+                source,
+                self.config.skip_corrupt_raw_files,
+                self.config.oversize_policy,
+            )
+        };
+        match chapters {
+            Ok(chapters) => chapter_ids
+                .iter()
+                .zip(chapters)
+                .map(|(chapter_id, chapter)| {
+                    let built = self.finish_chapter(volume_id, chapter_id, Ok(chapter), draft, io);
+                    progress.inc();
+                    built
+                })
+                .collect(),
+            Err(e) => {
+                error!(
+                    "Error processing volume {:?}: {}",
+                    volume_id.interface_id(),
+                    e
+                );
+                chapter_ids
+                    .iter()
+                    .map(|chapter_id| {
+                        progress.inc();
+                        ChapterBuilt::Failed(chapter_failure::<T>(volume_id, chapter_id, &e))
+                    })
+                    .collect()
+            }
+        }
+    }
+    /// Saves a Chapter formed from raw data and records it in the draft.
+    ///
+    /// Errors are logged and returned as [ChapterBuilt::Failed].
+    fn finish_chapter(
+        &self,
+        volume_id: &T::AssociatedVolumeId,
+        chapter_id: &T::AssociatedChapterId,
+        chapter_result: Result<ExtractedChapter<T>>,
+        draft: &ManifestDraft,
+        io: &IoLimit,
+    ) -> ChapterBuilt {
+        let current_chapter = format!(
+            "chapter (vol_id: {:?}, chap_id: {:?})",
+            volume_id.interface_id(),
+            chapter_id.interface_id()
+        );
+
+        let chapter_option = match chapter_result {
+            Ok((c, oversize)) => {
+                for value in oversize {
+                    warn!(
+                        "Oversize value in {}: {} ({:?}).",
+                        current_chapter, value, self.config.oversize_policy
+                    );
+                }
+                c
+            }
+            Err(e) => {
+                error!("Error processing {}: {}", current_chapter, e);
+                return ChapterBuilt::Failed(chapter_failure::<T>(volume_id, chapter_id, &e));
+            }
+        };
+
+        let mut entry = DraftEntry {
+            volume_interface_id: volume_id.interface_id(),
+            chapter_interface_id: chapter_id.interface_id(),
+            cid_v0: None,
+            bytes: 0,
+        };
+        let built = match chapter_option {
+            Some(chapter) => match self.save_chapter(chapter, io) {
+                Ok((cid, bytes)) => {
+                    entry.cid_v0 = Some(cid.clone());
+                    entry.bytes = bytes;
+                    ChapterBuilt::Written { cid_v0: cid, bytes }
+                }
+                Err(e) => {
+                    error!("Error processing {}: {}", current_chapter, e);
+                    return ChapterBuilt::Failed(chapter_failure::<T>(volume_id, chapter_id, &e));
+                }
+            },
+            None => {
+                debug!("No raw data (skipping) relevant to {}.", current_chapter);
+                ChapterBuilt::Empty
+            }
+        };
+        if let Err(e) = draft.record(&entry) {
+            warn!(
+                "Could not record {} in manifest draft: {}",
+                current_chapter, e
+            )
+        }
+        built
+    }
+    /// Writes a chapter to a file, returning the CID and size of the file.
+    ///
+    /// The chapter is encoded before waiting for permission to write.
+    fn save_chapter(&self, chapter: T::AssociatedChapter, io: &IoLimit) -> Result<(String, u64)> {
+        let chapter_dir_path = &self.config.chapter_dir_path(chapter.chapter_id());
+        fs::create_dir_all(chapter_dir_path)?;
+        let encoded = self.config.chapter_file_bytes::<T>(&chapter)?;
+        let filename = chapter.filename();
+        debug!(
+            "Saving chapter: {}, with {} records ({} bytes).",
+            &filename,
+            chapter.records().len(),
+            encoded.len()
+        );
+        let cid = cid_v0_string_from_bytes(&encoded)?;
+        let bytes = encoded.len() as u64;
+        let filepath = chapter_dir_path.join(&filename);
+        let _permit = io.acquire();
+        // Removed first so that a file hardlinked with another database is not modified.
+        if filepath.exists() {
+            fs::remove_file(&filepath)?;
+        }
+        if self.link_shared_chapter(chapter.chapter_id(), &filename, &cid, &filepath) {
+            return Ok((cid, bytes));
+        }
+        fs::write(&filepath, encoded).context(anyhow!("Unable to write file {:?}", &filepath))?;
+        Ok((cid, bytes))
+    }
+    /// Hardlinks a Chapter file to an identical file in another database.
+    ///
+    /// Databases for different networks often have identical Chapters. The
+    /// directories in config.shared_data_dirs are checked for a file with the same
+    /// name and CID.
+    ///
+    /// Returns false if there is no such file or the link could not be made
+    /// (e.g., the directories are on different file systems).
+    fn link_shared_chapter(
+        &self,
+        chapter_id: &T::AssociatedChapterId,
+        filename: &str,
+        cid: &str,
+        filepath: &Path,
+    ) -> bool {
+        for shared_dir in &self.config.shared_data_dirs {
+            let candidate = shared_dir.join(chapter_id.interface_id()).join(filename);
+            let Ok(bytes) = fs::read(&candidate) else {
+                continue;
+            };
+            if !matches!(cid_v0_string_from_bytes(&bytes), Ok(c) if c == cid) {
+                continue;
+            }
+            match fs::hard_link(&candidate, filepath) {
+                Ok(()) => {
+                    debug!("Linked chapter {} to {:?}.", filename, candidate);
+                    return true;
+                }
+                Err(e) => warn!("Could not link {:?} to {:?}: {}", filepath, candidate, e),
+            }
+        }
+        false
+    }
+    /**
+    Obtains the sample data for the database.
+
+    This includes processed (TODD-compliant) samples and raw samples
+    that can be used to create processed samples.
+
+    Samples may be in the cross-platform path (Directories crate),
+    the local folder (if repo is cloned from GH) or may need
+    to be obtained from a custom source. This method tries each in that
+    order.
+
+    The processed samples may need to be created from the raw samples, which
+    can be slow.
+
+    If `config.read_only` is set, the processed samples must already be
+    present: nothing is copied, downloaded or created.
+
+    If the raw samples cannot be obtained (e.g., without network access), small
+    synthetic raw samples are generated instead and the processed samples are
+    created from them. These have the sample filenames and Volumes, but not
+    real data.
+
+    The samples are then checked against the samples manifest (see
+    [samples](crate::manifest::samples)), which records the size and CID of each
+    sample file. Use [Todd::refresh_sample_data] to replace files that do not match.
+
+    ## Errors
+    [SamplesMismatch] if sample files are absent or differ from the manifest.
+
+    ## Example
+    ```
+    # use anyhow::Result;
+    # use min_know::{
+    #    config::{address_appearance_index::Network, choices::{DataKind, DirNature}},
+    #    database::types::Todd,
+    #    specs::address_appearance_index::AAISpec,
+    # };
+    let data_kind = DataKind::AddressAppearanceIndex(Network::default());
+    let db: Todd<AAISpec> = Todd::init(data_kind, DirNature::Sample)?;
+    db.get_sample_data()?;
+    # Ok::<(), anyhow::Error>(())
+    ```
+    */
+    pub fn get_sample_data(&self) -> Result<()> {
+        if let DirNature::Sample = self.config.dir_nature {
+        } else {
+            return Err(anyhow!("try to configure the db with DirNature::Sample"));
+        }
+        if self.config.read_only {
+            self.handle_database_samples()?;
+            return self.verify_sample_data();
+        }
+        match self.handle_raw_samples()? {
+            RawSamples::Real => self.handle_database_samples()?,
+            // Existing processed samples would not match the synthetic raw samples.
+            RawSamples::Synthetic => {
+                self.full_transformation()?;
+                warn!("Synthetic samples are not checked against the samples manifest.");
+                return Ok(());
+            }
+        }
+        self.verify_sample_data()
+    }
+    /**
+    Replaces sample files that do not match the samples manifest, then checks
+    them with [Todd::get_sample_data].
+
+    Files are copied from the local repository if present there, otherwise they
+    are removed and obtained again (e.g., downloaded). Use this when
+    `get_sample_data` returns a [SamplesMismatch] error, e.g., after the samples
+    in the repository are updated. Files that match are kept.
+
+    ## Example
+    ```ignore
+    let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Sample)?;
+    if let Err(e) = db.get_sample_data() {
+        if e.is::<SamplesMismatch>() {
+            db.refresh_sample_data()?;
+        }
+    }
+    ```
+    ## Errors
+    [ReadOnlyDatabase](crate::database::lock::ReadOnlyDatabase) if `config.read_only`
+    is set. [SamplesMismatch] if the files obtained again still do not match.
+    */
+    pub fn refresh_sample_data(&self) -> Result<()> {
+        if let DirNature::Sample = self.config.dir_nature {
+        } else {
+            return Err(anyhow!("try to configure the db with DirNature::Sample"));
+        }
+        self.check_writable("refresh_sample_data")?;
+        if let Some(manifest) = self.samples_manifest()? {
+            let local_base = self.config.local_sample_base_dir();
+            let mut cache = VerificationCache::read(&self.config.verification_cache_path()?)
+                .with_buffer(self.config.hash_buffer_bytes);
+            for mismatch in manifest.mismatches(|path| self.sample_file_path(path), &mut cache)? {
+                let Some(path) = self.sample_file_path(&mismatch.path) else {
+                    continue;
+                };
+                let local = local_base.join(&mismatch.path);
+                if local.is_file() {
+                    info!("Replacing sample file from local repository: {}", mismatch);
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::copy(&local, &path)?;
+                } else if mismatch.reason != SampleMismatchReason::Absent {
+                    info!("Removing sample file to obtain it again: {}", mismatch);
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+        self.get_sample_data()
+    }
+    /// Lists the raw samples of the database, with where each is obtained from
+    /// and, from the samples manifest, its size and CID.
+    ///
+    /// The samples manifest of the local repository is used if present, otherwise
+    /// the installed one. Nothing is downloaded or written.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<AAISpec> = Todd::init(DataKind::default(), DirNature::Sample)?;
+    /// for artifact in db.sample_artifacts()? {
+    ///     println!("{} {:?} ({:?} bytes)", artifact.name, artifact.url, artifact.bytes);
+    /// }
+    /// ```
+    pub fn sample_artifacts(&self) -> Result<Vec<SampleArtifact>> {
+        let mut artifacts = T::AssociatedSampleObtainer::sample_artifacts();
+        let manifest = [
+            self.config.local_samples_manifest_path(),
+            self.config.samples_manifest_path(),
+        ]
+        .into_iter()
+        .find(|path| path.exists())
+        .map(|path| SamplesManifest::read(&path))
+        .transpose()?;
+        let Some(manifest) = manifest else {
+            return Ok(artifacts);
+        };
+        let raw_dir = self.config.data_kind.raw_source_dir_name();
+        for artifact in &mut artifacts {
+            let path = format!("{}/{}", raw_dir, artifact.name);
+            if let Some(file) = manifest.files.iter().find(|f| f.path == path) {
+                artifact.bytes = artifact.bytes.or(Some(file.bytes));
+                if artifact.content_cid.is_none() {
+                    artifact.content_cid = Some(file.cid.clone());
+                }
+            }
+        }
+        Ok(artifacts)
+    }
+    /// Checks the samples against the samples manifest.
+    ///
+    /// Raw samples are not checked if `config.read_only` is set, as they are
+    /// not needed for queries.
+    fn verify_sample_data(&self) -> Result<()> {
+        let Some(manifest) = self.samples_manifest()? else {
+            info!("No samples manifest found: samples were not checked.");
+            return Ok(());
+        };
+        let cache_path = self.config.verification_cache_path()?;
+        let mut cache =
+            VerificationCache::read(&cache_path).with_buffer(self.config.hash_buffer_bytes);
+        let mismatches = manifest.mismatches(|path| self.sample_file_path(path), &mut cache)?;
+        self.record_verified(&cache, &cache_path);
+        if !mismatches.is_empty() {
+            for mismatch in &mismatches {
+                warn!(
+                    "Sample file does not match the samples manifest: {}",
+                    mismatch
+                );
+            }
+            return Err(SamplesMismatch {
+                dir: self.config.base_dir_nature_dependent.clone(),
+                version: manifest.version,
+                mismatches,
+            }
+            .into());
+        }
+        info!(
+            "Sample files match version {} of the samples manifest.",
+            manifest.version
+        );
+        Ok(())
+    }
+    /// Reads the samples manifest.
+    ///
+    /// The manifest in the local repository is used if present, and is installed
+    /// alongside the samples so that later checks do not need the repository.
+    fn samples_manifest(&self) -> Result<Option<SamplesManifest>> {
+        let local = self.config.local_samples_manifest_path();
+        let installed = self.config.samples_manifest_path();
+        if local.exists() {
+            let manifest = SamplesManifest::read(&local)?;
+            if !self.config.read_only
+                && SamplesManifest::read(&installed).ok() != Some(manifest.clone())
+            {
+                info!(
+                    "Installing version {} of the samples manifest.",
+                    manifest.version
+                );
+                manifest.write(&installed)?;
+            }
+            return Ok(Some(manifest));
+        }
+        if installed.exists() {
+            return Ok(Some(SamplesManifest::read(&installed)?));
+        }
+        Ok(None)
+    }
+    /// The location of a file listed in the samples manifest.
+    ///
+    /// Only the raw and processed samples are obtained by `get_sample_data`,
+    /// so other files (e.g., the database manifest) are not located.
+    fn sample_file_path(&self, path: &str) -> Option<PathBuf> {
+        let (dir, rest) = path.split_once('/')?;
+        let dir = Some(std::ffi::OsStr::new(dir));
+        if dir == self.config.raw_source.file_name() && !self.config.read_only {
+            return Some(self.config.raw_source.join(rest));
+        }
+        if dir == self.config.data_dir.file_name() {
+            return Some(self.config.data_dir.join(rest));
+        }
+        None
+    }
+    /// Ensures that the unprocessed samples are either present or obtained.
+    ///
+    /// Synthetic samples are generated if the samples cannot be obtained.
+    fn handle_raw_samples(&self) -> Result<RawSamples> {
+        let raw_source_dir = &self.config.raw_source;
+        let local_example_dir_raw = self.config.local_sample_raw_source();
+        let raw_sample_filenames = T::AssociatedSampleObtainer::raw_sample_filenames();
+
+        if raw_source_dir.contains_files(&raw_sample_filenames)? {
+            info!("Checking raw sample files: already present.");
+            return Ok(RawSamples::Real);
+        }
+
+        if local_example_dir_raw.contains_files(&raw_sample_filenames)? {
+            info!("Raw sample files found in local repository: moving to samples directory.");
+            local_example_dir_raw.copy_into_recursive(raw_source_dir)?;
+            return Ok(RawSamples::Real);
+        }
+        info!("Raw samples not found: downloading.");
+        match T::AssociatedSampleObtainer::get_raw_samples(raw_source_dir) {
+            Ok(()) => Ok(RawSamples::Real),
+            Err(e) => {
+                warn!(
+                    "Could not obtain raw samples ({:#}): generating synthetic samples in {:?}.",
+                    e, raw_source_dir
+                );
+                T::AssociatedSampleObtainer::synthetic_raw_samples(raw_source_dir)?;
+                Ok(RawSamples::Synthetic)
+            }
+        }
+    }
+    /// Ensures that the processed samples are either present or obtained.
+    ///
+    /// First looks in the expected location, then looks in the local
+    /// directory (and copies if present), then attempts to processes from raw
+    /// data.
+    fn handle_database_samples(&self) -> Result<()> {
+        let example_dir_processed = self.config.local_sample_data_dir();
+
+        let volume_interface_ids = T::AssociatedSampleObtainer::sample_volumes();
+
+        let Some(volume_interface_ids) = volume_interface_ids else {
+            info!("No sample filenames provided: creating samples from raw data.");
+            self.full_transformation()?;
+            return Ok(());
+        };
+        let volume_ids = volume_interface_ids
+            .iter()
+            .map(|x| T::AssociatedVolumeId::from_interface_id(x))
+            .collect::<Result<Vec<T::AssociatedVolumeId>>>();
+
+        let Ok(volume_ids) = volume_ids else {
+            warn!("Couldn't derive VolumeId from provided interface id: skipping check for existing samples.");
+            self.full_transformation()?;
+            return Ok(());
+        };
+        // Prepare an ID for every chapter (directory_name, filenames)
+        let mut dirnames_and_files: Vec<(String, Vec<String>)> = vec![];
+        for i in 0..T::NUM_CHAPTERS {
+            let Ok(chapter_id) = T::AssociatedChapterId::nth_id(i as u32) else {
+                warn!("Couldn't derive nth ChapterId: skipping check for existing samples.");
+                self.full_transformation()?;
+                return Ok(());
+            };
+            let mut filenames: Vec<String> = vec![];
+            for volume_id in &volume_ids {
+                let filename = T::AssociatedChapter::new_empty(volume_id, &chapter_id).filename();
+                filenames.push(filename);
+            }
+            dirnames_and_files.push((chapter_id.interface_id(), filenames));
+        }
+        // Check expected location.
+        let mut data_dir_complete = true;
+        for (dirname, filenames) in &dirnames_and_files {
+            let chap_dir = self.config.data_dir.join(dirname);
+            // Detect if any of the sample files are missing.
+            if !chap_dir.contains_files(filenames)? {
+                info!(
+                    "The {} dir was missing one of: {:?}",
+                    chap_dir.display(),
+                    filenames
+                );
+                data_dir_complete = false
+            }
+        }
+        if data_dir_complete {
+            info!("Sample directory already contains all database samples.");
+            return Ok(());
+        }
+        // Check local directory.
+        let mut local_data_dir_complete = true;
+        for (dirname, filenames) in &dirnames_and_files {
+            let chap_dir = example_dir_processed.join(dirname);
+            // Detect if any of the sample files are missing.
+            if !chap_dir.contains_files(filenames)? {
+                local_data_dir_complete = false
+            }
+        }
+        self.check_writable("get_sample_data")?;
+        if local_data_dir_complete {
+            info!("Local directory has sample files: copying to samples directory.");
+            for (dirname, _filenames) in &dirnames_and_files {
+                let src_chap_dir = example_dir_processed.join(dirname);
+                let dest_chap_dir = self.config.data_dir.join(dirname);
+                src_chap_dir.copy_into_recursive(&dest_chap_dir)?;
+            }
+            return Ok(());
+        } else {
+            info!("Local directory does not contain sample files: creating from raw data.");
+            self.full_transformation()?;
+        }
+        Ok(())
+    }
+}
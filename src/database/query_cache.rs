@@ -0,0 +1,295 @@
+//! Caching the results of queries, for long-running query servers.
+//!
+//! The values for a key do not change until the manifest does. With
+//! `config.query_cache` set, [Todd::find] keeps the values it returns, keyed by
+//! (database interface id, latest Volume in the manifest, key). All values are
+//! discarded when the manifest file changes (its modified time or length), and
+//! when a change is noticed by `Todd::watch` (requires the `watch` feature).
+//!
+//! Values are not cached if there is no manifest, and errors are not cached.
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::types::{FindMode, Todd},
+    specs::traits::{DataSpec, ManifestMethods, RecordKeyMethods},
+};
+
+/// The bounds of a query cache.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct QueryCacheLimits {
+    /// The most keys to hold values for. The oldest values are discarded first.
+    pub max_entries: usize,
+    /// Values older than this (seconds) are not used.
+    pub ttl_secs: u64,
+}
+
+/// (database interface id, latest Volume interface id, key).
+type CacheKey = (String, String, String);
+
+struct CachedValues<V> {
+    inserted: Instant,
+    values: Vec<V>,
+}
+
+struct CacheState<V> {
+    /// The manifest (modified time, length) when last checked.
+    manifest_checked: Option<(SystemTime, u64)>,
+    /// The latest Volume in the manifest when last checked.
+    latest_volume: Option<String>,
+    entries: HashMap<CacheKey, CachedValues<V>>,
+}
+
+impl<V> Default for CacheState<V> {
+    fn default() -> Self {
+        CacheState {
+            manifest_checked: None,
+            latest_volume: None,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+/// Query results, shared between clones of a database.
+pub(crate) struct QueryCache<V> {
+    state: Arc<Mutex<CacheState<V>>>,
+    invalidated: QueryCacheInvalidation,
+}
+
+/// Marks a [QueryCache] so that its values are discarded at the next query.
+///
+/// Unlike the cache, it does not depend on the type of the values, so it can
+/// be moved to other threads (e.g., a watcher).
+#[derive(Clone, Debug, Default)]
+pub(crate) struct QueryCacheInvalidation(Arc<AtomicBool>);
+
+impl QueryCacheInvalidation {
+    #[cfg_attr(not(feature = "watch"), allow(dead_code))]
+    pub(crate) fn invalidate(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+    /// Whether the cache was invalidated since last taken.
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+}
+
+impl<V> Default for QueryCache<V> {
+    fn default() -> Self {
+        QueryCache {
+            state: Arc::new(Mutex::new(CacheState::default())),
+            invalidated: QueryCacheInvalidation::default(),
+        }
+    }
+}
+
+impl<V> Clone for QueryCache<V> {
+    fn clone(&self) -> Self {
+        QueryCache {
+            state: self.state.clone(),
+            invalidated: self.invalidated.clone(),
+        }
+    }
+}
+
+impl<V> std::fmt::Debug for QueryCache<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let entries = self
+            .state
+            .lock()
+            .map(|s| s.entries.len())
+            .unwrap_or_default();
+        f.debug_struct("QueryCache")
+            .field("entries", &entries)
+            .finish()
+    }
+}
+
+impl<V> PartialEq for QueryCache<V> {
+    /// The cache does not affect the identity of a database.
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<V: Clone> QueryCache<V> {
+    /// Gets the latest Volume of the manifest, discarding all values if the
+    /// manifest changed since it was last checked.
+    ///
+    /// The manifest is only read again if its modified time or length changed.
+    /// Returns None if there is no manifest.
+    fn latest_volume<F>(&self, manifest: &Path, read_latest: F) -> Result<Option<String>>
+    where
+        F: FnOnce() -> Result<String>,
+    {
+        let mut state = self.lock()?;
+        let Some(manifest_state) = file_state(manifest) else {
+            state.entries.clear();
+            state.manifest_checked = None;
+            state.latest_volume = None;
+            return Ok(None);
+        };
+        if self.invalidated.take() || state.manifest_checked != Some(manifest_state) {
+            state.entries.clear();
+            state.latest_volume = Some(read_latest()?);
+            state.manifest_checked = Some(manifest_state);
+        }
+        Ok(state.latest_volume.clone())
+    }
+    /// Gets the values for a key, if cached within the time limit.
+    fn get(&self, key: &CacheKey, ttl: Duration) -> Result<Option<Vec<V>>> {
+        let state = self.lock()?;
+        Ok(state
+            .entries
+            .get(key)
+            .filter(|cached| cached.inserted.elapsed() < ttl)
+            .map(|cached| cached.values.clone()))
+    }
+    /// Keeps the values for a key, discarding expired then the oldest values
+    /// if the cache is full.
+    fn insert(&self, key: CacheKey, values: Vec<V>, limits: &QueryCacheLimits) -> Result<()> {
+        if limits.max_entries == 0 {
+            return Ok(());
+        }
+        let mut state = self.lock()?;
+        let ttl = Duration::from_secs(limits.ttl_secs);
+        if !state.entries.contains_key(&key) && state.entries.len() >= limits.max_entries {
+            state
+                .entries
+                .retain(|_key, cached| cached.inserted.elapsed() < ttl);
+        }
+        while !state.entries.contains_key(&key) && state.entries.len() >= limits.max_entries {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_key, cached)| cached.inserted)
+                .map(|(key, _cached)| key.clone());
+            match oldest {
+                Some(oldest) => state.entries.remove(&oldest),
+                None => break,
+            };
+        }
+        state.entries.insert(
+            key,
+            CachedValues {
+                inserted: Instant::now(),
+                values,
+            },
+        );
+        Ok(())
+    }
+    /// Discards all values and reads the manifest again at the next query,
+    /// when marked from elsewhere.
+    #[cfg_attr(not(feature = "watch"), allow(dead_code))]
+    pub(crate) fn invalidation(&self) -> QueryCacheInvalidation {
+        self.invalidated.clone()
+    }
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, CacheState<V>>> {
+        self.state
+            .lock()
+            .map_err(|_| anyhow!("Query cache lock poisoned."))
+    }
+}
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Obtains the RecordValues for a key as for [Self::find], using and
+    /// updating the query cache.
+    pub(crate) fn find_cached(
+        &self,
+        raw_record_key: &str,
+        limits: &QueryCacheLimits,
+    ) -> Result<Vec<T::AssociatedRecordValue>> {
+        let manifest_path = self.config.manifest_file_path()?;
+        let latest = self.query_cache.latest_volume(&manifest_path, || {
+            Ok(self.manifest()?.latest_volume_identifier().to_string())
+        })?;
+        let Some(latest) = latest else {
+            return Ok(self.find_with(raw_record_key, FindMode::Strict)?.values);
+        };
+        let key = (
+            self.config.data_kind.interface_id(),
+            latest,
+            self.record_key(raw_record_key)?.summary_string()?,
+        );
+        if let Some(values) = self
+            .query_cache
+            .get(&key, Duration::from_secs(limits.ttl_secs))?
+        {
+            return Ok(values);
+        }
+        let values = self.find_with(raw_record_key, FindMode::Strict)?.values;
+        self.query_cache.insert(key, values.clone(), limits)?;
+        Ok(values)
+    }
+}
+
+/// The (modified time, length) of a file, if present.
+fn file_state(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[test]
+fn cache_bounded_and_invalidated() {
+    let dir = std::env::temp_dir().join("min_know_test_query_cache");
+    fs::create_dir_all(&dir).unwrap();
+    let manifest = dir.join("manifest.json");
+    fs::write(&manifest, "first").unwrap();
+    let cache: QueryCache<u32> = QueryCache::default();
+    let limits = QueryCacheLimits {
+        max_entries: 2,
+        ttl_secs: 60,
+    };
+    let ttl = Duration::from_secs(limits.ttl_secs);
+    let key = |k: &str, latest: &str| ("db".to_string(), latest.to_string(), k.to_string());
+
+    let latest = cache
+        .latest_volume(&manifest, || Ok("volume_1".to_string()))
+        .unwrap()
+        .unwrap();
+    for (k, v) in [("a", 1), ("b", 2), ("c", 3)] {
+        cache.insert(key(k, &latest), vec![v], &limits).unwrap();
+    }
+    // The oldest is discarded.
+    assert_eq!(cache.get(&key("a", &latest), ttl).unwrap(), None);
+    assert_eq!(cache.get(&key("c", &latest), ttl).unwrap(), Some(vec![3]));
+    assert_eq!(cache.get(&key("c", &latest), Duration::ZERO).unwrap(), None);
+    // The manifest is not read again while unchanged.
+    let unchanged = cache
+        .latest_volume(&manifest, || panic!("manifest read again"))
+        .unwrap();
+    assert_eq!(unchanged, Some(latest.clone()));
+
+    fs::write(&manifest, "second manifest").unwrap();
+    let latest = cache
+        .latest_volume(&manifest, || Ok("volume_2".to_string()))
+        .unwrap()
+        .unwrap();
+    assert_eq!(latest, "volume_2");
+    assert_eq!(cache.get(&key("c", "volume_1"), ttl).unwrap(), None);
+    cache.insert(key("d", &latest), vec![4], &limits).unwrap();
+    cache.invalidation().invalidate();
+    cache
+        .latest_volume(&manifest, || Ok("volume_2".to_string()))
+        .unwrap();
+    assert_eq!(cache.get(&key("d", &latest), ttl).unwrap(), None);
+    fs::remove_file(&manifest).unwrap();
+    assert_eq!(
+        cache.latest_volume(&manifest, || unreachable!()).unwrap(),
+        None
+    );
+}
@@ -0,0 +1,245 @@
+//! Stable serialized forms of database state for external tools.
+//!
+//! Types that are generic over a [DataSpec] (e.g., [CompletenessAudit]) hold
+//! VolumeIds and ChapterIds, whose serialized form follows their internal layout
+//! and may change between versions. The reports here use interface ids instead
+//! (the strings in the manifest and filenames). E.g., "volume_014_400_000" and
+//! "chapter_0x4e".
+//!
+//! ## Stability
+//! Fields may be added in later versions. Fields are not renamed or removed
+//! without incrementing [REPORT_FORMAT_VERSION].
+//!
+//! Other types intended for JSON consumers use only strings, paths and numbers:
+//! [AcquisitionPlan][crate::database::acquisition::AcquisitionPlan],
+//! [MergeReport][crate::database::merge::MergeReport] and raw data statistics
+//! such as [ValidationReport][crate::extraction::validation::ValidationReport].
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::types::{AbsentFile, CompletenessAudit, InventoryItem, OrphanFile},
+    manifest::{provenance::ManifestProvenance, succession::ManifestSuccessor},
+    specs::traits::{ChapterIdMethods, DataSpec, VolumeIdMethods},
+};
+
+/// Version of the layout of the reports in this module.
+pub const REPORT_FORMAT_VERSION: u32 = 1;
+
+/// A [CompletenessAudit] with interface ids.
+///
+/// Created by [CompletenessAudit::to_report].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditReport {
+    pub format_version: u32,
+    /// E.g., "nametags".
+    pub spec_id: String,
+    /// Interface ids of Volumes that are not present in any Chapter directory.
+    pub absent_volumes: Vec<String>,
+    /// Interface ids of Chapters whose directory is not present.
+    pub absent_chapters: Vec<String>,
+    pub absent_files: Vec<AbsentFileEntry>,
+    pub orphan_files: Vec<OrphanFileEntry>,
+    /// Number of files in the manifest, excluding pruned Volumes.
+    #[serde(default)]
+    pub files_in_manifest: usize,
+    /// Number of files in the manifest that are present with the manifest CID.
+    #[serde(default)]
+    pub files_verified: usize,
+    /// Total size of the verified files.
+    #[serde(default)]
+    pub bytes_verified: u64,
+    /// Upstream source and license stated in the manifest.
+    #[serde(default)]
+    pub provenance: ManifestProvenance,
+    /// The database that replaces this one, if it is deprecated.
+    #[serde(default)]
+    pub superseded_by: Option<ManifestSuccessor>,
+    /// The files hashed by the audit and the time taken.
+    #[serde(default)]
+    pub throughput: HashThroughput,
+}
+
+/// The files read and hashed to check their CIDs, and the time taken.
+///
+/// Files with a CID recorded by an earlier audit (see
+/// [Todd::check_completeness][crate::database::types::Todd::check_completeness])
+/// are not read, so are not counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashThroughput {
+    pub files_hashed: usize,
+    pub bytes_hashed: u64,
+    /// Milliseconds spent reading and hashing files.
+    pub hashing_ms: u64,
+    /// Milliseconds taken by the whole operation.
+    pub total_ms: u64,
+}
+
+impl HashThroughput {
+    /// Megabytes (10^6 bytes) read and hashed per second spent hashing.
+    pub fn mb_per_sec(&self) -> f64 {
+        self.bytes_hashed as f64 / 1e6 / (self.hashing_ms.max(1) as f64 / 1000.0)
+    }
+}
+
+impl std::fmt::Display for HashThroughput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} files ({:.1} MB) hashed at {:.1} MB/s, {} ms in total",
+            self.files_hashed,
+            self.bytes_hashed as f64 / 1e6,
+            self.mb_per_sec(),
+            self.total_ms
+        )
+    }
+}
+
+/// A file in the manifest that is not available locally.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AbsentFileEntry {
+    pub volume_interface_id: String,
+    pub chapter_interface_id: String,
+    pub reason: AbsentReason,
+}
+
+/// Serialized as "different_hash" or "no_file".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AbsentReason {
+    /// The local file has a CID that differs from the manifest.
+    DifferentHash,
+    /// There is no local file.
+    NoFile,
+}
+
+/// A file in a Chapter directory that is not in the manifest.
+///
+/// The interface ids are absent for files that are not named like a Chapter file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrphanFileEntry {
+    pub path: PathBuf,
+    pub volume_interface_id: Option<String>,
+    pub chapter_interface_id: Option<String>,
+}
+
+/// The Chapter files present locally, with interface ids.
+///
+/// Created by [Todd::inventory_report][crate::database::types::Todd::inventory_report].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventoryReport {
+    pub format_version: u32,
+    /// E.g., "nametags".
+    pub spec_id: String,
+    /// Sorted by Volume, then by Chapter.
+    pub files: Vec<InventoryEntry>,
+}
+
+/// A Chapter file present locally.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventoryEntry {
+    pub volume_interface_id: String,
+    pub chapter_interface_id: String,
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+impl AuditReport {
+    /// Percentage of the files in the manifest that are verified (100 for an
+    /// empty manifest).
+    pub fn percent_complete(&self) -> f64 {
+        percent_of(self.files_verified, self.files_in_manifest)
+    }
+}
+
+impl<T: DataSpec> CompletenessAudit<T> {
+    /// The audit as JSON in the layout of [AuditReport].
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.to_report())?)
+    }
+    /// The audit with VolumeIds and ChapterIds as interface ids.
+    pub fn to_report(&self) -> AuditReport {
+        let absent_files = self
+            .absent_individual_files
+            .iter()
+            .map(|absent| {
+                let (volume_id, chapter_id, reason) = match absent {
+                    AbsentFile::DifferentHash(v, c) => (v, c, AbsentReason::DifferentHash),
+                    AbsentFile::NoFile(v, c) => (v, c, AbsentReason::NoFile),
+                };
+                AbsentFileEntry {
+                    volume_interface_id: volume_id.interface_id(),
+                    chapter_interface_id: chapter_id.interface_id(),
+                    reason,
+                }
+            })
+            .collect();
+        let orphan_files = self
+            .orphan_files
+            .iter()
+            .map(|orphan| match orphan {
+                OrphanFile::NotInManifest(volume_id, chapter_id, path) => OrphanFileEntry {
+                    path: path.clone(),
+                    volume_interface_id: Some(volume_id.interface_id()),
+                    chapter_interface_id: Some(chapter_id.interface_id()),
+                },
+                OrphanFile::Unknown(path) => OrphanFileEntry {
+                    path: path.clone(),
+                    volume_interface_id: None,
+                    chapter_interface_id: None,
+                },
+            })
+            .collect();
+        AuditReport {
+            format_version: REPORT_FORMAT_VERSION,
+            spec_id: T::SPEC_ID.to_string(),
+            absent_volumes: self
+                .absent_volume_ids
+                .iter()
+                .map(|v| v.interface_id())
+                .collect(),
+            absent_chapters: self
+                .absent_chapter_ids
+                .iter()
+                .map(|c| c.interface_id())
+                .collect(),
+            absent_files,
+            orphan_files,
+            files_in_manifest: self.files_in_manifest,
+            files_verified: self.files_verified,
+            bytes_verified: self.bytes_verified,
+            provenance: self.provenance.clone(),
+            superseded_by: self.superseded_by.clone(),
+            throughput: self.throughput,
+        }
+    }
+}
+
+/// `part` as a percentage of `whole`, or 100 if `whole` is zero.
+pub(crate) fn percent_of(part: usize, whole: usize) -> f64 {
+    match whole {
+        0 => 100.0,
+        _ => 100.0 * part as f64 / whole as f64,
+    }
+}
+
+impl InventoryReport {
+    pub(crate) fn new<T: DataSpec>(items: &[InventoryItem<T>]) -> Self {
+        let files = items
+            .iter()
+            .map(|(volume_id, chapter_id, path, bytes)| InventoryEntry {
+                volume_interface_id: volume_id.interface_id(),
+                chapter_interface_id: chapter_id.interface_id(),
+                path: path.clone(),
+                bytes: *bytes,
+            })
+            .collect();
+        InventoryReport {
+            format_version: REPORT_FORMAT_VERSION,
+            spec_id: T::SPEC_ID.to_string(),
+            files,
+        }
+    }
+}
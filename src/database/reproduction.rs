@@ -0,0 +1,356 @@
+//! Independent checks that a published Volume can be formed from raw data.
+//!
+//! A third party with the raw data can rebuild a Volume and compare the CIDs
+//! of the Chapter files with those in the published manifest. Matching CIDs
+//! show that the published Chapters hold exactly the raw data, formed
+//! according to the spec. Requires the `publisher` feature.
+//!
+//! This relies on builds being deterministic: the same raw data must always give
+//! the same Chapter and manifest bytes. [Todd::verify_determinism] checks this by
+//! building the whole database twice.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::types::Todd,
+    extraction::{raw_index::RawEntryRange, traits::ExtractorMethods},
+    specs::traits::{ChapterIdMethods, ChapterMethods, DataSpec, ManifestMethods, VolumeIdMethods},
+    utils::{ipfs::cid_v0_string_from_bytes, system::dir_paths},
+};
+
+/// The outcome of [Todd::verify_reproduction].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReproductionReport {
+    pub volume_interface_id: String,
+    /// Directory holding the rebuilt Chapter files.
+    ///
+    /// Kept if any Chapter does not match, for comparison with the published
+    /// files. Otherwise it is removed.
+    pub dir: PathBuf,
+    /// Chapters that were published or rebuilt (or both), in ChapterId order.
+    pub chapters: Vec<ChapterReproduction>,
+    /// Raw entries of the Volume listed in the manifest, for databases that
+    /// describe them (see [RawEntryRange]).
+    pub published_range: Option<RawEntryRange>,
+    /// Raw entries of the Volume in the raw data.
+    pub rebuilt_range: Option<RawEntryRange>,
+}
+
+/// A Chapter of a [ReproductionReport].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChapterReproduction {
+    pub chapter_interface_id: String,
+    /// CID in the manifest. None if the manifest does not list the Chapter.
+    pub published_cid: Option<String>,
+    /// CID of the rebuilt file. None if the raw data has nothing for the Chapter.
+    pub rebuilt_cid: Option<String>,
+}
+
+/// The outcome of [Todd::verify_determinism].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeterminismReport {
+    /// Directory holding the two builds, in `run_1` and `run_2`.
+    ///
+    /// Kept if any file differs, for comparison. Otherwise it is removed.
+    pub dir: PathBuf,
+    /// The files of either build (Chapter files and the manifest), in path order.
+    pub files: Vec<FileDeterminism>,
+}
+
+/// A file of a [DeterminismReport].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileDeterminism {
+    /// Path relative to the build directory. E.g., "nametags_manifest.json".
+    pub path: String,
+    /// CID of the file in the first and second build. None if the build does
+    /// not have the file.
+    pub cids: [Option<String>; 2],
+}
+
+impl FileDeterminism {
+    pub fn matches(&self) -> bool {
+        self.cids[0] == self.cids[1]
+    }
+}
+
+impl DeterminismReport {
+    /// Whether both builds have the same files with the same CIDs.
+    pub fn is_deterministic(&self) -> bool {
+        self.files.iter().all(|f| f.matches())
+    }
+    /// Files with a different CID, or present in only one build.
+    pub fn mismatches(&self) -> Vec<&FileDeterminism> {
+        self.files.iter().filter(|f| !f.matches()).collect()
+    }
+}
+
+impl Display for DeterminismReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mismatches = self.mismatches();
+        write!(
+            f,
+            "{} of {} files identical in two builds",
+            self.files.len() - mismatches.len(),
+            self.files.len()
+        )?;
+        if !mismatches.is_empty() {
+            let paths: Vec<&str> = mismatches.iter().map(|m| m.path.as_str()).collect();
+            write!(
+                f,
+                ". Differing: {} (builds in {:?})",
+                paths.join(", "),
+                self.dir
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl ChapterReproduction {
+    pub fn matches(&self) -> bool {
+        self.published_cid == self.rebuilt_cid
+    }
+}
+
+impl ReproductionReport {
+    /// Whether every Chapter was rebuilt with the published CID, from the
+    /// published raw entries (if listed).
+    pub fn is_reproduced(&self) -> bool {
+        self.chapters.iter().all(|c| c.matches()) && self.range_matches()
+    }
+    /// Whether the Volume holds the raw entries listed in the manifest.
+    ///
+    /// True if the manifest does not list them.
+    pub fn range_matches(&self) -> bool {
+        self.published_range.is_none() || self.published_range == self.rebuilt_range
+    }
+    /// Chapters with a different CID, or present on only one side.
+    pub fn mismatches(&self) -> Vec<&ChapterReproduction> {
+        self.chapters.iter().filter(|c| !c.matches()).collect()
+    }
+}
+
+impl Display for ReproductionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mismatches = self.mismatches();
+        write!(
+            f,
+            "Volume {}: {} of {} Chapters reproduced",
+            self.volume_interface_id,
+            self.chapters.len() - mismatches.len(),
+            self.chapters.len()
+        )?;
+        if !mismatches.is_empty() {
+            let ids: Vec<&str> = mismatches
+                .iter()
+                .map(|c| c.chapter_interface_id.as_str())
+                .collect();
+            write!(
+                f,
+                ". Mismatched: {} (rebuilt files in {:?})",
+                ids.join(", "),
+                self.dir
+            )?;
+        }
+        if !self.range_matches() {
+            write!(
+                f,
+                ". Raw entries differ from the manifest (published: {:?}, rebuilt: {:?})",
+                self.published_range, self.rebuilt_range
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Rebuilds the Chapters of a Volume from the raw data and compares their
+    /// CIDs with the manifest.
+    ///
+    /// The local Chapter files are not used or changed. Every Chapter is
+    /// rebuilt in full, regardless of `config.watched_keys`.
+    ///
+    /// ## Algorithm
+    /// 1. Scan the raw data and form all Chapters of the Volume.
+    /// 2. Write the Chapter files to a temporary directory.
+    /// 3. Compare the CID of each file with the CID in the manifest.
+    /// 4. Compare the raw entries of the Volume (first index, count and content
+    ///    hash) with those in the manifest, if listed.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Sample)?;
+    /// let volume_id = NameTagsVolumeId::from_interface_id("nametags_from_000_000_000")?;
+    /// let report = db.verify_reproduction(&volume_id)?;
+    /// println!("{}", report);
+    /// ```
+    ///
+    /// ## Errors
+    /// If the manifest or raw data cannot be read. Chapters that differ are
+    /// reported rather than being an error.
+    pub fn verify_reproduction(
+        &self,
+        volume_id: &T::AssociatedVolumeId,
+    ) -> Result<ReproductionReport> {
+        self.config.require_unencrypted("verify_reproduction")?;
+        let manifest = self.manifest()?;
+        let published_range = manifest
+            .raw_entry_ranges()?
+            .into_iter()
+            .find(|(v, _range)| v == volume_id)
+            .map(|(_volume_id, range)| range);
+        let published: HashMap<String, String> = manifest
+            .cids()?
+            .into_iter()
+            .filter(|m| &m.volume_id == volume_id)
+            .map(|m| (m.chapter_id.interface_id(), m.cid))
+            .collect();
+        let source = self.scan_source()?;
+        let chapter_ids = T::get_all_chapter_ids()?;
+        let formed = T::AssociatedExtractor::volume_from_source(
+            volume_id,
+            &chapter_ids,
+            &source,
+            self.config.skip_corrupt_raw_files,
+            self.config.oversize_policy,
+        )?;
+        let dir = std::env::temp_dir().join(format!(
+            "todd_{}_reproduction_{}_{}",
+            self.config.data_kind.interface_id(),
+            volume_id.interface_id(),
+            process::id()
+        ));
+        fs::create_dir_all(&dir)?;
+        let mut report = ReproductionReport {
+            volume_interface_id: volume_id.interface_id(),
+            dir,
+            chapters: vec![],
+            published_range,
+            rebuilt_range: T::AssociatedExtractor::raw_entry_range(volume_id, &source)?,
+        };
+        for (chapter_id, (chapter, _oversize)) in chapter_ids.iter().zip(formed) {
+            let rebuilt_cid = match chapter {
+                Some(chapter) => {
+                    let bytes = chapter.as_file_bytes()?;
+                    let path = report.dir.join(chapter.filename());
+                    fs::write(&path, &bytes)
+                        .with_context(|| format!("Unable to write file {:?}", path))?;
+                    Some(cid_v0_string_from_bytes(&bytes)?)
+                }
+                None => None,
+            };
+            let published_cid = published.get(&chapter_id.interface_id()).cloned();
+            if published_cid.is_none() && rebuilt_cid.is_none() {
+                continue;
+            }
+            report.chapters.push(ChapterReproduction {
+                chapter_interface_id: chapter_id.interface_id(),
+                published_cid,
+                rebuilt_cid,
+            });
+        }
+        if report.is_reproduced() {
+            if let Err(e) = fs::remove_dir_all(&report.dir) {
+                warn!("Could not remove {:?}: {}", report.dir, e);
+            }
+        }
+        info!("{}", report);
+        Ok(report)
+    }
+    /// Forms the whole database twice from the raw data and compares the CIDs
+    /// of every file of the two builds, including the manifest.
+    ///
+    /// Anything other than the raw data that changes the bytes (e.g., the order
+    /// raw files are read in, or the order Records are held in) would stop a
+    /// published database from being reproduced, see [Self::verify_reproduction].
+    ///
+    /// The database is not used or changed: each build is made in a temporary
+    /// directory by [Self::full_transformation], with the config of the
+    /// database. The second build uses one thread, so that differences caused
+    /// by the order Chapters are completed in are found.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<SignaturesSpec> = Todd::init(DataKind::Signatures, DirNature::Sample)?;
+    /// let report = db.verify_determinism()?;
+    /// assert!(report.is_deterministic(), "{}", report);
+    /// ```
+    ///
+    /// ## Errors
+    /// If the raw data cannot be read or either build fails. Files that differ
+    /// are reported rather than being an error.
+    pub fn verify_determinism(&self) -> Result<DeterminismReport>
+    where
+        Self: Clone,
+    {
+        self.config.require_unencrypted("verify_determinism")?;
+        let dir = std::env::temp_dir().join(format!(
+            "todd_{}_determinism_{}",
+            self.config.data_kind.interface_id(),
+            process::id()
+        ));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        let mut builds: Vec<BTreeMap<String, String>> = vec![];
+        for (run, threads) in [(1, self.config.parallelism.threads), (2, Some(1))] {
+            let base = dir.join(format!("run_{}", run));
+            let mut build = self.clone();
+            build.config.base_dir_nature_dependent = base.clone();
+            build.config.data_dir = base.join(self.config.data_kind.interface_id());
+            build.config.read_only = false;
+            build.config.keep_manifest_history = false;
+            build.config.shared_data_dirs = vec![];
+            build.config.parallelism.threads = threads;
+            build
+                .full_transformation()
+                .with_context(|| format!("Build {} of the determinism check failed", run))?;
+            builds.push(file_cids(&base, &base)?);
+        }
+        let paths: BTreeSet<&String> = builds.iter().flat_map(|b| b.keys()).collect();
+        let report = DeterminismReport {
+            files: paths
+                .into_iter()
+                .map(|path| FileDeterminism {
+                    path: path.clone(),
+                    cids: [builds[0].get(path).cloned(), builds[1].get(path).cloned()],
+                })
+                .collect(),
+            dir,
+        };
+        if report.is_deterministic() {
+            if let Err(e) = fs::remove_dir_all(&report.dir) {
+                warn!("Could not remove {:?}: {}", report.dir, e);
+            }
+        }
+        info!("{}", report);
+        Ok(report)
+    }
+}
+
+/// The CID of every file under a directory, by path relative to `base`.
+fn file_cids(dir: &Path, base: &Path) -> Result<BTreeMap<String, String>> {
+    let mut cids = BTreeMap::new();
+    for path in dir_paths(dir)? {
+        if path.is_dir() {
+            cids.extend(file_cids(&path, base)?);
+            continue;
+        }
+        let bytes = fs::read(&path).with_context(|| format!("Unable to read file {:?}", path))?;
+        let relative = path.strip_prefix(base)?.to_string_lossy().to_string();
+        cids.insert(relative, cid_v0_string_from_bytes(&bytes)?);
+    }
+    Ok(cids)
+}
@@ -0,0 +1,81 @@
+//! Helpers specific to the signatures database.
+use anyhow::Result;
+use sha3::{Digest, Keccak256};
+
+pub use crate::extraction::signatures::{SignatureIssue, SkippedSignature, TextValidationReport};
+use crate::{
+    extraction::signatures::{canonicalize_signature, SignaturesExtractor},
+    specs::signatures::SignaturesSpec,
+};
+
+use super::types::{Membership, Todd};
+
+/// Signature lookups in both directions.
+///
+/// ## Example
+/// ```
+/// # use min_know::{
+/// #    config::choices::{DataKind, DirNature},
+/// #    database::{signatures::SignatureClient, types::Todd},
+/// # };
+/// let client = SignatureClient::new(Todd::init(DataKind::Signatures, DirNature::Sample)?);
+/// let lookup = client.selector_of("transfer(address, uint256)")?;
+/// assert_eq!(lookup.selector, "a9059cbb");
+/// assert_eq!(lookup.text, "transfer(address,uint256)");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct SignatureClient {
+    pub db: Todd<SignaturesSpec>,
+}
+
+/// The selector for a text signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelectorOf {
+    /// Hex selector (no 0x prefix). E.g., "a9059cbb".
+    pub selector: String,
+    /// Canonical form of the text. E.g., "transfer(address,uint256)".
+    pub text: String,
+    /// Whether the text is present for the selector in the local Chapters.
+    pub present_locally: bool,
+}
+
+impl SignatureClient {
+    pub fn new(db: Todd<SignaturesSpec>) -> Self {
+        SignatureClient { db }
+    }
+    /// Returns the texts for a selector (forward lookup).
+    pub fn texts_of(&self, selector: &str) -> Result<Vec<String>> {
+        let mut texts = vec![];
+        for value in self.db.find(selector.trim_start_matches("0x"))? {
+            texts.extend(value.texts_as_strings()?);
+        }
+        Ok(texts)
+    }
+    /// Returns the selector for a text (reverse lookup).
+    ///
+    /// The selector is computed locally (keccak256 of the canonical text), then
+    /// local Chapters are checked for the text.
+    pub fn selector_of(&self, text: &str) -> Result<SelectorOf> {
+        let text = canonicalize_signature(text)?;
+        let selector = hex::encode(&Keccak256::digest(text.as_bytes())[0..4]);
+        let present_locally = match self.db.might_contain(&selector)? {
+            Membership::Yes(values) => {
+                let mut found = false;
+                for v in values {
+                    found |= v.texts_as_strings()?.contains(&text);
+                }
+                found
+            }
+            Membership::Maybe | Membership::No => false,
+        };
+        Ok(SelectorOf {
+            selector,
+            text,
+            present_locally,
+        })
+    }
+    /// Lists the raw signature texts that are skipped during extraction.
+    pub fn validation_report(&self) -> Result<TextValidationReport> {
+        SignaturesExtractor::validation_report(&self.db.config.raw_source)
+    }
+}
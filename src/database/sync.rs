@@ -0,0 +1,254 @@
+//! Keeping a full local copy of a database up to date and pinned on IPFS.
+//!
+//! A user who wants to support the network (rather than query a few keys) can
+//! hold every Chapter file in the manifest and pin them on their own IPFS node.
+//! [Todd::sync_and_pin] does this in one step and reports what it did. Requires
+//! the `net` feature.
+use std::{fmt::Display, fs};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::{
+    database::{
+        acquisition::{AcquisitionPlan, PlannedFile},
+        changes::ManifestChanges,
+        holdings::Holdings,
+        report::AuditReport,
+        types::{download_plan, verify_planned_files, Todd},
+    },
+    manifest::{mirrors::FileSource, trust::check_trusted_manifest},
+    specs::traits::{ChapterIdMethods, ChapterMethods, DataSpec, ManifestMethods, VolumeIdMethods},
+    utils::{download::download_bytes, gateway::Gateway, ipfs::cid_v0_string_from_bytes},
+};
+
+/// The outcome of [Todd::sync_and_pin].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncReport {
+    /// Changes from the local manifest to the publisher manifest.
+    ///
+    /// None if `config.publisher_manifest_url` is not set.
+    pub manifest_changes: Option<ManifestChanges>,
+    /// Audit of the local files before anything was downloaded.
+    pub audit_before: AuditReport,
+    /// Filenames of the files that were downloaded (absent, or with a different CID).
+    pub downloaded: Vec<String>,
+    /// Audit of the local files after downloading.
+    pub audit_after: AuditReport,
+    /// CIDs pinned by the IPFS node.
+    pub pinned: Vec<String>,
+    /// CIDs that could not be pinned, with the reason.
+    pub pin_failures: Vec<(String, String)>,
+}
+
+impl SyncReport {
+    /// Whether every file in the manifest is present, verified and pinned.
+    pub fn is_complete(&self) -> bool {
+        self.audit_after.absent_volumes.is_empty()
+            && self.audit_after.absent_chapters.is_empty()
+            && self.audit_after.absent_files.is_empty()
+            && self.pin_failures.is_empty()
+    }
+}
+
+impl Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.manifest_changes {
+            Some(changes) if changes.is_empty() => write!(f, "Manifest unchanged. ")?,
+            Some(_) => write!(f, "Manifest updated. ")?,
+            None => write!(f, "Local manifest used. ")?,
+        }
+        write!(
+            f,
+            "Downloaded {} files. {} absent Volumes, {} absent Chapters and {} absent files remain. Pinned {} CIDs",
+            self.downloaded.len(),
+            self.audit_after.absent_volumes.len(),
+            self.audit_after.absent_chapters.len(),
+            self.audit_after.absent_files.len(),
+            self.pinned.len()
+        )?;
+        match self.pin_failures.len() {
+            0 => write!(f, "."),
+            n => write!(f, " ({} failed).", n),
+        }
+    }
+}
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Brings the local database up to date with the publisher and pins every
+    /// Chapter file on an IPFS node. Requires the `net` feature.
+    ///
+    /// `ipfs_api` is the HTTP API of the IPFS node (e.g., "http://127.0.0.1:5001").
+    ///
+    /// ## Algorithm
+    /// 1. If `config.publisher_manifest_url` is set, fetch the manifest from there,
+    ///    check that it is trusted (see [crate::manifest::trust]) and for this
+    ///    database, and replace the local manifest.
+    /// 2. Audit the local files (see [Self::check_completeness]).
+    /// 3. Remove local files whose CID differs from the manifest.
+    /// 4. Download the absent files from the gateway. Volumes removed by
+    ///    [Self::prune] are skipped.
+    /// 5. Verify the downloaded files and audit again.
+    /// 6. Ask the IPFS node to pin the CID of every file that is present and verified.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Default)?;
+    /// let gateway = Gateway::new("http://127.0.0.1:8080")?;
+    /// let report = db.sync_and_pin(&gateway, "http://127.0.0.1:5001")?;
+    /// println!("{}", report);
+    /// ```
+    ///
+    /// ## Errors
+    /// If the publisher manifest cannot be obtained, is not trusted or is for another database,
+    /// or the IPFS API URL is not valid. Files that cannot be downloaded or pinned
+    /// are recorded in the report instead.
+    pub fn sync_and_pin(&self, gateway: &Gateway, ipfs_api: &str) -> Result<SyncReport> {
+        self.config.require_unencrypted("sync_and_pin")?;
+        let api =
+            Url::parse(ipfs_api).with_context(|| format!("Invalid IPFS API URL {}", ipfs_api))?;
+        let _lock = self.write_lock("sync_and_pin")?;
+        let manifest_changes = match &self.config.publisher_manifest_url {
+            Some(url) => Some(self.update_manifest(url)?),
+            None => {
+                info!("No publisher manifest URL is configured, using the local manifest.");
+                None
+            }
+        };
+        let audit_before = self.check_completeness()?.to_report();
+        let plan = self.plan_sync()?;
+        let downloaded: Vec<String> = plan
+            .files_to_download()
+            .map(|file| file.filename.clone())
+            .collect();
+        if let Err(e) = download_plan(&plan, &FileSource::Gateway(gateway.clone()))
+            .and_then(|_| verify_planned_files(&plan))
+        {
+            warn!("Not all files were obtained: {:#}", e);
+        }
+        let audit_after = self.check_completeness()?.to_report();
+        let cids: Vec<String> = self
+            .plan_sync()?
+            .files
+            .into_iter()
+            .filter(|file| file.local_bytes.is_some())
+            .map(|file| file.cid)
+            .collect();
+        let rt = Runtime::new()?;
+        let (pinned, pin_failures) = rt.block_on(pin_cids(&api, cids));
+        let report = SyncReport {
+            manifest_changes,
+            audit_before,
+            downloaded,
+            audit_after,
+            pinned,
+            pin_failures,
+        };
+        info!("{}", report);
+        Ok(report)
+    }
+    /// Replaces the local manifest with the one at the URL, returning the changes.
+    fn update_manifest(&self, url: &str) -> Result<ManifestChanges> {
+        let url = Url::parse(url).with_context(|| format!("Invalid manifest URL {}", url))?;
+        let rt = Runtime::new()?;
+        let bytes = rt.block_on(download_bytes(url.clone()))?;
+        check_trusted_manifest(&bytes, &self.config.trusted_manifest_cids)
+            .with_context(|| format!("Not using the manifest at {}", url))?;
+        let text = String::from_utf8(bytes).context("The publisher manifest is not text")?;
+        let manifest: T::AssociatedManifest =
+            serde_json::from_str(&text).context("Failed to parse the publisher manifest")?;
+        let expected = self.config.data_kind.interface_id();
+        if manifest.database_interface_id() != expected {
+            bail!(
+                "The manifest at {} is for database {}, not {}.",
+                url,
+                manifest.database_interface_id(),
+                expected
+            )
+        }
+        let changes = self.manifest_changes(&text, &[])?;
+        let path = self.config.manifest_file_path()?;
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, &text)
+            .with_context(|| format!("Unable to write file {:?}", partial))?;
+        fs::rename(&partial, &path)
+            .with_context(|| format!("Unable to move {:?} to {:?}", partial, path))?;
+        info!("Manifest updated from {}.", url);
+        Ok(changes)
+    }
+    /// Plans every file in the manifest, except for pruned Volumes.
+    ///
+    /// Only local files with the CID in the manifest are treated as present.
+    /// Local files with a different CID are removed.
+    fn plan_sync(&self) -> Result<AcquisitionPlan> {
+        let holdings = Holdings::read(&self.config.holdings_file_path()?)?;
+        let mut plan = AcquisitionPlan::default();
+        for m in self.manifest()?.cids()? {
+            if holdings.is_pruned(&m.volume_id.interface_id()) {
+                continue;
+            }
+            let dest_dir = self.config.chapter_dir_path(&m.chapter_id);
+            let filename = T::AssociatedChapter::new_empty(&m.volume_id, &m.chapter_id).filename();
+            let path = dest_dir.join(&filename);
+            let local_bytes = match fs::read(&path) {
+                Ok(bytes) if cid_v0_string_from_bytes(&bytes)? == m.cid => Some(bytes.len() as u64),
+                Ok(_) => {
+                    warn!("Removing {:?}, which differs from the manifest.", path);
+                    fs::remove_file(&path)?;
+                    None
+                }
+                Err(_) => None,
+            };
+            if !plan.chapters.contains(&m.chapter_id.interface_id()) {
+                plan.chapters.push(m.chapter_id.interface_id());
+            }
+            plan.files.push(PlannedFile {
+                cid: m.cid,
+                volume_interface_id: m.volume_id.interface_id(),
+                chapter_interface_id: m.chapter_id.interface_id(),
+                dest_dir,
+                filename,
+                local_bytes,
+            })
+        }
+        Ok(plan)
+    }
+}
+
+/// Asks an IPFS node to pin each CID, returning the pinned CIDs and the failures.
+async fn pin_cids(api: &Url, cids: Vec<String>) -> (Vec<String>, Vec<(String, String)>) {
+    let client = Client::new();
+    let mut pinned = vec![];
+    let mut failures = vec![];
+    for cid in cids {
+        match pin_cid(&client, api, &cid).await {
+            Ok(()) => pinned.push(cid),
+            Err(e) => {
+                warn!("Could not pin {}: {:#}", cid, e);
+                failures.push((cid, format!("{:#}", e)));
+            }
+        }
+    }
+    (pinned, failures)
+}
+
+/// Pins a CID with the `pin/add` endpoint of the IPFS HTTP API.
+async fn pin_cid(client: &Client, api: &Url, cid: &str) -> Result<()> {
+    let mut url = api.join("api/v0/pin/add")?;
+    url.query_pairs_mut().append_pair("arg", cid);
+    let response = client.post(url.clone()).send().await?;
+    if !response.status().is_success() {
+        bail!(
+            "Request to {} failed with status {}",
+            url,
+            response.status()
+        )
+    }
+    Ok(())
+}
@@ -1,39 +1,103 @@
 use std::{
+    collections::{HashMap, HashSet},
     fmt::Debug,
     fs,
-    path::PathBuf,
-    sync::{Arc, Mutex}, marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
 };
 
-use anyhow::{anyhow, Context, Result};
-use log::{debug, error, info, warn};
-use rayon::prelude::*;
-use reqwest::Url;
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "net")]
 use tokio::runtime::Runtime;
 
+#[cfg(feature = "compiled-index")]
+use crate::database::compiled::CompiledIndexCache;
 use crate::{
     config::{
-        choices::{DataKind, DirNature},
+        choices::{DataKind, DirNature, UnsupportedDataKind},
         dirs::ConfigStruct,
     },
-    extraction::traits::ExtractorMethods,
-    samples::traits::SampleObtainerMethods,
-    specs::traits::{
-        ChapterIdMethods, ChapterMethods, DataSpec, ManifestMethods, RecordMethods, VolumeIdMethods,
+    database::{
+        acquisition::{choose_at_random, AcquisitionPlan, PlannedFile},
+        changes::ManifestChanges,
+        errata::{apply_errata, LocalErratum, VolumeValues},
+        holdings::{Holdings, RetentionPolicy},
+        lock::{ReadOnlyDatabase, WriteLock},
+        merge::{MergeReport, RejectedFile, RejectionReason},
+        proof::InclusionProof,
+        query_cache::QueryCache,
+        report::{percent_of, HashThroughput, InventoryReport},
+        verified::VerificationCache,
+    },
+    manifest::{
+        lint::{lint_manifest, ManifestIssue},
+        provenance::ManifestProvenance,
+        succession::ManifestSuccessor,
+    },
+    specs::{
+        fingerprint::check_manifest_schema,
+        traits::{
+            ChapterIdMethods, ChapterMethods, DataSpec, ManifestCids, ManifestMethods,
+            RecordKeyMethods, RecordMethods, VolumeIdMethods,
+        },
     },
     utils::{
-        download::{download_files, DownloadTask},
+        bloom::BloomFilter,
         ipfs::cid_v0_string_from_bytes,
-        system::DirFunctions,
+        merkle::{self, Node},
+        system::dir_paths,
+    },
+};
+#[cfg(feature = "net")]
+use crate::{
+    database::acquisition::{FetchOnMiss, ObtainedChapter},
+    manifest::mirrors::FileSource,
+    utils::{
+        download::{block_on, download_bytes, download_bytes_within, download_files, DownloadTask},
+        ens::{self, EnsCache},
+        gateway::Gateway,
     },
 };
 
+pub use crate::extraction::{
+    limits::OversizeValue,
+    validation::{RawIssue, ValidationReport},
+};
+
+/// A Chapter file present in the file system: (VolumeId, ChapterId, path, size in bytes).
+pub type InventoryItem<T> = (
+    <T as DataSpec>::AssociatedVolumeId,
+    <T as DataSpec>::AssociatedChapterId,
+    PathBuf,
+    u64,
+);
+
+/// Local Chapter files that are in the manifest, keyed by
+/// (volume interface id, chapter interface id).
+type LocalManifestFiles = HashMap<(String, String), PathBuf>;
+
 /// The definition for the entire new database.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Todd<T: DataSpec> {
     spec: PhantomData<T>,
     pub config: ConfigStruct,
+    /// Removes the directory for DirNature::Ephemeral when the last clone is dropped.
+    #[serde(skip)]
+    ephemeral: Option<Arc<EphemeralDir>>,
+    /// The compiled index, once opened.
+    #[cfg(feature = "compiled-index")]
+    #[serde(skip)]
+    pub(crate) compiled_index: CompiledIndexCache,
+    /// ENS names resolved so far.
+    #[cfg(feature = "net")]
+    #[serde(skip)]
+    ens_names: EnsCache,
+    /// Results of queries, if `config.query_cache` is set.
+    #[serde(skip)]
+    pub(crate) query_cache: QueryCache<T::AssociatedRecordValue>,
 }
 
 /// Implement generic methods common to all databases.
@@ -41,193 +105,145 @@ impl<T> Todd<T>
     where T: DataSpec + Default + Sync
     {
     /// Initialise the database library with the given configuration.
+    ///
+    /// ## Errors
+    /// An [UnsupportedDataKind] error if the DataKind does not match the spec.
+    ///
+    /// If the data directory contains entries that are not Chapter directories
+    /// for the spec. See [Self::init_ignoring_unknown_dirs].
     pub fn init(data_kind: DataKind, directories: DirNature) -> Result<Self> {
-        assert!(
-            T::spec_matches_input(&data_kind),
-            "DataKind does not match Spec type"
-        );
+        Self::init_with_unknown_dirs(data_kind, directories, false)
+    }
+    /// Initialise the database library, skipping (with a warning) any entries
+    /// in the data directory that are not Chapter directories for the spec.
+    pub fn init_ignoring_unknown_dirs(data_kind: DataKind, directories: DirNature) -> Result<Self> {
+        Self::init_with_unknown_dirs(data_kind, directories, true)
+    }
+    fn init_with_unknown_dirs(
+        data_kind: DataKind,
+        directories: DirNature,
+        ignore_unknown_dirs: bool,
+    ) -> Result<Self> {
+        if !T::spec_matches_input(&data_kind) {
+            return Err(UnsupportedDataKind {
+                requested: data_kind.interface_id(),
+                implemented: DataKind::all()
+                    .iter()
+                    .filter(|kind| T::spec_matches_input(kind))
+                    .map(|kind| kind.interface_id())
+                    .collect(),
+            }
+            .into());
+        }
 
         // Use the spec to then get the DataConfig.
-        let config = directories.into_config(data_kind)?;
+        let mut config = directories.into_config(data_kind)?;
+        config.ignore_unknown_dirs = ignore_unknown_dirs;
+        // Detect data from other databases early, rather than at first use.
+        if config.data_dir.is_dir() {
+            config.chapter_dirs::<T>()?;
+        }
+        let ephemeral = match config.dir_nature {
+            DirNature::Ephemeral => Some(Arc::new(EphemeralDir(
+                config.base_dir_nature_dependent.clone(),
+            ))),
+            _ => None,
+        };
         Ok(Self {
             spec: PhantomData,
             config,
+            ephemeral,
+            #[cfg(feature = "compiled-index")]
+            compiled_index: CompiledIndexCache::default(),
+            #[cfg(feature = "net")]
+            ens_names: EnsCache::default(),
+            query_cache: QueryCache::default(),
         })
     }
-    /// Creates new and complete TODD-compliant database from
-    /// a specification and corresponding raw data source.
+    /// Deletes Chapter files for Volumes outside of the retention policy.
     ///
-    /// ## Example
-    /// ```ignore
-    /// let mut db: Todd<AAISpec> = Todd::init(DataKind::default(), DirNature::Sample)?;
-    /// db.full_transformation()?;
-    /// ```
-    /// ## Algorithm
-    /// Relies on the existence of an Extractor method that each database must implement.
-    /// That method raw source data in the specified directory and produces a Chapter
-    /// that matches the specified VolumeId and ChapterId.
-    ///
-    /// The returned Chapter is then saved.
-    /// This is repeated for all possible Chapters and may occur in parallel.
-    ///
-    pub fn full_transformation(&self) -> Result<()> {
-        let volume_ids = &T::get_all_volume_ids(&self.config.raw_source)?;
-        let chapter_ids = &T::get_all_chapter_ids()?;
-        self.create_chapter_combinations(volume_ids, chapter_ids)?;
-        info!("Finished creating database.");
-        self.generate_manifest()?;
-        Ok(())
-    }
-    /// Extends the database by transforming unincorporated raw data.
+    /// The policy and the removed Volumes are recorded in the holdings file, so that
+    /// [Self::check_completeness] and [Self::repair_from_raw] do not treat them as missing.
+    /// Use [Self::clear_retention_policy] to make them eligible for restoration.
+    ///
+    /// Returns the interface ids of the Volumes removed.
     ///
     /// ## Algorithm
-    /// - Get the latest VolumeId present in processed data.
-    /// - Get all VolumeIds possible based on raw data (use extractor methods)
-    /// - Keep only the VolumeIds that are later than the latest existing VolumeId.
-    /// - For vol_ids/chapter_ids combinations, self.create_chapter
-    /// - Generate manifest unless changes were None.
-    ///
-    /// ## Database specific concepts
-    ///
-    /// For each database, the latest volume can be found from raw data properties:
-    /// - AAI: Block number of the latest chunk is used.
-    /// - Nametag: Index of the last file in the append-only raw database.
-    ///     - Edits are appended not added as a new entry, not included in the exsisting file.
-    ///     - All entries have an index. The index of the latest entry is used.
-    /// - Contract source code: The index of the latest entry is used.
-    /// - 4 byte signature: The index of the latest entry is used.
-    pub fn extend(&self) -> Result<()> {
-        let all_possible_volume_ids = T::get_all_volume_ids(&self.config.raw_source)?;
-
-        let latest_existing_vol = self.config.latest_volume::<T>()?;
-        let index_of_existing = latest_existing_vol.is_nth()? as usize;
-
-        let mut new_volume_ids: Vec<T::AssociatedVolumeId> = vec![];
-        for (index, vol) in all_possible_volume_ids.into_iter().enumerate() {
-            if index > index_of_existing {
-                new_volume_ids.push(vol);
-            }
-        }
-        let chapter_ids = &T::get_all_chapter_ids()?;
-        self.create_chapter_combinations(&new_volume_ids, chapter_ids)?;
-        info!("Finished extending database.");
-        self.generate_manifest()?;
-        Ok(())
-    }
-    /// Identifies missing database files and creates them
-    /// by transforming unincorporated raw data.
     ///
-    /// Files are considered missing if they are present in the manifest and
-    /// absent in the file system.
-    pub fn repair_from_raw(&self) -> Result<()> {
-        let audit = self.check_completeness()?;
-        let missing_chapters = audit.missing_chapters()?;
-        if missing_chapters.is_empty() {
-            info!("Database is complete. No repairs needed.");
-            return Ok(());
-        }
-        info!(
-            "{} Chapter(s) are missing and will be created from raw data.",
-            missing_chapters.len()
-        );
-        self.create_specific_chapters(missing_chapters)?;
-        info!("Finished rapairing database.");
+    /// - Find the Volume positions to keep, relative to the latest local Volume.
+    /// - Delete each Chapter file (and its bloom filter) outside of those positions.
+    /// - Add the removed Volumes to the holdings file.
+    pub fn prune(&self, policy: RetentionPolicy) -> Result<Vec<String>> {
+        let _lock = self.write_lock("prune")?;
+        let inventory = self.inventory()?;
+        let Some((latest_vol, _, _, _)) = inventory.last() else {
+            bail!("No Chapter files found in {:?}", self.config.data_dir)
+        };
+        let range = match &policy {
+            RetentionPolicy::KeepLastN(_) => None,
+            RetentionPolicy::VolumeRange { oldest, newest } => Some((
+                T::AssociatedVolumeId::from_interface_id(oldest)?.is_nth()?,
+                T::AssociatedVolumeId::from_interface_id(newest)?.is_nth()?,
+            )),
+        };
+        let (first, last) = policy.positions_kept(latest_vol.is_nth()?, range)?;
 
-        Ok(())
-    }
-    /// Creates every possible Chapter using the VolumeIds/ChapterIds provided.
-    ///
-    /// Every combination of is created.
-    ///
-    /// Used by self.full_transformation() and self.extend().
-    fn create_chapter_combinations(
-        &self,
-        volume_ids: &[T::AssociatedVolumeId],
-        chapter_ids: &[T::AssociatedChapterId],
-    ) -> Result<()> {
-        info!(
-            "{} VolumeIds, each with {} ChapterIds.",
-            volume_ids.len(),
-            chapter_ids.len()
-        );
-        let mut ids: Vec<(&T::AssociatedVolumeId, &T::AssociatedChapterId)> = vec![];
-        for v in volume_ids {
-            for c in chapter_ids {
-                ids.push((v, c))
+        let path = self.config.holdings_file_path()?;
+        let mut holdings = Holdings::read(&path)?;
+        let mut removed: Vec<String> = vec![];
+        for (volume_id, chapter_id, chapter_path, _size) in &inventory {
+            let n = volume_id.is_nth()?;
+            if (first..=last).contains(&n) {
+                continue;
+            }
+            fs::remove_file(chapter_path)
+                .with_context(|| format!("Failed to remove {:?}", chapter_path))?;
+            let bloom_path = self.config.bloom_file_path::<T>(volume_id, chapter_id);
+            if bloom_path.exists() {
+                fs::remove_file(bloom_path)?;
+            }
+            let vol = volume_id.interface_id();
+            if !removed.contains(&vol) {
+                removed.push(vol);
             }
         }
-        self.create_specific_chapters(&ids)?;
-        Ok(())
+        for vol in &removed {
+            if !holdings.is_pruned(vol) {
+                holdings.pruned_volumes.push(vol.clone());
+            }
+        }
+        info!("Pruned {} Volume(s).", removed.len());
+        holdings.retention = Some(policy);
+        holdings.write(&path)?;
+        Ok(removed)
     }
-    /// Creates specific Chapters using the VolumeIds/ChapterIds provided.
+    /// Removes the retention policy and the record of pruned Volumes.
     ///
-    /// Used by self.repair() and indirectly by self.full_transformation() and self.extend().
-    fn create_specific_chapters(
-        &self,
-        ids: &[(&T::AssociatedVolumeId, &T::AssociatedChapterId)],
-    ) -> Result<()> {
-        let total_chapters = ids.len() as u32;
-        info!("{} total Chapters.", total_chapters);
-        let count = Arc::new(Mutex::new(0_u32));
-
-        ids.par_iter().for_each(|(volume_id, chapter_id)| {
-            self.create_chapter(volume_id, chapter_id);
-            log_count(
-                count.clone(),
-                total_chapters,
-                "Finished checking/creating chapter",
-                100,
-            );
-        });
+    /// Pruned Volumes are then reported as missing by [Self::check_completeness]
+    /// and can be restored with [Self::repair_from_raw].
+    pub fn clear_retention_policy(&self) -> Result<()> {
+        self.check_writable("clear_retention_policy")?;
+        let path = self.config.holdings_file_path()?;
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove holdings file {:?}", path))?;
+        }
         Ok(())
     }
-    /// Creates a new manifest file.
-    ///
-    /// This will override an existing manifest file. The file
-    /// in the directory alongside the data and raw data directories.
-    ///
-    /// ## Algorithm
-    /// 1. Goes through each Chapter file in the data directory.
-    /// 2. The IPFS CID (v0) is computed from the file bytes as-is (encoded).
-    /// 3. Additional database metadata is recorded.
-    /// 4. File is saved as a {database_interface_id}_manifest.json.
-    pub fn generate_manifest(&self) -> Result<()> {
-        info!("Generating manifest.");
-        let mut manifest = T::AssociatedManifest::default();
-        let mut cids: Vec<(String, T::AssociatedVolumeId, T::AssociatedChapterId)> = vec![];
-        // Go through all the files in config.data_dir
-        let chapter_dirs = fs::read_dir(&self.config.data_dir).with_context(|| {
-            format!("Couldn't read data directory {:?}.", &self.config.data_dir)
-        })?;
-        for chapter_dir in chapter_dirs {
-            // Obtain ChapterId from directory name.
-            let dir = chapter_dir?.path();
-            let chap_id = T::AssociatedChapterId::from_chapter_directory(&dir)?;
-            // Obtain VolumeIds using ChapterId
-            let chapter_files: Vec<(PathBuf, T::AssociatedVolumeId)> =
-                self.config.parse_all_files_for_chapter::<T>(&chap_id)?;
-            for (chapter_path, volume_id) in chapter_files {
-                let bytes = fs::read(chapter_path)?;
-                let cid = cid_v0_string_from_bytes(&bytes)?;
-                cids.push((cid, volume_id, chap_id.clone()))
-            }
-        }
-        let latest_volume: T::AssociatedVolumeId = self.config.latest_volume::<T>()?;
-        // For each file get filename (--> volume_id and chapter_id) and bytes
-        // CID from bytes
-        manifest.set_spec_version(T::spec_version());
-        manifest.set_schemas(T::spec_schemas_resource());
-        manifest.set_database_interface_id(self.config.data_kind.interface_id());
-        manifest.set_latest_volume_identifier(latest_volume.interface_id());
-        manifest.set_cids(&cids);
-
-        let manifest_path = self.config.manifest_file_path()?;
-        let json_manifest = serde_json::to_string_pretty(&manifest)?;
-
-        fs::write(&manifest_path, json_manifest)
-            .with_context(|| format!("Failed to write file: {:?}", &manifest_path))?;
-        debug!("Manifest saved.");
-        Ok(())
+    /// Reads the local bloom filter for a Chapter, if present.
+    pub fn bloom_filter(
+        &self,
+        volume_id: &T::AssociatedVolumeId,
+        chapter_id: &T::AssociatedChapterId,
+    ) -> Result<Option<BloomFilter>> {
+        let path = self.config.bloom_file_path::<T>(volume_id, chapter_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(&path).with_context(|| format!("Failed to read file {:?}", &path))?;
+        let filter = BloomFilter::from_bytes(&bytes)
+            .with_context(|| format!("Failed to decode bloom filter {:?}", &path))?;
+        Ok(Some(filter))
     }
     /// Checks the database for completeness with respect the manifest file
     /// present.
@@ -235,22 +251,63 @@ impl<T> Todd<T>
     /// ## Algorithm
     ///
     /// - Check for missing Chapter directories (a user may only need a subset).
+    /// - Skip Volumes removed by [Self::prune].
     /// - Of the present chapter directories, check volumes one at a time.
     ///     - If a volume is absent, record the reason (bad hash, no file)
     ///     - If a volume is absent across all chapter directories, then record the vol id
     ///     - Otherwise record the individual absent files.
+    /// - Record files in Chapter directories that are not in the manifest (see [Self::clean_orphans]).
+    ///
+    /// Files with the same size and modification time as in an earlier audit are
+    /// not hashed again (see [Self::check_completeness_forced]). Other files are
+    /// read in chunks of `config.hash_buffer_bytes`, and the rate and time taken
+    /// are reported in `throughput`.
+    ///
+    /// Lookups are by interface id in hash sets, so the time taken grows with
+    /// the number of files in the manifest rather than its square.
     pub fn check_completeness(&self) -> Result<CompletenessAudit<T>> {
+        self.audit_completeness(false)
+    }
+    /// Checks the database for completeness, hashing every file.
+    ///
+    /// As for [Self::check_completeness], but does not rely on the CIDs recorded
+    /// by earlier audits. Detects files changed without a change in size or
+    /// modification time.
+    pub fn check_completeness_forced(&self) -> Result<CompletenessAudit<T>> {
+        self.audit_completeness(true)
+    }
+    fn audit_completeness(&self, force: bool) -> Result<CompletenessAudit<T>> {
+        let start = Instant::now();
         let manifest = self.manifest()?;
+        let holdings = Holdings::read(&self.config.holdings_file_path()?)?;
+        let cache_path = self.config.verification_cache_path()?;
+        let mut cache =
+            VerificationCache::read(&cache_path).with_buffer(self.config.hash_buffer_bytes);
 
         let mut audit = CompletenessAudit {
             absent_chapter_ids: vec![],
             absent_volume_ids: vec![],
             absent_individual_files: vec![],
+            orphan_files: vec![],
+            files_in_manifest: 0,
+            files_verified: 0,
+            bytes_verified: 0,
+            provenance: manifest.provenance().clone(),
+            superseded_by: manifest.superseded_by().cloned(),
+            throughput: HashThroughput::default(),
         };
+        let pruned: HashSet<&str> = holdings.pruned_volumes.iter().map(|v| v.as_str()).collect();
         // Check directories first.
-        let present = self.chapters_present()?;
+        let present: HashSet<String> = self
+            .chapters_present()?
+            .iter()
+            .map(|c| c.interface_id())
+            .collect();
+        let mut absent_chapters: HashSet<String> = HashSet::new();
         for c in T::get_all_chapter_ids()? {
-            if !present.contains(&c) {
+            let chapter_interface_id = c.interface_id();
+            if !present.contains(&chapter_interface_id) {
+                absent_chapters.insert(chapter_interface_id);
                 audit.absent_chapter_ids.push(c)
             }
         }
@@ -258,158 +315,708 @@ impl<T> Todd<T>
         let latest_manifest_vol =
             T::AssociatedVolumeId::from_interface_id(manifest.latest_volume_identifier())?;
         let all_possible_volumes = latest_manifest_vol.all_prior()?;
-        // VolumeIds with at least one valid file observed.
-        let mut vols_seen: Vec<T::AssociatedVolumeId> = vec![];
+        // Interface ids of Volumes with at least one valid file observed.
+        let mut vols_seen: HashSet<String> = HashSet::new();
+        let manifest_cids = manifest.cids()?;
+        let (present_files, orphans) = self.sort_local_files(&manifest_cids)?;
+        audit.orphan_files = orphans;
 
-        for m in manifest.cids()? {
-            if audit.absent_chapter_ids.contains(&m.chapter_id) {
+        for m in manifest_cids {
+            let file_key = (m.volume_id.interface_id(), m.chapter_id.interface_id());
+            if pruned.contains(file_key.0.as_str()) {
+                continue;
+            }
+            audit.files_in_manifest += 1;
+            if absent_chapters.contains(&file_key.1) {
                 // Skip file if its directory is known to be absent by its ChapterId.
                 continue;
             }
-            // Try to read the file.
-            let chap_dir = self.config.chapter_dir_path(&m.chapter_id);
-            let filename = T::AssociatedChapter::new_empty(&m.volume_id, &m.chapter_id).filename();
-            let filepath = chap_dir.join(filename);
-
             // If it is absent, ::NoFile
-            if !filepath.exists() {
+            let Some(filepath) = present_files.get(&file_key) else {
                 let abs = AbsentFile::NoFile(m.volume_id, m.chapter_id);
                 audit.absent_individual_files.push(abs);
                 continue;
-            }
+            };
 
             // If it is wrong, ::DifferentHash
-            let bytes = fs::read(filepath)?;
-            let file_cid = cid_v0_string_from_bytes(&bytes)?;
+            let file_cid = cache.cid_of(filepath, force)?;
             if m.cid != file_cid {
                 let abs = AbsentFile::DifferentHash(m.volume_id, m.chapter_id);
                 audit.absent_individual_files.push(abs);
                 continue;
             }
 
-            // If is is present, add to vols_seen (unless alread there).
-            if !vols_seen.contains(&m.volume_id) {
-                // Record all volumes that are seen at least once.
-                vols_seen.push(m.volume_id)
-            }
+            // If is is present, record the Volume as seen at least once.
+            audit.files_verified += 1;
+            audit.bytes_verified += fs::metadata(filepath)?.len();
+            vols_seen.insert(file_key.0);
         }
 
         for v in all_possible_volumes {
-            if !vols_seen.contains(&v) {
+            let volume_interface_id = v.interface_id();
+            if !vols_seen.contains(&volume_interface_id)
+                && !pruned.contains(volume_interface_id.as_str())
+            {
                 audit.absent_volume_ids.push(v)
             }
         }
-
+        debug!(
+            "{} files unchanged since an earlier audit were not hashed.",
+            cache.unchanged
+        );
+        audit.throughput = HashThroughput {
+            total_ms: start.elapsed().as_millis() as u64,
+            ..cache.hashed
+        };
+        info!("Audit: {}.", audit.throughput);
+        self.record_verified(&cache, &cache_path);
         Ok(audit)
     }
-    /// Gets the ChapterIds of the Chapter directories that exist in the file system.
+    /// Sorts the files in the local Chapter directories into those in the manifest
+    /// and orphans.
+    pub(crate) fn sort_local_files(
+        &self,
+        manifest_cids: &[ManifestCids<T>],
+    ) -> Result<(LocalManifestFiles, Vec<OrphanFile<T>>)> {
+        let in_manifest: HashSet<(String, String)> = manifest_cids
+            .iter()
+            .map(|m| (m.volume_id.interface_id(), m.chapter_id.interface_id()))
+            .collect();
+        let mut present_files = HashMap::new();
+        let mut orphans = vec![];
+        for chapter_id in self.chapters_present()? {
+            let (files, unknown) = self.config.sort_files_for_chapter::<T>(&chapter_id)?;
+            for (path, reason) in unknown {
+                debug!("Not a Chapter file {:?}: {}", path, reason);
+                orphans.push(OrphanFile::Unknown(path));
+            }
+            for (path, volume_id) in files {
+                let key = (volume_id.interface_id(), chapter_id.interface_id());
+                match in_manifest.contains(&key) {
+                    true => {
+                        present_files.insert(key, path);
+                    }
+                    false => orphans.push(OrphanFile::NotInManifest(
+                        volume_id,
+                        chapter_id.clone(),
+                        path,
+                    )),
+                }
+            }
+        }
+        orphans.sort_by(|a, b| a.path().cmp(b.path()));
+        Ok((present_files, orphans))
+    }
+    /// Removes files in the Chapter directories that are not in the manifest.
     ///
-    /// Does not check if the directories are empty.
-    fn chapters_present(&self) -> Result<Vec<T::AssociatedChapterId>> {
-        let chapter_dirs = fs::read_dir(&self.config.data_dir).with_context(|| {
-            format!("Couldn't read data directory {:?}.", &self.config.data_dir)
-        })?;
-        let mut chapters_present: Vec<T::AssociatedChapterId> = vec![];
-        for chapter_dir in chapter_dirs {
-            // Obtain ChapterId from directory name.
-            let dir = chapter_dir?.path();
-            let chap_id = T::AssociatedChapterId::from_chapter_directory(&dir)?;
-            chapters_present.push(chap_id);
+    /// These may be left over from older builds. With `dry_run`, the files are only
+    /// listed (and logged). The bloom filters of removed Chapter files are also removed.
+    ///
+    /// Returns the orphan files found.
+    pub fn clean_orphans(&self, dry_run: bool) -> Result<Vec<OrphanFile<T>>> {
+        let _lock = match dry_run {
+            true => None,
+            false => Some(self.write_lock("clean_orphans")?),
+        };
+        let manifest_cids = self.manifest()?.cids()?;
+        let (_present, orphans) = self.sort_local_files(&manifest_cids)?;
+        for orphan in &orphans {
+            if dry_run {
+                info!("Would remove {}.", orphan);
+                continue;
+            }
+            fs::remove_file(orphan.path())
+                .with_context(|| format!("Failed to remove {:?}", orphan.path()))?;
+            if let OrphanFile::NotInManifest(volume_id, chapter_id, _) = orphan {
+                let bloom_path = self.config.bloom_file_path::<T>(volume_id, chapter_id);
+                if bloom_path.exists() {
+                    fs::remove_file(bloom_path)?;
+                }
+            }
+            info!("Removed {}.", orphan);
         }
-        Ok(chapters_present)
+        Ok(orphans)
     }
-    /// Creates then saves a single chapter.
+    /// Acquires the lock that prevents other processes from writing to the database.
+    ///
+    /// Held by operations that modify Chapter files or the manifest, until dropped.
     ///
     /// ## Errors
-    /// All errors encountered during child function execution are handled
-    /// by logging here (no errors are returned). This is to enable the
-    /// function to be called concurrently.
-    fn create_chapter(
-        &self,
-        volume_id: &T::AssociatedVolumeId,
-        chapter_id: &T::AssociatedChapterId,
-    ) {
-        let chapter_result = T::AssociatedExtractor::chapter_from_raw(
-            chapter_id,
-            volume_id,
-            &self.config.raw_source,
-        );
-        let current_chapter = format!(
-            "chapter (vol_id: {:?}, chap_id: {:?})",
-            volume_id.interface_id(),
-            chapter_id.interface_id()
-        );
-
-        let chapter_option = match chapter_result {
-            Ok(c) => c,
-            Err(e) => {
-                error!("Error processing {}: {}", current_chapter, e);
-                return;
+    /// [DatabaseLocked](crate::database::lock::DatabaseLocked) if another process is writing,
+    /// or [ReadOnlyDatabase] if `config.read_only` is set.
+    pub(crate) fn write_lock(&self, operation: &str) -> Result<WriteLock> {
+        self.check_writable(operation)?;
+        WriteLock::acquire(&self.config.lock_file_path()?, operation)
+    }
+    /// Records the files verified, so that unchanged files are not hashed
+    /// again. Nothing is recorded if `config.read_only` is set.
+    pub(crate) fn record_verified(&self, cache: &VerificationCache, cache_path: &Path) {
+        if self.config.read_only {
+            return;
+        }
+        if let Err(e) = cache.write(cache_path) {
+            warn!("Could not record verified files: {:#}", e);
+        }
+    }
+    /// Checks that an operation may write to the database.
+    ///
+    /// ## Errors
+    /// [ReadOnlyDatabase] if `config.read_only` is set.
+    pub(crate) fn check_writable(&self, operation: &str) -> Result<()> {
+        if self.config.read_only {
+            return Err(ReadOnlyDatabase {
+                path: self.config.base_dir_nature_dependent.clone(),
+                operation: operation.to_string(),
             }
+            .into());
+        }
+        Ok(())
+    }
+    /// Gets the Chapter files that exist in the file system.
+    ///
+    /// Each item is (VolumeId, ChapterId, path, size in bytes). Items are sorted
+    /// by VolumeId, then by ChapterId.
+    pub fn inventory(&self) -> Result<Vec<InventoryItem<T>>> {
+        let mut keyed: Vec<((u32, String), InventoryItem<T>)> = vec![];
+        for chapter_id in self.chapters_present()? {
+            let chapter_files = self.config.parse_all_files_for_chapter::<T>(&chapter_id)?;
+            for (path, volume_id) in chapter_files {
+                let size = fs::metadata(&path)
+                    .with_context(|| format!("Failed to read metadata for {:?}", path))?
+                    .len();
+                let sort_key = (volume_id.is_nth()?, chapter_id.interface_id());
+                keyed.push((sort_key, (volume_id, chapter_id.clone(), path, size)));
+            }
+        }
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(keyed.into_iter().map(|(_key, item)| item).collect())
+    }
+    /// Gets the Chapter files that exist in the file system, with interface ids.
+    ///
+    /// A stable form of [Self::inventory] for external tools. E.g., serialized as JSON.
+    pub fn inventory_report(&self) -> Result<InventoryReport> {
+        Ok(InventoryReport::new::<T>(&self.inventory()?))
+    }
+    /// Imports Chapter files from another local copy of the same database.
+    ///
+    /// Only files with the CID recorded in the local manifest are used. The other
+    /// data directory is not modified.
+    ///
+    /// ## Algorithm
+    ///
+    /// For each Chapter file in the other data directory:
+    /// - Reject it if it is not in the manifest or its CID differs from the manifest.
+    /// - Skip it if the local file is present with the CID in the manifest.
+    /// - Otherwise copy it, replacing any local file with a different CID.
+    pub fn merge_from(&self, other_data_dir: &Path) -> Result<MergeReport> {
+        let _lock = self.write_lock("merge_from")?;
+        let manifest_cids: HashMap<(String, String), String> = self
+            .manifest()?
+            .cids()?
+            .into_iter()
+            .map(|m| {
+                (
+                    (m.volume_id.interface_id(), m.chapter_id.interface_id()),
+                    m.cid,
+                )
+            })
+            .collect();
+        let other = ConfigStruct {
+            data_dir: other_data_dir.to_path_buf(),
+            ..self.config.clone()
         };
-
-        let Some(chapter) = chapter_option else {
-            debug!("No raw data (skipping) relevant to {}.", current_chapter);
-            return
-        };
-
-        match self.save_chapter(chapter) {
-            Ok(_) => {}
-            Err(e) => error!("Error processing {}: {}", current_chapter, e),
+        let mut report = MergeReport::default();
+        for (_dir, chapter_id) in other.chapter_dirs::<T>()? {
+            for (path, volume_id) in other.parse_all_files_for_chapter::<T>(&chapter_id)? {
+                let key = (volume_id.interface_id(), chapter_id.interface_id());
+                let Some(expected) = manifest_cids.get(&key) else {
+                    let reason = RejectionReason::NotInManifest;
+                    report.rejected.push(RejectedFile { path, reason });
+                    continue;
+                };
+                let (found, _bytes) = other.file_cid(&path)?;
+                if &found != expected {
+                    let reason = RejectionReason::DifferentHash {
+                        expected: expected.clone(),
+                        found,
+                    };
+                    report.rejected.push(RejectedFile { path, reason });
+                    continue;
+                }
+                let dest_dir = self.config.chapter_dir_path(&chapter_id);
+                let Some(filename) = path.file_name() else {
+                    bail!("Couldn't read filename {:?}.", path)
+                };
+                let dest = dest_dir.join(filename);
+                let local_matches = match fs::read(&dest) {
+                    Ok(bytes) => Some(&cid_v0_string_from_bytes(&bytes)? == expected),
+                    Err(_) => None,
+                };
+                match local_matches {
+                    Some(true) => {
+                        report.already_present.push(dest);
+                        continue;
+                    }
+                    Some(false) => report.replaced.push(dest.clone()),
+                    None => report.imported.push(dest.clone()),
+                }
+                fs::create_dir_all(&dest_dir)?;
+                // Removed first so that a file hardlinked with another database is not modified.
+                if dest.exists() {
+                    fs::remove_file(&dest)?;
+                }
+                fs::copy(&path, &dest)
+                    .with_context(|| format!("Failed to copy {:?} to {:?}", path, dest))?;
+            }
         }
+        info!("Merged from {:?}: {}.", other_data_dir, report);
+        Ok(report)
     }
-    /// Writes a chapter to a file.
-    fn save_chapter(&self, chapter: T::AssociatedChapter) -> Result<()> {
-        let chapter_dir_path = &self.config.chapter_dir_path(chapter.chapter_id());
-        fs::create_dir_all(chapter_dir_path)?;
-        let encoded = chapter.as_serialized_bytes()?;
-        let filename = chapter.filename();
-        debug!(
-            "Saving chapter: {}, with {} records ({} bytes).",
-            &filename,
-            chapter.records().len(),
-            encoded.len()
-        );
-        let filepath = chapter_dir_path.join(&filename);
-        fs::write(&filepath, encoded).context(anyhow!("Unable to write file {:?}", &filepath))?;
-        Ok(())
+    /// Gets the ChapterIds of the Chapter directories that exist in the file system.
+    ///
+    /// Does not check if the directories are empty.
+    fn chapters_present(&self) -> Result<Vec<T::AssociatedChapterId>> {
+        let chapters_present = self
+            .config
+            .chapter_dirs::<T>()?
+            .into_iter()
+            .map(|(_path, chapter_id)| chapter_id)
+            .collect();
+        Ok(chapters_present)
+    }
+    /// Converts a raw key (e.g., hex string) to a RecordKey.
+    ///
+    /// With the `net` feature, an ENS name (e.g., "vitalik.eth") is resolved to an
+    /// address for databases keyed by address, using `config.ens_rpc_url`.
+    /// Resolved names are cached.
+    pub(crate) fn record_key(&self, raw_key: &str) -> Result<T::AssociatedRecordKey> {
+        #[cfg(feature = "net")]
+        if T::ADDRESS_KEYED && ens::is_ens_name(raw_key) {
+            let Some(rpc_url) = &self.config.ens_rpc_url else {
+                bail!(
+                    "Set config.ens_rpc_url to resolve the ENS name {}.",
+                    raw_key
+                )
+            };
+            let address = self.ens_names.resolve(raw_key, rpc_url)?;
+            return T::raw_key_as_record_key(&address);
+        }
+        T::raw_key_as_record_key(raw_key)
     }
     /// Obtains the RecordValues that match a particular RecordKey
     ///
     /// Each Chapter contains Records with key-value pairs. This function
     /// aggregates values from all relevant Records (across different Chapters).
+    /// Values are in Volume order, oldest first. See [Self::find_by_volume]
+    /// to keep them grouped by Volume.
+    ///
+    /// With the `compiled-index` feature, a compiled index (see `Todd::compile_index()`)
+    /// is used instead of the Chapter files if it matches the manifest.
+    ///
+    /// With the `net` feature and `config.fetch_on_miss` set, a Chapter that is
+    /// absent locally is obtained from a gateway first. This blocks the calling
+    /// thread. Within a tokio runtime it must be a multi-threaded runtime, or the
+    /// call must be made from `tokio::task::spawn_blocking`.
+    ///
+    /// With `config.query_cache` set, values are kept until the manifest changes
+    /// (see [query_cache](crate::database::query_cache)).
+    ///
+    /// ## Errors
+    /// If the Chapter for the key is absent locally (and is not obtained), or
+    /// a Chapter file cannot be decoded. See [Self::find_with] to skip such files.
     pub fn find(&self, raw_record_key: &str) -> Result<Vec<T::AssociatedRecordValue>> {
-        let target_record_key = T::raw_key_as_record_key(raw_record_key)?;
+        if let Some(limits) = &self.config.query_cache {
+            return self.find_cached(raw_record_key, limits);
+        }
+        Ok(self.find_with(raw_record_key, FindMode::Strict)?.values)
+    }
+    /// Obtains the RecordValues that match a particular RecordKey, as for
+    /// [Self::find], with a choice of how undecodable Chapter files are treated.
+    ///
+    /// With [FindMode::Lenient], files that cannot be read or decoded are skipped
+    /// and listed in the result, so that the values from the other files can
+    /// still be used.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let found = db.find_with("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae", FindMode::Lenient)?;
+    /// for skipped in &found.skipped {
+    ///     println!("Skipped {:?}: {}", skipped.path, skipped.reason);
+    /// }
+    /// ```
+    ///
+    /// ## Errors
+    /// If the Chapter for the key is absent locally (and is not obtained). With
+    /// [FindMode::Strict], at the first Chapter file that cannot be decoded,
+    /// naming the file.
+    pub fn find_with(&self, raw_record_key: &str, mode: FindMode) -> Result<FindResult<T>> {
+        let target_record_key = self.record_key(raw_record_key)?;
         let chapter_id = T::record_key_to_chapter_id(&target_record_key)?;
+        let errata = self.errata_for_chapter(&chapter_id)?;
+        // The compiled index does not include errata.
+        #[cfg(feature = "compiled-index")]
+        if errata.is_empty() {
+            if let Some(values) = self.find_compiled(&target_record_key)? {
+                return Ok(FindResult {
+                    values,
+                    skipped: vec![],
+                });
+            }
+        }
+        let (matching, skipped) =
+            self.volume_values(raw_record_key, &target_record_key, &errata, mode)?;
+        Ok(FindResult {
+            values: matching
+                .into_iter()
+                .flat_map(|(_vol, values)| values)
+                .collect(),
+            skipped,
+        })
+    }
+    /// Obtains the RecordValues that match a particular RecordKey, as for
+    /// [Self::find], grouped by the Volume they are from (oldest first).
+    ///
+    /// Errata are applied to the Volumes they correct. The compiled index is
+    /// not used, as it does not record Volumes.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// for (volume_id, values) in db.find_by_volume("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae")? {
+    ///     println!("{}: {} values", volume_id.interface_id(), values.len());
+    /// }
+    /// ```
+    ///
+    /// ## Errors
+    /// As for [Self::find].
+    pub fn find_by_volume(&self, raw_record_key: &str) -> Result<VolumeValues<T>> {
+        let target_record_key = self.record_key(raw_record_key)?;
+        let chapter_id = T::record_key_to_chapter_id(&target_record_key)?;
+        let errata = self.errata_for_chapter(&chapter_id)?;
+        let (matching, _skipped) = self.volume_values(
+            raw_record_key,
+            &target_record_key,
+            &errata,
+            FindMode::Strict,
+        )?;
+        Ok(matching)
+    }
+    /// Reads the local Chapter files for a key and returns the matching values
+    /// of each Volume, with errata applied, in Volume order.
+    #[cfg_attr(not(feature = "net"), allow(unused_variables))]
+    fn volume_values(
+        &self,
+        raw_record_key: &str,
+        target_record_key: &T::AssociatedRecordKey,
+        errata: &[LocalErratum<T>],
+        mode: FindMode,
+    ) -> Result<(VolumeValues<T>, Vec<SkippedFile>)> {
+        let chapter_id = T::record_key_to_chapter_id(target_record_key)?;
         let chap_dir = self.config.chapter_dir_path(&chapter_id);
+        if !has_files(&chap_dir) {
+            #[cfg(feature = "net")]
+            if let Some(fetch) = &self.config.fetch_on_miss {
+                self.fetch_on_miss(raw_record_key, fetch)?;
+            }
+        }
+        if !has_files(&chap_dir) {
+            bail!(
+                "Chapter {} is not present locally. Obtain it first (e.g., Todd::obtain_relevant_data) \
+                or set config.fetch_on_miss.",
+                chapter_id.interface_id()
+            )
+        }
         // Read each file and collect matching Values
-        let files = fs::read_dir(&chap_dir)
-            .with_context(|| format!("Failed to read dir {:?}", chap_dir))?;
+        let files = dir_paths(&chap_dir)?;
+        let mut matching: VolumeValues<T> = vec![];
+        let mut skipped: Vec<SkippedFile> = vec![];
+        for path in files {
+            match self.matching_values(&path, target_record_key) {
+                Ok(found) => matching.push(found),
+                Err(e) if mode == FindMode::Lenient => {
+                    warn!("Skipping {:?}: {:#}", path, e);
+                    skipped.push(SkippedFile {
+                        path,
+                        reason: format!("{:#}", e),
+                    });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        apply_errata::<T>(&mut matching, errata, target_record_key);
+        let mut ordered = vec![];
+        for (volume_id, values) in matching {
+            ordered.push((volume_id.is_nth()?, volume_id, values));
+        }
+        ordered.sort_by_key(|(n, _, _)| *n);
+        let matching = ordered
+            .into_iter()
+            .map(|(_n, volume_id, values)| (volume_id, values))
+            .collect();
+        Ok((matching, skipped))
+    }
+    /// Obtains the files for a key whose Chapter is absent locally, for [Self::find].
+    ///
+    /// The files are those of [Self::plan_acquisition] for the key (including
+    /// any decoys), obtained one at a time and each verified against its CID.
+    ///
+    /// A file that would take the total over `max_bytes` is not kept, and its
+    /// download stops once that is known.
+    ///
+    /// Downloads run on a new tokio runtime, or on the current runtime if it is
+    /// multi-threaded (see [block_on]).
+    ///
+    /// ## Errors
+    /// If `max_bytes` would be exceeded before all the files are obtained. The
+    /// files obtained so far are kept. If called within a current-thread tokio
+    /// runtime.
+    #[cfg(feature = "net")]
+    fn fetch_on_miss(&self, raw_record_key: &str, fetch: &FetchOnMiss) -> Result<()> {
+        let gateway = fetch.gateway()?;
+        let plan = self.plan_acquisition(&[raw_record_key])?;
+        let _lock = self.write_lock("fetch_on_miss")?;
+        let files: Vec<&PlannedFile> = plan.files_to_download().collect();
+        info!(
+            "Obtaining {} files for {} from {}.",
+            files.len(),
+            raw_record_key,
+            fetch.gateway_url
+        );
+        let mut downloaded: u64 = 0;
+        for (index, file) in files.iter().enumerate() {
+            let url = gateway.fetch_url(&file.cid)?;
+            let remaining = fetch.max_bytes - downloaded;
+            let within = block_on(download_bytes_within(url, remaining))?;
+            let Some(bytes) = within? else {
+                bail!(
+                    "Stopped obtaining files for {} at {} bytes (config.fetch_on_miss.max_bytes), \
+                    with {} of {} files remaining.",
+                    raw_record_key,
+                    downloaded,
+                    files.len() - index,
+                    files.len()
+                )
+            };
+            downloaded += install_verified(&bytes, &file.cid, &file.dest_dir, &file.filename)?;
+        }
+        Ok(())
+    }
+    /// Reads a Chapter file and returns its VolumeId and the RecordValues for the RecordKey.
+    fn matching_values(
+        &self,
+        path: &Path,
+        target_record_key: &T::AssociatedRecordKey,
+    ) -> Result<(T::AssociatedVolumeId, Vec<T::AssociatedRecordValue>)> {
+        debug!("Reading file: {:?}", path);
+        let bytes = self.config.read_chapter_file(path)?;
+        let chapter = <T::AssociatedChapter>::from_file(bytes)
+            .with_context(|| format!("Failed to read/decode file: {:?}", path))?;
         let mut matching: Vec<T::AssociatedRecordValue> = vec![];
-        for filename in files {
-            let path = filename?.path();
-            debug!("Reading file: {:?}", path);
-            let bytes =
-                fs::read(&path).with_context(|| format!("Failed to read file from {:?}", path))?;
-            let chapter = <T::AssociatedChapter>::from_file(bytes)
-                .with_context(|| format!("Failed to read/decode file: {:?}", path))?;
-            let records = chapter.records();
-            for r in records {
-                let key = r.key();
-                if key == &target_record_key {
-                    let val = r.value().clone();
-                    matching.push(val)
+        for r in chapter.records() {
+            if r.key() == target_record_key {
+                matching.push(r.value().clone())
+            }
+        }
+        Ok((chapter.volume_id().clone(), matching))
+    }
+    /// Computes the root of a local Chapter from its Records.
+    ///
+    /// See [crate::utils::merkle] for the construction.
+    pub fn chapter_root(
+        &self,
+        volume_id: &T::AssociatedVolumeId,
+        chapter_id: &T::AssociatedChapterId,
+    ) -> Result<Node> {
+        let chapter = self.read_chapter(volume_id, chapter_id)?;
+        Ok(merkle::root(&record_leaves::<T>(&chapter)?))
+    }
+    /// Creates proofs that the Records for a key are in their local Chapters.
+    ///
+    /// One proof is returned for each matching Record, across all local Volumes.
+    /// A proof is checked with [InclusionProof::verify] using the root from
+    /// [Self::chapter_root].
+    pub fn inclusion_proofs(&self, raw_record_key: &str) -> Result<Vec<InclusionProof>> {
+        let target_record_key = self.record_key(raw_record_key)?;
+        let chapter_id = T::record_key_to_chapter_id(&target_record_key)?;
+        let mut proofs: Vec<InclusionProof> = vec![];
+        for (_path, volume_id) in self.config.parse_all_files_for_chapter::<T>(&chapter_id)? {
+            let chapter = self.read_chapter(&volume_id, &chapter_id)?;
+            let leaves = record_leaves::<T>(&chapter)?;
+            for (index, record) in chapter.records().iter().enumerate() {
+                if record.key() != &target_record_key {
+                    continue;
                 }
+                let branch = merkle::branch(&leaves, index)
+                    .ok_or_else(|| anyhow!("Record index {} out of range.", index))?;
+                proofs.push(InclusionProof {
+                    volume_interface_id: volume_id.interface_id(),
+                    chapter_interface_id: chapter_id.interface_id(),
+                    record: record.as_serialized_bytes()?,
+                    index: index as u64,
+                    record_count: leaves.len() as u64,
+                    branch,
+                })
             }
         }
-        Ok(matching)
+        Ok(proofs)
+    }
+    /// Reads and decodes a local Chapter file.
+    fn read_chapter(
+        &self,
+        volume_id: &T::AssociatedVolumeId,
+        chapter_id: &T::AssociatedChapterId,
+    ) -> Result<T::AssociatedChapter> {
+        let filename = T::AssociatedChapter::new_empty(volume_id, chapter_id).filename();
+        let path = self.config.chapter_dir_path(chapter_id).join(filename);
+        let bytes = self.config.read_chapter_file(&path)?;
+        <T::AssociatedChapter>::from_file(bytes)
+            .with_context(|| format!("Failed to read/decode file: {:?}", path))
     }
+    /// Checks if a key is present using only local data (Chapters and bloom filters).
+    ///
+    /// No network activity occurs, so this can be used to avoid lookups or
+    /// downloads for keys that are not in the database.
+    ///
+    /// ## Algorithm
+    /// For each Chapter file (from the manifest if present, otherwise local files):
+    /// 1. If the Chapter is present locally, it is searched.
+    /// 2. Otherwise, if a bloom filter is present, it is checked.
+    /// 3. Otherwise, nothing is known about the Chapter.
+    pub fn might_contain(&self, raw_record_key: &str) -> Result<Membership<T>> {
+        let target_record_key = self.record_key(raw_record_key)?;
+        let chapter_id = T::record_key_to_chapter_id(&target_record_key)?;
+        let item = bloom_item::<T>(&target_record_key)?;
+
+        let volume_ids: Vec<T::AssociatedVolumeId> = if self.config.manifest_file_path()?.exists() {
+            self.manifest()?
+                .cids()?
+                .into_iter()
+                .filter(|m| m.chapter_id == chapter_id)
+                .map(|m| m.volume_id)
+                .collect()
+        } else {
+            self.inventory()?
+                .into_iter()
+                .filter(|(_, c, _, _)| c == &chapter_id)
+                .map(|(v, _, _, _)| v)
+                .collect()
+        };
+        let chap_dir = self.config.chapter_dir_path(&chapter_id);
+        let mut values: VolumeValues<T> = vec![];
+        let mut maybe = false;
+        for volume_id in &volume_ids {
+            let filename = T::AssociatedChapter::new_empty(volume_id, &chapter_id).filename();
+            let path = chap_dir.join(filename);
+            if path.exists() {
+                values.push(self.matching_values(&path, &target_record_key)?);
+                continue;
+            }
+            match self.bloom_filter(volume_id, &chapter_id)? {
+                Some(filter) => maybe |= filter.contains(&item),
+                None => maybe = true,
+            }
+        }
+        let errata = self.errata_for_chapter(&chapter_id)?;
+        apply_errata::<T>(&mut values, &errata, &target_record_key);
+        let values: Vec<T::AssociatedRecordValue> = values
+            .into_iter()
+            .flat_map(|(_vol, values)| values)
+            .collect();
+        let membership = if !values.is_empty() {
+            Membership::Yes(values)
+        } else if maybe {
+            Membership::Maybe
+        } else {
+            Membership::No
+        };
+        Ok(membership)
+    }
+    /// Reads the current manifest.
     pub fn manifest(&self) -> Result<T::AssociatedManifest> {
+        self.manifest_at(&self.config.manifest_file_path()?)
+    }
+    /// Reads a manifest that is not the current manifest. E.g., an earlier
+    /// manifest (see [Self::manifest_history]) or one obtained from elsewhere.
+    ///
+    /// ## Errors
+    /// A [SchemaMismatch](crate::specs::fingerprint::SchemaMismatch) error if
+    /// the manifest is for the spec version of the library, but was made with
+    /// different Chapter types.
+    pub fn manifest_at(&self, path: &Path) -> Result<T::AssociatedManifest> {
+        let str = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest: {:?}", &path))?;
+        match serde_json::from_str::<T::AssociatedManifest>(&str) {
+            Ok(manifest) => {
+                check_manifest_schema::<T>(&manifest)
+                    .with_context(|| format!("Manifest {:?} does not match this library", path))?;
+                if let Some(successor) = manifest.superseded_by() {
+                    successor.warn_once(manifest.database_interface_id());
+                }
+                Ok(manifest)
+            }
+            Err(e) => {
+                let issues = lint_manifest::<T>(&str, &self.config.data_kind.interface_id())
+                    .unwrap_or_default();
+                let listed: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+                bail!(
+                    "Failed to parse manifest {:?} ({}). Issues: [{}]",
+                    path,
+                    e,
+                    listed.join(", ")
+                )
+            }
+        }
+    }
+    /// Checks the local manifest against the schema for the database.
+    ///
+    /// Returns every problem found, each naming the offending field or entry.
+    /// Publishers can use this before releasing a manifest.
+    pub fn lint_manifest(&self) -> Result<Vec<ManifestIssue>> {
         let path = self.config.manifest_file_path()?;
         let str = fs::read_to_string(&path)
             .with_context(|| format!("Failed to read manifest: {:?}", &path))?;
-        let manifest: T::AssociatedManifest = serde_json::from_str(&str)?;
-        Ok(manifest)
+        lint_manifest::<T>(&str, &self.config.data_kind.interface_id())
+            .with_context(|| format!("Manifest is not valid JSON: {:?}", &path))
+    }
+    /// Summarises what a newer manifest (e.g., just fetched from the publisher)
+    /// adds to the local database, for the given keys.
+    ///
+    /// Nothing is obtained. The summary can be shown to a user before the newer
+    /// manifest replaces the local one. Without a local manifest, everything in
+    /// the newer manifest is new.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<AAISpec> = Todd::init(DataKind::default(), DirNature::Default)?;
+    /// let newer = fs::read_to_string("fetched_manifest.json")?;
+    /// let changes = db.manifest_changes(&newer, &["0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae"])?;
+    /// println!("{}", changes);
+    /// ```
+    ///
+    /// ## Errors
+    /// If either manifest cannot be parsed, or a key is not valid.
+    pub fn manifest_changes(&self, newer_manifest: &str, keys: &[&str]) -> Result<ManifestChanges> {
+        let next: T::AssociatedManifest =
+            serde_json::from_str(newer_manifest).context("Failed to parse the newer manifest")?;
+        let previous = match self.config.manifest_file_path()?.exists() {
+            true => self.manifest()?,
+            false => T::AssociatedManifest::default(),
+        };
+        let mut chapter_ids: Vec<T::AssociatedChapterId> = vec![];
+        for k in keys {
+            let chapter_id = T::record_key_to_chapter_id(&self.record_key(k)?)?;
+            if !chapter_ids.contains(&chapter_id) {
+                chapter_ids.push(chapter_id);
+            }
+        }
+        let mut sizes: Vec<u64> = vec![];
+        if self.config.data_dir.exists() {
+            sizes = self.inventory()?.into_iter().map(|item| item.3).collect();
+        }
+        let average_bytes = match sizes.len() {
+            0 => None,
+            n => Some(sizes.iter().sum::<u64>() / n as u64),
+        };
+        ManifestChanges::new::<T>(&previous, &next, &chapter_ids, average_bytes)
     }
     /// Acquires the parts of the database that a user would be interested in.
     ///
@@ -417,182 +1024,411 @@ impl<T> Todd<T>
     /// locally to determine which Chapters are relevant. Those Chapters
     /// are then downloaded using the CIDs present in the local manifest file.
     ///
+    /// Equivalent to [Self::plan_acquisition] followed by [Self::execute_plan].
+    /// Requires the `net` feature.
+    ///
+    /// If no gateway is given, the mirrors listed in the manifest are used
+    /// (see [Self::execute_plan_from_mirrors]).
+    ///
+    /// Returns the plan, which includes any decoy Chapters that were also
+    /// obtained (see `config.decoy_chapters` and [AcquisitionPlan::anonymity_set]).
+    #[cfg(feature = "net")]
+    pub fn obtain_relevant_data(
+        &self,
+        keys: &[&str],
+        gateway: Option<&Gateway>,
+    ) -> Result<AcquisitionPlan> {
+        let plan = self.plan_acquisition(keys)?;
+        match gateway {
+            Some(gateway) => self.execute_plan(&plan, gateway)?,
+            None => self.execute_plan_from_mirrors(&plan)?,
+        }
+        Ok(plan)
+    }
+    /// Determines which files are required for the given keys, without
+    /// any network activity.
+    ///
     /// ## Algorithm
     ///
     /// 1. Convert the raw keys into ChapterIds.
     /// 2. Go through all the Chapter CIDs in the manifest.
-    /// 3. Keep Chapter CIDs that match the ChapterIds from the raw keys.
-    /// 4. Use the CIDs to download the Chapters and save locally.
-    pub fn obtain_relevant_data(&self, keys: &[&str], gateway: &str) -> Result<()> {
-        warn!("TODO: Manifest should be downloaded by an end user, not sourced locally.");
-
+    /// 3. Choose `config.decoy_chapters` other Chapters in the manifest at random.
+    /// 4. Keep Chapter CIDs that match the ChapterIds from the raw keys or the decoys.
+    /// 5. Note which of those files are already present locally.
+    pub fn plan_acquisition(&self, keys: &[&str]) -> Result<AcquisitionPlan> {
         let mut relevant_chapter_ids: Vec<T::AssociatedChapterId> = vec![];
         for k in keys {
-            let record_key = T::raw_key_as_record_key(k)?;
+            let record_key = self.record_key(k)?;
             let chapter_id = T::record_key_to_chapter_id(&record_key)?;
-            relevant_chapter_ids.push(chapter_id);
+            if !relevant_chapter_ids.contains(&chapter_id) {
+                relevant_chapter_ids.push(chapter_id);
+            }
         }
         let manifest = self.manifest()?;
-        let mut tasks: Vec<DownloadTask> = vec![];
+        let mut candidates: Vec<T::AssociatedChapterId> = vec![];
+        for m in manifest.cids()? {
+            let chapter_id = m.chapter_id;
+            if !relevant_chapter_ids.contains(&chapter_id) && !candidates.contains(&chapter_id) {
+                candidates.push(chapter_id)
+            }
+        }
+        let decoy_chapter_ids = choose_at_random(candidates, self.config.decoy_chapters);
+        let mut plan = AcquisitionPlan {
+            chapters: relevant_chapter_ids
+                .iter()
+                .map(|c| c.interface_id())
+                .collect(),
+            decoys: decoy_chapter_ids.iter().map(|c| c.interface_id()).collect(),
+            files: vec![],
+        };
+        relevant_chapter_ids.extend(decoy_chapter_ids);
         for m in manifest.cids()? {
             if relevant_chapter_ids.contains(&m.chapter_id) {
-                let url = Url::parse(gateway)?.join(&m.cid)?;
                 let dest_dir = self.config.chapter_dir_path(&m.chapter_id);
                 let filename =
                     T::AssociatedChapter::new_empty(&m.volume_id, &m.chapter_id).filename();
-                tasks.push(DownloadTask {
-                    url,
+                let local_bytes = fs::metadata(dest_dir.join(&filename))
+                    .ok()
+                    .map(|meta| meta.len());
+                plan.files.push(PlannedFile {
+                    cid: m.cid,
+                    volume_interface_id: m.volume_id.interface_id(),
+                    chapter_interface_id: m.chapter_id.interface_id(),
                     dest_dir,
                     filename,
+                    local_bytes,
+                })
+            }
+        }
+        for e in manifest.errata_cids()? {
+            if relevant_chapter_ids.contains(&e.chapter_id) {
+                let filename =
+                    T::AssociatedChapter::new_empty(&e.volume_id, &e.chapter_id).filename();
+                let path = self
+                    .config
+                    .errata_file_path::<T>(&e.chapter_id, e.sequence, &filename);
+                let (Some(dest_dir), Some(filename)) = (path.parent(), path.file_name()) else {
+                    bail!("Invalid erratum path {:?}", path)
+                };
+                plan.files.push(PlannedFile {
+                    cid: e.cid,
+                    volume_interface_id: e.volume_id.interface_id(),
+                    chapter_interface_id: e.chapter_id.interface_id(),
+                    dest_dir: dest_dir.to_path_buf(),
+                    filename: filename.to_string_lossy().to_string(),
+                    local_bytes: fs::metadata(&path).ok().map(|meta| meta.len()),
                 })
             }
         }
-        let rt = Runtime::new()?;
-        rt.block_on(download_files(tasks))?;
+        // Requested and decoy Chapters are interleaved when downloaded.
+        plan.files.sort_by(|a, b| {
+            a.chapter_interface_id
+                .cmp(&b.chapter_interface_id)
+                .then(a.volume_interface_id.cmp(&b.volume_interface_id))
+                .then(a.filename.cmp(&b.filename))
+        });
+        Ok(plan)
+    }
+    /// Downloads the files in a plan that are not already present locally.
+    ///
+    /// Requires the `net` feature.
+    #[cfg(feature = "net")]
+    pub fn execute_plan(&self, plan: &AcquisitionPlan, gateway: &Gateway) -> Result<()> {
+        self.config.require_unencrypted("execute_plan")?;
+        let _lock = self.write_lock("execute_plan")?;
+        download_plan(plan, &FileSource::Gateway(gateway.clone()))?;
         info!("TODO: Downloaded data can now be pinned on IPFS to support the network.");
         Ok(())
     }
-    /**
-    Obtains the sample data for the database.
-
-    This includes processed (TODD-compliant) samples and raw samples
-    that can be used to create processed samples.
-
-    Samples may be in the cross-platform path (Directories crate),
-    the local folder (if repo is cloned from GH) or may need
-    to be obtained from a custom source. This method tries each in that
-    order.
-
-    The processed samples may need to be created from the raw samples, which
-    can be slow.
-
-    ## Example
-    ```
-    # use anyhow::Result;
-    # use min_know::{
-    #    config::{address_appearance_index::Network, choices::{DataKind, DirNature}},
-    #    database::types::Todd,
-    #    specs::address_appearance_index::AAISpec,
-    # };
-    let data_kind = DataKind::AddressAppearanceIndex(Network::default());
-    let db: Todd<AAISpec> = Todd::init(data_kind, DirNature::Sample)?;
-    db.get_sample_data()?;
-    # Ok::<(), anyhow::Error>(())
-    ```
-    */
-    pub fn get_sample_data(&self) -> Result<()> {
-        if let DirNature::Sample = self.config.dir_nature {
-        } else {
-            return Err(anyhow!("try to configure the db with DirNature::Sample"));
+    /// Downloads the files in a plan that are not already present locally, from
+    /// the mirrors listed in the manifest.
+    ///
+    /// Requires the `net` feature.
+    ///
+    /// ## Algorithm
+    /// 1. Read the mirrors from the local manifest (see
+    ///    [ManifestMirrors::sources][crate::manifest::mirrors::ManifestMirrors::sources]).
+    ///    If there is more than one, order them by probing each (see [Self::rank_sources]).
+    /// 2. Download the absent files from the first mirror.
+    /// 3. Check the downloaded files against the CIDs in the plan. Files
+    ///    that differ are removed.
+    /// 4. If any files are still absent, repeat with the next mirror.
+    ///
+    /// ## Errors
+    /// If the manifest lists no mirrors, or some files could not be obtained
+    /// from any of them.
+    #[cfg(feature = "net")]
+    pub fn execute_plan_from_mirrors(&self, plan: &AcquisitionPlan) -> Result<()> {
+        self.config.require_unencrypted("execute_plan")?;
+        let _lock = self.write_lock("execute_plan")?;
+        let mut sources = self.manifest()?.mirrors().sources();
+        if sources.is_empty() {
+            bail!("The manifest lists no mirrors. Specify a gateway to obtain the files from.")
         }
-        self.handle_raw_samples()?;
-        self.handle_database_samples()?;
-        Ok(())
+        if sources.len() > 1 {
+            match self.rank_sources(sources.clone()) {
+                Ok(ranked) => sources = ranked.into_iter().map(|(source, _)| source).collect(),
+                Err(e) => warn!("Could not probe mirrors, using the listed order: {:#}", e),
+            }
+        }
+        let mut errors: Vec<String> = vec![];
+        for source in sources {
+            let result = download_plan(plan, &source).and_then(|_| verify_planned_files(plan));
+            match result {
+                Ok(()) => {
+                    info!(
+                        "TODO: Downloaded data can now be pinned on IPFS to support the network."
+                    );
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!(
+                        "Could not obtain all files from mirror {:?}: {:#}",
+                        source, e
+                    );
+                    errors.push(format!("{:#}", e));
+                }
+            }
+        }
+        bail!(
+            "Files could not be obtained from any of the {} mirrors in the manifest: {}",
+            errors.len(),
+            errors.join("; ")
+        )
     }
-    /// Ensures that the unprocessed samples are either present or obtained.
-    fn handle_raw_samples(&self) -> Result<()> {
-        let raw_source_dir = &self.config.raw_source;
-        let local_example_dir_raw = self.config.local_sample_raw_source();
-        let raw_sample_filenames = T::AssociatedSampleObtainer::raw_sample_filenames();
+    /// Downloads a single Chapter file listed in the manifest, verifies it and
+    /// installs it.
+    ///
+    /// Useful for a file found to be missing by [Self::check_completeness].
+    /// Requires the `net` feature.
+    ///
+    /// ## Algorithm
+    /// 1. Look up the CID for the Volume and Chapter in the manifest.
+    /// 2. If the local file already has that CID, nothing is downloaded.
+    /// 3. Otherwise, download the file and check that it has the CID.
+    /// 4. Write the file alongside the destination, then move it into place.
+    ///
+    /// ## Errors
+    /// If the Chapter is not in the manifest, or the downloaded file has a different CID.
+    /// A local file is only changed once a verified file has been downloaded.
+    #[cfg(feature = "net")]
+    pub fn obtain_chapter(
+        &self,
+        volume_id: &T::AssociatedVolumeId,
+        chapter_id: &T::AssociatedChapterId,
+        gateway: &Gateway,
+    ) -> Result<ObtainedChapter> {
+        self.config.require_unencrypted("obtain_chapter")?;
+        let _lock = self.write_lock("obtain_chapter")?;
+        let Some(cid) = self
+            .manifest()?
+            .cids()?
+            .into_iter()
+            .find(|m| &m.volume_id == volume_id && &m.chapter_id == chapter_id)
+            .map(|m| m.cid)
+        else {
+            bail!(
+                "Volume {} Chapter {} is not in the manifest.",
+                volume_id.interface_id(),
+                chapter_id.interface_id()
+            )
+        };
+        let dest_dir = self.config.chapter_dir_path(chapter_id);
+        let filename = T::AssociatedChapter::new_empty(volume_id, chapter_id).filename();
+        let path = dest_dir.join(&filename);
+        let local_matches = match fs::read(&path) {
+            Ok(bytes) => Some(cid_v0_string_from_bytes(&bytes)? == cid),
+            Err(_) => None,
+        };
+        if local_matches == Some(true) {
+            return Ok(ObtainedChapter::AlreadyPresent(path));
+        }
+        let bytes = download_verified(gateway, &cid, &dest_dir, &filename)?;
+        Ok(match local_matches {
+            Some(_) => ObtainedChapter::Replaced { path, bytes },
+            None => ObtainedChapter::Downloaded { path, bytes },
+        })
+    }
+}
+
+/// Downloads a file, checks that it has the CID, then installs it.
+///
+/// The file is written alongside the destination, then moved into place.
+/// Returns the size of the file.
+#[cfg(feature = "net")]
+fn download_verified(gateway: &Gateway, cid: &str, dest_dir: &Path, filename: &str) -> Result<u64> {
+    let url = gateway.fetch_url(cid)?;
+    let rt = Runtime::new()?;
+    let downloaded = rt.block_on(download_bytes(url))?;
+    install_verified(&downloaded, cid, dest_dir, filename)
+}
 
-        if raw_source_dir.contains_files(&raw_sample_filenames)? {
-            info!("Checking raw sample files: already present.");
-            return Ok(());
+/// Checks that downloaded bytes have the CID, then installs them as for
+/// [download_verified].
+#[cfg(feature = "net")]
+fn install_verified(downloaded: &[u8], cid: &str, dest_dir: &Path, filename: &str) -> Result<u64> {
+    let found = cid_v0_string_from_bytes(downloaded)?;
+    if found != cid {
+        bail!(
+            "Downloaded file {} has CID {}, but the manifest has {}.",
+            filename,
+            found,
+            cid
+        )
+    }
+    fs::create_dir_all(dest_dir)?;
+    let path = dest_dir.join(filename);
+    let partial = dest_dir.join(format!("{}.partial", filename));
+    fs::write(&partial, downloaded)
+        .with_context(|| format!("Unable to write file {:?}", partial))?;
+    fs::rename(&partial, &path)
+        .with_context(|| format!("Unable to move {:?} to {:?}", partial, path))?;
+    let bytes = downloaded.len() as u64;
+    info!("Obtained {} ({} bytes).", filename, bytes);
+    Ok(bytes)
+}
+
+/// Downloads the files in a plan that are not present locally from a source.
+#[cfg(feature = "net")]
+pub(crate) fn download_plan(plan: &AcquisitionPlan, source: &FileSource) -> Result<()> {
+    let mut tasks: Vec<DownloadTask> = vec![];
+    for file in plan.files_to_download() {
+        tasks.push(DownloadTask {
+            url: source.file_url(file)?,
+            dest_dir: file.dest_dir.clone(),
+            filename: file.filename.clone(),
+        })
+    }
+    let rt = Runtime::new()?;
+    rt.block_on(download_files(tasks))
+}
+
+/// Checks that the downloaded files of a plan have the CIDs in the plan.
+///
+/// Files with a different CID are removed.
+#[cfg(feature = "net")]
+pub(crate) fn verify_planned_files(plan: &AcquisitionPlan) -> Result<()> {
+    let mut mismatched: Vec<&str> = vec![];
+    for file in plan.files_to_download() {
+        let path = file.dest_dir.join(&file.filename);
+        let bytes = fs::read(&path).with_context(|| format!("Unable to read file {:?}", path))?;
+        if cid_v0_string_from_bytes(&bytes)? != file.cid {
+            fs::remove_file(&path)?;
+            mismatched.push(&file.filename);
         }
+    }
+    if !mismatched.is_empty() {
+        bail!(
+            "Downloaded files do not match the CIDs in the manifest: {}",
+            mismatched.join(", ")
+        )
+    }
+    Ok(())
+}
 
-        if local_example_dir_raw.contains_files(&raw_sample_filenames)? {
-            info!("Raw sample files found in local repository: moving to samples directory.");
-            local_example_dir_raw.copy_into_recursive(raw_source_dir)?;
-        } else {
-            info!("Raw samples not found: downloading.");
-            T::AssociatedSampleObtainer::get_raw_samples(raw_source_dir)?
+/// Whether a directory exists and has at least one entry, other than entries
+/// left by other programs (e.g., ".DS_Store").
+fn has_files(dir: &Path) -> bool {
+    dir_paths(dir).is_ok_and(|paths| !paths.is_empty())
+}
+
+/// A temporary directory that is removed when dropped.
+#[derive(Debug, PartialEq)]
+struct EphemeralDir(PathBuf);
+
+impl Drop for EphemeralDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.0) {
+            warn!("Could not remove ephemeral directory {:?}: {}", self.0, e);
         }
-        Ok(())
     }
-    /// Ensures that the processed samples are either present or obtained.
-    ///
-    /// First looks in the expected location, then looks in the local
-    /// directory (and copies if present), then attempts to processes from raw
-    /// data.
-    fn handle_database_samples(&self) -> Result<()> {
-        let example_dir_processed = self.config.local_sample_data_dir();
+}
 
-        let volume_interface_ids = T::AssociatedSampleObtainer::sample_volumes();
+/// The merkle leaves for the Records of a Chapter, in order.
+fn record_leaves<T: DataSpec>(chapter: &T::AssociatedChapter) -> Result<Vec<Node>> {
+    chapter
+        .records()
+        .iter()
+        .map(|r| Ok(merkle::leaf(&r.as_serialized_bytes()?)))
+        .collect()
+}
 
-        let Some(volume_interface_ids) = volume_interface_ids else {
-            info!("No sample filenames provided: creating samples from raw data.");
-            self.full_transformation()?;
-            return Ok(())
-        };
-        let volume_ids = volume_interface_ids
-            .iter()
-            .map(|x| T::AssociatedVolumeId::from_interface_id(x))
-            .collect::<Result<Vec<T::AssociatedVolumeId>>>();
+/// The bytes of a RecordKey that are inserted into a bloom filter.
+pub(crate) fn bloom_item<T: DataSpec>(key: &T::AssociatedRecordKey) -> Result<Vec<u8>> {
+    Ok(key.summary_string()?.into_bytes())
+}
 
-        let Ok(volume_ids) = volume_ids else {
-                warn!("Couldn't derive VolumeId from provided interface id: skipping check for existing samples.");
-                self.full_transformation()?;
-                return Ok(())
-            };
-        // Prepare an ID for every chapter (directory_name, filenames)
-        let mut dirnames_and_files: Vec<(String, Vec<String>)> = vec![];
-        for i in 0..T::NUM_CHAPTERS {
-            let Ok(chapter_id) = T::AssociatedChapterId::nth_id(i as u32) else {
-                warn!("Couldn't derive nth ChapterId: skipping check for existing samples.");
-                self.full_transformation()?;
-                return Ok(())
-            };
-            let mut filenames: Vec<String> = vec![];
-            for volume_id in &volume_ids {
-                let filename = T::AssociatedChapter::new_empty(volume_id, &chapter_id).filename();
-                filenames.push(filename);
-            }
-            dirnames_and_files.push((chapter_id.interface_id(), filenames));
-        }
-        // Check expected location.
-        let mut data_dir_complete = true;
-        for (dirname, filenames) in &dirnames_and_files {
-            let chap_dir = self.config.data_dir.join(dirname);
-            // Detect if any of the sample files are missing.
-            if !chap_dir.contains_files(filenames)? {
-                info!(
-                    "The {} dir was missing one of: {:?}",
-                    chap_dir.display(),
-                    filenames
-                );
-                data_dir_complete = false
-            }
-        }
-        if data_dir_complete {
-            info!("Sample directory already contains all database samples.");
-            return Ok(());
-        }
-        // Check local directory.
-        let mut local_data_dir_complete = true;
-        for (dirname, filenames) in &dirnames_and_files {
-            let chap_dir = example_dir_processed.join(dirname);
-            // Detect if any of the sample files are missing.
-            if !chap_dir.contains_files(filenames)? {
-                local_data_dir_complete = false
-            }
-        }
-        if local_data_dir_complete {
-            info!("Local directory has sample files: copying to samples directory.");
-            for (dirname, _filenames) in &dirnames_and_files {
-                let src_chap_dir = example_dir_processed.join(dirname);
-                let dest_chap_dir = self.config.data_dir.join(dirname);
-                src_chap_dir.copy_into_recursive(&dest_chap_dir)?;
-            }
-            return Ok(());
-        } else {
-            info!("Local directory does not contain sample files: creating from raw data.");
-            self.full_transformation()?;
+/// Whether a key is in the database, as determined from local data.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Membership<T: DataSpec> {
+    /// All relevant Chapters were searched or excluded by bloom filters.
+    No,
+    /// Some relevant Chapters are not present locally and may contain the key.
+    Maybe,
+    /// The key is present in local Chapters, with the values found.
+    Yes(Vec<T::AssociatedRecordValue>),
+}
+
+/// How [Todd::find_with] treats Chapter files that cannot be read or decoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FindMode {
+    /// Stop with an error naming the file.
+    #[default]
+    Strict,
+    /// Skip the file and record it in the result.
+    Lenient,
+}
+
+/// The values found by [Todd::find_with].
+#[derive(Clone, Debug, PartialEq)]
+pub struct FindResult<T: DataSpec> {
+    pub values: Vec<T::AssociatedRecordValue>,
+    /// Files that were skipped ([FindMode::Lenient] only). If not empty,
+    /// the values may be incomplete.
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// A Chapter file skipped by [Todd::find_with], with the reason.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// A file in a Chapter directory that is not in the manifest.
+///
+/// The serialized form follows the spec types. See
+/// [OrphanFileEntry][crate::database::report::OrphanFileEntry] for a stable form.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OrphanFile<T: DataSpec> {
+    /// A Chapter file for a VolumeId/ChapterId pair that is not in the manifest.
+    NotInManifest(T::AssociatedVolumeId, T::AssociatedChapterId, PathBuf),
+    /// A file that is not named like a Chapter file.
+    Unknown(PathBuf),
+}
+
+impl<T: DataSpec> OrphanFile<T> {
+    pub fn path(&self) -> &Path {
+        match self {
+            OrphanFile::NotInManifest(_, _, path) => path,
+            OrphanFile::Unknown(path) => path,
+        }
+    }
+}
+
+impl<T: DataSpec> std::fmt::Display for OrphanFile<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrphanFile::NotInManifest(..) => write!(f, "{:?} (not in manifest)", self.path()),
+            OrphanFile::Unknown(path) => write!(f, "{:?} (not a Chapter file)", path),
         }
-        Ok(())
     }
 }
 
 /// A file that is in a given manifest, but not available for some reason.
+///
+/// The serialized form follows the spec types. See
+/// [AbsentFileEntry][crate::database::report::AbsentFileEntry] for a stable form.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum AbsentFile<T: DataSpec> {
     DifferentHash(T::AssociatedVolumeId, T::AssociatedChapterId),
@@ -603,6 +1439,8 @@ pub enum AbsentFile<T: DataSpec> {
 ///
 /// Files are considered absent if they are present in the manifest and
 /// absent in the file system.
+///
+/// For external tools, [Self::to_report] gives a stable serializable form.
 #[derive(Clone, Debug, PartialEq)]
 pub struct CompletenessAudit<T: DataSpec> {
     /// VolumeIds in the Manifest that do not appear anywhere in the file system.
@@ -613,11 +1451,68 @@ pub struct CompletenessAudit<T: DataSpec> {
     ///
     /// Excludes files that are absent as part of a missing set of ChapterId/VolumeId.
     pub absent_individual_files: Vec<AbsentFile<T>>,
+    /// Files in the Chapter directories that are not in the manifest.
+    pub orphan_files: Vec<OrphanFile<T>>,
+    /// Number of files in the manifest, excluding pruned Volumes.
+    pub files_in_manifest: usize,
+    /// Number of files in the manifest that are present with the manifest CID.
+    pub files_verified: usize,
+    /// Total size of the verified files.
+    pub bytes_verified: u64,
+    /// Upstream source and license stated in the manifest.
+    pub provenance: ManifestProvenance,
+    /// The database that replaces this one, if it is deprecated.
+    pub superseded_by: Option<ManifestSuccessor>,
+    /// The files hashed by the audit and the time taken.
+    pub throughput: HashThroughput,
 }
 
 impl<T: DataSpec> CompletenessAudit<T> {
-    fn missing_chapters(&self) -> Result<&[(&T::AssociatedVolumeId, &T::AssociatedChapterId)]> {
-        todo!()
+    /// Percentage of the files in the manifest that are verified (100 for an
+    /// empty manifest).
+    pub fn percent_complete(&self) -> f64 {
+        percent_of(self.files_verified, self.files_in_manifest)
+    }
+    /// The Chapters in the manifest that the audit found absent, in manifest order.
+    ///
+    /// Combines the absent ChapterIds, absent VolumeIds and absent individual files.
+    /// Volumes removed by [Todd::prune] (recorded in the holdings) are excluded.
+    #[cfg(feature = "publisher")]
+    pub(crate) fn missing_chapters(
+        &self,
+        manifest_cids: &[ManifestCids<T>],
+        holdings: &Holdings,
+    ) -> Vec<(T::AssociatedVolumeId, T::AssociatedChapterId)> {
+        let chapters: HashSet<String> = self
+            .absent_chapter_ids
+            .iter()
+            .map(|c| c.interface_id())
+            .collect();
+        let volumes: HashSet<String> = self
+            .absent_volume_ids
+            .iter()
+            .map(|v| v.interface_id())
+            .collect();
+        let files: HashSet<(String, String)> = self
+            .absent_individual_files
+            .iter()
+            .map(|absent| match absent {
+                AbsentFile::DifferentHash(v, c) | AbsentFile::NoFile(v, c) => {
+                    (v.interface_id(), c.interface_id())
+                }
+            })
+            .collect();
+        manifest_cids
+            .iter()
+            .filter(|m| {
+                let file_key = (m.volume_id.interface_id(), m.chapter_id.interface_id());
+                !holdings.is_pruned(&file_key.0)
+                    && (chapters.contains(&file_key.1)
+                        || volumes.contains(&file_key.0)
+                        || files.contains(&file_key))
+            })
+            .map(|m| (m.volume_id.clone(), m.chapter_id.clone()))
+            .collect()
     }
 }
 
@@ -625,19 +1520,22 @@ impl<T: DataSpec> std::fmt::Display for CompletenessAudit<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{} missing ChapterIds, {} missing VolumeIds and {} missing individual files",
-            self.absent_volume_ids.len(),
+            "{} of {} files verified ({:.1}% complete, {} bytes). {} absent ChapterIds, {} absent VolumeIds, {} absent individual files and {} orphan files",
+            self.files_verified,
+            self.files_in_manifest,
+            self.percent_complete(),
+            self.bytes_verified,
             self.absent_chapter_ids.len(),
-            self.absent_individual_files.len()
-        )
-    }
-}
-
-/// Logs a counter with a message every time the count reaches a threshold.
-fn log_count(count: Arc<Mutex<u32>>, total: u32, message: &str, threshold: u32) {
-    let mut c = count.lock().unwrap();
-    *c += 1;
-    if *c % threshold == 0 {
-        info!("{} {} of {}", message, c, total)
+            self.absent_volume_ids.len(),
+            self.absent_individual_files.len(),
+            self.orphan_files.len()
+        )?;
+        if !self.provenance.is_empty() {
+            write!(f, ". {}", self.provenance)?;
+        }
+        if let Some(successor) = &self.superseded_by {
+            write!(f, ". {}", successor)?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,168 @@
+//! A record of the CIDs of local Chapter files, so that audits only hash
+//! files that have changed.
+//!
+//! A file is identified by its path, size and modification time. If these
+//! match the record, the recorded CID is used. A file changed without
+//! changing its size or modification time is not detected, so audits can
+//! be forced to hash every file (see
+//! [Todd::check_completeness_forced][crate::database::types::Todd::check_completeness_forced]).
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Instant, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::report::HashThroughput,
+    utils::ipfs::{cid_v0_string_from_file, DEFAULT_HASH_BUFFER_BYTES},
+};
+
+/// A file whose CID has been computed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct VerifiedFile {
+    bytes: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+    cid: String,
+}
+
+/// The CIDs of files, stored alongside the manifest.
+///
+/// Only the files looked up since the cache was read are written back, so
+/// files that are removed drop out of the cache.
+#[derive(Debug, Default)]
+pub(crate) struct VerificationCache {
+    previous: HashMap<PathBuf, VerifiedFile>,
+    current: HashMap<PathBuf, VerifiedFile>,
+    /// Number of lookups that used a recorded CID.
+    pub(crate) unchanged: usize,
+    /// Size of the chunks that files are read in. None means the default.
+    buffer_bytes: Option<usize>,
+    /// Files read and hashed since the cache was read (`total_ms` is not set).
+    pub(crate) hashed: HashThroughput,
+}
+
+impl VerificationCache {
+    /// Reads the cache file. An absent or unreadable file gives an empty cache.
+    pub(crate) fn read(path: &Path) -> Self {
+        let previous = match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable verification cache {:?}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        VerificationCache {
+            previous,
+            ..Default::default()
+        }
+    }
+    /// Reads files in chunks of the given size (`config.hash_buffer_bytes`)
+    /// rather than the default.
+    pub(crate) fn with_buffer(mut self, buffer_bytes: Option<usize>) -> Self {
+        self.buffer_bytes = buffer_bytes;
+        self
+    }
+    /// Writes the files looked up to the cache file.
+    pub(crate) fn write(&self, path: &Path) -> Result<()> {
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_string(&self.current)?)
+            .with_context(|| format!("Failed to write verification cache {:?}", partial))?;
+        fs::rename(&partial, path)
+            .with_context(|| format!("Unable to move {:?} to {:?}", partial, path))
+    }
+    /// The CID of a file.
+    ///
+    /// The file is only read if it differs from the record, or if `force` is set.
+    pub(crate) fn cid_of(&mut self, path: &Path, force: bool) -> Result<String> {
+        let meta = fs::metadata(path).with_context(|| format!("Unable to read file {:?}", path))?;
+        let modified = meta.modified()?.duration_since(UNIX_EPOCH)?;
+        let (modified_secs, modified_nanos) = (modified.as_secs(), modified.subsec_nanos());
+        if let Some(known) = self.previous.get(path) {
+            let unchanged = known.bytes == meta.len()
+                && known.modified_secs == modified_secs
+                && known.modified_nanos == modified_nanos;
+            if unchanged && !force {
+                self.unchanged += 1;
+                self.current.insert(path.to_path_buf(), known.clone());
+                return Ok(known.cid.clone());
+            }
+        }
+        let start = Instant::now();
+        let buffer_bytes = self.buffer_bytes.unwrap_or(DEFAULT_HASH_BUFFER_BYTES);
+        let (cid, bytes_read) = cid_v0_string_from_file(path, buffer_bytes)?;
+        self.hashed.files_hashed += 1;
+        self.hashed.bytes_hashed += bytes_read;
+        self.hashed.hashing_ms += start.elapsed().as_millis() as u64;
+        self.current.insert(
+            path.to_path_buf(),
+            VerifiedFile {
+                bytes: meta.len(),
+                modified_secs,
+                modified_nanos,
+                cid: cid.clone(),
+            },
+        );
+        Ok(cid)
+    }
+}
+
+#[test]
+fn unchanged_files_not_hashed_again() {
+    use std::time::{Duration, SystemTime};
+
+    use crate::utils::ipfs::cid_v0_string_from_bytes;
+
+    let dir = std::env::temp_dir().join("min_know_test_verification_cache");
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("chapter.ssz");
+    let cache_path = dir.join("verified.json");
+    fs::write(&file, b"first").unwrap();
+    let expected = cid_v0_string_from_bytes(b"first").unwrap();
+
+    let mut cache = VerificationCache::read(&cache_path).with_buffer(Some(2));
+    assert_eq!(cache.cid_of(&file, false).unwrap(), expected);
+    assert_eq!(cache.unchanged, 0);
+    assert_eq!(cache.hashed.files_hashed, 1);
+    assert_eq!(cache.hashed.bytes_hashed, 5);
+    cache.write(&cache_path).unwrap();
+
+    let mut cache = VerificationCache::read(&cache_path);
+    assert_eq!(cache.cid_of(&file, false).unwrap(), expected);
+    assert_eq!(cache.unchanged, 1);
+    assert_eq!(cache.hashed.files_hashed, 0);
+
+    // Same size and modification time: only detected when forced.
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    fs::write(&file, b"other").unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    let mut cache = VerificationCache::default();
+    cache.cid_of(&file, false).unwrap();
+    fs::write(&file, b"third").unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    cache.previous = cache.current.clone();
+    assert_eq!(
+        cache.cid_of(&file, false).unwrap(),
+        cid_v0_string_from_bytes(b"other").unwrap()
+    );
+    assert_eq!(
+        cache.cid_of(&file, true).unwrap(),
+        cid_v0_string_from_bytes(b"third").unwrap()
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
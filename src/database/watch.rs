@@ -0,0 +1,171 @@
+//! Noticing changes made to a database by other processes.
+//!
+//! A long-running query server can watch a database that a publisher process
+//! extends (e.g., by adding Volumes to the data directory and replacing the
+//! manifest). Requires the `watch` feature.
+//!
+//! Queries read the manifest and Chapter files when they run, so they see new
+//! data without a watcher. The watcher invalidates what is held between queries
+//! (the open compiled index and the query cache) and tells the integrator what changed, so that
+//! derived state (e.g., coverage shown to users) can be refreshed.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{
+    database::types::Todd,
+    specs::traits::{ChapterIdMethods, DataSpec},
+};
+
+/// A change to a watched database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DatabaseChange {
+    /// The manifest was written, replaced or removed.
+    Manifest,
+    /// Files in a Chapter directory were added, changed or removed.
+    Chapter {
+        /// E.g., "chapter_0x4e".
+        chapter_interface_id: String,
+        paths: Vec<PathBuf>,
+    },
+}
+
+/// Watches a database until dropped. Created by [Todd::watch].
+#[derive(Debug)]
+pub struct DatabaseWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl<T> Todd<T>
+where
+    T: DataSpec + Default + Sync,
+{
+    /// Calls `on_change` when the manifest or Chapter files of the database
+    /// change, until the returned watcher is dropped. Requires the `watch` feature.
+    ///
+    /// The callback runs on a thread of the watcher. A single write may be
+    /// reported more than once. Partial files written during downloads and
+    /// builds are not reported until they are moved into place.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Default)?;
+    /// let _watcher = db.watch(|change| println!("Database changed: {:?}", change))?;
+    /// ```
+    ///
+    /// ## Errors
+    /// If the directory of the database cannot be watched.
+    pub fn watch<F>(&self, mut on_change: F) -> Result<DatabaseWatcher>
+    where
+        F: FnMut(DatabaseChange) + Send + 'static,
+    {
+        let manifest_path = self.config.manifest_file_path()?;
+        let data_dir = self.config.data_dir.clone();
+        #[cfg(feature = "compiled-index")]
+        let compiled_index = self.compiled_index.clone();
+        let query_cache = self.query_cache.invalidation();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("Error watching database: {}", e);
+                    return;
+                }
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+            for change in changes_of::<T>(&event.paths, &manifest_path, &data_dir) {
+                debug!("Database change: {:?}", change);
+                #[cfg(feature = "compiled-index")]
+                compiled_index.invalidate();
+                query_cache.invalidate();
+                on_change(change);
+            }
+        })?;
+        let base = &self.config.base_dir_nature_dependent;
+        watcher
+            .watch(base, RecursiveMode::Recursive)
+            .with_context(|| format!("Unable to watch {:?}", base))?;
+        Ok(DatabaseWatcher { _watcher: watcher })
+    }
+}
+
+/// The changes that a set of paths affected by one event represent.
+///
+/// Paths that are not the manifest or in a Chapter directory are ignored.
+fn changes_of<T: DataSpec>(
+    paths: &[PathBuf],
+    manifest_path: &Path,
+    data_dir: &Path,
+) -> Vec<DatabaseChange> {
+    let mut changes: Vec<DatabaseChange> = vec![];
+    for path in paths {
+        if path.extension().is_some_and(|ext| ext == "partial") {
+            continue;
+        }
+        if path == manifest_path {
+            if !changes.contains(&DatabaseChange::Manifest) {
+                changes.push(DatabaseChange::Manifest);
+            }
+            continue;
+        }
+        let Some(chapter_dir) = path
+            .strip_prefix(data_dir)
+            .ok()
+            .and_then(|relative| relative.components().next())
+            .map(|dir| data_dir.join(dir))
+        else {
+            continue;
+        };
+        let Ok(chapter_id) = T::AssociatedChapterId::from_chapter_directory(&chapter_dir) else {
+            continue;
+        };
+        let chapter_interface_id = chapter_id.interface_id();
+        let existing = changes.iter_mut().find_map(|change| match change {
+            DatabaseChange::Chapter {
+                chapter_interface_id: id,
+                paths,
+            } if id == &chapter_interface_id => Some(paths),
+            _ => None,
+        });
+        match existing {
+            Some(paths) => paths.push(path.clone()),
+            None => changes.push(DatabaseChange::Chapter {
+                chapter_interface_id,
+                paths: vec![path.clone()],
+            }),
+        }
+    }
+    changes
+}
+
+#[test]
+fn paths_sorted_into_changes() {
+    use crate::specs::nametags::NameTagsSpec;
+
+    let base = PathBuf::from("/db");
+    let manifest = base.join("nametags_manifest.json");
+    let data_dir = base.join("nametags");
+    let chapter = data_dir.join("addresses_0x4e");
+    let paths = vec![
+        manifest.clone(),
+        base.join("nametags.lock"),
+        chapter.join("a.ssz_snappy"),
+        chapter.join("b.ssz_snappy.partial"),
+        chapter.join("b.ssz_snappy"),
+        data_dir.join("notes.txt"),
+    ];
+    assert_eq!(
+        changes_of::<NameTagsSpec>(&paths, &manifest, &data_dir),
+        vec![
+            DatabaseChange::Manifest,
+            DatabaseChange::Chapter {
+                chapter_interface_id: "addresses_0x4e".to_string(),
+                paths: vec![chapter.join("a.ssz_snappy"), chapter.join("b.ssz_snappy")],
+            },
+        ]
+    );
+}
@@ -1,118 +1,729 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
-    path::Path,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fs::{self, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
 use ssz_rs::{List, Vector};
 
 use crate::{
     parameters::address_appearance_index::BLOCKS_PER_VOLUME,
     specs::address_appearance_index::{
-        AAIAppearanceTx, AAIChapter, AAIChapterId, AAISpec, AAIVolumeId, RelicAddressAppearances,
-        RelicChapter, RelicVolumeIdentifier,
+        AAIAppearanceTx, AAIChapter, AAIChapterId, AAIRecord, AAIRecordKey, AAIRecordValue,
+        AAISpec, AAIVolumeId,
     },
     utils::unchained::{
-        files::{ChunkFile, ChunksDir},
-        structure::TransactionId,
+        constants::{ADDR, AP_ENTRY},
+        files::{file_structure, ChunkFile, ChunksDir},
+        structure::{AddressData, Header, TransactionId},
         types::{BlockRange, UnchainedFile},
     },
 };
 
-use super::traits::ExtractorMethods;
+use super::{
+    chifra_export::{validate_exports, AppearanceExports},
+    limits::OversizePolicy,
+    traits::{ExtractedChapter, ExtractorMethods, RawSourceFormat},
+    validation::ValidationReport,
+};
 
 pub struct AAIExtractor {}
 
+/// A scanned raw source for the address-appearance-index.
+pub enum AAISource {
+    /// The chunk files, with the block range of each parsed from the filename.
+    Chunks(ChunksDir),
+    /// Appearance lists exported by chifra (see [chifra_export][super::chifra_export]).
+    Exports(AppearanceExports),
+}
+
 impl ExtractorMethods<AAISpec> for AAIExtractor {
-    fn chapter_from_raw(
+    type Source = AAISource;
+
+    fn scan_source(source_dir: &Path) -> Result<AAISource> {
+        Ok(AAISource::Chunks(ChunksDir::new(source_dir)?))
+    }
+    fn scan_source_as(source_dir: &Path, format: RawSourceFormat) -> Result<AAISource> {
+        match format {
+            RawSourceFormat::Native => Self::scan_source(source_dir),
+            RawSourceFormat::ChifraExport => {
+                Ok(AAISource::Exports(AppearanceExports::new(source_dir)?))
+            }
+        }
+    }
+    fn chapter_from_source(
         chapter_id: &AAIChapterId,
         volume_id: &AAIVolumeId,
-        source_dir: &Path,
-    ) -> Result<Option<AAIChapter>> {
-        // Get relevant raw files.
-        let chunk_files: ChunksDir = ChunksDir::new(source_dir)?;
-        let block_range = volume_id.to_block_range()?;
-        let Some(relevant_files) = chunk_files.for_range(&block_range) else {
-            return Ok(None)
+        source: &AAISource,
+        skip_corrupt: bool,
+        _oversize: OversizePolicy,
+    ) -> Result<ExtractedChapter<AAISpec>> {
+        let chapter = match source {
+            AAISource::Chunks(chunks) => {
+                chapter_from_chunks(chapter_id, volume_id, chunks, strictness(skip_corrupt))?
+            }
+            AAISource::Exports(exports) => {
+                let prefix = chapter_id.val[0];
+                chapter_from_exports(chapter_id, volume_id, exports, |a| a[0] == prefix)?
+            }
+        };
+        Ok((chapter, vec![]))
+    }
+    /// Reads the appearances of each address with a binary search of each chunk
+    /// file, rather than reading every address in the Chapter.
+    fn chapter_from_source_for_keys(
+        chapter_id: &AAIChapterId,
+        volume_id: &AAIVolumeId,
+        source: &AAISource,
+        keys: &[AAIRecordKey],
+        skip_corrupt: bool,
+        _oversize: OversizePolicy,
+    ) -> Result<ExtractedChapter<AAISpec>> {
+        let addresses: Vec<Vec<u8>> = keys.iter().map(|k| k.key.to_vec()).collect();
+        let chunks = match source {
+            AAISource::Chunks(chunks) => chunks,
+            AAISource::Exports(exports) => {
+                let filter = |a: &[u8]| addresses.iter().any(|k| k.as_slice() == a);
+                let chapter = chapter_from_exports(chapter_id, volume_id, exports, filter)?;
+                return Ok((chapter, vec![]));
+            }
+        };
+        let block_range = volume_id.block_range()?;
+        let Some(relevant_files) = chunks.for_range(&block_range) else {
+            return Ok((None, vec![]));
+        };
+        let (chapter, stats) = get_appearances_for_addresses(
+            relevant_files,
+            block_range,
+            chapter_id,
+            &addresses,
+            strictness(skip_corrupt),
+        )?;
+        debug!(
+            "Chunk parsing for {} watched addresses in {:?}: {}",
+            addresses.len(),
+            block_range,
+            stats
+        );
+        Ok((Some(chapter), vec![]))
+    }
+    /// Reads each chunk file once, sorting the appearances into all the Chapters
+    /// at the same time.
+    fn volume_from_source(
+        volume_id: &AAIVolumeId,
+        chapter_ids: &[AAIChapterId],
+        source: &AAISource,
+        skip_corrupt: bool,
+        oversize: OversizePolicy,
+    ) -> Result<Vec<ExtractedChapter<AAISpec>>> {
+        let AAISource::Chunks(chunks) = source else {
+            return chapter_ids
+                .iter()
+                .map(|c| Self::chapter_from_source(c, volume_id, source, skip_corrupt, oversize))
+                .collect();
+        };
+        let chapters = chapters_in_single_pass(
+            volume_id,
+            chapter_ids,
+            chunks,
+            strictness(skip_corrupt),
+            MAX_BUFFERED_APPEARANCES,
+        )?;
+        Ok(chapters.into_iter().map(|c| (c, vec![])).collect())
+    }
+    /// Reads each chunk file that intersects the Volumes once, sorting the
+    /// appearances of the Chapter into the Volumes at the same time.
+    fn chapter_across_volumes(
+        chapter_id: &AAIChapterId,
+        volume_ids: &[AAIVolumeId],
+        source: &AAISource,
+        skip_corrupt: bool,
+        oversize: OversizePolicy,
+    ) -> Result<Vec<ExtractedChapter<AAISpec>>> {
+        let AAISource::Chunks(chunks) = source else {
+            return volume_ids
+                .iter()
+                .map(|v| Self::chapter_from_source(chapter_id, v, source, skip_corrupt, oversize))
+                .collect();
+        };
+        let chapters =
+            chapter_in_single_pass(chapter_id, volume_ids, chunks, strictness(skip_corrupt))?;
+        Ok(chapters.into_iter().map(|c| (c, vec![])).collect())
+    }
+    fn latest_volume_in_source(source: &AAISource) -> Result<AAIVolumeId> {
+        let latest_block = match source {
+            AAISource::Chunks(chunks) => latest_block_in_chunks(chunks)?,
+            AAISource::Exports(exports) => exports.latest_block()?,
         };
-        // Get appearances from files.
-        let leading_char = hex::encode(&chapter_id.val);
-        // This (RelicChapter->AAIChapter) is a workaround to use existing code.
-        // Ideally get_relevant_appearances() returns AAIChapter directly.
-        let relic_chapter: RelicChapter =
-            get_relevant_appearances(relevant_files, block_range, &leading_char)?;
-        let chapter = AAIChapter::from_relic(relic_chapter);
-        Ok(Some(chapter))
-    }
-    fn latest_possible_volume(source_dir: &Path) -> Result<AAIVolumeId> {
-        let chunk_files: ChunksDir = ChunksDir::new(source_dir)?;
         Ok(AAIVolumeId {
-            oldest_block: latest_full_volume(latest_block_in_chunks(&chunk_files)?)?,
+            oldest_block: latest_full_volume(latest_block)?,
         })
     }
+    /// Checks that each chunk file has a valid header and the size that the
+    /// header describes, and that the chunks cover the blocks without gaps.
+    fn validate_raw(source_dir: &Path) -> Result<ValidationReport> {
+        let chunk_files: ChunksDir = ChunksDir::new(source_dir)?;
+        let mut report = ValidationReport::default();
+        let mut previous: Option<BlockRange> = None;
+        for chunk in &chunk_files.paths {
+            report.files_checked += 1;
+            if let Err(e) = check_chunk_size(&chunk.path) {
+                report.add(chunk.path.clone(), format!("{:#}", e));
+            }
+            if let Some(previous) = previous {
+                if chunk.range.old > previous.new + 1 {
+                    let missing = format!(
+                        "blocks {}-{} are not in any chunk",
+                        previous.new + 1,
+                        chunk.range.old - 1
+                    );
+                    report.add(chunk.path.clone(), missing);
+                }
+            }
+            previous = Some(chunk.range);
+        }
+        Ok(report)
+    }
+    fn validate_raw_as(source_dir: &Path, format: RawSourceFormat) -> Result<ValidationReport> {
+        match format {
+            RawSourceFormat::Native => Self::validate_raw(source_dir),
+            RawSourceFormat::ChifraExport => validate_exports(source_dir),
+        }
+    }
+}
+
+/// Forms a Chapter from the exported appearances of the addresses that pass
+/// the filter (which must all belong to the Chapter).
+///
+/// Returns None if there are no such appearances in the Volume.
+fn chapter_from_exports<F>(
+    chapter_id: &AAIChapterId,
+    volume_id: &AAIVolumeId,
+    exports: &AppearanceExports,
+    filter: F,
+) -> Result<Option<AAIChapter>>
+where
+    F: Fn(&[u8]) -> bool,
+{
+    let block_range = volume_id.block_range()?;
+    let appearances = exports.matching(block_range, filter);
+    if appearances.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(chapter_from_appearances(
+        chapter_id.clone(),
+        block_range,
+        appearances,
+    )))
+}
+
+/// Checks that a chunk file is as long as its header describes.
+fn check_chunk_size(path: &PathBuf) -> Result<()> {
+    let mut file = fs::File::open(path)?;
+    let header = Header::from_reader(&mut file, path)?;
+    let expected = file_structure(&header).appearances.end as u64 + 1;
+    let found = file.metadata()?.len();
+    if found != expected {
+        bail!(
+            "expected {} bytes ({} addresses, {} appearances), found {}",
+            expected,
+            header.n_addresses,
+            header.n_appearances,
+            found
+        )
+    }
+    Ok(())
+}
+
+/// The strictness for whether unreadable chunk files are skipped.
+fn strictness(skip_corrupt: bool) -> ChunkStrictness {
+    match skip_corrupt {
+        true => ChunkStrictness::SkipCorrupt,
+        false => ChunkStrictness::Strict,
+    }
+}
+
+/// Forms a Chapter from the chunk files in the source directory.
+fn chapter_from_chunks(
+    chapter_id: &AAIChapterId,
+    volume_id: &AAIVolumeId,
+    chunk_files: &ChunksDir,
+    strictness: ChunkStrictness,
+) -> Result<Option<AAIChapter>> {
+    // Get relevant raw files.
+    let block_range = volume_id.block_range()?;
+    let Some(relevant_files) = chunk_files.for_range(&block_range) else {
+        return Ok(None);
+    };
+    // Get appearances from files.
+    let leading_char = hex::encode(&chapter_id.val);
+    let (chapter, stats) =
+        get_relevant_appearances(relevant_files, block_range, &leading_char, strictness)?;
+    debug!(
+        "Chunk parsing for 0x{} in {:?}: {}",
+        leading_char, block_range, stats
+    );
+    Ok(Some(chapter))
+}
+
+/// How chunk files that cannot be read are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkStrictness {
+    /// An unreadable chunk file is an error.
+    #[default]
+    Strict,
+    /// Unreadable chunk files are skipped (with a warning) and listed in the statistics.
+    SkipCorrupt,
+}
+
+/// Statistics from reading a set of chunk files.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkParseStats {
+    /// Chunk files read successfully.
+    pub chunks_read: usize,
+    /// Chunk files that could not be read, with the reason.
+    pub chunks_skipped: Vec<(PathBuf, String)>,
+    /// Appearances kept (relevant address and block range).
+    pub appearances_kept: u64,
+    /// Appearances for relevant addresses that were outside the block range.
+    pub appearances_discarded: u64,
+    /// Size of the chunk files read.
+    pub bytes_processed: u64,
+}
+
+impl std::fmt::Display for ChunkParseStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} chunks read ({} bytes), {} skipped, {} appearances kept, {} discarded",
+            self.chunks_read,
+            self.bytes_processed,
+            self.chunks_skipped.len(),
+            self.appearances_kept,
+            self.appearances_discarded
+        )
+    }
+}
+
+/// The addresses read from chunk files.
+#[derive(Clone, Copy)]
+enum AddressFilter<'a> {
+    /// Addresses that start with the hex characters. E.g., "4e".
+    LeadingChar(&'a str),
+    /// Specific addresses (20 bytes each).
+    Addresses(&'a [Vec<u8>]),
+    /// Addresses that start with any of the bytes (one byte per Chapter).
+    Chapters(&'a HashSet<u8>),
+}
+
+/// Reads the relevant appearances from a single chunk file.
+fn read_chunk(
+    chunk: &ChunkFile,
+    desired: BlockRange,
+    filter: AddressFilter,
+    stats: &mut ChunkParseStats,
+) -> Result<Vec<AddressData>> {
+    let mut uf: UnchainedFile = UnchainedFile::new(chunk.path.to_owned(), desired)?;
+    // Read appearances that pass the filter and are in desired range.
+    match filter {
+        AddressFilter::LeadingChar(leading_char) => uf.with_parsed(leading_char)?,
+        AddressFilter::Addresses(addresses) => uf.with_parsed_addresses(addresses)?,
+        AddressFilter::Chapters(chapters) => {
+            uf.with_parsed_matching(|address| chapters.contains(&address[0]))?
+        }
+    }
+    stats.chunks_read += 1;
+    stats.bytes_processed += fs::metadata(&chunk.path)?.len();
+    stats.appearances_discarded += uf.discarded;
+    stats.appearances_kept += uf
+        .parsed
+        .iter()
+        .map(|a| a.appearances.len() as u64)
+        .sum::<u64>();
+    Ok(uf.parsed)
 }
 
 /// For the given Unchained Index chunk files, finds transactions that match
 /// The desired block range and address leading hex characters.
+///
+/// Unreadable chunk files are handled according to the strictness.
 pub fn get_relevant_appearances(
     chunk_file_paths: Vec<&ChunkFile>,
     desired: BlockRange,
     leading_char: &str,
-) -> Result<RelicChapter> {
+    strictness: ChunkStrictness,
+) -> Result<(AAIChapter, ChunkParseStats)> {
+    let chapter_id = AAIChapterId {
+        val: Vector::from_iter(hex::decode(leading_char)?),
+    };
+    let filter = AddressFilter::LeadingChar(leading_char);
+    appearances_as_chapter(chunk_file_paths, desired, chapter_id, filter, strictness)
+}
+
+/// For the given Unchained Index chunk files, finds transactions for specific
+/// addresses within the desired block range.
+///
+/// The addresses (20 bytes each) must all belong to the given Chapter. Each
+/// address is found with a binary search, so this is much faster than
+/// [get_relevant_appearances] for a small number of addresses.
+pub fn get_appearances_for_addresses(
+    chunk_file_paths: Vec<&ChunkFile>,
+    desired: BlockRange,
+    chapter_id: &AAIChapterId,
+    addresses: &[Vec<u8>],
+    strictness: ChunkStrictness,
+) -> Result<(AAIChapter, ChunkParseStats)> {
+    let filter = AddressFilter::Addresses(addresses);
+    appearances_as_chapter(
+        chunk_file_paths,
+        desired,
+        chapter_id.clone(),
+        filter,
+        strictness,
+    )
+}
+
+/// Forms a Chapter from the appearances in the chunk files that pass the filter.
+fn appearances_as_chapter(
+    chunk_file_paths: Vec<&ChunkFile>,
+    desired: BlockRange,
+    chapter_id: AAIChapterId,
+    filter: AddressFilter,
+    strictness: ChunkStrictness,
+) -> Result<(AAIChapter, ChunkParseStats)> {
     let mut relevant_appearances: HashMap<Vec<u8>, Vec<TransactionId>> = HashMap::new();
-    for chunk in chunk_file_paths {
-        let path = chunk.path.to_owned();
-        // File reader
-        let mut uf: UnchainedFile = UnchainedFile::new(path, desired)?;
-        // Read appearances that have correct leading char and are in desired range.
-        uf.with_parsed(leading_char)?;
+    let stats = read_chunks(chunk_file_paths, desired, filter, strictness, |to_add| {
         // Add or update as appropriate.
-        for to_add in uf.parsed {
-            let key = to_add.address;
-            match relevant_appearances.entry(key) {
-                Entry::Occupied(mut entry) => {
-                    // Append to existing array and insert.
-                    entry.get_mut().extend(to_add.appearances);
+        match relevant_appearances.entry(to_add.address) {
+            Entry::Occupied(mut entry) => {
+                // Append to existing array and insert.
+                entry.get_mut().extend(to_add.appearances);
+            }
+            Entry::Vacant(entry) => {
+                // Insert.
+                entry.insert(to_add.appearances);
+            }
+        }
+        Ok(())
+    })?;
+    let chapter = chapter_from_appearances(chapter_id, desired, relevant_appearances);
+    Ok((chapter, stats))
+}
+
+/// Reads the appearances that pass the filter from each chunk file, passing
+/// them to `add` one address at a time.
+///
+/// Unreadable chunk files are handled according to the strictness. A skipped
+/// chunk file contributes no appearances.
+fn read_chunks<F>(
+    chunk_file_paths: Vec<&ChunkFile>,
+    desired: BlockRange,
+    filter: AddressFilter,
+    strictness: ChunkStrictness,
+    mut add: F,
+) -> Result<ChunkParseStats>
+where
+    F: FnMut(AddressData) -> Result<()>,
+{
+    let mut stats = ChunkParseStats::default();
+    for chunk in chunk_file_paths {
+        let parsed = match read_chunk(chunk, desired, filter, &mut stats) {
+            Ok(parsed) => parsed,
+            Err(e) => match strictness {
+                ChunkStrictness::Strict => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to read chunk file {:?}", chunk.path))
                 }
-                Entry::Vacant(entry) => {
-                    // Insert.
-                    entry.insert(to_add.appearances);
+                ChunkStrictness::SkipCorrupt => {
+                    warn!("Skipping unreadable chunk file {:?}: {:#}", chunk.path, e);
+                    stats
+                        .chunks_skipped
+                        .push((chunk.path.to_owned(), format!("{:#}", e)));
+                    continue;
                 }
-            }
+            },
+        };
+        for to_add in parsed {
+            add(to_add)?;
         }
     }
-    // Convert from hashmap to vector.
-    let mut addresses: Vec<RelicAddressAppearances> = relevant_appearances
+    Ok(stats)
+}
+
+/// Forms a Chapter from the appearances of each address in the Chapter.
+fn chapter_from_appearances(
+    chapter_id: AAIChapterId,
+    desired: BlockRange,
+    appearances: HashMap<Vec<u8>, Vec<TransactionId>>,
+) -> AAIChapter {
+    // Convert from hashmap to Records.
+    let mut records: Vec<AAIRecord> = appearances
         .into_iter()
-        .map(|(key, val)| RelicAddressAppearances {
-            address: Vector::from_iter(key),
-            appearances: {
-                // Start with Vec<TransactionId>
-                // Get Vec<AppearanceTx>
-                let t: Vec<AAIAppearanceTx> = val
+        .map(|(key, val)| AAIRecord {
+            key: AAIRecordKey {
+                key: Vector::from_iter(key),
+            },
+            value: AAIRecordValue {
+                value: val
                     .iter()
                     .map(|x| AAIAppearanceTx {
                         block: x.block,
                         index: x.index,
                     })
-                    .collect();
-                // Get AppearanceTxList
-                List::from_iter(t)
+                    .collect(),
             },
         })
         .collect();
     // Sort lexicographically by address. E.g., [0x0a, 0xa0, 0xaa].
-    addresses.sort_by(|a, b| a.address.cmp(&b.address));
+    records.sort_by(|a, b| a.key.key.cmp(&b.key.key));
 
-    let address_as_hex = hex::decode(leading_char)?;
-    let res = RelicChapter {
-        address_prefix: Vector::from_iter(address_as_hex),
-        identifier: RelicVolumeIdentifier {
+    AAIChapter {
+        chapter_id,
+        volume_id: AAIVolumeId {
             oldest_block: desired.old,
         },
-        addresses: List::from_iter(addresses),
+        records: List::from_iter(records),
+    }
+}
+
+/// Forms Chapters of a Volume, reading each chunk file once.
+///
+/// ## Algorithm
+/// 1. Read the appearances of the requested Chapters from each chunk file in turn.
+/// 2. Add them to a buffer per Chapter (see [ChapterBuffers]), which is moved
+///    to disk if too large.
+/// 3. Form each Chapter from its buffer.
+///
+/// Chapters are identical to those formed one at a time.
+fn chapters_in_single_pass(
+    volume_id: &AAIVolumeId,
+    chapter_ids: &[AAIChapterId],
+    chunk_files: &ChunksDir,
+    strictness: ChunkStrictness,
+    max_buffered: usize,
+) -> Result<Vec<Option<AAIChapter>>> {
+    let block_range = volume_id.block_range()?;
+    let Some(relevant_files) = chunk_files.for_range(&block_range) else {
+        return Ok(chapter_ids.iter().map(|_| None).collect());
     };
-    Ok(res)
+    let wanted: HashSet<u8> = chapter_ids.iter().map(|c| c.val[0]).collect();
+    let mut buffers = ChapterBuffers::new(max_buffered);
+    // 1. and 2.
+    let filter = AddressFilter::Chapters(&wanted);
+    let stats = read_chunks(relevant_files, block_range, filter, strictness, |to_add| {
+        buffers.add(to_add)
+    })?;
+    debug!(
+        "Single pass chunk parsing for {} Chapters in {:?}: {}",
+        chapter_ids.len(),
+        block_range,
+        stats
+    );
+    // 3.
+    chapter_ids
+        .iter()
+        .map(|c| {
+            let appearances = buffers.take(c.val[0])?;
+            Ok(Some(chapter_from_appearances(
+                c.clone(),
+                block_range,
+                appearances,
+            )))
+        })
+        .collect()
+}
+
+/// Forms a Chapter for each of the given Volumes, reading each chunk file once.
+///
+/// ## Algorithm
+/// 1. Find the chunk files that intersect any of the Volumes (the minimal set).
+/// 2. Read the appearances of the Chapter from each chunk file in turn, over the
+///    blocks from the oldest to the newest Volume.
+/// 3. Sort the appearances into the Volume that holds their block, discarding
+///    those in blocks between the Volumes.
+/// 4. Form the Chapter of each Volume.
+///
+/// Chapters are identical to those formed one at a time. Volumes without any
+/// chunk files have no Chapter.
+fn chapter_in_single_pass(
+    chapter_id: &AAIChapterId,
+    volume_ids: &[AAIVolumeId],
+    chunk_files: &ChunksDir,
+    strictness: ChunkStrictness,
+) -> Result<Vec<Option<AAIChapter>>> {
+    let ranges = volume_ids
+        .iter()
+        .map(|v| v.block_range())
+        .collect::<Result<Vec<BlockRange>>>()?;
+    let (Some(oldest), Some(newest)) = (
+        ranges.iter().map(|r| r.old).min(),
+        ranges.iter().map(|r| r.new).max(),
+    ) else {
+        return Ok(vec![]);
+    };
+    // 1.
+    let mut relevant_files: Vec<&ChunkFile> = vec![];
+    let mut has_files: Vec<bool> = vec![];
+    for range in &ranges {
+        let files = chunk_files.for_range(range).unwrap_or_default();
+        has_files.push(!files.is_empty());
+        for file in files {
+            if !relevant_files.iter().any(|f| f.path == file.path) {
+                relevant_files.push(file);
+            }
+        }
+    }
+    relevant_files.sort_by_key(|f| f.range.old);
+    // 2. and 3.
+    let mut per_volume: Vec<HashMap<Vec<u8>, Vec<TransactionId>>> =
+        ranges.iter().map(|_| HashMap::new()).collect();
+    let leading_char = hex::encode(&chapter_id.val);
+    let filter = AddressFilter::LeadingChar(&leading_char);
+    let desired = BlockRange {
+        old: oldest,
+        new: newest,
+    };
+    let stats = read_chunks(relevant_files, desired, filter, strictness, |to_add| {
+        for tx in to_add.appearances {
+            let Some(i) = ranges
+                .iter()
+                .position(|r| r.old <= tx.block && tx.block <= r.new)
+            else {
+                continue;
+            };
+            per_volume[i]
+                .entry(to_add.address.clone())
+                .or_default()
+                .push(tx);
+        }
+        Ok(())
+    })?;
+    debug!(
+        "Single pass chunk parsing for 0x{} in {} Volumes: {}",
+        leading_char,
+        volume_ids.len(),
+        stats
+    );
+    // 4.
+    Ok(per_volume
+        .into_iter()
+        .zip(ranges)
+        .zip(has_files)
+        .map(|((appearances, range), has_files)| {
+            has_files.then(|| chapter_from_appearances(chapter_id.clone(), range, appearances))
+        })
+        .collect())
+}
+
+/// Appearances held in memory during a single pass over chunk files, before
+/// they are moved to disk.
+const MAX_BUFFERED_APPEARANCES: usize = 20_000_000;
+
+/// Distinguishes the spill directories of buffers in the same process.
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Appearances for many Chapters, gathered in a single pass over chunk files.
+///
+/// Appearances are held in memory, keyed by Chapter (the first byte of the address).
+/// When more than a maximum are held, they are appended to a spill file per
+/// Chapter and memory is cleared. Spill files are removed when dropped.
+struct ChapterBuffers {
+    in_memory: HashMap<u8, HashMap<Vec<u8>, Vec<TransactionId>>>,
+    buffered: usize,
+    max_buffered: usize,
+    spill_dir: PathBuf,
+    spilled: HashSet<u8>,
+}
+
+impl ChapterBuffers {
+    fn new(max_buffered: usize) -> Self {
+        let spill_dir = std::env::temp_dir().join(format!(
+            "min_know_aai_spill_{}_{}",
+            std::process::id(),
+            SPILL_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        ChapterBuffers {
+            in_memory: HashMap::new(),
+            buffered: 0,
+            max_buffered,
+            spill_dir,
+            spilled: HashSet::new(),
+        }
+    }
+    fn add(&mut self, data: AddressData) -> Result<()> {
+        self.buffered += data.appearances.len();
+        self.in_memory
+            .entry(data.address[0])
+            .or_default()
+            .entry(data.address)
+            .or_default()
+            .extend(data.appearances);
+        if self.buffered > self.max_buffered {
+            self.spill()?;
+        }
+        Ok(())
+    }
+    fn spill_path(&self, chapter: u8) -> PathBuf {
+        self.spill_dir.join(format!("{:02x}", chapter))
+    }
+    /// Appends the appearances in memory to the spill files.
+    ///
+    /// Each entry is the address (20 bytes) then the appearance (8 bytes).
+    fn spill(&mut self) -> Result<()> {
+        debug!(
+            "Moving {} appearances to {:?}",
+            self.buffered, self.spill_dir
+        );
+        fs::create_dir_all(&self.spill_dir)?;
+        for (chapter, appearances) in std::mem::take(&mut self.in_memory) {
+            let path = self.spill_path(chapter);
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let mut writer = BufWriter::new(file);
+            for (address, txs) in appearances {
+                for tx in txs {
+                    writer.write_all(&address)?;
+                    tx.to_writer(&mut writer)?;
+                }
+            }
+            writer.flush()?;
+            self.spilled.insert(chapter);
+        }
+        self.buffered = 0;
+        Ok(())
+    }
+    /// Removes the appearances for a Chapter, including those on disk.
+    ///
+    /// Appearances for an address keep the order they were added in.
+    fn take(&mut self, chapter: u8) -> Result<HashMap<Vec<u8>, Vec<TransactionId>>> {
+        let mut appearances: HashMap<Vec<u8>, Vec<TransactionId>> = HashMap::new();
+        if self.spilled.remove(&chapter) {
+            let path = self.spill_path(chapter);
+            let bytes = fs::read(&path)?;
+            for entry in bytes.chunks_exact(ADDR + AP_ENTRY) {
+                let (address, tx) = entry.split_at(ADDR);
+                let tx = TransactionId::from_reader(tx)?;
+                appearances.entry(address.to_vec()).or_default().push(tx);
+            }
+            fs::remove_file(path)?;
+        }
+        for (address, txs) in self.in_memory.remove(&chapter).unwrap_or_default() {
+            appearances.entry(address).or_default().extend(txs);
+        }
+        Ok(appearances)
+    }
+}
+
+impl Drop for ChapterBuffers {
+    fn drop(&mut self) {
+        if self.spill_dir.exists() {
+            let _ = fs::remove_dir_all(&self.spill_dir);
+        }
+    }
 }
 
 /// Finds the latest block in an Unchained Index chunks directory.
@@ -160,3 +771,162 @@ fn test_latest_vol_id() {
     assert_eq!(latest_full_volume(299_998).unwrap(), 100_000);
     assert_eq!(latest_full_volume(299_999).unwrap(), 200_000);
 }
+
+#[test]
+fn corrupt_chunks_skipped_when_allowed() {
+    use crate::utils::unchained::writer::ChunkWriter;
+
+    let dir = std::env::temp_dir().join("min_know_test_corrupt_chunks");
+    let _ = std::fs::remove_dir_all(&dir);
+    let address = [0x4e; 20];
+    let mut good = ChunkWriter::new(BlockRange::new(100, 200).unwrap());
+    good.add_appearance(&address, 150, 1).unwrap();
+    good.write_to_dir(&dir).unwrap();
+    let mut bad = ChunkWriter::new(BlockRange::new(201, 300).unwrap());
+    bad.add_appearance(&address, 250, 1).unwrap();
+    let bad_path = bad.write_to_dir(&dir).unwrap();
+    let bytes = std::fs::read(&bad_path).unwrap();
+    std::fs::write(&bad_path, &bytes[..bytes.len() - 20]).unwrap();
+
+    let chunks = ChunksDir::new(&dir).unwrap();
+    let desired = BlockRange::new(0, 999).unwrap();
+    let files = chunks.for_range(&desired).unwrap();
+    let err = get_relevant_appearances(files.clone(), desired, "4e", ChunkStrictness::Strict)
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("000000201-000000300.bin"));
+
+    let (chapter, stats) =
+        get_relevant_appearances(files, desired, "4e", ChunkStrictness::SkipCorrupt).unwrap();
+    assert_eq!(chapter.records.len(), 1);
+    assert_eq!(stats.chunks_read, 1);
+    assert_eq!(stats.chunks_skipped.len(), 1);
+    assert_eq!(stats.chunks_skipped[0].0, bad_path);
+    assert_eq!(stats.appearances_kept, 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn chapters_from_raw_match_sample_files() {
+    use crate::{
+        config::{
+            address_appearance_index::Network,
+            choices::{DataKind, DirNature},
+        },
+        specs::traits::{ChapterIdMethods, ChapterMethods},
+    };
+
+    let data_kind = DataKind::AddressAppearanceIndex(Network::default());
+    let config = DirNature::Sample.into_config(data_kind).unwrap();
+    for n in [0x00, 0x4e, 0xff] {
+        let chapter_id = AAIChapterId::nth_id(n).unwrap();
+        let files = config
+            .parse_all_files_for_chapter::<AAISpec>(&chapter_id)
+            .unwrap();
+        assert!(!files.is_empty());
+        // Each stored Chapter is reproduced exactly from the raw chunk files.
+        for (path, volume_id) in files {
+            let chapter =
+                AAIExtractor::chapter_from_raw(&chapter_id, &volume_id, &config.raw_source)
+                    .unwrap()
+                    .unwrap();
+            let stored = std::fs::read(&path).unwrap();
+            assert_eq!(chapter.as_serialized_bytes().unwrap(), stored, "{:?}", path);
+        }
+    }
+}
+
+#[test]
+fn single_pass_chapters_match_sample_files() {
+    use crate::{
+        config::{
+            address_appearance_index::Network,
+            choices::{DataKind, DirNature},
+        },
+        specs::traits::{ChapterIdMethods, ChapterMethods},
+    };
+
+    let data_kind = DataKind::AddressAppearanceIndex(Network::default());
+    let config = DirNature::Sample.into_config(data_kind).unwrap();
+    let chunks = ChunksDir::new(&config.raw_source).unwrap();
+    let chapter_ids: Vec<AAIChapterId> = [0x00, 0x4e, 0xff]
+        .into_iter()
+        .map(|n| AAIChapterId::nth_id(n).unwrap())
+        .collect();
+    let strict = ChunkStrictness::Strict;
+    let files = config
+        .parse_all_files_for_chapter::<AAISpec>(&chapter_ids[0])
+        .unwrap();
+    assert!(!files.is_empty());
+    for (_path, volume_id) in files {
+        // A small buffer moves appearances to disk many times.
+        let chapters = chapters_in_single_pass(&volume_id, &chapter_ids, &chunks, strict, 1000);
+        for (chapter_id, chapter) in chapter_ids.iter().zip(chapters.unwrap()) {
+            let chapter = chapter.unwrap();
+            let path = config.chapter_dir_path(chapter_id).join(chapter.filename());
+            let stored = std::fs::read(&path).unwrap();
+            assert_eq!(chapter.as_serialized_bytes().unwrap(), stored, "{:?}", path);
+        }
+    }
+}
+
+#[test]
+fn chapter_across_volumes_matches_sample_files() {
+    use crate::{
+        config::{
+            address_appearance_index::Network,
+            choices::{DataKind, DirNature},
+        },
+        specs::traits::{ChapterIdMethods, ChapterMethods},
+    };
+
+    let data_kind = DataKind::AddressAppearanceIndex(Network::default());
+    let config = DirNature::Sample.into_config(data_kind).unwrap();
+    let chunks = ChunksDir::new(&config.raw_source).unwrap();
+    let chapter_id = AAIChapterId::nth_id(0x4e).unwrap();
+    let files = config
+        .parse_all_files_for_chapter::<AAISpec>(&chapter_id)
+        .unwrap();
+    assert!(files.len() > 1);
+    // The first Volume has no chunk files in the samples.
+    let mut volume_ids = vec![AAIVolumeId { oldest_block: 0 }];
+    volume_ids.extend(files.iter().map(|(_path, volume_id)| volume_id.clone()));
+    let strict = ChunkStrictness::Strict;
+    let chapters = chapter_in_single_pass(&chapter_id, &volume_ids, &chunks, strict).unwrap();
+    assert_eq!(chapters.len(), volume_ids.len());
+    assert!(chapters[0].is_none());
+    for chapter in chapters.into_iter().skip(1) {
+        let chapter = chapter.unwrap();
+        let path = config
+            .chapter_dir_path(&chapter_id)
+            .join(chapter.filename());
+        let stored = std::fs::read(&path).unwrap();
+        assert_eq!(chapter.as_serialized_bytes().unwrap(), stored, "{:?}", path);
+    }
+}
+
+#[test]
+fn truncated_chunks_and_gaps_reported() {
+    use crate::utils::unchained::writer::ChunkWriter;
+
+    let dir = std::env::temp_dir().join("min_know_test_validate_chunks");
+    let _ = std::fs::remove_dir_all(&dir);
+    let address = [0x4e; 20];
+    for (old, new) in [(100, 200), (201, 300), (401, 500)] {
+        let mut chunk = ChunkWriter::new(BlockRange::new(old, new).unwrap());
+        chunk.add_appearance(&address, old + 1, 1).unwrap();
+        chunk.write_to_dir(&dir).unwrap();
+    }
+    let report = AAIExtractor::validate_raw(&dir).unwrap();
+    assert_eq!(report.files_checked, 3);
+    assert_eq!(report.issues.len(), 1);
+    assert!(report.issues[0].problem.contains("blocks 301-400"));
+
+    let truncated = dir.join("000000201-000000300.bin");
+    let bytes = std::fs::read(&truncated).unwrap();
+    std::fs::write(&truncated, &bytes[..bytes.len() - 20]).unwrap();
+    let report = AAIExtractor::validate_raw(&dir).unwrap();
+    assert_eq!(report.issues.len(), 2);
+    assert_eq!(report.issues[0].path, truncated);
+    assert!(report.issues[0].problem.starts_with("expected"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
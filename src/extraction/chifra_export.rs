@@ -0,0 +1,279 @@
+//! Appearance lists exported by TrueBlocks (`chifra export --appearances`), an
+//! alternative raw source for the address-appearance-index.
+//!
+//! Some users keep only the exported lists, rather than the Unchained Index
+//! chunk files. With `config.raw_source_format` set to
+//! [RawSourceFormat::ChifraExport][super::traits::RawSourceFormat::ChifraExport],
+//! each file in the raw source directory is read as an export:
+//! - `.csv`: a header row, then one appearance per row (`chifra export --fmt csv`).
+//! - `.json`: an array of appearances, or an object with the array under `data`
+//!   (`chifra export --fmt json`).
+//!
+//! Appearances have the fields `address`, `blockNumber` and `transactionIndex`.
+//! Other fields (e.g., `timestamp`, `date`) are ignored. Other files are skipped.
+//!
+//! Chapters are formed from the appearances as they would be from the chunk
+//! files, so they hold the same Records as Chapters formed from chunks with the
+//! same appearances. Chapters without any appearances are not formed. As exports
+//! do not record which blocks were covered, the latest block is that of the
+//! newest appearance.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::warn;
+use serde::Deserialize;
+
+use crate::utils::{
+    system::dir_paths,
+    unchained::{structure::TransactionId, types::BlockRange},
+};
+
+use super::validation::ValidationReport;
+
+/// An appearance in an export.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ExportedAppearance {
+    /// E.g., "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".
+    pub address: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: u32,
+    #[serde(rename = "transactionIndex")]
+    pub transaction_index: u32,
+}
+
+/// A JSON export: either the array of appearances, or an object holding it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonExport {
+    Wrapped { data: Vec<ExportedAppearance> },
+    Plain(Vec<ExportedAppearance>),
+}
+
+/// The appearances from every export in a directory, by address.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AppearanceExports {
+    pub dir: PathBuf,
+    /// Appearances of each address (20 bytes), in order and without duplicates.
+    by_address: BTreeMap<Vec<u8>, Vec<TransactionId>>,
+}
+
+impl AppearanceExports {
+    /// Reads every export in the directory.
+    ///
+    /// ## Errors
+    /// If the directory or an export cannot be read, or an export holds an
+    /// invalid address.
+    pub fn new(dir: &Path) -> Result<Self> {
+        let mut by_address: BTreeMap<Vec<u8>, Vec<TransactionId>> = BTreeMap::new();
+        for path in export_files(dir)? {
+            for appearance in read_export(&path)? {
+                let address = address_bytes(&appearance.address)
+                    .with_context(|| format!("Invalid appearance in {:?}", path))?;
+                by_address.entry(address).or_default().push(TransactionId {
+                    block: appearance.block_number,
+                    index: appearance.transaction_index,
+                });
+            }
+        }
+        for appearances in by_address.values_mut() {
+            appearances.sort_by_key(|tx| (tx.block, tx.index));
+            appearances.dedup();
+        }
+        Ok(AppearanceExports {
+            dir: dir.to_path_buf(),
+            by_address,
+        })
+    }
+    /// The block of the newest appearance.
+    pub fn latest_block(&self) -> Result<u32> {
+        self.by_address
+            .values()
+            .filter_map(|appearances| appearances.last())
+            .map(|tx| tx.block)
+            .max()
+            .ok_or_else(|| anyhow!("Expected exports in {:?}, found none.", self.dir))
+    }
+    /// The appearances within the blocks, for addresses that pass the filter.
+    ///
+    /// Addresses without appearances in the blocks are not included.
+    pub(crate) fn matching<F>(
+        &self,
+        desired: BlockRange,
+        filter: F,
+    ) -> HashMap<Vec<u8>, Vec<TransactionId>>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        self.by_address
+            .iter()
+            .filter(|(address, _)| filter(address))
+            .filter_map(|(address, appearances)| {
+                let within: Vec<TransactionId> = appearances
+                    .iter()
+                    .filter(|tx| desired.old <= tx.block && tx.block <= desired.new)
+                    .cloned()
+                    .collect();
+                (!within.is_empty()).then(|| (address.clone(), within))
+            })
+            .collect()
+    }
+}
+
+/// Lists the exports in a directory (`.csv` and `.json` files), sorted.
+pub fn export_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = dir_paths(dir).with_context(|| format!("Can't read: {}", dir.display()))?;
+    let mut paths = vec![];
+    for path in entries {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") | Some("json") => paths.push(path),
+            _ => warn!("Skipping file that is not a chifra export: {:?}", path),
+        }
+    }
+    Ok(paths)
+}
+
+/// Reads the appearances in an export, by the file extension.
+pub fn read_export(path: &Path) -> Result<Vec<ExportedAppearance>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read export {:?}", path))?;
+    let appearances = match path.extension().and_then(|e| e.to_str()) {
+        Some("csv") => appearances_from_csv(&contents),
+        Some("json") => match serde_json::from_str::<JsonExport>(&contents) {
+            Ok(JsonExport::Wrapped { data }) => Ok(data),
+            Ok(JsonExport::Plain(data)) => Ok(data),
+            Err(e) => Err(e.into()),
+        },
+        _ => bail!("Unknown export format, expected .csv or .json"),
+    };
+    appearances.with_context(|| format!("Failed to parse export {:?}", path))
+}
+
+/// Checks that each export can be read and holds valid addresses.
+pub fn validate_exports(dir: &Path) -> Result<ValidationReport> {
+    let mut report = ValidationReport::default();
+    for path in export_files(dir)? {
+        report.files_checked += 1;
+        let appearances = match read_export(&path) {
+            Ok(a) => a,
+            Err(e) => {
+                report.add(path, format!("{:#}", e));
+                continue;
+            }
+        };
+        if let Some(e) = appearances
+            .iter()
+            .find_map(|a| address_bytes(&a.address).err())
+        {
+            report.add(path, format!("{:#}", e));
+        }
+    }
+    Ok(report)
+}
+
+/// Parses CSV with a header row. Values may be quoted, as in chifra output.
+fn appearances_from_csv(contents: &str) -> Result<Vec<ExportedAppearance>> {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Ok(vec![]);
+    };
+    let columns: Vec<&str> = csv_fields(header).collect();
+    let column = |name: &str| {
+        columns
+            .iter()
+            .position(|c| *c == name)
+            .ok_or_else(|| anyhow!("Missing column {:?}", name))
+    };
+    let (address, block, index) = (
+        column("address")?,
+        column("blockNumber")?,
+        column("transactionIndex")?,
+    );
+    let mut appearances = vec![];
+    for (row, line) in lines.enumerate() {
+        let fields: Vec<&str> = csv_fields(line).collect();
+        let field = |i: usize| {
+            fields
+                .get(i)
+                .copied()
+                .ok_or_else(|| anyhow!("Row {} has {} fields", row + 1, fields.len()))
+        };
+        appearances.push(ExportedAppearance {
+            address: field(address)?.to_string(),
+            block_number: field(block)?
+                .parse()
+                .with_context(|| format!("Invalid blockNumber in row {}", row + 1))?,
+            transaction_index: field(index)?
+                .parse()
+                .with_context(|| format!("Invalid transactionIndex in row {}", row + 1))?,
+        });
+    }
+    Ok(appearances)
+}
+
+fn csv_fields(line: &str) -> impl Iterator<Item = &str> {
+    line.split(',').map(|field| field.trim().trim_matches('"'))
+}
+
+/// Decodes a hex address, which must be 20 bytes.
+fn address_bytes(address: &str) -> Result<Vec<u8>> {
+    let bytes = hex::decode(address.trim_start_matches("0x"))
+        .with_context(|| format!("Invalid address {:?}", address))?;
+    if bytes.len() != 20 {
+        bail!(
+            "Address {:?} has {} bytes, expected 20",
+            address,
+            bytes.len()
+        )
+    }
+    Ok(bytes)
+}
+
+#[test]
+fn csv_and_json_exports_read() {
+    let dir = std::env::temp_dir().join("min_know_test_chifra_exports");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let a = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let b = "0x4e00000000000000000000000000000000000001";
+    let csv = format!(
+        "\"address\",\"blockNumber\",\"transactionIndex\",\"timestamp\"\n\
+         \"{a}\",\"200\",\"3\",\"1600000000\"\n\
+         \"{a}\",\"100\",\"7\",\"1500000000\"\n"
+    );
+    fs::write(dir.join("a.csv"), csv).unwrap();
+    let json = format!(
+        r#"{{"data": [
+            {{"address": "{a}", "blockNumber": 100, "transactionIndex": 7}},
+            {{"address": "{b}", "blockNumber": 150, "transactionIndex": 0, "date": "x"}}
+        ]}}"#
+    );
+    fs::write(dir.join("b.json"), json).unwrap();
+    fs::write(dir.join("README.md"), "not an export").unwrap();
+
+    let exports = AppearanceExports::new(&dir).unwrap();
+    assert_eq!(exports.latest_block().unwrap(), 200);
+    let all = exports.matching(BlockRange { old: 0, new: 999 }, |_| true);
+    let a_txs = &all[&address_bytes(a).unwrap()];
+    // Sorted, and the appearance in both exports is held once.
+    let blocks: Vec<(u32, u32)> = a_txs.iter().map(|tx| (tx.block, tx.index)).collect();
+    assert_eq!(blocks, vec![(100, 7), (200, 3)]);
+    // Filtered by block and by address.
+    let later = exports.matching(BlockRange { old: 120, new: 999 }, |address| {
+        address[0] == 0x4e
+    });
+    assert_eq!(later.len(), 1);
+    assert!(later.contains_key(&address_bytes(b).unwrap()));
+
+    fs::write(
+        dir.join("c.csv"),
+        "\"address\",\"blockNumber\"\n\"0x01\",\"1\"\n",
+    )
+    .unwrap();
+    let error = AppearanceExports::new(&dir).unwrap_err();
+    assert!(format!("{:#}", error).contains("Missing column \"transactionIndex\""));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,170 @@
+//! Extraction of event signatures from the topic0 layout
+//! (<https://github.com/wmitsuda/topic0>).
+//!
+//! The raw source is the `without_parameter_names` directory, which has a file
+//! named by each topic (64 hex characters) containing the event signature text.
+//! Texts are parsed the same way as function signatures.
+use anyhow::{bail, Context, Result};
+use log::warn;
+use ssz_rs::List;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    parameters::events::{BYTES_FOR_TOPIC_CHARS, EVENTS_PER_VOLUME, MAX_TEXTS_PER_RECORD},
+    specs::{
+        events::{
+            EventsChapter, EventsChapterId, EventsRecord, EventsRecordKey, EventsRecordValue,
+            EventsSpec, EventsVolumeId,
+        },
+        signatures::Text,
+    },
+    utils::system::dir_paths,
+};
+
+use super::{
+    limits::{LimitCheck, OversizePolicy, OversizeValue},
+    raw_index::RawFileIndex,
+    signatures::{first_inside_last, raw_paths, texts_from_raw},
+    traits::{canonical_order, ExtractedChapter, ExtractorMethods},
+    validation::ValidationReport,
+};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventsExtractor {}
+
+impl ExtractorMethods<EventsSpec> for EventsExtractor {
+    /// Paths of the raw files, sorted and grouped by Volume and Chapter.
+    type Source = RawFileIndex<PathBuf>;
+
+    fn scan_source(source_dir: &Path) -> Result<RawFileIndex<PathBuf>> {
+        let mut paths = raw_paths(source_dir)?;
+        paths.sort_by_key(|path| topic_of(path).unwrap_or_default().to_string());
+        Ok(RawFileIndex::new(paths, EVENTS_PER_VOLUME, |path| {
+            // 'ddf252ad...' -> 'dd'
+            let topic = topic_of(path).unwrap_or_default();
+            topic.chars().take(2 * BYTES_FOR_TOPIC_CHARS).collect()
+        }))
+    }
+
+    fn chapter_from_source(
+        chapter_id: &EventsChapterId,
+        volume_id: &EventsVolumeId,
+        source: &RawFileIndex<PathBuf>,
+        _skip_corrupt: bool,
+        oversize: OversizePolicy,
+    ) -> Result<ExtractedChapter<EventsSpec>> {
+        let mut records: Vec<EventsRecord> = vec![];
+        let mut oversize_values: Vec<OversizeValue> = vec![];
+        let relevant_files =
+            source.chapter_files(volume_id.first_event as usize, &chapter_id.as_string());
+
+        for path in relevant_files {
+            let Some(topic) = topic_of(path) else {
+                bail!("Couldn't read filename: {}", path.display())
+            };
+            let contents = fs::read_to_string(path)?;
+            let (texts, skipped) = texts_from_raw(topic, &contents);
+            for s in skipped {
+                warn!("Skipping event text: {}", s);
+            }
+            let mut check = LimitCheck::new(topic, oversize);
+            let texts = check.items("texts", texts, MAX_TEXTS_PER_RECORD);
+            let (keep, found) = check.finish()?;
+            oversize_values.extend(found);
+            if texts.is_empty() || !keep {
+                continue;
+            }
+            let texts: Vec<Text> = texts.iter().map(|t| Text::from_string(t)).collect();
+
+            let record = EventsRecord {
+                key: EventsRecordKey::from_topic(topic)?,
+                value: EventsRecordValue {
+                    texts: List::from_iter(texts),
+                },
+            };
+            records.push(record);
+        }
+        if records.is_empty() {
+            return Ok((None, oversize_values));
+        }
+        let records = canonical_order::<EventsSpec>(records)?;
+        let chapter = EventsChapter {
+            chapter_id: chapter_id.clone(),
+            volume_id: volume_id.clone(),
+            records: List::from_iter(records),
+        };
+        Ok((Some(chapter), oversize_values))
+    }
+
+    fn latest_volume_in_source(source: &RawFileIndex<PathBuf>) -> Result<EventsVolumeId> {
+        let count = source.len() as u32;
+        let first_event = first_inside_last(count, EVENTS_PER_VOLUME as u32)?;
+        Ok(EventsVolumeId { first_event })
+    }
+
+    fn oversize_report(source_dir: &Path) -> Result<Vec<OversizeValue>> {
+        let mut report = vec![];
+        for path in raw_paths(source_dir)? {
+            let topic = topic_of(&path).unwrap_or_default();
+            let contents = fs::read_to_string(&path)?;
+            let (texts, _skipped) = texts_from_raw(topic, &contents);
+            let mut check = LimitCheck::new(topic, OversizePolicy::Truncate);
+            check.items("texts", texts, MAX_TEXTS_PER_RECORD);
+            report.extend(check.finish()?.1);
+        }
+        Ok(report)
+    }
+
+    /// Checks that each raw file is named by a valid topic and that its
+    /// texts are valid event signatures.
+    fn validate_raw(source_dir: &Path) -> Result<ValidationReport> {
+        let paths = dir_paths(source_dir)
+            .with_context(|| format!("Can't read: {}", source_dir.display()))?;
+        let mut report = ValidationReport::default();
+        for path in paths {
+            report.files_checked += 1;
+            let Some(topic) = topic_of(&path) else {
+                report.add(path, "filename is not valid UTF-8");
+                continue;
+            };
+            if let Err(e) = EventsRecordKey::from_topic(topic) {
+                report.add(path.clone(), format!("invalid topic: {:#}", e));
+            }
+            let contents = match fs::read_to_string(&path) {
+                Ok(c) => c,
+                Err(e) => {
+                    report.add(path, format!("unreadable: {}", e));
+                    continue;
+                }
+            };
+            let (_texts, skipped) = texts_from_raw(topic, &contents);
+            for s in skipped {
+                let problem = format!("invalid text {:?} ({})", s.text, s.reason);
+                report.add(path.clone(), problem);
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// The hex topic that a raw file is named by, without any "0x" prefix.
+fn topic_of(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    Some(name.trim_start_matches("0x"))
+}
+
+#[test]
+fn topics_from_filenames() {
+    let topic = "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+    assert_eq!(topic_of(Path::new(topic)), Some(topic));
+    assert_eq!(
+        topic_of(&Path::new("raw").join(format!("0x{}", topic))),
+        Some(topic)
+    );
+    let (texts, skipped) = texts_from_raw(topic, "Transfer(address, address, uint256)\n");
+    assert_eq!(texts, vec!["Transfer(address,address,uint256)"]);
+    assert!(skipped.is_empty());
+}
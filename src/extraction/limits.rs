@@ -0,0 +1,141 @@
+//! Size limits for RecordValues formed from raw data.
+//!
+//! Specs define maximum lengths for the variable size parts of a RecordValue
+//! (e.g., bytes in a name, or number of tags). Raw data that exceeds these is
+//! handled during extraction according to an [OversizePolicy], rather than
+//! failing later when the Chapter is encoded.
+use std::fmt::Display;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// How raw values that exceed the size limits of a spec are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Hash, Deserialize, Serialize)]
+pub enum OversizePolicy {
+    /// The Record is left out of the Chapter.
+    Skip,
+    /// Strings are shortened (at a character boundary) and lists have their
+    /// last items removed.
+    #[default]
+    Truncate,
+    /// The Chapter fails with an error that names the key.
+    Error,
+}
+
+/// A part of a raw value that exceeded a size limit of the spec.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OversizeValue {
+    /// The raw key of the Record. E.g., "0xabcd...1234".
+    pub key: String,
+    /// The part of the value. E.g., "name" or "tags".
+    pub field: String,
+    /// Size of the raw value (bytes for strings, items for lists).
+    pub size: usize,
+    /// Maximum size defined by the spec.
+    pub limit: usize,
+}
+
+impl Display for OversizeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} has size {} (limit {})",
+            self.key, self.field, self.size, self.limit
+        )
+    }
+}
+
+/// Applies size limits to the parts of a single raw value.
+pub(crate) struct LimitCheck {
+    key: String,
+    policy: OversizePolicy,
+    found: Vec<OversizeValue>,
+}
+
+impl LimitCheck {
+    pub(crate) fn new(key: &str, policy: OversizePolicy) -> Self {
+        LimitCheck {
+            key: key.to_string(),
+            policy,
+            found: vec![],
+        }
+    }
+    /// Checks the length of a string in bytes, truncating it if that is the policy.
+    pub(crate) fn string(&mut self, field: &str, mut s: String, limit: usize) -> String {
+        if s.len() > limit {
+            self.record(field, s.len(), limit);
+            if self.policy == OversizePolicy::Truncate {
+                let mut end = limit;
+                while !s.is_char_boundary(end) {
+                    end -= 1;
+                }
+                s.truncate(end);
+            }
+        }
+        s
+    }
+    /// Checks the number of items in a list, truncating it if that is the policy.
+    pub(crate) fn items<I>(&mut self, field: &str, mut items: Vec<I>, limit: usize) -> Vec<I> {
+        if items.len() > limit {
+            self.record(field, items.len(), limit);
+            if self.policy == OversizePolicy::Truncate {
+                items.truncate(limit);
+            }
+        }
+        items
+    }
+    fn record(&mut self, field: &str, size: usize, limit: usize) {
+        self.found.push(OversizeValue {
+            key: self.key.clone(),
+            field: field.to_string(),
+            size,
+            limit,
+        })
+    }
+    /// Returns whether the Record should be kept, and the parts that exceeded limits.
+    ///
+    /// ## Errors
+    /// If a limit was exceeded and the policy is [OversizePolicy::Error].
+    pub(crate) fn finish(self) -> Result<(bool, Vec<OversizeValue>)> {
+        if self.found.is_empty() {
+            return Ok((true, self.found));
+        }
+        match self.policy {
+            OversizePolicy::Skip => Ok((false, self.found)),
+            OversizePolicy::Truncate => Ok((true, self.found)),
+            OversizePolicy::Error => {
+                let found: Vec<String> = self.found.iter().map(|v| v.to_string()).collect();
+                bail!(
+                    "Raw value exceeds the size limits of the spec: {}. Use a different oversize policy to skip or truncate it.",
+                    found.join(", ")
+                )
+            }
+        }
+    }
+}
+
+#[test]
+fn limits_applied_by_policy() {
+    let check = |policy| {
+        let mut check = LimitCheck::new("0xab", policy);
+        let s = check.string("name", "aé".to_string(), 2);
+        let items = check.items("tags", vec![1, 2, 3], 2);
+        (s, items, check.finish())
+    };
+    let (s, items, result) = check(OversizePolicy::Truncate);
+    assert_eq!((s.as_str(), items), ("a", vec![1, 2]));
+    let (keep, found) = result.unwrap();
+    assert!(keep);
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].to_string(), "0xab name has size 3 (limit 2)");
+
+    let (s, _, result) = check(OversizePolicy::Skip);
+    assert_eq!(s, "aé");
+    assert!(!result.unwrap().0);
+
+    let (_, _, result) = check(OversizePolicy::Error);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("0xab tags has size 3"));
+}
@@ -1,6 +1,11 @@
 //! Each type of source database that will be extracted needs custom algorithms
 //! to parse the data.
 pub mod address_appearance_index;
+pub mod chifra_export;
+pub mod events;
+pub mod limits;
 pub mod nametags;
+pub mod raw_index;
 pub mod signatures;
 pub mod traits;
+pub mod validation;
@@ -1,21 +1,31 @@
 use std::{
-    fs::{self, read_dir},
-    path::Path,
+    fs,
+    path::{Path, PathBuf},
 };
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use log::warn;
 use serde::{Deserialize, Serialize};
 use ssz_rs::List;
 
 use crate::{
-    parameters::nametags::ENTRIES_PER_VOLUME,
+    parameters::nametags::{
+        BYTES_FOR_ADDRESS_CHARS, ENTRIES_PER_VOLUME, MAX_BYTES_PER_NAME, MAX_BYTES_PER_SOURCE,
+        MAX_BYTES_PER_TAG, MAX_NAMES_PER_RECORD, MAX_TAGS_PER_RECORD,
+    },
     specs::nametags::{
         NameTagsChapter, NameTagsChapterId, NameTagsRecord, NameTagsRecordKey, NameTagsRecordValue,
         NameTagsSpec, NameTagsVolumeId,
     },
+    utils::system::dir_paths,
 };
 
-use super::traits::ExtractorMethods;
+use super::{
+    limits::{LimitCheck, OversizePolicy, OversizeValue},
+    raw_index::{RawEntryRange, RawFileIndex},
+    traits::{canonical_order, ExtractedChapter, ExtractorMethods},
+    validation::ValidationReport,
+};
 
 /// Strongly typed parser for the JSON data in the raw (unprocessed data).
 #[derive(Serialize, Deserialize)]
@@ -26,22 +36,39 @@ pub struct RawValue {
     pub name: Option<String>,
     /// Raw data has multiple tags per address.
     pub tags: Option<Vec<String>>,
+    /// When the names and tags were added (seconds since the Unix epoch), if
+    /// the raw data records it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added: Option<u64>,
 }
 
 impl RawValue {
-    /// Creates a record value from from raw data.
-    fn into_record_value(self) -> NameTagsRecordValue {
+    /// Creates a record value from from raw data, applying the size limits of the spec.
+    fn into_record_value(
+        self,
+        source: Option<&str>,
+        check: &mut LimitCheck,
+    ) -> NameTagsRecordValue {
         // Allow for 0, 1 or more names.
         let names = match self.name {
             Some(n) => vec![n],
             None => vec![],
         };
+        let names = check
+            .items("names", names, MAX_NAMES_PER_RECORD)
+            .into_iter()
+            .map(|n| check.string("name", n, MAX_BYTES_PER_NAME))
+            .collect();
         // Allow for 0, 1 or more tags.
-        let tags = match self.tags {
-            Some(t) => t,
-            None => vec![],
-        };
-        NameTagsRecordValue::from_strings(names, tags)
+        let tags = self.tags.unwrap_or_default();
+        let tags = check
+            .items("tags", tags, MAX_TAGS_PER_RECORD)
+            .into_iter()
+            .map(|t| check.string("tag", t, MAX_BYTES_PER_TAG))
+            .collect();
+        let source = source.map(|s| check.string("source", s.to_string(), MAX_BYTES_PER_SOURCE));
+        NameTagsRecordValue::from_strings_with_source(names, tags, source.as_deref())
+            .with_added(self.added)
     }
 }
 
@@ -49,56 +76,223 @@ impl RawValue {
 pub struct NameTagsExtractor;
 
 impl ExtractorMethods<NameTagsSpec> for NameTagsExtractor {
-    fn chapter_from_raw(
+    /// The raw files, sorted by path and grouped by Volume and Chapter.
+    type Source = RawFileIndex<RawFile>;
+
+    fn scan_source(source_dir: &Path) -> Result<RawFileIndex<RawFile>> {
+        let mut files = raw_files(source_dir)?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(RawFileIndex::new(files, ENTRIES_PER_VOLUME, |file| {
+            // '0xabcd' -> 'ab'
+            file.address
+                .chars()
+                .skip(2)
+                .take(2 * BYTES_FOR_ADDRESS_CHARS)
+                .collect()
+        }))
+    }
+
+    fn chapter_from_source(
         chapter_id: &NameTagsChapterId,
         volume_id: &NameTagsVolumeId,
-        source_dir: &Path,
-    ) -> Result<Option<NameTagsChapter>> {
-        let Ok(dir) = fs::read_dir(source_dir) else {
-            bail!("Couldn't read dir {}", source_dir.display())};
+        source: &RawFileIndex<RawFile>,
+        _skip_corrupt: bool,
+        oversize: OversizePolicy,
+    ) -> Result<ExtractedChapter<NameTagsSpec>> {
         // Get appropriate range and appropriate files in that range.
         let mut records: Vec<NameTagsRecord> = vec![];
-        // Files are ordered deterministically (but not lexicographically),
-        // so picking out the right files by index is ok.
-        let relevant_files = dir
-            .skip(volume_id.first_address as usize)
-            .take(ENTRIES_PER_VOLUME)
-            .collect::<Result<Vec<_>, _>>()?;
+        let mut oversize_values: Vec<OversizeValue> = vec![];
+        let relevant_files =
+            source.chapter_files(volume_id.first_address as usize, &chapter_id.as_string());
 
         for file in relevant_files {
-            let name = file.file_name();
-            let Some(address) = name.to_str() else {
-                bail!("Couldn't read filename: {}", file.path().display())};
-            // '0xabcd' -> 'ab'
-            let candidate: String = address.to_string().chars().skip(2).take(2).collect();
-            if chapter_id.matches(&candidate) {
-                // Make NameTagsRecord
-                let contents = fs::read(file.path())?;
-                let data: RawValue = serde_json::from_slice(&contents)?;
-                let record = NameTagsRecord {
-                    key: NameTagsRecordKey::from_address(address)?,
-                    value: data.into_record_value(),
-                };
-                records.push(record);
+            // Make NameTagsRecord
+            let contents = fs::read(&file.path)?;
+            let data: RawValue = serde_json::from_slice(&contents)?;
+            let mut check = LimitCheck::new(&file.address, oversize);
+            let value = data.into_record_value(file.source.as_deref(), &mut check);
+            let (keep, found) = check.finish()?;
+            oversize_values.extend(found);
+            if !keep {
+                continue;
             }
+            let record = NameTagsRecord {
+                key: NameTagsRecordKey::from_address(&file.address)?,
+                value,
+            };
+            records.push(record);
         }
         if records.is_empty() {
-            return Ok(None);
+            return Ok((None, oversize_values));
         }
+        let records = canonical_order::<NameTagsSpec>(records)?;
         // Make and return NameTagsChapter{}
-        Ok(Some(NameTagsChapter {
+        let chapter = NameTagsChapter {
             chapter_id: chapter_id.clone(),
             volume_id: volume_id.clone(),
             records: List::from_iter(records),
-        }))
+        };
+        Ok((Some(chapter), oversize_values))
     }
 
-    fn latest_possible_volume(source_dir: &Path) -> Result<NameTagsVolumeId> {
-        let Ok(dir) = read_dir(source_dir) else {bail!("Can't read: {}", source_dir.display())};
-        let count = dir.count() as u32;
+    fn latest_volume_in_source(source: &RawFileIndex<RawFile>) -> Result<NameTagsVolumeId> {
+        let count = source.len() as u32;
         let first_address = first_inside_last(count, ENTRIES_PER_VOLUME as u32)?;
         Ok(NameTagsVolumeId { first_address })
     }
+
+    /// Entries are named by address and source (e.g., "0xabcd...1234.ofac").
+    fn raw_entry_range(
+        volume_id: &NameTagsVolumeId,
+        source: &RawFileIndex<RawFile>,
+    ) -> Result<Option<RawEntryRange>> {
+        source.entry_range(volume_id.first_address as usize, |file| {
+            let name = match &file.source {
+                Some(source) => format!("{}.{}", file.address, source),
+                None => file.address.clone(),
+            };
+            Ok((name, fs::read(&file.path)?))
+        })
+    }
+
+    fn raw_entry_ranges(
+        volume_ids: &[NameTagsVolumeId],
+        source_dir: &Path,
+    ) -> Result<Vec<(NameTagsVolumeId, RawEntryRange)>> {
+        let source = Self::scan_source(source_dir)?;
+        let mut ranges = vec![];
+        for volume_id in volume_ids {
+            if let Some(range) = Self::raw_entry_range(volume_id, &source)? {
+                ranges.push((volume_id.clone(), range));
+            }
+        }
+        Ok(ranges)
+    }
+
+    fn oversize_report(source_dir: &Path) -> Result<Vec<OversizeValue>> {
+        let mut report = vec![];
+        for file in raw_files(source_dir)? {
+            let contents = fs::read(&file.path)?;
+            let data: RawValue = serde_json::from_slice(&contents)?;
+            let mut check = LimitCheck::new(&file.address, OversizePolicy::Truncate);
+            data.into_record_value(file.source.as_deref(), &mut check);
+            report.extend(check.finish()?.1);
+        }
+        Ok(report)
+    }
+
+    /// Checks that each raw file is named by a valid address and contains
+    /// valid JSON.
+    fn validate_raw(source_dir: &Path) -> Result<ValidationReport> {
+        let mut report = ValidationReport::default();
+        let (files, non_utf8) = raw_files_and_non_utf8(source_dir)?;
+        for path in non_utf8 {
+            report.files_checked += 1;
+            report.add(path, "filename is not valid UTF-8");
+        }
+        for file in files {
+            report.files_checked += 1;
+            match hex::decode(file.address.trim_start_matches("0x")) {
+                Ok(bytes) if bytes.len() == 20 => {}
+                Ok(bytes) => report.add(
+                    file.path.clone(),
+                    format!("address has {} bytes, expected 20", bytes.len()),
+                ),
+                Err(e) => report.add(file.path.clone(), format!("invalid address: {}", e)),
+            }
+            let contents = match fs::read(&file.path) {
+                Ok(c) => c,
+                Err(e) => {
+                    report.add(file.path, format!("unreadable: {}", e));
+                    continue;
+                }
+            };
+            if let Err(e) = serde_json::from_slice::<RawValue>(&contents) {
+                report.add(file.path, format!("invalid JSON: {}", e));
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// A raw data file for a single address.
+pub struct RawFile {
+    path: PathBuf,
+    /// E.g., "0xabcd...1234"
+    address: String,
+    /// Namespace for the names and tags in the file. E.g., "ofac".
+    source: Option<String>,
+}
+
+/// Lists the raw data files in the source directory.
+///
+/// Sources (namespaces) are defined by either convention:
+/// - Subdirectory: `<source>/<address>`
+/// - Filename: `<address>.<source>`
+///
+/// Files without a source are placed directly in the source directory
+/// and named by address. Subdirectory contents are listed in place of the
+/// subdirectory.
+///
+/// Files left by other programs (e.g., ".DS_Store") are ignored. Files and
+/// subdirectories with names that are not valid UTF-8 are skipped with a warning.
+fn raw_files(source_dir: &Path) -> Result<Vec<RawFile>> {
+    let (files, non_utf8) = raw_files_and_non_utf8(source_dir)?;
+    for path in non_utf8 {
+        warn!(
+            "Skipping raw data with a name that is not valid UTF-8: {:?}",
+            path
+        );
+    }
+    Ok(files)
+}
+
+/// As for [raw_files], also returning the paths with names that are not
+/// valid UTF-8.
+fn raw_files_and_non_utf8(source_dir: &Path) -> Result<(Vec<RawFile>, Vec<PathBuf>)> {
+    let paths =
+        dir_paths(source_dir).with_context(|| format!("Can't read: {}", source_dir.display()))?;
+    let mut files = vec![];
+    let mut non_utf8 = vec![];
+    for path in paths {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()).map(String::from) else {
+            non_utf8.push(path);
+            continue;
+        };
+        if path.is_dir() {
+            for inner in dir_paths(&path)? {
+                match inner.file_name().and_then(|n| n.to_str()) {
+                    Some(_) => files.push(raw_file(inner, Some(name.clone()))?),
+                    None => non_utf8.push(inner),
+                }
+            }
+        } else {
+            files.push(raw_file(path, None)?);
+        }
+    }
+    Ok((files, non_utf8))
+}
+
+/// Parses the address and source from the filename.
+fn raw_file(path: PathBuf, dir_source: Option<String>) -> Result<RawFile> {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        bail!("Couldn't read filename: {}", path.display())
+    };
+    let (address, file_source) = match name.split_once('.') {
+        Some((address, source)) => (address.to_string(), Some(source.to_string())),
+        None => (name.to_string(), None),
+    };
+    if dir_source.is_some() && file_source.is_some() {
+        bail!(
+            "Source defined by both directory and filename: {}",
+            path.display()
+        )
+    }
+    Ok(RawFile {
+        address,
+        source: dir_source.or(file_source),
+        path,
+    })
 }
 
 /// Gets the global index of the first address in the last volume.
@@ -124,3 +318,191 @@ fn latest_in_sample() {
     assert_eq!(first_inside_last(2000, 1000).unwrap(), 1000);
     assert_eq!(first_inside_last(2001, 1000).unwrap(), 1000);
 }
+
+#[test]
+fn raw_file_sources() {
+    let dir = std::env::temp_dir().join("min_know_test_nametags_sources");
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    fs::create_dir_all(dir.join("ofac")).unwrap();
+    fs::write(dir.join(address), "{}").unwrap();
+    fs::write(dir.join(format!("{}.community", address)), "{}").unwrap();
+    fs::write(dir.join("ofac").join(address), "{}").unwrap();
+
+    let mut sources: Vec<Option<String>> = raw_files(&dir)
+        .unwrap()
+        .into_iter()
+        .inspect(|f| assert_eq!(f.address, address))
+        .map(|f| f.source)
+        .collect();
+    sources.sort();
+    assert_eq!(
+        sources,
+        vec![
+            None,
+            Some("community".to_string()),
+            Some("ofac".to_string())
+        ]
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn chapter_bytes_independent_of_raw_order() {
+    use crate::specs::traits::{ChapterIdMethods, ChapterMethods, RecordKeyMethods};
+
+    let base = std::env::temp_dir().join("min_know_test_nametags_order");
+    let _ = fs::remove_dir_all(&base);
+    let files = [
+        (
+            "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae",
+            r#"{"name":"b"}"#,
+        ),
+        (
+            "0xde00000000000000000000000000000000000001",
+            r#"{"name":"a"}"#,
+        ),
+        (
+            "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae.ofac",
+            r#"{"tags":["c"]}"#,
+        ),
+    ];
+    let (forward, reverse) = (base.join("forward"), base.join("reverse"));
+    fs::create_dir_all(&forward).unwrap();
+    fs::create_dir_all(&reverse).unwrap();
+    for (name, contents) in files.iter() {
+        fs::write(forward.join(name), contents).unwrap();
+    }
+    for (name, contents) in files.iter().rev() {
+        fs::write(reverse.join(name), contents).unwrap();
+    }
+    let chapter_id = NameTagsChapterId::from_interface_id("addresses_0xde").unwrap();
+    let volume_id = NameTagsVolumeId { first_address: 0 };
+    let bytes = |dir: &Path| {
+        NameTagsExtractor::chapter_from_raw(&chapter_id, &volume_id, dir)
+            .unwrap()
+            .unwrap()
+            .as_serialized_bytes()
+            .unwrap()
+    };
+    assert_eq!(bytes(&forward), bytes(&reverse));
+    let chapter = NameTagsExtractor::chapter_from_raw(&chapter_id, &volume_id, &forward)
+        .unwrap()
+        .unwrap();
+    let first_key = chapter.records[0].key.summary_string().unwrap();
+    assert_eq!(format!("0x{}", first_key), files[1].0);
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn oversize_values_follow_policy() {
+    use crate::specs::traits::{ChapterIdMethods, RecordValueMethods};
+
+    let dir = std::env::temp_dir().join("min_know_test_nametags_oversize");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let long_name = "n".repeat(MAX_BYTES_PER_NAME + 1);
+    fs::write(
+        dir.join("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae"),
+        format!(r#"{{"name":"{}","tags":["a"]}}"#, long_name),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("0xde00000000000000000000000000000000000001"),
+        r#"{"name":"short"}"#,
+    )
+    .unwrap();
+    let chapter_id = NameTagsChapterId::from_interface_id("addresses_0xde").unwrap();
+    let volume_id = NameTagsVolumeId { first_address: 0 };
+    let extract = |policy| {
+        NameTagsExtractor::chapter_from_raw_with_policies(
+            &chapter_id,
+            &volume_id,
+            &dir,
+            false,
+            policy,
+        )
+    };
+
+    let (chapter, oversize) = extract(OversizePolicy::Truncate).unwrap();
+    assert_eq!(oversize.len(), 1);
+    assert_eq!(oversize[0].field, "name");
+    assert_eq!(oversize[0].size, MAX_BYTES_PER_NAME + 1);
+    let chapter = chapter.unwrap();
+    assert_eq!(chapter.records.len(), 2);
+    let names: Vec<String> = chapter
+        .records
+        .iter()
+        .flat_map(|r| r.value.names_as_strings().unwrap())
+        .collect();
+    assert!(names.contains(&"n".repeat(MAX_BYTES_PER_NAME)));
+    assert!(chapter.records[1].value.as_serialized_bytes().is_ok());
+
+    let (chapter, oversize) = extract(OversizePolicy::Skip).unwrap();
+    assert_eq!(oversize.len(), 1);
+    assert_eq!(chapter.unwrap().records.len(), 1);
+
+    let err = extract(OversizePolicy::Error).unwrap_err().to_string();
+    assert!(err.contains("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae name"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn added_time_read_from_raw_data() {
+    use crate::specs::traits::ChapterIdMethods;
+
+    let dir = std::env::temp_dir().join("min_know_test_nametags_added");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae"),
+        r#"{"name":"EthDev","added":1438269973}"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("0xde00000000000000000000000000000000000001"),
+        r#"{"name":"short"}"#,
+    )
+    .unwrap();
+    let (chapter, _) = NameTagsExtractor::chapter_from_raw_with_policies(
+        &NameTagsChapterId::from_interface_id("addresses_0xde").unwrap(),
+        &NameTagsVolumeId { first_address: 0 },
+        &dir,
+        false,
+        OversizePolicy::Error,
+    )
+    .unwrap();
+    let added: Vec<Option<u64>> = chapter
+        .unwrap()
+        .records
+        .iter()
+        .map(|r| r.value.added())
+        .collect();
+    assert_eq!(added, vec![None, Some(1438269973)]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn invalid_raw_files_reported() {
+    let dir = std::env::temp_dir().join("min_know_test_nametags_validate");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae"),
+        r#"{"name":"a"}"#,
+    )
+    .unwrap();
+    fs::write(dir.join("0xde01"), r#"{"name":"b"}"#).unwrap();
+    fs::write(
+        dir.join("0xde00000000000000000000000000000000000001"),
+        r#"{"name":"#,
+    )
+    .unwrap();
+    let report = NameTagsExtractor::validate_raw(&dir).unwrap();
+    assert_eq!(report.files_checked, 3);
+    let mut problems: Vec<&str> = report.issues.iter().map(|i| i.problem.as_str()).collect();
+    problems.sort();
+    assert_eq!(problems.len(), 2);
+    assert!(problems[0].starts_with("address has 2 bytes"));
+    assert!(problems[1].starts_with("invalid JSON"));
+    fs::remove_dir_all(dir).unwrap();
+}
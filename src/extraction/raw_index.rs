@@ -0,0 +1,151 @@
+//! An index of raw source files, built once per build.
+//!
+//! For specs with one raw file per Record (nametags, signatures), files are
+//! assigned to Volumes by their position and to Chapters by a prefix of their
+//! name. The index holds the files in sorted order, so positions do not depend on
+//! the order that the file system lists them, and groups them by Volume and
+//! Chapter. Forming a Chapter then only visits the files of that Chapter.
+//!
+//! The position of a file is a global entry index that the raw source only holds
+//! implicitly. [RawFileIndex::entry_range] describes the entries of a Volume
+//! ([RawEntryRange]), which the manifest records so that a rebuild or audit can
+//! confirm the Volume boundaries.
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The raw entries that form a Volume.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct RawEntryRange {
+    /// Global index of the first entry in the Volume.
+    pub first_index: u64,
+    /// Number of entries in the Volume.
+    pub count: u64,
+    /// Hex SHA-256 of the entries, in order. Each entry contributes its name and
+    /// contents, each preceded by its length (u64, little endian).
+    pub content_hash: String,
+}
+
+/// Raw files grouped by Volume and Chapter.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RawFileIndex<F> {
+    files: Vec<F>,
+    per_volume: usize,
+    /// For each Volume, the positions of its files keyed by Chapter prefix.
+    volumes: Vec<HashMap<String, Vec<usize>>>,
+}
+
+impl<F> RawFileIndex<F> {
+    /// Indexes files that are already sorted.
+    ///
+    /// Each Volume holds `per_volume` files. The Chapter prefix of a file
+    /// (e.g., "ab") is given by `chapter_key`.
+    pub fn new(files: Vec<F>, per_volume: usize, chapter_key: impl Fn(&F) -> String) -> Self {
+        let mut volumes: Vec<HashMap<String, Vec<usize>>> = vec![];
+        for (position, file) in files.iter().enumerate() {
+            if position % per_volume == 0 {
+                volumes.push(HashMap::new());
+            }
+            if let Some(volume) = volumes.last_mut() {
+                volume.entry(chapter_key(file)).or_default().push(position);
+            }
+        }
+        RawFileIndex {
+            files,
+            per_volume,
+            volumes,
+        }
+    }
+    /// All files, in order.
+    pub fn files(&self) -> &[F] {
+        &self.files
+    }
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+    /// The files of a Chapter in the Volume that starts at position `first`, in order.
+    pub fn chapter_files<'a>(
+        &'a self,
+        first: usize,
+        chapter_key: &str,
+    ) -> impl Iterator<Item = &'a F> {
+        self.volumes
+            .get(first / self.per_volume)
+            .and_then(|volume| volume.get(chapter_key))
+            .into_iter()
+            .flatten()
+            .map(|position| &self.files[*position])
+    }
+    /// Describes the entries of the Volume that starts at position `first`.
+    ///
+    /// The name and contents of each entry are given by `entry`. Returns None
+    /// if there are no entries at that position.
+    pub fn entry_range(
+        &self,
+        first: usize,
+        entry: impl Fn(&F) -> Result<(String, Vec<u8>)>,
+    ) -> Result<Option<RawEntryRange>> {
+        let Some(files) = self.files.get(first..) else {
+            return Ok(None);
+        };
+        let files = &files[..files.len().min(self.per_volume)];
+        if files.is_empty() {
+            return Ok(None);
+        }
+        let mut hasher = Sha256::new();
+        for file in files {
+            let (name, contents) = entry(file)?;
+            hasher.update((name.len() as u64).to_le_bytes());
+            hasher.update(name.as_bytes());
+            hasher.update((contents.len() as u64).to_le_bytes());
+            hasher.update(&contents);
+        }
+        Ok(Some(RawEntryRange {
+            first_index: first as u64,
+            count: files.len() as u64,
+            content_hash: hex::encode(hasher.finalize()),
+        }))
+    }
+}
+
+#[test]
+fn files_grouped_by_volume_and_chapter() {
+    let files: Vec<String> = ["aa1", "ab1", "aa2", "ab2", "aa3"]
+        .iter()
+        .map(|f| f.to_string())
+        .collect();
+    let index = RawFileIndex::new(files, 2, |f| f[..2].to_string());
+    assert_eq!(index.len(), 5);
+    let chapter = |first, key| index.chapter_files(first, key).cloned().collect::<Vec<_>>();
+    assert_eq!(chapter(0, "aa"), vec!["aa1"]);
+    assert_eq!(chapter(0, "ab"), vec!["ab1"]);
+    assert_eq!(chapter(2, "aa"), vec!["aa2"]);
+    assert_eq!(chapter(4, "aa"), vec!["aa3"]);
+    assert!(chapter(4, "ab").is_empty());
+    assert!(chapter(6, "aa").is_empty());
+}
+
+#[test]
+fn entry_range_follows_position() {
+    let files: Vec<String> = ["aa1", "ab1", "aa2", "ab2", "aa3"]
+        .iter()
+        .map(|f| f.to_string())
+        .collect();
+    let index = RawFileIndex::new(files.clone(), 2, |f| f[..2].to_string());
+    let entry = |f: &String| Ok((f.clone(), f.as_bytes().to_vec()));
+    let first = index.entry_range(0, entry).unwrap().unwrap();
+    assert_eq!((first.first_index, first.count), (0, 2));
+    let last = index.entry_range(4, entry).unwrap().unwrap();
+    assert_eq!((last.first_index, last.count), (4, 1));
+    assert!(index.entry_range(6, entry).unwrap().is_none());
+    // Same entries, same hash. Different entries in the Volume, different hash.
+    let again = RawFileIndex::new(files, 2, |f| f[..2].to_string());
+    assert_eq!(again.entry_range(0, entry).unwrap().unwrap(), first);
+    let second = index.entry_range(2, entry).unwrap().unwrap();
+    assert_ne!(second.content_hash, first.content_hash);
+}
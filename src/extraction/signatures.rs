@@ -1,83 +1,392 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
 use ssz_rs::List;
 use std::{
-    fs::{self, read_dir},
-    path::Path,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
 };
 
 use crate::{
-    parameters::signatures::SIGNATURES_PER_VOLUME,
+    parameters::signatures::{
+        BYTES_FOR_SIGNATURE_CHARS, MAX_BYTES_PER_TEXT, MAX_TEXTS_PER_RECORD, SIGNATURES_PER_VOLUME,
+    },
     specs::signatures::{
         SignaturesChapter, SignaturesChapterId, SignaturesRecord, SignaturesRecordKey,
         SignaturesRecordValue, SignaturesSpec, SignaturesVolumeId, Text,
     },
+    utils::system::dir_paths,
 };
 
-use super::traits::ExtractorMethods;
+use super::{
+    limits::{LimitCheck, OversizePolicy, OversizeValue},
+    raw_index::{RawEntryRange, RawFileIndex},
+    traits::{canonical_order, ExtractedChapter, ExtractorMethods},
+    validation::ValidationReport,
+};
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct SignaturesExtractor {}
 
 impl ExtractorMethods<SignaturesSpec> for SignaturesExtractor {
-    fn chapter_from_raw(
+    /// Paths of the raw files, sorted and grouped by Volume and Chapter.
+    type Source = RawFileIndex<PathBuf>;
+
+    fn scan_source(source_dir: &Path) -> Result<RawFileIndex<PathBuf>> {
+        let paths = raw_paths(source_dir)?;
+        Ok(RawFileIndex::new(paths, SIGNATURES_PER_VOLUME, |path| {
+            // 'abcdef01' -> 'ab'
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            name.chars().take(2 * BYTES_FOR_SIGNATURE_CHARS).collect()
+        }))
+    }
+
+    fn chapter_from_source(
         chapter_id: &SignaturesChapterId,
         volume_id: &SignaturesVolumeId,
-        source_dir: &Path,
-    ) -> Result<Option<SignaturesChapter>> {
-        let Ok(dir) = fs::read_dir(source_dir) else {
-            bail!("Couldn't read dir {}", source_dir.display())};
+        source: &RawFileIndex<PathBuf>,
+        _skip_corrupt: bool,
+        oversize: OversizePolicy,
+    ) -> Result<ExtractedChapter<SignaturesSpec>> {
         // Get appropriate range and appropriate files in that range.
         let mut records: Vec<SignaturesRecord> = vec![];
-        // Files are ordered deterministically (but not lexicographically),
-        // so picking out the right files by index is ok.
-        let relevant_files = dir
-            .skip(volume_id.first_signature as usize)
-            .take(SIGNATURES_PER_VOLUME)
-            .collect::<Result<Vec<_>, _>>()?;
-
-        for file in relevant_files {
-            let name = file.file_name();
-            let Some(signature) = name.to_str() else {
-                bail!("Couldn't read filename: {}", file.path().display())};
-            // 'abcdef01' -> 'abcdef01' and 'abcdef01234567...' -> 'abcdef01'
-            let candidate: String = signature.to_string().chars().take(8).collect();
-
-            if chapter_id.matches(&candidate) {
-                // Make SignaturesRecord
-                let contents = fs::read_to_string(file.path())?;
-                // Format if collisions: "<text>;<text>;<text>"
-                let texts: Vec<Text> = contents.split(';').map(Text::from_string).collect();
-
-                let record = SignaturesRecord {
-                    key: SignaturesRecordKey::from_signature(signature)?,
-                    value: SignaturesRecordValue {
-                        texts: List::from_iter(texts),
-                    },
-                };
-                records.push(record);
+        let mut oversize_values: Vec<OversizeValue> = vec![];
+        let relevant_files =
+            source.chapter_files(volume_id.first_signature as usize, &chapter_id.as_string());
+
+        for path in relevant_files {
+            let Some(signature) = path.file_name().and_then(|n| n.to_str()) else {
+                bail!("Couldn't read filename: {}", path.display())
+            };
+            // Make SignaturesRecord
+            let contents = fs::read_to_string(path)?;
+            let (texts, skipped) = texts_from_raw(signature, &contents);
+            for s in skipped {
+                warn!("Skipping signature text: {}", s);
             }
+            let mut check = LimitCheck::new(signature, oversize);
+            let texts = check.items("texts", texts, MAX_TEXTS_PER_RECORD);
+            let (keep, found) = check.finish()?;
+            oversize_values.extend(found);
+            if texts.is_empty() || !keep {
+                continue;
+            }
+            let texts: Vec<Text> = texts.iter().map(|t| Text::from_string(t)).collect();
+
+            let record = SignaturesRecord {
+                key: SignaturesRecordKey::from_signature(signature)?,
+                value: SignaturesRecordValue::from_texts(texts, added_from_raw(&contents)),
+            };
+            records.push(record);
         }
         if records.is_empty() {
-            return Ok(None);
+            return Ok((None, oversize_values));
         }
+        let records = canonical_order::<SignaturesSpec>(records)?;
         // Make and return SignaturesChapter{}
-        Ok(Some(SignaturesChapter {
+        let chapter = SignaturesChapter {
             chapter_id: chapter_id.clone(),
             volume_id: volume_id.clone(),
             records: List::from_iter(records),
-        }))
+        };
+        Ok((Some(chapter), oversize_values))
     }
 
-    fn latest_possible_volume(source_dir: &Path) -> Result<SignaturesVolumeId> {
-        let Ok(dir) = read_dir(source_dir) else {bail!("Can't read: {}", source_dir.display())};
-        let count = dir.count() as u32;
+    fn latest_volume_in_source(source: &RawFileIndex<PathBuf>) -> Result<SignaturesVolumeId> {
+        let count = source.len() as u32;
         let first_signature = first_inside_last(count, SIGNATURES_PER_VOLUME as u32)?;
         Ok(SignaturesVolumeId { first_signature })
     }
+
+    /// Entries are named by selector (e.g., "a9059cbb").
+    fn raw_entry_range(
+        volume_id: &SignaturesVolumeId,
+        source: &RawFileIndex<PathBuf>,
+    ) -> Result<Option<RawEntryRange>> {
+        source.entry_range(volume_id.first_signature as usize, |path| {
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            Ok((name.to_string(), fs::read(path)?))
+        })
+    }
+
+    fn raw_entry_ranges(
+        volume_ids: &[SignaturesVolumeId],
+        source_dir: &Path,
+    ) -> Result<Vec<(SignaturesVolumeId, RawEntryRange)>> {
+        let source = Self::scan_source(source_dir)?;
+        let mut ranges = vec![];
+        for volume_id in volume_ids {
+            if let Some(range) = Self::raw_entry_range(volume_id, &source)? {
+                ranges.push((volume_id.clone(), range));
+            }
+        }
+        Ok(ranges)
+    }
+
+    fn oversize_report(source_dir: &Path) -> Result<Vec<OversizeValue>> {
+        let mut report = vec![];
+        for path in raw_paths(source_dir)? {
+            let signature = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            let contents = fs::read_to_string(&path)?;
+            let (texts, _skipped) = texts_from_raw(signature, &contents);
+            let mut check = LimitCheck::new(signature, OversizePolicy::Truncate);
+            check.items("texts", texts, MAX_TEXTS_PER_RECORD);
+            report.extend(check.finish()?.1);
+        }
+        Ok(report)
+    }
+
+    /// Checks that each raw file is named by a valid selector and that its
+    /// texts are valid signatures.
+    fn validate_raw(source_dir: &Path) -> Result<ValidationReport> {
+        let paths = dir_paths(source_dir)
+            .with_context(|| format!("Can't read: {}", source_dir.display()))?;
+        let mut report = ValidationReport::default();
+        for path in paths {
+            report.files_checked += 1;
+            let Some(signature) = path.file_name().and_then(|n| n.to_str()) else {
+                report.add(path, "filename is not valid UTF-8");
+                continue;
+            };
+            if let Err(e) = SignaturesRecordKey::from_signature(signature) {
+                report.add(path.clone(), format!("invalid selector: {:#}", e));
+            }
+            let contents = match fs::read_to_string(&path) {
+                Ok(c) => c,
+                Err(e) => {
+                    report.add(path, format!("unreadable: {}", e));
+                    continue;
+                }
+            };
+            let (_texts, skipped) = texts_from_raw(signature, &contents);
+            for s in skipped {
+                let problem = format!("invalid text {:?} ({})", s.text, s.reason);
+                report.add(path.clone(), problem);
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl SignaturesExtractor {
+    /// Checks every raw signature file and reports the texts that
+    /// would be skipped during extraction.
+    pub fn validation_report(source_dir: &Path) -> Result<TextValidationReport> {
+        let mut report = TextValidationReport::default();
+        for path in raw_paths(source_dir)? {
+            let signature = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            let contents = fs::read_to_string(&path)?;
+            let (texts, skipped) = texts_from_raw(signature, &contents);
+            report.accepted += texts.len();
+            report.skipped.extend(skipped);
+        }
+        Ok(report)
+    }
+}
+
+/// Texts that were checked in raw signature data.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextValidationReport {
+    /// Number of texts that are included in the database.
+    pub accepted: usize,
+    /// Texts that are excluded from the database.
+    pub skipped: Vec<SkippedSignature>,
+}
+
+/// A text that was not included in the database.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedSignature {
+    /// Hex selector. E.g., "a9059cbb".
+    pub selector: String,
+    /// The raw text as it appears in the source data.
+    pub text: String,
+    pub reason: SignatureIssue,
+}
+
+impl Display for SkippedSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:?} ({})", self.selector, self.text, self.reason)
+    }
+}
+
+/// Reasons that a text signature is invalid.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureIssue {
+    Empty,
+    NonAscii,
+    /// The function name is not a valid identifier.
+    InvalidName,
+    /// The parameter list is missing or has unbalanced parentheses.
+    MalformedParameters,
+    InvalidCharacter(char),
+    /// Longer than the maximum text length in bytes.
+    TooLong(usize),
+}
+
+impl Display for SignatureIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureIssue::Empty => write!(f, "empty"),
+            SignatureIssue::NonAscii => write!(f, "contains non-ASCII characters"),
+            SignatureIssue::InvalidName => write!(f, "invalid function name"),
+            SignatureIssue::MalformedParameters => write!(f, "malformed parameter list"),
+            SignatureIssue::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            SignatureIssue::TooLong(n) => {
+                write!(f, "{} bytes exceeds maximum of {}", n, MAX_BYTES_PER_TEXT)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SignatureIssue {}
+
+/// Converts a text signature to canonical form, or reports why it is invalid.
+///
+/// Surrounding whitespace and whitespace in the parameter list are removed.
+/// E.g., " transfer(address, uint256) " -> "transfer(address,uint256)".
+pub fn canonicalize_signature(raw: &str) -> Result<String, SignatureIssue> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return Err(SignatureIssue::Empty);
+    }
+    if !trimmed.is_ascii() {
+        return Err(SignatureIssue::NonAscii);
+    }
+    let Some((name, params)) = trimmed.split_once('(') else {
+        return Err(SignatureIssue::MalformedParameters);
+    };
+    let mut name_chars = name.chars();
+    let valid_start =
+        matches!(name_chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$');
+    if !valid_start || !name_chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$') {
+        return Err(SignatureIssue::InvalidName);
+    }
+    let params: String = params
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    // Parameters close the opening parenthesis exactly at the end.
+    let mut depth: usize = 1;
+    for (i, c) in params.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 && i != params.len() - 1 {
+                    return Err(SignatureIssue::MalformedParameters);
+                }
+            }
+            c if c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '[' | ']' | ',') => {}
+            c => return Err(SignatureIssue::InvalidCharacter(c)),
+        }
+    }
+    if depth != 0 {
+        return Err(SignatureIssue::MalformedParameters);
+    }
+    let canonical = format!("{}({}", name, params);
+    if canonical.len() > MAX_BYTES_PER_TEXT {
+        return Err(SignatureIssue::TooLong(canonical.len()));
+    }
+    Ok(canonical)
+}
+
+/// Parses the contents of a raw signature file into canonical texts.
+///
+/// Collisions are separated by ';' ("<text>;<text>"). Empty entries
+/// (e.g., from a trailing separator) are ignored and duplicates removed.
+/// When an entry was added may follow the text (see [added_from_raw]), and is
+/// not part of the text.
+pub(crate) fn texts_from_raw(
+    selector: &str,
+    contents: &str,
+) -> (Vec<String>, Vec<SkippedSignature>) {
+    let mut texts: Vec<String> = vec![];
+    let mut skipped = vec![];
+    for raw in contents.split(';') {
+        if raw.trim().is_empty() {
+            continue;
+        }
+        let (raw, _added) = split_added(raw);
+        match canonicalize_signature(raw) {
+            Ok(text) => {
+                if !texts.contains(&text) {
+                    texts.push(text)
+                }
+            }
+            Err(reason) => skipped.push(SkippedSignature {
+                selector: selector.to_string(),
+                text: raw.to_string(),
+                reason,
+            }),
+        }
+    }
+    (texts, skipped)
+}
+
+/// When the texts in a raw signature file were added: the earliest time given
+/// for any entry.
+///
+/// Entries may record when they were added in seconds since the Unix epoch,
+/// after an '@' ("transfer(address,uint256)@1438269973"). Returns `None` if no
+/// entry does.
+pub(crate) fn added_from_raw(contents: &str) -> Option<u64> {
+    contents
+        .split(';')
+        .filter_map(|raw| split_added(raw).1)
+        .min()
+}
+
+/// Splits a raw entry into the text and when it was added, if given.
+///
+/// A time that is not a whole number is left in the text, which is then invalid.
+fn split_added(raw: &str) -> (&str, Option<u64>) {
+    match raw.rsplit_once('@') {
+        Some((text, added)) => match added.trim().parse::<u64>() {
+            Ok(added) => (text, Some(added)),
+            Err(_) => (raw, None),
+        },
+        None => (raw, None),
+    }
+}
+
+/// The raw files in the source directory, sorted, without files left by other
+/// programs.
+///
+/// Files with names that are not valid UTF-8 cannot be named by a selector (or
+/// topic) and are skipped with a warning. They are reported by `validate_raw`.
+pub(super) fn raw_paths(source_dir: &Path) -> Result<Vec<PathBuf>> {
+    let paths =
+        dir_paths(source_dir).with_context(|| format!("Can't read: {}", source_dir.display()))?;
+    Ok(paths
+        .into_iter()
+        .filter(|path| match path.file_name().and_then(|n| n.to_str()) {
+            Some(_) => true,
+            None => {
+                warn!(
+                    "Skipping raw file with a name that is not valid UTF-8: {:?}",
+                    path
+                );
+                false
+            }
+        })
+        .collect())
 }
 
 /// Gets the global index of the first address in the last volume.
-fn first_inside_last(count: u32, capacity: u32) -> Result<u32> {
+pub(crate) fn first_inside_last(count: u32, capacity: u32) -> Result<u32> {
     if count < capacity {
         bail!(
             "Not enough data to make the first Volume. (need: {}, have: {})",
@@ -99,3 +408,62 @@ fn latest_in_sample() {
     assert_eq!(first_inside_last(2000, 1000).unwrap(), 1000);
     assert_eq!(first_inside_last(2001, 1000).unwrap(), 1000);
 }
+
+#[test]
+fn canonical_signatures() {
+    assert_eq!(
+        canonicalize_signature(" transfer(address, uint256)\n").unwrap(),
+        "transfer(address,uint256)"
+    );
+    assert_eq!(
+        canonicalize_signature("f((uint256,address)[],bytes32[2])").unwrap(),
+        "f((uint256,address)[],bytes32[2])"
+    );
+    assert_eq!(canonicalize_signature("  "), Err(SignatureIssue::Empty));
+    assert_eq!(
+        canonicalize_signature("trånsfer()"),
+        Err(SignatureIssue::NonAscii)
+    );
+    assert_eq!(
+        canonicalize_signature("my func()"),
+        Err(SignatureIssue::InvalidName)
+    );
+    assert_eq!(
+        canonicalize_signature("1f()"),
+        Err(SignatureIssue::InvalidName)
+    );
+    assert_eq!(
+        canonicalize_signature("f(uint256"),
+        Err(SignatureIssue::MalformedParameters)
+    );
+    assert_eq!(
+        canonicalize_signature("f()x"),
+        Err(SignatureIssue::MalformedParameters)
+    );
+    assert_eq!(
+        canonicalize_signature("f(a-b)"),
+        Err(SignatureIssue::InvalidCharacter('-'))
+    );
+}
+
+#[test]
+fn collisions_separated() {
+    let (texts, skipped) = texts_from_raw("abcdef01", "a();b(uint8);;a() ;bad name();");
+    assert_eq!(texts, vec!["a()", "b(uint8)"]);
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].reason, SignatureIssue::InvalidName);
+}
+
+#[test]
+fn added_times_read_from_entries() {
+    let contents = "a()@1500000000;b(uint8) @ 1438269973 ;c()";
+    let (texts, skipped) = texts_from_raw("abcdef01", contents);
+    assert_eq!(texts, vec!["a()", "b(uint8)", "c()"]);
+    assert!(skipped.is_empty());
+    assert_eq!(added_from_raw(contents), Some(1438269973));
+    assert_eq!(added_from_raw("a();b()"), None);
+
+    let (texts, skipped) = texts_from_raw("abcdef01", "a()@yesterday");
+    assert!(texts.is_empty());
+    assert_eq!(skipped.len(), 1);
+}
@@ -1,8 +1,34 @@
 use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 
-use crate::specs::traits::DataSpec;
+use crate::specs::traits::{ChapterMethods, DataSpec, RecordMethods};
+
+use super::{
+    limits::{OversizePolicy, OversizeValue},
+    raw_index::RawEntryRange,
+    validation::ValidationReport,
+};
+
+/// A Chapter formed from raw data (if any matched), with the raw values that
+/// exceeded the size limits of the spec.
+pub type ExtractedChapter<T> = (
+    Option<<T as DataSpec>::AssociatedChapter>,
+    Vec<OversizeValue>,
+);
+
+/// The format of the files in the raw source directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Hash, Deserialize, Serialize)]
+pub enum RawSourceFormat {
+    /// The raw data that the spec describes. E.g., Unchained Index chunk files for
+    /// the address-appearance-index.
+    #[default]
+    Native,
+    /// Appearance lists exported by `chifra export --appearances` (CSV or JSON),
+    /// for the address-appearance-index (see [chifra_export][super::chifra_export]).
+    ChifraExport,
+}
 
 /// A new database must implement this trait.
 ///
@@ -12,7 +38,109 @@ use crate::specs::traits::DataSpec;
 /// Each database has different algorithms for turning raw data into
 /// TODD-compliant data. Each database must provide a
 /// type that implements this trait.
+///
+/// ## Sources
+/// The raw source directory is scanned (e.g., listing and parsing filenames) once
+/// with [Self::scan_source]. The resulting [Self::Source] is then shared by the
+/// creation of every Chapter in a build. Methods that take a source directory
+/// instead scan it on each call, and are convenient for one-off use.
 pub trait ExtractorMethods<T: DataSpec> {
+    /// A raw source directory after it has been scanned. E.g., the raw files grouped
+    /// by Volume and Chapter ([RawFileIndex][super::raw_index::RawFileIndex]).
+    type Source: Send + Sync;
+    /// Scans the raw source directory.
+    fn scan_source(source_dir: &Path) -> Result<Self::Source>;
+    /// Scans a raw source directory that holds data in the given format.
+    ///
+    /// By default only the native format is supported.
+    fn scan_source_as(source_dir: &Path, format: RawSourceFormat) -> Result<Self::Source> {
+        match format {
+            RawSourceFormat::Native => Self::scan_source(source_dir),
+            other => bail!(
+                "Raw source format {:?} is not supported by this database.",
+                other
+            ),
+        }
+    }
+    /// Returns a formed Chapter using raw data from a scanned source directory.
+    ///
+    /// Returns `None` if the are no source files that match the provided IDs.
+    /// This may be the case when processing sample data.
+    ///
+    /// Unreadable raw files are skipped (with a warning) if `skip_corrupt` is set,
+    /// and raw values that exceed the size limits of the spec are handled
+    /// according to the policy. Also returns the raw values that exceeded the
+    /// limits. Databases without variable size values only distinguish `skip_corrupt`.
+    fn chapter_from_source(
+        chapter_id: &T::AssociatedChapterId,
+        volume_id: &T::AssociatedVolumeId,
+        source: &Self::Source,
+        skip_corrupt: bool,
+        oversize: OversizePolicy,
+    ) -> Result<ExtractedChapter<T>>;
+    /// Forms the given Chapters of a Volume from a scanned source, in the order
+    /// of the ChapterIds.
+    ///
+    /// By default each Chapter is formed separately with [Self::chapter_from_source].
+    /// Databases whose raw files each hold data for many Chapters override this
+    /// to read each raw file once per Volume, rather than once per Chapter.
+    fn volume_from_source(
+        volume_id: &T::AssociatedVolumeId,
+        chapter_ids: &[T::AssociatedChapterId],
+        source: &Self::Source,
+        skip_corrupt: bool,
+        oversize: OversizePolicy,
+    ) -> Result<Vec<ExtractedChapter<T>>> {
+        chapter_ids
+            .iter()
+            .map(|c| Self::chapter_from_source(c, volume_id, source, skip_corrupt, oversize))
+            .collect()
+    }
+    /// Forms a Chapter for each of the given Volumes from a scanned source, in the
+    /// order of the VolumeIds.
+    ///
+    /// By default each Chapter is formed separately with [Self::chapter_from_source].
+    /// Databases whose raw files each hold data for many Volumes override this
+    /// to read each relevant raw file once, rather than once per Volume.
+    fn chapter_across_volumes(
+        chapter_id: &T::AssociatedChapterId,
+        volume_ids: &[T::AssociatedVolumeId],
+        source: &Self::Source,
+        skip_corrupt: bool,
+        oversize: OversizePolicy,
+    ) -> Result<Vec<ExtractedChapter<T>>> {
+        volume_ids
+            .iter()
+            .map(|v| Self::chapter_from_source(chapter_id, v, source, skip_corrupt, oversize))
+            .collect()
+    }
+    /// Returns the VolumeId of the latest possible volume that can be made from
+    /// a scanned source directory.
+    ///
+    /// See [Self::latest_possible_volume].
+    fn latest_volume_in_source(source: &Self::Source) -> Result<T::AssociatedVolumeId>;
+    /// Describes the raw entries that form a Volume in a scanned source.
+    ///
+    /// Only databases that assign entries to Volumes by their position in the
+    /// raw source (e.g., nametags) describe ranges, which are recorded in the
+    /// manifest. By default returns None, as Volume membership is explicit in the
+    /// raw data (e.g., block numbers).
+    fn raw_entry_range(
+        _volume_id: &T::AssociatedVolumeId,
+        _source: &Self::Source,
+    ) -> Result<Option<RawEntryRange>> {
+        Ok(None)
+    }
+    /// As for [Self::raw_entry_range], for each of the Volumes, scanning the
+    /// source directory first.
+    ///
+    /// By default returns nothing, without scanning.
+    fn raw_entry_ranges(
+        _volume_ids: &[T::AssociatedVolumeId],
+        _source_dir: &Path,
+    ) -> Result<Vec<(T::AssociatedVolumeId, RawEntryRange)>> {
+        Ok(vec![])
+    }
     /// Returns a formed Chapter using raw data in the provided source directory.
     ///
     /// Returns `None` if the are no source files that match the provided IDs.
@@ -21,7 +149,102 @@ pub trait ExtractorMethods<T: DataSpec> {
         chapter_id: &T::AssociatedChapterId,
         volume_id: &T::AssociatedVolumeId,
         source_dir: &Path,
-    ) -> Result<Option<T::AssociatedChapter>>;
+    ) -> Result<Option<T::AssociatedChapter>> {
+        let (chapter, _oversize) = Self::chapter_from_raw_with_policies(
+            chapter_id,
+            volume_id,
+            source_dir,
+            false,
+            OversizePolicy::default(),
+        )?;
+        Ok(chapter)
+    }
+    /// As for [Self::chapter_from_raw], but raw files that cannot be read are
+    /// skipped (with a warning) rather than causing an error.
+    fn chapter_from_raw_skipping_corrupt(
+        chapter_id: &T::AssociatedChapterId,
+        volume_id: &T::AssociatedVolumeId,
+        source_dir: &Path,
+    ) -> Result<Option<T::AssociatedChapter>> {
+        let (chapter, _oversize) = Self::chapter_from_raw_with_policies(
+            chapter_id,
+            volume_id,
+            source_dir,
+            true,
+            OversizePolicy::default(),
+        )?;
+        Ok(chapter)
+    }
+    /// As for [Self::chapter_from_source], scanning the source directory first.
+    fn chapter_from_raw_with_policies(
+        chapter_id: &T::AssociatedChapterId,
+        volume_id: &T::AssociatedVolumeId,
+        source_dir: &Path,
+        skip_corrupt: bool,
+        oversize: OversizePolicy,
+    ) -> Result<ExtractedChapter<T>> {
+        let source = Self::scan_source(source_dir)?;
+        Self::chapter_from_source(chapter_id, volume_id, &source, skip_corrupt, oversize)
+    }
+    /// As for [Self::chapter_from_source], but the Chapter only holds
+    /// the Records for the given keys (which all belong to the Chapter).
+    ///
+    /// Used to keep a small local database for a few keys (see
+    /// `ConfigStruct::watched_keys`).
+    ///
+    /// By default the full Chapter is formed and then filtered, which requires
+    /// [ChapterMethods::from_records]. Databases that can read the raw data for
+    /// specific keys directly (e.g., with a binary search) override this.
+    fn chapter_from_source_for_keys(
+        chapter_id: &T::AssociatedChapterId,
+        volume_id: &T::AssociatedVolumeId,
+        source: &Self::Source,
+        keys: &[T::AssociatedRecordKey],
+        skip_corrupt: bool,
+        oversize: OversizePolicy,
+    ) -> Result<ExtractedChapter<T>> {
+        let (chapter, oversize_values) =
+            Self::chapter_from_source(chapter_id, volume_id, source, skip_corrupt, oversize)?;
+        let Some(chapter) = chapter else {
+            return Ok((None, oversize_values));
+        };
+        let records: Vec<T::AssociatedRecord> = chapter
+            .records()
+            .iter()
+            .filter(|r| keys.contains(r.key()))
+            .cloned()
+            .collect();
+        let chapter = T::AssociatedChapter::from_records(volume_id, chapter_id, records)?;
+        Ok((Some(chapter), oversize_values))
+    }
+    /// Lists every raw value in the source directory that exceeds the size
+    /// limits of the spec.
+    fn oversize_report(_source_dir: &Path) -> Result<Vec<OversizeValue>> {
+        Ok(vec![])
+    }
+    /// Checks every raw file in the source directory without forming Chapters.
+    ///
+    /// Reports problems that would cause Chapter creation to fail or to leave
+    /// out data (e.g., truncated or malformed files), so that they can be fixed
+    /// before a long build.
+    ///
+    /// ## Errors
+    /// If the source directory cannot be read. Problems with individual files
+    /// are listed in the report.
+    fn validate_raw(source_dir: &Path) -> Result<ValidationReport>;
+    /// As for [Self::validate_raw], for a raw source directory that holds data in
+    /// the given format.
+    ///
+    /// By default only the native format is supported.
+    fn validate_raw_as(source_dir: &Path, format: RawSourceFormat) -> Result<ValidationReport> {
+        match format {
+            RawSourceFormat::Native => Self::validate_raw(source_dir),
+            other => bail!(
+                "Raw source format {:?} is not supported by this database.",
+                other
+            ),
+        }
+    }
     /// Returns the VolumeId of the latest possible volume that can be made from
     /// the available raw data.
     ///
@@ -31,5 +254,22 @@ pub trait ExtractorMethods<T: DataSpec> {
     /// If volumes are produce every 100 units of data (0-99, 100-199, ...),
     /// and the raw data has 340 units. Then the latest will exclude the
     /// incomplete 40 and return the id for volume 200-299.
-    fn latest_possible_volume(source_dir: &Path) -> Result<T::AssociatedVolumeId>;
+    fn latest_possible_volume(source_dir: &Path) -> Result<T::AssociatedVolumeId> {
+        Self::latest_volume_in_source(&Self::scan_source(source_dir)?)
+    }
+}
+
+/// Sorts Records into the canonical order for a Chapter.
+///
+/// Records are ordered by their SSZ encoding, so that a Chapter (and its CID)
+/// does not depend on the order that raw files are read.
+pub(crate) fn canonical_order<T: DataSpec>(
+    records: Vec<T::AssociatedRecord>,
+) -> Result<Vec<T::AssociatedRecord>> {
+    let mut keyed = records
+        .into_iter()
+        .map(|r| Ok((r.as_serialized_bytes()?, r)))
+        .collect::<Result<Vec<_>>>()?;
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(keyed.into_iter().map(|(_bytes, r)| r).collect())
 }
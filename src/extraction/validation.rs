@@ -0,0 +1,55 @@
+//! Checks of raw data that can be made before a database is built.
+//!
+//! Problems in raw data (e.g., a truncated chunk file or invalid JSON) otherwise
+//! only appear when the Chapters that use that data are created, which may be
+//! hours into a build. See [super::traits::ExtractorMethods::validate_raw].
+use std::{fmt::Display, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// The result of checking the raw data in a source directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    /// Number of raw files checked.
+    pub files_checked: usize,
+    /// Problems found, in the order the files were checked.
+    pub issues: Vec<RawIssue>,
+}
+
+impl ValidationReport {
+    /// Whether no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+    pub(crate) fn add(&mut self, path: PathBuf, problem: impl Display) {
+        self.issues.push(RawIssue {
+            path,
+            problem: problem.to_string(),
+        })
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} raw files checked, {} issues",
+            self.files_checked,
+            self.issues.len()
+        )
+    }
+}
+
+/// A problem with a raw file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawIssue {
+    pub path: PathBuf,
+    /// E.g., "expected 4096 bytes, found 2048".
+    pub problem: String,
+}
+
+impl Display for RawIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.path, self.problem)
+    }
+}
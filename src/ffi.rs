@@ -0,0 +1,366 @@
+//! A C interface to the query methods, for applications not written in Rust
+//! (e.g., mobile wallets). Requires the `ffi` feature.
+//!
+//! Build a shared library with:
+//! ```sh
+//! cargo rustc --release --features ffi --lib --crate-type cdylib
+//! ```
+//!
+//! Each function takes C strings and returns a JSON string, or NULL on error
+//! (including a panic, which does not unwind into the caller).
+//! The layout of the values of each database is given by [min_know_value_schema].
+//! The message for the most recent error on the calling thread is returned by
+//! [min_know_last_error]. Every returned string must be released with
+//! [min_know_string_free].
+//!
+//! The `database` argument is the database interface id, e.g., "nametags".
+//! The address appearance index uses the default network. The `base_dir` is the
+//! directory that holds the manifest and the data directory. Databases are
+//! opened read only (see `config.read_only`), so these functions never write.
+//! Chapters are read from the file system, there is no in-memory storage (e.g., for WASM).
+//!
+//! ## Example
+//! ```c
+//! char* json = min_know_find("nametags", "/data/min-know", "0xde0b...7bae");
+//! if (json == NULL) {
+//!     char* error = min_know_last_error();
+//!     // ...
+//!     min_know_string_free(error);
+//! }
+//! min_know_string_free(json);
+//! ```
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::choices::{DataKind, DirNature, PathPair},
+    database::types::Todd,
+    specs::{
+        address_appearance_index::AAISpec,
+        events::EventsSpec,
+        nametags::NameTagsSpec,
+        signatures::SignaturesSpec,
+        traits::{
+            ChapterIdMethods, DataSpec, ManifestMethods, RecordValueMethods, VolumeCoverage,
+            VolumeIdMethods,
+        },
+    },
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// A Volume held locally, returned by [min_know_coverage].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeldVolume {
+    pub volume_interface_id: String,
+    /// The raw data covered, if the spec defines it.
+    pub coverage: Option<VolumeCoverage>,
+    /// Number of Chapter files held for the Volume.
+    pub chapters: usize,
+}
+
+/// The contents of a manifest, returned by [min_know_parse_manifest].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestSummary {
+    pub database_interface_id: String,
+    pub spec_version: String,
+    pub latest_volume_identifier: String,
+    /// Interface ids of the Volumes listed, in order.
+    pub volumes: Vec<String>,
+    /// Interface ids of the Chapters listed, in order.
+    pub chapters: Vec<String>,
+    /// Number of Chapter files listed.
+    pub files: usize,
+}
+
+/// Finds the values for a key, as a JSON list of value summaries
+/// (see [RecordValueMethods::summary]).
+///
+/// # Safety
+/// The arguments must be NULL or valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn min_know_find(
+    database: *const c_char,
+    base_dir: *const c_char,
+    key: *const c_char,
+) -> *mut c_char {
+    respond(|| {
+        let key = read_str(key, "key")?;
+        let base_dir = read_str(base_dir, "base_dir")?;
+        match data_kind(read_str(database, "database")?)? {
+            k @ DataKind::AddressAppearanceIndex(_) => find_json::<AAISpec>(k, base_dir, key),
+            k @ DataKind::NameTags => find_json::<NameTagsSpec>(k, base_dir, key),
+            k @ DataKind::Signatures => find_json::<SignaturesSpec>(k, base_dir, key),
+            k @ DataKind::Events => find_json::<EventsSpec>(k, base_dir, key),
+        }
+    })
+}
+
+/// The Volumes held locally, as a JSON list of [HeldVolume], in order.
+///
+/// # Safety
+/// The arguments must be NULL or valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn min_know_coverage(
+    database: *const c_char,
+    base_dir: *const c_char,
+) -> *mut c_char {
+    respond(|| {
+        let base_dir = read_str(base_dir, "base_dir")?;
+        match data_kind(read_str(database, "database")?)? {
+            k @ DataKind::AddressAppearanceIndex(_) => coverage_json::<AAISpec>(k, base_dir),
+            k @ DataKind::NameTags => coverage_json::<NameTagsSpec>(k, base_dir),
+            k @ DataKind::Signatures => coverage_json::<SignaturesSpec>(k, base_dir),
+            k @ DataKind::Events => coverage_json::<EventsSpec>(k, base_dir),
+        }
+    })
+}
+
+/// Parses the text of a manifest, returning a [ManifestSummary] as JSON.
+///
+/// # Safety
+/// The arguments must be NULL or valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn min_know_parse_manifest(
+    database: *const c_char,
+    manifest_json: *const c_char,
+) -> *mut c_char {
+    respond(|| {
+        let text = read_str(manifest_json, "manifest_json")?;
+        match data_kind(read_str(database, "database")?)? {
+            DataKind::AddressAppearanceIndex(_) => manifest_json_summary::<AAISpec>(text),
+            DataKind::NameTags => manifest_json_summary::<NameTagsSpec>(text),
+            DataKind::Signatures => manifest_json_summary::<SignaturesSpec>(text),
+            DataKind::Events => manifest_json_summary::<EventsSpec>(text),
+        }
+    })
+}
+
+/// Describes the JSON layout of each value returned by [min_know_find], as a
+/// [ValueSchema][crate::specs::value_schema::ValueSchema] (see [DataSpec::value_schema]).
+///
+/// # Safety
+/// The argument must be NULL or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn min_know_value_schema(database: *const c_char) -> *mut c_char {
+    respond(|| {
+        let schema = match data_kind(read_str(database, "database")?)? {
+            DataKind::AddressAppearanceIndex(_) => AAISpec::value_schema(),
+            DataKind::NameTags => NameTagsSpec::value_schema(),
+            DataKind::Signatures => SignaturesSpec::value_schema(),
+            DataKind::Events => EventsSpec::value_schema(),
+        };
+        Ok(serde_json::to_string(&schema)?)
+    })
+}
+
+/// The message of the most recent error on this thread, or NULL if there was none.
+#[no_mangle]
+pub extern "C" fn min_know_last_error() -> *mut c_char {
+    LAST_ERROR.with(|e| match e.borrow().as_deref() {
+        Some(message) => into_c_string(message),
+        None => std::ptr::null_mut(),
+    })
+}
+
+/// Releases a string returned by this library.
+///
+/// # Safety
+/// The string must have been returned by this library and not already released.
+#[no_mangle]
+pub unsafe extern "C" fn min_know_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Runs a request, recording any error for [min_know_last_error].
+///
+/// A panic is caught and recorded as an error, as unwinding across the C
+/// boundary is undefined behaviour.
+fn respond<F>(request: F) -> *mut c_char
+where
+    F: FnOnce() -> Result<String>,
+{
+    let message = match catch_unwind(AssertUnwindSafe(request)) {
+        Ok(Ok(json)) => return into_c_string(&json),
+        Ok(Err(e)) => format!("{:#}", e),
+        Err(panic) => {
+            let reason = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown reason".to_string());
+            format!("Panicked: {}", reason)
+        }
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    std::ptr::null_mut()
+}
+
+fn into_c_string(s: &str) -> *mut c_char {
+    // JSON and error messages do not contain NUL.
+    CString::new(s.replace('\0', ""))
+        .map(CString::into_raw)
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// # Safety
+/// The pointer must be NULL or a valid NUL-terminated string.
+unsafe fn read_str<'a>(s: *const c_char, name: &str) -> Result<&'a str> {
+    if s.is_null() {
+        bail!("The {} argument is NULL.", name)
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|e| anyhow!("The {} argument is not UTF-8: {}", name, e))
+}
+
+/// The DataKind for a database interface id.
+fn data_kind(database: &str) -> Result<DataKind> {
+    DataKind::from_name(database)
+}
+
+/// Opens a database in the directory, read only.
+fn open<T: DataSpec + Default + Sync>(data_kind: DataKind, base_dir: &str) -> Result<Todd<T>> {
+    let paths = PathPair {
+        raw_source: None,
+        processed_data_dir: Some(PathBuf::from(base_dir)),
+    };
+    let mut db: Todd<T> = Todd::init(data_kind, DirNature::Custom(paths))?;
+    db.config.read_only = true;
+    Ok(db)
+}
+
+fn find_json<T: DataSpec + Default + Sync>(
+    data_kind: DataKind,
+    base_dir: &str,
+    key: &str,
+) -> Result<String> {
+    let summaries = open::<T>(data_kind, base_dir)?
+        .find(key)?
+        .iter()
+        .map(|value| value.summary())
+        .collect::<Result<Vec<_>>>()?;
+    Ok(serde_json::to_string(&summaries)?)
+}
+
+fn coverage_json<T: DataSpec + Default + Sync>(
+    data_kind: DataKind,
+    base_dir: &str,
+) -> Result<String> {
+    let mut held: Vec<HeldVolume> = vec![];
+    for (volume_id, _chapter_id, _path, _size) in open::<T>(data_kind, base_dir)?.inventory()? {
+        let volume_interface_id = volume_id.interface_id();
+        match held.last_mut() {
+            Some(v) if v.volume_interface_id == volume_interface_id => v.chapters += 1,
+            _ => held.push(HeldVolume {
+                volume_interface_id,
+                coverage: volume_id.coverage(),
+                chapters: 1,
+            }),
+        }
+    }
+    Ok(serde_json::to_string(&held)?)
+}
+
+fn manifest_json_summary<T: DataSpec>(text: &str) -> Result<String> {
+    let manifest: T::AssociatedManifest = serde_json::from_str(text)?;
+    let cids = manifest.cids()?;
+    let mut summary = ManifestSummary {
+        database_interface_id: manifest.database_interface_id().to_string(),
+        spec_version: manifest.spec_version().to_string(),
+        latest_volume_identifier: manifest.latest_volume_identifier().to_string(),
+        volumes: vec![],
+        chapters: vec![],
+        files: cids.len(),
+    };
+    for m in cids {
+        let volume = m.volume_id.interface_id();
+        if !summary.volumes.contains(&volume) {
+            summary.volumes.push(volume);
+        }
+        let chapter = m.chapter_id.interface_id();
+        if !summary.chapters.contains(&chapter) {
+            summary.chapters.push(chapter);
+        }
+    }
+    Ok(serde_json::to_string(&summary)?)
+}
+
+#[test]
+fn queries_through_c_interface() {
+    use crate::specs::value_schema::ValueSchema;
+
+    let sample: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Sample).unwrap();
+    let base_dir = sample.config.base_dir_nature_dependent.to_str().unwrap();
+    let c = |s: &str| CString::new(s).unwrap();
+    let take = |ptr: *mut c_char| {
+        assert!(!ptr.is_null());
+        let s = unsafe { CStr::from_ptr(ptr) }.to_str().unwrap().to_string();
+        unsafe { min_know_string_free(ptr) };
+        s
+    };
+    let (database, base_dir) = (c("nametags"), c(base_dir));
+    let key = c("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae");
+
+    let found = take(unsafe { min_know_find(database.as_ptr(), base_dir.as_ptr(), key.as_ptr()) });
+    let expected: Vec<_> = sample
+        .find("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae")
+        .unwrap()
+        .iter()
+        .map(|v| v.summary().unwrap())
+        .collect();
+    assert!(!expected.is_empty());
+    assert_eq!(found, serde_json::to_string(&expected).unwrap());
+    let schema: ValueSchema =
+        serde_json::from_str(&take(unsafe { min_know_value_schema(database.as_ptr()) })).unwrap();
+    assert!(ValueSchema::list_of(schema).describes(&serde_json::from_str(&found).unwrap()));
+
+    let held: Vec<HeldVolume> = serde_json::from_str(&take(unsafe {
+        min_know_coverage(database.as_ptr(), base_dir.as_ptr())
+    }))
+    .unwrap();
+    assert_eq!(held[0].volume_interface_id, "nametags_from_000_000_000");
+    assert_eq!(held.len(), 2);
+
+    let text = std::fs::read_to_string(sample.config.manifest_file_path().unwrap()).unwrap();
+    let manifest = c(&text);
+    let summary: ManifestSummary = serde_json::from_str(&take(unsafe {
+        min_know_parse_manifest(database.as_ptr(), manifest.as_ptr())
+    }))
+    .unwrap();
+    assert_eq!(
+        summary.volumes,
+        vec!["nametags_from_000_000_000", "nametags_from_000_001_000"]
+    );
+    assert_eq!(summary.files, 512);
+
+    let unknown = c("unknown");
+    assert!(unsafe { min_know_find(unknown.as_ptr(), base_dir.as_ptr(), key.as_ptr()) }.is_null());
+    assert!(take(min_know_last_error()).contains("Unknown database"));
+    assert!(unsafe { min_know_find(database.as_ptr(), std::ptr::null(), key.as_ptr()) }.is_null());
+    assert!(take(min_know_last_error()).contains("base_dir argument is NULL"));
+}
+
+#[test]
+fn panics_are_returned_as_errors() {
+    let json = respond(|| panic!("Chapter {} is malformed", "addresses_0xde"));
+    assert!(json.is_null());
+    let error = min_know_last_error();
+    let message = unsafe { CStr::from_ptr(error) }
+        .to_str()
+        .unwrap()
+        .to_string();
+    unsafe { min_know_string_free(error) };
+    assert_eq!(message, "Panicked: Chapter addresses_0xde is malformed");
+}
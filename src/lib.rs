@@ -2,6 +2,8 @@
 pub mod config;
 pub mod database;
 pub(crate) mod extraction;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod manifest;
 pub(crate) mod parameters;
 pub(crate) mod samples;
@@ -0,0 +1,262 @@
+//! Checks a manifest against the expected schema before it is used or published.
+//!
+//! Deserializing a manifest only reports the first problem and does not say
+//! which Chapter entry is at fault. Linting reports every problem found, each
+//! naming the entry (e.g., `chapter_cids[12].cid_v0`).
+use std::collections::HashSet;
+
+use anyhow::Result;
+use cid::{Cid, Version};
+use serde_json::{Map, Value};
+
+use crate::specs::traits::{ChapterIdMethods, DataSpec, VolumeIdMethods};
+
+/// A problem found in a manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestIssue {
+    /// The field or entry with the problem. E.g., `chapter_cids[3].cid_v0`.
+    pub location: String,
+    pub problem: String,
+}
+
+impl std::fmt::Display for ManifestIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.problem)
+    }
+}
+
+/// Lists the problems in a JSON manifest for the spec.
+///
+/// ## Algorithm
+/// - Required fields must be present with the correct type.
+/// - The spec version and database interface id must match those expected.
+/// - The schema fingerprint, if present, must match that of the library (for the
+///   same spec version).
+/// - Each CID must be a valid CIDv0.
+/// - Each Volume and Chapter interface id must be in the form defined by the spec.
+/// - Each (Volume, Chapter) pair must only be listed once.
+///
+/// ## Errors
+/// Only if the text is not JSON. Schema problems are returned as issues.
+pub fn lint_manifest<T: DataSpec>(
+    json: &str,
+    database_interface_id: &str,
+) -> Result<Vec<ManifestIssue>> {
+    let value: Value = serde_json::from_str(json)?;
+    let mut issues = vec![];
+    let Value::Object(manifest) = value else {
+        issue(&mut issues, "manifest", "expected a JSON object");
+        return Ok(issues);
+    };
+    let version = string_field(&manifest, "spec_version", "", &mut issues);
+    if let Some(version) = version {
+        if version != T::spec_version() {
+            let problem = format!("expected \"{}\", found \"{}\"", T::spec_version(), version);
+            issue(&mut issues, "spec_version", &problem);
+        }
+    }
+    if manifest.contains_key("schema_fingerprint") {
+        let found = string_field(&manifest, "schema_fingerprint", "", &mut issues);
+        let expected = T::schema_fingerprint();
+        // Other spec versions have other Chapter types.
+        let same_version = version == Some(T::spec_version().as_str());
+        if let Some(found) = found.filter(|f| same_version && *f != expected) {
+            let problem = format!("expected \"{}\", found \"{}\"", expected, found);
+            issue(&mut issues, "schema_fingerprint", &problem);
+        }
+    }
+    string_field(&manifest, "schemas", "", &mut issues);
+    if let Some(id) = string_field(&manifest, "database_interface_id", "", &mut issues) {
+        if id != database_interface_id {
+            let problem = format!("expected \"{}\", found \"{}\"", database_interface_id, id);
+            issue(&mut issues, "database_interface_id", &problem);
+        }
+    }
+    if let Some(id) = string_field(&manifest, "latest_volume_identifier", "", &mut issues) {
+        check_volume_id::<T>(id, "latest_volume_identifier", &mut issues);
+    }
+    match manifest.get("chapter_cids") {
+        Some(entries) => check_entries::<T>(entries, "chapter_cids", &mut issues),
+        None => issue(&mut issues, "chapter_cids", "missing"),
+    }
+    if let Some(entries) = manifest.get("bloom_filter_cids") {
+        check_entries::<T>(entries, "bloom_filter_cids", &mut issues);
+    }
+    if let Some(entries) = manifest.get("errata_cids") {
+        check_entries::<T>(entries, "errata_cids", &mut issues);
+    }
+    Ok(issues)
+}
+
+fn issue(issues: &mut Vec<ManifestIssue>, location: &str, problem: &str) {
+    issues.push(ManifestIssue {
+        location: location.to_string(),
+        problem: problem.to_string(),
+    })
+}
+
+/// Gets a required string field, recording an issue if it is absent or not a string.
+fn string_field<'a>(
+    object: &'a Map<String, Value>,
+    name: &str,
+    prefix: &str,
+    issues: &mut Vec<ManifestIssue>,
+) -> Option<&'a str> {
+    let location = format!("{}{}", prefix, name);
+    match object.get(name) {
+        Some(Value::String(s)) => Some(s),
+        Some(other) => {
+            issue(
+                issues,
+                &location,
+                &format!("expected a string, found {}", other),
+            );
+            None
+        }
+        None => {
+            issue(issues, &location, "missing");
+            None
+        }
+    }
+}
+
+fn check_entries<T: DataSpec>(entries: &Value, name: &str, issues: &mut Vec<ManifestIssue>) {
+    let Value::Array(entries) = entries else {
+        issue(issues, name, "expected an array");
+        return;
+    };
+    let mut seen: HashSet<(&str, &str)> = HashSet::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let prefix = format!("{}[{}].", name, index);
+        let Value::Object(entry) = entry else {
+            issue(
+                issues,
+                &format!("{}[{}]", name, index),
+                "expected an object",
+            );
+            continue;
+        };
+        let volume = string_field(entry, "volume_interface_id", &prefix, issues);
+        if let Some(v) = volume {
+            check_volume_id::<T>(v, &format!("{}volume_interface_id", prefix), issues);
+        }
+        let chapter = string_field(entry, "chapter_interface_id", &prefix, issues);
+        if let Some(c) = chapter {
+            check_chapter_id::<T>(c, &format!("{}chapter_interface_id", prefix), issues);
+        }
+        if let Some(cid) = string_field(entry, "cid_v0", &prefix, issues) {
+            if !is_cid_v0(cid) {
+                let problem = format!("\"{}\" is not a valid CIDv0", cid);
+                issue(issues, &format!("{}cid_v0", prefix), &problem);
+            }
+        }
+        if let (Some(v), Some(c)) = (volume, chapter) {
+            if !seen.insert((v, c)) {
+                let location = format!("{}[{}]", name, index);
+                issue(
+                    issues,
+                    &location,
+                    &format!("duplicate entry for {} {}", v, c),
+                );
+            }
+        }
+    }
+}
+
+/// Records an issue unless the id parses and is in canonical form.
+fn check_volume_id<T: DataSpec>(id: &str, location: &str, issues: &mut Vec<ManifestIssue>) {
+    match T::AssociatedVolumeId::from_interface_id(id) {
+        Ok(v) if v.interface_id() == id => {}
+        Ok(v) => {
+            let problem = format!(
+                "\"{}\" is not canonical, expected \"{}\"",
+                id,
+                v.interface_id()
+            );
+            issue(issues, location, &problem)
+        }
+        Err(e) => issue(
+            issues,
+            location,
+            &format!("invalid VolumeId \"{}\": {}", id, e),
+        ),
+    }
+}
+
+/// Records an issue unless the id parses and is in canonical form.
+fn check_chapter_id<T: DataSpec>(id: &str, location: &str, issues: &mut Vec<ManifestIssue>) {
+    match T::AssociatedChapterId::from_interface_id(id) {
+        Ok(c) if c.interface_id() == id => {}
+        Ok(c) => {
+            let problem = format!(
+                "\"{}\" is not canonical, expected \"{}\"",
+                id,
+                c.interface_id()
+            );
+            issue(issues, location, &problem)
+        }
+        Err(e) => issue(
+            issues,
+            location,
+            &format!("invalid ChapterId \"{}\": {}", id, e),
+        ),
+    }
+}
+
+fn is_cid_v0(cid: &str) -> bool {
+    matches!(Cid::try_from(cid), Ok(c) if c.version() == Version::V0)
+}
+
+#[test]
+fn lint_names_offending_entries() {
+    use crate::specs::nametags::NameTagsSpec;
+
+    let json = format!(
+        r#"{{
+        "spec_version": "{}",
+        "database_interface_id": "nametags",
+        "latest_volume_identifier": "nametags_from_000_001_000",
+        "chapter_cids": [
+            {{"volume_interface_id": "nametags_from_000_000_000", "chapter_interface_id": "addresses_0x00", "cid_v0": "QmNpXdysAvS9PzEjnG6WeX18G9pxAa1mwL6TePrttV7XUM"}},
+            {{"volume_interface_id": "nametags_from_000_000_000", "chapter_interface_id": "addresses_0x00", "cid_v0": "QmNpXdysAvS9PzEjnG6WeX18G9pxAa1mwL6TePrttV7XUM"}},
+            {{"volume_interface_id": "volume_1", "chapter_interface_id": "addresses_0x01", "cid_v0": "not_a_cid"}}
+        ]
+    }}"#,
+        NameTagsSpec::spec_version()
+    );
+    let issues = lint_manifest::<NameTagsSpec>(&json, "nametags").unwrap();
+    let locations: Vec<&str> = issues.iter().map(|i| i.location.as_str()).collect();
+    assert_eq!(
+        locations,
+        vec![
+            "schemas",
+            "chapter_cids[1]",
+            "chapter_cids[2].volume_interface_id",
+            "chapter_cids[2].cid_v0"
+        ]
+    );
+    let issues = lint_manifest::<NameTagsSpec>(&json, "signatures").unwrap();
+    assert!(issues.iter().any(|i| i.location == "database_interface_id"));
+}
+
+#[test]
+fn lint_checks_schema_fingerprint() {
+    use crate::specs::nametags::NameTagsSpec;
+
+    let json = |version: &str, fingerprint: &str| {
+        format!(
+            r#"{{"spec_version": "{}", "schema_fingerprint": "{}", "schemas": "", "database_interface_id": "nametags", "latest_volume_identifier": "nametags_from_000_000_000", "chapter_cids": []}}"#,
+            version, fingerprint
+        )
+    };
+    let current = NameTagsSpec::spec_version();
+    let lint = |json: String| lint_manifest::<NameTagsSpec>(&json, "nametags").unwrap();
+    assert!(lint(json(&current, &NameTagsSpec::schema_fingerprint())).is_empty());
+    let issues = lint(json(&current, "00"));
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].location, "schema_fingerprint");
+    // Only the spec version is reported for a manifest of another version.
+    let issues = lint(json("0.0.1", "00"));
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].location, "spec_version");
+}
@@ -0,0 +1,229 @@
+//! Descriptors that partial mirrors publish to advertise the files they hold.
+//!
+//! A community pinner may only host some Chapters (e.g., "addresses_0x00" to
+//! "addresses_0x1f"). The pinner publishes a [MirrorHoldings] descriptor
+//! alongside their pins, listing the Chapters (and any other files) held and
+//! where to fetch them. A user combines several descriptors (see [assign_files])
+//! to obtain a plan from many partial mirrors.
+//!
+//! Descriptors are for one manifest, identified by CID. Files obtained through a
+//! descriptor are checked against the CIDs in the local manifest, so a descriptor
+//! cannot substitute data. A descriptor may be signed by the mirror operator
+//! with an Ethereum key (see [MirrorHoldings::sign], which requires the `web3`
+//! feature) to attribute it.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use crate::{database::acquisition::PlannedFile, manifest::mirrors::ManifestMirrors};
+
+/// The files a mirror holds, for one manifest.
+///
+/// Created by [Todd::describe_holdings][crate::database::types::Todd::describe_holdings].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorHoldings {
+    /// E.g., "nametags".
+    pub database_interface_id: String,
+    /// CID of the manifest that lists the files.
+    pub manifest_cid: String,
+    /// Where the held files can be fetched.
+    pub mirrors: ManifestMirrors,
+    /// Interface ids of Chapters of which every file in the manifest is held.
+    pub chapters: Vec<String>,
+    /// CIDs of files held in Chapters that are only held in part.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    /// Signature of the mirror operator over [Self::signing_hash].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<HoldingsSignature>,
+}
+
+/// A secp256k1 signature of a [MirrorHoldings] descriptor.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HoldingsSignature {
+    /// Ethereum address of the signer. E.g., "0x7b2c...".
+    pub signer: String,
+    /// 65 bytes as hex: r, s, then the recovery id (0 or 1).
+    pub signature: String,
+}
+
+impl MirrorHoldings {
+    /// Whether the mirror holds a file of the manifest.
+    pub fn holds(&self, file: &PlannedFile) -> bool {
+        self.chapters.contains(&file.chapter_interface_id) || self.files.contains(&file.cid)
+    }
+    /// The keccak256 hash of the descriptor without its signature, which is
+    /// what the mirror operator signs.
+    pub fn signing_hash(&self) -> Result<[u8; 32]> {
+        let unsigned = MirrorHoldings {
+            signature: None,
+            ..self.clone()
+        };
+        let bytes = serde_json::to_vec(&unsigned)?;
+        Ok(Keccak256::digest(bytes).into())
+    }
+}
+
+#[cfg(feature = "web3")]
+impl MirrorHoldings {
+    /// Signs the descriptor, replacing any signature. Requires the `web3` feature.
+    pub fn sign<K: web3::signing::Key>(&mut self, key: K) -> Result<()> {
+        let hash = self.signing_hash()?;
+        let signed = key
+            .sign_message(&hash)
+            .map_err(|e| anyhow::anyhow!("Unable to sign holdings: {}", e))?;
+        let mut bytes = signed.r.as_bytes().to_vec();
+        bytes.extend_from_slice(signed.s.as_bytes());
+        bytes.push(signed.v as u8);
+        self.signature = Some(HoldingsSignature {
+            signer: format!("{:?}", key.address()),
+            signature: hex::encode(bytes),
+        });
+        Ok(())
+    }
+    /// The signer of the descriptor, or None if it is not signed. Requires
+    /// the `web3` feature.
+    ///
+    /// ## Errors
+    /// If the signature is malformed or was not made by the stated signer
+    /// over this descriptor.
+    pub fn verified_signer(&self) -> Result<Option<String>> {
+        let Some(signature) = &self.signature else {
+            return Ok(None);
+        };
+        let bytes = hex::decode(signature.signature.trim_start_matches("0x"))?;
+        let [rs @ .., recovery_id] = bytes.as_slice() else {
+            anyhow::bail!("The holdings signature is empty.")
+        };
+        let recovered = web3::signing::recover(&self.signing_hash()?, rs, *recovery_id as i32)
+            .map_err(|e| anyhow::anyhow!("Invalid holdings signature: {}", e))?;
+        let recovered = format!("{:?}", recovered);
+        if !recovered.eq_ignore_ascii_case(&signature.signer) {
+            anyhow::bail!(
+                "The holdings are signed by {}, not the stated signer {}.",
+                recovered,
+                signature.signer
+            )
+        }
+        Ok(Some(recovered))
+    }
+}
+
+/// The descriptors to obtain the files of a plan from. Made by [assign_files].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorAssignment {
+    /// Files to obtain using each descriptor, in the order the descriptors were given.
+    pub by_descriptor: Vec<Vec<PlannedFile>>,
+    /// Files that no descriptor holds.
+    pub unheld: Vec<PlannedFile>,
+}
+
+/// Assigns each file to a descriptor that holds it.
+///
+/// Files are spread across the mirrors: each goes to the descriptor holding it
+/// that has the fewest files assigned so far (the earliest, if tied).
+pub fn assign_files(files: &[PlannedFile], holdings: &[MirrorHoldings]) -> MirrorAssignment {
+    assign_files_except(files, holdings, |_, _| false)
+}
+
+/// As for [assign_files], but never assigns a file to a descriptor (by position)
+/// for which `excluded` is true.
+pub(crate) fn assign_files_except<F>(
+    files: &[PlannedFile],
+    holdings: &[MirrorHoldings],
+    excluded: F,
+) -> MirrorAssignment
+where
+    F: Fn(usize, &PlannedFile) -> bool,
+{
+    let mut assignment = MirrorAssignment {
+        by_descriptor: vec![vec![]; holdings.len()],
+        unheld: vec![],
+    };
+    for file in files {
+        let least_loaded = holdings
+            .iter()
+            .enumerate()
+            .filter(|(i, h)| h.holds(file) && !excluded(*i, file))
+            .min_by_key(|(i, _)| assignment.by_descriptor[*i].len());
+        match least_loaded {
+            Some((i, _)) => assignment.by_descriptor[i].push(file.clone()),
+            None => assignment.unheld.push(file.clone()),
+        }
+    }
+    assignment
+}
+
+#[test]
+fn files_spread_across_holders() {
+    let file = |chapter: &str, cid: &str| PlannedFile {
+        cid: cid.to_string(),
+        volume_interface_id: "nametags_from_000_000_000".to_string(),
+        chapter_interface_id: chapter.to_string(),
+        dest_dir: Default::default(),
+        filename: format!("{}.ssz_snappy", cid),
+        local_bytes: None,
+    };
+    let low = MirrorHoldings {
+        chapters: vec!["addresses_0x00".to_string(), "addresses_0x01".to_string()],
+        ..Default::default()
+    };
+    let both = MirrorHoldings {
+        chapters: vec!["addresses_0x01".to_string()],
+        files: vec!["Qm3".to_string()],
+        ..Default::default()
+    };
+    let files = vec![
+        file("addresses_0x00", "Qm0"),
+        file("addresses_0x01", "Qm1"),
+        file("addresses_0x01", "Qm2"),
+        file("addresses_0x02", "Qm3"),
+        file("addresses_0x03", "Qm4"),
+    ];
+    let assignment = assign_files(&files, &[low.clone(), both.clone()]);
+    let cids = |files: &[PlannedFile]| files.iter().map(|f| f.cid.clone()).collect::<Vec<_>>();
+    assert_eq!(cids(&assignment.by_descriptor[0]), vec!["Qm0", "Qm2"]);
+    assert_eq!(cids(&assignment.by_descriptor[1]), vec!["Qm1", "Qm3"]);
+    assert_eq!(cids(&assignment.unheld), vec!["Qm4"]);
+
+    let assignment = assign_files_except(&files[..3], &[low, both], |i, _| i == 1);
+    assert_eq!(
+        cids(&assignment.by_descriptor[0]),
+        vec!["Qm0", "Qm1", "Qm2"]
+    );
+}
+
+#[test]
+fn signing_hash_ignores_signature() {
+    let mut holdings = MirrorHoldings {
+        database_interface_id: "nametags".to_string(),
+        chapters: vec!["addresses_0x00".to_string()],
+        ..Default::default()
+    };
+    let hash = holdings.signing_hash().unwrap();
+    holdings.signature = Some(HoldingsSignature::default());
+    assert_eq!(holdings.signing_hash().unwrap(), hash);
+    holdings.chapters.clear();
+    assert_ne!(holdings.signing_hash().unwrap(), hash);
+}
+
+#[cfg(feature = "web3")]
+#[test]
+fn signed_holdings_verified() {
+    use secp256k1::SecretKey;
+    use web3::signing::{Key, SecretKeyRef};
+
+    let key = SecretKey::from_slice(&[7u8; 32]).unwrap();
+    let mut holdings = MirrorHoldings {
+        database_interface_id: "nametags".to_string(),
+        chapters: vec!["addresses_0x00".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(holdings.verified_signer().unwrap(), None);
+    holdings.sign(SecretKeyRef::new(&key)).unwrap();
+    let signer = format!("{:?}", SecretKeyRef::new(&key).address());
+    assert_eq!(holdings.verified_signer().unwrap(), Some(signer));
+
+    holdings.chapters.push("addresses_0x01".to_string());
+    assert!(holdings.verified_signer().is_err());
+}
@@ -0,0 +1,138 @@
+//! Locations a manifest advertises for obtaining its Chapter files.
+//!
+//! Gateways come and go, so a publisher may list where the files can be fetched
+//! alongside the CIDs. When a user does not specify a gateway,
+//! [Todd::obtain_relevant_data][crate::database::types::Todd::obtain_relevant_data]
+//! tries the mirrors in the order: IPFS gateways, subdomain gateways, HTTP mirrors,
+//! after moving those that serve a probed file correctly to the front (see
+//! [crate::database::probe]). Files obtained from mirrors are checked against
+//! the CIDs in the manifest.
+#[cfg(feature = "net")]
+use anyhow::{anyhow, Result};
+#[cfg(feature = "net")]
+use log::warn;
+#[cfg(feature = "net")]
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "net")]
+use crate::{database::acquisition::PlannedFile, utils::gateway::Gateway};
+
+/// Mirrors listed in a manifest under `mirrors`. All lists may be empty.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct ManifestMirrors {
+    /// Path style IPFS gateways. E.g., "https://ipfs.io".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ipfs_gateways: Vec<String>,
+    /// Subdomain style IPFS gateways. E.g., "https://dweb.link".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subdomain_gateways: Vec<String>,
+    /// Plain HTTP servers with the layout of a data directory.
+    ///
+    /// A file is fetched from `{mirror}/{chapter_interface_id}/{filename}`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub http_mirrors: Vec<String>,
+    /// Magnet links or infohashes of torrents of the data directory.
+    ///
+    /// Listed for out-of-band bootstrapping with other software, they are
+    /// not used by this library.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub torrents: Vec<String>,
+}
+
+impl ManifestMirrors {
+    /// Whether no mirrors are listed.
+    pub fn is_empty(&self) -> bool {
+        self.ipfs_gateways.is_empty()
+            && self.subdomain_gateways.is_empty()
+            && self.http_mirrors.is_empty()
+            && self.torrents.is_empty()
+    }
+}
+
+/// A location that Chapter files can be downloaded from. Requires the `net` feature.
+#[cfg(feature = "net")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileSource {
+    /// An IPFS gateway, which fetches files by CID.
+    Gateway(Gateway),
+    /// A server with the layout of a data directory.
+    HttpMirror(Url),
+}
+
+#[cfg(feature = "net")]
+impl FileSource {
+    /// The URL of a planned file at this source.
+    pub fn file_url(&self, file: &PlannedFile) -> Result<Url> {
+        match self {
+            FileSource::Gateway(gateway) => gateway.fetch_url(&file.cid),
+            FileSource::HttpMirror(base) => {
+                let mut url = base.clone();
+                url.path_segments_mut()
+                    .map_err(|_| anyhow!("Mirror URL cannot be a base: {}", base))?
+                    .pop_if_empty()
+                    .push(&file.chapter_interface_id)
+                    .push(&file.filename);
+                Ok(url)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+impl ManifestMirrors {
+    /// The IPFS gateways and HTTP mirrors, in the order they are tried.
+    ///
+    /// Mirrors with invalid URLs are skipped with a warning.
+    pub fn sources(&self) -> Vec<FileSource> {
+        let gateways = self
+            .ipfs_gateways
+            .iter()
+            .map(|url| Gateway::new(url).map(FileSource::Gateway));
+        let subdomains = self
+            .subdomain_gateways
+            .iter()
+            .map(|url| Gateway::subdomain(url).map(FileSource::Gateway));
+        let http = self
+            .http_mirrors
+            .iter()
+            .map(|url| Ok(FileSource::HttpMirror(Url::parse(url)?)));
+        gateways
+            .chain(subdomains)
+            .chain(http)
+            .filter_map(|source| match source {
+                Ok(source) => Some(source),
+                Err(e) => {
+                    warn!("Skipped invalid mirror in manifest: {:#}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn mirror_sources_in_order() {
+    let mirrors = ManifestMirrors {
+        ipfs_gateways: vec!["http://127.0.0.1:8080".to_string()],
+        subdomain_gateways: vec!["not a url".to_string()],
+        http_mirrors: vec!["https://example.com/nametags/".to_string()],
+        torrents: vec!["magnet:?xt=urn:btih:0000".to_string()],
+    };
+    let sources = mirrors.sources();
+    assert_eq!(sources.len(), 2);
+    assert!(matches!(sources[0], FileSource::Gateway(_)));
+    let file = PlannedFile {
+        cid: "QmY6LjJ1HExi2TgHshc56ecPdVSNaWrzFbWq9sahHFrNoM".to_string(),
+        volume_interface_id: "nametags_from_000_000_000".to_string(),
+        chapter_interface_id: "nametags_0x4e".to_string(),
+        dest_dir: Default::default(),
+        filename: "nametags_from_000_000_000_chapter_0x4e.ssz_snappy".to_string(),
+        local_bytes: None,
+    };
+    assert_eq!(
+        sources[1].file_url(&file).unwrap().as_str(),
+        "https://example.com/nametags/nametags_0x4e/nametags_from_000_000_000_chapter_0x4e.ssz_snappy"
+    );
+}
@@ -5,5 +5,14 @@
 // then SimpleSerialize to perform JSON encoding. Module
 // separation makes procedural macros simple in this instance.
 pub mod address_appearance_index;
+pub mod events;
+pub mod lint;
+pub mod mirror_holdings;
+pub mod mirrors;
 pub mod nametags;
+pub mod provenance;
+pub mod raw_ranges;
+pub mod samples;
 pub mod signatures;
+pub mod succession;
+pub mod trust;
@@ -0,0 +1,77 @@
+//! Where the data in a manifest comes from and how it may be used.
+//!
+//! Publishers state the upstream source of the raw data and the license of
+//! the database. E.g., "Unchained Index v0.2.0" or "4byte.directory dump of 2022-10-01".
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// Provenance listed in a manifest under `provenance`. All fields are optional.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct ManifestProvenance {
+    /// Name of the upstream source. E.g., "Unchained Index".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_name: Option<String>,
+    /// Date the raw data was retrieved from the source. E.g., "2022-10-01".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieval_date: Option<String>,
+    /// Version of the upstream data. E.g., "v0.2.0".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_version: Option<String>,
+    /// CID of the upstream data (e.g., the Unchained Index manifest).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_cid: Option<String>,
+    /// License of the database. E.g., an SPDX identifier such as "CC0-1.0".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+}
+
+impl ManifestProvenance {
+    /// Whether no provenance is stated.
+    pub fn is_empty(&self) -> bool {
+        self == &ManifestProvenance::default()
+    }
+}
+
+impl Display for ManifestProvenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "Provenance not stated.");
+        }
+        write!(
+            f,
+            "Source: {}",
+            self.source_name.as_deref().unwrap_or("unknown")
+        )?;
+        if let Some(version) = &self.upstream_version {
+            write!(f, " {}", version)?;
+        }
+        if let Some(cid) = &self.upstream_cid {
+            write!(f, " (CID {})", cid)?;
+        }
+        if let Some(date) = &self.retrieval_date {
+            write!(f, ", retrieved {}", date)?;
+        }
+        write!(
+            f,
+            ". License: {}.",
+            self.license.as_deref().unwrap_or("not stated")
+        )
+    }
+}
+
+#[test]
+fn provenance_display() {
+    let provenance = ManifestProvenance {
+        source_name: Some("Unchained Index".to_string()),
+        retrieval_date: Some("2022-10-01".to_string()),
+        upstream_version: Some("v0.2.0".to_string()),
+        upstream_cid: None,
+        license: None,
+    };
+    assert_eq!(
+        provenance.to_string(),
+        "Source: Unchained Index v0.2.0, retrieved 2022-10-01. License: not stated."
+    );
+    assert!(ManifestProvenance::default().is_empty());
+}
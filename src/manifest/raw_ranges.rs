@@ -0,0 +1,16 @@
+//! The raw entries that form each Volume, for specs that assign entries to
+//! Volumes by their position in the raw source (nametags, signatures).
+//!
+//! Listed under `raw_entry_ranges` so that a rebuild or audit can confirm the
+//! Volume boundaries without depending on how a file system orders the raw files.
+use serde::{Deserialize, Serialize};
+
+use crate::extraction::raw_index::RawEntryRange;
+
+/// The raw entries of a Volume, as listed in the manifest.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct ManifestRawEntryRange {
+    pub volume_interface_id: String,
+    #[serde(flatten)]
+    pub range: RawEntryRange,
+}
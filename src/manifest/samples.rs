@@ -0,0 +1,248 @@
+//! A record of the sample files of a database, so that corrupted or outdated
+//! samples are detected.
+//!
+//! Each database in `./data/samples` has a `samples_manifest.json` listing every
+//! sample file (raw and processed) with its size and CID (v0). The `version`
+//! is increased whenever the samples change.
+//!
+//! [Todd::get_sample_data][crate::database::types::Todd::get_sample_data] checks
+//! the samples against the manifest and returns a [SamplesMismatch] error for
+//! files that differ. [Todd::refresh_sample_data][crate::database::types::Todd::refresh_sample_data]
+//! replaces them.
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::verified::VerificationCache,
+    utils::{ipfs::cid_v0_string_from_bytes, system::dir_paths},
+};
+
+/// The name of the samples manifest, in the base directory of the samples.
+pub const SAMPLES_MANIFEST_FILENAME: &str = "samples_manifest.json";
+
+/// The sample files of a database.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SamplesManifest {
+    /// Increased whenever the samples change.
+    pub version: u32,
+    /// E.g., "nametags".
+    pub database_interface_id: String,
+    /// Sorted by path.
+    pub files: Vec<SampleFile>,
+}
+
+/// A sample file, relative to the base directory of the samples.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampleFile {
+    /// Path components separated by "/". E.g., "nametags/addresses_0x00/<filename>".
+    pub path: String,
+    pub bytes: u64,
+    pub cid: String,
+}
+
+/// A sample file that does not match the samples manifest.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampleMismatch {
+    pub path: String,
+    pub reason: SampleMismatchReason,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SampleMismatchReason {
+    Absent,
+    DifferentSize { expected: u64, found: u64 },
+    DifferentHash { expected: String, found: String },
+}
+
+/// Error returned when sample files do not match the samples manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SamplesMismatch {
+    /// The base directory of the samples.
+    pub dir: PathBuf,
+    pub version: u32,
+    pub mismatches: Vec<SampleMismatch>,
+}
+
+impl SamplesManifest {
+    /// Lists every file in the directory, other than the samples manifest and
+    /// files left by other programs (e.g., ".DS_Store").
+    ///
+    /// ## Errors
+    /// If a file name is not valid UTF-8, as the manifest records paths as strings.
+    pub fn generate(dir: &Path, database_interface_id: &str, version: u32) -> Result<Self> {
+        let mut files = vec![];
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            let entries = dir_paths(&current)
+                .with_context(|| format!("Couldn't read sample directory {:?}", current))?;
+            for path in entries {
+                let relative = relative_path(dir, &path)?;
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+                if relative == SAMPLES_MANIFEST_FILENAME {
+                    continue;
+                }
+                let bytes = fs::read(&path)?;
+                files.push(SampleFile {
+                    path: relative,
+                    bytes: bytes.len() as u64,
+                    cid: cid_v0_string_from_bytes(&bytes)?,
+                });
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(SamplesManifest {
+            version,
+            database_interface_id: database_interface_id.to_string(),
+            files,
+        })
+    }
+    /// Reads a samples manifest.
+    pub fn read(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read samples manifest {:?}", path))?;
+        serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse samples manifest {:?}", path))
+    }
+    /// Writes the samples manifest.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write samples manifest {:?}", path))
+    }
+    /// The listed files that do not match the manifest. Files not listed are ignored.
+    ///
+    /// Each file is found with `locate`, from its path in the manifest. Files
+    /// for which `locate` returns None are not checked. The cache avoids hashing
+    /// files that have not changed since they were last checked.
+    #[cfg_attr(not(feature = "publisher"), allow(dead_code))]
+    pub(crate) fn mismatches<F>(
+        &self,
+        locate: F,
+        cache: &mut VerificationCache,
+    ) -> Result<Vec<SampleMismatch>>
+    where
+        F: Fn(&str) -> Option<PathBuf>,
+    {
+        let mut mismatches = vec![];
+        for file in &self.files {
+            let Some(path) = locate(&file.path) else {
+                continue;
+            };
+            let reason = match fs::metadata(&path) {
+                Err(_) => Some(SampleMismatchReason::Absent),
+                Ok(m) if m.len() != file.bytes => Some(SampleMismatchReason::DifferentSize {
+                    expected: file.bytes,
+                    found: m.len(),
+                }),
+                Ok(_) => match cache.cid_of(&path, false)? {
+                    cid if cid == file.cid => None,
+                    found => Some(SampleMismatchReason::DifferentHash {
+                        expected: file.cid.clone(),
+                        found,
+                    }),
+                },
+            };
+            if let Some(reason) = reason {
+                mismatches.push(SampleMismatch {
+                    path: file.path.clone(),
+                    reason,
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+/// The path relative to the base directory, with "/" separators.
+fn relative_path(base: &Path, path: &Path) -> Result<String> {
+    let components = path
+        .strip_prefix(base)
+        .unwrap_or(path)
+        .components()
+        .map(|c| {
+            c.as_os_str()
+                .to_str()
+                .ok_or_else(|| anyhow!("Sample file name is not valid UTF-8: {:?}", path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(components.join("/"))
+}
+
+impl Display for SampleMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            SampleMismatchReason::Absent => write!(f, "{} is absent", self.path),
+            SampleMismatchReason::DifferentSize { expected, found } => write!(
+                f,
+                "{} has {} bytes, expected {}",
+                self.path, found, expected
+            ),
+            SampleMismatchReason::DifferentHash { expected, found } => {
+                write!(f, "{} has CID {}, expected {}", self.path, found, expected)
+            }
+        }
+    }
+}
+
+impl Display for SamplesMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} sample files in {:?} do not match version {} of the samples manifest",
+            self.mismatches.len(),
+            self.dir,
+            self.version
+        )?;
+        if let Some(first) = self.mismatches.first() {
+            write!(f, " (first: {})", first)?;
+        }
+        write!(f, ". Use refresh_sample_data() to obtain them again.")
+    }
+}
+
+impl std::error::Error for SamplesMismatch {}
+
+#[test]
+fn sample_files_checked_against_manifest() {
+    let dir = std::env::temp_dir().join("min_know_test_samples_manifest");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("raw")).unwrap();
+    fs::write(dir.join("raw").join("a"), b"first").unwrap();
+    fs::write(dir.join("raw").join("b"), b"second").unwrap();
+    fs::write(dir.join("c"), b"third").unwrap();
+    let manifest = SamplesManifest::generate(&dir, "nametags", 2).unwrap();
+    let paths: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(paths, vec!["c", "raw/a", "raw/b"]);
+    manifest
+        .write(&dir.join(SAMPLES_MANIFEST_FILENAME))
+        .unwrap();
+    assert_eq!(
+        SamplesManifest::read(&dir.join(SAMPLES_MANIFEST_FILENAME)).unwrap(),
+        manifest
+    );
+    let locate = |path: &str| Some(dir.join(path));
+    let mut cache = VerificationCache::default();
+    assert!(manifest.mismatches(locate, &mut cache).unwrap().is_empty());
+
+    fs::write(dir.join("raw").join("a"), b"FIRST").unwrap();
+    fs::remove_file(dir.join("raw").join("b")).unwrap();
+    fs::write(dir.join("c"), b"changed").unwrap();
+    let mut cache = VerificationCache::default();
+    let only_raw = |path: &str| path.starts_with("raw/").then(|| dir.join(path));
+    let mismatches = manifest.mismatches(only_raw, &mut cache).unwrap();
+    assert_eq!(mismatches.len(), 2);
+    assert!(matches!(
+        mismatches[0].reason,
+        SampleMismatchReason::DifferentHash { .. }
+    ));
+    assert_eq!(mismatches[1].reason, SampleMismatchReason::Absent);
+    fs::remove_dir_all(&dir).unwrap();
+}
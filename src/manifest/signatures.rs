@@ -3,18 +3,45 @@ use std::fmt::Display;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::specs::{
-    signatures::{SignaturesChapterId, SignaturesSpec, SignaturesVolumeId},
-    traits::{ChapterIdMethods, ManifestCids, ManifestMethods, VolumeIdMethods},
+use crate::{
+    extraction::raw_index::RawEntryRange,
+    manifest::{
+        mirrors::ManifestMirrors, provenance::ManifestProvenance,
+        raw_ranges::ManifestRawEntryRange, succession::ManifestSuccessor,
+    },
+    specs::{
+        signatures::{SignaturesChapterId, SignaturesSpec, SignaturesVolumeId},
+        traits::{ChapterIdMethods, ManifestCids, ManifestMethods, VolumeIdMethods},
+    },
 };
 
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct SignaturesManifest {
     pub spec_version: String,
+    /// Fingerprint of the SSZ layout of the Chapters (see
+    /// [DataSpec::schema_fingerprint](crate::specs::traits::DataSpec::schema_fingerprint)).
+    /// Absent for manifests made before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_fingerprint: Option<String>,
     pub schemas: String,
     pub database_interface_id: String,
     pub latest_volume_identifier: String,
     pub chapter_cids: Vec<SignaturesManifestChapter>,
+    /// CIDs of the optional bloom filter for each Chapter.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bloom_filter_cids: Vec<SignaturesManifestChapter>,
+    /// Where the files can be obtained from, as advertised by the publisher.
+    #[serde(default, skip_serializing_if = "ManifestMirrors::is_empty")]
+    pub mirrors: ManifestMirrors,
+    /// Upstream source and license of the data.
+    #[serde(default, skip_serializing_if = "ManifestProvenance::is_empty")]
+    pub provenance: ManifestProvenance,
+    /// The database that replaces this one, if it is deprecated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded_by: Option<ManifestSuccessor>,
+    /// The raw entries that form each Volume.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub raw_entry_ranges: Vec<ManifestRawEntryRange>,
 }
 
 impl ManifestMethods<SignaturesSpec> for SignaturesManifest {
@@ -26,6 +53,14 @@ impl ManifestMethods<SignaturesSpec> for SignaturesManifest {
         self.spec_version = version
     }
 
+    fn schema_fingerprint(&self) -> Option<&str> {
+        self.schema_fingerprint.as_deref()
+    }
+
+    fn set_schema_fingerprint(&mut self, fingerprint: Option<String>) {
+        self.schema_fingerprint = fingerprint
+    }
+
     fn schemas(&self) -> &str {
         &self.schemas
     }
@@ -83,6 +118,85 @@ impl ManifestMethods<SignaturesSpec> for SignaturesManifest {
                 .then(a.chapter_interface_id.cmp(&b.chapter_interface_id))
         })
     }
+
+    fn bloom_filter_cids(&self) -> Result<Vec<ManifestCids<SignaturesSpec>>> {
+        let mut result: Vec<ManifestCids<SignaturesSpec>> = vec![];
+        for chapter in &self.bloom_filter_cids {
+            let volume_id = SignaturesVolumeId::from_interface_id(&chapter.volume_interface_id)?;
+            let chapter_id = SignaturesChapterId::from_interface_id(&chapter.chapter_interface_id)?;
+            result.push(ManifestCids {
+                cid: chapter.cid_v0.clone(),
+                volume_id,
+                chapter_id,
+            })
+        }
+        Ok(result)
+    }
+
+    fn set_bloom_filter_cids<C>(&mut self, cids: &[(C, SignaturesVolumeId, SignaturesChapterId)])
+    where
+        C: AsRef<str> + Display,
+    {
+        self.bloom_filter_cids = cids
+            .iter()
+            .map(|(cid, volume_id, chapter_id)| SignaturesManifestChapter {
+                volume_interface_id: volume_id.interface_id(),
+                chapter_interface_id: chapter_id.interface_id(),
+                cid_v0: cid.to_string(),
+            })
+            .collect();
+        self.bloom_filter_cids.sort_by(|a, b| {
+            a.volume_interface_id
+                .cmp(&b.volume_interface_id)
+                .then(a.chapter_interface_id.cmp(&b.chapter_interface_id))
+        })
+    }
+
+    fn mirrors(&self) -> &ManifestMirrors {
+        &self.mirrors
+    }
+
+    fn set_mirrors(&mut self, mirrors: ManifestMirrors) {
+        self.mirrors = mirrors
+    }
+
+    fn provenance(&self) -> &ManifestProvenance {
+        &self.provenance
+    }
+
+    fn set_provenance(&mut self, provenance: ManifestProvenance) {
+        self.provenance = provenance
+    }
+
+    fn superseded_by(&self) -> Option<&ManifestSuccessor> {
+        self.superseded_by.as_ref()
+    }
+
+    fn set_superseded_by(&mut self, successor: Option<ManifestSuccessor>) {
+        self.superseded_by = successor
+    }
+
+    fn raw_entry_ranges(&self) -> Result<Vec<(SignaturesVolumeId, RawEntryRange)>> {
+        self.raw_entry_ranges
+            .iter()
+            .map(|r| {
+                let volume_id = SignaturesVolumeId::from_interface_id(&r.volume_interface_id)?;
+                Ok((volume_id, r.range.clone()))
+            })
+            .collect()
+    }
+
+    fn set_raw_entry_ranges(&mut self, ranges: &[(SignaturesVolumeId, RawEntryRange)]) {
+        self.raw_entry_ranges = ranges
+            .iter()
+            .map(|(volume_id, range)| ManifestRawEntryRange {
+                volume_interface_id: volume_id.interface_id(),
+                range: range.clone(),
+            })
+            .collect();
+        self.raw_entry_ranges
+            .sort_by(|a, b| a.volume_interface_id.cmp(&b.volume_interface_id));
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
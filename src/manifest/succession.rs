@@ -0,0 +1,75 @@
+//! Successors of databases that are no longer maintained.
+//!
+//! When a spec is superseded (e.g., by a new version of the nametags spec),
+//! the publisher can point the old manifest at the new database. Users of the
+//! old manifest are warned when it is read.
+use std::{fmt::Display, sync::Mutex};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Databases that have been warned about as superseded in this process.
+static WARNED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+/// The database that replaces the one in a manifest, listed under `superseded_by`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct ManifestSuccessor {
+    /// Interface id of the new database. E.g., "nametags_v2".
+    pub database_interface_id: String,
+    /// CID of the manifest of the new database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_cid: Option<String>,
+    /// Topic that the new manifest is published under (e.g., an IPNS name or
+    /// a pubsub topic), for manifests that change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    /// Reason or migration advice from the publisher.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl ManifestSuccessor {
+    /// Logs a warning that a database is superseded, once per database per process.
+    pub(crate) fn warn_once(&self, superseded_interface_id: &str) {
+        let mut warned = WARNED.lock().unwrap_or_else(|e| e.into_inner());
+        if warned.iter().any(|id| id == superseded_interface_id) {
+            return;
+        }
+        warned.push(superseded_interface_id.to_string());
+        warn!(
+            "Database {} is deprecated. {}",
+            superseded_interface_id, self
+        );
+    }
+}
+
+impl Display for ManifestSuccessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Superseded by {}", self.database_interface_id)?;
+        match (&self.manifest_cid, &self.topic) {
+            (Some(cid), Some(topic)) => write!(f, " (manifest CID {}, topic {})", cid, topic)?,
+            (Some(cid), None) => write!(f, " (manifest CID {})", cid)?,
+            (None, Some(topic)) => write!(f, " (topic {})", topic)?,
+            (None, None) => {}
+        }
+        write!(f, ".")?;
+        if let Some(note) = &self.note {
+            write!(f, " {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn successor_display() {
+    let successor = ManifestSuccessor {
+        database_interface_id: "nametags_v2".to_string(),
+        manifest_cid: Some("QmSuccessor".to_string()),
+        topic: None,
+        note: Some("Tags are now namespaced.".to_string()),
+    };
+    assert_eq!(
+        successor.to_string(),
+        "Superseded by nametags_v2 (manifest CID QmSuccessor). Tags are now namespaced."
+    );
+}
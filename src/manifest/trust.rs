@@ -0,0 +1,87 @@
+//! Manifests that an application trusts, identified by CID.
+//!
+//! An application that bundles a database can pin the manifests it expects,
+//! so that a gateway or publisher cannot substitute another manifest. Trusted
+//! CIDs (v0) are set when the application is built, with the comma separated
+//! `MIN_KNOW_TRUSTED_MANIFEST_CIDS` environment variable, or at run time with
+//! `config.trusted_manifest_cids`.
+//!
+//! Manifests obtained by `Todd::sync_and_pin`, `Todd::import_car` and
+//! `Todd::init_from_manifest` are checked before any Chapter files are obtained.
+use anyhow::{bail, Result};
+
+use crate::utils::ipfs::cid_v0_string_from_bytes;
+
+/// Manifest CIDs trusted when the crate was compiled.
+const COMPILED_TRUSTED_MANIFEST_CIDS: Option<&str> = option_env!("MIN_KNOW_TRUSTED_MANIFEST_CIDS");
+
+/// The manifest CIDs trusted when the crate was compiled, from the
+/// `MIN_KNOW_TRUSTED_MANIFEST_CIDS` environment variable.
+pub fn compiled_trusted_manifest_cids() -> Vec<String> {
+    parse_cid_list(COMPILED_TRUSTED_MANIFEST_CIDS.unwrap_or_default())
+}
+
+/// Checks that a manifest is trusted, returning its CID.
+///
+/// The compiled and configured lists are checked separately. Each list that
+/// is not empty must contain the CID, so configuration cannot widen the CIDs
+/// trusted at compile time. If both are empty, every manifest is trusted.
+///
+/// ## Errors
+/// If a list that is not empty does not contain the CID of the manifest.
+pub fn check_trusted_manifest(manifest_bytes: &[u8], configured: &[String]) -> Result<String> {
+    check_against(
+        manifest_bytes,
+        &[
+            ("compiled in", &compiled_trusted_manifest_cids()),
+            ("configured", configured),
+        ],
+    )
+}
+
+fn check_against(manifest_bytes: &[u8], lists: &[(&str, &[String])]) -> Result<String> {
+    let cid = cid_v0_string_from_bytes(manifest_bytes)?;
+    for (origin, trusted) in lists {
+        if !trusted.is_empty() && !trusted.contains(&cid) {
+            bail!(
+                "The manifest (CID {}) is not one of the {} trusted manifests: {}",
+                cid,
+                origin,
+                trusted.join(", ")
+            )
+        }
+    }
+    Ok(cid)
+}
+
+fn parse_cid_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|cid| !cid.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn manifests_checked_against_each_list() {
+    let manifest = b"{\"database_interface_id\": \"nametags\"}";
+    let cid = cid_v0_string_from_bytes(manifest).unwrap();
+    let other = "QmNLei78zWmzUdbeRB3CiUfAizWUrbeeZh5K1rhAQKCh51".to_string();
+    let none: Vec<String> = vec![];
+    let both = vec![other.clone(), cid.clone()];
+    assert_eq!(
+        check_against(manifest, &[("compiled in", &none)]).unwrap(),
+        cid
+    );
+    assert!(check_against(manifest, &[("compiled in", &both), ("configured", &none)]).is_ok());
+    let err = check_against(
+        manifest,
+        &[("compiled in", &both), ("configured", &[other])],
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("configured trusted manifests"));
+    assert_eq!(
+        parse_cid_list(" Qm1, ,Qm2,"),
+        vec!["Qm1".to_string(), "Qm2".to_string()]
+    );
+}
@@ -9,6 +9,12 @@
 ///     - ...
 pub const BLOCKS_PER_VOLUME: u32 = 100_000;
 
+/// Number of Volumes that interface ids can represent.
+///
+/// Interface ids have nine digits (e.g., "volume_014_400_000"), so the last
+/// Volume starts at block 999_900_000.
+pub const MAX_VOLUMES: u32 = 1_000_000_000 / BLOCKS_PER_VOLUME;
+
 /// Number of hex characters that address within a [chapter][0] share.
 ///
 /// Alias for [ADDRESS_CHARS_SIMILARITY_DEPTH][1]. A depth of `2` indicates
@@ -0,0 +1,24 @@
+//! Paramaters are defined in the spec
+//!
+//! See: https://github.com/perama-v/TODD/blob/main/example_specs/events.md
+/// Number of additions present in a single Volume.
+///
+/// This is a count of topic hashes, and includes additions to
+/// topics already present in the database.
+pub const EVENTS_PER_VOLUME: usize = 1_000;
+
+/// Number of Volumes that interface ids can represent.
+///
+/// Interface ids have nine digits (e.g., "events_starting_000_630_000").
+pub const MAX_VOLUMES: u32 = 1_000_000_000 / EVENTS_PER_VOLUME as u32;
+
+/// Derived from EVENTS_PER_VOLUME.
+pub const MAX_RECORDS_PER_CHAPTER: usize = EVENTS_PER_VOLUME;
+
+/// Number of bytes required to describe a chapter.
+pub const BYTES_FOR_TOPIC_CHARS: usize = 1;
+
+/// Number of bytes for a topic (keccak256 hash of the event signature).
+pub const BYTES_PER_TOPIC: usize = 32;
+
+pub const MAX_TEXTS_PER_RECORD: usize = 256;
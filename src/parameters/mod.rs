@@ -1,4 +1,5 @@
 //! This module contains parameters particular to each database type.
 pub mod address_appearance_index;
+pub mod events;
 pub mod nametags;
 pub mod signatures;
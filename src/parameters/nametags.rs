@@ -4,6 +4,11 @@
 /// addresses already present in the database.
 pub const ENTRIES_PER_VOLUME: usize = 1_000;
 
+/// Number of Volumes that interface ids can represent.
+///
+/// Interface ids have nine digits (e.g., "nametags_from_000_630_000").
+pub const MAX_VOLUMES: u32 = 1_000_000_000 / ENTRIES_PER_VOLUME as u32;
+
 /// Derived from ENTRIES_PER_VOLUME.
 pub const MAX_RECORDS_PER_CHAPTER: usize = ENTRIES_PER_VOLUME;
 
@@ -19,6 +24,9 @@ pub const MAX_BYTES_PER_NAME: usize = 32;
 
 pub const MAX_BYTES_PER_TAG: usize = 32;
 
+/// Maximum length of the source (namespace) of a name or tag. E.g., "ofac".
+pub const MAX_BYTES_PER_SOURCE: usize = 32;
+
 pub const MAX_TAGS_PER_RECORD: usize = 256;
 
 pub const MAX_NAMES_PER_RECORD: usize = 256;
@@ -7,6 +7,11 @@
 /// signatures already present in the database.
 pub const SIGNATURES_PER_VOLUME: usize = 1_000;
 
+/// Number of Volumes that interface ids can represent.
+///
+/// Interface ids have nine digits (e.g., "mappings_starting_000_630_000").
+pub const MAX_VOLUMES: u32 = 1_000_000_000 / SIGNATURES_PER_VOLUME as u32;
+
 /// Derived from ENTRIES_PER_VOLUME.
 pub const MAX_RECORDS_PER_CHAPTER: usize = SIGNATURES_PER_VOLUME;
 
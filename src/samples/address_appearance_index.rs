@@ -1,13 +1,18 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+#[cfg(feature = "net")]
 use log::info;
+#[cfg(feature = "net")]
 use reqwest::Url;
+#[cfg(feature = "net")]
 use tokio::runtime::Runtime;
 
+#[cfg(feature = "net")]
+use crate::utils::download::{download_files, DownloadTask};
 use crate::{
-    samples::traits::SampleObtainerMethods,
-    utils::download::{download_files, DownloadTask},
+    samples::traits::{SampleArtifact, SampleObtainerMethods},
+    utils::unchained::{files::get_range, writer::ChunkWriter},
 };
 
 pub struct AAISampleObtainer;
@@ -19,10 +24,24 @@ impl SampleObtainerMethods for AAISampleObtainer {
     fn sample_volumes() -> Option<Vec<&'static str>> {
         Some(SAMPLE_VOLUMES.to_vec())
     }
+    /// The chunk files, with the IPFS CID and gateway URL of each.
+    fn sample_artifacts() -> Vec<SampleArtifact> {
+        SAMPLE_CHUNKS
+            .iter()
+            .zip(SAMPLE_CHUNK_CIDS)
+            .map(|(name, cid)| SampleArtifact {
+                name: name.to_string(),
+                url: Some(format!("{}{}", SAMPLE_UNCHAINED_URL, cid)),
+                ipfs_cid: Some(cid.to_string()),
+                ..Default::default()
+            })
+            .collect()
+    }
 
     /// Downloads the sample Unchained Index chunk files from IPFS.
     ///
     /// Saves five 25MB files locally in the sample directory.
+    #[cfg(feature = "net")]
     fn get_raw_samples(dir: &Path) -> Result<()> {
         let mut tasks: Vec<DownloadTask> = vec![];
         for (index, chunk_name) in SAMPLE_CHUNK_CIDS.iter().enumerate() {
@@ -38,6 +57,29 @@ impl SampleObtainerMethods for AAISampleObtainer {
 
         Ok(())
     }
+    #[cfg(not(feature = "net"))]
+    fn get_raw_samples(dir: &Path) -> Result<()> {
+        anyhow::bail!(
+            "Raw samples are absent from {:?} and downloading them requires the `net` feature.",
+            dir
+        )
+    }
+
+    /// Writes chunk files for the sample block ranges, each with one appearance
+    /// for each of a few addresses.
+    fn synthetic_raw_samples(dir: &Path) -> Result<()> {
+        for chunk_name in SAMPLE_CHUNKS {
+            let range = get_range(&PathBuf::from(chunk_name))?;
+            let mut chunk = ChunkWriter::new(range);
+            // Addresses 0x0000.., 0x1111.., ... 0xffff.. cover a spread of Chapters.
+            for n in (0..=255u8).step_by(17) {
+                let block = range.old + n as u32 % (range.new - range.old + 1);
+                chunk.add_appearance(&[n; 20], block, n as u32)?;
+            }
+            chunk.write_to_dir(dir)?;
+        }
+        Ok(())
+    }
 }
 
 static SAMPLE_VOLUMES: [&str; 4] = [
@@ -55,6 +97,7 @@ static SAMPLE_CHUNKS: [&str; 5] = [
     "015508866-015511829.bin",
 ];
 
+/// IPFS CIDs of the sample chunk files, in the same order as the filenames.
 pub static SAMPLE_CHUNK_CIDS: [&str; 5] = [
     "QmNpXdysAvS9PzEjnG6WeX18G9pxAa1mwL6TePrttV7XUM",
     "QmanGdgER53dayvG61zudQewdRSpx93ELWxxui9QiJRqwr",
@@ -64,3 +107,37 @@ pub static SAMPLE_CHUNK_CIDS: [&str; 5] = [
 ];
 
 static SAMPLE_UNCHAINED_URL: &str = "https://ipfs.unchainedindex.io/ipfs/";
+
+#[test]
+fn synthetic_samples_match_sample_names() {
+    use crate::{
+        extraction::{address_appearance_index::AAIExtractor, traits::ExtractorMethods},
+        utils::system::DirFunctions,
+    };
+
+    let dir = std::env::temp_dir().join("min_know_test_aai_synthetic_samples");
+    let _ = std::fs::remove_dir_all(&dir);
+    AAISampleObtainer::synthetic_raw_samples(&dir).unwrap();
+    assert!(dir.contains_files(&SAMPLE_CHUNKS).unwrap());
+    // The sample chunks are not consecutive, but each is complete.
+    let report = AAIExtractor::validate_raw(&dir).unwrap();
+    assert_eq!(report.files_checked, SAMPLE_CHUNKS.len());
+    assert!(report
+        .issues
+        .iter()
+        .all(|i| i.problem.contains("not in any chunk")));
+    let latest = AAIExtractor::latest_possible_volume(&dir).unwrap();
+    assert_eq!(latest.oldest_block, 15_400_000);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn sample_artifacts_have_sources() {
+    let artifacts = AAISampleObtainer::sample_artifacts();
+    let names: Vec<&str> = artifacts.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(names, SAMPLE_CHUNKS);
+    for (artifact, cid) in artifacts.iter().zip(SAMPLE_CHUNK_CIDS) {
+        assert_eq!(artifact.ipfs_cid.as_deref(), Some(cid));
+        assert!(artifact.url.as_ref().unwrap().ends_with(cid));
+    }
+}
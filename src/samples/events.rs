@@ -0,0 +1,97 @@
+use std::{fs, path::Path, sync::OnceLock};
+
+use anyhow::{bail, Result};
+use sha3::{Digest, Keccak256};
+
+use super::traits::SampleObtainerMethods;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventsSampleObtainer {}
+
+impl SampleObtainerMethods for EventsSampleObtainer {
+    fn raw_sample_filenames() -> Vec<&'static str> {
+        sample_events()
+            .iter()
+            .map(|(topic, _text)| topic.as_str())
+            .collect()
+    }
+
+    fn sample_volumes() -> Option<Vec<&'static str>> {
+        Some(SAMPLE_VOLUMES.to_vec())
+    }
+
+    fn get_raw_samples(dir: &Path) -> Result<()> {
+        bail!(
+            "Raw events samples are absent from {:?} and there is no source to download them from.",
+            dir
+        )
+    }
+
+    /// Writes a file for each sample event with a single text.
+    ///
+    /// Each file is named by the hash of its text, as in the topic0 repository.
+    /// Common events are included, the rest are made up.
+    fn synthetic_raw_samples(dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        for (topic, text) in sample_events() {
+            fs::write(dir.join(topic), text)?;
+        }
+        Ok(())
+    }
+}
+
+static SAMPLE_VOLUMES: [&str; 2] = ["events_starting_000_000_000", "events_starting_000_001_000"];
+
+/// Number of raw sample files. Enough for two Volumes.
+const SAMPLE_COUNT: usize = 2050;
+
+/// Events that are common on mainnet.
+static COMMON_EVENTS: [&str; 12] = [
+    "Transfer(address,address,uint256)",
+    "Approval(address,address,uint256)",
+    "ApprovalForAll(address,address,bool)",
+    "TransferSingle(address,address,address,uint256,uint256)",
+    "TransferBatch(address,address,address,uint256[],uint256[])",
+    "Deposit(address,uint256)",
+    "Withdrawal(address,uint256)",
+    "Swap(address,uint256,uint256,uint256,uint256,address)",
+    "Swap(address,address,int256,int256,uint160,uint128,int24)",
+    "Sync(uint112,uint112)",
+    "OwnershipTransferred(address,address)",
+    "NameRegistered(string,bytes32,address,uint256,uint256)",
+];
+
+/// The (topic, text) of each raw sample file.
+fn sample_events() -> &'static [(String, String)] {
+    static EVENTS: OnceLock<Vec<(String, String)>> = OnceLock::new();
+    EVENTS.get_or_init(|| {
+        let made_up = (0..).map(|n| format!("SampleEvent{}(uint256)", n));
+        COMMON_EVENTS
+            .iter()
+            .map(|text| text.to_string())
+            .chain(made_up)
+            .take(SAMPLE_COUNT)
+            .map(|text| (hex::encode(Keccak256::digest(text.as_bytes())), text))
+            .collect()
+    })
+}
+
+#[test]
+fn synthetic_samples_match_sample_names() {
+    use crate::{
+        extraction::{events::EventsExtractor, traits::ExtractorMethods},
+        specs::traits::VolumeIdMethods,
+        utils::system::DirFunctions,
+    };
+
+    let dir = std::env::temp_dir().join("min_know_test_events_synthetic_samples");
+    let _ = fs::remove_dir_all(&dir);
+    EventsSampleObtainer::synthetic_raw_samples(&dir).unwrap();
+    let filenames = EventsSampleObtainer::raw_sample_filenames();
+    assert!(dir.contains_files(&filenames).unwrap());
+    assert!(filenames.contains(&"ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"));
+    assert!(EventsExtractor::validate_raw(&dir).unwrap().is_valid());
+    let latest = EventsExtractor::latest_possible_volume(&dir).unwrap();
+    assert_eq!(&latest.interface_id(), SAMPLE_VOLUMES.last().unwrap());
+    fs::remove_dir_all(dir).unwrap();
+}
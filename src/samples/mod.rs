@@ -3,6 +3,7 @@
 //! The generic database manager can obtain samples, but requires that
 //! each specification implement the necessary non-generic methods.
 pub mod address_appearance_index;
+pub mod events;
 pub mod nametags;
 pub mod signatures;
 pub mod traits;
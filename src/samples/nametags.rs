@@ -1,6 +1,6 @@
-use std::path::Path;
+use std::{fs, path::Path};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
 use super::traits::SampleObtainerMethods;
@@ -17,15 +17,28 @@ impl SampleObtainerMethods for NameTagsSampleObtainer {
         Some(SAMPLE_VOLUMES.to_vec())
     }
 
-    fn get_raw_samples(_dir: &Path) -> Result<()> {
-        todo!("Could fetch samples from the full raw data source if needed.")
+    fn get_raw_samples(dir: &Path) -> Result<()> {
+        bail!(
+            "Raw nametags samples are absent from {:?} and there is no source to download them from.",
+            dir
+        )
+    }
+
+    /// Writes a file for each sample address, with a numbered name and one tag.
+    fn synthetic_raw_samples(dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        for (index, address) in SAMPLE_FILENAMES.iter().enumerate() {
+            let contents = format!(r#"{{"name":"sample-{}","tags":["synthetic"]}}"#, index);
+            fs::write(dir.join(address), contents)?;
+        }
+        Ok(())
     }
 }
 
 static SAMPLE_VOLUMES: [&str; 2] = ["nametags_from_000_000_000", "nametags_from_000_001_000"];
 
 /// The nametag files present in the ./data/samples/todd_nametags/raw_source_nametags dir.
-pub const SAMPLE_FILENAMES: [&str; 2063] = [
+pub static SAMPLE_FILENAMES: [&str; 2063] = [
     "0x0000000000000000000000000000000000000000",
     "0x00000b20f0f6a3a212aa6b85106709cd5941457c",
     "0x00003183f59e825911d98fb509a157cd2abbae25",
@@ -2090,3 +2103,21 @@ pub const SAMPLE_FILENAMES: [&str; 2063] = [
     "0xffb07ed8265cb3f566069e60a469c1b97a972a12",
     "0xffebaf265d6653c40e635f5c7891089dc4c8e9be",
 ];
+
+#[test]
+fn synthetic_samples_match_sample_names() {
+    use crate::{
+        extraction::{nametags::NameTagsExtractor, traits::ExtractorMethods},
+        specs::traits::VolumeIdMethods,
+        utils::system::DirFunctions,
+    };
+
+    let dir = std::env::temp_dir().join("min_know_test_nametags_synthetic_samples");
+    let _ = fs::remove_dir_all(&dir);
+    NameTagsSampleObtainer::synthetic_raw_samples(&dir).unwrap();
+    assert!(dir.contains_files(&SAMPLE_FILENAMES).unwrap());
+    assert!(NameTagsExtractor::validate_raw(&dir).unwrap().is_valid());
+    let latest = NameTagsExtractor::latest_possible_volume(&dir).unwrap();
+    assert_eq!(&latest.interface_id(), SAMPLE_VOLUMES.last().unwrap());
+    fs::remove_dir_all(dir).unwrap();
+}
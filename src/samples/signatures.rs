@@ -1,3 +1,7 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Result};
+
 use super::traits::SampleObtainerMethods;
 
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -12,8 +16,22 @@ impl SampleObtainerMethods for SignaturesSampleObtainer {
         Some(SAMPLE_VOLUMES.to_vec())
     }
 
-    fn get_raw_samples(_dir: &std::path::Path) -> anyhow::Result<()> {
-        todo!("Could fetch samples from the full raw data source if needed.")
+    fn get_raw_samples(dir: &Path) -> Result<()> {
+        bail!(
+            "Raw signatures samples are absent from {:?} and there is no source to download them from.",
+            dir
+        )
+    }
+
+    /// Writes a file for each sample selector with a single text.
+    ///
+    /// The texts are valid signatures, but do not hash to the selector.
+    fn synthetic_raw_samples(dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        for selector in SAMPLE_FILENAMES {
+            fs::write(dir.join(selector), format!("sample_{}()", selector))?;
+        }
+        Ok(())
     }
 }
 
@@ -22,7 +40,7 @@ static SAMPLE_VOLUMES: [&str; 2] = [
     "mappings_starting_000_001_000",
 ];
 
-pub const SAMPLE_FILENAMES: [&str; 2099] = [
+pub static SAMPLE_FILENAMES: [&str; 2099] = [
     "00008711", "0000e2a7", "0014f956", "001640f9", "00177b3f", "004225b8", "00675149", "006ca26b",
     "00862c3c", "00bf6d03", "013efd8b", "015129a1", "017fc984", "018479c5", "01c4a07e", "01d1f0ec",
     "01fd050d", "02431422", "0251fd2c", "026a9cdd", "027a4281", "0299d7d3", "02c5115b", "02c6883d",
@@ -287,3 +305,21 @@ pub const SAMPLE_FILENAMES: [&str; 2099] = [
     "fef667b7", "fefa5ce3", "ff49994b", "ff6a3331", "ff78ae98", "ff8f9b9f", "ff8fec7f", "ffa6475d",
     "ffaa4b20", "ffe4914a", "fffaf740",
 ];
+
+#[test]
+fn synthetic_samples_match_sample_names() {
+    use crate::{
+        extraction::{signatures::SignaturesExtractor, traits::ExtractorMethods},
+        specs::traits::VolumeIdMethods,
+        utils::system::DirFunctions,
+    };
+
+    let dir = std::env::temp_dir().join("min_know_test_signatures_synthetic_samples");
+    let _ = fs::remove_dir_all(&dir);
+    SignaturesSampleObtainer::synthetic_raw_samples(&dir).unwrap();
+    assert!(dir.contains_files(&SAMPLE_FILENAMES).unwrap());
+    assert!(SignaturesExtractor::validate_raw(&dir).unwrap().is_valid());
+    let latest = SignaturesExtractor::latest_possible_volume(&dir).unwrap();
+    assert_eq!(&latest.interface_id(), SAMPLE_VOLUMES.last().unwrap());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -1,6 +1,26 @@
 use std::path::Path;
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// A raw sample file, and where it is obtained from.
+///
+/// Allows tooling to mirror or verify the samples, and documentation to list them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampleArtifact {
+    /// Filename in the raw sample directory. E.g., "011283653-011286904.bin".
+    pub name: String,
+    /// Where the file is downloaded from, if anywhere.
+    pub url: Option<String>,
+    /// IPFS CID that the file is published under, if any.
+    pub ipfs_cid: Option<String>,
+    /// Size of the file, if known.
+    pub bytes: Option<u64>,
+    /// CID (v0) of the file bytes, as listed in the samples manifest (see
+    /// [SamplesManifest][crate::manifest::samples::SamplesManifest]), if known.
+    pub content_cid: Option<String>,
+}
+
 /// Gets samples for the given todd database.
 ///
 /// Processed samples are TODD-style samples. Raw samples
@@ -15,6 +35,19 @@ pub trait SampleObtainerMethods {
     /// Used to check if the samples are present. These filenames
     /// are known in advance and must be hard coded.
     fn raw_sample_filenames() -> Vec<&'static str>;
+    /// Returns the raw samples, with where each is obtained from.
+    ///
+    /// By default only the names (from [Self::raw_sample_filenames]) are known.
+    /// Sizes and CIDs are added from the samples manifest by `Todd::sample_artifacts`.
+    fn sample_artifacts() -> Vec<SampleArtifact> {
+        Self::raw_sample_filenames()
+            .into_iter()
+            .map(|name| SampleArtifact {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
     /// Returns the volume interface ids for volumes that
     /// are represented in the samples.
     ///
@@ -23,4 +56,10 @@ pub trait SampleObtainerMethods {
     fn sample_volumes() -> Option<Vec<&'static str>>;
     /// Detects if processed samples are present at the given location.
     fn get_raw_samples(dir: &Path) -> Result<()>;
+    /// Writes small synthetic raw samples to the given location.
+    ///
+    /// Used when the raw samples cannot be obtained (e.g., without network access).
+    /// The files have the names in [Self::raw_sample_filenames] and produce the
+    /// Volumes in [Self::sample_volumes], but their contents are not real data.
+    fn synthetic_raw_samples(dir: &Path) -> Result<()>;
 }
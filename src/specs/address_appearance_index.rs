@@ -1,7 +1,11 @@
 //! Address Appearance Index (AAI)
 
+use std::io::Read;
+
 use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 use ssz_rs::prelude::*;
+#[cfg(feature = "web3")]
 use web3::types::{BlockId, BlockNumber, TransactionId};
 
 use crate::{
@@ -9,14 +13,22 @@ use crate::{
     extraction::address_appearance_index::AAIExtractor,
     manifest::address_appearance_index::AAIManifest,
     parameters::address_appearance_index::{
-        BLOCKS_PER_VOLUME, DEFAULT_BYTES_PER_ADDRESS, MAX_ADDRESSES_PER_VOLUME,
-        MAX_RECORDS_PER_CHAPTER, MAX_TXS_PER_VOLUME, NUM_CHAPTERS, NUM_COMMON_BYTES,
+        BLOCKS_PER_VOLUME, DEFAULT_BYTES_PER_ADDRESS, MAX_RECORDS_PER_CHAPTER, MAX_TXS_PER_VOLUME,
+        MAX_VOLUMES, NUM_CHAPTERS, NUM_COMMON_BYTES,
     },
     samples::address_appearance_index::AAISampleObtainer,
-    utils::{self, unchained::types::BlockRange},
+    specs::{
+        header::{chapter_payload, corrupt},
+        ids::{nth_chapter_bytes, ChapterIdForm, VolumeIdForm},
+    },
+    utils::unchained::types::BlockRange,
 };
 
-use super::traits::*;
+use super::{
+    fingerprint::{container, field, SszDescriptor},
+    traits::*,
+    value_schema::ValueSchema,
+};
 
 /// Spec for the Address Appearance Index database.
 #[derive(Clone, Debug, Default, PartialEq, PartialOrd, Hash)]
@@ -24,6 +36,8 @@ pub struct AAISpec {}
 
 impl DataSpec for AAISpec {
     const NUM_CHAPTERS: usize = NUM_CHAPTERS as usize;
+    const SPEC_ID: &'static str = "address_appearance_index";
+    const ADDRESS_KEYED: bool = true;
 
     // const MAX_VOLUMES: usize = 1_000_000_000;
 
@@ -46,7 +60,11 @@ impl DataSpec for AAISpec {
     type AssociatedManifest = AAIManifest;
 
     fn spec_matches_input(data_kind: &DataKind) -> bool {
-        matches!(data_kind, DataKind::AddressAppearanceIndex(_))
+        // Exhaustive so that new DataKind variants must be considered here.
+        match data_kind {
+            DataKind::AddressAppearanceIndex(_) => true,
+            DataKind::NameTags | DataKind::Signatures | DataKind::Events => false,
+        }
     }
 
     fn spec_version() -> String {
@@ -56,11 +74,25 @@ impl DataSpec for AAISpec {
     fn spec_schemas_resource() -> String {
         String::from("https://github.com/perama-v/address-index/tree/main/address_appearance_index")
     }
+    fn value_schema() -> ValueSchema {
+        ValueSchema::list_of(ValueSchema::object(vec![
+            (
+                "block",
+                "The Ethereum execution block number.",
+                ValueSchema::Integer,
+            ),
+            (
+                "index",
+                "The index of the transaction in the block.",
+                ValueSchema::Integer,
+            ),
+        ]))
+    }
 
     fn record_key_to_chapter_id(
         record_key: &Self::AssociatedRecordKey,
     ) -> Result<Self::AssociatedChapterId> {
-        let bytes = record_key.key[0..2].to_vec();
+        let bytes = record_key.key[0..NUM_COMMON_BYTES].to_vec();
         Ok(AAIChapterId {
             val: Vector::from_iter(bytes),
         })
@@ -74,22 +106,32 @@ impl DataSpec for AAISpec {
     }
 }
 
+/// E.g., "volume_014_400_000".
+const VOLUME_ID_FORM: VolumeIdForm = VolumeIdForm {
+    prefix: "volume_",
+    per_volume: BLOCKS_PER_VOLUME,
+    max_volumes: MAX_VOLUMES,
+};
+
+/// E.g., "chapter_0x4e".
+const CHAPTER_ID_FORM: ChapterIdForm = ChapterIdForm {
+    prefix: "chapter_0x",
+    num_bytes: NUM_COMMON_BYTES,
+};
+
 #[derive(Clone, Debug, Default, PartialEq, PartialOrd, Hash, SimpleSerialize)]
 pub struct AAIVolumeId {
     pub oldest_block: u32,
 }
 impl VolumeIdMethods<AAISpec> for AAIVolumeId {
     fn interface_id(&self) -> String {
-        format!(
-            "volume_{}",
-            utils::string::num_as_triplet(self.oldest_block)
-        )
+        VOLUME_ID_FORM.format(self.oldest_block)
     }
     fn nth_id(n: u32) -> Result<Self> {
         // n=0, id=0
         // n=1, id=100_000
         // n=2, id=200_000
-        let oldest_block = n * BLOCKS_PER_VOLUME;
+        let oldest_block = VOLUME_ID_FORM.nth_start(n)?;
         Ok(AAIVolumeId { oldest_block })
     }
 
@@ -100,17 +142,37 @@ impl VolumeIdMethods<AAISpec> for AAIVolumeId {
         Ok(self.oldest_block / BLOCKS_PER_VOLUME)
     }
 
+    fn coverage(&self) -> Option<VolumeCoverage> {
+        Some(VolumeCoverage {
+            unit: "blocks".to_string(),
+            first: self.oldest_block as u64,
+            last: self.oldest_block as u64 + BLOCKS_PER_VOLUME as u64 - 1,
+        })
+    }
+
     fn from_interface_id(interface_id: &str) -> Result<Self> {
-        let oldest_block = interface_id
-            .trim_start_matches("volume")
-            .replace('_', "")
-            .parse::<u32>()?;
+        let oldest_block = VOLUME_ID_FORM.parse(interface_id)?;
         Ok(AAIVolumeId { oldest_block })
     }
 }
 impl AAIVolumeId {
-    pub(crate) fn to_block_range(&self) -> Result<BlockRange> {
-        BlockRange::new(self.oldest_block, BLOCKS_PER_VOLUME - 1 + self.oldest_block)
+    /// The Volume that holds appearances for a block.
+    ///
+    /// ## Errors
+    /// If the block is beyond the last possible Volume.
+    pub fn containing_block(block: u32) -> Result<Self> {
+        Self::nth_id(block / BLOCKS_PER_VOLUME)
+    }
+    /// Determines if a block is in the range of the Volume.
+    pub fn contains_block(&self, block: u32) -> bool {
+        block >= self.oldest_block && block - self.oldest_block < BLOCKS_PER_VOLUME
+    }
+    /// The blocks (inclusive) that the Volume covers.
+    pub fn block_range(&self) -> Result<BlockRange> {
+        let Some(last) = self.oldest_block.checked_add(BLOCKS_PER_VOLUME - 1) else {
+            bail!("Volume starting at block {} overflows.", self.oldest_block)
+        };
+        BlockRange::new(self.oldest_block, last)
     }
 }
 
@@ -120,27 +182,24 @@ pub struct AAIChapterId {
 }
 impl ChapterIdMethods<AAISpec> for AAIChapterId {
     fn interface_id(&self) -> String {
-        let chars = hex::encode(&self.val);
-        format!("chapter_0x{}", chars)
+        CHAPTER_ID_FORM.format(&self.val)
     }
     fn nth_id(n: u32) -> Result<Self> {
-        if n as usize >= AAISpec::NUM_CHAPTERS {
-            bail!("'n' must be <= NUM_CHAPTERS")
-        }
-        let byte_vec = vec![n as u8];
+        let byte_vec = nth_chapter_bytes(n, AAISpec::NUM_CHAPTERS)?;
         Ok(AAIChapterId {
             val: Vector::from_iter(byte_vec),
         })
     }
     fn from_interface_id(id_string: &str) -> Result<Self> {
-        let string = id_string.trim_start_matches("chapter_0x");
-        let bytes = hex::decode(string)?;
+        let bytes = CHAPTER_ID_FORM.parse(id_string)?;
         Ok(AAIChapterId {
             val: Vector::from_iter(bytes),
         })
     }
 }
 
+/// Records are in canonical order: ascending by their SSZ encoding. The key is
+/// the leading fixed size field, so this orders by key, then by value.
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
 pub struct AAIChapter {
     pub chapter_id: AAIChapterId,
@@ -163,18 +222,24 @@ impl ChapterMethods<AAISpec> for AAIChapter {
     fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
         Ok(serialize::<Self>(self)?)
     }
-    /// Reads a Chapter from file. Currently reads Relic file structure.
+    /// Reads a Chapter from file.
+    ///
+    /// Files in the prototype format (see [AAIChapterFormat]) are converted on read.
     fn from_file(data: Vec<u8>) -> Result<Self> {
-        // Files are ssz encoded.
-        let chapter = match deserialize::<Self>(&data) {
-            Ok(c) => c,
-            Err(e) => bail!(
-                "Could not decode the SSZ data. Check that the library
+        if let Some(payload) = chapter_payload::<AAISpec>(&data)? {
+            return deserialize::<Self>(payload).map_err(corrupt);
+        }
+        match Self::format_of(&data)? {
+            AAIChapterFormat::Current => match deserialize::<Self>(&data) {
+                Ok(c) => Ok(c),
+                Err(e) => bail!(
+                    "Could not decode the SSZ data. Check that the library
             spec version matches the version in the manifest.  {:?}",
-                e
-            ),
-        };
-        Ok(chapter)
+                    e
+                ),
+            },
+            AAIChapterFormat::Prototype => Self::from_prototype_bytes(&data),
+        }
     }
     fn filename(&self) -> String {
         format!(
@@ -191,38 +256,114 @@ impl ChapterMethods<AAISpec> for AAIChapter {
             records: List::default(),
         }
     }
+
+    fn from_records(
+        volume_id: &AAIVolumeId,
+        chapter_id: &AAIChapterId,
+        records: Vec<AAIRecord>,
+    ) -> Result<Self> {
+        if records.len() > MAX_RECORDS_PER_CHAPTER {
+            bail!(
+                "{} Records exceeds the maximum of {} per Chapter.",
+                records.len(),
+                MAX_RECORDS_PER_CHAPTER
+            )
+        }
+        Ok(AAIChapter {
+            chapter_id: chapter_id.clone(),
+            volume_id: volume_id.clone(),
+            records: List::from_iter(records),
+        })
+    }
+}
+
+/// The layout of the bytes in an AAI Chapter file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AAIChapterFormat {
+    /// SSZ encoded [AAIChapter], with or without a
+    /// [ChapterHeader][crate::specs::header::ChapterHeader]. Written by this library.
+    Current,
+    /// Snappy compressed Chapter from the prototype pipeline. Only read, and
+    /// converted by [Todd::migrate_prototype_layout][crate::database::types::Todd::migrate_prototype_layout].
+    Prototype,
 }
 
 impl AAIChapter {
-    /// A helper function that converts the old data chapter (file) structure into the new
-    /// one.
+    /// Detects the format of the bytes of a Chapter file, without decoding the Records.
     ///
-    /// This is used to minimise changes to the transformation/exctraction code during
-    /// the move to generics and can replaced eventually.
-    pub(crate) fn from_relic(data: RelicChapter) -> Self {
-        let chapter_id = AAIChapterId {
-            val: Vector::from_iter(data.address_prefix.to_vec()),
+    /// ## Algorithm
+    /// 1. A Chapter header means the current format.
+    /// 2. A snappy frame stream identifier means the prototype format.
+    /// 3. SSZ whose Records offset directly follows the ChapterId and VolumeId
+    ///    means the current format.
+    /// 4. Raw snappy that decompresses to the same SSZ layout means the prototype format.
+    ///
+    /// ## Errors
+    /// If the bytes are in neither format.
+    pub fn format_of(data: &[u8]) -> Result<AAIChapterFormat> {
+        if chapter_payload::<AAISpec>(data)?.is_some() {
+            return Ok(AAIChapterFormat::Current);
+        }
+        if data.starts_with(SNAPPY_FRAME_MAGIC) {
+            return Ok(AAIChapterFormat::Prototype);
+        }
+        if has_chapter_layout(data) {
+            return Ok(AAIChapterFormat::Current);
+        }
+        if let Ok(decompressed) = snap::raw::Decoder::new().decompress_vec(data) {
+            if has_chapter_layout(&decompressed) {
+                return Ok(AAIChapterFormat::Prototype);
+            }
+        }
+        bail!("Chapter file is neither SSZ nor a snappy compressed prototype Chapter.")
+    }
+    /// Decodes a Chapter written by the prototype pipeline (`transform.rs`),
+    /// whose files are snappy compressed and hold the addresses without a
+    /// RecordValue container.
+    ///
+    /// Both raw and framed snappy are accepted.
+    pub fn from_prototype_bytes(data: &[u8]) -> Result<Self> {
+        let decompressed = match data.starts_with(SNAPPY_FRAME_MAGIC) {
+            true => {
+                let mut bytes = vec![];
+                snap::read::FrameDecoder::new(data).read_to_end(&mut bytes)?;
+                bytes
+            }
+            false => snap::raw::Decoder::new().decompress_vec(data)?,
         };
-        let volume_id = AAIVolumeId {
-            oldest_block: data.identifier.oldest_block,
+        let prototype = match deserialize::<AAIChapterPrototype>(&decompressed) {
+            Ok(p) => p,
+            Err(e) => bail!("Could not decode the prototype SSZ data. {:?}", e),
         };
-        let mut records: Vec<AAIRecord> = vec![];
-        for item in data.addresses.iter() {
-            let r = AAIRecord {
-                key: AAIRecordKey {
-                    key: Vector::from_iter(item.address.to_vec()),
-                },
-                value: AAIRecordValue {
-                    value: List::from_iter(item.appearances.to_vec()),
-                },
-            };
-            records.push(r)
-        }
-        AAIChapter {
-            chapter_id,
-            volume_id,
+        let records = prototype.addresses.iter().map(|a| AAIRecord {
+            key: AAIRecordKey {
+                key: a.address.clone(),
+            },
+            value: AAIRecordValue {
+                value: a.appearances.clone(),
+            },
+        });
+        Ok(AAIChapter {
+            chapter_id: AAIChapterId {
+                val: prototype.address_prefix,
+            },
+            volume_id: prototype.identifier,
             records: List::from_iter(records),
-        }
+        })
+    }
+    /// Encodes the Chapter as the prototype pipeline did (raw snappy).
+    #[cfg(test)]
+    pub(crate) fn as_prototype_bytes(&self) -> Result<Vec<u8>> {
+        let addresses = self.records.iter().map(|r| AAIRecordPrototype {
+            address: r.key.key.clone(),
+            appearances: r.value.value.clone(),
+        });
+        let prototype = AAIChapterPrototype {
+            address_prefix: self.chapter_id.val.clone(),
+            identifier: self.volume_id.clone(),
+            addresses: List::from_iter(addresses),
+        };
+        Ok(snap::raw::Encoder::new().compress_vec(&serialize(&prototype)?)?)
     }
 }
 
@@ -239,6 +380,14 @@ impl RecordMethods<AAISpec> for AAIRecord {
     fn value(&self) -> &AAIRecordValue {
         &self.value
     }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
+
+    fn from_key_value(key: AAIRecordKey, value: AAIRecordValue) -> Self {
+        AAIRecord { key, value }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
@@ -249,6 +398,10 @@ impl RecordKeyMethods for AAIRecordKey {
     fn summary_string(&self) -> Result<String> {
         Ok(hex::encode(&self.key))
     }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
 }
 
 /// Equivalent to AddressAppearances. Consists of a single address and some
@@ -259,6 +412,7 @@ pub struct AAIRecordValue {
     pub value: List<AAIAppearanceTx, MAX_TXS_PER_VOLUME>,
 }
 impl RecordValueMethods for AAIRecordValue {
+    type Summary = Vec<TxRef>;
     /// Return a String representation of the contents of the RecordValue.
     fn summary_strings(&self) -> Result<Vec<String>> {
         let mut s: Vec<String> = vec![];
@@ -268,6 +422,34 @@ impl RecordValueMethods for AAIRecordValue {
         }
         Ok(s)
     }
+
+    fn summary(&self) -> Result<Vec<TxRef>> {
+        Ok(self
+            .value
+            .iter()
+            .map(|v| TxRef {
+                block: v.block,
+                index: v.index,
+            })
+            .collect())
+    }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
+
+    fn from_serialized_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(deserialize::<Self>(bytes)?)
+    }
+}
+
+/// A transaction that an address appears in. The summary of [AAIRecordValue].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxRef {
+    /// The Ethereum execution block number.
+    pub block: u32,
+    /// The index of the transaction in a block.
+    pub index: u32,
 }
 
 /// An identifier for a single transaction.
@@ -282,7 +464,8 @@ pub struct AAIAppearanceTx {
 }
 
 impl AAIAppearanceTx {
-    /// Converts to web3.rs transaction type.
+    /// Converts to web3.rs transaction type. Requires the `web3` feature.
+    #[cfg(feature = "web3")]
     pub fn as_web3_tx_id(&self) -> web3::types::TransactionId {
         let block_num = BlockNumber::Number(<_>::from(self.block));
         let tx_block_id = BlockId::Number(block_num);
@@ -291,34 +474,140 @@ impl AAIAppearanceTx {
     }
 }
 
-//
-//
-// Relic structures. The files are currently stored in this format, but this
-// can be changed to a simpler format (using RecordKey and RecordValue directly).
-//
-//
+// Types from the prototype pipeline, used to read and migrate its Chapter files.
+
+/// Start of a framed snappy stream.
+const SNAPPY_FRAME_MAGIC: &[u8] = b"\xff\x06\x00\x00sNaPpY";
 
-#[derive(PartialEq, Debug, Default, SimpleSerialize, Clone)]
-pub struct RelicChapter {
-    /// Prefix common to all addresses that this data covers.
-    pub address_prefix: Vector<u8, DEFAULT_BYTES_PER_ADDRESS>,
-    /// The blocks that this chunk data covers.
-    pub identifier: RelicVolumeIdentifier,
-    /// The addresses that appeared in this range and the relevant transactions.
-    pub addresses: List<RelicAddressAppearances, MAX_ADDRESSES_PER_VOLUME>,
+/// Byte length of the fixed part of an SSZ Chapter (current or prototype):
+/// ChapterId, VolumeId and the offset of the Records.
+const CHAPTER_FIXED_BYTES: usize = NUM_COMMON_BYTES + 4 + 4;
+
+/// Whether the bytes start like an SSZ Chapter, with the Records directly after
+/// the fixed part.
+fn has_chapter_layout(data: &[u8]) -> bool {
+    let Some(offset) = data.get(CHAPTER_FIXED_BYTES - 4..CHAPTER_FIXED_BYTES) else {
+        return false;
+    };
+    u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize == CHAPTER_FIXED_BYTES
 }
 
-#[derive(Debug, Default, PartialEq, Clone, SimpleSerialize)]
-pub struct RelicAddressAppearances {
-    /// The address that appeared in a transaction.
-    pub address: Vector<u8, DEFAULT_BYTES_PER_ADDRESS>,
-    /// The transactions where the address appeared.
-    pub appearances: List<AAIAppearanceTx, MAX_TXS_PER_VOLUME>,
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct AAIChapterPrototype {
+    address_prefix: Vector<u8, NUM_COMMON_BYTES>,
+    identifier: AAIVolumeId,
+    addresses: List<AAIRecordPrototype, MAX_RECORDS_PER_CHAPTER>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Default, SimpleSerialize)]
-pub struct RelicVolumeIdentifier {
-    pub oldest_block: u32,
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct AAIRecordPrototype {
+    address: Vector<u8, DEFAULT_BYTES_PER_ADDRESS>,
+    appearances: List<AAIAppearanceTx, MAX_TXS_PER_VOLUME>,
+}
+
+// SSZ layouts, for the schema fingerprint.
+
+impl SszDescriptor for AAIChapter {
+    fn ssz_descriptor() -> String {
+        let AAIChapter {
+            chapter_id,
+            volume_id,
+            records,
+        } = AAIChapter::default();
+        container(&[field(&chapter_id), field(&volume_id), field(&records)])
+    }
+}
+
+impl SszDescriptor for AAIChapterId {
+    fn ssz_descriptor() -> String {
+        let AAIChapterId { val } = AAIChapterId::default();
+        container(&[field(&val)])
+    }
+}
+
+impl SszDescriptor for AAIVolumeId {
+    fn ssz_descriptor() -> String {
+        let AAIVolumeId { oldest_block } = AAIVolumeId::default();
+        container(&[field(&oldest_block)])
+    }
+}
+
+impl SszDescriptor for AAIRecord {
+    fn ssz_descriptor() -> String {
+        let AAIRecord { key, value } = AAIRecord::default();
+        container(&[field(&key), field(&value)])
+    }
+}
+
+impl SszDescriptor for AAIRecordKey {
+    fn ssz_descriptor() -> String {
+        let AAIRecordKey { key } = AAIRecordKey::default();
+        container(&[field(&key)])
+    }
+}
+
+impl SszDescriptor for AAIRecordValue {
+    fn ssz_descriptor() -> String {
+        let AAIRecordValue { value } = AAIRecordValue::default();
+        container(&[field(&value)])
+    }
+}
+
+impl SszDescriptor for AAIAppearanceTx {
+    fn ssz_descriptor() -> String {
+        let AAIAppearanceTx { block, index } = AAIAppearanceTx::default();
+        container(&[field(&block), field(&index)])
+    }
+}
+
+#[test]
+fn reads_prototype_chapter() {
+    let key = AAISpec::raw_key_as_record_key("0x4e8a9a2b47e2c6ee2e5ed1d7b0fd07d6ee3e8c51").unwrap();
+    let chapter = AAIChapter {
+        chapter_id: AAIChapterId::nth_id(0x4e).unwrap(),
+        volume_id: AAIVolumeId::nth_id(144).unwrap(),
+        records: List::from_iter(vec![AAIRecord {
+            key,
+            value: AAIRecordValue {
+                value: List::from_iter(vec![AAIAppearanceTx {
+                    block: 14_400_001,
+                    index: 3,
+                }]),
+            },
+        }]),
+    };
+    let raw = chapter.as_prototype_bytes().unwrap();
+    assert_eq!(
+        AAIChapter::format_of(&raw).unwrap(),
+        AAIChapterFormat::Prototype
+    );
+    assert_eq!(AAIChapter::from_file(raw.clone()).unwrap(), chapter);
+
+    let ssz = snap::raw::Decoder::new().decompress_vec(&raw).unwrap();
+    let mut framed = vec![];
+    let mut encoder = snap::write::FrameEncoder::new(&mut framed);
+    std::io::Write::write_all(&mut encoder, &ssz).unwrap();
+    drop(encoder);
+    assert_eq!(
+        AAIChapter::format_of(&framed).unwrap(),
+        AAIChapterFormat::Prototype
+    );
+    assert_eq!(AAIChapter::from_file(framed).unwrap(), chapter);
+    // The current encoding is not a prototype Chapter.
+    assert!(AAIChapter::from_prototype_bytes(&ssz).is_err());
+
+    let current = chapter.as_serialized_bytes().unwrap();
+    assert_eq!(
+        AAIChapter::format_of(&current).unwrap(),
+        AAIChapterFormat::Current
+    );
+    assert_eq!(AAIChapter::from_file(current).unwrap(), chapter);
+    let empty = AAIChapter::new_empty(&chapter.volume_id, &chapter.chapter_id);
+    assert_eq!(
+        AAIChapter::format_of(&empty.as_serialized_bytes().unwrap()).unwrap(),
+        AAIChapterFormat::Current
+    );
+    assert!(AAIChapter::format_of(b"not a chapter").is_err());
 }
 
 #[test]
@@ -333,3 +622,25 @@ fn encode_decode() -> Result<()> {
     assert_eq!(data_in, data_out);
     Ok(())
 }
+
+#[test]
+fn volume_block_ranges() {
+    let volume_id = AAIVolumeId::containing_block(17_345_678).unwrap();
+    assert_eq!(volume_id.interface_id(), "volume_017_300_000");
+    let range = volume_id.block_range().unwrap();
+    assert_eq!((range.old, range.new), (17_300_000, 17_399_999));
+    assert!(range.contains_block(17_345_678));
+    assert!(volume_id.contains_block(17_399_999));
+    assert!(!volume_id.contains_block(17_400_000));
+    assert!(!volume_id.contains_block(17_299_999));
+    assert!(AAIVolumeId::containing_block(u32::MAX).is_err());
+}
+
+#[test]
+fn record_key_to_chapter_id_uses_spec_length() {
+    let record_key = AAIRecordKey {
+        key: Vector::from_iter(hex::decode("de0b295669a9fd93d5f28d9ec85e40f4cb697bae").unwrap()),
+    };
+    let chapter_id = AAISpec::record_key_to_chapter_id(&record_key).unwrap();
+    assert_eq!(chapter_id.interface_id(), "chapter_0xde");
+}
@@ -0,0 +1,429 @@
+use std::fmt::Display;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use ssz_rs::prelude::*;
+
+use crate::{
+    config::choices::DataKind,
+    extraction::events::EventsExtractor,
+    manifest::events::EventsManifest,
+    parameters::events::{
+        BYTES_FOR_TOPIC_CHARS, BYTES_PER_TOPIC, EVENTS_PER_VOLUME, MAX_RECORDS_PER_CHAPTER,
+        MAX_TEXTS_PER_RECORD, MAX_VOLUMES,
+    },
+    samples::events::EventsSampleObtainer,
+    specs::{
+        header::{chapter_payload, corrupt},
+        ids::{nth_chapter_bytes, ChapterIdForm, VolumeIdForm},
+        signatures::Text,
+    },
+};
+
+use super::{
+    fingerprint::{container, field, SszDescriptor},
+    traits::*,
+    value_schema::ValueSchema,
+};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventsSpec {}
+
+impl DataSpec for EventsSpec {
+    const NUM_CHAPTERS: usize = 256;
+    const SPEC_ID: &'static str = "events";
+    // Logs often have topics that are not events (e.g., anonymous events), so favour
+    // fewer false positives.
+    const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.001;
+
+    type AssociatedChapter = EventsChapter;
+
+    type AssociatedChapterId = EventsChapterId;
+
+    type AssociatedVolumeId = EventsVolumeId;
+
+    type AssociatedRecord = EventsRecord;
+
+    type AssociatedRecordKey = EventsRecordKey;
+
+    type AssociatedRecordValue = EventsRecordValue;
+
+    type AssociatedExtractor = EventsExtractor;
+
+    type AssociatedSampleObtainer = EventsSampleObtainer;
+
+    type AssociatedManifest = EventsManifest;
+
+    fn spec_matches_input(data_kind: &DataKind) -> bool {
+        // Exhaustive so that new DataKind variants must be considered here.
+        match data_kind {
+            DataKind::Events => true,
+            DataKind::AddressAppearanceIndex(_) | DataKind::NameTags | DataKind::Signatures => {
+                false
+            }
+        }
+    }
+
+    fn spec_version() -> String {
+        String::from("0.1.0")
+    }
+
+    fn spec_schemas_resource() -> String {
+        String::from("https://github.com/perama-v/TODD/blob/main/example_specs/events.md")
+    }
+    fn value_schema() -> ValueSchema {
+        ValueSchema::object(vec![(
+            "texts",
+            "Event signatures with the topic. E.g., \"Transfer(address,address,uint256)\".",
+            ValueSchema::list_of(ValueSchema::String),
+        )])
+    }
+
+    fn record_key_to_chapter_id(record_key: &EventsRecordKey) -> Result<EventsChapterId> {
+        let bytes = record_key.key[0..BYTES_FOR_TOPIC_CHARS].to_vec();
+        Ok(EventsChapterId {
+            val: Vector::from_iter(bytes),
+        })
+    }
+
+    fn raw_key_as_record_key(key: &str) -> Result<EventsRecordKey> {
+        let raw_bytes = hex::decode(key.trim_start_matches("0x"))?;
+        Ok(EventsRecordKey {
+            key: Vector::from_iter(raw_bytes),
+        })
+    }
+}
+
+/// Records are in canonical order: ascending by their SSZ encoding. The key is
+/// the leading fixed size field, so this orders by key, then by value.
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+pub struct EventsChapter {
+    pub chapter_id: EventsChapterId,
+    pub volume_id: EventsVolumeId,
+    pub records: List<EventsRecord, MAX_RECORDS_PER_CHAPTER>,
+}
+
+impl ChapterMethods<EventsSpec> for EventsChapter {
+    fn volume_id(&self) -> &EventsVolumeId {
+        &self.volume_id
+    }
+
+    fn chapter_id(&self) -> &EventsChapterId {
+        &self.chapter_id
+    }
+
+    fn records(&self) -> &Vec<EventsRecord> {
+        &self.records
+    }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
+
+    fn from_file(data: Vec<u8>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        if let Some(payload) = chapter_payload::<EventsSpec>(&data)? {
+            return deserialize::<Self>(payload).map_err(corrupt);
+        }
+        // Files without a header are ssz encoded.
+        let chapter = match deserialize::<Self>(&data) {
+            Ok(c) => c,
+            Err(e) => bail!(
+                "Could not decode the SSZ data. Check that the library
+            spec version matches the version in the manifest.  {:?}",
+                e
+            ),
+        };
+        Ok(chapter)
+    }
+
+    fn filename(&self) -> String {
+        format!(
+            "{}_{}.ssz",
+            self.volume_id.interface_id(),
+            self.chapter_id.interface_id()
+        )
+    }
+
+    fn new_empty(volume_id: &EventsVolumeId, chapter_id: &EventsChapterId) -> Self {
+        EventsChapter {
+            chapter_id: chapter_id.clone(),
+            volume_id: volume_id.clone(),
+            records: List::default(),
+        }
+    }
+
+    fn from_records(
+        volume_id: &EventsVolumeId,
+        chapter_id: &EventsChapterId,
+        records: Vec<EventsRecord>,
+    ) -> Result<Self> {
+        if records.len() > MAX_RECORDS_PER_CHAPTER {
+            bail!(
+                "{} Records exceeds the maximum of {} per Chapter.",
+                records.len(),
+                MAX_RECORDS_PER_CHAPTER
+            )
+        }
+        Ok(EventsChapter {
+            chapter_id: chapter_id.clone(),
+            volume_id: volume_id.clone(),
+            records: List::from_iter(records),
+        })
+    }
+}
+
+/// E.g., "topics_0xdd".
+const CHAPTER_ID_FORM: ChapterIdForm = ChapterIdForm {
+    prefix: "topics_0x",
+    num_bytes: BYTES_FOR_TOPIC_CHARS,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+pub struct EventsChapterId {
+    pub val: Vector<u8, BYTES_FOR_TOPIC_CHARS>,
+}
+
+impl ChapterIdMethods<EventsSpec> for EventsChapterId {
+    fn from_interface_id(id_string: &str) -> Result<Self> {
+        let bytes = CHAPTER_ID_FORM.parse(id_string)?;
+        Ok(EventsChapterId {
+            val: Vector::from_iter(bytes),
+        })
+    }
+
+    fn interface_id(&self) -> String {
+        CHAPTER_ID_FORM.format(&self.val)
+    }
+
+    fn nth_id(n: u32) -> Result<EventsChapterId> {
+        let byte_vec = nth_chapter_bytes(n, EventsSpec::NUM_CHAPTERS)?;
+        Ok(EventsChapterId {
+            val: Vector::from_iter(byte_vec),
+        })
+    }
+}
+
+impl EventsChapterId {
+    /// Returns the ChapterId as a hex string (no 0x prefix).
+    pub fn as_string(&self) -> String {
+        hex::encode(&self.val)
+    }
+    /// Returns true if the candidate string starts with the ChapterId.
+    pub fn matches(&self, candidate: &str) -> bool {
+        candidate.starts_with(&self.as_string())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd, Hash, SimpleSerialize)]
+pub struct EventsVolumeId {
+    /// Refers to the first event in the Volume.
+    ///
+    /// It is global index of the topic
+    /// where all volumes are ordered oldest to youngest.
+    ///
+    /// ## Example
+    ///
+    /// The first event in the first volume is 0, the first event in the
+    /// second volume is 1000 (derived from EVENTS_PER_VOLUME).
+    pub first_event: u32,
+}
+
+/// E.g., "events_starting_000_001_000".
+const VOLUME_ID_FORM: VolumeIdForm = VolumeIdForm {
+    prefix: "events_starting_",
+    per_volume: EVENTS_PER_VOLUME as u32,
+    max_volumes: MAX_VOLUMES,
+};
+
+impl VolumeIdMethods<EventsSpec> for EventsVolumeId {
+    fn from_interface_id(interface_id: &str) -> Result<Self> {
+        let first_event = VOLUME_ID_FORM.parse(interface_id)?;
+        Ok(EventsVolumeId { first_event })
+    }
+
+    fn interface_id(&self) -> String {
+        VOLUME_ID_FORM.format(self.first_event)
+    }
+
+    fn nth_id(n: u32) -> Result<EventsVolumeId> {
+        Ok(EventsVolumeId {
+            first_event: VOLUME_ID_FORM.nth_start(n)?,
+        })
+    }
+
+    fn is_nth(&self) -> Result<u32> {
+        Ok(self.first_event / EVENTS_PER_VOLUME as u32)
+    }
+
+    fn coverage(&self) -> Option<VolumeCoverage> {
+        Some(VolumeCoverage {
+            unit: "events".to_string(),
+            first: self.first_event as u64,
+            last: self.first_event as u64 + EVENTS_PER_VOLUME as u64 - 1,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+pub struct EventsRecord {
+    pub key: EventsRecordKey,
+    pub value: EventsRecordValue,
+}
+
+impl RecordMethods<EventsSpec> for EventsRecord {
+    fn key(&self) -> &EventsRecordKey {
+        &self.key
+    }
+
+    fn value(&self) -> &EventsRecordValue {
+        &self.value
+    }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
+
+    fn from_key_value(key: EventsRecordKey, value: EventsRecordValue) -> Self {
+        EventsRecord { key, value }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+pub struct EventsRecordKey {
+    key: Vector<u8, BYTES_PER_TOPIC>,
+}
+
+impl RecordKeyMethods for EventsRecordKey {
+    fn summary_string(&self) -> Result<String> {
+        Ok(hex::encode(&self.key))
+    }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
+}
+
+impl EventsRecordKey {
+    /// Makes a key from a hex topic (64 characters, with or without "0x").
+    pub fn from_topic(topic: &str) -> Result<Self> {
+        let topic = topic.trim_start_matches("0x");
+        if topic.len() != 2 * BYTES_PER_TOPIC {
+            bail!("Topic provided must be 64 characters long.")
+        }
+        let raw_bytes = hex::decode(topic)?;
+        Ok(Self {
+            key: Vector::from_iter(raw_bytes),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+pub struct EventsRecordValue {
+    pub texts: List<Text, MAX_TEXTS_PER_RECORD>,
+}
+
+impl Display for EventsRecordValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = String::from("Text(s):");
+        let strings = match self.texts_as_strings() {
+            Ok(s) => s,
+            Err(_) => return Err(std::fmt::Error),
+        };
+        for t in strings {
+            s.push(' ');
+            s.push_str(&t)
+        }
+        write!(f, "{}", s)?;
+        Ok(())
+    }
+}
+
+impl RecordValueMethods for EventsRecordValue {
+    type Summary = EventsSummary;
+    fn summary_strings(&self) -> Result<Vec<String>> {
+        let t = format!("texts: {:?}", self.texts_as_strings()?);
+        Ok(vec![t])
+    }
+
+    fn summary(&self) -> Result<EventsSummary> {
+        Ok(EventsSummary {
+            texts: self.texts_as_strings()?,
+        })
+    }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
+
+    fn from_serialized_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(deserialize::<Self>(bytes)?)
+    }
+}
+
+impl EventsRecordValue {
+    /// Turns SSZ bytes into a vector of readable strings.
+    pub fn texts_as_strings(&self) -> Result<Vec<String>> {
+        let mut s = vec![];
+        for n in &self.texts.to_vec() {
+            s.push(n.to_utf8_string()?)
+        }
+        Ok(s)
+    }
+}
+
+/// The texts for a topic. The summary of [EventsRecordValue].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventsSummary {
+    pub texts: Vec<String>,
+}
+
+// SSZ layouts, for the schema fingerprint.
+
+impl SszDescriptor for EventsChapter {
+    fn ssz_descriptor() -> String {
+        let EventsChapter {
+            chapter_id,
+            volume_id,
+            records,
+        } = EventsChapter::default();
+        container(&[field(&chapter_id), field(&volume_id), field(&records)])
+    }
+}
+
+impl SszDescriptor for EventsChapterId {
+    fn ssz_descriptor() -> String {
+        let EventsChapterId { val } = EventsChapterId::default();
+        container(&[field(&val)])
+    }
+}
+
+impl SszDescriptor for EventsVolumeId {
+    fn ssz_descriptor() -> String {
+        let EventsVolumeId { first_event } = EventsVolumeId::default();
+        container(&[field(&first_event)])
+    }
+}
+
+impl SszDescriptor for EventsRecord {
+    fn ssz_descriptor() -> String {
+        let EventsRecord { key, value } = EventsRecord::default();
+        container(&[field(&key), field(&value)])
+    }
+}
+
+impl SszDescriptor for EventsRecordKey {
+    fn ssz_descriptor() -> String {
+        let EventsRecordKey { key } = EventsRecordKey::default();
+        container(&[field(&key)])
+    }
+}
+
+impl SszDescriptor for EventsRecordValue {
+    fn ssz_descriptor() -> String {
+        let EventsRecordValue { texts } = EventsRecordValue::default();
+        container(&[field(&texts)])
+    }
+}
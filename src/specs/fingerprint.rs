@@ -0,0 +1,171 @@
+//! A fingerprint of the SSZ layout of the Chapters of a spec.
+//!
+//! The spec version is set by hand and may not be changed when the Chapter
+//! types are. The fingerprint is computed from the types themselves: a
+//! descriptor of the Chapter type (each field type in order, with list and
+//! vector lengths) is hashed. It is stored in the manifest when the manifest is
+//! generated, and a manifest for the same spec version with a different
+//! fingerprint was made by a build with different Chapter types.
+//!
+//! Field and type names are not part of the descriptor, as they do not change
+//! the encoding.
+//!
+//! ## Example
+//! The descriptor of the nametags Record value (names, tags and provenance):
+//! ```text
+//! container(list<container(list<uint8,32>,list<uint8,32>),256>,list<container(list<uint8,32>,list<uint8,32>),256>,container(list<uint64,1>))
+//! ```
+use std::fmt::Display;
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use ssz_rs::prelude::*;
+
+use super::traits::{DataSpec, ManifestMethods};
+
+/// A type that describes its SSZ layout.
+///
+/// Containers list the descriptor of each field in declaration order, using
+/// [container] and [field]. The fields are destructured so that adding a field
+/// to the type without adding it to the descriptor does not compile.
+pub trait SszDescriptor {
+    fn ssz_descriptor() -> String;
+}
+
+impl SszDescriptor for u8 {
+    fn ssz_descriptor() -> String {
+        "uint8".to_string()
+    }
+}
+
+impl SszDescriptor for u16 {
+    fn ssz_descriptor() -> String {
+        "uint16".to_string()
+    }
+}
+
+impl SszDescriptor for u32 {
+    fn ssz_descriptor() -> String {
+        "uint32".to_string()
+    }
+}
+
+impl SszDescriptor for u64 {
+    fn ssz_descriptor() -> String {
+        "uint64".to_string()
+    }
+}
+
+impl SszDescriptor for bool {
+    fn ssz_descriptor() -> String {
+        "boolean".to_string()
+    }
+}
+
+impl<T: SszDescriptor + SimpleSerialize, const N: usize> SszDescriptor for Vector<T, N> {
+    fn ssz_descriptor() -> String {
+        format!("vector<{},{}>", T::ssz_descriptor(), N)
+    }
+}
+
+impl<T: SszDescriptor + SimpleSerialize, const N: usize> SszDescriptor for List<T, N> {
+    fn ssz_descriptor() -> String {
+        format!("list<{},{}>", T::ssz_descriptor(), N)
+    }
+}
+
+/// The descriptor of a container with fields of the given descriptors.
+pub fn container(fields: &[String]) -> String {
+    format!("container({})", fields.join(","))
+}
+
+/// The descriptor of the type of a field.
+pub fn field<F: SszDescriptor>(_field: &F) -> String {
+    F::ssz_descriptor()
+}
+
+/// Hashes a descriptor: sha256, hex encoded.
+pub fn fingerprint(descriptor: &str) -> String {
+    hex::encode(Sha256::digest(descriptor.as_bytes()))
+}
+
+/// Error returned when a manifest was made for the same spec version as the
+/// library, but with a different Chapter layout.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaMismatch {
+    pub spec_version: String,
+    /// The fingerprint of the Chapter types of this library.
+    pub expected: String,
+    /// The fingerprint in the manifest.
+    pub found: String,
+}
+
+impl Display for SchemaMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The manifest for spec version {} has schema fingerprint {}, but this library has {}. It was made by a build with different Chapter types.",
+            self.spec_version, self.found, self.expected
+        )
+    }
+}
+
+impl std::error::Error for SchemaMismatch {}
+
+/// Checks that a manifest for the spec version of the library has the schema
+/// fingerprint of the library.
+///
+/// Manifests for other spec versions, or without a fingerprint, are not checked.
+///
+/// ## Errors
+/// A [SchemaMismatch] error if the fingerprints differ.
+pub fn check_manifest_schema<T: DataSpec>(manifest: &T::AssociatedManifest) -> Result<()> {
+    if manifest.spec_version() != T::spec_version() {
+        return Ok(());
+    }
+    let Some(found) = manifest.schema_fingerprint() else {
+        return Ok(());
+    };
+    let expected = T::schema_fingerprint();
+    if found != expected {
+        return Err(SchemaMismatch {
+            spec_version: T::spec_version(),
+            expected,
+            found: found.to_string(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[test]
+fn fingerprints_follow_layout() {
+    use crate::specs::{
+        address_appearance_index::AAISpec, events::EventsSpec, nametags::NameTagsSpec,
+        signatures::SignaturesSpec, traits::DataSpec,
+    };
+
+    assert_eq!(
+        List::<Vector<u8, 4>, 10>::ssz_descriptor(),
+        "list<vector<uint8,4>,10>"
+    );
+    assert_eq!(
+        container(&[field(&0u32), field(&List::<u64, 1>::default())]),
+        "container(uint32,list<uint64,1>)"
+    );
+    // Lengths are part of the layout.
+    assert_ne!(
+        fingerprint(&List::<u8, 32>::ssz_descriptor()),
+        fingerprint(&List::<u8, 64>::ssz_descriptor())
+    );
+    let all = [
+        AAISpec::schema_fingerprint(),
+        NameTagsSpec::schema_fingerprint(),
+        SignaturesSpec::schema_fingerprint(),
+        EventsSpec::schema_fingerprint(),
+    ];
+    for (i, a) in all.iter().enumerate() {
+        assert_eq!(a.len(), 64);
+        assert!(all[i + 1..].iter().all(|b| a != b));
+    }
+}
@@ -0,0 +1,386 @@
+//! Property-based test data generators for each spec (tests only).
+//!
+//! [SpecGenerators] provides [proptest] strategies for the Records of a spec.
+//! [chapter] combines them into a Chapter of any Volume and Chapter, with keys
+//! that belong to the Chapter, in canonical order (ascending by SSZ encoding,
+//! one Record per key).
+//!
+//! Byte fields are generated up to their SSZ maximum. List lengths are generated
+//! up to the SSZ maximum or [MAX_GENERATED_LEN], whichever is smaller, so that
+//! cases stay quick to encode.
+//!
+//! The round-trip properties ([check_chapter_round_trip], [check_manifest_cids])
+//! are run for every spec by the tests at the end of this module.
+use std::collections::HashSet;
+
+use proptest::{collection::vec, prelude::*};
+use ssz_rs::prelude::*;
+
+use crate::{
+    parameters::{
+        address_appearance_index::DEFAULT_BYTES_PER_ADDRESS,
+        events::BYTES_PER_TOPIC,
+        nametags::{
+            BYTES_PER_ADDRESS, MAX_BYTES_PER_NAME, MAX_BYTES_PER_SOURCE, MAX_BYTES_PER_TAG,
+            MAX_NAMES_PER_RECORD, MAX_TAGS_PER_RECORD,
+        },
+        signatures::{BYTES_PER_SIGNATURE, MAX_BYTES_PER_TEXT, MAX_TEXTS_PER_RECORD},
+    },
+    specs::{
+        address_appearance_index::{
+            AAIAppearanceTx, AAIChapter, AAIChapterId, AAIRecord, AAIRecordKey, AAIRecordValue,
+            AAISpec, AAIVolumeId,
+        },
+        events::{
+            EventsChapter, EventsChapterId, EventsRecord, EventsRecordKey, EventsRecordValue,
+            EventsSpec, EventsVolumeId,
+        },
+        nametags::{
+            Name, NameTagsChapter, NameTagsChapterId, NameTagsRecord, NameTagsRecordKey,
+            NameTagsRecordValue, NameTagsSpec, NameTagsVolumeId, Tag,
+        },
+        provenance::RecordProvenance,
+        signatures::{
+            SignaturesChapter, SignaturesChapterId, SignaturesRecord, SignaturesRecordKey,
+            SignaturesRecordValue, SignaturesSpec, SignaturesVolumeId, Text,
+        },
+        traits::{
+            ChapterIdMethods, ChapterMethods, DataSpec, ManifestMethods, RecordKeyMethods,
+            RecordMethods, RecordValueMethods, VolumeIdMethods,
+        },
+    },
+    utils::ipfs::cid_v0_string_from_bytes,
+};
+
+/// The longest list generated, for lists with a larger SSZ maximum.
+pub(crate) const MAX_GENERATED_LEN: usize = 8;
+
+/// Strategies for the Records of a spec.
+pub(crate) trait SpecGenerators: DataSpec + 'static {
+    /// Records with keys that belong to the Chapter.
+    fn record(chapter_id: &Self::AssociatedChapterId) -> BoxedStrategy<Self::AssociatedRecord>;
+    /// A Chapter holding the Records, which are in canonical order.
+    fn chapter_of(
+        volume_id: &Self::AssociatedVolumeId,
+        chapter_id: &Self::AssociatedChapterId,
+        records: Vec<Self::AssociatedRecord>,
+    ) -> Self::AssociatedChapter;
+    /// The number of Volumes to choose from. Less than `MAX_VOLUMES`.
+    fn volumes() -> u32 {
+        1_000
+    }
+}
+
+/// Bytes up to the given length.
+fn bytes_up_to(max: usize) -> impl Strategy<Value = Vec<u8>> {
+    vec(any::<u8>(), 0..=max)
+}
+
+/// A list length up to the SSZ maximum, or [MAX_GENERATED_LEN].
+fn len_up_to(max: usize) -> std::ops::RangeInclusive<usize> {
+    0..=max.min(MAX_GENERATED_LEN)
+}
+
+/// Key bytes that start with the bytes of the ChapterId.
+fn key_bytes(chapter_bytes: &[u8], key_len: usize) -> impl Strategy<Value = Vec<u8>> {
+    let prefix = chapter_bytes.to_vec();
+    vec(any::<u8>(), key_len - prefix.len()).prop_map(move |rest| {
+        let mut key = prefix.clone();
+        key.extend(rest);
+        key
+    })
+}
+
+/// Chapters of the spec, in any Volume and Chapter.
+pub(crate) fn chapter<T: SpecGenerators>() -> BoxedStrategy<T::AssociatedChapter> {
+    (0..T::volumes(), 0..T::NUM_CHAPTERS as u32)
+        .prop_flat_map(|(volume, chapter)| {
+            let volume_id = T::AssociatedVolumeId::nth_id(volume).unwrap();
+            let chapter_id = T::AssociatedChapterId::nth_id(chapter).unwrap();
+            let records = vec(T::record(&chapter_id), 0..=MAX_GENERATED_LEN);
+            (Just(volume_id), Just(chapter_id), records)
+        })
+        .prop_map(|(volume_id, chapter_id, records)| {
+            T::chapter_of(&volume_id, &chapter_id, canonical::<T>(records))
+        })
+        .boxed()
+}
+
+/// Sorts Records by their SSZ encoding, keeping the first Record for each key.
+fn canonical<T: DataSpec>(records: Vec<T::AssociatedRecord>) -> Vec<T::AssociatedRecord> {
+    let mut encoded: Vec<(Vec<u8>, T::AssociatedRecord)> = records
+        .into_iter()
+        .map(|r| (r.as_serialized_bytes().unwrap(), r))
+        .collect();
+    encoded.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut keys = HashSet::new();
+    encoded
+        .into_iter()
+        .filter(|(_, r)| keys.insert(r.key().as_serialized_bytes().unwrap()))
+        .map(|(_, r)| r)
+        .collect()
+}
+
+impl SpecGenerators for AAISpec {
+    fn record(chapter_id: &AAIChapterId) -> BoxedStrategy<AAIRecord> {
+        let txs = vec(
+            (any::<u32>(), any::<u32>())
+                .prop_map(|(block, index)| AAIAppearanceTx { block, index }),
+            1..=MAX_GENERATED_LEN,
+        );
+        (key_bytes(&chapter_id.val, DEFAULT_BYTES_PER_ADDRESS), txs)
+            .prop_map(|(key, txs)| AAIRecord {
+                key: AAIRecordKey {
+                    key: Vector::from_iter(key),
+                },
+                value: AAIRecordValue {
+                    value: List::from_iter(txs),
+                },
+            })
+            .boxed()
+    }
+    fn chapter_of(
+        volume_id: &AAIVolumeId,
+        chapter_id: &AAIChapterId,
+        records: Vec<AAIRecord>,
+    ) -> AAIChapter {
+        AAIChapter {
+            chapter_id: chapter_id.clone(),
+            volume_id: volume_id.clone(),
+            records: List::from_iter(records),
+        }
+    }
+}
+
+impl SpecGenerators for NameTagsSpec {
+    fn record(chapter_id: &NameTagsChapterId) -> BoxedStrategy<NameTagsRecord> {
+        let name = (
+            bytes_up_to(MAX_BYTES_PER_NAME),
+            bytes_up_to(MAX_BYTES_PER_SOURCE),
+        )
+            .prop_map(|(val, source)| Name {
+                val: List::from_iter(val),
+                source: List::from_iter(source),
+            });
+        let tag = (
+            bytes_up_to(MAX_BYTES_PER_TAG),
+            bytes_up_to(MAX_BYTES_PER_SOURCE),
+        )
+            .prop_map(|(val, source)| Tag {
+                val: List::from_iter(val),
+                source: List::from_iter(source),
+            });
+        (
+            key_bytes(&chapter_id.val, BYTES_PER_ADDRESS),
+            vec(name, len_up_to(MAX_NAMES_PER_RECORD)),
+            vec(tag, len_up_to(MAX_TAGS_PER_RECORD)),
+            provenance(),
+        )
+            .prop_map(|(key, names, tags, provenance)| NameTagsRecord {
+                key: NameTagsRecordKey::from_address(&hex::encode(key)).unwrap(),
+                value: NameTagsRecordValue {
+                    names: List::from_iter(names),
+                    tags: List::from_iter(tags),
+                    provenance,
+                },
+            })
+            .boxed()
+    }
+    fn chapter_of(
+        volume_id: &NameTagsVolumeId,
+        chapter_id: &NameTagsChapterId,
+        records: Vec<NameTagsRecord>,
+    ) -> NameTagsChapter {
+        NameTagsChapter::from_records(volume_id, chapter_id, records).unwrap()
+    }
+}
+
+/// Provenance, known or unknown.
+fn provenance() -> impl Strategy<Value = RecordProvenance> {
+    proptest::option::of(any::<u64>()).prop_map(RecordProvenance::from_timestamp)
+}
+
+/// Texts, as used by the signatures and events specs.
+fn texts() -> impl Strategy<Value = Vec<Text>> {
+    let text = bytes_up_to(MAX_BYTES_PER_TEXT).prop_map(|val| Text {
+        val: List::from_iter(val),
+    });
+    vec(text, len_up_to(MAX_TEXTS_PER_RECORD))
+}
+
+impl SpecGenerators for SignaturesSpec {
+    fn record(chapter_id: &SignaturesChapterId) -> BoxedStrategy<SignaturesRecord> {
+        (
+            key_bytes(&chapter_id.val, BYTES_PER_SIGNATURE),
+            texts(),
+            provenance(),
+        )
+            .prop_map(|(key, texts, provenance)| SignaturesRecord {
+                key: SignaturesRecordKey::from_signature(&hex::encode(key)).unwrap(),
+                value: SignaturesRecordValue {
+                    texts: List::from_iter(texts),
+                    provenance,
+                },
+            })
+            .boxed()
+    }
+    fn chapter_of(
+        volume_id: &SignaturesVolumeId,
+        chapter_id: &SignaturesChapterId,
+        records: Vec<SignaturesRecord>,
+    ) -> SignaturesChapter {
+        SignaturesChapter {
+            chapter_id: chapter_id.clone(),
+            volume_id: volume_id.clone(),
+            records: List::from_iter(records),
+        }
+    }
+}
+
+impl SpecGenerators for EventsSpec {
+    fn record(chapter_id: &EventsChapterId) -> BoxedStrategy<EventsRecord> {
+        (key_bytes(&chapter_id.val, BYTES_PER_TOPIC), texts())
+            .prop_map(|(key, texts)| EventsRecord {
+                key: EventsRecordKey::from_topic(&hex::encode(key)).unwrap(),
+                value: EventsRecordValue {
+                    texts: List::from_iter(texts),
+                },
+            })
+            .boxed()
+    }
+    fn chapter_of(
+        volume_id: &EventsVolumeId,
+        chapter_id: &EventsChapterId,
+        records: Vec<EventsRecord>,
+    ) -> EventsChapter {
+        EventsChapter {
+            chapter_id: chapter_id.clone(),
+            volume_id: volume_id.clone(),
+            records: List::from_iter(records),
+        }
+    }
+}
+
+/// Checks that a Chapter survives encoding, and that its filename and ids
+/// can be read back.
+pub(crate) fn check_chapter_round_trip<T: DataSpec>(
+    chapter: &T::AssociatedChapter,
+) -> Result<(), TestCaseError> {
+    let file = chapter.as_file_bytes().unwrap();
+    prop_assert_eq!(
+        &T::AssociatedChapter::from_file(file.clone()).unwrap(),
+        chapter
+    );
+    let metadata = T::AssociatedChapter::peek_metadata(file.as_slice())
+        .unwrap()
+        .unwrap();
+    prop_assert_eq!(metadata.record_count, chapter.records().len() as u64);
+
+    for record in chapter.records() {
+        let value = record.value();
+        let bytes = value.as_serialized_bytes().unwrap();
+        prop_assert_eq!(
+            &T::AssociatedRecordValue::from_serialized_bytes(&bytes).unwrap(),
+            value
+        );
+        prop_assert_eq!(
+            &T::record_key_to_chapter_id(record.key()).unwrap(),
+            chapter.chapter_id()
+        );
+    }
+
+    let chapter_name = chapter.chapter_id().interface_id();
+    let filename = chapter.filename();
+    let volume_name = chapter.volume_id().interface_id();
+    prop_assert!(filename.starts_with(&volume_name));
+    prop_assert!(filename.contains(&chapter_name));
+    prop_assert_eq!(
+        &T::AssociatedVolumeId::from_interface_id(&volume_name).unwrap(),
+        chapter.volume_id()
+    );
+    prop_assert_eq!(
+        &T::AssociatedChapterId::from_interface_id(&chapter_name).unwrap(),
+        chapter.chapter_id()
+    );
+    Ok(())
+}
+
+/// Checks that the CIDs set in a manifest are returned by `cids()` (also after
+/// a JSON round trip), in Volume then Chapter order.
+pub(crate) fn check_manifest_cids<T: DataSpec>(
+    chapters: &[T::AssociatedChapter],
+) -> Result<(), TestCaseError> {
+    let mut entries = vec![];
+    for chapter in chapters {
+        let cid = cid_v0_string_from_bytes(&chapter.as_file_bytes().unwrap()).unwrap();
+        entries.push((
+            cid,
+            chapter.volume_id().clone(),
+            chapter.chapter_id().clone(),
+        ));
+    }
+    let mut manifest = T::AssociatedManifest::default();
+    manifest.set_cids(&entries);
+    let json = serde_json::to_string(&manifest).unwrap();
+    let manifest: T::AssociatedManifest = serde_json::from_str(&json).unwrap();
+
+    let mut expected: Vec<(String, String, String)> = entries
+        .iter()
+        .map(|(cid, v, c)| (v.interface_id(), c.interface_id(), cid.clone()))
+        .collect();
+    expected.sort();
+    let mut found: Vec<(String, String, String)> = manifest
+        .cids()
+        .unwrap()
+        .into_iter()
+        .map(|m| {
+            (
+                m.volume_id.interface_id(),
+                m.chapter_id.interface_id(),
+                m.cid,
+            )
+        })
+        .collect();
+    prop_assert!(found
+        .windows(2)
+        .all(|w| (&w[0].0, &w[0].1) <= (&w[1].0, &w[1].1)));
+    // Entries for the same Volume and Chapter keep the order they were set in.
+    found.sort();
+    prop_assert_eq!(found, expected);
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn aai_chapters_round_trip(chapter in chapter::<AAISpec>()) {
+        check_chapter_round_trip::<AAISpec>(&chapter)?;
+    }
+
+    #[test]
+    fn nametags_chapters_round_trip(chapter in chapter::<NameTagsSpec>()) {
+        check_chapter_round_trip::<NameTagsSpec>(&chapter)?;
+    }
+
+    #[test]
+    fn signatures_chapters_round_trip(chapter in chapter::<SignaturesSpec>()) {
+        check_chapter_round_trip::<SignaturesSpec>(&chapter)?;
+    }
+
+    #[test]
+    fn events_chapters_round_trip(chapter in chapter::<EventsSpec>()) {
+        check_chapter_round_trip::<EventsSpec>(&chapter)?;
+    }
+
+    #[test]
+    fn manifest_cids_round_trip(
+        aai in vec(chapter::<AAISpec>(), 0..4),
+        nametags in vec(chapter::<NameTagsSpec>(), 0..4),
+        signatures in vec(chapter::<SignaturesSpec>(), 0..4),
+        events in vec(chapter::<EventsSpec>(), 0..4),
+    ) {
+        check_manifest_cids::<AAISpec>(&aai)?;
+        check_manifest_cids::<NameTagsSpec>(&nametags)?;
+        check_manifest_cids::<SignaturesSpec>(&signatures)?;
+        check_manifest_cids::<EventsSpec>(&events)?;
+    }
+}
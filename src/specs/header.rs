@@ -0,0 +1,478 @@
+//! A header at the start of Chapter files.
+//!
+//! The header names the spec, spec version and codec of the Chapter. A file from
+//! another database or spec version is then reported as such, rather than as data
+//! that could not be decoded. Chapter files written before the header was added
+//! have none, and are read as before.
+//!
+//! From header version 2, the header also holds [ChapterMetadata], so that
+//! audits can count Records without decoding the Chapter (see
+//! [ChapterMethods::peek_metadata][super::traits::ChapterMethods::peek_metadata]).
+//!
+//! ## Layout
+//! - Magic bytes "TODD" (4 bytes)
+//! - Header format version (1 byte)
+//! - Codec (1 byte)
+//! - Spec id length (1 byte), then the spec id. E.g., "nametags".
+//! - Spec version length (1 byte), then the spec version. E.g., "0.2.0".
+//! - Version 2 only:
+//!     - Record count (8 bytes, little endian)
+//!     - Encoded Chapter size in bytes (8 bytes, little endian)
+//!     - Smallest RecordKey length (2 bytes, little endian), then the key.
+//!     - Largest RecordKey length (2 bytes, little endian), then the key.
+//!
+//! The encoded Chapter follows.
+use std::{
+    fmt::{Debug, Display},
+    io::{ErrorKind, Read},
+};
+
+use anyhow::{bail, Result};
+
+use super::traits::{DataSpec, RecordKeyMethods, RecordMethods};
+
+/// Bytes at the start of every Chapter file with a header.
+pub const CHAPTER_MAGIC: [u8; 4] = *b"TODD";
+/// Version of the header layout without metadata.
+const HEADER_VERSION_V1: u8 = 1;
+/// Version of the header layout with [ChapterMetadata].
+const HEADER_VERSION: u8 = 2;
+
+/// How the Chapter following the header is encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Ssz,
+}
+
+impl Codec {
+    fn as_byte(&self) -> u8 {
+        match self {
+            Codec::Ssz => 0,
+        }
+    }
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Codec::Ssz),
+            _ => None,
+        }
+    }
+}
+
+/// The header of a Chapter file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChapterHeader {
+    /// E.g., "nametags".
+    pub spec_id: String,
+    /// E.g., "0.2.0".
+    pub spec_version: String,
+    pub codec: Codec,
+    /// Absent for headers written before version 2.
+    pub metadata: Option<ChapterMetadata>,
+}
+
+/// A summary of a Chapter, held in its header.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChapterMetadata {
+    /// The number of Records in the Chapter.
+    pub record_count: u64,
+    /// The size in bytes of the encoded Chapter that follows the header.
+    pub serialized_size: u64,
+    /// The smallest serialized RecordKey (by byte order). Empty if there are no Records.
+    pub min_key: Vec<u8>,
+    /// The largest serialized RecordKey (by byte order). Empty if there are no Records.
+    pub max_key: Vec<u8>,
+}
+
+impl ChapterMetadata {
+    /// The metadata of a Chapter with the Records, given its encoded size.
+    pub fn for_records<T: DataSpec>(
+        records: &[T::AssociatedRecord],
+        serialized_size: usize,
+    ) -> Result<Self> {
+        let mut keys = vec![];
+        for record in records {
+            keys.push(record.key().as_serialized_bytes()?);
+        }
+        Ok(ChapterMetadata {
+            record_count: keys.len() as u64,
+            serialized_size: serialized_size as u64,
+            min_key: keys.iter().min().cloned().unwrap_or_default(),
+            max_key: keys.iter().max().cloned().unwrap_or_default(),
+        })
+    }
+}
+
+impl ChapterHeader {
+    /// The header for Chapters written by this library for the spec.
+    pub fn for_spec<T: DataSpec>() -> Self {
+        ChapterHeader {
+            spec_id: T::SPEC_ID.to_string(),
+            spec_version: T::spec_version(),
+            codec: Codec::Ssz,
+            metadata: None,
+        }
+    }
+    /// Encodes the header. Headers without metadata use the version 1 layout.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = CHAPTER_MAGIC.to_vec();
+        bytes.push(match self.metadata {
+            Some(_) => HEADER_VERSION,
+            None => HEADER_VERSION_V1,
+        });
+        bytes.push(self.codec.as_byte());
+        for field in [&self.spec_id, &self.spec_version] {
+            let Ok(len) = u8::try_from(field.len()) else {
+                bail!("Chapter header field {:?} is longer than 255 bytes.", field)
+            };
+            bytes.push(len);
+            bytes.extend_from_slice(field.as_bytes());
+        }
+        if let Some(metadata) = &self.metadata {
+            bytes.extend(metadata.record_count.to_le_bytes());
+            bytes.extend(metadata.serialized_size.to_le_bytes());
+            for key in [&metadata.min_key, &metadata.max_key] {
+                let Ok(len) = u16::try_from(key.len()) else {
+                    bail!(
+                        "RecordKey of {} bytes is too long for a Chapter header.",
+                        key.len()
+                    )
+                };
+                bytes.extend(len.to_le_bytes());
+                bytes.extend_from_slice(key);
+            }
+        }
+        Ok(bytes)
+    }
+    /// Reads the header at the start of a Chapter file.
+    ///
+    /// Returns the header and the number of bytes it occupies, or `None` if the
+    /// file has no header (a legacy file).
+    ///
+    /// ## Errors
+    /// [ChapterFileError] if the header is present but cannot be read.
+    pub fn read(data: &[u8]) -> Result<Option<(Self, usize)>> {
+        let mut remaining = data;
+        let header = Self::read_from(&mut remaining)?;
+        Ok(header.map(|header| (header, data.len() - remaining.len())))
+    }
+    /// Reads the header from the start of a Chapter file, without reading
+    /// further.
+    ///
+    /// Returns `None` if the file has no header (a legacy file).
+    ///
+    /// ## Errors
+    /// [ChapterFileError] if the header is present but cannot be read, or the
+    /// reader fails.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Option<Self>> {
+        let mut magic = vec![];
+        reader
+            .by_ref()
+            .take(CHAPTER_MAGIC.len() as u64)
+            .read_to_end(&mut magic)?;
+        if magic != CHAPTER_MAGIC {
+            return Ok(None);
+        }
+        let version = read_array::<1, R>(reader)?[0];
+        if version != HEADER_VERSION && version != HEADER_VERSION_V1 {
+            return Err(ChapterFileError::UnsupportedHeader(version).into());
+        }
+        let codec_byte = read_array::<1, R>(reader)?[0];
+        let Some(codec) = Codec::from_byte(codec_byte) else {
+            return Err(ChapterFileError::UnsupportedCodec(codec_byte).into());
+        };
+        let mut fields = vec![];
+        for _ in 0..2 {
+            let len = read_array::<1, R>(reader)?[0] as usize;
+            let field = read_vec(reader, len)?;
+            fields.push(String::from_utf8_lossy(&field).to_string());
+        }
+        let spec_version = fields.pop().unwrap_or_default();
+        let spec_id = fields.pop().unwrap_or_default();
+        let metadata = match version {
+            HEADER_VERSION => {
+                let record_count = u64::from_le_bytes(read_array(reader)?);
+                let serialized_size = u64::from_le_bytes(read_array(reader)?);
+                let min_len = u16::from_le_bytes(read_array(reader)?) as usize;
+                let min_key = read_vec(reader, min_len)?;
+                let max_len = u16::from_le_bytes(read_array(reader)?) as usize;
+                let max_key = read_vec(reader, max_len)?;
+                Some(ChapterMetadata {
+                    record_count,
+                    serialized_size,
+                    min_key,
+                    max_key,
+                })
+            }
+            _ => None,
+        };
+        Ok(Some(ChapterHeader {
+            spec_id,
+            spec_version,
+            codec,
+            metadata,
+        }))
+    }
+}
+
+/// Reads exactly N bytes of a header.
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes).map_err(header_read_error)?;
+    Ok(bytes)
+}
+
+/// Reads exactly `len` bytes of a header.
+fn read_vec<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes).map_err(header_read_error)?;
+    Ok(bytes)
+}
+
+/// A header that ends early is corrupt, other read errors are passed on.
+fn header_read_error(e: std::io::Error) -> anyhow::Error {
+    match e.kind() {
+        ErrorKind::UnexpectedEof => {
+            ChapterFileError::Corrupt("truncated header".to_string()).into()
+        }
+        _ => e.into(),
+    }
+}
+
+/// Reasons that a Chapter file cannot be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChapterFileError {
+    /// The file is a Chapter of a different database.
+    WrongDatabase { expected: String, found: String },
+    /// The file is a Chapter of a different version of the spec.
+    WrongVersion { expected: String, found: String },
+    /// The header layout version is not known to this library.
+    UnsupportedHeader(u8),
+    /// The codec is not known to this library.
+    UnsupportedCodec(u8),
+    /// The file has a valid header but the Chapter could not be decoded.
+    Corrupt(String),
+}
+
+impl Display for ChapterFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChapterFileError::WrongDatabase { expected, found } => write!(
+                f,
+                "Chapter is from the {} database, expected {}",
+                found, expected
+            ),
+            ChapterFileError::WrongVersion { expected, found } => write!(
+                f,
+                "Chapter has spec version {}, this library reads version {}",
+                found, expected
+            ),
+            ChapterFileError::UnsupportedHeader(v) => {
+                write!(f, "Chapter header version {} is not supported", v)
+            }
+            ChapterFileError::UnsupportedCodec(c) => {
+                write!(f, "Chapter codec {} is not supported", c)
+            }
+            ChapterFileError::Corrupt(reason) => write!(f, "Chapter data is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ChapterFileError {}
+
+/// Returns the encoded Chapter that follows the header of a Chapter file.
+///
+/// Returns `None` for a legacy file without a header.
+///
+/// ## Errors
+/// [ChapterFileError] if the header is for a different spec or spec version,
+/// or cannot be read.
+pub(crate) fn chapter_payload<T: DataSpec>(data: &[u8]) -> Result<Option<&[u8]>> {
+    match versioned_chapter_payload::<T>(data)? {
+        Some((version, _)) if version != T::spec_version() => Err(wrong_version::<T>(version)),
+        Some((_, payload)) => Ok(Some(payload)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the spec version in the header of a Chapter file and the encoded
+/// Chapter that follows, for specs that migrate earlier versions on read (see
+/// [DataSpec::legacy_spec_versions]).
+///
+/// Returns `None` for a legacy file without a header.
+///
+/// ## Errors
+/// [ChapterFileError] if the header is for a different spec, a version that is
+/// neither current nor legacy, or cannot be read.
+pub(crate) fn versioned_chapter_payload<T: DataSpec>(
+    data: &[u8],
+) -> Result<Option<(String, &[u8])>> {
+    let Some((header, len)) = ChapterHeader::read(data)? else {
+        return Ok(None);
+    };
+    check_spec::<T>(&header)?;
+    let payload = &data[len..];
+    if let Some(metadata) = header.metadata {
+        if metadata.serialized_size != payload.len() as u64 {
+            return Err(ChapterFileError::Corrupt(format!(
+                "header records {} bytes of Chapter data, found {}",
+                metadata.serialized_size,
+                payload.len()
+            ))
+            .into());
+        }
+    }
+    Ok(Some((header.spec_version, payload)))
+}
+
+/// Reads the metadata from the header of a Chapter file, for
+/// [ChapterMethods::peek_metadata].
+pub(crate) fn peek_metadata<T: DataSpec, R: Read>(
+    mut reader: R,
+) -> Result<Option<ChapterMetadata>> {
+    let Some(header) = ChapterHeader::read_from(&mut reader)? else {
+        return Ok(None);
+    };
+    check_spec::<T>(&header)?;
+    Ok(header.metadata)
+}
+
+/// Checks that a header is for the spec and a spec version that can be read
+/// (current or legacy).
+fn check_spec<T: DataSpec>(header: &ChapterHeader) -> Result<()> {
+    if header.spec_id != T::SPEC_ID {
+        return Err(ChapterFileError::WrongDatabase {
+            expected: T::SPEC_ID.to_string(),
+            found: header.spec_id.clone(),
+        }
+        .into());
+    }
+    if header.spec_version != T::spec_version()
+        && !T::legacy_spec_versions().contains(&header.spec_version)
+    {
+        return Err(wrong_version::<T>(header.spec_version.clone()));
+    }
+    Ok(())
+}
+
+/// The error for a Chapter file with a header for another spec version.
+pub(crate) fn wrong_version<T: DataSpec>(found: String) -> anyhow::Error {
+    ChapterFileError::WrongVersion {
+        expected: T::spec_version(),
+        found,
+    }
+    .into()
+}
+
+/// The error for a Chapter with a valid header that cannot be decoded.
+pub(crate) fn corrupt(e: impl Debug) -> anyhow::Error {
+    ChapterFileError::Corrupt(format!("{:?}", e)).into()
+}
+
+#[test]
+fn headers_distinguish_chapter_file_errors() {
+    use crate::specs::{
+        nametags::{NameTagsChapter, NameTagsChapterId, NameTagsSpec, NameTagsVolumeId},
+        signatures::SignaturesChapter,
+        traits::{ChapterIdMethods, ChapterMethods},
+    };
+
+    let chapter = NameTagsChapter::new_empty(
+        &NameTagsVolumeId { first_address: 0 },
+        &NameTagsChapterId::nth_id(1).unwrap(),
+    );
+    let file = chapter.as_file_bytes().unwrap();
+    assert!(file.starts_with(&CHAPTER_MAGIC));
+    assert_eq!(NameTagsChapter::from_file(file.clone()).unwrap(), chapter);
+    // Legacy files without a header.
+    let legacy = chapter.as_serialized_bytes().unwrap();
+    assert_eq!(NameTagsChapter::from_file(legacy).unwrap(), chapter);
+
+    let error = |result: Result<SignaturesChapter>| {
+        result.unwrap_err().downcast::<ChapterFileError>().unwrap()
+    };
+    assert!(matches!(
+        error(SignaturesChapter::from_file(file.clone())),
+        ChapterFileError::WrongDatabase { found, .. } if found == "nametags"
+    ));
+
+    let mut header = ChapterHeader::for_spec::<NameTagsSpec>();
+    header.spec_version = "9.9.9".to_string();
+    let mut other_version = header.to_bytes().unwrap();
+    other_version.extend(chapter.as_serialized_bytes().unwrap());
+    let err = NameTagsChapter::from_file(other_version).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ChapterFileError>(),
+        Some(ChapterFileError::WrongVersion { .. })
+    ));
+
+    let truncated = file[..file.len() - 4].to_vec();
+    let err = NameTagsChapter::from_file(truncated).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ChapterFileError>(),
+        Some(ChapterFileError::Corrupt(_))
+    ));
+}
+
+#[test]
+fn metadata_read_without_decoding() {
+    use crate::specs::{
+        nametags::{NameTagsChapter, NameTagsSpec},
+        signatures::SignaturesChapter,
+        traits::{ChapterMethods, RecordKeyMethods, RecordMethods},
+    };
+
+    let legacy = std::fs::read(
+        "./data/samples/todd_nametags/nametags/addresses_0xe3/\
+        nametags_from_000_000_000_addresses_0xe3.ssz",
+    )
+    .unwrap();
+    assert_eq!(
+        NameTagsChapter::peek_metadata(legacy.as_slice()).unwrap(),
+        None
+    );
+    let chapter = NameTagsChapter::from_file(legacy).unwrap();
+    let file = chapter.as_file_bytes().unwrap();
+    assert_eq!(NameTagsChapter::from_file(file.clone()).unwrap(), chapter);
+
+    // Only the header is read: the Chapter data may be absent.
+    let (header, header_len) = ChapterHeader::read(&file).unwrap().unwrap();
+    let metadata = NameTagsChapter::peek_metadata(&file[..header_len])
+        .unwrap()
+        .unwrap();
+    assert_eq!(header.metadata, Some(metadata.clone()));
+    let keys: Vec<Vec<u8>> = chapter
+        .records()
+        .iter()
+        .map(|r| r.key().as_serialized_bytes().unwrap())
+        .collect();
+    assert!(!keys.is_empty());
+    assert_eq!(metadata.record_count, keys.len() as u64);
+    assert_eq!(metadata.serialized_size as usize, file.len() - header_len);
+    assert_eq!(Some(&metadata.min_key), keys.iter().min());
+    assert_eq!(Some(&metadata.max_key), keys.iter().max());
+    assert!(matches!(
+        SignaturesChapter::peek_metadata(file.as_slice())
+            .unwrap_err()
+            .downcast::<ChapterFileError>()
+            .unwrap(),
+        ChapterFileError::WrongDatabase { .. }
+    ));
+
+    // Version 1 headers are still read, without metadata.
+    let mut v1 = ChapterHeader::for_spec::<NameTagsSpec>()
+        .to_bytes()
+        .unwrap();
+    assert_eq!(v1[CHAPTER_MAGIC.len()], HEADER_VERSION_V1);
+    v1.extend(chapter.as_serialized_bytes().unwrap());
+    assert_eq!(NameTagsChapter::peek_metadata(v1.as_slice()).unwrap(), None);
+    assert_eq!(NameTagsChapter::from_file(v1).unwrap(), chapter);
+
+    // A size that does not match the Chapter data is reported.
+    let mut extended = file.clone();
+    extended.push(0);
+    let err = NameTagsChapter::from_file(extended).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ChapterFileError>(),
+        Some(ChapterFileError::Corrupt(_))
+    ));
+}
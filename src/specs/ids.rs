@@ -0,0 +1,300 @@
+//! Conversions between VolumeIds and ChapterIds and their interface ids.
+//!
+//! Interface ids appear in manifests and filenames, which may come from
+//! elsewhere, so they are parsed strictly. Each malformed id is rejected with
+//! an error that names the expected form, rather than a bare parse error.
+//!
+//! Every spec uses the same grammar, with its own prefixes:
+//! - VolumeIds ([VolumeIdForm]): `{prefix}{triplet}`, where the triplet is the
+//!   first item of the Volume as `000_000_000`. E.g., "volume_014_400_000".
+//! - ChapterIds ([ChapterIdForm]): `{prefix}{hex}`, with a fixed number of
+//!   lowercase hex characters. E.g., "addresses_0x4e".
+//!
+//! An interface id is accepted only if formatting the parsed id gives the same
+//! string, so there is one interface id for each id.
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::utils::string::{num_as_triplet, triplet_as_num};
+
+/// The grammar of the interface ids of a spec's VolumeIds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct VolumeIdForm {
+    /// E.g., "volume_".
+    pub prefix: &'static str,
+    /// Items (e.g., blocks) in each Volume.
+    pub per_volume: u32,
+    /// The number of Volumes that interface ids can represent.
+    pub max_volumes: u32,
+}
+
+impl VolumeIdForm {
+    /// The interface id of the Volume starting at the item.
+    pub(crate) fn format(&self, start: u32) -> String {
+        format!("{}{}", self.prefix, num_as_triplet(start))
+    }
+    /// Reads the first item of a Volume from an interface id.
+    ///
+    /// ## Errors
+    /// If the prefix is absent, the number is malformed, or the Volume does not
+    /// start at a multiple of `per_volume`.
+    pub(crate) fn parse(&self, interface_id: &str) -> Result<u32> {
+        let Some(triplet) = interface_id.strip_prefix(self.prefix) else {
+            bail!(
+                "VolumeId \"{}\" does not start with \"{}\".",
+                interface_id,
+                self.prefix
+            )
+        };
+        let start = triplet_as_num(triplet)
+            .with_context(|| format!("VolumeId \"{}\" is malformed", interface_id))?;
+        if start % self.per_volume != 0 {
+            bail!(
+                "VolumeId \"{}\" does not start at a multiple of {}.",
+                interface_id,
+                self.per_volume
+            )
+        }
+        Ok(start)
+    }
+    /// The first item (e.g., block) of the n-th Volume.
+    ///
+    /// ## Errors
+    /// If `n` is not less than `max_volumes`.
+    pub(crate) fn nth_start(&self, n: u32) -> Result<u32> {
+        if n >= self.max_volumes {
+            bail!(
+                "Volume {} is beyond the last Volume ({}) that interface ids can represent.",
+                n,
+                self.max_volumes - 1
+            )
+        }
+        n.checked_mul(self.per_volume)
+            .ok_or_else(|| anyhow!("Volume {} starts beyond {}.", n, u32::MAX))
+    }
+}
+
+/// The grammar of the interface ids of a spec's ChapterIds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ChapterIdForm {
+    /// E.g., "addresses_0x".
+    pub prefix: &'static str,
+    /// Bytes in each ChapterId.
+    pub num_bytes: usize,
+}
+
+impl ChapterIdForm {
+    /// The interface id of the ChapterId bytes.
+    pub(crate) fn format(&self, bytes: &[u8]) -> String {
+        format!("{}{}", self.prefix, hex::encode(bytes))
+    }
+    /// Reads the bytes of a ChapterId from an interface id.
+    ///
+    /// ## Errors
+    /// If the prefix is absent or the id does not have `num_bytes` of lowercase hex.
+    pub(crate) fn parse(&self, interface_id: &str) -> Result<Vec<u8>> {
+        let Some(hex_chars) = interface_id.strip_prefix(self.prefix) else {
+            bail!(
+                "ChapterId \"{}\" does not start with \"{}\".",
+                interface_id,
+                self.prefix
+            )
+        };
+        let well_formed = hex_chars.len() == 2 * self.num_bytes
+            && hex_chars
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        if !well_formed {
+            bail!(
+                "ChapterId \"{}\" should end with {} lowercase hex characters.",
+                interface_id,
+                2 * self.num_bytes
+            )
+        }
+        Ok(hex::decode(hex_chars)?)
+    }
+}
+
+/// The ChapterId bytes for the n-th Chapter.
+///
+/// ## Errors
+/// If `n` is not less than `num_chapters`.
+pub(crate) fn nth_chapter_bytes(n: u32, num_chapters: usize) -> Result<Vec<u8>> {
+    if n as usize >= num_chapters {
+        bail!(
+            "Chapter {} is beyond the last Chapter ({}).",
+            n,
+            num_chapters - 1
+        )
+    }
+    Ok(vec![n as u8])
+}
+
+/// Checks that ids survive conversion to and from interface ids and positions.
+#[cfg(test)]
+fn round_trips<T: crate::specs::traits::DataSpec>(max_volumes: u32) {
+    use crate::specs::traits::{ChapterIdMethods, VolumeIdMethods};
+
+    // Deterministic sample of positions, including both ends.
+    let mut positions = vec![0, 1, max_volumes - 1];
+    let mut n: u32 = 7;
+    for _ in 0..200 {
+        n = n.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        positions.push(n % max_volumes);
+    }
+    for n in positions {
+        let volume_id = T::AssociatedVolumeId::nth_id(n).unwrap();
+        let interface_id = volume_id.interface_id();
+        let parsed = T::AssociatedVolumeId::from_interface_id(&interface_id).unwrap();
+        assert_eq!(parsed, volume_id);
+        assert_eq!(parsed.is_nth().unwrap(), n);
+    }
+    for n in [max_volumes, u32::MAX / 2, u32::MAX] {
+        assert!(T::AssociatedVolumeId::nth_id(n).is_err());
+    }
+    for n in 0..T::NUM_CHAPTERS as u32 {
+        let chapter_id = T::AssociatedChapterId::nth_id(n).unwrap();
+        let interface_id = chapter_id.interface_id();
+        let parsed = T::AssociatedChapterId::from_interface_id(&interface_id).unwrap();
+        assert_eq!(parsed, chapter_id);
+    }
+    assert!(T::AssociatedChapterId::nth_id(T::NUM_CHAPTERS as u32).is_err());
+}
+
+#[test]
+fn ids_round_trip_for_all_specs() {
+    use crate::{
+        parameters,
+        specs::{
+            address_appearance_index::AAISpec, events::EventsSpec, nametags::NameTagsSpec,
+            signatures::SignaturesSpec,
+        },
+    };
+    round_trips::<AAISpec>(parameters::address_appearance_index::MAX_VOLUMES);
+    round_trips::<NameTagsSpec>(parameters::nametags::MAX_VOLUMES);
+    round_trips::<SignaturesSpec>(parameters::signatures::MAX_VOLUMES);
+    round_trips::<EventsSpec>(parameters::events::MAX_VOLUMES);
+}
+
+#[test]
+fn malformed_ids_rejected() {
+    use crate::specs::{
+        address_appearance_index::{AAIChapterId, AAIVolumeId},
+        traits::{ChapterIdMethods, VolumeIdMethods},
+    };
+    let volume_error = |id| AAIVolumeId::from_interface_id(id).unwrap_err().to_string();
+    assert!(volume_error("volume_abc").contains("malformed"));
+    assert!(volume_error("volume_14400000").contains("malformed"));
+    assert!(volume_error("volume_014_400_001").contains("multiple of 100000"));
+    assert!(volume_error("chapter_014_400_000").contains("does not start with"));
+    let chapter_error = |id| AAIChapterId::from_interface_id(id).unwrap_err().to_string();
+    assert!(chapter_error("chapter_0x4").contains("2 lowercase hex"));
+    assert!(chapter_error("chapter_0xzz").contains("2 lowercase hex"));
+    assert!(chapter_error("chapter_0x4E").contains("2 lowercase hex"));
+    assert!(chapter_error("volume_0x4e").contains("does not start with"));
+}
+
+/// Checks that every interface id accepted for the spec is the canonical form
+/// of the id, among valid ids changed at random and random strings.
+#[cfg(test)]
+fn fuzz_canonical<T: crate::specs::traits::DataSpec>(seed: u64) {
+    use crate::specs::traits::{ChapterIdMethods, VolumeIdMethods};
+
+    // Deterministic pseudo-random numbers.
+    let mut state = seed;
+    let mut below = |n: usize| {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (state >> 33) as usize % n
+    };
+    let alphabet: Vec<char> = "0123456789abcdefABCDEFx_ -".chars().collect();
+    let valid = [
+        T::AssociatedVolumeId::nth_id(0).unwrap().interface_id(),
+        T::AssociatedVolumeId::nth_id(123).unwrap().interface_id(),
+        T::AssociatedChapterId::nth_id(0).unwrap().interface_id(),
+        T::AssociatedChapterId::nth_id(T::NUM_CHAPTERS as u32 - 1)
+            .unwrap()
+            .interface_id(),
+    ];
+    let (mut accepted, mut rejected) = (0, 0);
+    for _ in 0..5_000 {
+        let mut chars: Vec<char> = valid[below(valid.len())].chars().collect();
+        for _ in 0..=below(3) {
+            let position = below(chars.len() + 1);
+            let random = alphabet[below(alphabet.len())];
+            match below(4) {
+                0 if position < chars.len() => chars[position] = random,
+                1 => chars.insert(position, random),
+                2 if position < chars.len() => {
+                    chars.remove(position);
+                }
+                _ => chars.truncate(position),
+            }
+        }
+        let candidate: String = chars.into_iter().collect();
+        let as_volume = T::AssociatedVolumeId::from_interface_id(&candidate);
+        let as_chapter = T::AssociatedChapterId::from_interface_id(&candidate);
+        if let Ok(volume_id) = &as_volume {
+            assert_eq!(volume_id.interface_id(), candidate);
+            assert!(volume_id.is_nth().is_ok());
+        }
+        if let Ok(chapter_id) = &as_chapter {
+            assert_eq!(chapter_id.interface_id(), candidate);
+        }
+        match as_volume.is_ok() || as_chapter.is_ok() {
+            true => accepted += 1,
+            false => rejected += 1,
+        }
+    }
+    // Some changes leave a valid id (e.g., a different digit).
+    assert!(accepted > 0 && rejected > 0);
+}
+
+#[test]
+fn only_canonical_ids_accepted() {
+    use crate::specs::{
+        address_appearance_index::AAISpec, events::EventsSpec, nametags::NameTagsSpec,
+        signatures::SignaturesSpec,
+    };
+    fuzz_canonical::<AAISpec>(1);
+    fuzz_canonical::<NameTagsSpec>(2);
+    fuzz_canonical::<SignaturesSpec>(3);
+    fuzz_canonical::<EventsSpec>(4);
+}
+
+#[test]
+fn forms_format_and_parse() {
+    let volumes = VolumeIdForm {
+        prefix: "volume_",
+        per_volume: 100_000,
+        max_volumes: 10_000,
+    };
+    assert_eq!(volumes.format(14_400_000), "volume_014_400_000");
+    assert_eq!(volumes.parse("volume_014_400_000").unwrap(), 14_400_000);
+    assert_eq!(volumes.nth_start(144).unwrap(), 14_400_000);
+    assert!(volumes.nth_start(10_000).is_err());
+    for malformed in [
+        "volume_014_400_000 ",
+        "volume_14_400_000",
+        "volume_014_400_0000",
+        "volume_014400_000",
+        "volume_+14_400_000",
+        "Volume_014_400_000",
+    ] {
+        assert!(volumes.parse(malformed).is_err(), "{}", malformed);
+    }
+    let chapters = ChapterIdForm {
+        prefix: "chapter_0x",
+        num_bytes: 2,
+    };
+    assert_eq!(chapters.format(&[0x4e, 0x0a]), "chapter_0x4e0a");
+    assert_eq!(chapters.parse("chapter_0x4e0a").unwrap(), vec![0x4e, 0x0a]);
+    for malformed in [
+        "chapter_0x4e",
+        "chapter_0x4e0a0",
+        "chapter_4e0a",
+        "chapter_0x+e0a",
+    ] {
+        assert!(chapters.parse(malformed).is_err(), "{}", malformed);
+    }
+}
@@ -1,7 +1,15 @@
 #![doc = include_str!("../../GETTING_STARTED.md")]
 pub mod address_appearance_index;
+pub mod events;
+pub mod fingerprint;
+#[cfg(test)]
+pub(crate) mod generators;
+pub mod header;
+pub(crate) mod ids;
 #[allow(dead_code)]
 pub(crate) mod my_database;
 pub mod nametags;
+pub mod provenance;
 pub mod signatures;
 pub mod traits;
+pub mod value_schema;
@@ -1,6 +1,21 @@
+//! The nametags database: names and tags for addresses.
+//!
+//! ## Merging Volumes
+//!
+//! Raw data is append-only, so an address that is edited in a later batch has
+//! Records in more than one Volume. [merge_volumes] combines them, in Volume
+//! order (oldest first), source by source:
+//! - A source with names or tags in a later Volume replaces all the names and
+//!   tags from that source in earlier Volumes, as the raw file for an address
+//!   and source holds all of its names and tags.
+//! - Names and tags from other sources are kept.
+//!
+//! Names and tags without a source are treated as one source. Within a Volume,
+//! duplicates are removed.
 use std::str::from_utf8;
 
 use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 use ssz_rs::prelude::*;
 
 use crate::manifest::nametags::NameTagsManifest;
@@ -10,13 +25,22 @@ use crate::{
     extraction::nametags::NameTagsExtractor,
     parameters::nametags::{
         BYTES_FOR_ADDRESS_CHARS, BYTES_PER_ADDRESS, ENTRIES_PER_VOLUME, MAX_BYTES_PER_NAME,
-        MAX_BYTES_PER_TAG, MAX_NAMES_PER_RECORD, MAX_TAGS_PER_RECORD,
+        MAX_BYTES_PER_SOURCE, MAX_BYTES_PER_TAG, MAX_NAMES_PER_RECORD, MAX_TAGS_PER_RECORD,
+        MAX_VOLUMES,
     },
     samples::nametags::NameTagsSampleObtainer,
-    utils,
+    specs::{
+        header::{corrupt, versioned_chapter_payload},
+        ids::{nth_chapter_bytes, ChapterIdForm, VolumeIdForm},
+        provenance::RecordProvenance,
+    },
 };
 
-use super::traits::*;
+use super::{
+    fingerprint::{container, field, SszDescriptor},
+    traits::*,
+    value_schema::ValueSchema,
+};
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct NameTagsSpec {}
@@ -24,6 +48,9 @@ pub struct NameTagsSpec {}
 // Uncomment the line below to start adding a new database to this library.
 impl DataSpec for NameTagsSpec {
     const NUM_CHAPTERS: usize = 256;
+    const SPEC_ID: &'static str = "nametags";
+    const ADDRESS_KEYED: bool = true;
+    const SUPPORTS_ERRATA: bool = true;
 
     type AssociatedChapter = NameTagsChapter;
 
@@ -44,21 +71,50 @@ impl DataSpec for NameTagsSpec {
     type AssociatedManifest = NameTagsManifest;
 
     fn spec_matches_input(data_kind: &DataKind) -> bool {
-        matches!(data_kind, DataKind::NameTags)
+        // Exhaustive so that new DataKind variants must be considered here.
+        match data_kind {
+            DataKind::NameTags => true,
+            DataKind::AddressAppearanceIndex(_) | DataKind::Signatures | DataKind::Events => false,
+        }
     }
 
     fn spec_version() -> String {
-        String::from("0.1.0")
+        // 0.2.0: Names and Tags have a source (namespace).
+        // 0.3.0: Values have a provenance (when they were added).
+        String::from("0.3.0")
+    }
+
+    fn legacy_spec_versions() -> Vec<String> {
+        vec![String::from("0.2.0")]
     }
 
     fn spec_schemas_resource() -> String {
         String::from("https://github.com/perama-v/TODD/blob/main/example_specs/nametag.md")
     }
+    fn value_schema() -> ValueSchema {
+        ValueSchema::object(vec![
+            (
+                "names",
+                "Names of the address.",
+                ValueSchema::list_of(ValueSchema::String),
+            ),
+            (
+                "tags",
+                "Tags of the address.",
+                ValueSchema::list_of(ValueSchema::String),
+            ),
+            (
+                "added",
+                "When the names and tags were added (seconds since the Unix epoch), if known.",
+                ValueSchema::optional(ValueSchema::Integer),
+            ),
+        ])
+    }
 
     fn record_key_to_chapter_id(
         record_key: &Self::AssociatedRecordKey,
     ) -> Result<Self::AssociatedChapterId> {
-        let bytes = record_key.key[0..2].to_vec();
+        let bytes = record_key.key[0..BYTES_FOR_ADDRESS_CHARS].to_vec();
         Ok(NameTagsChapterId {
             val: Vector::from_iter(bytes),
         })
@@ -72,6 +128,8 @@ impl DataSpec for NameTagsSpec {
     }
 }
 
+/// Records are in canonical order: ascending by their SSZ encoding. The key is
+/// the leading fixed size field, so this orders by key, then by value.
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
 pub struct NameTagsChapter {
     pub chapter_id: NameTagsChapterId,
@@ -100,14 +158,26 @@ impl ChapterMethods<NameTagsSpec> for NameTagsChapter {
     where
         Self: Sized,
     {
-        // Files are ssz encoded.
+        if let Some((version, payload)) = versioned_chapter_payload::<NameTagsSpec>(&data)? {
+            return match version.as_str() {
+                "0.2.0" => Self::from_v0_2_0_bytes(payload).map_err(corrupt),
+                _ => deserialize::<Self>(payload).map_err(corrupt),
+            };
+        }
+        // Files without a header are ssz encoded.
         let chapter = match deserialize::<Self>(&data) {
             Ok(c) => c,
-            Err(e) => bail!(
-                "Could not decode the SSZ data. Check that the library
+            Err(e) => {
+                match Self::from_v0_2_0_bytes(&data).or_else(|_| Self::from_v0_1_0_bytes(&data)) {
+                    // Files from earlier spec versions are migrated on read.
+                    Ok(c) => c,
+                    Err(_) => bail!(
+                        "Could not decode the SSZ data. Check that the library
             spec version matches the version in the manifest.  {:?}",
-                e
-            ),
+                        e
+                    ),
+                }
+            }
         };
         Ok(chapter)
     }
@@ -127,8 +197,95 @@ impl ChapterMethods<NameTagsSpec> for NameTagsChapter {
             records: List::default(),
         }
     }
+
+    fn from_records(
+        volume_id: &NameTagsVolumeId,
+        chapter_id: &NameTagsChapterId,
+        records: Vec<NameTagsRecord>,
+    ) -> Result<Self> {
+        if records.len() > MAX_RECORDS_PER_CHAPTER {
+            bail!(
+                "{} Records exceeds the maximum of {} per Chapter.",
+                records.len(),
+                MAX_RECORDS_PER_CHAPTER
+            )
+        }
+        Ok(NameTagsChapter {
+            chapter_id: chapter_id.clone(),
+            volume_id: volume_id.clone(),
+            records: List::from_iter(records),
+        })
+    }
 }
 
+impl NameTagsChapter {
+    /// Decodes a Chapter encoded with spec version 0.1.0, where names and tags
+    /// had no source. The migrated names and tags have an empty source.
+    pub fn from_v0_1_0_bytes(data: &[u8]) -> Result<Self> {
+        let legacy = match deserialize::<NameTagsChapterV0_1_0>(data) {
+            Ok(c) => c,
+            Err(e) => bail!(
+                "Could not decode the SSZ data as spec version 0.1.0. {:?}",
+                e
+            ),
+        };
+        let mut records = vec![];
+        for r in legacy.records.iter() {
+            let names = r.value.names.iter().map(|n| Name {
+                val: n.val.clone(),
+                source: List::default(),
+            });
+            let tags = r.value.tags.iter().map(|t| Tag {
+                val: t.val.clone(),
+                source: List::default(),
+            });
+            records.push(NameTagsRecord {
+                key: r.key.clone(),
+                value: NameTagsRecordValue {
+                    names: List::from_iter(names),
+                    tags: List::from_iter(tags),
+                    provenance: RecordProvenance::default(),
+                },
+            })
+        }
+        Ok(NameTagsChapter {
+            chapter_id: legacy.chapter_id,
+            volume_id: legacy.volume_id,
+            records: List::from_iter(records),
+        })
+    }
+    /// Decodes a Chapter encoded with spec version 0.2.0, where values had no
+    /// provenance. The migrated values have an empty provenance.
+    pub fn from_v0_2_0_bytes(data: &[u8]) -> Result<Self> {
+        let legacy = match deserialize::<NameTagsChapterV0_2_0>(data) {
+            Ok(c) => c,
+            Err(e) => bail!(
+                "Could not decode the SSZ data as spec version 0.2.0. {:?}",
+                e
+            ),
+        };
+        let records = legacy.records.iter().map(|r| NameTagsRecord {
+            key: r.key.clone(),
+            value: NameTagsRecordValue {
+                names: r.value.names.clone(),
+                tags: r.value.tags.clone(),
+                provenance: RecordProvenance::default(),
+            },
+        });
+        Ok(NameTagsChapter {
+            chapter_id: legacy.chapter_id,
+            volume_id: legacy.volume_id,
+            records: List::from_iter(records),
+        })
+    }
+}
+
+/// E.g., "addresses_0xe3".
+const CHAPTER_ID_FORM: ChapterIdForm = ChapterIdForm {
+    prefix: "addresses_0x",
+    num_bytes: BYTES_FOR_ADDRESS_CHARS,
+};
+
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
 pub struct NameTagsChapterId {
     pub val: Vector<u8, BYTES_FOR_ADDRESS_CHARS>,
@@ -136,22 +293,18 @@ pub struct NameTagsChapterId {
 
 impl ChapterIdMethods<NameTagsSpec> for NameTagsChapterId {
     fn from_interface_id(id_string: &str) -> Result<Self> {
-        let string = id_string.trim_start_matches("addresses_0x");
-        let bytes = hex::decode(string)?;
+        let bytes = CHAPTER_ID_FORM.parse(id_string)?;
         Ok(NameTagsChapterId {
             val: Vector::from_iter(bytes),
         })
     }
 
     fn interface_id(&self) -> String {
-        format!("addresses_0x{}", self.as_string())
+        CHAPTER_ID_FORM.format(&self.val)
     }
 
     fn nth_id(n: u32) -> Result<NameTagsChapterId> {
-        if n as usize >= NameTagsSpec::NUM_CHAPTERS {
-            bail!("'n' must be <= NUM_CHAPTERS")
-        }
-        let byte_vec = vec![n as u8];
+        let byte_vec = nth_chapter_bytes(n, NameTagsSpec::NUM_CHAPTERS)?;
         Ok(NameTagsChapterId {
             val: Vector::from_iter(byte_vec),
         })
@@ -169,6 +322,13 @@ impl NameTagsChapterId {
     }
 }
 
+/// E.g., "nametags_from_000_630_000".
+const VOLUME_ID_FORM: VolumeIdForm = VolumeIdForm {
+    prefix: "nametags_from_",
+    per_volume: ENTRIES_PER_VOLUME as u32,
+    max_volumes: MAX_VOLUMES,
+};
+
 #[derive(Clone, Debug, Default, PartialEq, Hash, PartialOrd, SimpleSerialize)]
 pub struct NameTagsVolumeId {
     /// Refers to the first address in the Volume. It is index of the address
@@ -183,33 +343,31 @@ pub struct NameTagsVolumeId {
 
 impl VolumeIdMethods<NameTagsSpec> for NameTagsVolumeId {
     fn from_interface_id(interface_id: &str) -> Result<Self> {
-        let Ok(first_address) = interface_id
-            .trim_start_matches("nametags_from_")
-            .replace('_', "")
-            .parse::<u32>()
-            else {
-                bail!("The string: {} was not formatted as expected.", interface_id)};
-
+        let first_address = VOLUME_ID_FORM.parse(interface_id)?;
         Ok(NameTagsVolumeId { first_address })
     }
 
     fn interface_id(&self) -> String {
-        // From the spec: "nametags_from_000_630_000"
-        format!(
-            "nametags_from_{}",
-            utils::string::num_as_triplet(self.first_address)
-        )
+        VOLUME_ID_FORM.format(self.first_address)
     }
 
     fn nth_id(n: u32) -> Result<NameTagsVolumeId> {
         Ok(NameTagsVolumeId {
-            first_address: n * ENTRIES_PER_VOLUME as u32,
+            first_address: VOLUME_ID_FORM.nth_start(n)?,
         })
     }
 
     fn is_nth(&self) -> Result<u32> {
         Ok(self.first_address / ENTRIES_PER_VOLUME as u32)
     }
+
+    fn coverage(&self) -> Option<VolumeCoverage> {
+        Some(VolumeCoverage {
+            unit: "addresses".to_string(),
+            first: self.first_address as u64,
+            last: self.first_address as u64 + ENTRIES_PER_VOLUME as u64 - 1,
+        })
+    }
 }
 
 impl NameTagsVolumeId {
@@ -233,6 +391,14 @@ impl RecordMethods<NameTagsSpec> for NameTagsRecord {
     fn value(&self) -> &NameTagsRecordValue {
         &self.value
     }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
+
+    fn from_key_value(key: NameTagsRecordKey, value: NameTagsRecordValue) -> Self {
+        NameTagsRecord { key, value }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
@@ -244,6 +410,10 @@ impl RecordKeyMethods for NameTagsRecordKey {
     fn summary_string(&self) -> Result<String> {
         Ok(hex::encode(&self.key))
     }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
 }
 
 impl NameTagsRecordKey {
@@ -259,13 +429,36 @@ impl NameTagsRecordKey {
 pub struct NameTagsRecordValue {
     pub names: List<Name, MAX_NAMES_PER_RECORD>,
     pub tags: List<Tag, MAX_TAGS_PER_RECORD>,
+    /// When the names and tags were added. Empty if unknown.
+    pub provenance: RecordProvenance,
 }
 
 impl RecordValueMethods for NameTagsRecordValue {
+    type Summary = NameTagsSummary;
     fn summary_strings(&self) -> Result<Vec<String>> {
         let n = format!("names: {:?}", self.names_as_strings()?);
         let t = format!("tags: {:?}", self.tags_as_strings()?);
-        Ok(vec![n, t])
+        let mut strings = vec![n, t];
+        if let Some(added) = self.added() {
+            strings.push(format!("added: {}", added));
+        }
+        Ok(strings)
+    }
+
+    fn summary(&self) -> Result<NameTagsSummary> {
+        Ok(NameTagsSummary {
+            names: self.names_as_strings()?,
+            tags: self.tags_as_strings()?,
+            added: self.added(),
+        })
+    }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
+
+    fn from_serialized_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(deserialize::<Self>(bytes)?)
     }
 }
 
@@ -282,7 +475,36 @@ impl NameTagsRecordValue {
         NameTagsRecordValue {
             names: List::from_iter(name_vec),
             tags: List::from_iter(tag_vec),
+            provenance: RecordProvenance::default(),
+        }
+    }
+    /// Creates a record value where every name and tag is from the given source.
+    pub fn from_strings_with_source(
+        names: Vec<String>,
+        tags: Vec<String>,
+        source: Option<&str>,
+    ) -> Self {
+        let mut value = Self::from_strings(names, tags);
+        if let Some(source) = source {
+            let source: List<u8, MAX_BYTES_PER_SOURCE> =
+                List::from_iter(source.as_bytes().to_vec());
+            for n in value.names.iter_mut() {
+                n.source = source.clone();
+            }
+            for t in value.tags.iter_mut() {
+                t.source = source.clone();
+            }
         }
+        value
+    }
+    /// Records when the names and tags were added (seconds since the Unix epoch).
+    pub fn with_added(mut self, added: Option<u64>) -> Self {
+        self.provenance = RecordProvenance::from_timestamp(added);
+        self
+    }
+    /// When the names and tags were added (seconds since the Unix epoch), if known.
+    pub fn added(&self) -> Option<u64> {
+        self.provenance.added()
     }
     /// Turns SSZ bytes into a vector of readable strings.
     pub fn names_as_strings(&self) -> Result<Vec<String>> {
@@ -300,17 +522,133 @@ impl NameTagsRecordValue {
         }
         Ok(s)
     }
+    /// Names paired with their source, if one is recorded.
+    pub fn names_with_sources(&self) -> Result<Vec<(String, Option<String>)>> {
+        let mut s = vec![];
+        for n in self.names.iter() {
+            s.push((n.to_utf8_string()?, source_as_string(&n.source)?))
+        }
+        Ok(s)
+    }
+    /// Tags paired with their source, if one is recorded.
+    pub fn tags_with_sources(&self) -> Result<Vec<(String, Option<String>)>> {
+        let mut s = vec![];
+        for t in self.tags.iter() {
+            s.push((t.to_utf8_string()?, source_as_string(&t.source)?))
+        }
+        Ok(s)
+    }
+}
+
+/// The names and tags of an address. The summary of [NameTagsRecordValue].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameTagsSummary {
+    pub names: Vec<String>,
+    pub tags: Vec<String>,
+    /// When the names and tags were added (seconds since the Unix epoch), if known.
+    #[serde(default)]
+    pub added: Option<u64>,
+}
+
+/// A name or tag after merging Volumes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Label {
+    pub text: String,
+    pub source: Option<String>,
+    /// The Volume the label is from.
+    pub volume_interface_id: String,
+}
+
+/// The names and tags of an address, merged across Volumes. See [merge_volumes].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergedNameTags {
+    pub names: Vec<Label>,
+    pub tags: Vec<Label>,
+}
+
+/// Merges the values for an address from each Volume (see the module
+/// documentation for the rule).
+///
+/// ## Example
+/// ```ignore
+/// let merged = merge_volumes(&db.find_by_volume("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae")?)?;
+/// ```
+pub fn merge_volumes(
+    values: &[(NameTagsVolumeId, Vec<NameTagsRecordValue>)],
+) -> Result<MergedNameTags> {
+    let mut ordered: Vec<&(NameTagsVolumeId, Vec<NameTagsRecordValue>)> = values.iter().collect();
+    ordered.sort_by_key(|(volume_id, _)| volume_id.first_address);
+    let mut merged = MergedNameTags::default();
+    for (volume_id, values) in ordered {
+        let volume = volume_id.interface_id();
+        let mut names = vec![];
+        let mut tags = vec![];
+        for value in values {
+            names.extend(value.names_with_sources()?);
+            tags.extend(value.tags_with_sources()?);
+        }
+        let mut sources: Vec<Option<String>> = vec![];
+        for (_text, source) in names.iter().chain(tags.iter()) {
+            if !sources.contains(source) {
+                sources.push(source.clone());
+            }
+        }
+        replace_by_source(&mut merged.names, &sources, &volume, names);
+        replace_by_source(&mut merged.tags, &sources, &volume, tags);
+    }
+    Ok(merged)
+}
+
+/// Replaces the labels of each source present in a Volume.
+///
+/// Replacements take the position of the first label they replace, so that
+/// the order of sources is stable.
+fn replace_by_source(
+    labels: &mut Vec<Label>,
+    sources: &[Option<String>],
+    volume: &str,
+    incoming: Vec<(String, Option<String>)>,
+) {
+    for source in sources {
+        let position = labels
+            .iter()
+            .position(|l| &l.source == source)
+            .unwrap_or(labels.len());
+        labels.retain(|l| &l.source != source);
+        let mut replacements: Vec<Label> = vec![];
+        for (text, _) in incoming.iter().filter(|(_, s)| s == source) {
+            if !replacements.iter().any(|l| &l.text == text) {
+                replacements.push(Label {
+                    text: text.clone(),
+                    source: source.clone(),
+                    volume_interface_id: volume.to_string(),
+                });
+            }
+        }
+        labels.splice(position..position, replacements);
+    }
+}
+
+/// An empty source is represented as None.
+fn source_as_string(source: &List<u8, MAX_BYTES_PER_SOURCE>) -> Result<Option<String>> {
+    if source.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(from_utf8(source)?.to_string()))
 }
 
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
 pub struct Name {
     pub val: List<u8, MAX_BYTES_PER_NAME>,
+    /// Where the name originated (namespace). Empty if unknown.
+    pub source: List<u8, MAX_BYTES_PER_SOURCE>,
 }
 
 impl Name {
     pub fn from_string(s: &str) -> Self {
         Name {
             val: List::from_iter(s.as_bytes().to_vec()),
+            source: List::default(),
         }
     }
     pub fn to_utf8_string(&self) -> Result<String> {
@@ -323,12 +661,15 @@ impl Name {
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
 pub struct Tag {
     pub val: List<u8, MAX_BYTES_PER_TAG>,
+    /// Where the tag originated (namespace). Empty if unknown.
+    pub source: List<u8, MAX_BYTES_PER_SOURCE>,
 }
 
 impl Tag {
     pub fn from_string(s: &str) -> Self {
         Tag {
             val: List::from_iter(s.as_bytes().to_vec()),
+            source: List::default(),
         }
     }
     pub fn to_utf8_string(&self) -> Result<String> {
@@ -337,3 +678,290 @@ impl Tag {
         Ok(s.to_string())
     }
 }
+
+// Types from spec version 0.1.0, used to migrate existing Chapter files.
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct NameTagsChapterV0_1_0 {
+    chapter_id: NameTagsChapterId,
+    volume_id: NameTagsVolumeId,
+    records: List<NameTagsRecordV0_1_0, MAX_RECORDS_PER_CHAPTER>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct NameTagsRecordV0_1_0 {
+    key: NameTagsRecordKey,
+    value: NameTagsRecordValueV0_1_0,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct NameTagsRecordValueV0_1_0 {
+    names: List<NameV0_1_0, MAX_NAMES_PER_RECORD>,
+    tags: List<TagV0_1_0, MAX_TAGS_PER_RECORD>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct NameV0_1_0 {
+    val: List<u8, MAX_BYTES_PER_NAME>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct TagV0_1_0 {
+    val: List<u8, MAX_BYTES_PER_TAG>,
+}
+
+// Types from spec version 0.2.0. Names and tags are unchanged.
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct NameTagsChapterV0_2_0 {
+    chapter_id: NameTagsChapterId,
+    volume_id: NameTagsVolumeId,
+    records: List<NameTagsRecordV0_2_0, MAX_RECORDS_PER_CHAPTER>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct NameTagsRecordV0_2_0 {
+    key: NameTagsRecordKey,
+    value: NameTagsRecordValueV0_2_0,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct NameTagsRecordValueV0_2_0 {
+    names: List<Name, MAX_NAMES_PER_RECORD>,
+    tags: List<Tag, MAX_TAGS_PER_RECORD>,
+}
+
+// SSZ layouts, for the schema fingerprint.
+
+impl SszDescriptor for NameTagsChapter {
+    fn ssz_descriptor() -> String {
+        let NameTagsChapter {
+            chapter_id,
+            volume_id,
+            records,
+        } = NameTagsChapter::default();
+        container(&[field(&chapter_id), field(&volume_id), field(&records)])
+    }
+}
+
+impl SszDescriptor for NameTagsChapterId {
+    fn ssz_descriptor() -> String {
+        let NameTagsChapterId { val } = NameTagsChapterId::default();
+        container(&[field(&val)])
+    }
+}
+
+impl SszDescriptor for NameTagsVolumeId {
+    fn ssz_descriptor() -> String {
+        let NameTagsVolumeId { first_address } = NameTagsVolumeId::default();
+        container(&[field(&first_address)])
+    }
+}
+
+impl SszDescriptor for NameTagsRecord {
+    fn ssz_descriptor() -> String {
+        let NameTagsRecord { key, value } = NameTagsRecord::default();
+        container(&[field(&key), field(&value)])
+    }
+}
+
+impl SszDescriptor for NameTagsRecordKey {
+    fn ssz_descriptor() -> String {
+        let NameTagsRecordKey { key } = NameTagsRecordKey::default();
+        container(&[field(&key)])
+    }
+}
+
+impl SszDescriptor for NameTagsRecordValue {
+    fn ssz_descriptor() -> String {
+        let NameTagsRecordValue {
+            names,
+            tags,
+            provenance,
+        } = NameTagsRecordValue::default();
+        container(&[field(&names), field(&tags), field(&provenance)])
+    }
+}
+
+impl SszDescriptor for Name {
+    fn ssz_descriptor() -> String {
+        let Name { val, source } = Name::default();
+        container(&[field(&val), field(&source)])
+    }
+}
+
+impl SszDescriptor for Tag {
+    fn ssz_descriptor() -> String {
+        let Tag { val, source } = Tag::default();
+        container(&[field(&val), field(&source)])
+    }
+}
+
+#[test]
+fn migrates_v0_1_0_chapter() {
+    let key =
+        NameTagsRecordKey::from_address("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae").unwrap();
+    let legacy = NameTagsChapterV0_1_0 {
+        chapter_id: NameTagsChapterId::nth_id(0xde).unwrap(),
+        volume_id: NameTagsVolumeId::default(),
+        records: List::from_iter(vec![NameTagsRecordV0_1_0 {
+            key: key.clone(),
+            value: NameTagsRecordValueV0_1_0 {
+                names: List::from_iter(vec![NameV0_1_0 {
+                    val: List::from_iter(b"EthDev".to_vec()),
+                }]),
+                tags: List::from_iter(vec![TagV0_1_0 {
+                    val: List::from_iter(b"ethereum-foundation".to_vec()),
+                }]),
+            },
+        }]),
+    };
+    let bytes = serialize(&legacy).unwrap();
+    let chapter = NameTagsChapter::from_file(bytes).unwrap();
+    let value = &chapter.records[0].value;
+    assert_eq!(chapter.records[0].key, key);
+    assert_eq!(
+        value.names_with_sources().unwrap(),
+        vec![("EthDev".to_string(), None)]
+    );
+    assert_eq!(
+        value.tags_as_strings().unwrap(),
+        vec!["ethereum-foundation"]
+    );
+}
+
+#[test]
+fn sources_round_trip() {
+    let value = NameTagsRecordValue::from_strings_with_source(
+        vec!["Tornado Cash".to_string()],
+        vec!["sanctioned".to_string()],
+        Some("ofac"),
+    );
+    let mut chapter = NameTagsChapter::new_empty(
+        &NameTagsVolumeId::default(),
+        &NameTagsChapterId::nth_id(0).unwrap(),
+    );
+    chapter.records.push(NameTagsRecord {
+        key: NameTagsRecordKey::default(),
+        value,
+    });
+    let decoded = NameTagsChapter::from_file(chapter.as_serialized_bytes().unwrap()).unwrap();
+    assert_eq!(decoded, chapter);
+    assert_eq!(
+        decoded.records[0].value.tags_with_sources().unwrap(),
+        vec![("sanctioned".to_string(), Some("ofac".to_string()))]
+    );
+}
+
+#[test]
+fn migrates_v0_2_0_chapter() {
+    use crate::specs::header::ChapterHeader;
+
+    let key =
+        NameTagsRecordKey::from_address("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae").unwrap();
+    let value = NameTagsRecordValue::from_strings_with_source(
+        vec!["EthDev".to_string()],
+        vec![],
+        Some("rolodeth"),
+    );
+    let legacy = NameTagsChapterV0_2_0 {
+        chapter_id: NameTagsChapterId::nth_id(0xde).unwrap(),
+        volume_id: NameTagsVolumeId::default(),
+        records: List::from_iter(vec![NameTagsRecordV0_2_0 {
+            key: key.clone(),
+            value: NameTagsRecordValueV0_2_0 {
+                names: value.names.clone(),
+                tags: value.tags.clone(),
+            },
+        }]),
+    };
+    let bytes = serialize(&legacy).unwrap();
+    let chapter = NameTagsChapter::from_file(bytes.clone()).unwrap();
+    assert_eq!(chapter.records[0].key, key);
+    assert_eq!(chapter.records[0].value, value);
+    assert_eq!(chapter.records[0].value.added(), None);
+
+    let mut header = ChapterHeader::for_spec::<NameTagsSpec>();
+    header.spec_version = "0.2.0".to_string();
+    let mut with_header = header.to_bytes().unwrap();
+    with_header.extend(bytes);
+    assert_eq!(NameTagsChapter::from_file(with_header).unwrap(), chapter);
+}
+
+#[test]
+fn provenance_in_summaries() {
+    let value = NameTagsRecordValue::from_strings(vec!["EthDev".to_string()], vec![]);
+    assert_eq!(value.summary_strings().unwrap().len(), 2);
+    assert_eq!(value.summary().unwrap().added, None);
+
+    let value = value.with_added(Some(1_438_269_973));
+    assert_eq!(
+        value.summary_strings().unwrap().last().unwrap(),
+        "added: 1438269973"
+    );
+    let summary = serde_json::to_value(value.summary().unwrap()).unwrap();
+    assert_eq!(summary["added"], 1_438_269_973);
+    assert!(NameTagsSpec::value_schema().describes(&summary));
+    let unknown = serde_json::to_value(NameTagsRecordValue::default().summary().unwrap()).unwrap();
+    assert!(NameTagsSpec::value_schema().describes(&unknown));
+}
+
+#[test]
+fn later_volumes_replace_labels_by_source() {
+    let value = |names: &[&str], tags: &[&str], source: Option<&str>| {
+        let names = names.iter().map(|n| n.to_string()).collect();
+        let tags = tags.iter().map(|t| t.to_string()).collect();
+        NameTagsRecordValue::from_strings_with_source(names, tags, source)
+    };
+    let volume = |first_address| NameTagsVolumeId { first_address };
+    let values = vec![
+        (
+            volume(1000),
+            vec![value(&["Ethereum Foundation"], &[], Some("rolodeth"))],
+        ),
+        (
+            volume(0),
+            vec![
+                value(&["EthDev"], &["ethereum-foundation"], Some("rolodeth")),
+                value(&["EF"], &["foundation", "foundation"], Some("etherscan")),
+                value(&["Dev"], &[], None),
+            ],
+        ),
+    ];
+    let merged = merge_volumes(&values).unwrap();
+    let names: Vec<(&str, Option<&str>, &str)> = merged
+        .names
+        .iter()
+        .map(|l| {
+            (
+                l.text.as_str(),
+                l.source.as_deref(),
+                l.volume_interface_id.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (
+                "Ethereum Foundation",
+                Some("rolodeth"),
+                "nametags_from_000_001_000"
+            ),
+            ("EF", Some("etherscan"), "nametags_from_000_000_000"),
+            ("Dev", None, "nametags_from_000_000_000"),
+        ]
+    );
+    // The later rolodeth Record has no tags, so the earlier rolodeth tag is removed.
+    let tags: Vec<&str> = merged.tags.iter().map(|l| l.text.as_str()).collect();
+    assert_eq!(tags, vec!["foundation"]);
+}
+
+#[test]
+fn record_key_to_chapter_id_uses_spec_length() {
+    let record_key = NameTagsRecordKey {
+        key: Vector::from_iter(hex::decode("de0b295669a9fd93d5f28d9ec85e40f4cb697bae").unwrap()),
+    };
+    let chapter_id = NameTagsSpec::record_key_to_chapter_id(&record_key).unwrap();
+    assert_eq!(chapter_id.interface_id(), "addresses_0xde");
+}
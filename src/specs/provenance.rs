@@ -0,0 +1,51 @@
+//! When a Record value was added, for specs that record it.
+//!
+//! The time comes from the raw data, where the raw data has it (e.g., an
+//! `"added"` field in a raw nametags file). It is not taken from file
+//! modification times, so that forming a Chapter again gives the same bytes.
+//!
+//! SSZ has no optional type, so the time is held in a list of at most one item.
+use ssz_rs::prelude::*;
+
+use super::fingerprint::{container, field, SszDescriptor};
+
+/// When a Record value was added to the raw data. Empty if not known.
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+pub struct RecordProvenance {
+    /// Seconds since the Unix epoch.
+    pub added: List<u64, 1>,
+}
+
+impl RecordProvenance {
+    pub fn from_timestamp(added: Option<u64>) -> Self {
+        RecordProvenance {
+            added: List::from_iter(added),
+        }
+    }
+    /// Seconds since the Unix epoch, if known.
+    pub fn added(&self) -> Option<u64> {
+        self.added.first().copied()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+    }
+}
+
+impl SszDescriptor for RecordProvenance {
+    fn ssz_descriptor() -> String {
+        let RecordProvenance { added } = RecordProvenance::default();
+        container(&[field(&added)])
+    }
+}
+
+#[test]
+fn provenance_holds_at_most_one_time() {
+    let known = RecordProvenance::from_timestamp(Some(1_650_000_000));
+    assert_eq!(known.added(), Some(1_650_000_000));
+    let bytes = serialize(&known).unwrap();
+    assert_eq!(deserialize::<RecordProvenance>(&bytes).unwrap(), known);
+
+    let unknown = RecordProvenance::from_timestamp(None);
+    assert!(unknown.is_empty());
+    assert_eq!(unknown, RecordProvenance::default());
+}
@@ -1,6 +1,7 @@
 use std::{fmt::Display, str::from_utf8};
 
 use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 use ssz_rs::prelude::*;
 
 use crate::{
@@ -9,19 +10,30 @@ use crate::{
     manifest::signatures::SignaturesManifest,
     parameters::signatures::{
         BYTES_FOR_SIGNATURE_CHARS, BYTES_PER_SIGNATURE, MAX_BYTES_PER_TEXT,
-        MAX_RECORDS_PER_CHAPTER, MAX_TEXTS_PER_RECORD, SIGNATURES_PER_VOLUME,
+        MAX_RECORDS_PER_CHAPTER, MAX_TEXTS_PER_RECORD, MAX_VOLUMES, SIGNATURES_PER_VOLUME,
     },
     samples::signatures::SignaturesSampleObtainer,
-    utils,
+    specs::{
+        header::{corrupt, versioned_chapter_payload},
+        ids::{nth_chapter_bytes, ChapterIdForm, VolumeIdForm},
+        provenance::RecordProvenance,
+    },
 };
 
-use super::traits::*;
+use super::{
+    fingerprint::{container, field, SszDescriptor},
+    traits::*,
+    value_schema::ValueSchema,
+};
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct SignaturesSpec {}
 
 impl DataSpec for SignaturesSpec {
     const NUM_CHAPTERS: usize = 256;
+    const SPEC_ID: &'static str = "signatures";
+    // Lookups for unknown selectors are common, so favour fewer false positives.
+    const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.001;
 
     type AssociatedChapter = SignaturesChapter;
 
@@ -42,19 +54,42 @@ impl DataSpec for SignaturesSpec {
     type AssociatedManifest = SignaturesManifest;
 
     fn spec_matches_input(data_kind: &DataKind) -> bool {
-        matches!(data_kind, DataKind::Signatures)
+        // Exhaustive so that new DataKind variants must be considered here.
+        match data_kind {
+            DataKind::Signatures => true,
+            DataKind::AddressAppearanceIndex(_) | DataKind::NameTags | DataKind::Events => false,
+        }
     }
 
     fn spec_version() -> String {
-        String::from("0.1.0")
+        // 0.2.0: Values have a provenance (when they were added).
+        String::from("0.2.0")
+    }
+
+    fn legacy_spec_versions() -> Vec<String> {
+        vec![String::from("0.1.0")]
     }
 
     fn spec_schemas_resource() -> String {
         String::from("https://github.com/perama-v/TODD/blob/main/example_specs/signatures.md")
     }
+    fn value_schema() -> ValueSchema {
+        ValueSchema::object(vec![
+            (
+                "texts",
+                "Function signatures with the selector. E.g., \"transfer(address,uint256)\".",
+                ValueSchema::list_of(ValueSchema::String),
+            ),
+            (
+                "added",
+                "When the texts were added (seconds since the Unix epoch), if known.",
+                ValueSchema::optional(ValueSchema::Integer),
+            ),
+        ])
+    }
 
     fn record_key_to_chapter_id(record_key: &SignaturesRecordKey) -> Result<SignaturesChapterId> {
-        let bytes = record_key.key[0..2].to_vec();
+        let bytes = record_key.key[0..BYTES_FOR_SIGNATURE_CHARS].to_vec();
         Ok(SignaturesChapterId {
             val: Vector::from_iter(bytes),
         })
@@ -68,6 +103,8 @@ impl DataSpec for SignaturesSpec {
     }
 }
 
+/// Records are in canonical order: ascending by their SSZ encoding. The key is
+/// the leading fixed size field, so this orders by key, then by value.
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
 pub struct SignaturesChapter {
     pub chapter_id: SignaturesChapterId,
@@ -96,14 +133,24 @@ impl ChapterMethods<SignaturesSpec> for SignaturesChapter {
     where
         Self: Sized,
     {
-        // Files are ssz encoded.
+        if let Some((version, payload)) = versioned_chapter_payload::<SignaturesSpec>(&data)? {
+            return match version.as_str() {
+                "0.1.0" => Self::from_v0_1_0_bytes(payload).map_err(corrupt),
+                _ => deserialize::<Self>(payload).map_err(corrupt),
+            };
+        }
+        // Files without a header are ssz encoded.
         let chapter = match deserialize::<Self>(&data) {
             Ok(c) => c,
-            Err(e) => bail!(
-                "Could not decode the SSZ data. Check that the library
+            Err(e) => match Self::from_v0_1_0_bytes(&data) {
+                // Files from spec version 0.1.0 are migrated on read.
+                Ok(c) => c,
+                Err(_) => bail!(
+                    "Could not decode the SSZ data. Check that the library
             spec version matches the version in the manifest.  {:?}",
-                e
-            ),
+                    e
+                ),
+            },
         };
         Ok(chapter)
     }
@@ -123,8 +170,59 @@ impl ChapterMethods<SignaturesSpec> for SignaturesChapter {
             records: List::default(),
         }
     }
+
+    fn from_records(
+        volume_id: &SignaturesVolumeId,
+        chapter_id: &SignaturesChapterId,
+        records: Vec<SignaturesRecord>,
+    ) -> Result<Self> {
+        if records.len() > MAX_RECORDS_PER_CHAPTER {
+            bail!(
+                "{} Records exceeds the maximum of {} per Chapter.",
+                records.len(),
+                MAX_RECORDS_PER_CHAPTER
+            )
+        }
+        Ok(SignaturesChapter {
+            chapter_id: chapter_id.clone(),
+            volume_id: volume_id.clone(),
+            records: List::from_iter(records),
+        })
+    }
 }
 
+impl SignaturesChapter {
+    /// Decodes a Chapter encoded with spec version 0.1.0, where values had no
+    /// provenance. The migrated values have an empty provenance.
+    pub fn from_v0_1_0_bytes(data: &[u8]) -> Result<Self> {
+        let legacy = match deserialize::<SignaturesChapterV0_1_0>(data) {
+            Ok(c) => c,
+            Err(e) => bail!(
+                "Could not decode the SSZ data as spec version 0.1.0. {:?}",
+                e
+            ),
+        };
+        let records = legacy.records.iter().map(|r| SignaturesRecord {
+            key: r.key.clone(),
+            value: SignaturesRecordValue {
+                texts: r.value.texts.clone(),
+                provenance: RecordProvenance::default(),
+            },
+        });
+        Ok(SignaturesChapter {
+            chapter_id: legacy.chapter_id,
+            volume_id: legacy.volume_id,
+            records: List::from_iter(records),
+        })
+    }
+}
+
+/// E.g., "signatures_0xdd".
+const CHAPTER_ID_FORM: ChapterIdForm = ChapterIdForm {
+    prefix: "signatures_0x",
+    num_bytes: BYTES_FOR_SIGNATURE_CHARS,
+};
+
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
 pub struct SignaturesChapterId {
     pub val: Vector<u8, BYTES_FOR_SIGNATURE_CHARS>,
@@ -132,22 +230,18 @@ pub struct SignaturesChapterId {
 
 impl ChapterIdMethods<SignaturesSpec> for SignaturesChapterId {
     fn from_interface_id(id_string: &str) -> Result<Self> {
-        let string = id_string.trim_start_matches("signatures_0x");
-        let bytes = hex::decode(string)?;
+        let bytes = CHAPTER_ID_FORM.parse(id_string)?;
         Ok(SignaturesChapterId {
             val: Vector::from_iter(bytes),
         })
     }
 
     fn interface_id(&self) -> String {
-        format!("signatures_0x{}", self.as_string())
+        CHAPTER_ID_FORM.format(&self.val)
     }
 
     fn nth_id(n: u32) -> Result<SignaturesChapterId> {
-        if n as usize >= SignaturesSpec::NUM_CHAPTERS {
-            bail!("'n' must be <= NUM_CHAPTERS")
-        }
-        let byte_vec = vec![n as u8];
+        let byte_vec = nth_chapter_bytes(n, SignaturesSpec::NUM_CHAPTERS)?;
         Ok(SignaturesChapterId {
             val: Vector::from_iter(byte_vec),
         })
@@ -179,37 +273,40 @@ pub struct SignaturesVolumeId {
     pub first_signature: u32,
 }
 
-const VOL_PREFIX: &str = "mappings_starting_";
+/// E.g., "mappings_starting_000_001_000".
+const VOLUME_ID_FORM: VolumeIdForm = VolumeIdForm {
+    prefix: "mappings_starting_",
+    per_volume: SIGNATURES_PER_VOLUME as u32,
+    max_volumes: MAX_VOLUMES,
+};
 
 impl VolumeIdMethods<SignaturesSpec> for SignaturesVolumeId {
     fn from_interface_id(interface_id: &str) -> Result<Self> {
-        let Ok(first_signature) = interface_id
-            .trim_start_matches(VOL_PREFIX)
-            .replace('_', "")
-            .parse::<u32>()
-            else {
-                bail!("The string: {} was not formatted as expected.", interface_id)};
-
+        let first_signature = VOLUME_ID_FORM.parse(interface_id)?;
         Ok(SignaturesVolumeId { first_signature })
     }
 
     fn interface_id(&self) -> String {
-        format!(
-            "{}{}",
-            VOL_PREFIX,
-            utils::string::num_as_triplet(self.first_signature)
-        )
+        VOLUME_ID_FORM.format(self.first_signature)
     }
 
     fn nth_id(n: u32) -> Result<SignaturesVolumeId> {
         Ok(SignaturesVolumeId {
-            first_signature: n * SIGNATURES_PER_VOLUME as u32,
+            first_signature: VOLUME_ID_FORM.nth_start(n)?,
         })
     }
 
     fn is_nth(&self) -> Result<u32> {
         Ok(self.first_signature / SIGNATURES_PER_VOLUME as u32)
     }
+
+    fn coverage(&self) -> Option<VolumeCoverage> {
+        Some(VolumeCoverage {
+            unit: "signatures".to_string(),
+            first: self.first_signature as u64,
+            last: self.first_signature as u64 + SIGNATURES_PER_VOLUME as u64 - 1,
+        })
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
@@ -226,6 +323,14 @@ impl RecordMethods<SignaturesSpec> for SignaturesRecord {
     fn value(&self) -> &SignaturesRecordValue {
         &self.value
     }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
+
+    fn from_key_value(key: SignaturesRecordKey, value: SignaturesRecordValue) -> Self {
+        SignaturesRecord { key, value }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
@@ -237,6 +342,10 @@ impl RecordKeyMethods for SignaturesRecordKey {
     fn summary_string(&self) -> Result<String> {
         Ok(hex::encode(&self.key))
     }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
 }
 
 impl SignaturesRecordKey {
@@ -254,6 +363,8 @@ impl SignaturesRecordKey {
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
 pub struct SignaturesRecordValue {
     pub texts: List<Text, MAX_TEXTS_PER_RECORD>,
+    /// When the texts were added. Empty if unknown.
+    pub provenance: RecordProvenance,
 }
 
 impl Display for SignaturesRecordValue {
@@ -273,13 +384,45 @@ impl Display for SignaturesRecordValue {
 }
 
 impl RecordValueMethods for SignaturesRecordValue {
+    type Summary = SignaturesSummary;
     fn summary_strings(&self) -> Result<Vec<String>> {
         let t = format!("texts: {:?}", self.texts_as_strings()?);
-        Ok(vec![t])
+        let mut strings = vec![t];
+        if let Some(added) = self.added() {
+            strings.push(format!("added: {}", added));
+        }
+        Ok(strings)
+    }
+
+    fn summary(&self) -> Result<SignaturesSummary> {
+        Ok(SignaturesSummary {
+            texts: self.texts_as_strings()?,
+            added: self.added(),
+        })
+    }
+
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serialize::<Self>(self)?)
+    }
+
+    fn from_serialized_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(deserialize::<Self>(bytes)?)
     }
 }
 
 impl SignaturesRecordValue {
+    /// Creates a record value with texts added at the given time (seconds
+    /// since the Unix epoch), if known.
+    pub fn from_texts(texts: Vec<Text>, added: Option<u64>) -> Self {
+        SignaturesRecordValue {
+            texts: List::from_iter(texts),
+            provenance: RecordProvenance::from_timestamp(added),
+        }
+    }
+    /// When the texts were added (seconds since the Unix epoch), if known.
+    pub fn added(&self) -> Option<u64> {
+        self.provenance.added()
+    }
     /// Turns SSZ bytes into a vector of readable strings.
     pub fn texts_as_strings(&self) -> Result<Vec<String>> {
         let mut s = vec![];
@@ -290,6 +433,15 @@ impl SignaturesRecordValue {
     }
 }
 
+/// The texts for a selector. The summary of [SignaturesRecordValue].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignaturesSummary {
+    pub texts: Vec<String>,
+    /// When the texts were added (seconds since the Unix epoch), if known.
+    #[serde(default)]
+    pub added: Option<u64>,
+}
+
 #[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
 pub struct Text {
     pub val: List<u8, MAX_BYTES_PER_TEXT>,
@@ -307,3 +459,120 @@ impl Text {
         Ok(s.to_string())
     }
 }
+
+// Types from spec version 0.1.0, used to migrate existing Chapter files. Texts
+// are unchanged.
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct SignaturesChapterV0_1_0 {
+    chapter_id: SignaturesChapterId,
+    volume_id: SignaturesVolumeId,
+    records: List<SignaturesRecordV0_1_0, MAX_RECORDS_PER_CHAPTER>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct SignaturesRecordV0_1_0 {
+    key: SignaturesRecordKey,
+    value: SignaturesRecordValueV0_1_0,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, SimpleSerialize)]
+struct SignaturesRecordValueV0_1_0 {
+    texts: List<Text, MAX_TEXTS_PER_RECORD>,
+}
+
+// SSZ layouts, for the schema fingerprint.
+
+impl SszDescriptor for SignaturesChapter {
+    fn ssz_descriptor() -> String {
+        let SignaturesChapter {
+            chapter_id,
+            volume_id,
+            records,
+        } = SignaturesChapter::default();
+        container(&[field(&chapter_id), field(&volume_id), field(&records)])
+    }
+}
+
+impl SszDescriptor for SignaturesChapterId {
+    fn ssz_descriptor() -> String {
+        let SignaturesChapterId { val } = SignaturesChapterId::default();
+        container(&[field(&val)])
+    }
+}
+
+impl SszDescriptor for SignaturesVolumeId {
+    fn ssz_descriptor() -> String {
+        let SignaturesVolumeId { first_signature } = SignaturesVolumeId::default();
+        container(&[field(&first_signature)])
+    }
+}
+
+impl SszDescriptor for SignaturesRecord {
+    fn ssz_descriptor() -> String {
+        let SignaturesRecord { key, value } = SignaturesRecord::default();
+        container(&[field(&key), field(&value)])
+    }
+}
+
+impl SszDescriptor for SignaturesRecordKey {
+    fn ssz_descriptor() -> String {
+        let SignaturesRecordKey { key } = SignaturesRecordKey::default();
+        container(&[field(&key)])
+    }
+}
+
+impl SszDescriptor for SignaturesRecordValue {
+    fn ssz_descriptor() -> String {
+        let SignaturesRecordValue { texts, provenance } = SignaturesRecordValue::default();
+        container(&[field(&texts), field(&provenance)])
+    }
+}
+
+impl SszDescriptor for Text {
+    fn ssz_descriptor() -> String {
+        let Text { val } = Text::default();
+        container(&[field(&val)])
+    }
+}
+
+#[test]
+fn migrates_v0_1_0_chapter() {
+    use crate::specs::header::ChapterHeader;
+
+    let key = SignaturesRecordKey::from_signature("a9059cbb").unwrap();
+    let texts = List::from_iter(vec![Text::from_string("transfer(address,uint256)")]);
+    let legacy = SignaturesChapterV0_1_0 {
+        chapter_id: SignaturesChapterId::nth_id(0xa9).unwrap(),
+        volume_id: SignaturesVolumeId::default(),
+        records: List::from_iter(vec![SignaturesRecordV0_1_0 {
+            key: key.clone(),
+            value: SignaturesRecordValueV0_1_0 {
+                texts: texts.clone(),
+            },
+        }]),
+    };
+    let bytes = serialize(&legacy).unwrap();
+    let chapter = SignaturesChapter::from_file(bytes.clone()).unwrap();
+    assert_eq!(chapter.records[0].key, key);
+    assert_eq!(
+        chapter.records[0].value,
+        SignaturesRecordValue::from_texts(texts.to_vec(), None)
+    );
+
+    // Files with a header for the earlier version are also migrated.
+    let mut header = ChapterHeader::for_spec::<SignaturesSpec>();
+    header.spec_version = "0.1.0".to_string();
+    let mut with_header = header.to_bytes().unwrap();
+    with_header.extend(bytes);
+    assert_eq!(SignaturesChapter::from_file(with_header).unwrap(), chapter);
+}
+
+#[test]
+fn record_key_to_chapter_id_uses_spec_length() {
+    let record_key = SignaturesRecordKey {
+        key: Vector::from_iter(hex::decode("a9059cbb").unwrap()),
+    };
+    let chapter_id = SignaturesSpec::record_key_to_chapter_id(&record_key).unwrap();
+    assert_eq!(chapter_id.interface_id(), "signatures_0xa9");
+}
@@ -1,14 +1,25 @@
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
 use crate::config::choices::DataKind;
+use crate::extraction::raw_index::RawEntryRange;
 use crate::extraction::traits::ExtractorMethods;
+use crate::manifest::mirrors::ManifestMirrors;
+use crate::manifest::provenance::ManifestProvenance;
+use crate::manifest::succession::ManifestSuccessor;
 use crate::samples::traits::SampleObtainerMethods;
 
+use super::{
+    fingerprint::{fingerprint, SszDescriptor},
+    header::{peek_metadata, ChapterHeader, ChapterMetadata},
+    value_schema::ValueSchema,
+};
+
 // Placeholder for the real trait.
 pub trait SszDecode {}
 
@@ -53,15 +64,29 @@ impl<T> BasicUsefulTraits for T where T: Clone + Debug + Default + PartialEq + S
 /// - RecordKey: Something a user has/knows (address) and uses to get more information
 /// - RecordValue: The data that is the result of a record_key (appearance transactions).
 /// - raw_pair: a raw_key-raw_value pair where key can be turned into a record_key
-/// and value becomes an record_value.
+///   and value becomes an record_value.
 /// - raw_key (unformatted record_key)
 /// - raw_value (unformatted record_value)
 pub trait DataSpec: Sized {
     const NUM_CHAPTERS: usize;
+    /// Identifies the spec in Chapter file headers. E.g., "nametags".
+    const SPEC_ID: &'static str;
+    /// Target false positive rate for the optional Chapter bloom filters.
+    ///
+    /// Lower rates produce larger filters.
+    const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.01;
+    /// Whether RecordKeys are Ethereum addresses. If so, raw keys may also be
+    /// ENS names (see `ConfigStruct::ens_rpc_url`).
+    const ADDRESS_KEYED: bool = false;
+    /// Whether published Volumes may be corrected by errata (see [crate::database::errata]).
+    ///
+    /// Specs that support errata implement [ChapterMethods::from_records] and the
+    /// errata methods of [ManifestMethods].
+    const SUPPORTS_ERRATA: bool = false;
 
     // Associated types. They must meet certain trait bounds. (Alias: Bound).
 
-    type AssociatedChapter: ChapterMethods<Self> + BasicUsefulTraits;
+    type AssociatedChapter: ChapterMethods<Self> + SszDescriptor + BasicUsefulTraits;
     type AssociatedChapterId: ChapterIdMethods<Self> + BasicUsefulTraits;
     type AssociatedVolumeId: VolumeIdMethods<Self> + UsefulTraits;
 
@@ -80,9 +105,25 @@ pub trait DataSpec: Sized {
     ///
     /// This is used in coordinating platform-specific directories. It ensures
     /// that all implementations of the spec also create a new enum variant.
+    ///
+    /// Implementations should match exhaustively (no wildcard), so that adding
+    /// a DataKind variant requires each spec to be updated.
     fn spec_matches_input(data_kind: &DataKind) -> bool;
     /// Returns the version of the specification for the particular database.
     fn spec_version() -> String;
+    /// Earlier spec versions with Chapter files that are migrated when read
+    /// (see [ChapterMethods::from_file]).
+    ///
+    /// A Chapter file with a header for any other version is reported as the
+    /// wrong version.
+    fn legacy_spec_versions() -> Vec<String> {
+        vec![]
+    }
+    /// A fingerprint of the SSZ layout of the Chapter type (see
+    /// [crate::specs::fingerprint]).
+    fn schema_fingerprint() -> String {
+        fingerprint(&Self::AssociatedChapter::ssz_descriptor())
+    }
     /// Returns the number of Chapters that the spec defines.
     fn num_chapters() -> usize {
         Self::NUM_CHAPTERS
@@ -91,6 +132,9 @@ pub trait DataSpec: Sized {
     ///
     /// For example, a CID or a URL.
     fn spec_schemas_resource() -> String;
+    /// Describes the JSON layout of the value summaries of the spec
+    /// (see [RecordValueMethods::summary]), for tools that handle any database.
+    fn value_schema() -> ValueSchema;
     /// Gets all possible ChapterIds for a given spec.
     ///
     /// This is used when creating a new database, where chapters can be created
@@ -123,6 +167,16 @@ pub trait DataSpec: Sized {
     /// If the key is a hex string, it might convert that to
     /// a struct capable of ssz encoding.
     fn raw_key_as_record_key(key: &str) -> Result<Self::AssociatedRecordKey>;
+    /// Applies the RecordValues of an erratum to the RecordValues for the same
+    /// RecordKey in the Volume that the erratum corrects.
+    ///
+    /// By default the corrections replace the original values.
+    fn apply_errata(
+        _original: Vec<Self::AssociatedRecordValue>,
+        corrections: Vec<Self::AssociatedRecordValue>,
+    ) -> Vec<Self::AssociatedRecordValue> {
+        corrections
+    }
 }
 
 /**
@@ -161,6 +215,10 @@ marker traits to define common functions.
 */
 pub trait VolumeIdMethods<T: DataSpec>: Sized {
     /// Returns the VolumeId for the given interface id.
+    ///
+    /// # Error
+    /// Returns an error if the interface id is not in the canonical form
+    /// (see [Self::interface_id]) or is not the start of a Volume.
     fn from_interface_id(interface_id: &str) -> Result<Self>;
     /// Returns the interface id for the Volume.
     fn interface_id(&self) -> String;
@@ -179,6 +237,10 @@ pub trait VolumeIdMethods<T: DataSpec>: Sized {
     /// n=2, id=200_000
     /// let oldest_block = n * BLOCKS_PER_VOLUME;
     /// ```
+    ///
+    /// # Error
+    /// Returns an error if n is not less than the `MAX_VOLUMES` parameter of the
+    /// spec (the number of Volumes that interface ids can represent).
     fn nth_id(n: u32) -> Result<T::AssociatedVolumeId>;
     /// The zero-based position for the given VolumeId.
     ///
@@ -207,9 +269,28 @@ pub trait VolumeIdMethods<T: DataSpec>: Sized {
         }
         Ok(vols)
     }
+    /// The raw data that a complete Volume covers, if the spec defines it.
+    ///
+    /// E.g., the blocks of an address appearance index Volume.
+    fn coverage(&self) -> Option<VolumeCoverage> {
+        None
+    }
+}
+
+/// A range of raw data (inclusive), such as blocks or raw entries.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeCoverage {
+    /// What is counted. E.g., "blocks".
+    pub unit: String,
+    pub first: u64,
+    pub last: u64,
 }
 pub trait ChapterIdMethods<T: DataSpec>: Sized {
     /// Returns the ChapterId from an interface id.
+    ///
+    /// # Error
+    /// Returns an error if the interface id is not in the canonical form
+    /// (see [Self::interface_id]).
     fn from_interface_id(id_string: &str) -> Result<Self>;
     /// Returns the interface id for the Chapter.
     fn interface_id(&self) -> String;
@@ -250,12 +331,33 @@ pub trait RecordKeyMethods {
     /// hex::encode(self.key.to_vec())
     /// ```
     fn summary_string(&self) -> Result<String>;
+    /// RecordKey as byte representation. Used by the compiled index.
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>>;
 }
 
 /// Methods that RecordValues must implement.
 pub trait RecordValueMethods {
+    /// A structured form of the value for applications (see [Self::summary]).
+    type Summary: Serialize + for<'de> Deserialize<'de> + Clone + Debug + PartialEq;
     /// Returns the value, with all elements as Strings in a vector.
+    ///
+    /// For display only. The format may change, applications should use
+    /// [Self::summary].
     fn summary_strings(&self) -> Result<Vec<String>>;
+    /// Returns the value in a structured form that can be serialized.
+    ///
+    /// ## Example
+    /// For the address appearance index, the transactions:
+    /// ```json
+    /// [{"block": 14400001, "index": 3}]
+    /// ```
+    fn summary(&self) -> Result<Self::Summary>;
+    /// RecordValue as byte representation. Used by the compiled index.
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>>;
+    /// RecordValue from the bytes produced by as_serialized_bytes().
+    fn from_serialized_bytes(bytes: &[u8]) -> Result<Self>
+    where
+        Self: Sized;
 }
 
 /// Marker trait.
@@ -264,6 +366,12 @@ pub trait RecordMethods<T: DataSpec> {
     fn key(&self) -> &T::AssociatedRecordKey;
     /// Get the RecordValue of the Record.
     fn value(&self) -> &T::AssociatedRecordValue;
+    /// Record struct as byte representation. Used for inclusion proofs.
+    fn as_serialized_bytes(&self) -> Result<Vec<u8>>;
+    /// Creates a Record from its parts.
+    fn from_key_value(key: T::AssociatedRecordKey, value: T::AssociatedRecordValue) -> Self
+    where
+        Self: Sized;
 }
 /// Methods for the smallest distributable chapter in the database.
 ///
@@ -293,15 +401,69 @@ pub trait ChapterMethods<T: DataSpec> {
     ///
     /// This allows databases to have custom methods (SSZ, SSZ+snappy, etc.)
     fn as_serialized_bytes(&self) -> Result<Vec<u8>>;
+    /// Chapter as the contents of a Chapter file: a [ChapterHeader] (with
+    /// [ChapterMetadata]) followed by the serialized Chapter.
+    fn as_file_bytes(&self) -> Result<Vec<u8>> {
+        let serialized = self.as_serialized_bytes()?;
+        let mut header = ChapterHeader::for_spec::<T>();
+        header.metadata = Some(ChapterMetadata::for_records::<T>(
+            self.records(),
+            serialized.len(),
+        )?);
+        let mut bytes = header.to_bytes()?;
+        bytes.extend(serialized);
+        Ok(bytes)
+    }
+    /// Reads the [ChapterMetadata] (record count, encoded size, smallest and
+    /// largest RecordKey) from the start of a Chapter file, without decoding
+    /// the Chapter.
+    ///
+    /// Only the header is read. Returns `None` for files without metadata
+    /// (written before header version 2, or without a header).
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let file = fs::File::open(path)?;
+    /// if let Some(metadata) = NameTagsChapter::peek_metadata(file)? {
+    ///     println!("{} records", metadata.record_count);
+    /// }
+    /// ```
+    ///
+    /// ## Errors
+    /// [ChapterFileError][super::header::ChapterFileError] if the header is for
+    /// another spec or spec version, or cannot be read.
+    fn peek_metadata<R: Read>(reader: R) -> Result<Option<ChapterMetadata>>
+    where
+        Self: Sized,
+    {
+        peek_metadata::<T, R>(reader)
+    }
     /// Chapter struct from byte representation from storage.
     ///
     /// This allows databases to have custom methods (SSZ, SSZ+snappy, etc.)
+    ///
+    /// Files may start with a [ChapterHeader] (see [super::header]), which is
+    /// checked. Files written before headers were added have none.
     fn from_file(data: Vec<u8>) -> Result<Self>
     where
         Self: Sized;
     /// The filename of the chapter
     fn filename(&self) -> String;
     fn new_empty(volume_id: &T::AssociatedVolumeId, chapter_id: &T::AssociatedChapterId) -> Self;
+    /// Creates a Chapter that holds the given Records, which are in canonical order.
+    ///
+    /// Required for specs that support errata, and for
+    /// [ChapterBuilder][crate::database::builder::ChapterBuilder].
+    fn from_records(
+        _volume_id: &T::AssociatedVolumeId,
+        _chapter_id: &T::AssociatedChapterId,
+        _records: Vec<T::AssociatedRecord>,
+    ) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        bail!("Chapters for this spec cannot be created from Records.")
+    }
 }
 
 /// Methods for the manifest of the database.
@@ -317,6 +479,11 @@ pub trait ManifestMethods<T: DataSpec> {
     fn spec_version(&self) -> &str;
     /// Sets the version string.
     fn set_spec_version(&mut self, version: String);
+    /// Returns the fingerprint of the SSZ layout of the Chapters, if recorded
+    /// (see [DataSpec::schema_fingerprint]).
+    fn schema_fingerprint(&self) -> Option<&str>;
+    /// Sets the fingerprint of the SSZ layout of the Chapters.
+    fn set_schema_fingerprint(&mut self, fingerprint: Option<String>);
     /// Returns the schemas string that can be used to acquire the spec
     /// for the database.
     fn schemas(&self) -> &str;
@@ -350,6 +517,43 @@ pub trait ManifestMethods<T: DataSpec> {
     fn set_cids<C>(&mut self, cids: &[(C, T::AssociatedVolumeId, T::AssociatedChapterId)])
     where
         C: AsRef<str> + Display;
+    /// Returns the CIDs for all Chapter bloom filters (may be empty).
+    fn bloom_filter_cids(&self) -> Result<Vec<ManifestCids<T>>>;
+    /// Sets the CIDs for all Chapter bloom filters, replacing existing ones.
+    fn set_bloom_filter_cids<C>(
+        &mut self,
+        cids: &[(C, T::AssociatedVolumeId, T::AssociatedChapterId)],
+    ) where
+        C: AsRef<str> + Display;
+    /// Returns the CIDs for all errata (empty for specs without errata).
+    fn errata_cids(&self) -> Result<Vec<ManifestErratum<T>>> {
+        Ok(vec![])
+    }
+    /// Sets the CIDs for all errata, replacing existing ones.
+    ///
+    /// Specs without errata ignore these.
+    fn set_errata_cids(&mut self, _errata: &[ManifestErratum<T>]) {}
+    /// Returns the mirrors that the files can be obtained from.
+    fn mirrors(&self) -> &ManifestMirrors;
+    /// Sets the mirrors, replacing existing ones.
+    fn set_mirrors(&mut self, mirrors: ManifestMirrors);
+    /// Returns the upstream source and license of the data.
+    fn provenance(&self) -> &ManifestProvenance;
+    /// Sets the provenance, replacing any existing one.
+    fn set_provenance(&mut self, provenance: ManifestProvenance);
+    /// Returns the database that replaces this one, if it is deprecated.
+    fn superseded_by(&self) -> Option<&ManifestSuccessor>;
+    /// Sets the database that replaces this one.
+    fn set_superseded_by(&mut self, successor: Option<ManifestSuccessor>);
+    /// Returns the raw entries that form each Volume (empty for specs that do
+    /// not describe them).
+    fn raw_entry_ranges(&self) -> Result<Vec<(T::AssociatedVolumeId, RawEntryRange)>> {
+        Ok(vec![])
+    }
+    /// Sets the raw entries that form each Volume, replacing existing ones.
+    ///
+    /// Specs that do not describe them ignore these.
+    fn set_raw_entry_ranges(&mut self, _ranges: &[(T::AssociatedVolumeId, RawEntryRange)]) {}
 }
 
 pub struct ManifestCids<T: DataSpec> {
@@ -357,3 +561,12 @@ pub struct ManifestCids<T: DataSpec> {
     pub(crate) volume_id: T::AssociatedVolumeId,
     pub(crate) chapter_id: T::AssociatedChapterId,
 }
+
+/// An erratum listed in the manifest: a correction to the Chapter of a Volume.
+pub struct ManifestErratum<T: DataSpec> {
+    pub(crate) cid: String,
+    pub(crate) volume_id: T::AssociatedVolumeId,
+    pub(crate) chapter_id: T::AssociatedChapterId,
+    /// Errata for the same Chapter are applied in ascending sequence (from 1).
+    pub(crate) sequence: u32,
+}
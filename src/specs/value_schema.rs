@@ -0,0 +1,122 @@
+//! Machine-readable descriptions of the values of each database.
+//!
+//! Each spec describes the JSON layout of its value summaries (see
+//! [RecordValueMethods::summary][crate::specs::traits::RecordValueMethods::summary])
+//! with [DataSpec::value_schema][crate::specs::traits::DataSpec::value_schema].
+//! Generic tools can then present values of any database without knowing its types.
+//!
+//! ## Example
+//! The schema for the names and tags of an address:
+//! ```json
+//! {
+//!   "type": "object",
+//!   "fields": [
+//!     {"name": "names", "description": "...", "type": "list", "items": {"type": "string"}},
+//!     {"name": "tags", "description": "...", "type": "list", "items": {"type": "string"}}
+//!   ]
+//! }
+//! ```
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The layout of a JSON value.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ValueSchema {
+    String,
+    /// A whole number that is not negative.
+    Integer,
+    List {
+        items: Box<ValueSchema>,
+    },
+    /// A value of the schema, or null if not known.
+    Optional {
+        item: Box<ValueSchema>,
+    },
+    /// An object with the fields in order. Other fields are not present.
+    Object {
+        fields: Vec<SchemaField>,
+    },
+}
+
+/// A field of a [ValueSchema::Object].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaField {
+    pub name: String,
+    pub description: String,
+    #[serde(flatten)]
+    pub schema: ValueSchema,
+}
+
+impl ValueSchema {
+    /// A list with items of the schema.
+    pub fn list_of(items: ValueSchema) -> Self {
+        ValueSchema::List {
+            items: Box::new(items),
+        }
+    }
+    /// A value of the schema, or null.
+    pub fn optional(item: ValueSchema) -> Self {
+        ValueSchema::Optional {
+            item: Box::new(item),
+        }
+    }
+    /// An object with fields of (name, description, schema).
+    pub fn object(fields: Vec<(&str, &str, ValueSchema)>) -> Self {
+        ValueSchema::Object {
+            fields: fields
+                .into_iter()
+                .map(|(name, description, schema)| SchemaField {
+                    name: name.to_string(),
+                    description: description.to_string(),
+                    schema,
+                })
+                .collect(),
+        }
+    }
+    /// Whether a JSON value has the layout of the schema.
+    pub fn describes(&self, value: &Value) -> bool {
+        match (self, value) {
+            (ValueSchema::String, Value::String(_)) => true,
+            (ValueSchema::Integer, Value::Number(n)) => n.is_u64(),
+            (ValueSchema::List { items }, Value::Array(values)) => {
+                values.iter().all(|v| items.describes(v))
+            }
+            (ValueSchema::Optional { item }, value) => value.is_null() || item.describes(value),
+            (ValueSchema::Object { fields }, Value::Object(map)) => {
+                map.len() == fields.len()
+                    && fields
+                        .iter()
+                        .all(|f| map.get(&f.name).is_some_and(|v| f.schema.describes(v)))
+            }
+            _ => false,
+        }
+    }
+}
+
+#[test]
+fn schema_describes_values() {
+    use serde_json::json;
+
+    let schema = ValueSchema::list_of(ValueSchema::object(vec![
+        ("block", "Block number.", ValueSchema::Integer),
+        ("label", "A label.", ValueSchema::String),
+    ]));
+    assert!(schema.describes(&json!([])));
+    assert!(schema.describes(&json!([{"block": 3, "label": "a"}])));
+    assert!(!schema.describes(&json!([{"block": -3, "label": "a"}])));
+    assert!(!schema.describes(&json!([{"block": 3}])));
+    assert!(!schema.describes(&json!([{"block": 3, "label": "a", "other": 1}])));
+    assert!(!schema.describes(&json!({"block": 3, "label": "a"})));
+
+    let optional = ValueSchema::optional(ValueSchema::Integer);
+    assert!(optional.describes(&json!(null)));
+    assert!(optional.describes(&json!(3)));
+    assert!(!optional.describes(&json!("3")));
+
+    let text = serde_json::to_string(&schema).unwrap();
+    assert!(
+        text.starts_with(r#"{"type":"list","items":{"type":"object","fields":[{"name":"block""#)
+    );
+    assert_eq!(serde_json::from_str::<ValueSchema>(&text).unwrap(), schema);
+}
@@ -0,0 +1,132 @@
+//! Bloom filters for testing whether a key may be present in a Chapter.
+//!
+//! A filter can be obtained and checked before a Chapter is downloaded.
+//! A negative result means that the key is definitely absent.
+//!
+//! ## Encoding
+//! - 4 bytes: number of hash functions (u32, little endian)
+//! - 8 bytes: number of bits (u64, little endian)
+//! - remaining bytes: the bit array
+//!
+//! Hashing uses FNV-1a (64 bit) with two offsets combined by double hashing,
+//! so that filters are portable across platforms and versions.
+use anyhow::{bail, Result};
+
+const HEADER_BYTES: usize = 12;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+const FNV_OFFSET_A: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_OFFSET_B: u64 = 0x8422_2325_cbf2_9ce4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BloomFilter {
+    num_hashes: u32,
+    num_bits: u64,
+    bits: Vec<u8>,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for the expected number of items
+    /// and the desired false positive rate (between 0 and 1, exclusive).
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Result<Self> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            bail!(
+                "Bloom filter false positive rate must be in (0, 1), got {}",
+                false_positive_rate
+            )
+        }
+        let n = expected_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(n * false_positive_rate.ln()) / (ln2 * ln2))
+            .ceil()
+            .max(8.0) as u64;
+        let num_hashes = ((num_bits as f64 / n) * ln2).round().max(1.0) as u32;
+        Ok(BloomFilter {
+            num_hashes,
+            num_bits,
+            bits: vec![0; num_bits.div_ceil(8) as usize],
+        })
+    }
+    pub fn insert(&mut self, item: &[u8]) {
+        let positions: Vec<u64> = self.bit_positions(item).collect();
+        for i in positions {
+            self.bits[(i / 8) as usize] |= 1 << (i % 8);
+        }
+    }
+    /// Returns false if the item is definitely absent.
+    pub fn contains(&self, item: &[u8]) -> bool {
+        self.bit_positions(item)
+            .all(|i| self.bits[(i / 8) as usize] & (1 << (i % 8)) != 0)
+    }
+    fn bit_positions(&self, item: &[u8]) -> impl Iterator<Item = u64> + '_ {
+        let h1 = fnv1a(FNV_OFFSET_A, item);
+        // Odd so that positions do not repeat for a power-of-two number of bits.
+        let h2 = fnv1a(FNV_OFFSET_B, item) | 1;
+        (0..self.num_hashes as u64)
+            .map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits)
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_BYTES + self.bits.len());
+        bytes.extend(self.num_hashes.to_le_bytes());
+        bytes.extend(self.num_bits.to_le_bytes());
+        bytes.extend(&self.bits);
+        bytes
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_BYTES {
+            bail!(
+                "Bloom filter has {} bytes, too short for header.",
+                bytes.len()
+            )
+        }
+        let num_hashes = u32::from_le_bytes(bytes[0..4].try_into()?);
+        let num_bits = u64::from_le_bytes(bytes[4..HEADER_BYTES].try_into()?);
+        let bits = bytes[HEADER_BYTES..].to_vec();
+        if num_hashes == 0 || num_bits == 0 || num_bits.div_ceil(8) != bits.len() as u64 {
+            bail!(
+                "Bloom filter header ({} hashes, {} bits) does not match {} bytes of data.",
+                num_hashes,
+                num_bits,
+                bits.len()
+            )
+        }
+        Ok(BloomFilter {
+            num_hashes,
+            num_bits,
+            bits,
+        })
+    }
+}
+
+fn fnv1a(offset: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(offset, |hash, b| (hash ^ *b as u64).wrapping_mul(FNV_PRIME))
+}
+
+#[test]
+fn bloom_no_false_negatives() {
+    let mut filter = BloomFilter::new(1000, 0.01).unwrap();
+    for i in 0..1000_u32 {
+        filter.insert(&i.to_be_bytes());
+    }
+    let decoded = BloomFilter::from_bytes(&filter.to_bytes()).unwrap();
+    assert_eq!(filter, decoded);
+    for i in 0..1000_u32 {
+        assert!(decoded.contains(&i.to_be_bytes()));
+    }
+    let false_positives = (1000..11_000_u32)
+        .filter(|i| decoded.contains(&i.to_be_bytes()))
+        .count();
+    // Expect around 100 (1%).
+    assert!(false_positives < 200, "{} false positives", false_positives);
+}
+
+#[test]
+fn bloom_rejects_bad_input() {
+    assert!(BloomFilter::new(10, 0.0).is_err());
+    assert!(BloomFilter::new(10, 1.0).is_err());
+    assert!(BloomFilter::from_bytes(&[0; 4]).is_err());
+    let mut bytes = BloomFilter::new(10, 0.1).unwrap().to_bytes();
+    bytes.pop();
+    assert!(BloomFilter::from_bytes(&bytes).is_err());
+}
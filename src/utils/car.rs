@@ -0,0 +1,404 @@
+//! Content addressable archives (CARv1), which hold many IPFS blocks in one file.
+//!
+//! A CAR file is a header (DAG-CBOR, listing the root CIDs) followed by blocks.
+//! Each section is prefixed by its length as an unsigned varint. Only the parts
+//! of DAG-CBOR needed for the header are implemented.
+//!
+//! See: <https://ipld.io/specs/transport/car/carv1/>
+use std::io::{ErrorKind, Read, Write};
+
+use anyhow::{anyhow, bail, Context, Result};
+use cid::{
+    multihash::{Code, MultihashDigest},
+    Cid,
+};
+
+/// Multicodec for raw bytes.
+pub(crate) const RAW: u64 = 0x55;
+/// CBOR tag for a CID.
+const CID_TAG: u64 = 42;
+/// Largest archive header accepted, in bytes. A header only lists the roots.
+const MAX_HEADER_LEN: u64 = 1 << 20;
+/// Largest block accepted, in bytes. Larger than any Chapter file.
+const MAX_BLOCK_LEN: u64 = 1 << 28;
+
+/// A block of data and its CID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CarBlock {
+    pub cid: Cid,
+    pub data: Vec<u8>,
+}
+
+impl CarBlock {
+    /// A block of raw bytes, with a CIDv1 of the sha256 of the bytes.
+    pub(crate) fn raw(data: Vec<u8>) -> Self {
+        CarBlock {
+            cid: Cid::new_v1(RAW, Code::Sha2_256.digest(&data)),
+            data,
+        }
+    }
+    /// Checks that the data has the hash in the CID.
+    pub(crate) fn verify(&self) -> Result<()> {
+        let code = Code::try_from(self.cid.hash().code())
+            .map_err(|e| anyhow!("Block {} has an unsupported hash: {}", self.cid, e))?;
+        if &code.digest(&self.data) != self.cid.hash() {
+            bail!("Block {} does not match its CID.", self.cid)
+        }
+        Ok(())
+    }
+}
+
+/// Writes a CARv1 file: a header with the root, then blocks written one at a time.
+pub(crate) struct CarWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CarWriter<W> {
+    /// Writes the header.
+    pub(crate) fn new(mut writer: W, root: &Cid) -> Result<Self> {
+        let header = header_bytes(root);
+        write_varint(&mut writer, header.len() as u64)?;
+        writer.write_all(&header)?;
+        Ok(CarWriter { writer })
+    }
+    pub(crate) fn write_block(&mut self, block: &CarBlock) -> Result<()> {
+        let cid = block.cid.to_bytes();
+        write_varint(&mut self.writer, (cid.len() + block.data.len()) as u64)?;
+        self.writer.write_all(&cid)?;
+        self.writer.write_all(&block.data)?;
+        Ok(())
+    }
+    /// Flushes the underlying writer.
+    pub(crate) fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads the blocks of a CARv1 file one at a time.
+pub(crate) struct CarReader<R: Read> {
+    reader: R,
+    pub roots: Vec<Cid>,
+}
+
+impl<R: Read> CarReader<R> {
+    /// Reads the header.
+    ///
+    /// ## Errors
+    /// If the header is not a CARv1 header, or is longer than [MAX_HEADER_LEN].
+    pub(crate) fn new(mut reader: R) -> Result<Self> {
+        let Some(len) = read_varint(&mut reader)? else {
+            bail!("The archive is empty.")
+        };
+        if len > MAX_HEADER_LEN {
+            bail!(
+                "The archive header is {} bytes, more than the maximum of {}.",
+                len,
+                MAX_HEADER_LEN
+            )
+        }
+        let header = read_section(&mut reader, len).context("The archive header is incomplete.")?;
+        let roots = parse_header(&header)?;
+        Ok(CarReader { reader, roots })
+    }
+    /// The next block, or None at the end of the archive.
+    ///
+    /// The block is not verified (see [CarBlock::verify]).
+    ///
+    /// ## Errors
+    /// If the archive ends part way through the block, or the block is longer
+    /// than [MAX_BLOCK_LEN].
+    pub(crate) fn next_block(&mut self) -> Result<Option<CarBlock>> {
+        let Some(len) = read_varint(&mut self.reader)? else {
+            return Ok(None);
+        };
+        if len > MAX_BLOCK_LEN {
+            bail!(
+                "An archive block is {} bytes, more than the maximum of {}.",
+                len,
+                MAX_BLOCK_LEN
+            )
+        }
+        let section = read_section(&mut self.reader, len)
+            .context("The archive ends part way through a block.")?;
+        let mut cursor = std::io::Cursor::new(&section);
+        let cid = Cid::read_bytes(&mut cursor).context("Invalid block CID")?;
+        let data = section[cursor.position() as usize..].to_vec();
+        Ok(Some(CarBlock { cid, data }))
+    }
+}
+
+/// DAG-CBOR for `{"roots": [root], "version": 1}`.
+fn header_bytes(root: &Cid) -> Vec<u8> {
+    let mut out = vec![];
+    cbor_head(&mut out, 5, 2);
+    cbor_head(&mut out, 3, 5);
+    out.extend_from_slice(b"roots");
+    cbor_head(&mut out, 4, 1);
+    cbor_head(&mut out, 6, CID_TAG);
+    let cid = root.to_bytes();
+    cbor_head(&mut out, 2, cid.len() as u64 + 1);
+    // Multibase prefix for raw binary.
+    out.push(0);
+    out.extend_from_slice(&cid);
+    cbor_head(&mut out, 3, 7);
+    out.extend_from_slice(b"version");
+    cbor_head(&mut out, 0, 1);
+    out
+}
+
+/// Reads the roots from a header, checking the version.
+fn parse_header(bytes: &[u8]) -> Result<Vec<Cid>> {
+    let mut pos = 0;
+    let (major, entries) = read_cbor_head(bytes, &mut pos)?;
+    if major != 5 {
+        bail!("The archive header is not a map.")
+    }
+    let mut roots = vec![];
+    let mut version = None;
+    for _ in 0..entries {
+        let key = read_cbor_text(bytes, &mut pos)?;
+        match key.as_str() {
+            "roots" => {
+                let (major, len) = read_cbor_head(bytes, &mut pos)?;
+                if major != 4 {
+                    bail!("The archive roots are not a list.")
+                }
+                for _ in 0..len {
+                    let (major, tag) = read_cbor_head(bytes, &mut pos)?;
+                    if major != 6 || tag != CID_TAG {
+                        bail!("An archive root is not a CID.")
+                    }
+                    let (major, len) = read_cbor_head(bytes, &mut pos)?;
+                    let cid_bytes = take_bytes(bytes, &mut pos, len)
+                        .filter(|_| major == 2)
+                        .ok_or_else(|| anyhow!("An archive root is not a CID."))?;
+                    match cid_bytes.split_first() {
+                        Some((0, cid)) => roots.push(Cid::try_from(cid)?),
+                        _ => bail!("An archive root is not a CID."),
+                    }
+                }
+            }
+            "version" => {
+                let (major, value) = read_cbor_head(bytes, &mut pos)?;
+                if major != 0 {
+                    bail!("The archive version is not a number.")
+                }
+                version = Some(value);
+            }
+            other => bail!("Unexpected key in archive header: {}", other),
+        }
+    }
+    if version != Some(1) {
+        bail!("Only CARv1 archives are supported (found {:?}).", version)
+    }
+    Ok(roots)
+}
+
+/// Writes a CBOR major type and argument (length or value).
+fn cbor_head(out: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    match arg {
+        0..=23 => out.push(major | arg as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, arg as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(arg as u16).to_be_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(arg as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&arg.to_be_bytes());
+        }
+    }
+}
+
+/// Reads a CBOR major type and argument.
+fn read_cbor_head(bytes: &[u8], pos: &mut usize) -> Result<(u8, u64)> {
+    let truncated = || anyhow!("The archive header is truncated.");
+    let first = *bytes.get(*pos).ok_or_else(truncated)?;
+    *pos += 1;
+    let size = match first & 0x1f {
+        n @ 0..=23 => return Ok((first >> 5, n as u64)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => bail!("Unsupported CBOR in the archive header."),
+    };
+    let arg_bytes = take_bytes(bytes, pos, size).ok_or_else(truncated)?;
+    let arg = arg_bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+    Ok((first >> 5, arg))
+}
+
+fn read_cbor_text(bytes: &[u8], pos: &mut usize) -> Result<String> {
+    let (major, len) = read_cbor_head(bytes, pos)?;
+    let text = take_bytes(bytes, pos, len)
+        .filter(|_| major == 3)
+        .ok_or_else(|| anyhow!("Expected text in the archive header."))?;
+    Ok(String::from_utf8(text.to_vec())?)
+}
+
+/// The next `len` bytes, advancing `pos` past them, or None if fewer remain.
+fn take_bytes<'a>(bytes: &'a [u8], pos: &mut usize, len: u64) -> Option<&'a [u8]> {
+    let end = pos.checked_add(usize::try_from(len).ok()?)?;
+    let taken = bytes.get(*pos..end)?;
+    *pos = end;
+    Some(taken)
+}
+
+/// Reads a section of a given length.
+///
+/// Memory is allocated as bytes are read, so a length that is longer than the
+/// input does not allocate the full length.
+fn read_section<R: Read>(reader: &mut R, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut section = vec![];
+    reader.by_ref().take(len).read_to_end(&mut section)?;
+    if (section.len() as u64) < len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(section)
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            writer.write_all(&[byte])?;
+            return Ok(());
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+/// Reads an unsigned varint, or None at the end of the input.
+fn read_varint<R: Read>(reader: &mut R) -> Result<Option<u64>> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8];
+        match reader.read_exact(&mut byte) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof && i == 0 => return Ok(None),
+            Err(e) => return Err(e).context("The archive ends part way through a length."),
+        }
+        if i == 9 && byte[0] > 1 {
+            // More than 64 bits.
+            break;
+        }
+        value |= ((byte[0] & 0x7f) as u64) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    bail!("Invalid length in the archive.")
+}
+
+#[test]
+fn car_round_trip() {
+    let root = CarBlock::raw(b"{\"manifest\": true}".to_vec());
+    let block = CarBlock::raw(vec![7u8; 300]);
+    let mut writer = CarWriter::new(vec![], &root.cid).unwrap();
+    writer.write_block(&root).unwrap();
+    writer.write_block(&block).unwrap();
+    let bytes = writer.finish().unwrap();
+
+    let mut reader = CarReader::new(bytes.as_slice()).unwrap();
+    assert_eq!(reader.roots, vec![root.cid]);
+    assert_eq!(reader.next_block().unwrap(), Some(root));
+    let read = reader.next_block().unwrap().unwrap();
+    read.verify().unwrap();
+    assert_eq!(read, block);
+    assert_eq!(reader.next_block().unwrap(), None);
+}
+
+#[test]
+fn car_header_layout() {
+    // A map of 2, "roots", a list of 1, tag 42, then 37 bytes (prefix and CID).
+    let root = CarBlock::raw(b"beep boop".to_vec()).cid;
+    assert_eq!(
+        root.to_string(),
+        "bafkreieq5jui4j25lacwomsqgjeswwl3y5zcdrresptwgmfylxo2depppq"
+    );
+    let header = header_bytes(&root);
+    assert_eq!(
+        &header[..9],
+        &[0xa2, 0x65, b'r', b'o', b'o', b't', b's', 0x81, 0xd8]
+    );
+    assert_eq!(&header[9..12], &[0x2a, 0x58, 0x25]);
+    assert_eq!(&header[header.len() - 9..], b"\x67version\x01");
+    assert_eq!(parse_header(&header).unwrap(), vec![root]);
+}
+
+#[test]
+fn corrupt_blocks_rejected() {
+    let mut block = CarBlock::raw(b"chapter".to_vec());
+    block.data = b"chaptex".to_vec();
+    assert!(block.verify().is_err());
+    let truncated = {
+        let mut writer = CarWriter::new(vec![], &block.cid).unwrap();
+        writer.write_block(&block).unwrap();
+        let mut bytes = writer.finish().unwrap();
+        bytes.pop();
+        bytes
+    };
+    let mut reader = CarReader::new(truncated.as_slice()).unwrap();
+    assert!(reader.next_block().is_err());
+}
+
+#[test]
+fn truncated_and_oversized_lengths_rejected() {
+    // Varints that end part way, or exceed 64 bits.
+    assert!(CarReader::new([0x80u8, 0x80].as_slice()).is_err());
+    assert!(CarReader::new([0xffu8; 11].as_slice()).is_err());
+    let mut too_long = [0xffu8; 10];
+    too_long[9] = 0x02;
+    assert!(CarReader::new(too_long.as_slice()).is_err());
+    // A header length over the maximum.
+    let mut bytes = vec![];
+    write_varint(&mut bytes, u64::MAX).unwrap();
+    assert!(CarReader::new(bytes.as_slice()).is_err());
+    // A header length past the end of the archive.
+    let mut bytes = vec![];
+    write_varint(&mut bytes, MAX_HEADER_LEN).unwrap();
+    bytes.extend_from_slice(&[0xa2; 10]);
+    assert!(CarReader::new(bytes.as_slice()).is_err());
+
+    let root = CarBlock::raw(b"manifest".to_vec());
+    let header = CarWriter::new(vec![], &root.cid).unwrap().finish().unwrap();
+    // A block length over the maximum.
+    let mut bytes = header.clone();
+    write_varint(&mut bytes, MAX_BLOCK_LEN + 1).unwrap();
+    let mut reader = CarReader::new(bytes.as_slice()).unwrap();
+    assert!(reader.next_block().is_err());
+    // A block length past the end of the archive.
+    let mut bytes = header;
+    write_varint(&mut bytes, MAX_BLOCK_LEN).unwrap();
+    bytes.extend_from_slice(&root.cid.to_bytes());
+    let mut reader = CarReader::new(bytes.as_slice()).unwrap();
+    assert!(reader.next_block().is_err());
+}
+
+#[test]
+fn header_lengths_past_the_end_rejected() {
+    // A root claiming u64::MAX bytes.
+    let mut header = vec![];
+    cbor_head(&mut header, 5, 1);
+    cbor_head(&mut header, 3, 5);
+    header.extend_from_slice(b"roots");
+    cbor_head(&mut header, 4, 1);
+    cbor_head(&mut header, 6, CID_TAG);
+    cbor_head(&mut header, 2, u64::MAX);
+    assert!(parse_header(&header).is_err());
+    // A key claiming more bytes than remain.
+    let mut header = vec![];
+    cbor_head(&mut header, 5, 1);
+    cbor_head(&mut header, 3, 64);
+    header.extend_from_slice(b"roots");
+    assert!(parse_header(&header).is_err());
+    // A truncated CBOR argument.
+    assert!(parse_header(&[0xbb, 0x01]).is_err());
+}
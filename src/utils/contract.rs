@@ -0,0 +1,335 @@
+//! Utilities for the metadata that the Solidity compiler appends to contract bytecode.
+//!
+//! The final two bytes of runtime bytecode are the big-endian length of a
+//! CBOR-encoded map that directly precedes them. The map may contain a hash of the
+//! contract metadata file and the compiler version.
+//!
+//! See: <https://docs.soliditylang.org/en/latest/metadata.html#encoding-of-the-metadata-hash-in-the-bytecode>
+//!
+//! Bytecode is often obtained from a node (`eth_getCode`) and cannot be trusted
+//! to be well formed, so these functions return errors rather than panic.
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+
+/// Number of bytes at the end of the bytecode that hold the metadata length.
+const LENGTH_SUFFIX_BYTES: usize = 2;
+
+/// A value in the metadata CBOR map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MetadataValue {
+    Bytes(Vec<u8>),
+    Text(String),
+    Bool(bool),
+    Uint(u64),
+}
+
+/// Returns the CBOR-encoded metadata that is appended to the bytecode.
+///
+/// ## Errors
+/// If the bytecode is too short to hold the length suffix, or if the
+/// length suffix describes more bytes than precede it.
+pub fn read_metadata(code: &[u8]) -> Result<&[u8]> {
+    let code_len = code.len();
+    if code_len < LENGTH_SUFFIX_BYTES {
+        bail!(
+            "Bytecode has {} byte(s), too short to contain a metadata length suffix.",
+            code_len
+        )
+    }
+    let suffix = &code[code_len - LENGTH_SUFFIX_BYTES..];
+    let metadata_len = u16::from_be_bytes([suffix[0], suffix[1]]) as usize;
+    let available = code_len - LENGTH_SUFFIX_BYTES;
+    if metadata_len == 0 || metadata_len > available {
+        bail!(
+            "Bytecode metadata length suffix ({} bytes) is invalid for {} bytes of preceding code.",
+            metadata_len,
+            available
+        )
+    }
+    Ok(&code[available - metadata_len..available])
+}
+
+/// Decodes the metadata CBOR map into (key, value) pairs.
+///
+/// Only the subset of CBOR that the compiler produces is supported
+/// (a map with text keys and byte string, text, boolean or integer values).
+pub fn decode_metadata(cbor: &[u8]) -> Result<Vec<(String, MetadataValue)>> {
+    let mut reader = CborReader { data: cbor, pos: 0 };
+    let (major, entries) = reader.header()?;
+    if major != MAJOR_MAP {
+        bail!("Metadata is not a CBOR map (major type {}).", major)
+    }
+    let mut pairs = vec![];
+    for _ in 0..entries {
+        let key = match reader.value()? {
+            MetadataValue::Text(t) => t,
+            other => bail!("Metadata map key is not text: {:?}", other),
+        };
+        let value = reader.value()?;
+        pairs.push((key, value));
+    }
+    if reader.pos != cbor.len() {
+        bail!(
+            "Metadata has {} unexpected trailing byte(s).",
+            cbor.len() - reader.pos
+        )
+    }
+    Ok(pairs)
+}
+
+/// Extracts the metadata from hex-encoded runtime bytecode.
+///
+/// Accepts bytecode with or without a "0x" prefix, as returned by a node.
+/// Never panics: malformed input of any kind results in an error.
+pub fn try_extract_metadata(bytecode: &str) -> Result<Vec<(String, MetadataValue)>> {
+    let code = hex::decode(bytecode.trim().trim_start_matches("0x"))
+        .map_err(|e| anyhow!("Bytecode is not valid hex: {}", e))?;
+    let cbor = read_metadata(&code)?;
+    decode_metadata(cbor)
+}
+
+/// Compiler-provided information about a deployed contract.
+///
+/// Both an IPFS and a Swarm hash may be present, which lets callers
+/// choose how to retrieve the metadata file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContractMetadata {
+    /// Content identifier of the metadata file on IPFS.
+    pub ipfs: Option<Cid>,
+    /// Hash of the metadata file on Swarm ("bzzr0" or "bzzr1").
+    pub swarm: Option<[u8; 32]>,
+    /// Compiler version. E.g., "0.8.17".
+    pub solc_version: Option<String>,
+    /// Whether experimental compiler features were used.
+    pub experimental: bool,
+}
+
+impl ContractMetadata {
+    /// Extracts the metadata from hex-encoded runtime bytecode.
+    pub fn from_runtime_bytecode(bytecode: &str) -> Result<Self> {
+        Self::from_pairs(try_extract_metadata(bytecode)?)
+    }
+    /// Interprets decoded (key, value) pairs. Unknown keys are ignored.
+    pub fn from_pairs(pairs: Vec<(String, MetadataValue)>) -> Result<Self> {
+        let mut metadata = ContractMetadata::default();
+        for (key, value) in pairs {
+            match (key.as_str(), value) {
+                ("ipfs", MetadataValue::Bytes(b)) => {
+                    let cid = Cid::try_from(b.as_slice())
+                        .map_err(|e| anyhow!("Metadata ipfs value is not a valid CID: {}", e))?;
+                    metadata.ipfs = Some(cid);
+                }
+                ("bzzr0" | "bzzr1", MetadataValue::Bytes(b)) => {
+                    let Ok(hash) = <[u8; 32]>::try_from(b.as_slice()) else {
+                        bail!("Metadata {} value has {} bytes, expected 32.", key, b.len())
+                    };
+                    metadata.swarm = Some(hash);
+                }
+                ("solc", MetadataValue::Bytes(b)) => {
+                    let [major, minor, patch] = b.as_slice() else {
+                        bail!("Metadata solc value has {} bytes, expected 3.", b.len())
+                    };
+                    metadata.solc_version = Some(format!("{}.{}.{}", major, minor, patch));
+                }
+                // Pre-release compilers store the full version string.
+                ("solc", MetadataValue::Text(t)) => metadata.solc_version = Some(t),
+                ("experimental", MetadataValue::Bool(b)) => metadata.experimental = b,
+                ("ipfs" | "bzzr0" | "bzzr1" | "solc" | "experimental", other) => {
+                    bail!("Metadata {} has unexpected value type: {:?}", key, other)
+                }
+                _ => {}
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+/// Returns the IPFS CID of the contract metadata file, if present.
+pub fn cid_from_runtime_bytecode(bytecode: &str) -> Result<Option<Cid>> {
+    Ok(ContractMetadata::from_runtime_bytecode(bytecode)?.ipfs)
+}
+
+const MAJOR_UINT: u8 = 0;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_SIMPLE: u8 = 7;
+
+/// Bounds-checked reader for the CBOR subset used in metadata.
+struct CborReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> CborReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Metadata truncated: wanted {} byte(s) at position {} of {}.",
+                    n,
+                    self.pos,
+                    self.data.len()
+                )
+            })?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    /// Reads the major type and its argument.
+    fn header(&mut self) -> Result<(u8, u64)> {
+        let initial = self.take(1)?[0];
+        let major = initial >> 5;
+        let info = initial & 0x1f;
+        if major == MAJOR_SIMPLE {
+            return Ok((major, info as u64));
+        }
+        let argument = match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into()?) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into()?) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into()?),
+            _ => bail!("Unsupported CBOR length encoding ({}).", info),
+        };
+        Ok((major, argument))
+    }
+    fn value(&mut self) -> Result<MetadataValue> {
+        let (major, argument) = self.header()?;
+        let value = match major {
+            MAJOR_UINT => MetadataValue::Uint(argument),
+            MAJOR_BYTES => MetadataValue::Bytes(self.take(usize::try_from(argument)?)?.to_vec()),
+            MAJOR_TEXT => {
+                let bytes = self.take(usize::try_from(argument)?)?;
+                MetadataValue::Text(String::from_utf8(bytes.to_vec())?)
+            }
+            MAJOR_SIMPLE => match argument {
+                20 => MetadataValue::Bool(false),
+                21 => MetadataValue::Bool(true),
+                other => bail!("Unsupported CBOR simple value ({}).", other),
+            },
+            other => bail!("Unsupported CBOR major type ({}).", other),
+        };
+        Ok(value)
+    }
+}
+
+/// Metadata from solc 0.8.17: {"ipfs": <34 bytes>, "solc": <0x000811>}.
+#[cfg(test)]
+const SAMPLE_METADATA: &str = "a2646970667358221220f7c6ae1a6b3b3a16b8b4a1b8b3e7e5c4d0e5d8e1d5a1c6d1b0e8b5c6a7f8e9d064736f6c63430008110033";
+
+#[test]
+fn reads_sample_metadata() {
+    let code = format!("0x6080604052{}", SAMPLE_METADATA);
+    let pairs = try_extract_metadata(&code).unwrap();
+    assert_eq!(pairs.len(), 2);
+    assert_eq!(pairs[0].0, "ipfs");
+    assert!(matches!(&pairs[0].1, MetadataValue::Bytes(b) if b.len() == 34));
+    assert_eq!(
+        pairs[1],
+        ("solc".to_string(), MetadataValue::Bytes(vec![0, 8, 17]))
+    );
+}
+
+#[test]
+fn structured_sample_metadata() {
+    let code = format!("0x6080604052{}", SAMPLE_METADATA);
+    let metadata = ContractMetadata::from_runtime_bytecode(&code).unwrap();
+    assert_eq!(metadata.solc_version.as_deref(), Some("0.8.17"));
+    assert!(metadata.ipfs.is_some());
+    assert_eq!(metadata.ipfs, cid_from_runtime_bytecode(&code).unwrap());
+    assert!(metadata.swarm.is_none());
+    assert!(!metadata.experimental);
+}
+
+#[test]
+fn structured_swarm_metadata() {
+    // {"bzzr1": <32 bytes>, "experimental": true, "solc": "0.6.0-nightly"}
+    let mut cbor = vec![0xa3, 0x65];
+    cbor.extend(b"bzzr1");
+    cbor.extend([0x58, 0x20]);
+    cbor.extend([0x11; 32]);
+    cbor.push(0x6c);
+    cbor.extend(b"experimental");
+    cbor.push(0xf5);
+    cbor.push(0x64);
+    cbor.extend(b"solc");
+    cbor.push(0x6d);
+    cbor.extend(b"0.6.0-nightly");
+    let metadata = ContractMetadata::from_pairs(decode_metadata(&cbor).unwrap()).unwrap();
+    assert_eq!(metadata.swarm, Some([0x11; 32]));
+    assert_eq!(metadata.solc_version.as_deref(), Some("0.6.0-nightly"));
+    assert!(metadata.experimental);
+    assert!(metadata.ipfs.is_none());
+}
+
+#[test]
+fn structured_bzzr0_and_experimental_ipfs_metadata() {
+    // solc 0.5.x: {"bzzr0": <32 bytes>, "solc": <0x000511>}
+    let mut cbor = vec![0xa2, 0x65];
+    cbor.extend(b"bzzr0");
+    cbor.extend([0x58, 0x20]);
+    cbor.extend([0x22; 32]);
+    cbor.push(0x64);
+    cbor.extend(b"solc");
+    cbor.extend([0x43, 0x00, 0x05, 0x11]);
+    let code = format!("0x6080604052{}{:04x}", hex::encode(&cbor), cbor.len());
+    let metadata = ContractMetadata::from_runtime_bytecode(&code).unwrap();
+    assert_eq!(metadata.swarm, Some([0x22; 32]));
+    assert_eq!(metadata.solc_version.as_deref(), Some("0.5.17"));
+    assert!(metadata.ipfs.is_none());
+    assert!(!metadata.experimental);
+
+    // {"ipfs": <34 bytes>, "experimental": true, "solc": <0x000811>}
+    let sample = hex::decode(SAMPLE_METADATA).unwrap();
+    let sample_cbor = &sample[..sample.len() - 2];
+    let mut cbor = vec![0xa3];
+    cbor.extend(&sample_cbor[1..]);
+    cbor.push(0x6c);
+    cbor.extend(b"experimental");
+    cbor.push(0xf5);
+    let code = format!("0x6080604052{}{:04x}", hex::encode(&cbor), cbor.len());
+    let metadata = ContractMetadata::from_runtime_bytecode(&code).unwrap();
+    assert!(metadata.ipfs.is_some());
+    assert_eq!(metadata.solc_version.as_deref(), Some("0.8.17"));
+    assert!(metadata.experimental);
+    assert!(metadata.swarm.is_none());
+}
+
+#[test]
+fn rejects_malformed_bytecode() {
+    assert!(read_metadata(&[]).is_err());
+    assert!(read_metadata(&[0x33]).is_err());
+    // Length suffix larger than the code.
+    assert!(read_metadata(&[0x60, 0x80, 0xff, 0xff]).is_err());
+    // Length suffix of zero.
+    assert!(read_metadata(&[0x60, 0x80, 0x00, 0x00]).is_err());
+    assert!(try_extract_metadata("0xzz").is_err());
+    // Truncated byte string inside the map.
+    assert!(try_extract_metadata("0xa1646970667358220003").is_err());
+}
+
+#[test]
+fn arbitrary_bytes_never_panic() {
+    // Deterministic xorshift so that failures are reproducible.
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for _ in 0..10_000 {
+        let len = (next() % 64) as usize;
+        let mut code: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+        // Often point the suffix back into the random bytes.
+        if len >= 2 && next() % 2 == 0 {
+            let metadata_len = (next() % len as u64) as u16;
+            code[len - 2..].copy_from_slice(&metadata_len.to_be_bytes());
+        }
+        let _ = try_extract_metadata(&hex::encode(&code));
+    }
+}
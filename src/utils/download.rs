@@ -1,15 +1,36 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, bail, Ok, Result};
 use futures_util::{future::join_all, stream::StreamExt};
 use log::{debug, info};
-use reqwest::Url;
-use tokio::{fs::File, io::AsyncWriteExt};
+use reqwest::{
+    header::{ACCEPT_ENCODING, RANGE},
+    Client, StatusCode, Url,
+};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    runtime::{Handle, Runtime, RuntimeFlavor},
+    task::block_in_place,
+};
 
 /**
 Downloads files to a specified directory concurrently.
 
 The urls and corresponding filenames must be in the correct order.
+
+Responses may be compressed in transit (gzip or deflate), which is undone
+transparently. A file is written as `{filename}.partial` and moved into place
+once complete. A partial file left by an interrupted download is resumed with
+an HTTP Range request, if the server supports it.
+
+## Errors
+If any file could not be downloaded. The other files are still downloaded.
+
 ## Example
 The following can be executed within a non-async function.
 ```ignore
@@ -36,7 +57,7 @@ rt.block_on(download_files(vec![task]))?;
 ```
 */
 pub async fn download_files(urls_dirs_filenames: Vec<DownloadTask>) -> Result<()> {
-    let client = reqwest::Client::new();
+    let client = Client::new();
     let mut download_handles = vec![];
 
     for task in urls_dirs_filenames {
@@ -49,22 +70,147 @@ pub async fn download_files(urls_dirs_filenames: Vec<DownloadTask>) -> Result<()
         };
         debug!("Downloading file {} from: {}", &task.filename, task.url);
         let client = client.clone();
-        let handle = tokio::spawn(async move {
-            let mut file = File::create(filepath).await?;
-            let mut stream = client.get(task.url).send().await?.bytes_stream();
-            while let Some(result) = stream.next().await {
-                let chunk = result?;
-                file.write_all(&chunk).await?;
-            }
-            file.flush().await?;
-            Ok(())
-        });
+        let handle =
+            tokio::spawn(async move { download_resumable(&client, task.url, &filepath).await });
         download_handles.push(handle);
     }
-    join_all(download_handles).await;
+    let mut failed = vec![];
+    for result in join_all(download_handles).await {
+        if let Err(e) = result.map_err(|e| anyhow!(e)).and_then(|r| r) {
+            failed.push(format!("{:#}", e));
+        }
+    }
+    if !failed.is_empty() {
+        bail!("{} downloads failed: {}", failed.len(), failed.join("; "))
+    }
     Ok(())
 }
 
+/// Downloads a file via `{filename}.partial`, resuming from an existing partial file.
+///
+/// The remaining bytes are requested with a Range header. A server that does not
+/// support ranges sends the whole file, which replaces the partial file.
+async fn download_resumable(client: &Client, url: Url, filepath: &Path) -> Result<()> {
+    let partial = partial_path(filepath);
+    loop {
+        let existing = tokio::fs::metadata(&partial)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+        let mut request = client.get(url.clone());
+        if existing > 0 {
+            // Byte offsets refer to the uncompressed file.
+            request = request
+                .header(RANGE, format!("bytes={}-", existing))
+                .header(ACCEPT_ENCODING, "identity");
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if existing > 0 && status == StatusCode::RANGE_NOT_SATISFIABLE {
+            // The partial file is not a prefix of the file. Start again.
+            debug!(
+                "Range not satisfiable for {:?}, downloading in full.",
+                filepath
+            );
+            tokio::fs::remove_file(&partial).await?;
+            continue;
+        }
+        if !status.is_success() {
+            bail!("Request to {} failed with status {}", url, status)
+        }
+        let mut file = if existing > 0 && status == StatusCode::PARTIAL_CONTENT {
+            debug!("Resuming {:?} from byte {}.", filepath, existing);
+            OpenOptions::new().append(true).open(&partial).await?
+        } else {
+            File::create(&partial).await?
+        };
+        let mut stream = response.bytes_stream();
+        while let Some(result) = stream.next().await {
+            let chunk = result?;
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        tokio::fs::rename(&partial, filepath).await?;
+        return Ok(());
+    }
+}
+
+/// The path that a file is downloaded to before it is complete.
+fn partial_path(filepath: &Path) -> PathBuf {
+    let mut name = filepath.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    filepath.with_file_name(name)
+}
+
+/// Downloads a single file into memory.
+///
+/// ## Errors
+/// If the server does not respond with a success status.
+pub async fn download_bytes(url: Url) -> Result<Vec<u8>> {
+    debug!("Downloading from: {}", url);
+    let response = reqwest::get(url.clone()).await?;
+    if !response.status().is_success() {
+        bail!(
+            "Request to {} failed with status {}",
+            url,
+            response.status()
+        )
+    }
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Downloads a single file into memory, unless it is larger than `max_bytes`.
+///
+/// Returns None once the file is known to be larger, from the Content-Length
+/// header or from the bytes received, without reading the rest.
+///
+/// ## Errors
+/// If the server does not respond with a success status.
+pub async fn download_bytes_within(url: Url, max_bytes: u64) -> Result<Option<Vec<u8>>> {
+    debug!("Downloading at most {} bytes from: {}", max_bytes, url);
+    let mut response = reqwest::get(url.clone()).await?;
+    if !response.status().is_success() {
+        bail!(
+            "Request to {} failed with status {}",
+            url,
+            response.status()
+        )
+    }
+    if response.content_length().is_some_and(|len| len > max_bytes) {
+        return Ok(None);
+    }
+    let mut bytes: Vec<u8> = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() as u64 > max_bytes {
+            return Ok(None);
+        }
+    }
+    Ok(Some(bytes))
+}
+
+/// Runs a future to completion from synchronous code, such as a download
+/// started by a query.
+///
+/// Within a multi-threaded tokio runtime, the calling worker thread is given
+/// over to blocking while the future runs (see [block_in_place]). Outside of a
+/// runtime, a new runtime is started.
+///
+/// ## Errors
+/// If called within a current-thread tokio runtime, which cannot block.
+pub(crate) fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    let Result::Ok(handle) = Handle::try_current() else {
+        return Ok(Runtime::new()?.block_on(future));
+    };
+    if handle.runtime_flavor() != RuntimeFlavor::MultiThread {
+        bail!(
+            "Cannot wait for a download within a current-thread tokio runtime. \
+            Use a multi-threaded runtime, or call from tokio::task::spawn_blocking."
+        )
+    }
+    Ok(block_in_place(|| handle.block_on(future)))
+}
+
 /// Details of a file to be downloaded and stored locally.
 ///
 /// Used for coordinating concurrent downloads.
@@ -75,3 +221,100 @@ pub struct DownloadTask {
     /// Name of the file.
     pub filename: String,
 }
+
+/// Serves `body` for one request, honouring a Range header if `ranges` is true.
+#[cfg(test)]
+fn serve_with_ranges(body: &'static [u8], ranges: bool) -> Url {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/file", listener.local_addr().unwrap())).unwrap();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = vec![];
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        let request = String::from_utf8_lossy(&request).to_lowercase();
+        let start = request
+            .lines()
+            .find_map(|line| line.strip_prefix("range: bytes="))
+            .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok())
+            .filter(|_| ranges);
+        let header = match start {
+            Some(start) => format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                start,
+                body.len() - 1,
+                body.len(),
+                body.len() - start
+            ),
+            None => format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            ),
+        };
+        stream.write_all(header.as_bytes()).unwrap();
+        stream.write_all(&body[start.unwrap_or(0)..]).unwrap();
+    });
+    url
+}
+
+#[test]
+fn resumes_partial_download() {
+    let dir = std::env::temp_dir().join("min_know_test_download_resume");
+    fs::create_dir_all(&dir).unwrap();
+    let body: &'static [u8] = b"0123456789abcdef";
+    for ranges in [true, false] {
+        let filename = format!("file_{}", ranges);
+        let filepath = dir.join(&filename);
+        let _ = fs::remove_file(&filepath);
+        // An interrupted download left a prefix of the file.
+        fs::write(partial_path(&filepath), &body[..6]).unwrap();
+        let task = DownloadTask {
+            url: serve_with_ranges(body, ranges),
+            dest_dir: dir.clone(),
+            filename,
+        };
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(download_files(vec![task]))
+            .unwrap();
+        assert_eq!(fs::read(&filepath).unwrap(), body);
+        assert!(!partial_path(&filepath).exists());
+    }
+}
+
+#[test]
+fn failed_download_is_reported() {
+    let dir = std::env::temp_dir().join("min_know_test_download_failed");
+    let _ = fs::remove_dir_all(&dir);
+    let task = DownloadTask {
+        url: Url::parse("http://127.0.0.1:1/file").unwrap(),
+        dest_dir: dir.clone(),
+        filename: "file".to_string(),
+    };
+    let result = tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(download_files(vec![task]));
+    assert!(result.is_err());
+    assert!(!dir.join("file").exists());
+}
+
+#[test]
+fn block_on_within_runtimes() {
+    use tokio::runtime::Builder;
+
+    assert_eq!(block_on(async { 1 }).unwrap(), 1);
+    // A query made by async code, on a multi-threaded runtime.
+    let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+    let spawned = rt.block_on(async { tokio::spawn(async { block_on(async { 2 }) }).await });
+    assert_eq!(spawned.unwrap().unwrap(), 2);
+    // A current-thread runtime cannot block.
+    let rt = Builder::new_current_thread().build().unwrap();
+    assert!(rt.block_on(async { block_on(async { 3 }) }).is_err());
+}
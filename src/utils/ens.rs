@@ -0,0 +1,155 @@
+//! Resolution of ENS names (e.g., "vitalik.eth") to addresses.
+//!
+//! Requires the `net` feature. Names are resolved with `eth_call` requests to an
+//! Ethereum JSON-RPC endpoint: first the resolver for the name is obtained from the
+//! ENS registry, then the address is obtained from the resolver.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Result};
+use log::debug;
+use reqwest::Url;
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+use tokio::runtime::Runtime;
+
+/// The ENS registry, at the same address on mainnet and testnets.
+const ENS_REGISTRY: &str = "0x00000000000C2E074eC69A0dFb2997BA6C7d2e1e";
+/// Function selector for `resolver(bytes32)`.
+const RESOLVER_SELECTOR: &str = "0178b8bf";
+/// Function selector for `addr(bytes32)`.
+const ADDR_SELECTOR: &str = "3b3b57de";
+
+/// Whether a raw key looks like an ENS name rather than a hex string.
+pub fn is_ens_name(raw_key: &str) -> bool {
+    raw_key.contains('.') && !raw_key.starts_with("0x")
+}
+
+/// Computes the ENS namehash of a name.
+///
+/// Names are lowercased. Other normalization (UTS-46) is not performed.
+pub fn namehash(name: &str) -> [u8; 32] {
+    let mut node = [0u8; 32];
+    if name.is_empty() {
+        return node;
+    }
+    for label in name.to_lowercase().rsplit('.') {
+        let label_hash = Keccak256::digest(label.as_bytes());
+        let mut hasher = Keccak256::new();
+        hasher.update(node);
+        hasher.update(label_hash);
+        node = hasher.finalize().into();
+    }
+    node
+}
+
+/// Resolved names, shared between clones of a database.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct EnsCache(Arc<Mutex<HashMap<String, String>>>);
+
+impl PartialEq for EnsCache {
+    /// The cache does not affect the identity of a database.
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl EnsCache {
+    /// Gets the address (0x-prefixed hex) for a name, using the endpoint if the
+    /// name has not been resolved before.
+    pub(crate) fn resolve(&self, name: &str, rpc_url: &str) -> Result<String> {
+        let name = name.to_lowercase();
+        let mut cache = self
+            .0
+            .lock()
+            .map_err(|_| anyhow!("ENS cache lock poisoned."))?;
+        if let Some(address) = cache.get(&name) {
+            return Ok(address.clone());
+        }
+        let address = resolve_name(&name, rpc_url)?;
+        debug!("Resolved ENS name {} to {}.", name, address);
+        cache.insert(name, address.clone());
+        Ok(address)
+    }
+}
+
+/// Resolves a name to an address (0x-prefixed hex).
+///
+/// ## Errors
+/// If the name has no resolver, or the resolver has no address for the name.
+fn resolve_name(name: &str, rpc_url: &str) -> Result<String> {
+    let url = Url::parse(rpc_url)?;
+    let node = hex::encode(namehash(name));
+    let rt = Runtime::new()?;
+    let resolver = rt.block_on(eth_call_address(
+        &url,
+        ENS_REGISTRY,
+        &format!("0x{}{}", RESOLVER_SELECTOR, node),
+    ))?;
+    let Some(resolver) = resolver else {
+        bail!("ENS name {} has no resolver.", name)
+    };
+    let address = rt.block_on(eth_call_address(
+        &url,
+        &resolver,
+        &format!("0x{}{}", ADDR_SELECTOR, node),
+    ))?;
+    address.ok_or_else(|| anyhow!("ENS name {} does not resolve to an address.", name))
+}
+
+/// Makes an `eth_call` that returns a single address.
+///
+/// Returns None for the zero address.
+async fn eth_call_address(url: &Url, to: &str, data: &str) -> Result<Option<String>> {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_call",
+        "params": [{"to": to, "data": data}, "latest"],
+    });
+    let response = reqwest::Client::new()
+        .post(url.clone())
+        .header("Content-Type", "application/json")
+        .body(request.to_string())
+        .send()
+        .await?
+        .text()
+        .await?;
+    let response: Value = serde_json::from_str(&response)?;
+    if let Some(error) = response.get("error") {
+        bail!("eth_call to {} failed: {}", to, error)
+    }
+    let Some(result) = response.get("result").and_then(Value::as_str) else {
+        bail!("eth_call to {} returned no result: {}", to, response)
+    };
+    let word = hex::decode(result.trim_start_matches("0x"))?;
+    if word.len() != 32 {
+        bail!(
+            "eth_call to {} returned {} bytes, expected 32.",
+            to,
+            word.len()
+        )
+    }
+    if word.iter().all(|b| *b == 0) {
+        return Ok(None);
+    }
+    Ok(Some(format!("0x{}", hex::encode(&word[12..]))))
+}
+
+#[test]
+fn namehash_matches_known_values() {
+    assert_eq!(namehash(""), [0u8; 32]);
+    assert_eq!(
+        hex::encode(namehash("eth")),
+        "93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae"
+    );
+    assert_eq!(
+        hex::encode(namehash("foo.eth")),
+        "de9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"
+    );
+    assert_eq!(namehash("Foo.ETH"), namehash("foo.eth"));
+    assert!(is_ens_name("vitalik.eth"));
+    assert!(!is_ens_name("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae"));
+}
@@ -0,0 +1,161 @@
+//! IPFS gateways used to fetch content by CID.
+//!
+//! Gateways serve content in one of two styles:
+//! - Path: `https://<gateway>/ipfs/<cid>`
+//! - Subdomain: `https://<cid>.ipfs.<gateway>/`
+//!
+//! Subdomain gateways require a case-insensitive CID, so CIDv0 strings
+//! are converted to CIDv1 (base32) for those gateways.
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use reqwest::Url;
+
+/// How a gateway expects the CID to be placed in the URL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GatewayStyle {
+    /// `https://<gateway>/ipfs/<cid>`
+    Path,
+    /// `https://<cid>.ipfs.<gateway>/`
+    Subdomain,
+}
+
+/// A validated IPFS gateway.
+///
+/// ## Example
+/// ```
+/// use min_know::utils::gateway::Gateway;
+///
+/// let cid = "QmY6LjJ1HExi2TgHshc56ecPdVSNaWrzFbWq9sahHFrNoM";
+/// let path = Gateway::new("http://127.0.0.1:8080")?;
+/// assert_eq!(
+///     path.fetch_url(cid)?.as_str(),
+///     "http://127.0.0.1:8080/ipfs/QmY6LjJ1HExi2TgHshc56ecPdVSNaWrzFbWq9sahHFrNoM"
+/// );
+/// let subdomain = Gateway::subdomain("https://dweb.link")?;
+/// assert_eq!(
+///     subdomain.fetch_url(cid)?.as_str(),
+///     "https://bafybeieq5jui4j25lacwomsqgjeswwl3y5zcdrresptwgmfylxo2depppq.ipfs.dweb.link/"
+/// );
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gateway {
+    base: Url,
+    style: GatewayStyle,
+}
+
+impl Gateway {
+    /// Creates a path style gateway.
+    ///
+    /// The URL may be given with or without the trailing "/ipfs/" segment.
+    pub fn new(url: &str) -> Result<Self> {
+        let mut base = parse_base(url)?;
+        let path = base.path().trim_end_matches('/').to_string();
+        let path = if path.ends_with("/ipfs") {
+            format!("{}/", path)
+        } else {
+            format!("{}/ipfs/", path)
+        };
+        base.set_path(&path);
+        Ok(Gateway {
+            base,
+            style: GatewayStyle::Path,
+        })
+    }
+    /// Creates a subdomain style gateway from its root URL. E.g., "https://dweb.link".
+    pub fn subdomain(url: &str) -> Result<Self> {
+        let base = parse_base(url)?;
+        if base.domain().is_none() {
+            bail!("Subdomain gateway requires a domain name, got: {}", url)
+        }
+        if base.path() != "/" {
+            bail!("Subdomain gateway must not have a path, got: {}", url)
+        }
+        Ok(Gateway {
+            base,
+            style: GatewayStyle::Subdomain,
+        })
+    }
+    pub fn style(&self) -> GatewayStyle {
+        self.style
+    }
+    /// Returns the URL to fetch the given CID from.
+    pub fn fetch_url(&self, cid: &str) -> Result<Url> {
+        let parsed = Cid::from_str(cid).map_err(|e| anyhow!("Invalid CID ({}): {}", cid, e))?;
+        match self.style {
+            GatewayStyle::Path => Ok(self.base.join(&parsed.to_string())?),
+            GatewayStyle::Subdomain => {
+                let v1 = Cid::new_v1(parsed.codec(), *parsed.hash());
+                let domain = self
+                    .base
+                    .domain()
+                    .ok_or_else(|| anyhow!("Gateway has no domain: {}", self.base))?;
+                let mut url = self.base.clone();
+                url.set_host(Some(&format!("{}.ipfs.{}", v1, domain)))?;
+                Ok(url)
+            }
+        }
+    }
+}
+
+impl FromStr for Gateway {
+    type Err = anyhow::Error;
+    /// Parses a path style gateway.
+    fn from_str(s: &str) -> Result<Self> {
+        Gateway::new(s)
+    }
+}
+
+impl Display for Gateway {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:?} style)", self.base, self.style)
+    }
+}
+
+/// Parses and checks a URL that is suitable as a gateway base.
+fn parse_base(url: &str) -> Result<Url> {
+    let base =
+        Url::parse(url.trim()).map_err(|e| anyhow!("Invalid gateway URL ({}): {}", url, e))?;
+    if !matches!(base.scheme(), "http" | "https") {
+        bail!("Gateway URL must use http or https, got: {}", url)
+    }
+    if base.host().is_none() {
+        bail!("Gateway URL has no host: {}", url)
+    }
+    if base.query().is_some() || base.fragment().is_some() {
+        bail!("Gateway URL must not have a query or fragment: {}", url)
+    }
+    Ok(base)
+}
+
+#[test]
+fn path_gateway_normalized() {
+    let cid = "QmY6LjJ1HExi2TgHshc56ecPdVSNaWrzFbWq9sahHFrNoM";
+    let expected = format!("https://ipfs.io/ipfs/{}", cid);
+    for url in [
+        "https://ipfs.io",
+        "https://ipfs.io/",
+        "https://ipfs.io/ipfs",
+        "https://ipfs.io/ipfs/",
+    ] {
+        assert_eq!(
+            Gateway::new(url).unwrap().fetch_url(cid).unwrap().as_str(),
+            expected
+        );
+    }
+}
+
+#[test]
+fn invalid_gateways_rejected() {
+    assert!(Gateway::new("ftp://ipfs.io").is_err());
+    assert!(Gateway::new("not a url").is_err());
+    assert!(Gateway::new("https://ipfs.io/?a=b").is_err());
+    assert!(Gateway::subdomain("http://127.0.0.1:8080").is_err());
+    assert!(Gateway::subdomain("https://dweb.link/ipfs").is_err());
+    assert!(Gateway::new("https://ipfs.io")
+        .unwrap()
+        .fetch_url("nonsense")
+        .is_err());
+}
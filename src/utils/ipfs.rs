@@ -1,8 +1,18 @@
-use anyhow::Result;
+use std::{
+    fs::File,
+    io::{ErrorKind, Read},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
 use cid::{
     multihash::{Code, MultihashDigest},
     Cid,
 };
+use sha2::{Digest, Sha256};
+
+/// Size of the buffer used to hash files if none is configured (1 MiB).
+pub const DEFAULT_HASH_BUFFER_BYTES: usize = 1 << 20;
 
 /// Computes the CIDv0 for the given bytes, returning as a String.
 pub fn cid_v0_string_from_bytes(bytes: &[u8]) -> Result<String> {
@@ -11,6 +21,40 @@ pub fn cid_v0_string_from_bytes(bytes: &[u8]) -> Result<String> {
     Ok(cid.to_string())
 }
 
+/// Computes the CIDv0 of a file, returning it as a String with the number of
+/// bytes read.
+///
+/// The file is read in chunks of `buffer_bytes`, rather than all at once, so
+/// that large files are not held in memory.
+pub fn cid_v0_string_from_file(path: &Path, buffer_bytes: usize) -> Result<(String, u64)> {
+    let file = File::open(path).with_context(|| format!("Unable to read file {:?}", path))?;
+    cid_v0_string_from_reader(file, buffer_bytes)
+        .with_context(|| format!("Unable to read file {:?}", path))
+}
+
+/// Computes the CIDv0 of everything read from the reader, in chunks of
+/// `buffer_bytes` (at least one byte).
+pub fn cid_v0_string_from_reader(
+    mut reader: impl Read,
+    buffer_bytes: usize,
+) -> Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; buffer_bytes.max(1)];
+    let mut total: u64 = 0;
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        hasher.update(&buffer[..read]);
+        total += read as u64;
+    }
+    let cid = Cid::new_v0(Code::Sha2_256.wrap(&hasher.finalize())?)?;
+    Ok((cid.to_string(), total))
+}
+
 /// Computes the CIDv1 for the given bytes.
 #[allow(dead_code)]
 pub fn cid_v1_from_bytes(bytes: &[u8]) -> Result<Vec<u8>> {
@@ -50,3 +94,14 @@ fn str_to_cidv1() {
         "bafkreieq5jui4j25lacwomsqgjeswwl3y5zcdrresptwgmfylxo2depppq"
     );
 }
+
+#[test]
+fn chunked_cid_matches_whole() {
+    let bytes: Vec<u8> = (0..10_000u32).map(|n| (n % 251) as u8).collect();
+    let expected = cid_v0_string_from_bytes(&bytes).unwrap();
+    for buffer_bytes in [0, 1, 7, 4096, DEFAULT_HASH_BUFFER_BYTES] {
+        let (cid, read) = cid_v0_string_from_reader(&bytes[..], buffer_bytes).unwrap();
+        assert_eq!(cid, expected);
+        assert_eq!(read, bytes.len() as u64);
+    }
+}
@@ -0,0 +1,102 @@
+//! Merkle trees over the Records of a Chapter, in the style of SSZ list merkleization.
+//!
+//! Leaves are the SHA-256 of each serialized Record. Leaves are padded with zero
+//! hashes to a power of two, hashed pairwise to a single node and the number of
+//! leaves is then mixed in (as a 32 byte little endian integer).
+use sha2::{Digest, Sha256};
+
+pub type Node = [u8; 32];
+
+/// Hash of the bytes of a single leaf.
+pub fn leaf(bytes: &[u8]) -> Node {
+    Sha256::digest(bytes).into()
+}
+
+fn hash_pair(left: &Node, right: &Node) -> Node {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn mix_in_length(root: &Node, length: u64) -> Node {
+    let mut length_node = [0u8; 32];
+    length_node[..8].copy_from_slice(&length.to_le_bytes());
+    hash_pair(root, &length_node)
+}
+
+/// Leaves padded with zero hashes to the next power of two (minimum one leaf).
+fn padded(leaves: &[Node]) -> Vec<Node> {
+    let width = leaves.len().max(1).next_power_of_two();
+    let mut layer = leaves.to_vec();
+    layer.resize(width, [0u8; 32]);
+    layer
+}
+
+fn next_layer(layer: &[Node]) -> Vec<Node> {
+    layer
+        .chunks(2)
+        .map(|pair| hash_pair(&pair[0], &pair[1]))
+        .collect()
+}
+
+/// Root of the tree, with the number of leaves mixed in.
+pub fn root(leaves: &[Node]) -> Node {
+    let mut layer = padded(leaves);
+    while layer.len() > 1 {
+        layer = next_layer(&layer);
+    }
+    mix_in_length(&layer[0], leaves.len() as u64)
+}
+
+/// Sibling nodes from the leaf at the index up to (excluding) the root.
+///
+/// Returns None if the index is out of range.
+pub fn branch(leaves: &[Node], index: usize) -> Option<Vec<Node>> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut layer = padded(leaves);
+    let mut position = index;
+    let mut siblings = vec![];
+    while layer.len() > 1 {
+        siblings.push(layer[position ^ 1]);
+        layer = next_layer(&layer);
+        position /= 2;
+    }
+    Some(siblings)
+}
+
+/// Root computed from a leaf and its branch.
+pub fn root_from_branch(leaf: &Node, index: usize, branch: &[Node], length: u64) -> Node {
+    let mut node = *leaf;
+    let mut position = index;
+    for sibling in branch {
+        node = match position % 2 {
+            0 => hash_pair(&node, sibling),
+            _ => hash_pair(sibling, &node),
+        };
+        position /= 2;
+    }
+    mix_in_length(&node, length)
+}
+
+#[test]
+fn branch_reproduces_root() {
+    let leaves: Vec<Node> = (0..5u8).map(|i| leaf(&[i])).collect();
+    let expected = root(&leaves);
+    for (index, l) in leaves.iter().enumerate() {
+        let siblings = branch(&leaves, index).unwrap();
+        assert_eq!(siblings.len(), 3);
+        assert_eq!(root_from_branch(l, index, &siblings, 5), expected);
+        assert_ne!(root_from_branch(l, index, &siblings, 4), expected);
+    }
+    assert!(branch(&leaves, 5).is_none());
+}
+
+#[test]
+fn single_leaf_has_empty_branch() {
+    let leaves = vec![leaf(b"only")];
+    assert!(branch(&leaves, 0).unwrap().is_empty());
+    assert_eq!(root_from_branch(&leaves[0], 0, &[], 1), root(&leaves));
+}
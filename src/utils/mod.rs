@@ -1,5 +1,18 @@
+pub mod bloom;
+pub(crate) mod car;
+pub mod contract;
+#[cfg(feature = "net")]
 pub(crate) mod download;
+#[cfg(feature = "net")]
+pub mod ens;
+#[cfg(feature = "net")]
+pub mod gateway;
 pub(crate) mod ipfs;
+pub mod merkle;
+pub mod parallelism;
+#[cfg(feature = "publisher")]
+pub(crate) mod progress;
 pub(crate) mod string;
+#[cfg(feature = "publisher")]
 pub(crate) mod system;
 pub mod unchained;
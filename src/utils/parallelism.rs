@@ -0,0 +1,123 @@
+//! Limits on the resources used when forming Chapters.
+//!
+//! By default, Chapters are formed on all cores. A publisher running
+//! alongside other services can set a [Parallelism] to use fewer threads,
+//! and fewer still for reading raw data and writing Chapter files.
+#[cfg(feature = "publisher")]
+use std::sync::{Condvar, Mutex};
+
+#[cfg(feature = "publisher")]
+use anyhow::Result;
+#[cfg(feature = "publisher")]
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+
+/// The number of threads used to form Chapters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Hash, Deserialize, Serialize)]
+pub struct Parallelism {
+    /// Threads that form Chapters. None (the default) means one per core.
+    #[serde(default)]
+    pub threads: Option<usize>,
+    /// Of those threads, how many may read raw data or write Chapter files
+    /// at the same time. None (the default) means all of them.
+    #[serde(default)]
+    pub io_threads: Option<usize>,
+}
+
+#[cfg(feature = "publisher")]
+impl Parallelism {
+    /// A thread pool with the configured number of threads.
+    pub(crate) fn thread_pool(&self) -> Result<ThreadPool> {
+        Ok(ThreadPoolBuilder::new()
+            .num_threads(self.threads.unwrap_or_default())
+            .thread_name(|n| format!("min-know-{}", n))
+            .build()?)
+    }
+    /// A limit on concurrent reading and writing.
+    pub(crate) fn io_limit(&self) -> IoLimit {
+        IoLimit::new(self.io_threads.unwrap_or(usize::MAX).max(1))
+    }
+}
+
+/// Limits how many threads read or write files at the same time.
+#[cfg(feature = "publisher")]
+#[derive(Debug)]
+pub(crate) struct IoLimit {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+#[cfg(feature = "publisher")]
+impl IoLimit {
+    pub(crate) fn new(permits: usize) -> Self {
+        IoLimit {
+            available: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+    /// Waits until fewer than the limit of threads hold a permit.
+    ///
+    /// The permit is released when dropped.
+    pub(crate) fn acquire(&self) -> IoPermit<'_> {
+        let mut available = self.available.lock().unwrap_or_else(|e| e.into_inner());
+        while *available == 0 {
+            available = self
+                .released
+                .wait(available)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *available -= 1;
+        IoPermit { limit: self }
+    }
+}
+
+/// Permission to read or write files. See [IoLimit::acquire].
+#[cfg(feature = "publisher")]
+pub(crate) struct IoPermit<'a> {
+    limit: &'a IoLimit,
+}
+
+#[cfg(feature = "publisher")]
+impl Drop for IoPermit<'_> {
+    fn drop(&mut self) {
+        let mut available = self
+            .limit
+            .available
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *available += 1;
+        self.limit.released.notify_one();
+    }
+}
+
+#[cfg(feature = "publisher")]
+#[test]
+fn io_limit_bounds_concurrent_holders() {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use rayon::prelude::*;
+
+    let parallelism = Parallelism {
+        threads: Some(4),
+        io_threads: Some(2),
+    };
+    let pool = parallelism.thread_pool().unwrap();
+    assert_eq!(pool.current_num_threads(), 4);
+    let limit = parallelism.io_limit();
+    let holding = AtomicUsize::new(0);
+    let most = AtomicUsize::new(0);
+    pool.install(|| {
+        (0..16).into_par_iter().for_each(|_| {
+            let _permit = limit.acquire();
+            let now = holding.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            holding.fetch_sub(1, Ordering::SeqCst);
+        })
+    });
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+}
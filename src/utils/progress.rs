@@ -0,0 +1,135 @@
+//! Progress reporting for long running parallel work.
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+/// A lock-free counter of completed tasks that logs throughput and time remaining.
+///
+/// Safe to share across rayon threads by reference.
+pub(crate) struct Progress {
+    message: String,
+    total: u64,
+    /// Log every time this many tasks complete. Zero disables count based logging.
+    threshold: u64,
+    completed: AtomicU64,
+    start: Instant,
+    done: AtomicBool,
+}
+
+impl Progress {
+    pub(crate) fn new(total: u64, message: &str, threshold: u64) -> Self {
+        Progress {
+            message: message.to_string(),
+            total,
+            threshold,
+            completed: AtomicU64::new(0),
+            start: Instant::now(),
+            done: AtomicBool::new(false),
+        }
+    }
+    /// Records a completed task, logging every `threshold` completions.
+    pub(crate) fn inc(&self) {
+        let c = self
+            .completed
+            .fetch_add(1, Ordering::Relaxed)
+            .saturating_add(1);
+        if self.threshold != 0 && c.is_multiple_of(self.threshold) {
+            info!("{}", self.summary())
+        }
+    }
+    pub(crate) fn completed(&self) -> u64 {
+        self.completed.load(Ordering::Relaxed)
+    }
+    /// Completed tasks per second.
+    pub(crate) fn rate(&self) -> f64 {
+        rate(self.completed(), self.start.elapsed())
+    }
+    /// Estimated time until all tasks are complete.
+    pub(crate) fn eta(&self) -> Option<Duration> {
+        eta(self.completed(), self.total, self.start.elapsed())
+    }
+    fn summary(&self) -> String {
+        let eta = match self.eta() {
+            Some(d) => format!("{}s", d.as_secs()),
+            None => "unknown".to_string(),
+        };
+        format!(
+            "{} {} of {} ({:.1}/s, ETA {})",
+            self.message,
+            self.completed(),
+            self.total,
+            self.rate(),
+            eta
+        )
+    }
+    /// Performs the work, also logging progress at a regular interval so that
+    /// slow tasks do not appear to have stalled.
+    pub(crate) fn run_with_heartbeat<R>(
+        &self,
+        interval: Option<Duration>,
+        work: impl FnOnce() -> R,
+    ) -> R {
+        let Some(interval) = interval else {
+            return work();
+        };
+        thread::scope(|s| {
+            let heartbeat = s.spawn(|| loop {
+                thread::park_timeout(interval);
+                if self.done.load(Ordering::Relaxed) {
+                    break;
+                }
+                info!("{}", self.summary())
+            });
+            let result = work();
+            self.done.store(true, Ordering::Relaxed);
+            heartbeat.thread().unpark();
+            result
+        })
+    }
+}
+
+/// Tasks per second, or zero if no time has elapsed.
+fn rate(completed: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        completed as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+/// Remaining time at the current rate, or None before the first completion.
+fn eta(completed: u64, total: u64, elapsed: Duration) -> Option<Duration> {
+    if completed == 0 {
+        return None;
+    }
+    let remaining = total.saturating_sub(completed);
+    let per_task = elapsed.as_secs_f64() / completed as f64;
+    Duration::try_from_secs_f64(per_task * remaining as f64).ok()
+}
+
+#[test]
+fn rate_and_eta() {
+    let elapsed = Duration::from_secs(10);
+    assert_eq!(rate(50, elapsed), 5.0);
+    assert_eq!(rate(50, Duration::ZERO), 0.0);
+    assert_eq!(eta(0, 100, elapsed), None);
+    assert_eq!(eta(50, 100, elapsed), Some(Duration::from_secs(10)));
+    // More completions than expected does not underflow.
+    assert_eq!(eta(150, 100, elapsed), Some(Duration::ZERO));
+}
+
+#[test]
+fn counts_across_threads() {
+    use rayon::prelude::*;
+
+    let progress = Progress::new(1000, "Test", 0);
+    progress.run_with_heartbeat(Some(Duration::from_millis(1)), || {
+        (0..1000).into_par_iter().for_each(|_| progress.inc());
+    });
+    assert_eq!(progress.completed(), 1000);
+}
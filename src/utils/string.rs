@@ -1,3 +1,5 @@
+use anyhow::{bail, Result};
+
 /// Turns u32 into 000_000_000 formatted string.
 pub fn num_as_triplet(number: u32) -> String {
     let mut name = format!("{:0>9}", number);
@@ -7,7 +9,45 @@ pub fn num_as_triplet(number: u32) -> String {
     name
 }
 
+/// Reads a number in the 000_000_000 format of [num_as_triplet].
+///
+/// ## Errors
+/// If the string is not three groups of three digits separated by underscores.
+pub fn triplet_as_num(triplet: &str) -> Result<u32> {
+    let groups: Vec<&str> = triplet.split('_').collect();
+    let well_formed = groups.len() == 3
+        && groups
+            .iter()
+            .all(|g| g.len() == 3 && g.bytes().all(|b| b.is_ascii_digit()));
+    if !well_formed {
+        bail!(
+            "Expected a number formatted as 000_000_000, got \"{}\"",
+            triplet
+        )
+    }
+    Ok(groups.concat().parse::<u32>()?)
+}
+
 #[test]
 fn triplet_splits_ok() {
     assert_eq!(num_as_triplet(4_010_302), String::from("004_010_302"));
 }
+
+#[test]
+fn triplet_round_trip() {
+    assert_eq!(triplet_as_num("004_010_302").unwrap(), 4_010_302);
+    assert_eq!(
+        triplet_as_num(&num_as_triplet(999_999_999)).unwrap(),
+        999_999_999
+    );
+    for malformed in [
+        "abc",
+        "4010302",
+        "004_010_30",
+        "004_010_302_",
+        "+04_010_302",
+        "",
+    ] {
+        assert!(triplet_as_num(malformed).is_err());
+    }
+}
@@ -1,11 +1,58 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use log::debug;
 
 use std::{
+    ffi::OsStr,
     fmt::Display,
     fs::{self},
     path::{Path, PathBuf},
 };
 
+/// Filenames that operating systems and editors create in directories.
+const INCIDENTAL_FILENAMES: [&str; 2] = ["Thumbs.db", "desktop.ini"];
+
+/// Extensions of temporary files left by editors and other programs.
+///
+/// Files that this library writes before moving them into place (`.partial`)
+/// are not included, so that they are reported if an operation was interrupted.
+const INCIDENTAL_EXTENSIONS: [&str; 3] = ["tmp", "temp", "swp"];
+
+/// Determines if a directory entry was left by another program rather than
+/// being database or raw data: hidden entries (e.g., ".DS_Store"),
+/// "Thumbs.db", editor backups ("name~") and temporary files ("name.tmp").
+///
+/// Names that are not valid UTF-8 are compared by their valid parts.
+pub(crate) fn is_incidental(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    if name.starts_with('.') || name.ends_with('~') || INCIDENTAL_FILENAMES.contains(&&*name) {
+        return true;
+    }
+    match name.rsplit_once('.') {
+        Some((_, ext)) => INCIDENTAL_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()),
+        None => false,
+    }
+}
+
+/// The paths in a directory, sorted, without entries left by other programs
+/// (see [is_incidental]).
+///
+/// Paths are not converted to strings, so names that are long or not valid
+/// UTF-8 are returned as-is for the caller to handle.
+pub(crate) fn dir_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Couldn't read dir {:?}.", dir))?;
+    let mut paths = vec![];
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Couldn't read entry in dir {:?}.", dir))?;
+        if is_incidental(&entry.file_name()) {
+            debug!("Ignoring {:?} in {:?}.", entry.file_name(), dir);
+            continue;
+        }
+        paths.push(entry.path());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
 pub trait DirFunctions {
     /// Determines if a directory contains all the filenames provided.
     ///
@@ -56,3 +103,54 @@ impl DirFunctions for PathBuf {
         Ok(())
     }
 }
+
+#[test]
+fn incidental_files_detected() {
+    for name in [
+        ".DS_Store",
+        "._addresses_0x00",
+        ".git",
+        "Thumbs.db",
+        "a.ssz~",
+        "x.TMP",
+        "y.swp",
+    ] {
+        assert!(is_incidental(OsStr::new(name)), "{}", name);
+    }
+    for name in [
+        "addresses_0x00",
+        "nametags_from_000_000_000_addresses_0x00.ssz",
+        "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae.ofac",
+        "a.ssz.partial",
+        "a9059cbb",
+    ] {
+        assert!(!is_incidental(OsStr::new(name)), "{}", name);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn dir_paths_keeps_non_utf8_names() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = std::env::temp_dir().join(format!("min_know_dir_paths_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let non_utf8 = OsStr::from_bytes(b"ab\xff\xfe");
+    let long = "a".repeat(255);
+    for name in [
+        OsStr::new(".DS_Store"),
+        OsStr::new("b.tmp"),
+        non_utf8,
+        OsStr::new(&long),
+    ] {
+        fs::write(dir.join(name), b"").unwrap();
+    }
+    let names: Vec<_> = dir_paths(&dir)
+        .unwrap()
+        .iter()
+        .map(|p| p.file_name().unwrap().to_os_string())
+        .collect();
+    // Sorted by bytes, so the long name ('a') is before 'ab..'.
+    assert_eq!(names, vec![OsStr::new(&long), non_utf8]);
+    fs::remove_dir_all(&dir).unwrap();
+}
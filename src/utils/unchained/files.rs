@@ -1,9 +1,8 @@
 use anyhow::{anyhow, Context, Result};
 use regex::Regex;
-use std::{
-    fs,
-    path::{Path, PathBuf},
-};
+use std::path::{Path, PathBuf};
+
+use crate::utils::system::dir_paths;
 
 use super::{
     constants::{AD_ENTRY, AP_ENTRY, HEAD},
@@ -14,21 +13,27 @@ use super::{
 /// Details for files in the Unchained Index chunk directory.
 pub struct ChunksDir {
     pub dir: PathBuf,
+    /// Chunk files, sorted by their first block.
     pub paths: Vec<ChunkFile>,
 }
 
 impl ChunksDir {
     /// Obtains information about all the available chunk files.
     ///
+    /// Chunk files are sorted by their first block.
+    ///
     /// # Example
     /// If the chunk files are in "xyz/trueblocks/unchained/mainnet/finalized",
     /// then this is the path passed in.
+    ///
+    /// ## Errors
+    /// If the directory cannot be read, or has a file that is not named with a
+    /// block range. E.g., "011283653-011286904.bin".
     pub fn new(dir_path: &Path) -> Result<Self> {
-        let files = fs::read_dir(dir_path)
+        let files = dir_paths(dir_path)
             .with_context(|| format!("Failed to read dir from {:?}", dir_path))?;
         let mut paths: Vec<ChunkFile> = vec![];
-        for file in files {
-            let path = file?.path();
+        for path in files {
             let range = get_range(&path)?;
             let chunk = ChunkFile { path, range };
             paths.push(chunk);
@@ -57,6 +62,7 @@ impl ChunksDir {
     }
 }
 
+/// A chunk file and the blocks it covers (from the filename).
 #[derive(Clone, Debug)]
 pub struct ChunkFile {
     pub path: PathBuf,
@@ -84,9 +90,11 @@ pub fn file_structure(h: &Header) -> Body {
 /// Get first and last block that an index chunk covers.
 pub fn get_range(path: &PathBuf) -> anyhow::Result<BlockRange> {
     // Two 9 digit values .../123456789-123456789.bin
+    // Only the filename is read, so the directory may have any name.
     let path_string = path
-        .to_str()
-        .ok_or_else(|| anyhow!("Cannot read path {:?} as string.", path))?;
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("Cannot read filename of {:?} as string.", path))?;
     let bounds = Regex::new(
         r"(?x)
     (?P<low>\d{9})  # the earliest block.
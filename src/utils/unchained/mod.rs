@@ -2,7 +2,59 @@
 //!
 //! The chunk files map Ethereum addresses to the transactions they appear in.
 //! Functions in this library allow for this data to be extracted for use.
+//! The reader does not depend on the rest of min-know and can be used to read
+//! chunk files produced by chifra (trueblocks-core).
+//!
+//! ## Reading chunk files
+//! - [files::ChunksDir] finds the chunk files in a directory and selects
+//!   those that cover a block range.
+//! - [types::UnchainedFile] reads a single chunk file:
+//!     - [types::UnchainedFile::addresses] iterates over every address.
+//!     - [types::UnchainedFile::appearances_of] finds a single address without
+//!       reading the whole file.
+//!     - [types::UnchainedFile::with_parsed] reads all addresses that start with
+//!       given hex characters.
+//!     - [types::UnchainedFile::with_parsed_matching] reads all addresses that
+//!       satisfy a predicate.
+//! - [writer::ChunkWriter] creates chunk files (e.g., for tests).
+//!
+//! Only the chunk file versions in [structure::ChunkVersion::SUPPORTED] are read.
+//! Files with other versions return an [structure::UnsupportedChunkVersion] error.
+//!
+//! ## Example
+//! ```
+//! use min_know::utils::unchained::{
+//!     files::ChunksDir,
+//!     types::{BlockRange, UnchainedFile},
+//!     writer::ChunkWriter,
+//! };
+//! # let dir = std::env::temp_dir().join("min_know_doc_unchained_reader");
+//! let address = [0x4e; 20];
+//! // Create a chunk file to read.
+//! let mut chunk = ChunkWriter::new(BlockRange::new(100, 200)?);
+//! chunk.add_appearance(&address, 150, 3)?;
+//! chunk.add_appearance(&[0xab; 20], 120, 0)?;
+//! chunk.write_to_dir(&dir)?;
+//!
+//! let desired = BlockRange::new(0, 1_000)?;
+//! let chunks = ChunksDir::new(&dir)?;
+//! for chunk in chunks.for_range(&desired).unwrap_or_default() {
+//!     let mut file = UnchainedFile::new(chunk.path.clone(), desired)?;
+//!     // Every address.
+//!     for data in file.addresses() {
+//!         let data = data?;
+//!         println!("0x{} in {} txs", hex::encode(&data.address), data.appearances.len());
+//!     }
+//!     // A single address.
+//!     let found = file.appearances_of(&address)?.unwrap();
+//!     assert_eq!(found.appearances[0].block, 150);
+//!     assert!(file.appearances_of(&[0x01; 20])?.is_none());
+//! }
+//! # std::fs::remove_dir_all(&dir)?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
 pub(crate) mod constants;
 pub mod files;
 pub(crate) mod structure;
 pub mod types;
+pub mod writer;
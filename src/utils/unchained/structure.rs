@@ -1,37 +1,121 @@
 //! Contains the structure of the Unchained Index as defined in
 //! the Unchained Index specification.
 use anyhow::{anyhow, Result};
-use byteorder::{LittleEndian, ReadBytesExt};
-use std::{io::Read, path::PathBuf};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use sha3::{Digest, Keccak256};
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    path::PathBuf,
+};
 
 use super::constants::{ADDR, MAGIC, VAL, VER};
 
 #[derive(Default)]
 /// Stores values extracted from file header.
 pub struct Header {
+    pub version: ChunkVersion,
     pub n_addresses: u32,
     pub n_appearances: u32,
 }
 
 impl Header {
-    /// Obtains values from file header and validates magic number.
+    /// Obtains values from file header and validates magic number and version.
+    ///
+    /// ## Errors
+    /// [UnsupportedChunkVersion] if the version hash is not one of
+    /// [ChunkVersion::SUPPORTED].
     pub fn from_reader(mut rdr: impl Read, path: &PathBuf) -> Result<Header> {
         let mut magic: [u8; VAL] = [0; VAL];
         rdr.read_exact(&mut magic)?;
         if magic != MAGIC {
             return Err(anyhow!("file {:?} has incorrect magic bytes", path));
         }
-        let mut version: [u8; VER] = [0; VER];
-        rdr.read_exact(&mut version)?;
+        let mut version_hash: [u8; VER] = [0; VER];
+        rdr.read_exact(&mut version_hash)?;
+        let Some(version) = ChunkVersion::from_hash(&version_hash) else {
+            return Err(UnsupportedChunkVersion {
+                path: path.clone(),
+                found: version_hash,
+            }
+            .into());
+        };
         let n_addresses = rdr.read_u32::<LittleEndian>()?;
         let n_appearances = rdr.read_u32::<LittleEndian>()?;
         Ok(Header {
+            version,
             n_addresses,
             n_appearances,
         })
     }
+    /// Writes the file header (magic, version, counts) to the writer.
+    pub fn to_writer(&self, mut wtr: impl Write, version: &[u8; VER]) -> std::io::Result<()> {
+        wtr.write_all(&MAGIC)?;
+        wtr.write_all(version)?;
+        wtr.write_u32::<LittleEndian>(self.n_addresses)?;
+        wtr.write_u32::<LittleEndian>(self.n_appearances)?;
+        Ok(())
+    }
+}
+
+/// A version of the chunk file format.
+///
+/// The file header identifies the version with the keccak256 hash of the
+/// TrueBlocks specification version string. Files with other versions may have
+/// a different layout, so are not read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkVersion {
+    /// "trueblocks-core@v0.40.0".
+    #[default]
+    V0_40_0,
+}
+
+impl ChunkVersion {
+    /// The versions that can be read.
+    pub const SUPPORTED: [ChunkVersion; 1] = [ChunkVersion::V0_40_0];
+    /// The specification version string. E.g., "trueblocks-core@v0.40.0".
+    pub fn spec_string(&self) -> &str {
+        match self {
+            ChunkVersion::V0_40_0 => "trueblocks-core@v0.40.0",
+        }
+    }
+    /// The version hash written in the file header.
+    pub fn hash(&self) -> [u8; VER] {
+        Keccak256::digest(self.spec_string().as_bytes()).into()
+    }
+    /// The supported version with the given hash, if any.
+    pub fn from_hash(hash: &[u8; VER]) -> Option<Self> {
+        Self::SUPPORTED.into_iter().find(|v| &v.hash() == hash)
+    }
+}
+
+/// Error returned when a chunk file has a version that is not supported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsupportedChunkVersion {
+    /// The chunk file.
+    pub path: PathBuf,
+    /// The version hash in the file header.
+    pub found: [u8; VER],
 }
 
+impl Display for UnsupportedChunkVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let supported: Vec<&str> = ChunkVersion::SUPPORTED
+            .iter()
+            .map(|v| v.spec_string())
+            .collect();
+        write!(
+            f,
+            "Chunk file {:?} has unsupported version hash 0x{} (supported: {})",
+            self.path,
+            hex::encode(self.found),
+            supported.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedChunkVersion {}
+
 /// Records information about important byte indices in the chunk file.
 pub struct Body {
     /// Table in binary file containing addresses.
@@ -74,6 +158,13 @@ impl AddressEntry {
             count,
         })
     }
+    /// Writes an address entry to the writer.
+    pub fn to_writer(&self, mut wtr: impl Write) -> std::io::Result<()> {
+        wtr.write_all(&self.address)?;
+        wtr.write_u32::<LittleEndian>(self.offset)?;
+        wtr.write_u32::<LittleEndian>(self.count)?;
+        Ok(())
+    }
 }
 
 /// Holds selected transactions for a given address.
@@ -101,4 +192,35 @@ impl TransactionId {
         let index = rdr.read_u32::<LittleEndian>()?;
         Ok(TransactionId { block, index })
     }
+    /// Writes an appearance (Tx) entry to the writer.
+    pub fn to_writer(&self, mut wtr: impl Write) -> std::io::Result<()> {
+        wtr.write_u32::<LittleEndian>(self.block)?;
+        wtr.write_u32::<LittleEndian>(self.index)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn chunk_versions_read_from_header() {
+    let hash = ChunkVersion::V0_40_0.hash();
+    assert_eq!(hex::encode(&hash[..4]), "81ae14ba");
+    let path = PathBuf::from("000000000-000000001.bin");
+    let header = Header {
+        n_addresses: 2,
+        n_appearances: 3,
+        ..Default::default()
+    };
+    let mut bytes = vec![];
+    header.to_writer(&mut bytes, &hash).unwrap();
+    let read = Header::from_reader(bytes.as_slice(), &path).unwrap();
+    assert_eq!(read.version, ChunkVersion::V0_40_0);
+    assert_eq!((read.n_addresses, read.n_appearances), (2, 3));
+
+    let mut bytes = vec![];
+    header.to_writer(&mut bytes, &[0x11; VER]).unwrap();
+    let error = Header::from_reader(bytes.as_slice(), &path).err().unwrap();
+    let unsupported = error.downcast_ref::<UnsupportedChunkVersion>().unwrap();
+    assert_eq!(unsupported.path, path);
+    assert_eq!(unsupported.found, [0x11; VER]);
+    assert!(error.to_string().contains("trueblocks-core@v0.40.0"));
 }
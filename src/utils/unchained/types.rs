@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::fs::File;
 use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::path::PathBuf;
@@ -6,11 +7,13 @@ use anyhow::{anyhow, Result};
 use log::debug;
 
 use super::{
-    constants::{AD_ENTRY, AP_ENTRY},
+    constants::{ADDR, AD_ENTRY, AP_ENTRY},
     files::{file_structure, get_range, no_unexpected_appearances},
-    structure::{AddressData, AddressEntry, Body, Header, TransactionId},
+    structure::{AddressEntry, Body, Header},
 };
 
+pub use super::structure::{AddressData, TransactionId};
+
 /**
 Unchained Index .bin file read and tracker.
 
@@ -68,6 +71,8 @@ pub struct UnchainedFile {
     pub(crate) present: BlockRange,
     pub(crate) desired: BlockRange,
     pub(crate) contains_unwanted_blocks: bool,
+    /// Number of appearances read that were outside the desired range.
+    pub(crate) discarded: u64,
     pub parsed: Vec<AddressData>,
 }
 
@@ -100,6 +105,7 @@ impl UnchainedFile {
             present,
             desired,
             contains_unwanted_blocks,
+            discarded: 0,
             parsed,
         })
     }
@@ -109,6 +115,25 @@ impl UnchainedFile {
     /// Only addresses that begin with the specified hex characters are included.
     /// E.g., "0xbe"
     ///
+    /// See [Self::with_parsed_matching] for the algorithm.
+    pub fn with_parsed(&mut self, address_leading_char: &str) -> Result<()> {
+        let address_starting_bytes = hex::decode(address_leading_char)?;
+        self.with_parsed_matching(|address| address.starts_with(&address_starting_bytes))?;
+        debug!(
+            "In {:?}. {:0>7} addresses started with 0x{} and had tx in range ({}-{}). Chunk attributes: nAddr {:0>7}, nApp {:0>7}.",
+            self.path.file_name().unwrap(), self.parsed.len(), address_leading_char,
+            self.desired.old, self.desired.new,
+            self.header.n_addresses, self.header.n_appearances
+        );
+        Ok(())
+    }
+
+    /// Populates the self.parsed field with AddressData for addresses that
+    /// satisfy the predicate.
+    ///
+    /// The predicate is called with the address bytes (20 bytes) of every
+    /// address in the file. Appearances are only read for matching addresses.
+    ///
     /// Algorithm:
     /// 1. Iterate over address entries, starting reader at the address table.
     /// 2. For current address entry, read the address, offset and count.
@@ -119,10 +144,25 @@ impl UnchainedFile {
     /// 7. Save to transactions to database, adding to existing AddressData for that address.
     /// 8. Update address byte index for the next entry
     /// 9. Jump back to address table, go to 2.
-    pub fn with_parsed(&mut self, address_leading_char: &str) -> Result<()> {
-        let address_starting_bytes = hex::decode(address_leading_char)?;
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use min_know::utils::unchained::types::{BlockRange, UnchainedFile};
+    /// # let path = std::path::PathBuf::from("011283653-011286904.bin");
+    /// let mine = [[0x4e; 20], [0xab; 20]];
+    /// let mut file = UnchainedFile::new(path, BlockRange::new(0, 16_000_000)?)?;
+    /// file.with_parsed_matching(|address| mine.iter().any(|m| m == address))?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn with_parsed_matching<F>(&mut self, predicate: F) -> Result<()>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
         let mut txs: Vec<AddressData> = vec![];
         let mut addresses_parsed = 0;
+        self.body.addresses.current = self.body.addresses.start;
+        self.reader
+            .seek(SeekFrom::Start(self.body.addresses.current as u64))?;
         // 1.
         while addresses_parsed < self.header.n_addresses {
             // 2.
@@ -132,7 +172,7 @@ impl UnchainedFile {
             let app_passed = address_entry.offset as usize * AP_ENTRY;
             self.body.appearances.current = self.body.appearances.start + app_passed;
             let address = address_entry.address.clone();
-            if address.starts_with(address_starting_bytes.as_ref()) {
+            if predicate(&address) {
                 // 4. to 7.
                 let potential_appearances: Option<Vec<TransactionId>> =
                     self.parse_appearances(&address_entry)?;
@@ -153,16 +193,92 @@ impl UnchainedFile {
                 .seek(SeekFrom::Start(self.body.addresses.current as u64))?;
         }
         self.parsed = txs;
+        Ok(())
+    }
 
-        debug!(
-            "In {:?}. {:0>7} addresses started with 0x{} and had tx in range ({}-{}). Chunk attributes: nAddr {:0>7}, nApp {:0>7}.",
-            self.path.file_name().unwrap(), self.parsed.len(), address_leading_char,
-            self.desired.old, self.desired.new,
-            self.header.n_addresses, self.header.n_appearances
-        );
+    /// Populates the self.parsed field with AddressData for the target addresses.
+    ///
+    /// Each address is found with [Self::appearances_of], so only a small part
+    /// of the file is read. This is faster than [Self::with_parsed_matching]
+    /// for a small number of addresses (e.g., the addresses of a wallet).
+    ///
+    /// ## Errors
+    /// If an address is not 20 bytes.
+    pub fn with_parsed_addresses(&mut self, targets: &[Vec<u8>]) -> Result<()> {
+        let mut txs: Vec<AddressData> = vec![];
+        for target in targets {
+            if let Some(data) = self.appearances_of(target)? {
+                txs.push(data)
+            }
+        }
+        txs.sort_by(|a, b| a.address.cmp(&b.address));
+        txs.dedup();
+        self.parsed = txs;
         Ok(())
     }
 
+    /// Iterates over every address in the file, in ascending order.
+    ///
+    /// Each item has the appearances of the address within the desired block range.
+    /// Addresses without appearances in the desired range are not yielded.
+    /// Unlike [Self::with_parsed], the addresses are not filtered by leading
+    /// characters and are not held in memory.
+    pub fn addresses(&mut self) -> AddressIter<'_> {
+        AddressIter {
+            file: self,
+            next: 0,
+        }
+    }
+    /// Reads the appearances of a single address within the desired block range.
+    ///
+    /// Returns `None` if the address is not in the file, or has no appearances
+    /// in the desired block range.
+    ///
+    /// ## Algorithm
+    /// The address table is sorted, so the address is found with a binary
+    /// search. Only the entries visited by the search and the appearances of
+    /// the address are read.
+    ///
+    /// ## Errors
+    /// If the address is not 20 bytes, or the file cannot be read.
+    pub fn appearances_of(&mut self, address: &[u8]) -> Result<Option<AddressData>> {
+        if address.len() != ADDR {
+            return Err(anyhow!(
+                "Address must be {} bytes, got {} bytes.",
+                ADDR,
+                address.len()
+            ));
+        }
+        let (mut low, mut high) = (0, self.header.n_addresses);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let entry = self.address_entry(mid)?;
+            match entry.address.as_slice().cmp(address) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return self.address_data(&entry),
+            }
+        }
+        Ok(None)
+    }
+    /// Reads the nth entry of the address table.
+    fn address_entry(&mut self, n: u32) -> Result<AddressEntry> {
+        let position = self.body.addresses.start + n as usize * AD_ENTRY;
+        self.reader.seek(SeekFrom::Start(position as u64))?;
+        Ok(AddressEntry::from_reader(self.reader.by_ref())?)
+    }
+    /// Reads the appearances for an address entry.
+    fn address_data(&mut self, entry: &AddressEntry) -> Result<Option<AddressData>> {
+        let app_passed = entry.offset as usize * AP_ENTRY;
+        self.body.appearances.current = self.body.appearances.start + app_passed;
+        let data = self
+            .parse_appearances(entry)?
+            .map(|appearances| AddressData {
+                address: entry.address.clone(),
+                appearances,
+            });
+        Ok(data)
+    }
     /// Processes the appearances (transactions) for a given address
     fn parse_appearances(
         &mut self,
@@ -183,6 +299,7 @@ impl UnchainedFile {
                     entries.push(appearance);
                 } else {
                     // Exclude transactions not within the desired block range.
+                    self.discarded += 1;
                 }
             } else {
                 entries.push(appearance);
@@ -199,6 +316,41 @@ impl UnchainedFile {
     }
 }
 
+/// Iterator over the addresses in a chunk file.
+///
+/// Created by [UnchainedFile::addresses].
+pub struct AddressIter<'a> {
+    file: &'a mut UnchainedFile,
+    /// Index of the next entry in the address table.
+    next: u32,
+}
+
+impl Iterator for AddressIter<'_> {
+    type Item = Result<AddressData>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < self.file.header.n_addresses {
+            let n = self.next;
+            self.next += 1;
+            let data = self
+                .file
+                .address_entry(n)
+                .and_then(|entry| self.file.address_data(&entry));
+            match data {
+                Ok(Some(data)) => return Some(Ok(data)),
+                // All appearances were outside the desired range.
+                Ok(None) => continue,
+                Err(e) => {
+                    // Stop after an error.
+                    self.next = self.file.header.n_addresses;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct BlockRange {
     pub old: u32,
@@ -241,6 +393,10 @@ impl BlockRange {
         }
         false
     }
+    /// True if the block is in the range.
+    pub fn contains_block(&self, block: u32) -> bool {
+        self.old <= block && self.new >= block
+    }
     /// True if range contains the specified transaction.
     fn contains(&self, tx: &TransactionId) -> bool {
         if self.old <= tx.block && self.new >= tx.block {
@@ -249,3 +405,45 @@ impl BlockRange {
         false
     }
 }
+
+#[test]
+fn addresses_iterated_and_found_by_search() {
+    use super::writer::ChunkWriter;
+
+    let dir = std::env::temp_dir().join("min_know_test_unchained_search");
+    let mut chunk = ChunkWriter::new(BlockRange::new(100, 200).unwrap());
+    for n in (0..=255).step_by(5) {
+        chunk
+            .add_appearance(&[n; ADDR], 100 + n as u32 % 100, 0)
+            .unwrap();
+    }
+    let path = chunk.write_to_dir(&dir).unwrap();
+    // Excludes blocks 100-149.
+    let mut file = UnchainedFile::new(path, BlockRange::new(150, 300).unwrap()).unwrap();
+    let all: Vec<AddressData> = file.addresses().collect::<Result<_>>().unwrap();
+    assert_eq!(all.len(), 22);
+    assert!(all.iter().all(|a| a.appearances[0].block >= 150));
+    for data in &all {
+        assert_eq!(
+            file.appearances_of(&data.address).unwrap().as_ref(),
+            Some(data)
+        );
+    }
+    // Present, but outside the range.
+    assert!(file.appearances_of(&[0; ADDR]).unwrap().is_none());
+    // Absent.
+    assert!(file.appearances_of(&[1; ADDR]).unwrap().is_none());
+    assert!(file.appearances_of(&[1; 4]).is_err());
+    // Reading by leading characters is unaffected by the search.
+    file.with_parsed("32").unwrap();
+    assert_eq!(file.parsed.len(), 1);
+
+    let targets = vec![vec![0xfa; ADDR], vec![0x32; ADDR], vec![1; ADDR]];
+    file.with_parsed_addresses(&targets).unwrap();
+    let by_search = file.parsed.clone();
+    file.with_parsed_matching(|a| targets.iter().any(|t| t == a))
+        .unwrap();
+    assert_eq!(by_search.len(), 2);
+    assert_eq!(by_search, file.parsed);
+    std::fs::remove_dir_all(dir).unwrap();
+}
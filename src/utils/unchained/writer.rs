@@ -0,0 +1,177 @@
+//! Constructs Unchained Index chunk files.
+//!
+//! The byte layout is the same as that read by [UnchainedFile][super::types::UnchainedFile],
+//! which allows small chunk files with known contents to be created (e.g., for tests)
+//! rather than depending on large real chunk files.
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use super::{
+    constants::{ADDR, AD_ENTRY, AP_ENTRY, HEAD, VER},
+    structure::{AddressEntry, ChunkVersion, Header, TransactionId},
+    types::BlockRange,
+};
+
+/**
+Builds an Unchained Index chunk file from known appearances.
+
+Addresses are written in ascending order and the appearances for each
+address are ordered by block then transaction index.
+
+# Example
+```
+use min_know::utils::unchained::{types::BlockRange, writer::ChunkWriter};
+
+let mut chunk = ChunkWriter::new(BlockRange::new(11_000_000, 11_000_100)?);
+let address = hex::decode("de0b295669a9fd93d5f28d9ec85e40f4cb697bae")?;
+chunk.add_appearance(&address, 11_000_050, 3)?;
+assert_eq!(chunk.filename(), "011000000-011000100.bin");
+assert_eq!(chunk.to_bytes()?.len(), 44 + 28 + 8);
+# Ok::<(), anyhow::Error>(())
+```
+*/
+#[derive(Clone, Debug)]
+pub struct ChunkWriter {
+    range: BlockRange,
+    version: [u8; VER],
+    appearances: BTreeMap<Vec<u8>, Vec<TransactionId>>,
+}
+
+impl ChunkWriter {
+    /// Creates a writer for a chunk that covers the given block range.
+    pub fn new(range: BlockRange) -> Self {
+        ChunkWriter {
+            range,
+            version: ChunkVersion::default().hash(),
+            appearances: BTreeMap::new(),
+        }
+    }
+    /// Sets the version hash that is written to the file header.
+    ///
+    /// The default is the hash of the latest [ChunkVersion]. Other hashes create
+    /// files that cannot be read (e.g., to test version handling).
+    pub fn with_version(mut self, version: [u8; VER]) -> Self {
+        self.version = version;
+        self
+    }
+    /// Records that an address appeared in a transaction.
+    ///
+    /// ## Errors
+    /// If the address is not 20 bytes, or if the block is outside the
+    /// range of the chunk.
+    pub fn add_appearance(&mut self, address: &[u8], block: u32, index: u32) -> Result<()> {
+        if address.len() != ADDR {
+            bail!(
+                "Address must be {} bytes, got {} bytes: 0x{}",
+                ADDR,
+                address.len(),
+                hex::encode(address)
+            )
+        }
+        if block < self.range.old || block > self.range.new {
+            bail!(
+                "Block {} is outside chunk range ({}-{}).",
+                block,
+                self.range.old,
+                self.range.new
+            )
+        }
+        self.appearances
+            .entry(address.to_vec())
+            .or_default()
+            .push(TransactionId { block, index });
+        Ok(())
+    }
+    /// Returns the filename for the chunk. E.g., "011283653-011286904.bin".
+    pub fn filename(&self) -> String {
+        format!("{:0>9}-{:0>9}.bin", self.range.old, self.range.new)
+    }
+    /// Returns the chunk file as bytes.
+    ///
+    /// ## Algorithm
+    /// 1. Header (magic, version, number of addresses and appearances).
+    /// 2. Address table, where each entry points (offset, count) into the appearances table.
+    /// 3. Appearance table.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let n_addresses = self.appearances.len();
+        let n_appearances: usize = self.appearances.values().map(|a| a.len()).sum();
+        let mut bytes: Vec<u8> =
+            Vec::with_capacity(HEAD + n_addresses * AD_ENTRY + n_appearances * AP_ENTRY);
+        let header = Header {
+            n_addresses: n_addresses as u32,
+            n_appearances: n_appearances as u32,
+            ..Default::default()
+        };
+        header.to_writer(&mut bytes, &self.version)?;
+
+        let mut ordered: Vec<(&Vec<u8>, Vec<&TransactionId>)> = vec![];
+        for (address, txs) in &self.appearances {
+            let mut txs: Vec<&TransactionId> = txs.iter().collect();
+            txs.sort_by_key(|tx| (tx.block, tx.index));
+            ordered.push((address, txs));
+        }
+        let mut offset: u32 = 0;
+        for (address, txs) in &ordered {
+            let entry = AddressEntry {
+                address: address.to_vec(),
+                offset,
+                count: txs.len() as u32,
+            };
+            entry.to_writer(&mut bytes)?;
+            offset += txs.len() as u32;
+        }
+        for (_address, txs) in &ordered {
+            for tx in txs {
+                tx.to_writer(&mut bytes)?;
+            }
+        }
+        Ok(bytes)
+    }
+    /// Writes the chunk file to the given directory, returning the path of the file.
+    pub fn write_to_dir(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(self.filename());
+        fs::write(&path, self.to_bytes()?)
+            .with_context(|| format!("Failed to write chunk file {:?}", &path))?;
+        Ok(path)
+    }
+}
+
+#[test]
+fn written_chunk_is_readable() {
+    use super::types::UnchainedFile;
+
+    let dir = std::env::temp_dir().join("min_know_test_chunk_writer");
+    let range = BlockRange::new(100, 200).unwrap();
+    let mut chunk = ChunkWriter::new(range);
+    let a = [0x4e; ADDR];
+    let b = [0xab; ADDR];
+    chunk.add_appearance(&b, 150, 0).unwrap();
+    chunk.add_appearance(&a, 120, 7).unwrap();
+    chunk.add_appearance(&a, 101, 2).unwrap();
+    assert!(chunk.add_appearance(&a, 201, 0).is_err());
+    assert!(chunk.add_appearance(&a[1..], 150, 0).is_err());
+
+    let path = chunk.write_to_dir(&dir).unwrap();
+    assert_eq!(
+        fs::read(&path).unwrap().len(),
+        HEAD + 2 * AD_ENTRY + 3 * AP_ENTRY
+    );
+    let mut file = UnchainedFile::new(path.clone(), range).unwrap();
+    file.with_parsed("4e").unwrap();
+    assert_eq!(file.parsed.len(), 1);
+    assert_eq!(file.parsed[0].address, a.to_vec());
+    let blocks: Vec<u32> = file.parsed[0].appearances.iter().map(|x| x.block).collect();
+    assert_eq!(blocks, vec![101, 120]);
+
+    let unknown = chunk.with_version([0x11; VER]);
+    fs::write(&path, unknown.to_bytes().unwrap()).unwrap();
+    let error = UnchainedFile::new(path, range).err().unwrap();
+    assert!(error.is::<super::structure::UnsupportedChunkVersion>());
+    fs::remove_dir_all(dir).unwrap();
+}
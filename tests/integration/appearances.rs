@@ -6,14 +6,24 @@ use cid::{
     Cid,
 };
 use min_know::{
+    config::{
+        address_appearance_index::Network,
+        choices::{DataKind, DirNature, RawSourceFormat},
+    },
+    database::{appearances::AppearancesClient, types::Todd},
     specs::{
-        address_appearance_index::{AAIAppearanceTx, AAIChapterId, AAISpec, AAIVolumeId},
-        traits::{ChapterIdMethods, VolumeIdMethods},
+        address_appearance_index::{
+            AAIAppearanceTx, AAIChapter, AAIChapterId, AAISpec, AAIVolumeId,
+        },
+        traits::{
+            ChapterIdMethods, ChapterMethods, DataSpec, ManifestMethods, RecordValueMethods,
+            VolumeIdMethods,
+        },
     },
     utils::unchained::types::{BlockRange, UnchainedFile},
 };
 
-use crate::common::aai_db;
+use crate::common::{aai_db, assert_builds_deterministic, ephemeral_copy};
 
 #[test]
 fn index_dir_readable() {
@@ -47,7 +57,7 @@ fn sample_files_all_greater_than_50kb() {
         for (chapter_path, _volume_id) in chapter_files {
             let bytes = fs::read(chapter_path).unwrap();
             let kbytes = bytes.len() / 1000;
-            assert_eq!(kbytes > 50, true);
+            assert!(kbytes > 50);
         }
     }
 }
@@ -80,6 +90,175 @@ fn detects_known_txs() {
     assert_eq!(known_count, appearances.len());
 }
 
+#[test]
+fn appearance_summaries_serialize_as_tx_refs() {
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let values = aai_db().find(address).unwrap();
+    let summary = values[0].summary().unwrap();
+    assert_eq!(summary.len(), values[0].value.len());
+    let json = serde_json::to_value(&summary).unwrap();
+    assert_eq!(json[0]["block"], values[0].value[0].block);
+    assert_eq!(json[0]["index"], values[0].value[0].index);
+    assert!(AAISpec::value_schema().describes(&json));
+}
+
+#[test]
+fn watched_addresses_build_a_small_index() {
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let data_kind = DataKind::AddressAppearanceIndex(Network::default());
+    let mut db: Todd<AAISpec> = Todd::init(data_kind, DirNature::Ephemeral).unwrap();
+    db.config.raw_source = aai_db().config.raw_source;
+    db.config.watched_keys = vec![address.to_string()];
+    db.full_transformation().unwrap();
+
+    let inventory = db.inventory().unwrap();
+    assert!(!inventory.is_empty());
+    assert!(inventory
+        .iter()
+        .all(|(_vol, chapter, _path, _size)| chapter.interface_id() == "chapter_0xde"));
+    let mut appearances: Vec<AAIAppearanceTx> = vec![];
+    for v in db.find(address).unwrap() {
+        appearances.extend(v.value.to_vec());
+    }
+    assert_eq!(appearances.len(), 53);
+    // Other addresses in the same Chapter are not included.
+    for (_vol, _chapter, path, _size) in inventory {
+        let chapter = AAIChapter::from_file(fs::read(path).unwrap()).unwrap();
+        assert!(chapter.records.len() <= 1);
+    }
+}
+
+#[test]
+fn builds_are_deterministic() {
+    let mut db = aai_db();
+    // Each raw chunk file is read once per Volume rather than once per Chapter.
+    db.config.single_pass_volumes = true;
+    assert_builds_deterministic(&db);
+}
+
+#[test]
+fn chapter_and_volume_rebuilt_from_raw_data() {
+    let db = ephemeral_copy(&aai_db());
+    let inventory = db.inventory().unwrap();
+    let path_of = |volume: &str, chapter: &str| {
+        inventory
+            .iter()
+            .find(|(v, c, _path, _size)| v.interface_id() == volume && c.interface_id() == chapter)
+            .map(|(_v, _c, path, _size)| path.clone())
+            .unwrap()
+    };
+    let in_chapter = path_of("volume_011_200_000", "chapter_0x4e");
+    let in_volume = path_of("volume_014_400_000", "chapter_0x00");
+    // Sample files may be in an earlier format, so Chapters are compared.
+    let chapter_at = |path: &PathBuf| {
+        AAIChapter::from_file(fs::read(path).unwrap())
+            .unwrap()
+            .as_serialized_bytes()
+            .unwrap()
+    };
+    let originals: Vec<Vec<u8>> = [&in_chapter, &in_volume]
+        .iter()
+        .map(|path| chapter_at(path))
+        .collect();
+    fs::write(&in_chapter, b"corrupt").unwrap();
+    fs::write(&in_volume, b"corrupt").unwrap();
+
+    let chapter_id = AAIChapterId::from_interface_id("chapter_0x4e").unwrap();
+    db.rebuild_chapter(&chapter_id).unwrap();
+    assert_eq!(chapter_at(&in_chapter), originals[0]);
+    assert_eq!(fs::read(&in_volume).unwrap(), b"corrupt");
+
+    let volume_id = AAIVolumeId::from_interface_id("volume_014_400_000").unwrap();
+    db.rebuild_volume(&volume_id).unwrap();
+    assert_eq!(chapter_at(&in_volume), originals[1]);
+    assert_eq!(db.inventory().unwrap().len(), inventory.len());
+}
+
+#[test]
+fn chifra_exports_used_as_raw_source() {
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let mut appearances: Vec<AAIAppearanceTx> = vec![];
+    for v in aai_db().find(address).unwrap() {
+        appearances.extend(v.value.to_vec());
+    }
+    // Exported as chifra would, half as CSV and half as JSON.
+    let dir = std::env::temp_dir().join("min_know_test_chifra_export_source");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let (first, second) = appearances.split_at(appearances.len() / 2);
+    let mut csv = String::from("\"address\",\"blockNumber\",\"transactionIndex\"\n");
+    for a in first {
+        csv.push_str(&format!(
+            "\"{}\",\"{}\",\"{}\"\n",
+            address, a.block, a.index
+        ));
+    }
+    fs::write(dir.join("first.csv"), csv).unwrap();
+    let data: Vec<serde_json::Value> = second
+        .iter()
+        .map(|a| {
+            serde_json::json!({
+                "address": address,
+                "blockNumber": a.block,
+                "transactionIndex": a.index,
+            })
+        })
+        .collect();
+    let json = serde_json::json!({ "data": data }).to_string();
+    fs::write(dir.join("second.json"), json).unwrap();
+
+    let data_kind = DataKind::AddressAppearanceIndex(Network::default());
+    let mut db: Todd<AAISpec> = Todd::init(data_kind, DirNature::Ephemeral).unwrap();
+    db.config.raw_source = dir.clone();
+    db.config.raw_source_format = RawSourceFormat::ChifraExport;
+    assert!(db.validate_raw().unwrap().is_valid());
+    db.full_transformation().unwrap();
+
+    // Appearances after the latest complete Volume are not included.
+    let latest = db
+        .manifest()
+        .unwrap()
+        .latest_volume_identifier()
+        .to_string();
+    let last_block = AAIVolumeId::from_interface_id(&latest)
+        .unwrap()
+        .block_range()
+        .unwrap()
+        .new;
+    let expected: Vec<AAIAppearanceTx> = appearances
+        .into_iter()
+        .filter(|a| a.block <= last_block)
+        .collect();
+    assert!(!expected.is_empty());
+    let mut found: Vec<AAIAppearanceTx> = vec![];
+    for v in db.find(address).unwrap() {
+        found.extend(v.value.to_vec());
+    }
+    assert_eq!(found, expected);
+    assert!(db
+        .inventory()
+        .unwrap()
+        .iter()
+        .all(|(_vol, chapter, _path, _size)| chapter.interface_id() == "chapter_0xde"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sample_artifacts_listed_with_sizes() {
+    let db = aai_db();
+    let artifacts = db.sample_artifacts().unwrap();
+    assert_eq!(artifacts.len(), 5);
+    for artifact in &artifacts {
+        assert!(artifact.url.is_some() && artifact.ipfs_cid.is_some());
+        let path = db.config.raw_source.join(&artifact.name);
+        assert_eq!(artifact.bytes, Some(fs::metadata(&path).unwrap().len()));
+    }
+    let first = &artifacts[0];
+    let bytes = fs::read(db.config.raw_source.join(&first.name)).unwrap();
+    let cid = Cid::new_v0(Code::Sha2_256.digest(&bytes)).unwrap();
+    assert_eq!(first.content_cid, Some(cid.to_string()));
+}
+
 #[test]
 fn sample_header_sample_ok() {
     let db = aai_db();
@@ -108,3 +287,116 @@ fn cid_in_out() {
     let out = Cid::try_from(data).unwrap();
     assert_eq!(cid, out);
 }
+
+#[test]
+fn inventory_covers_sample_files() {
+    let inventory = aai_db().inventory().unwrap();
+    // 256 chapters, each with 4 sample volumes.
+    assert_eq!(inventory.len(), 1024);
+    let (first_vol, first_chap, _path, size) = &inventory[0];
+    assert_eq!(first_vol.interface_id(), "volume_011_200_000");
+    assert_eq!(first_chap.interface_id(), "chapter_0x00");
+    assert!(*size > 0);
+    let (last_vol, last_chap, _path, _size) = inventory.last().unwrap();
+    assert_eq!(last_vol.interface_id(), "volume_014_400_000");
+    assert_eq!(last_chap.interface_id(), "chapter_0xff");
+}
+
+#[test]
+fn plan_acquisition_uses_local_manifest() {
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let plan = aai_db().plan_acquisition(&[address, address]).unwrap();
+    assert_eq!(plan.chapters, vec!["chapter_0xde"]);
+    // One file per sample volume, all already present.
+    assert_eq!(plan.files.len(), 4);
+    assert_eq!(plan.files_to_download().count(), 0);
+    assert!(plan.local_bytes() > 0);
+    let json = serde_json::to_string(&plan).unwrap();
+    assert_eq!(plan, serde_json::from_str(&json).unwrap());
+}
+
+#[test]
+fn plan_acquisition_adds_decoy_chapters() {
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let mut db = aai_db();
+    db.config.decoy_chapters = 3;
+    let plan = db.plan_acquisition(&[address]).unwrap();
+    assert_eq!(plan.chapters, vec!["chapter_0xde"]);
+    assert_eq!(plan.decoys.len(), 3);
+    assert!(!plan.decoys.contains(&plan.chapters[0]));
+    let set = plan.anonymity_set();
+    assert_eq!(set.len(), 4);
+    // Every Chapter in the set has its file for each sample volume.
+    assert_eq!(plan.files.len(), 16);
+    for chapter in &set {
+        let files = plan
+            .files
+            .iter()
+            .filter(|f| &f.chapter_interface_id == chapter);
+        assert_eq!(files.count(), 4);
+    }
+}
+
+#[test]
+fn inclusion_proofs_verify_against_chapter_root() {
+    let db = aai_db();
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let proofs = db.inclusion_proofs(address).unwrap();
+    assert!(!proofs.is_empty());
+    for proof in &proofs {
+        let volume = AAIVolumeId::from_interface_id(&proof.volume_interface_id).unwrap();
+        let chapter = AAIChapterId::from_interface_id(&proof.chapter_interface_id).unwrap();
+        let root = db.chapter_root(&volume, &chapter).unwrap();
+        assert!(proof.verify(&root));
+        let mut tampered = proof.clone();
+        tampered.record[0] ^= 1;
+        assert!(!tampered.verify(&root));
+    }
+}
+
+#[test]
+fn sample_manifest_lints_clean() {
+    assert_eq!(aai_db().lint_manifest().unwrap(), vec![]);
+}
+
+#[test]
+fn block_coverage_of_sample_volumes() {
+    let client = AppearancesClient::new(aai_db());
+    let local = client.local_coverage().unwrap();
+    assert_eq!(local, client.published_coverage().unwrap());
+    let starts: Vec<u32> = local.volumes.iter().map(|v| v.range.old).collect();
+    assert_eq!(starts, vec![11_200_000, 12_300_000, 13_400_000, 14_400_000]);
+    assert!(local.covers(11_200_000));
+    assert!(local.covers(14_499_999));
+    assert!(!local.covers(11_300_000));
+    assert!(!local.covers(17_345_678));
+    let volume = local.volume_for(13_456_789).unwrap();
+    assert_eq!(volume.volume_interface_id, "volume_013_400_000");
+    assert_eq!(
+        volume.range,
+        BlockRange::new(13_400_000, 13_499_999).unwrap()
+    );
+    assert_eq!(local.ranges().len(), 4);
+    // Adjacent Volumes are joined.
+    let mut joined = local.clone();
+    let mut next = joined.volumes[3].clone();
+    next.volume_interface_id = "volume_014_500_000".to_string();
+    next.range = AAIVolumeId::containing_block(14_500_000)
+        .unwrap()
+        .block_range()
+        .unwrap();
+    joined.volumes.push(next);
+    let ranges = joined.ranges();
+    assert_eq!(ranges.len(), 4);
+    assert_eq!(ranges[3], BlockRange::new(14_400_000, 14_599_999).unwrap());
+
+    // Holding some Chapters of a Volume does not cover its blocks.
+    let db = ephemeral_copy(&aai_db());
+    let volume_id = AAIVolumeId::containing_block(12_345_678).unwrap();
+    let chapter_id = AAIChapterId::nth_id(0).unwrap();
+    let filename = AAIChapter::new_empty(&volume_id, &chapter_id).filename();
+    fs::remove_file(db.config.chapter_dir_path(&chapter_id).join(filename)).unwrap();
+    let local = AppearancesClient::new(db).local_coverage().unwrap();
+    assert_eq!(local.volume_for(12_345_678).unwrap().chapters, 255);
+    assert!(!local.covers(12_345_678));
+}
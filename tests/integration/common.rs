@@ -1,10 +1,21 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpListener,
+    path::Path,
+    thread,
+};
+
 use min_know::{
     config::{
         address_appearance_index::Network,
         choices::{DataKind, DirNature},
     },
     database::types::Todd,
-    specs::{address_appearance_index::AAISpec, nametags::NameTagsSpec},
+    specs::{
+        address_appearance_index::AAISpec, events::EventsSpec, nametags::NameTagsSpec,
+        signatures::SignaturesSpec, traits::DataSpec,
+    },
 };
 
 pub fn aai_db() -> Todd<AAISpec> {
@@ -18,3 +29,123 @@ pub fn nametags_db() -> Todd<NameTagsSpec> {
     let db: Todd<NameTagsSpec> = Todd::init(data_kind, DirNature::Sample).unwrap();
     db
 }
+
+pub fn signatures_db() -> Todd<SignaturesSpec> {
+    let data_kind = DataKind::Signatures;
+    let db: Todd<SignaturesSpec> = Todd::init(data_kind, DirNature::Sample).unwrap();
+    db
+}
+
+pub fn events_db() -> Todd<EventsSpec> {
+    let data_kind = DataKind::Events;
+    let db: Todd<EventsSpec> = Todd::init(data_kind, DirNature::Sample).unwrap();
+    db
+}
+
+/// Copies the Chapter directories and manifest of a database to a new directory.
+pub fn copy_database(data_dir: &Path, manifest: &Path, dest_base: &Path) {
+    let dest_data_dir = dest_base.join(data_dir.file_name().unwrap());
+    for chapter_dir in fs::read_dir(data_dir).unwrap() {
+        let chapter_dir = chapter_dir.unwrap().path();
+        let dest = dest_data_dir.join(chapter_dir.file_name().unwrap());
+        fs::create_dir_all(&dest).unwrap();
+        for file in fs::read_dir(&chapter_dir).unwrap() {
+            let file = file.unwrap().path();
+            fs::copy(&file, dest.join(file.file_name().unwrap())).unwrap();
+        }
+    }
+    fs::copy(manifest, dest_base.join(manifest.file_name().unwrap())).unwrap();
+}
+
+/// An ephemeral database with a copy of the Chapters and manifest of another database.
+///
+/// Raw source data is read from the other database rather than copied.
+pub fn ephemeral_copy<T: DataSpec + Default + Sync>(db: &Todd<T>) -> Todd<T> {
+    let data_kind = db.config.data_kind.clone();
+    let mut copy: Todd<T> = Todd::init(data_kind, DirNature::Ephemeral).unwrap();
+    copy.config.raw_source = db.config.raw_source.clone();
+    let manifest = db.config.manifest_file_path().unwrap();
+    copy_database(
+        &db.config.data_dir,
+        &manifest,
+        &copy.config.base_dir_nature_dependent,
+    );
+    copy
+}
+
+/// Builds the database twice from its raw data and checks that the Chapter files
+/// and manifest of the two builds are identical.
+pub fn assert_builds_deterministic<T: DataSpec + Default + Sync>(db: &Todd<T>)
+where
+    Todd<T>: Clone,
+{
+    let report = db.verify_determinism().unwrap();
+    assert!(report.is_deterministic(), "{}", report);
+    let manifest = db.config.manifest_file_path().unwrap();
+    let manifest = manifest.file_name().unwrap().to_str().unwrap();
+    assert!(report.files.iter().any(|f| f.path == manifest));
+    assert!(report.files.len() > 1);
+    assert!(!report.dir.exists());
+}
+
+/// Serves the bytes over HTTP for one request, returning the server URL.
+pub fn serve_once(body: Vec<u8>) -> String {
+    serve_in_order(vec![body])
+}
+
+/// Serves each response body over HTTP for one request, in order, returning the server URL.
+pub fn serve_in_order(bodies: Vec<Vec<u8>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for body in bodies {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+        }
+    });
+    url
+}
+
+/// Serves files by URL path (e.g., "/dir/file") over HTTP, returning the server URL.
+///
+/// Other paths are not found. The server runs until the test process exits.
+pub fn serve_paths(files: Vec<(String, Vec<u8>)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = vec![];
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let request = String::from_utf8_lossy(&request);
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = match files.iter().find(|(p, _)| p == path) {
+                Some((_, body)) => ("200 OK", body.as_slice()),
+                None => ("404 Not Found", &[][..]),
+            };
+            let header = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(body).unwrap();
+        }
+    });
+    url
+}
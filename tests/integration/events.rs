@@ -0,0 +1,52 @@
+use std::collections::HashSet;
+
+use min_know::specs::{
+    events::EventsSpec,
+    traits::{DataSpec, ManifestMethods, RecordValueMethods},
+};
+
+use crate::common::events_db;
+
+/// keccak256("Transfer(address,address,uint256)")
+const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+#[test]
+fn sample_topic_has_event_text() {
+    let values = events_db().find(TRANSFER_TOPIC).unwrap();
+    assert_eq!(values.len(), 1);
+    assert_eq!(
+        values[0].texts_as_strings().unwrap(),
+        vec!["Transfer(address,address,uint256)"]
+    );
+    let json = serde_json::to_value(values[0].summary().unwrap()).unwrap();
+    assert!(EventsSpec::value_schema().describes(&json));
+    // The prefix is optional.
+    assert_eq!(
+        events_db()
+            .find(TRANSFER_TOPIC.trim_start_matches("0x"))
+            .unwrap(),
+        values
+    );
+}
+
+#[test]
+fn sample_raw_events_valid() {
+    let report = events_db().validate_raw().unwrap();
+    assert!(report.is_valid());
+    assert_eq!(report.files_checked, 2050);
+}
+
+#[test]
+fn sample_manifest_has_both_volumes() {
+    let manifest = events_db().manifest().unwrap();
+    assert_eq!(manifest.database_interface_id(), "events");
+    let volumes: HashSet<&str> = manifest
+        .chapter_cids
+        .iter()
+        .map(|c| c.volume_interface_id.as_str())
+        .collect();
+    assert_eq!(
+        volumes,
+        HashSet::from(["events_starting_000_000_000", "events_starting_000_001_000"])
+    );
+}
@@ -1,3 +1,5 @@
 mod appearances;
 mod common;
+mod events;
 mod nametags;
+mod signatures;
@@ -1,12 +1,41 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
 use anyhow::Context;
-use min_know::specs::{
-    nametags::{NameTagsChapterId, NameTagsSpec, NameTagsVolumeId},
-    traits::ChapterIdMethods,
+use min_know::{
+    config::choices::{DataKind, DirNature, Parallelism, RawRegressionPolicy, UnsupportedDataKind},
+    database::{
+        builder::ChapterBuilder,
+        encryption::{is_encrypted, EncryptedDatabase, EncryptionKey},
+        extension::{ChapterOutcome, ChapterStatus, ChaptersFailed, ExtendStatus},
+        holdings::RetentionPolicy,
+        lock::ReadOnlyDatabase,
+        nametags::NametagClient,
+        query_cache::QueryCacheLimits,
+        types::{FindMode, Membership, Todd},
+    },
+    manifest::{
+        provenance::ManifestProvenance,
+        samples::{SampleMismatchReason, SamplesManifest, SamplesMismatch},
+        succession::ManifestSuccessor,
+    },
+    specs::{
+        fingerprint::SchemaMismatch,
+        nametags::{
+            NameTagsChapter, NameTagsChapterId, NameTagsRecord, NameTagsRecordKey,
+            NameTagsRecordValue, NameTagsSpec, NameTagsVolumeId,
+        },
+        traits::{
+            ChapterIdMethods, ChapterMethods, DataSpec, ManifestMethods, RecordKeyMethods,
+            RecordValueMethods, VolumeIdMethods,
+        },
+    },
 };
 
-use crate::common::nametags_db;
+use crate::common::{assert_builds_deterministic, ephemeral_copy, nametags_db, serve_in_order};
 
 #[test]
 fn index_dir_readable() {
@@ -41,7 +70,7 @@ fn sample_files_all_greater_than_5_bytes() {
             .unwrap();
         for (chapter_path, _volume_id) in chapter_files {
             let bytes = fs::read(chapter_path).unwrap();
-            assert_eq!(bytes.len() > 5, true);
+            assert!(bytes.len() > 5);
         }
     }
 }
@@ -68,3 +97,949 @@ fn detects_known_nametags() {
     let expected_tags = vec!["ethereum-foundation"];
     assert_eq!(expected_tags, tags);
 }
+
+#[test]
+fn find_modes_for_undecodable_files() {
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let db = ephemeral_copy(&nametags_db());
+    let expected = db.find(address).unwrap();
+    // The values are in the later Volume.
+    let corrupt = db
+        .config
+        .data_dir
+        .join("addresses_0xde/nametags_from_000_000_000_addresses_0xde.ssz");
+    fs::write(&corrupt, b"not a chapter").unwrap();
+
+    let error = format!("{:#}", db.find(address).unwrap_err());
+    assert!(error.contains("nametags_from_000_000_000_addresses_0xde.ssz"));
+    assert!(db.find_with(address, FindMode::Strict).is_err());
+
+    let found = db.find_with(address, FindMode::Lenient).unwrap();
+    assert_eq!(found.values, expected);
+    assert_eq!(found.skipped.len(), 1);
+    assert_eq!(found.skipped[0].path, corrupt);
+    assert!(found.skipped[0].reason.contains("decode"));
+}
+
+#[test]
+fn record_values_have_structured_summaries() {
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let values = nametags_db().find(address).unwrap();
+    let summary = values[0].summary().unwrap();
+    assert_eq!(summary.names, vec!["EthDev"]);
+    assert_eq!(summary.tags, vec!["ethereum-foundation"]);
+    assert_eq!(
+        serde_json::to_string(&summary).unwrap(),
+        r#"{"names":["EthDev"],"tags":["ethereum-foundation"],"added":null}"#
+    );
+}
+
+#[test]
+fn bloom_filters_contain_known_key() {
+    let db = ephemeral_copy(&nametags_db());
+    db.generate_bloom_filters().unwrap();
+    let bloom_files = fs::read_dir(db.config.bloom_dir()).unwrap().count();
+    let inventory = db.inventory().unwrap();
+    assert_eq!(bloom_files, inventory.len());
+
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let key = NameTagsSpec::raw_key_as_record_key(address).unwrap();
+    let item = key.summary_string().unwrap();
+    let chapter_id = NameTagsSpec::record_key_to_chapter_id(&key).unwrap();
+    // The key is in one of the Volumes for the Chapter.
+    let found = inventory
+        .iter()
+        .filter(|(_, c, _, _)| c == &chapter_id)
+        .any(|(volume_id, _, _, _)| {
+            let filter = db.bloom_filter(volume_id, &chapter_id).unwrap().unwrap();
+            filter.contains(item.as_bytes())
+        });
+    assert!(found);
+}
+
+#[test]
+fn might_contain_uses_local_chapters() {
+    let db = nametags_db();
+    let known = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let Membership::Yes(values) = db.might_contain(known).unwrap() else {
+        panic!("Known address not found.")
+    };
+    assert_eq!(values, db.find(known).unwrap());
+    let unknown = "0x0000000000000000000000000000000000000001";
+    assert_eq!(db.might_contain(unknown).unwrap(), Membership::No);
+}
+
+#[test]
+fn prune_keeps_latest_volume() {
+    // Copy the sample database so that files can be removed.
+    let db = ephemeral_copy(&nametags_db());
+
+    let removed = db.prune(RetentionPolicy::KeepLastN(1)).unwrap();
+    assert_eq!(removed, vec!["nametags_from_000_000_000"]);
+    let inventory = db.inventory().unwrap();
+    assert_eq!(inventory.len(), 256);
+    // Pruned volumes are not reported as missing.
+    let audit = db.check_completeness().unwrap();
+    assert!(audit.absent_volume_ids.is_empty());
+    assert!(audit.absent_individual_files.is_empty());
+    // A deleted file in a kept Volume is restored by a repair, the pruned Volume is not.
+    let (_vol, _chap, path, _size) = &inventory[0];
+    fs::remove_file(path).unwrap();
+    db.repair_from_raw().unwrap();
+    assert_eq!(db.inventory().unwrap(), inventory);
+    // Unless the policy is cleared.
+    db.clear_retention_policy().unwrap();
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.absent_volume_ids.len(), 1);
+}
+
+#[test]
+fn merge_from_validates_against_manifest() {
+    let sample = nametags_db();
+    let manifest = sample.config.manifest_file_path().unwrap();
+    let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Ephemeral).unwrap();
+    let local = &db.config.base_dir_nature_dependent;
+    fs::copy(&manifest, local.join(manifest.file_name().unwrap())).unwrap();
+
+    let report = db.merge_from(&sample.config.data_dir).unwrap();
+    assert_eq!(report.imported.len(), 512);
+    assert!(report.rejected.is_empty());
+
+    // A modified file in the other copy is rejected, the rest are already present.
+    let other = ephemeral_copy(&sample);
+    let (_vol, _chap, path, _size) = &db.inventory().unwrap()[0];
+    let filename = path.file_name().unwrap();
+    let chapter = path.parent().unwrap().file_name().unwrap();
+    fs::write(
+        other.config.data_dir.join(chapter).join(filename),
+        b"modified",
+    )
+    .unwrap();
+    let report = db.merge_from(&other.config.data_dir).unwrap();
+    assert_eq!(report.rejected.len(), 1);
+    assert_eq!(report.already_present.len(), 511);
+    assert_eq!(report.files_written(), 0);
+
+    // A modified local file is replaced.
+    fs::write(path, b"modified").unwrap();
+    let report = db.merge_from(&sample.config.data_dir).unwrap();
+    assert_eq!(report.replaced, vec![path.clone()]);
+}
+
+#[test]
+fn ephemeral_dir_removed_on_drop() {
+    let db = ephemeral_copy(&nametags_db());
+    let base = db.config.base_dir_nature_dependent.clone();
+    let clone = db.clone();
+    drop(db);
+    assert_eq!(clone.inventory().unwrap().len(), 512);
+    drop(clone);
+    assert!(!base.exists());
+}
+
+#[test]
+fn ens_names_resolved_and_cached() {
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let resolver = "0x4976fb03c32e5b8cfe2b6ccb31c09ba78ebaba41";
+    let word = |a: &str| format!("0x{:0>64}", a.trim_start_matches("0x"));
+    let response = |result: String| {
+        format!("{{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"{}\"}}", result).into_bytes()
+    };
+    let mut db = nametags_db();
+    db.config.ens_rpc_url = Some(serve_in_order(vec![
+        response(word(resolver)),
+        response(word(address)),
+    ]));
+    let expected = db.find(address).unwrap();
+    assert_eq!(db.find("EthDev.eth").unwrap(), expected);
+    // The server has no more responses, so this uses the cache.
+    assert_eq!(db.find("ethdev.eth").unwrap(), expected);
+}
+
+#[test]
+fn oversize_report_lists_long_sample_values() {
+    let db = nametags_db();
+    let report = db.oversize_report().unwrap();
+    assert!(!report.is_empty());
+    assert!(report.iter().all(|v| v.size > v.limit));
+    assert!(report
+        .iter()
+        .any(|v| v.field == "name" && v.key == "0x26645fdcf7f52831ba891cec92673e0fbc593571"));
+}
+
+#[test]
+fn errata_overlay_volume_in_find() {
+    let db = ephemeral_copy(&nametags_db());
+    let address = "0x26645fdcf7f52831ba891cec92673e0fbc593571";
+    let original = db.find(address).unwrap();
+    assert!(!original.is_empty());
+    let proofs = db.inclusion_proofs(address).unwrap();
+    let volume_id = NameTagsVolumeId::from_interface_id(&proofs[0].volume_interface_id).unwrap();
+    let correction = |name: &str| NameTagsRecord {
+        key: NameTagsRecordKey::from_address(address).unwrap(),
+        value: NameTagsRecordValue::from_strings(vec![name.to_string()], vec![]),
+    };
+    db.add_errata(&volume_id, vec![correction("first")])
+        .unwrap();
+    db.add_errata(&volume_id, vec![correction("second")])
+        .unwrap();
+
+    let values = db.find(address).unwrap();
+    assert_eq!(values.len(), original.len());
+    let names: Vec<Vec<String>> = values
+        .iter()
+        .map(|v| v.names_as_strings().unwrap())
+        .collect();
+    assert!(names.contains(&vec!["second".to_string()]));
+    assert!(!names.contains(&vec!["first".to_string()]));
+
+    db.generate_manifest().unwrap();
+    let sequences: Vec<u32> = db
+        .manifest()
+        .unwrap()
+        .errata_cids
+        .iter()
+        .map(|e| e.sequence)
+        .collect();
+    assert_eq!(sequences, vec![1, 2]);
+}
+
+#[test]
+fn full_transformation_keeps_files_matching_manifest() {
+    let db = ephemeral_copy(&nametags_db());
+    // The sample manifest is for an earlier spec version, so every Chapter is formed.
+    db.full_transformation().unwrap();
+    let inventory = db.inventory().unwrap();
+    let (_vol, _chap, modified, _size) = &inventory[0];
+    let (_vol, _chap, unchanged, _size) = &inventory[1];
+    let original = fs::read(modified).unwrap();
+    fs::write(modified, b"modified").unwrap();
+    // A file that is written again has a new modification time.
+    let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let file = fs::File::options().write(true).open(unchanged).unwrap();
+    file.set_modified(old).unwrap();
+    drop(file);
+
+    db.full_transformation().unwrap();
+    // Only the modified file was formed again.
+    assert_eq!(fs::read(modified).unwrap(), original);
+    assert_eq!(fs::metadata(unchanged).unwrap().modified().unwrap(), old);
+}
+
+#[test]
+fn full_transformation_with_limited_parallelism() {
+    let form = |parallelism: Parallelism| {
+        let mut db = ephemeral_copy(&nametags_db());
+        db.config.parallelism = parallelism;
+        fs::remove_dir_all(&db.config.data_dir).unwrap();
+        db.full_transformation().unwrap();
+        db.inventory()
+            .unwrap()
+            .iter()
+            .map(|(_, _, path, _)| fs::read(path).unwrap())
+            .collect::<Vec<Vec<u8>>>()
+    };
+    let expected = form(Parallelism::default());
+    assert!(!expected.is_empty());
+    let limited = Parallelism {
+        threads: Some(2),
+        io_threads: Some(1),
+    };
+    assert_eq!(form(limited), expected);
+}
+
+#[test]
+fn published_volume_reproduced_from_raw_data() {
+    let db = ephemeral_copy(&nametags_db());
+    // The sample Chapter files include data beyond the sample raw data.
+    fs::remove_dir_all(&db.config.data_dir).unwrap();
+    db.full_transformation().unwrap();
+    let manifest = db.manifest().unwrap();
+    let volume_id =
+        NameTagsVolumeId::from_interface_id(manifest.latest_volume_identifier()).unwrap();
+    let report = db.verify_reproduction(&volume_id).unwrap();
+    assert!(!report.chapters.is_empty());
+    assert!(report.is_reproduced(), "{}", report);
+    assert!(!report.dir.exists());
+
+    // A manifest with a different CID is not reproduced.
+    let manifest_path = db.config.manifest_file_path().unwrap();
+    let text = fs::read_to_string(&manifest_path).unwrap();
+    let published = report.chapters[0].published_cid.clone().unwrap();
+    let other = report.chapters[1].published_cid.clone().unwrap();
+    fs::write(&manifest_path, text.replacen(&published, &other, 1)).unwrap();
+    let report = db.verify_reproduction(&volume_id).unwrap();
+    assert_eq!(report.mismatches().len(), 1);
+    assert_eq!(report.mismatches()[0].rebuilt_cid, Some(published));
+    assert!(report.dir.exists());
+    fs::remove_dir_all(&report.dir).unwrap();
+}
+
+#[test]
+fn raw_entry_ranges_recorded_and_checked() {
+    let mut db = ephemeral_copy(&nametags_db());
+    let raw = std::env::temp_dir().join("min_know_test_nametags_raw_entry_ranges");
+    let _ = fs::remove_dir_all(&raw);
+    fs::create_dir_all(&raw).unwrap();
+    let mut names: Vec<_> = fs::read_dir(&db.config.raw_source)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    for name in names.iter().take(2000) {
+        fs::copy(db.config.raw_source.join(name), raw.join(name)).unwrap();
+    }
+    db.config.raw_source = raw.clone();
+    fs::remove_dir_all(&db.config.data_dir).unwrap();
+    db.full_transformation().unwrap();
+
+    let ranges = db.manifest().unwrap().raw_entry_ranges().unwrap();
+    let boundaries: Vec<(String, u64, u64)> = ranges
+        .iter()
+        .map(|(v, r)| (v.interface_id(), r.first_index, r.count))
+        .collect();
+    assert_eq!(
+        boundaries,
+        vec![
+            ("nametags_from_000_000_000".to_string(), 0, 1000),
+            ("nametags_from_000_001_000".to_string(), 1000, 1000)
+        ]
+    );
+    let volume_id = &ranges[1].0;
+    let report = db.verify_reproduction(volume_id).unwrap();
+    assert!(report.range_matches());
+    assert_eq!(report.rebuilt_range.as_ref(), Some(&ranges[1].1));
+
+    // Removing an earlier entry moves the boundaries of the Volume.
+    fs::remove_file(raw.join(&names[0])).unwrap();
+    let report = db.verify_reproduction(volume_id).unwrap();
+    assert!(!report.range_matches());
+    assert!(!report.is_reproduced());
+    assert_eq!(report.rebuilt_range.unwrap().count, 999);
+    fs::remove_dir_all(&report.dir).unwrap();
+    fs::remove_dir_all(&raw).unwrap();
+}
+
+#[test]
+fn builds_are_deterministic() {
+    assert_builds_deterministic(&nametags_db());
+}
+
+#[test]
+fn extend_with_fewer_raw_volumes_than_existing() {
+    let mut db = ephemeral_copy(&nametags_db());
+    // Enough raw data for the first Volume only.
+    let raw = std::env::temp_dir().join("min_know_test_nametags_raw_regression");
+    let _ = fs::remove_dir_all(&raw);
+    fs::create_dir_all(&raw).unwrap();
+    let mut names: Vec<_> = fs::read_dir(&db.config.raw_source)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    for name in names.iter().take(1500) {
+        fs::copy(db.config.raw_source.join(name), raw.join(name)).unwrap();
+    }
+    db.config.raw_source = raw.clone();
+    let before = db.inventory().unwrap();
+    assert!(before
+        .iter()
+        .any(|item| item.0.interface_id() == "nametags_from_000_001_000"));
+
+    // Trusting the existing data is the default.
+    db.extend().unwrap();
+    assert_eq!(db.inventory().unwrap(), before);
+
+    db.config.raw_regression = RawRegressionPolicy::Error;
+    let error = db.extend().unwrap_err().to_string();
+    assert!(error.contains("nametags_from_000_000_000 (number 0, addresses 0-999)"));
+    assert!(error.contains("nametags_from_000_001_000 (number 1, addresses 1000-1999)"));
+    assert_eq!(db.inventory().unwrap(), before);
+
+    db.config.raw_regression = RawRegressionPolicy::RebuildFromRaw;
+    db.extend().unwrap();
+    let after = db.inventory().unwrap();
+    assert!(!after.is_empty());
+    assert!(after
+        .iter()
+        .all(|item| item.0.interface_id() == "nametags_from_000_000_000"));
+    assert_eq!(
+        db.manifest().unwrap().latest_volume_identifier(),
+        "nametags_from_000_000_000"
+    );
+    fs::remove_dir_all(&raw).unwrap();
+}
+
+#[test]
+fn extend_resumes_after_failed_chapters() {
+    let mut db = ephemeral_copy(&nametags_db());
+    let raw = std::env::temp_dir().join("min_know_test_nametags_extend_resume");
+    let _ = fs::remove_dir_all(&raw);
+    fs::create_dir_all(&raw).unwrap();
+    for entry in fs::read_dir(&db.config.raw_source).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, raw.join(path.file_name().unwrap())).unwrap();
+    }
+    db.config.raw_source = raw.clone();
+    assert_eq!(db.extend().unwrap().status(), ExtendStatus::NothingToDo);
+
+    // Remove the latest Volume, so that extending adds it again.
+    for (volume_id, _chapter_id, path, _size) in db.inventory().unwrap() {
+        if volume_id.interface_id() == "nametags_from_000_001_000" {
+            fs::remove_file(path).unwrap();
+        }
+    }
+    db.generate_manifest().unwrap();
+    let manifest = fs::read(db.config.manifest_file_path().unwrap()).unwrap();
+    // A raw file for Chapter "addresses_0xb9" of that Volume cannot be parsed.
+    let corrupt = raw.join("0xb9879f2630f8fda2b3c8ab5b6f18765cd2f5967c");
+    let original = fs::read(&corrupt).unwrap();
+    fs::write(&corrupt, b"{ not json").unwrap();
+
+    let outcome = db.extend().unwrap();
+    assert_eq!(outcome.status(), ExtendStatus::PartiallyFailed);
+    assert_eq!(outcome.volumes_added, vec!["nametags_from_000_001_000"]);
+    assert_eq!(outcome.errors.len(), 1);
+    assert_eq!(outcome.errors[0].chapter_interface_id, "addresses_0xb9");
+    assert!(outcome.chapters_written > 0);
+    assert!(!outcome.manifest_updated);
+    assert_eq!(
+        fs::read(db.config.manifest_file_path().unwrap()).unwrap(),
+        manifest
+    );
+
+    // Once the raw data is fixed, only the failed Chapter is formed.
+    fs::write(&corrupt, original).unwrap();
+    let outcome = db.extend().unwrap();
+    assert_eq!(outcome.status(), ExtendStatus::Extended);
+    assert_eq!(outcome.chapters_written, 1);
+    assert!(outcome.errors.is_empty());
+    assert!(outcome.manifest_updated);
+    assert_eq!(
+        db.manifest().unwrap().latest_volume_identifier(),
+        "nametags_from_000_001_000"
+    );
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.files_verified, audit.files_in_manifest);
+
+    assert_eq!(db.extend().unwrap(), Default::default());
+    fs::remove_dir_all(&raw).unwrap();
+}
+
+#[test]
+fn transform_stream_yields_each_chapter() {
+    let db = ephemeral_copy(&nametags_db());
+    fs::remove_dir_all(&db.config.data_dir).unwrap();
+    let mut stream = db.transform_stream().unwrap();
+    let outcomes: Vec<ChapterOutcome> = stream.by_ref().collect();
+    stream.finish().unwrap();
+
+    let written: Vec<(String, String, u64)> = outcomes
+        .iter()
+        .filter_map(|o| match &o.status {
+            ChapterStatus::Written { bytes, .. } => Some((
+                o.volume_interface_id.clone(),
+                o.chapter_interface_id.clone(),
+                *bytes,
+            )),
+            _ => None,
+        })
+        .collect();
+    let inventory = db.inventory().unwrap();
+    assert!(!inventory.is_empty());
+    assert_eq!(written.len(), inventory.len());
+    for (volume_id, chapter_id, _path, size) in inventory {
+        assert!(written.contains(&(volume_id.interface_id(), chapter_id.interface_id(), size)));
+    }
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.files_verified, audit.files_in_manifest);
+
+    // Nothing new to form.
+    let mut stream = db.extend_stream().unwrap();
+    assert_eq!(stream.next(), None);
+    assert_eq!(stream.finish().unwrap().status(), ExtendStatus::NothingToDo);
+}
+
+#[test]
+fn full_transformation_returns_failed_chapters() {
+    let mut db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Ephemeral).unwrap();
+    let raw = std::env::temp_dir().join("min_know_test_nametags_failed_chapters");
+    let _ = fs::remove_dir_all(&raw);
+    fs::create_dir_all(&raw).unwrap();
+    for entry in fs::read_dir(&nametags_db().config.raw_source).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, raw.join(path.file_name().unwrap())).unwrap();
+    }
+    db.config.raw_source = raw.clone();
+    let corrupt = raw.join("0xb9879f2630f8fda2b3c8ab5b6f18765cd2f5967c");
+    let original = fs::read(&corrupt).unwrap();
+    fs::write(&corrupt, b"{ not json").unwrap();
+
+    let error = db.full_transformation().unwrap_err();
+    let failed = error.downcast_ref::<ChaptersFailed>().unwrap();
+    assert_eq!(failed.operation, "full_transformation");
+    assert_eq!(failed.failures.len(), 1);
+    assert_eq!(failed.failures[0].chapter_interface_id, "addresses_0xb9");
+    assert_eq!(
+        failed.failures[0].volume_interface_id,
+        "nametags_from_000_001_000"
+    );
+    assert!(!db.config.manifest_file_path().unwrap().exists());
+
+    fs::write(&corrupt, original).unwrap();
+    db.full_transformation().unwrap();
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.files_verified, audit.files_in_manifest);
+    fs::remove_dir_all(&raw).unwrap();
+}
+
+#[test]
+fn repair_from_raw_returns_failed_chapters() {
+    let mut db = ephemeral_copy(&nametags_db());
+    let raw = std::env::temp_dir().join("min_know_test_nametags_failed_repair");
+    let _ = fs::remove_dir_all(&raw);
+    fs::create_dir_all(&raw).unwrap();
+    for entry in fs::read_dir(&db.config.raw_source).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, raw.join(path.file_name().unwrap())).unwrap();
+    }
+    db.config.raw_source = raw.clone();
+    let corrupt = raw.join("0xb9879f2630f8fda2b3c8ab5b6f18765cd2f5967c");
+    let original = fs::read(&corrupt).unwrap();
+    fs::write(&corrupt, b"{ not json").unwrap();
+    let (_vol, _chap, path, _size) = db
+        .inventory()
+        .unwrap()
+        .into_iter()
+        .find(|(vol, chap, _, _)| {
+            vol.interface_id() == "nametags_from_000_001_000"
+                && chap.interface_id() == "addresses_0xb9"
+        })
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let error = db.repair_from_raw().unwrap_err();
+    let failed = error.downcast_ref::<ChaptersFailed>().unwrap();
+    assert_eq!(failed.operation, "repair_from_raw");
+    assert_eq!(failed.failures.len(), 1);
+    assert_eq!(failed.failures[0].chapter_interface_id, "addresses_0xb9");
+    assert!(!path.exists());
+
+    fs::write(&corrupt, original).unwrap();
+    db.repair_from_raw().unwrap();
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.files_verified, audit.files_in_manifest);
+    fs::remove_dir_all(&raw).unwrap();
+}
+
+#[test]
+fn encrypted_database_queried_with_key_only() {
+    let address = "0xb9879f2630f8fda2b3c8ab5b6f18765cd2f5967c";
+    let mut db = ephemeral_copy(&nametags_db());
+    db.config.encryption_key = Some(EncryptionKey::new([7; 32]));
+    fs::remove_dir_all(&db.config.data_dir).unwrap();
+    db.full_transformation().unwrap();
+    let (_vol, _chap, path, _size) = &db.inventory().unwrap()[0];
+    assert!(is_encrypted(&fs::read(path).unwrap()));
+    let values = db.find(address).unwrap();
+    assert!(!values.is_empty());
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.files_verified, audit.files_in_manifest);
+
+    // Operations that rely on public CIDs are not available.
+    let car = db.config.base_dir_nature_dependent.join("nametags.car");
+    let error = db.export_car(&car).unwrap_err();
+    let encrypted = error.downcast_ref::<EncryptedDatabase>().unwrap();
+    assert_eq!(encrypted.operation, "export_car");
+    assert!(!car.exists());
+
+    db.config.encryption_key = Some(EncryptionKey::new([8; 32]));
+    assert!(db.find(address).is_err());
+    db.config.encryption_key = None;
+    let error = format!("{:#}", db.find(address).unwrap_err());
+    assert!(error.contains("encryption_key is not set"), "{}", error);
+}
+
+#[test]
+fn read_only_database_is_not_written() {
+    let mut db = ephemeral_copy(&nametags_db());
+    db.config.read_only = true;
+    let manifest_path = db.config.manifest_file_path().unwrap();
+    let manifest = fs::read(&manifest_path).unwrap();
+
+    let error = db.generate_manifest().unwrap_err();
+    let read_only = error.downcast_ref::<ReadOnlyDatabase>().unwrap();
+    assert_eq!(read_only.operation, "generate_manifest");
+    assert_eq!(read_only.path, db.config.base_dir_nature_dependent);
+    for result in [
+        db.extend().map(|_| ()),
+        db.full_transformation(),
+        db.clear_retention_policy(),
+    ] {
+        assert!(result.unwrap_err().is::<ReadOnlyDatabase>());
+    }
+    assert_eq!(fs::read(&manifest_path).unwrap(), manifest);
+    assert!(!db.config.lock_file_path().unwrap().exists());
+
+    // Queries and audits still work, without recording verified files.
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.files_verified, audit.files_in_manifest);
+    assert!(!db.config.verification_cache_path().unwrap().exists());
+    let mut sample = nametags_db();
+    sample.config.read_only = true;
+    sample.get_sample_data().unwrap();
+
+    // Samples that are not present are not created.
+    let mut empty: Todd<NameTagsSpec> =
+        Todd::init(DataKind::NameTags, DirNature::Ephemeral).unwrap();
+    empty.config.dir_nature = DirNature::Sample;
+    empty.config.read_only = true;
+    assert!(empty
+        .get_sample_data()
+        .unwrap_err()
+        .is::<ReadOnlyDatabase>());
+    assert!(!empty.config.data_dir.exists());
+}
+
+#[test]
+fn sample_files_checked_and_refreshed() {
+    // A fresh samples directory, filled from the local repository.
+    let mut db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Ephemeral).unwrap();
+    db.config.dir_nature = DirNature::Sample;
+    db.get_sample_data().unwrap();
+    let manifest = SamplesManifest::read(&db.config.samples_manifest_path()).unwrap();
+    assert_eq!(manifest.database_interface_id, "nametags");
+
+    let chapter = db
+        .config
+        .data_dir
+        .join("addresses_0x00")
+        .join("nametags_from_000_000_000_addresses_0x00.ssz");
+    let raw = db
+        .config
+        .raw_source
+        .join("0xb9879f2630f8fda2b3c8ab5b6f18765cd2f5967c");
+    let chapter_bytes = fs::read(&chapter).unwrap();
+    let mut corrupt = chapter_bytes.clone();
+    corrupt[100] ^= 0xff;
+    fs::write(&chapter, corrupt).unwrap();
+    let raw_bytes = fs::read(&raw).unwrap();
+    fs::write(&raw, b"{}").unwrap();
+
+    let error = db.get_sample_data().unwrap_err();
+    let mismatch = error.downcast_ref::<SamplesMismatch>().unwrap();
+    let paths: Vec<&str> = mismatch
+        .mismatches
+        .iter()
+        .map(|m| m.path.as_str())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            "nametags/addresses_0x00/nametags_from_000_000_000_addresses_0x00.ssz",
+            "raw_source_nametags/0xb9879f2630f8fda2b3c8ab5b6f18765cd2f5967c",
+        ]
+    );
+    assert!(matches!(
+        mismatch.mismatches[0].reason,
+        SampleMismatchReason::DifferentHash { .. }
+    ));
+    assert!(matches!(
+        mismatch.mismatches[1].reason,
+        SampleMismatchReason::DifferentSize { .. }
+    ));
+
+    db.refresh_sample_data().unwrap();
+    assert_eq!(fs::read(&chapter).unwrap(), chapter_bytes);
+    assert_eq!(fs::read(&raw).unwrap(), raw_bytes);
+    fs::remove_dir_all(&db.config.base_dir_nature_dependent).unwrap();
+}
+
+#[test]
+fn manifest_history_kept_and_restored() {
+    let mut db = ephemeral_copy(&nametags_db());
+    db.config.keep_manifest_history = true;
+    db.generate_manifest().unwrap();
+    let history = db.manifest_history().unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].spec_version, NameTagsSpec::spec_version());
+    // Generated in the same second as the next manifest, so recorded as earlier.
+    let earlier = db
+        .config
+        .manifest_history_path(&history[0].spec_version, history[0].generated - 60);
+    fs::rename(&history[0].path, &earlier).unwrap();
+    let full = db.manifest().unwrap();
+
+    let (_volume_id, _chapter_id, path, _size) = db.inventory().unwrap().remove(0);
+    fs::remove_file(path).unwrap();
+    db.generate_manifest().unwrap();
+    let history = db.manifest_history().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].path, earlier);
+    let listed = |m: &<NameTagsSpec as DataSpec>::AssociatedManifest| m.cids().unwrap().len();
+    assert_eq!(listed(&db.manifest().unwrap()), listed(&full) - 1);
+    assert_eq!(db.manifest_at(&earlier).unwrap(), full);
+
+    db.use_manifest_version(&history[0]).unwrap();
+    assert_eq!(db.manifest().unwrap(), full);
+    // Without the setting, no history is kept.
+    db.config.keep_manifest_history = false;
+    db.generate_manifest().unwrap();
+    assert_eq!(db.manifest_history().unwrap().len(), 2);
+}
+
+#[test]
+fn query_cache_used_until_manifest_changes() {
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let mut db = ephemeral_copy(&nametags_db());
+    db.config.query_cache = Some(QueryCacheLimits {
+        max_entries: 10,
+        ttl_secs: 600,
+    });
+    let expected = db.find(address).unwrap();
+    let corrupt = db
+        .config
+        .data_dir
+        .join("addresses_0xde/nametags_from_000_000_000_addresses_0xde.ssz");
+    fs::write(&corrupt, b"not a chapter").unwrap();
+    // Cached values are used, including for other spellings of the key.
+    assert_eq!(db.find(address).unwrap(), expected);
+    let upper_case = "0xDE0B295669A9FD93D5F28D9EC85E40F4CB697BAE";
+    assert_eq!(db.clone().find(upper_case).unwrap(), expected);
+    assert!(db.find_with(address, FindMode::Strict).is_err());
+
+    let manifest_path = db.config.manifest_file_path().unwrap();
+    let mut manifest = fs::read_to_string(&manifest_path).unwrap();
+    manifest.push('\n');
+    fs::write(&manifest_path, manifest).unwrap();
+    assert!(db.find(address).is_err());
+}
+
+#[test]
+fn manifest_changes_from_newer_manifest() {
+    let sample = nametags_db();
+    let newer = fs::read_to_string(sample.config.manifest_file_path().unwrap()).unwrap();
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+
+    // Without a local manifest, everything is new.
+    let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Ephemeral).unwrap();
+    let changes = db.manifest_changes(&newer, &[address]).unwrap();
+    assert_eq!(
+        changes.new_volumes,
+        vec!["nametags_from_000_000_000", "nametags_from_000_001_000"]
+    );
+    let coverage = changes.new_coverage.clone().unwrap();
+    assert_eq!((coverage.first, coverage.last), (0, 1999));
+    assert_eq!(changes.new_files, 512);
+    assert_eq!(changes.for_keys.chapters, vec!["addresses_0xde"]);
+    assert_eq!(changes.for_keys.files.len(), 2);
+    assert_eq!(changes.for_keys.estimated_bytes, None);
+    assert!(changes.to_string().contains("covering addresses 0-1999"));
+
+    // A local copy of the same manifest has no changes.
+    let db = ephemeral_copy(&sample);
+    let changes = db.manifest_changes(&newer, &[address]).unwrap();
+    assert!(changes.is_empty());
+    assert!(changes.for_keys.files.is_empty());
+    let json = serde_json::to_string(&changes).unwrap();
+    assert!(json.contains("\"new_volumes\":[]"));
+}
+
+#[test]
+fn provenance_stated_in_manifest_and_audit() {
+    let mut db = ephemeral_copy(&nametags_db());
+    assert!(db.check_completeness().unwrap().provenance.is_empty());
+    let provenance = ManifestProvenance {
+        source_name: Some("Sample nametags".to_string()),
+        retrieval_date: Some("2022-10-01".to_string()),
+        license: Some("CC0-1.0".to_string()),
+        ..Default::default()
+    };
+    db.config.manifest_provenance = provenance.clone();
+    db.generate_manifest().unwrap();
+
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.provenance, provenance);
+    assert!(audit.to_string().contains("License: CC0-1.0."));
+    assert_eq!(audit.to_report().provenance, provenance);
+
+    // Kept when the manifest is generated again without configured provenance.
+    db.config.manifest_provenance = ManifestProvenance::default();
+    db.generate_manifest().unwrap();
+    assert_eq!(db.check_completeness().unwrap().provenance, provenance);
+}
+
+#[test]
+fn successor_stated_in_manifest_and_audit() {
+    let mut db = ephemeral_copy(&nametags_db());
+    let previous = fs::read_to_string(db.config.manifest_file_path().unwrap()).unwrap();
+    assert!(!previous.contains("superseded_by"));
+    let successor = ManifestSuccessor {
+        database_interface_id: "nametags_v2".to_string(),
+        manifest_cid: Some("QmaGbMhGwC2tnCHmiu3xtnLbdWquNC7VT5U4kQGLxy6qEh".to_string()),
+        topic: Some("/ipns/nametags-v2".to_string()),
+        note: None,
+    };
+    db.config.manifest_superseded_by = Some(successor.clone());
+    db.generate_manifest().unwrap();
+    assert_eq!(db.manifest().unwrap().superseded_by(), Some(&successor));
+
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.superseded_by, Some(successor.clone()));
+    assert!(audit.to_string().contains("Superseded by nametags_v2"));
+    assert_eq!(audit.to_report().superseded_by, Some(successor.clone()));
+
+    // Seen by users of the old manifest when they compare it to the new one.
+    let newer = fs::read_to_string(db.config.manifest_file_path().unwrap()).unwrap();
+    let old_db = ephemeral_copy(&nametags_db());
+    let changes = old_db.manifest_changes(&newer, &[]).unwrap();
+    assert_eq!(changes.superseded_by, Some(successor.clone()));
+    assert!(changes
+        .to_string()
+        .starts_with("Superseded by nametags_v2 (manifest CID"));
+
+    // Kept when the manifest is generated again without a configured successor.
+    db.config.manifest_superseded_by = None;
+    db.generate_manifest().unwrap();
+    assert_eq!(
+        db.check_completeness().unwrap().superseded_by,
+        Some(successor)
+    );
+}
+
+#[test]
+fn labels_merged_across_volumes() {
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let db = ephemeral_copy(&nametags_db());
+    let key = NameTagsRecordKey::from_address(address).unwrap();
+    let chapter_id = NameTagsSpec::record_key_to_chapter_id(&key).unwrap();
+    // Later raw batches that edit the address.
+    let later = [
+        (2000, vec!["Ethereum Foundation"], Some("rolodeth")),
+        (3000, vec!["EF"], None),
+    ];
+    for (first_address, names, source) in later {
+        let names = names.into_iter().map(String::from).collect();
+        let record = NameTagsRecord {
+            key: key.clone(),
+            value: NameTagsRecordValue::from_strings_with_source(names, vec![], source),
+        };
+        let volume_id = NameTagsVolumeId { first_address };
+        let chapter = NameTagsChapter::from_records(&volume_id, &chapter_id, vec![record]).unwrap();
+        let path = db
+            .config
+            .chapter_dir_path(&chapter_id)
+            .join(chapter.filename());
+        fs::write(path, chapter.as_serialized_bytes().unwrap()).unwrap();
+    }
+    let by_volume = db.find_by_volume(address).unwrap();
+    let volumes: Vec<u32> = by_volume.iter().map(|(v, _)| v.first_address).collect();
+    assert_eq!(volumes, vec![0, 1000, 2000, 3000]);
+    assert_eq!(db.find(address).unwrap().len(), 3);
+
+    let labels = NametagClient::new(db).labels(address).unwrap();
+    let names: Vec<(&str, Option<&str>)> = labels
+        .names
+        .iter()
+        .map(|l| (l.text.as_str(), l.source.as_deref()))
+        .collect();
+    // "EthDev" (no source) is replaced by the later "EF" (no source).
+    assert_eq!(
+        names,
+        vec![("EF", None), ("Ethereum Foundation", Some("rolodeth"))]
+    );
+    assert_eq!(
+        labels.names[0].volume_interface_id,
+        "nametags_from_000_003_000"
+    );
+    assert!(labels.tags.is_empty());
+}
+
+#[test]
+fn built_chapters_published_without_raw_data() {
+    let db: Todd<NameTagsSpec> = Todd::init(DataKind::NameTags, DirNature::Ephemeral).unwrap();
+    let address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae";
+    let chapter_id = NameTagsChapterId::from_interface_id("addresses_0xde").unwrap();
+    let mut chapters = vec![];
+    for (n, name) in ["EthDev", "Ethereum Foundation"].iter().enumerate() {
+        let volume_id = NameTagsVolumeId::nth_id(n as u32).unwrap();
+        let mut builder = ChapterBuilder::<NameTagsSpec>::new(&volume_id, &chapter_id).unwrap();
+        builder
+            .add_raw(
+                address,
+                NameTagsRecordValue::from_strings(vec![name.to_string()], vec![]),
+            )
+            .unwrap();
+        chapters.push(builder.build().unwrap());
+    }
+    db.save_built_chapters(chapters).unwrap();
+    db.generate_manifest().unwrap();
+
+    assert_eq!(
+        db.manifest().unwrap().latest_volume_identifier(),
+        "nametags_from_000_001_000"
+    );
+    let names: Vec<String> = db
+        .find(address)
+        .unwrap()
+        .iter()
+        .flat_map(|v| v.names_as_strings().unwrap())
+        .collect();
+    assert_eq!(names, vec!["EthDev", "Ethereum Foundation"]);
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.files_in_manifest, 2);
+    assert_eq!(audit.files_verified, 2);
+}
+
+#[test]
+fn audit_reports_hashing_throughput() {
+    let mut db = ephemeral_copy(&nametags_db());
+    db.config.hash_buffer_bytes = Some(64);
+    let audit = db.check_completeness_forced().unwrap();
+    assert_eq!(audit.files_verified, audit.files_in_manifest);
+    assert_eq!(audit.throughput.files_hashed, audit.files_verified);
+    assert_eq!(audit.throughput.bytes_hashed, audit.bytes_verified);
+    assert!(audit.throughput.total_ms >= audit.throughput.hashing_ms);
+    assert!(audit.throughput.mb_per_sec() > 0.0);
+    assert_eq!(audit.to_report().throughput, audit.throughput);
+
+    // Files unchanged since the first audit are not hashed again.
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.files_verified, audit.files_in_manifest);
+    assert_eq!(audit.throughput.files_hashed, 0);
+}
+
+#[test]
+fn init_with_other_data_kind_names_supported_kinds() {
+    let error = Todd::<NameTagsSpec>::init(DataKind::Signatures, DirNature::Ephemeral).unwrap_err();
+    let unsupported = error.downcast_ref::<UnsupportedDataKind>().unwrap();
+    assert_eq!(unsupported.requested, "signatures");
+    assert_eq!(unsupported.implemented, vec!["nametags".to_string()]);
+}
+
+#[test]
+fn manifest_from_other_chapter_types_rejected() {
+    let db = ephemeral_copy(&nametags_db());
+    db.generate_manifest().unwrap();
+    let mut manifest = db.manifest().unwrap();
+    assert_eq!(
+        manifest.schema_fingerprint,
+        Some(NameTagsSpec::schema_fingerprint())
+    );
+
+    // Same spec version, but made by a build with other Chapter types.
+    manifest.schema_fingerprint = Some("00".repeat(32));
+    let path = db.config.manifest_file_path().unwrap();
+    fs::write(&path, serde_json::to_string(&manifest).unwrap()).unwrap();
+    let error = db.manifest().unwrap_err();
+    let mismatch = error.downcast_ref::<SchemaMismatch>().unwrap();
+    assert_eq!(mismatch.found, "00".repeat(32));
+    assert_eq!(mismatch.expected, NameTagsSpec::schema_fingerprint());
+    let issues = db.lint_manifest().unwrap();
+    assert!(issues.iter().any(|i| i.location == "schema_fingerprint"));
+
+    // Manifests made before the fingerprint was recorded are read.
+    manifest.schema_fingerprint = None;
+    fs::write(&path, serde_json::to_string(&manifest).unwrap()).unwrap();
+    assert!(db.manifest().is_ok());
+}
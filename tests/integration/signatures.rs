@@ -0,0 +1,800 @@
+use std::{fs, sync::mpsc, time::Duration};
+
+use cid::{
+    multihash::{Code, MultihashDigest},
+    Cid,
+};
+use min_know::{
+    config::choices::{DataKind, DirNature, PathPair},
+    database::{
+        acquisition::{FetchOnMiss, ObtainedChapter},
+        lock::{DatabaseLocked, LockHolder},
+        manifest_only::ManifestSource,
+        probe::SourceHealth,
+        signatures::SignatureClient,
+        types::Todd,
+        watch::DatabaseChange,
+    },
+    manifest::mirrors::{FileSource, ManifestMirrors},
+    specs::{
+        signatures::{SignaturesChapter, SignaturesChapterId, SignaturesSpec, SignaturesVolumeId},
+        traits::{ChapterIdMethods, ChapterMethods, ManifestMethods, VolumeIdMethods},
+    },
+    utils::gateway::Gateway,
+};
+use reqwest::Url;
+
+use crate::common::{
+    assert_builds_deterministic, ephemeral_copy, serve_in_order, serve_once, serve_paths,
+    signatures_db,
+};
+
+#[test]
+fn selector_of_known_text() {
+    let client = SignatureClient::new(signatures_db());
+    let lookup = client
+        .selector_of("Transfer(address, address, uint256)")
+        .unwrap();
+    assert_eq!(lookup.selector, "ddf252ad");
+    assert_eq!(lookup.text, "Transfer(address,address,uint256)");
+    assert!(lookup.present_locally);
+    assert!(client
+        .texts_of(&lookup.selector)
+        .unwrap()
+        .contains(&lookup.text));
+}
+
+#[test]
+fn sample_raw_signatures_valid() {
+    let report = SignatureClient::new(signatures_db())
+        .validation_report()
+        .unwrap();
+    assert!(report.skipped.is_empty());
+}
+
+#[test]
+fn invalid_raw_data_stops_build_before_chapters() {
+    let mut db: Todd<SignaturesSpec> =
+        Todd::init(DataKind::Signatures, DirNature::Ephemeral).unwrap();
+    let sample_raw = signatures_db().config.raw_source;
+    let report = signatures_db().validate_raw().unwrap();
+    assert!(report.is_valid());
+    assert_eq!(
+        report.files_checked,
+        fs::read_dir(&sample_raw).unwrap().count()
+    );
+
+    let raw = db.config.base_dir_nature_dependent.join("raw");
+    fs::create_dir_all(&raw).unwrap();
+    for entry in fs::read_dir(&sample_raw).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, raw.join(path.file_name().unwrap())).unwrap();
+    }
+    fs::write(raw.join("a9059cb"), "transfer(address,uint256)").unwrap();
+    db.config.raw_source = raw;
+    db.config.validate_raw_first = true;
+    let err = db.full_transformation().unwrap_err();
+    assert!(err.to_string().contains("1 issues"));
+    assert!(!db.config.data_dir.exists());
+}
+
+#[test]
+fn manifest_draft_reused_then_removed() {
+    let mut db: Todd<SignaturesSpec> =
+        Todd::init(DataKind::Signatures, DirNature::Ephemeral).unwrap();
+    db.config.raw_source = signatures_db().config.raw_source;
+    db.full_transformation().unwrap();
+    let draft_path = db.config.manifest_draft_path().unwrap();
+    assert!(!draft_path.exists());
+    let manifest = db.manifest().unwrap();
+    assert!(!manifest.chapter_cids.is_empty());
+
+    // A CID recorded in a draft (e.g., from an interrupted build) is used as-is.
+    let first = &manifest.chapter_cids[0];
+    let (_vol, _chap, _path, size) = &db.inventory().unwrap()[0];
+    let entry = format!(
+        "{{\"volume_interface_id\":\"{}\",\"chapter_interface_id\":\"{}\",\"cid_v0\":\"draft_cid\",\"bytes\":{}}}\n",
+        first.volume_interface_id, first.chapter_interface_id, size
+    );
+    fs::write(&draft_path, entry).unwrap();
+    db.generate_manifest().unwrap();
+    assert_eq!(db.manifest().unwrap().chapter_cids[0].cid_v0, "draft_cid");
+    assert!(!draft_path.exists());
+}
+
+#[test]
+fn foreign_directories_detected() {
+    let dir = std::env::temp_dir().join("min_know_test_signatures_foreign");
+    let _ = fs::remove_dir_all(&dir);
+    let paths = PathPair {
+        raw_source: None,
+        processed_data_dir: Some(dir.clone()),
+    };
+    // An address appearance index Chapter directory.
+    fs::create_dir_all(dir.join("signatures").join("chapter_0x00")).unwrap();
+    let err = Todd::<SignaturesSpec>::init(DataKind::Signatures, DirNature::Custom(paths.clone()))
+        .unwrap_err();
+    assert!(err.to_string().contains("chapter_0x00"));
+
+    let db: Todd<SignaturesSpec> =
+        Todd::init_ignoring_unknown_dirs(DataKind::Signatures, DirNature::Custom(paths)).unwrap();
+    assert!(db.inventory().unwrap().is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn sample_manifest_lints_clean() {
+    assert_eq!(signatures_db().lint_manifest().unwrap(), vec![]);
+}
+
+#[test]
+fn builds_are_deterministic() {
+    assert_builds_deterministic(&signatures_db());
+}
+
+#[cfg(unix)]
+#[test]
+fn identical_chapters_linked_across_databases() {
+    use std::os::unix::fs::MetadataExt;
+
+    let raw_source = signatures_db().config.raw_source;
+    let mut first: Todd<SignaturesSpec> =
+        Todd::init(DataKind::Signatures, DirNature::Ephemeral).unwrap();
+    first.config.raw_source = raw_source.clone();
+    first.full_transformation().unwrap();
+
+    let mut second: Todd<SignaturesSpec> =
+        Todd::init(DataKind::Signatures, DirNature::Ephemeral).unwrap();
+    second.config.raw_source = raw_source;
+    second.config.shared_data_dirs = vec![first.config.data_dir.clone()];
+    second.full_transformation().unwrap();
+    assert_eq!(
+        second.manifest().unwrap().chapter_cids,
+        first.manifest().unwrap().chapter_cids
+    );
+
+    let (_vol, chapter, path, _size) = &second.inventory().unwrap()[0];
+    let shared = first
+        .config
+        .chapter_dir_path(chapter)
+        .join(path.file_name().unwrap());
+    assert_eq!(
+        fs::metadata(path).unwrap().ino(),
+        fs::metadata(shared).unwrap().ino()
+    );
+}
+
+#[test]
+fn compiled_index_used_while_manifest_unchanged() {
+    let selector = "0xddf252ad";
+    let db = ephemeral_copy(&signatures_db());
+    let from_files = db.find(selector).unwrap();
+    assert!(!from_files.is_empty());
+    assert!(db.compile_index().unwrap() > 0);
+
+    // The Chapter files are no longer needed.
+    fs::remove_dir_all(&db.config.data_dir).unwrap();
+    assert_eq!(db.find(selector).unwrap(), from_files);
+
+    // A different manifest means the index is not used.
+    let manifest = db.config.manifest_file_path().unwrap();
+    let mut contents = fs::read_to_string(&manifest).unwrap();
+    contents.push('\n');
+    fs::write(&manifest, contents).unwrap();
+    assert!(db.find(selector).is_err());
+}
+
+#[test]
+fn obtain_chapter_verifies_download() {
+    let db = ephemeral_copy(&signatures_db());
+    let (volume_id, chapter_id, path, _size) = db.inventory().unwrap().remove(0);
+    let original = fs::read(&path).unwrap();
+    let gateway = Gateway::new("http://127.0.0.1:1").unwrap();
+    let obtained = db
+        .obtain_chapter(&volume_id, &chapter_id, &gateway)
+        .unwrap();
+    assert_eq!(obtained, ObtainedChapter::AlreadyPresent(path.clone()));
+
+    fs::remove_file(&path).unwrap();
+    // A file that does not match the manifest is not installed.
+    let gateway = Gateway::new(&serve_once(b"modified".to_vec())).unwrap();
+    assert!(db
+        .obtain_chapter(&volume_id, &chapter_id, &gateway)
+        .is_err());
+    assert!(!path.exists());
+
+    let gateway = Gateway::new(&serve_once(original.clone())).unwrap();
+    let obtained = db
+        .obtain_chapter(&volume_id, &chapter_id, &gateway)
+        .unwrap();
+    let bytes = original.len() as u64;
+    assert_eq!(
+        obtained,
+        ObtainedChapter::Downloaded {
+            path: path.clone(),
+            bytes
+        }
+    );
+    assert_eq!(fs::read(&path).unwrap(), original);
+}
+
+#[test]
+fn orphan_files_reported_and_cleaned() {
+    let db = ephemeral_copy(&signatures_db());
+    assert!(db.check_completeness().unwrap().orphan_files.is_empty());
+
+    let (_volume_id, chapter_id, path, _size) = db.inventory().unwrap().remove(0);
+    let volume_id = SignaturesVolumeId::nth_id(999).unwrap();
+    let old_build = SignaturesChapter::new_empty(&volume_id, &chapter_id).filename();
+    let chapter_dir = path.parent().unwrap();
+    fs::copy(&path, chapter_dir.join(old_build)).unwrap();
+    fs::write(chapter_dir.join("notes.txt"), b"notes").unwrap();
+
+    let audit = db.check_completeness().unwrap();
+    assert_eq!(audit.orphan_files.len(), 2);
+    assert!(audit.absent_individual_files.is_empty());
+
+    let dry_run = db.clean_orphans(true).unwrap();
+    assert_eq!(dry_run, audit.orphan_files);
+    assert!(dry_run.iter().all(|orphan| orphan.path().exists()));
+    db.clean_orphans(false).unwrap();
+    assert!(dry_run.iter().all(|orphan| !orphan.path().exists()));
+    assert!(db.check_completeness().unwrap().orphan_files.is_empty());
+}
+
+#[test]
+fn audit_and_inventory_reports_use_interface_ids() {
+    let db = ephemeral_copy(&signatures_db());
+    let inventory = db.inventory_report().unwrap();
+    assert_eq!(inventory.spec_id, "signatures");
+    let first = &inventory.files[0];
+    let (volume_id, chapter_id, _path, _size) = db.inventory().unwrap().remove(0);
+    assert_eq!(first.volume_interface_id, volume_id.interface_id());
+    assert_eq!(first.chapter_interface_id, chapter_id.interface_id());
+
+    fs::remove_file(&first.path).unwrap();
+    let chapter_dir = first.path.parent().unwrap();
+    fs::write(chapter_dir.join("notes.txt"), b"notes").unwrap();
+    let audit = db.check_completeness().unwrap();
+    let report = audit.to_report();
+    assert_eq!(report.files_in_manifest, inventory.files.len());
+    assert_eq!(report.files_verified, inventory.files.len() - 1);
+    let bytes: u64 = inventory.files[1..].iter().map(|f| f.bytes).sum();
+    assert_eq!(report.bytes_verified, bytes);
+    assert!(report.percent_complete() < 100.0);
+    assert!(audit.to_string().starts_with(&format!(
+        "{} of {} files verified",
+        report.files_verified, report.files_in_manifest
+    )));
+    let json: serde_json::Value = serde_json::from_str(&audit.to_json().unwrap()).unwrap();
+    let absent = &json["absent_files"][0];
+    assert_eq!(
+        absent["volume_interface_id"],
+        first.volume_interface_id.as_str()
+    );
+    assert_eq!(
+        absent["chapter_interface_id"],
+        first.chapter_interface_id.as_str()
+    );
+    assert_eq!(absent["reason"], "no_file");
+    assert_eq!(
+        json["orphan_files"][0]["chapter_interface_id"],
+        serde_json::Value::Null
+    );
+    assert_eq!(report, serde_json::from_value(json).unwrap());
+}
+
+#[test]
+fn writes_refused_while_another_process_holds_lock() {
+    let db = ephemeral_copy(&signatures_db());
+    let lock_path = db.config.lock_file_path().unwrap();
+    // Process 1 is always running.
+    let holder = LockHolder {
+        pid: 1,
+        since: 0,
+        operation: "extend".to_string(),
+    };
+    fs::write(&lock_path, serde_json::to_vec(&holder).unwrap()).unwrap();
+    let err = db.generate_manifest().unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<DatabaseLocked>().unwrap().holder,
+        Some(holder)
+    );
+    // Reading is unaffected.
+    assert!(db.find("ddf252ad").is_ok());
+
+    fs::remove_file(&lock_path).unwrap();
+    db.generate_manifest().unwrap();
+    assert!(!lock_path.exists());
+}
+
+#[test]
+fn find_fetches_absent_chapter_when_enabled() {
+    let selector = "0xddf252ad";
+    let mut db = ephemeral_copy(&signatures_db());
+    let expected = db.find(selector).unwrap();
+    let chapter_id = SignaturesChapterId::from_interface_id("signatures_0xdd").unwrap();
+    let chapter_dir = db.config.chapter_dir_path(&chapter_id);
+    let mut files: Vec<_> = fs::read_dir(&chapter_dir)
+        .unwrap()
+        .map(|f| f.unwrap().path())
+        .collect();
+    files.sort();
+    let contents: Vec<Vec<u8>> = files.iter().map(|f| fs::read(f).unwrap()).collect();
+    fs::remove_dir_all(&chapter_dir).unwrap();
+    let err = db.find(selector).unwrap_err();
+    assert!(err.to_string().contains("not present locally"));
+
+    // Files are downloaded in the order of the plan (by Volume).
+    db.config.fetch_on_miss = Some(FetchOnMiss {
+        gateway_url: serve_in_order(contents.clone()),
+        subdomain_gateway: false,
+        max_bytes: 1_000_000,
+    });
+    assert_eq!(db.find(selector).unwrap(), expected);
+    assert_eq!(fs::read(&files[1]).unwrap(), contents[1]);
+
+    // Downloads stop before the size cap is exceeded.
+    fs::remove_dir_all(&chapter_dir).unwrap();
+    db.config.fetch_on_miss = Some(FetchOnMiss {
+        gateway_url: serve_in_order(contents.clone()),
+        subdomain_gateway: false,
+        max_bytes: contents[0].len() as u64,
+    });
+    let err = db.find(selector).unwrap_err();
+    assert!(err.to_string().contains("1 of 2 files remaining"));
+    assert!(files[0].exists());
+    assert!(!files[1].exists());
+
+    // A cap smaller than one Chapter file obtains nothing.
+    fs::remove_dir_all(&chapter_dir).unwrap();
+    db.config.fetch_on_miss = Some(FetchOnMiss {
+        gateway_url: serve_in_order(contents.clone()),
+        subdomain_gateway: false,
+        max_bytes: contents[0].len() as u64 - 1,
+    });
+    let err = db.find(selector).unwrap_err();
+    assert!(err.to_string().contains("at 0 bytes"));
+    assert!(err.to_string().contains("2 of 2 files remaining"));
+    assert!(!chapter_dir.exists() || fs::read_dir(&chapter_dir).unwrap().next().is_none());
+}
+
+#[test]
+fn manifest_only_database_fetches_chapters_on_demand() {
+    let selector = "0xddf252ad";
+    let source = signatures_db();
+    let expected = source.find(selector).unwrap();
+    let manifest = fs::read(source.config.manifest_file_path().unwrap()).unwrap();
+    let chapter_id = SignaturesChapterId::from_interface_id("signatures_0xdd").unwrap();
+    let mut files: Vec<_> = fs::read_dir(source.config.chapter_dir_path(&chapter_id))
+        .unwrap()
+        .map(|f| f.unwrap().path())
+        .collect();
+    files.sort();
+    let contents: Vec<Vec<u8>> = files.iter().map(|f| fs::read(f).unwrap()).collect();
+    let cache_dir = std::env::temp_dir().join("min_know_test_manifest_only");
+    let _ = fs::remove_dir_all(&cache_dir);
+
+    // From bytes, nothing but the manifest is written.
+    let db: Todd<SignaturesSpec> = Todd::init_from_manifest(
+        DataKind::Signatures,
+        ManifestSource::Bytes(manifest.clone()),
+        &cache_dir,
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        db.manifest().unwrap().latest_volume_identifier(),
+        source.manifest().unwrap().latest_volume_identifier()
+    );
+    assert!(!db.config.data_dir.exists());
+    assert!(!db.config.raw_source.exists());
+    let err = db.find(selector).unwrap_err();
+    assert!(err.to_string().contains("not present locally"));
+    let other = String::from_utf8(manifest.clone())
+        .unwrap()
+        .replace("\"signatures\"", "\"nametags\"");
+    let err = Todd::<SignaturesSpec>::init_from_manifest(
+        DataKind::Signatures,
+        ManifestSource::Bytes(other.into_bytes()),
+        &cache_dir,
+        None,
+    )
+    .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("for database nametags, not signatures"));
+
+    // From a CID, the manifest then the Chapter files are obtained.
+    fs::remove_dir_all(&cache_dir).unwrap();
+    let cid = Cid::new_v0(Code::Sha2_256.digest(&manifest))
+        .unwrap()
+        .to_string();
+    let mut bodies = vec![manifest];
+    bodies.extend(contents);
+    let fetch = FetchOnMiss {
+        gateway_url: serve_in_order(bodies),
+        subdomain_gateway: false,
+        max_bytes: 1_000_000,
+    };
+    let db: Todd<SignaturesSpec> = Todd::init_from_manifest(
+        DataKind::Signatures,
+        ManifestSource::Cid(cid.clone()),
+        &cache_dir,
+        Some(fetch),
+    )
+    .unwrap();
+    assert!(!db.config.data_dir.exists());
+    assert_eq!(db.find(selector).unwrap(), expected);
+    // Once held, the manifest is not obtained again.
+    let again: Todd<SignaturesSpec> = Todd::init_from_manifest(
+        DataKind::Signatures,
+        ManifestSource::Cid(cid),
+        &cache_dir,
+        None,
+    )
+    .unwrap();
+    assert_eq!(again.find(selector).unwrap(), expected);
+    fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+#[test]
+fn obtain_from_manifest_mirrors() {
+    let selector = "0xddf252ad";
+    let db = ephemeral_copy(&signatures_db());
+    let expected = db.find(selector).unwrap();
+    let chapter_id = SignaturesChapterId::from_interface_id("signatures_0xdd").unwrap();
+    let chapter_dir = db.config.chapter_dir_path(&chapter_id);
+    let mut files = vec![];
+    for entry in fs::read_dir(&chapter_dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        files.push((
+            format!("/signatures_0xdd/{}", name),
+            fs::read(&path).unwrap(),
+        ));
+    }
+    fs::remove_dir_all(&chapter_dir).unwrap();
+    let err = db.obtain_relevant_data(&[selector], None).unwrap_err();
+    assert!(err.to_string().contains("lists no mirrors"));
+
+    // The gateway serves files that do not match the manifest, so the HTTP mirror is used.
+    let mirrors = ManifestMirrors {
+        ipfs_gateways: vec![serve_in_order(vec![b"not a chapter".to_vec(); 2])],
+        http_mirrors: vec![serve_paths(files)],
+        ..Default::default()
+    };
+    let manifest_path = db.config.manifest_file_path().unwrap();
+    let mut manifest = db.manifest().unwrap();
+    manifest.set_mirrors(mirrors.clone());
+    fs::write(
+        &manifest_path,
+        serde_json::to_string_pretty(&manifest).unwrap(),
+    )
+    .unwrap();
+    db.obtain_relevant_data(&[selector], None).unwrap();
+    assert_eq!(db.find(selector).unwrap(), expected);
+
+    // A new manifest keeps the mirrors.
+    db.generate_manifest().unwrap();
+    assert_eq!(db.manifest().unwrap().mirrors(), &mirrors);
+}
+
+#[test]
+fn gateways_probed_and_ranked() {
+    let db = ephemeral_copy(&signatures_db());
+    let mut served = vec![];
+    for (volume_id, chapter_id, path, _size) in db.inventory().unwrap() {
+        let bytes = fs::read(&path).unwrap();
+        let name = SignaturesChapter::new_empty(&volume_id, &chapter_id).filename();
+        served.push((
+            format!("/{}/{}", chapter_id.interface_id(), name),
+            bytes.clone(),
+        ));
+        let cid = Cid::new_v0(Code::Sha2_256.digest(&bytes)).unwrap();
+        served.push((format!("/ipfs/{}", cid), bytes));
+    }
+    let healthy = Gateway::new(&serve_paths(served.clone())).unwrap();
+    let probe = db.probe_gateway(&healthy).unwrap();
+    assert!(probe.is_healthy(), "{}", probe);
+    assert!(probe.bytes > 0);
+
+    let wrong = Gateway::new(&serve_once(b"not a chapter".to_vec())).unwrap();
+    let probe = db.probe_gateway(&wrong).unwrap();
+    assert!(
+        matches!(probe.health, SourceHealth::WrongBytes { .. }),
+        "{}",
+        probe
+    );
+
+    // Unhealthy sources are tried last.
+    let missing = Gateway::new(&serve_paths(vec![])).unwrap();
+    let mirror = Url::parse(&serve_paths(served)).unwrap();
+    let ranked = db
+        .rank_sources(vec![
+            FileSource::Gateway(missing.clone()),
+            FileSource::HttpMirror(mirror.clone()),
+        ])
+        .unwrap();
+    assert_eq!(ranked[0].0, FileSource::HttpMirror(mirror));
+    assert!(ranked[0].1.is_healthy());
+    assert_eq!(ranked[1].0, FileSource::Gateway(missing));
+    assert!(matches!(
+        ranked[1].1.health,
+        SourceHealth::Unreachable { .. }
+    ));
+}
+
+#[test]
+fn sync_and_pin_whole_database() {
+    let mut db = ephemeral_copy(&signatures_db());
+    let manifest_path = db.config.manifest_file_path().unwrap();
+    let manifest_bytes = fs::read(&manifest_path).unwrap();
+    let mut gateway_files = vec![];
+    let mut pin_paths = vec![];
+    let mut paths = vec![];
+    let manifest: serde_json::Value = serde_json::from_slice(&manifest_bytes).unwrap();
+    for entry in manifest["chapter_cids"].as_array().unwrap() {
+        let volume_id =
+            SignaturesVolumeId::from_interface_id(entry["volume_interface_id"].as_str().unwrap())
+                .unwrap();
+        let chapter_id =
+            SignaturesChapterId::from_interface_id(entry["chapter_interface_id"].as_str().unwrap())
+                .unwrap();
+        let cid = entry["cid_v0"].as_str().unwrap();
+        let filename = SignaturesChapter::new_empty(&volume_id, &chapter_id).filename();
+        let path = db.config.chapter_dir_path(&chapter_id).join(filename);
+        gateway_files.push((format!("/ipfs/{}", cid), fs::read(&path).unwrap()));
+        pin_paths.push((format!("/api/v0/pin/add?arg={}", cid), b"{}".to_vec()));
+        paths.push(path);
+    }
+    // One file is absent, one differs from the manifest and one CID cannot be pinned.
+    fs::remove_file(&paths[0]).unwrap();
+    fs::write(&paths[1], b"not a chapter").unwrap();
+    pin_paths.pop();
+    gateway_files.push(("/ipns/signatures".to_string(), manifest_bytes));
+    let gateway_url = serve_paths(gateway_files);
+    db.config.publisher_manifest_url = Some(format!("{}/ipns/signatures", gateway_url));
+    fs::remove_file(&manifest_path).unwrap();
+
+    let gateway = Gateway::new(&gateway_url).unwrap();
+    let report = db.sync_and_pin(&gateway, &serve_paths(pin_paths)).unwrap();
+    assert!(manifest_path.exists());
+    assert_eq!(report.audit_before.absent_files.len(), 2);
+    assert_eq!(report.downloaded.len(), 2);
+    assert!(report.audit_after.absent_files.is_empty());
+    assert_eq!(report.pinned.len(), paths.len() - 1);
+    assert_eq!(report.pin_failures.len(), 1);
+    assert!(!report.is_complete());
+    assert!(report.to_string().contains("Pinned"));
+}
+
+#[test]
+fn car_export_and_import() {
+    let source = ephemeral_copy(&signatures_db());
+    let car = source
+        .config
+        .base_dir_nature_dependent
+        .join("signatures.car");
+    let export = source.export_car(&car).unwrap();
+    assert!(export.missing.is_empty());
+    let inventory = source.inventory().unwrap();
+    assert_eq!(export.chapters, inventory.len());
+
+    let db: Todd<SignaturesSpec> = Todd::init(DataKind::Signatures, DirNature::Ephemeral).unwrap();
+    let import = db.import_car(&car).unwrap();
+    assert_eq!(import.written.len(), inventory.len());
+    assert!(import.missing.is_empty());
+    assert_eq!(db.manifest().unwrap(), source.manifest().unwrap());
+    let audit = db.check_completeness().unwrap();
+    assert!(audit.absent_individual_files.is_empty());
+    assert!(audit.absent_volume_ids.is_empty());
+    assert_eq!(
+        db.find("0xddf252ad").unwrap(),
+        source.find("0xddf252ad").unwrap()
+    );
+
+    // A corrupted archive is rejected.
+    let mut bytes = fs::read(&car).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    fs::write(&car, bytes).unwrap();
+    let other: Todd<SignaturesSpec> =
+        Todd::init(DataKind::Signatures, DirNature::Ephemeral).unwrap();
+    assert!(other.import_car(&car).is_err());
+    assert!(!other.config.data_dir.exists());
+    assert!(!other.config.manifest_file_path().unwrap().exists());
+}
+
+#[test]
+fn untrusted_manifests_rejected() {
+    let source = ephemeral_copy(&signatures_db());
+    let car = source
+        .config
+        .base_dir_nature_dependent
+        .join("signatures.car");
+    let root = Cid::try_from(source.export_car(&car).unwrap().root.as_str()).unwrap();
+    let manifest_cid = Cid::new_v0(*root.hash()).unwrap().to_string();
+    let other_cid = "QmNLei78zWmzUdbeRB3CiUfAizWUrbeeZh5K1rhAQKCh51".to_string();
+
+    let mut db: Todd<SignaturesSpec> =
+        Todd::init(DataKind::Signatures, DirNature::Ephemeral).unwrap();
+    let manifest_path = db.config.manifest_file_path().unwrap();
+    db.config.trusted_manifest_cids = vec![other_cid];
+    let err = db.import_car(&car).unwrap_err();
+    assert!(format!("{:#}", err).contains(&manifest_cid));
+    assert!(!manifest_path.exists());
+    assert!(!db.config.data_dir.exists());
+
+    // A manifest from the publisher is checked before any files are downloaded.
+    let manifest_bytes = fs::read(source.config.manifest_file_path().unwrap()).unwrap();
+    db.config.publisher_manifest_url = Some(serve_once(manifest_bytes));
+    let gateway = Gateway::new(&serve_paths(vec![])).unwrap();
+    assert!(db.sync_and_pin(&gateway, "http://127.0.0.1:5001").is_err());
+    assert!(!manifest_path.exists());
+    assert!(!db.config.data_dir.exists());
+
+    db.config.trusted_manifest_cids.push(manifest_cid);
+    db.import_car(&car).unwrap();
+    assert_eq!(db.manifest().unwrap(), source.manifest().unwrap());
+}
+
+#[test]
+fn watcher_reports_new_chapter_files_and_manifest() {
+    let db = ephemeral_copy(&signatures_db());
+    let (sender, receiver) = mpsc::channel();
+    let watcher = db
+        .watch(move |change| sender.send(change).unwrap())
+        .unwrap();
+
+    let (volume_id, chapter_id, path, _size) = db.inventory().unwrap().remove(0);
+    let next_volume = SignaturesVolumeId::nth_id(volume_id.is_nth().unwrap() + 1_000).unwrap();
+    let new_file =
+        path.with_file_name(SignaturesChapter::new_empty(&next_volume, &chapter_id).filename());
+    fs::copy(&path, &new_file).unwrap();
+    let manifest_path = db.config.manifest_file_path().unwrap();
+    fs::write(&manifest_path, fs::read(&manifest_path).unwrap()).unwrap();
+
+    let mut chapter_changed = false;
+    let mut manifest_changed = false;
+    while !(chapter_changed && manifest_changed) {
+        match receiver.recv_timeout(Duration::from_secs(10)).unwrap() {
+            DatabaseChange::Manifest => manifest_changed = true,
+            DatabaseChange::Chapter {
+                chapter_interface_id,
+                paths,
+            } => {
+                assert_eq!(chapter_interface_id, chapter_id.interface_id());
+                chapter_changed |= paths.contains(&new_file);
+            }
+        }
+    }
+    drop(watcher);
+}
+
+#[test]
+fn plan_obtained_from_partial_mirrors() {
+    let source = ephemeral_copy(&signatures_db());
+    let mut chapters: Vec<String> = source
+        .inventory()
+        .unwrap()
+        .iter()
+        .map(|(_, chapter_id, _, _)| chapter_id.interface_id())
+        .collect();
+    chapters.sort();
+    chapters.dedup();
+    let (low, high) = chapters.split_at(chapters.len() / 2);
+    // Each mirror holds half of the Chapters.
+    let mirror = |held: &[String]| {
+        let db = ephemeral_copy(&source);
+        for chapter in chapters.iter().filter(|c| !held.contains(c)) {
+            fs::remove_dir_all(db.config.data_dir.join(chapter)).unwrap();
+        }
+        let files = db
+            .inventory()
+            .unwrap()
+            .into_iter()
+            .map(|(_, chapter_id, path, _)| {
+                let filename = path.file_name().unwrap().to_str().unwrap();
+                (
+                    format!("/{}/{}", chapter_id.interface_id(), filename),
+                    fs::read(&path).unwrap(),
+                )
+            })
+            .collect();
+        let mirrors = ManifestMirrors {
+            http_mirrors: vec![serve_paths(files)],
+            ..Default::default()
+        };
+        db.describe_holdings(mirrors).unwrap()
+    };
+    let low_holdings = mirror(low);
+    assert_eq!(low_holdings.chapters, low);
+    assert!(low_holdings.files.is_empty());
+    let high_holdings = mirror(high);
+    let mut stale = high_holdings.clone();
+    stale.manifest_cid = "QmNLei78zWmzUdbeRB3CiUfAizWUrbeeZh5K1rhAQKCh51".to_string();
+
+    let db = ephemeral_copy(&source);
+    fs::remove_dir_all(&db.config.data_dir).unwrap();
+    let key = |chapter: &String| format!("{}000000", chapter.trim_start_matches("signatures_"));
+    let keys = [key(&low[0]), key(&high[0])];
+    let keys: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+    let plan = db.plan_acquisition(&keys).unwrap();
+    assert_eq!(plan.chapters, vec![low[0].clone(), high[0].clone()]);
+
+    // A descriptor for another manifest is not used.
+    assert!(db
+        .execute_plan_from_holdings(&plan, &[low_holdings.clone(), stale])
+        .is_err());
+    db.execute_plan_from_holdings(&plan, &[low_holdings, high_holdings])
+        .unwrap();
+    for file in &plan.files {
+        assert!(file.dest_dir.join(&file.filename).exists());
+    }
+    assert_eq!(db.find(keys[1]).unwrap(), source.find(keys[1]).unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+fn unusual_filenames_in_data_and_raw_dirs() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let non_utf8 = OsStr::from_bytes(b"a9\xff\xfe");
+    let long = "f".repeat(255);
+    let sample_raw = signatures_db().config.raw_source;
+    let mut plain: Todd<SignaturesSpec> =
+        Todd::init(DataKind::Signatures, DirNature::Ephemeral).unwrap();
+    plain.config.raw_source = sample_raw.clone();
+    plain.full_transformation().unwrap();
+
+    // Raw files left by other programs are ignored, and names that are not
+    // valid UTF-8 are reported rather than stopping the build.
+    let mut db: Todd<SignaturesSpec> =
+        Todd::init(DataKind::Signatures, DirNature::Ephemeral).unwrap();
+    let raw = db.config.base_dir_nature_dependent.join("raw");
+    fs::create_dir_all(&raw).unwrap();
+    for entry in fs::read_dir(&sample_raw).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, raw.join(path.file_name().unwrap())).unwrap();
+    }
+    for name in [
+        OsStr::new(".DS_Store"),
+        OsStr::new("a9059cbb.swp"),
+        non_utf8,
+    ] {
+        fs::write(raw.join(name), "transfer(address,uint256)").unwrap();
+    }
+    db.config.raw_source = raw;
+    let report = db.validate_raw().unwrap();
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].path.file_name(), Some(non_utf8));
+    db.full_transformation().unwrap();
+    assert_eq!(
+        db.manifest().unwrap().chapter_cids,
+        plain.manifest().unwrap().chapter_cids
+    );
+
+    // Data directory files left by other programs are not Chapters or orphans.
+    let (_volume_id, _chapter_id, path, _size) = db.inventory().unwrap().remove(0);
+    let chapter_dir = path.parent().unwrap();
+    fs::write(db.config.data_dir.join(".DS_Store"), b"").unwrap();
+    fs::write(db.config.data_dir.join("Thumbs.db"), b"").unwrap();
+    fs::write(chapter_dir.join(".DS_Store"), b"").unwrap();
+    fs::write(chapter_dir.join("chapter.tmp"), b"").unwrap();
+    assert_eq!(
+        db.config.chapter_dirs::<SignaturesSpec>().unwrap().len(),
+        plain.config.chapter_dirs::<SignaturesSpec>().unwrap().len()
+    );
+    assert!(!db.find("0x00008711").unwrap().is_empty());
+    let audit = db.check_completeness().unwrap();
+    assert!(audit.orphan_files.is_empty());
+    assert_eq!(audit.files_verified, audit.files_in_manifest);
+
+    // Other files with long or non-UTF-8 names are orphans.
+    fs::write(chapter_dir.join(non_utf8), b"").unwrap();
+    fs::write(chapter_dir.join(&long), b"").unwrap();
+    assert_eq!(db.check_completeness().unwrap().orphan_files.len(), 2);
+    db.clean_orphans(false).unwrap();
+    assert!(db.check_completeness().unwrap().orphan_files.is_empty());
+}